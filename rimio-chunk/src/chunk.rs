@@ -0,0 +1,137 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Size of every part of a blob but the last.
+pub const PART_SIZE: usize = 64 * 1024 * 1024;
+
+/// One part of a blob as a node stores it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartDigest {
+    pub part_no: u32,
+    pub sha256: String,
+    pub length: u64,
+}
+
+/// The parts and etag a node computes for a blob body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobManifest {
+    pub size_bytes: u64,
+    /// SHA-256 of the whole body.
+    pub etag: String,
+    pub part_size: u64,
+    pub parts: Vec<PartDigest>,
+}
+
+impl BlobManifest {
+    pub fn part_count(&self) -> u32 {
+        self.parts.len() as u32
+    }
+}
+
+/// Splits a body fed in pieces of any size into parts, hashing as it goes.
+#[derive(Clone)]
+pub struct Chunker {
+    part_size: usize,
+    body: Sha256,
+    size_bytes: u64,
+    part: Sha256,
+    part_len: usize,
+    parts: Vec<PartDigest>,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunker {
+    pub fn new() -> Self {
+        Self::with_part_size(PART_SIZE)
+    }
+
+    /// Only useful for tests; nodes always split at [`PART_SIZE`].
+    pub fn with_part_size(part_size: usize) -> Self {
+        Self {
+            part_size: part_size.max(1),
+            body: Sha256::new(),
+            size_bytes: 0,
+            part: Sha256::new(),
+            part_len: 0,
+            parts: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.body.update(data);
+        self.size_bytes += data.len() as u64;
+
+        while !data.is_empty() {
+            let take = (self.part_size - self.part_len).min(data.len());
+            self.part.update(&data[..take]);
+            self.part_len += take;
+            data = &data[take..];
+            if self.part_len == self.part_size {
+                self.finish_part();
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> BlobManifest {
+        if self.part_len > 0 {
+            self.finish_part();
+        }
+
+        BlobManifest {
+            size_bytes: self.size_bytes,
+            etag: hex::encode(self.body.finalize()),
+            part_size: self.part_size as u64,
+            parts: self.parts,
+        }
+    }
+
+    fn finish_part(&mut self) {
+        let part = core::mem::take(&mut self.part);
+        self.parts.push(PartDigest {
+            part_no: self.parts.len() as u32,
+            sha256: hex::encode(part.finalize()),
+            length: self.part_len as u64,
+        });
+        self.part_len = 0;
+    }
+}
+
+/// The manifest of a body held in memory.
+pub fn chunk(body: &[u8]) -> BlobManifest {
+    let mut chunker = Chunker::new();
+    chunker.update(body);
+    chunker.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256_hex;
+
+    #[test]
+    fn parts_do_not_depend_on_how_the_body_arrives() {
+        let body: Vec<u8> = (0..1000u32).map(|n| n as u8).collect();
+        let mut whole = Chunker::with_part_size(300);
+        whole.update(&body);
+        let whole = whole.finish();
+
+        let mut pieces = Chunker::with_part_size(300);
+        for piece in body.chunks(7) {
+            pieces.update(piece);
+        }
+        assert_eq!(pieces.finish(), whole);
+
+        assert_eq!(whole.etag, sha256_hex(&body));
+        assert_eq!(whole.part_count(), 4);
+        assert_eq!(whole.parts[3].length, 100);
+        assert_eq!(whole.parts[1].sha256, sha256_hex(&body[300..600]));
+        assert!(chunk(&[]).parts.is_empty());
+    }
+}
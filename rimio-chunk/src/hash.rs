@@ -0,0 +1,7 @@
+use alloc::string::String;
+use sha2::{Digest, Sha256};
+
+/// Lowercase hex SHA-256 of `data`, the form parts, etags and heads use.
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
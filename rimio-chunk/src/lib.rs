@@ -0,0 +1,19 @@
+//! Rimio Chunk - how blobs are hashed, split into parts and mapped to slots.
+//!
+//! Everything a client needs to pre-chunk and pre-hash an upload exactly like
+//! a node does. The crate is `no_std` (with `alloc`) so it also builds for
+//! `wasm32` targets such as browsers and edge functions.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod chunk;
+pub mod hash;
+pub mod path;
+pub mod slot;
+
+pub use chunk::{BlobManifest, Chunker, PART_SIZE, PartDigest, chunk};
+pub use hash::sha256_hex;
+pub use path::{PathError, normalize_blob_path};
+pub use slot::{KeyHasher, slot_for_key, slot_for_tagged_key, slot_hash_tag};
@@ -0,0 +1,39 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    Empty,
+    InvalidComponent(String),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "blob path cannot be empty"),
+            Self::InvalidComponent(component) => {
+                write!(f, "invalid blob path component: {}", component)
+            }
+        }
+    }
+}
+
+/// Strips leading and trailing slashes and rejects empty, `.` and `..`
+/// components, giving the form paths are stored and hashed under.
+pub fn normalize_blob_path(path: &str) -> Result<String, PathError> {
+    let trimmed = path.trim_matches('/');
+    if trimmed.is_empty() {
+        return Err(PathError::Empty);
+    }
+
+    let mut components = Vec::new();
+    for component in trimmed.split('/') {
+        if component.is_empty() || component == "." || component == ".." {
+            return Err(PathError::InvalidComponent(component.into()));
+        }
+        components.push(component);
+    }
+
+    Ok(components.join("/"))
+}
@@ -0,0 +1,153 @@
+use core::hash::Hasher;
+
+/// SipHash-1-3 with zero keys, the hasher slots have always been computed
+/// with (std's `DefaultHasher::new()`), written out so `no_std` targets
+/// get the same slots. Integers are fed little-endian.
+#[derive(Debug, Clone)]
+pub struct KeyHasher {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    tail: u64,
+    tail_len: u32,
+    length: u64,
+}
+
+impl Default for KeyHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyHasher {
+    pub fn new() -> Self {
+        Self {
+            v0: 0x736f_6d65_7073_6575,
+            v1: 0x646f_7261_6e64_6f6d,
+            v2: 0x6c79_6765_6e65_7261,
+            v3: 0x7465_6462_7974_6573,
+            tail: 0,
+            tail_len: 0,
+            length: 0,
+        }
+    }
+
+    /// Feeds a string the way `str: Hash` does: its bytes, then `0xff`.
+    pub fn write_key(&mut self, key: &str) {
+        self.write(key.as_bytes());
+        self.write(&[0xff]);
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13) ^ self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16) ^ self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21) ^ self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17) ^ self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    fn compress(&mut self, word: u64) {
+        self.v3 ^= word;
+        self.round();
+        self.v0 ^= word;
+    }
+}
+
+impl Hasher for KeyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.tail |= (*byte as u64) << (8 * self.tail_len);
+            self.tail_len += 1;
+            if self.tail_len == 8 {
+                self.compress(self.tail);
+                self.tail = 0;
+                self.tail_len = 0;
+            }
+        }
+        self.length += bytes.len() as u64;
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+        state.compress(((self.length & 0xff) << 56) | self.tail);
+        state.v2 ^= 0xff;
+        state.round();
+        state.round();
+        state.round();
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+/// The non-empty `{tag}` of a key, if it has one.
+pub fn slot_hash_tag(key: &str) -> Option<&str> {
+    let start = key.find('{')?;
+    let len = key[start + 1..].find('}')?;
+    if len == 0 {
+        return None;
+    }
+
+    Some(&key[start + 1..start + 1 + len])
+}
+
+/// Maps a key to its slot by hashing the whole key.
+pub fn slot_for_key(key: &str, total_slots: u16) -> u16 {
+    let mut hasher = KeyHasher::new();
+    hasher.write_key(key);
+    (hasher.finish() % total_slots as u64) as u16
+}
+
+/// Maps a key to its slot, hashing only its `{tag}` when it has one.
+///
+/// Publishers can co-locate related paths (e.g. `releases/{v2}/index.json`
+/// and `releases/{v2}/app.bin`) in one slot and commit them together. Keys
+/// with braces written under [`slot_for_key`] live elsewhere, so a cluster
+/// must pick one of the two before it stores data.
+pub fn slot_for_tagged_key(key: &str, total_slots: u16) -> u16 {
+    slot_for_key(slot_hash_tag(key).unwrap_or(key), total_slots)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::format;
+    use core::hash::Hash;
+    use std::collections::hash_map::DefaultHasher;
+
+    #[test]
+    fn matches_the_std_default_hasher() {
+        for n in 0..200u64 {
+            let key = format!("bucket/{}/{}", "x".repeat(n as usize % 19), n);
+            let mut expected = DefaultHasher::new();
+            key.hash(&mut expected);
+            n.hash(&mut expected);
+
+            let mut actual = KeyHasher::new();
+            actual.write_key(&key);
+            actual.write_u64(n);
+            assert_eq!(actual.finish(), expected.finish(), "key={}", key);
+        }
+    }
+
+    #[test]
+    fn untagged_routing_hashes_the_whole_key() {
+        assert_eq!(slot_for_key("bucket/a.txt", 2048), 1_754);
+        assert_eq!(slot_for_key("a/{tag}/b", 2048), 508);
+        assert_eq!(slot_for_tagged_key("bucket/a.txt", 2048), 1_754);
+        assert_eq!(
+            slot_for_tagged_key("a/{tag}/b", 2048),
+            slot_for_key("tag", 2048)
+        );
+    }
+}
@@ -1,13 +1,18 @@
 use crate::{
-    ArchiveStore, BlobMeta, ClusterClient, MetadataStore, PartStore, PutBlobArchiveWriter,
-    Registry, Result, RimError, SlotInfo, SlotManager,
+    ArchiveStore, BlobMeta, ClusterClient, MetadataStore, NodeStore, PartStore,
+    PutBlobArchiveWriter, Registry, Result, RimError, SlotInfo, SlotManager,
 };
-use rusqlite::{Connection, OptionalExtension, params};
-use std::path::PathBuf;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::interval;
 
+mod uploader;
+
+pub use uploader::{ArchiveTieringConfig, ArchiveUploadReport, ArchiveUploader};
+
 #[derive(Debug, Clone)]
 pub struct ArchiveLifecycleConfig {
     pub sync_interval: Duration,
@@ -23,7 +28,9 @@ impl Default for ArchiveLifecycleConfig {
     }
 }
 
-#[derive(Debug, Clone)]
+const ARCHIVE_CURSOR_SCOPE: &str = "archive_sync";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ArchiveSyncCursor {
     updated_at: String,
     blob_path: String,
@@ -40,93 +47,44 @@ impl ArchiveSyncCursor {
     }
 }
 
-struct ArchiveCursorStore {
-    db_path: PathBuf,
-}
-
-impl ArchiveCursorStore {
-    fn new(db_path: PathBuf) -> Result<Self> {
-        if let Some(parent) = db_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
-        let store = Self { db_path };
-        store.ensure_schema()?;
-        Ok(store)
+/// Moves cursors from the standalone `archive/sync_cursor.sqlite3` of data
+/// layout v1 into the node store, then renames the old file so the import
+/// runs once.
+pub(crate) fn import_legacy_cursors(local_data_dir: &Path, node_store: &NodeStore) -> Result<()> {
+    let legacy_path = local_data_dir.join("archive").join("sync_cursor.sqlite3");
+    if !legacy_path.exists() {
+        return Ok(());
     }
 
-    fn ensure_schema(&self) -> Result<()> {
-        let conn = self.connection()?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS archive_sync_cursor (
-                slot_id INTEGER PRIMARY KEY,
-                updated_at TEXT NOT NULL,
-                blob_path TEXT NOT NULL,
-                generation INTEGER NOT NULL,
-                updated_local_at TEXT NOT NULL
-            )",
-            [],
+    let cursors = {
+        let conn = Connection::open(&legacy_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT slot_id, updated_at, blob_path, generation FROM archive_sync_cursor",
         )?;
-
-        Ok(())
-    }
-
-    fn connection(&self) -> Result<Connection> {
-        let conn = Connection::open(&self.db_path)?;
-        conn.pragma_update(None, "journal_mode", "WAL")?;
-        conn.busy_timeout(Duration::from_secs(5))?;
-        Ok(conn)
-    }
-
-    fn load(&self, slot_id: u16) -> Result<Option<ArchiveSyncCursor>> {
-        let conn = self.connection()?;
-        let cursor = conn
-            .query_row(
-                "SELECT updated_at, blob_path, generation
-                 FROM archive_sync_cursor
-                 WHERE slot_id = ?1",
-                params![slot_id as i64],
-                |row| {
-                    Ok(ArchiveSyncCursor {
-                        updated_at: row.get(0)?,
-                        blob_path: row.get(1)?,
-                        generation: row.get(2)?,
-                    })
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                ArchiveSyncCursor {
+                    updated_at: row.get(1)?,
+                    blob_path: row.get(2)?,
+                    generation: row.get(3)?,
                 },
-            )
-            .optional()?;
+            ))
+        })?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()?
+    };
 
-        Ok(cursor)
+    for (slot_id, cursor) in &cursors {
+        node_store.save_cursor(ARCHIVE_CURSOR_SCOPE, *slot_id as u16, cursor)?;
     }
 
-    fn save(&self, slot_id: u16, cursor: &ArchiveSyncCursor) -> Result<()> {
-        let conn = self.connection()?;
-        let now = chrono::Utc::now().to_rfc3339();
-
-        conn.execute(
-            "INSERT INTO archive_sync_cursor (
-                slot_id,
-                updated_at,
-                blob_path,
-                generation,
-                updated_local_at
-            ) VALUES (?1, ?2, ?3, ?4, ?5)
-            ON CONFLICT(slot_id) DO UPDATE SET
-                updated_at = excluded.updated_at,
-                blob_path = excluded.blob_path,
-                generation = excluded.generation,
-                updated_local_at = excluded.updated_local_at",
-            params![
-                slot_id as i64,
-                cursor.updated_at,
-                cursor.blob_path,
-                cursor.generation,
-                now,
-            ],
-        )?;
-
-        Ok(())
-    }
+    std::fs::rename(&legacy_path, legacy_path.with_extension("sqlite3.imported"))?;
+    tracing::info!(
+        "imported legacy archive cursors: slots={} from={}",
+        cursors.len(),
+        legacy_path.display()
+    );
+    Ok(())
 }
 
 pub struct ArchiveLifecycleManager {
@@ -136,7 +94,7 @@ pub struct ArchiveLifecycleManager {
     part_store: Arc<PartStore>,
     cluster_client: Arc<ClusterClient>,
     archive_writer: PutBlobArchiveWriter,
-    cursor_store: ArchiveCursorStore,
+    node_store: Arc<NodeStore>,
     config: ArchiveLifecycleConfig,
 }
 
@@ -150,12 +108,9 @@ impl ArchiveLifecycleManager {
         cluster_client: Arc<ClusterClient>,
         archive_store: Arc<dyn ArchiveStore>,
         archive_key_prefix: String,
-        local_data_dir: PathBuf,
+        node_store: Arc<NodeStore>,
         config: ArchiveLifecycleConfig,
     ) -> Result<Self> {
-        let cursor_store =
-            ArchiveCursorStore::new(local_data_dir.join("archive").join("sync_cursor.sqlite3"))?;
-
         Ok(Self {
             local_node_id,
             registry,
@@ -163,7 +118,7 @@ impl ArchiveLifecycleManager {
             part_store,
             cluster_client,
             archive_writer: PutBlobArchiveWriter::new(archive_store, archive_key_prefix),
-            cursor_store,
+            node_store,
             config,
         })
     }
@@ -210,7 +165,9 @@ impl ArchiveLifecycleManager {
 
         let slot = self.slot_manager.get_slot(slot_info.slot_id).await?;
         let metadata_store = MetadataStore::new(slot)?;
-        let cursor = self.cursor_store.load(slot_info.slot_id)?;
+        let cursor: Option<ArchiveSyncCursor> = self
+            .node_store
+            .load_cursor(ARCHIVE_CURSOR_SCOPE, slot_info.slot_id)?;
 
         let updates = metadata_store.list_meta_updates_after(
             cursor.as_ref().map(|value| value.updated_at.as_str()),
@@ -227,7 +184,8 @@ impl ArchiveLifecycleManager {
                     .await?;
             }
 
-            self.cursor_store.save(slot_info.slot_id, &cursor_value)?;
+            self.node_store
+                .save_cursor(ARCHIVE_CURSOR_SCOPE, slot_info.slot_id, &cursor_value)?;
         }
 
         Ok(())
@@ -309,7 +267,7 @@ impl ArchiveLifecycleManager {
                     ))
                 })?;
 
-            let bytes = self
+            let local = self
                 .part_store
                 .get_part(
                     meta.slot_id,
@@ -318,7 +276,16 @@ impl ArchiveLifecycleManager {
                     part_no,
                     &part_entry.sha256,
                 )
-                .await?;
+                .await;
+            // Tiering may have left only the archived copy of the part.
+            let bytes = match (local, part_entry.archive_url.as_deref()) {
+                (Ok(bytes), _) => bytes,
+                (Err(_), Some(archive_url)) if part_entry.size_bytes > 0 => {
+                    crate::read_archive_range_bytes(archive_url, 0, part_entry.size_bytes - 1)
+                        .await?
+                }
+                (Err(error), _) => return Err(error),
+            };
 
             all.extend_from_slice(&bytes);
         }
@@ -345,9 +312,14 @@ mod tests {
     #[test]
     fn archive_cursor_store_roundtrip() {
         let dir = tempfile::tempdir().expect("tempdir");
-        let store = ArchiveCursorStore::new(dir.path().join("cursor.sqlite3")).expect("store");
+        let store = NodeStore::open(dir.path()).expect("store");
 
-        assert!(store.load(7).expect("load").is_none());
+        assert!(
+            store
+                .load_cursor::<ArchiveSyncCursor>(ARCHIVE_CURSOR_SCOPE, 7)
+                .expect("load")
+                .is_none()
+        );
 
         let cursor = ArchiveSyncCursor {
             updated_at: "2026-01-01T00:00:00Z".to_string(),
@@ -355,11 +327,48 @@ mod tests {
             generation: 12,
         };
 
-        store.save(7, &cursor).expect("save");
+        store
+            .save_cursor(ARCHIVE_CURSOR_SCOPE, 7, &cursor)
+            .expect("save");
 
-        let loaded = store.load(7).expect("reload").expect("cursor");
+        let loaded: ArchiveSyncCursor = store
+            .load_cursor(ARCHIVE_CURSOR_SCOPE, 7)
+            .expect("reload")
+            .expect("cursor");
         assert_eq!(loaded.updated_at, cursor.updated_at);
         assert_eq!(loaded.blob_path, cursor.blob_path);
         assert_eq!(loaded.generation, cursor.generation);
     }
+
+    #[test]
+    fn legacy_archive_cursors_are_imported_once() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let legacy_dir = dir.path().join("archive");
+        std::fs::create_dir_all(&legacy_dir).expect("legacy dir");
+        let conn = Connection::open(legacy_dir.join("sync_cursor.sqlite3")).expect("legacy db");
+        conn.execute_batch(
+            "CREATE TABLE archive_sync_cursor (
+                slot_id INTEGER PRIMARY KEY,
+                updated_at TEXT NOT NULL,
+                blob_path TEXT NOT NULL,
+                generation INTEGER NOT NULL,
+                updated_local_at TEXT NOT NULL
+            );
+            INSERT INTO archive_sync_cursor VALUES (4, '2026-01-01T00:00:00Z', 'x/y', 3, '');",
+        )
+        .expect("legacy rows");
+        drop(conn);
+
+        let store = NodeStore::open(dir.path()).expect("store");
+        import_legacy_cursors(dir.path(), &store).expect("import");
+        import_legacy_cursors(dir.path(), &store).expect("second import is a no-op");
+
+        let loaded: ArchiveSyncCursor = store
+            .load_cursor(ARCHIVE_CURSOR_SCOPE, 4)
+            .expect("load")
+            .expect("cursor");
+        assert_eq!(loaded.blob_path, "x/y");
+        assert_eq!(loaded.generation, 3);
+        assert!(!legacy_dir.join("sync_cursor.sqlite3").exists());
+    }
 }
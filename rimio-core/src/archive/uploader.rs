@@ -0,0 +1,523 @@
+//! Tiering cold parts to the archive.
+//!
+//! Archive sync copies every blob to the archive as it is written. With
+//! `eager_sync` off, only cold data goes there instead: blobs a lifecycle
+//! rule wants transitioned and, while the data disk is fuller than
+//! `disk_high_percent`, the oldest blobs until it drops below
+//! `disk_low_percent`. Each part is stored once under its sha256, so replicas
+//! and blobs with the same bytes share the object, and its URL goes on the
+//! node's own part entry. Heads are left alone, so replicas tier on their own
+//! without their heads drifting apart.
+
+use crate::operations::apply_lifecycle::{
+    LifecycleAction, common_prefix, due_action, evict_archived_parts,
+};
+use crate::{
+    BlobMeta, HeadKind, LifecycleRule, MetadataStore, PartStore, PutBlobArchiveWriter, Result,
+    SharedClock, SlotManager, system_clock, verify_hash,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Metas read per slot and step while relieving the disk.
+const TIERING_BATCH_SIZE: usize = 64;
+
+/// Heads read per step of a lifecycle walk.
+const TIERING_PAGE_SIZE: usize = 1000;
+
+/// When parts move to the archive; the `tiering` section of the archive
+/// config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveTieringConfig {
+    /// Keep copying every blob to the archive as it is written. Tiering then
+    /// only removes local copies; turn it off to upload cold parts alone.
+    #[serde(default = "default_eager_sync")]
+    pub eager_sync: bool,
+    #[serde(default = "default_tiering_interval_secs")]
+    pub interval_secs: u64,
+    /// Disk use, in percent, past which the oldest blobs are tiered.
+    #[serde(default)]
+    pub disk_high_percent: Option<f64>,
+    /// Disk use, in percent, at which tiering for space stops.
+    #[serde(default = "default_disk_low_percent")]
+    pub disk_low_percent: f64,
+    /// Remove local copies once the archive holds them. Without it parts
+    /// are only uploaded, and the disk is not relieved.
+    #[serde(default = "default_evict_local")]
+    pub evict_local: bool,
+}
+
+impl Default for ArchiveTieringConfig {
+    fn default() -> Self {
+        Self {
+            eager_sync: default_eager_sync(),
+            interval_secs: default_tiering_interval_secs(),
+            disk_high_percent: None,
+            disk_low_percent: default_disk_low_percent(),
+            evict_local: default_evict_local(),
+        }
+    }
+}
+
+fn default_eager_sync() -> bool {
+    true
+}
+
+fn default_tiering_interval_secs() -> u64 {
+    600
+}
+
+fn default_disk_low_percent() -> f64 {
+    75.0
+}
+
+fn default_evict_local() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ArchiveUploadReport {
+    pub uploaded_parts: u64,
+    pub uploaded_bytes: u64,
+    pub evicted_parts: u64,
+    pub freed_bytes: u64,
+    pub failed: u64,
+    /// Use of the data disk when the pass started, when it could be read.
+    pub disk_used_percent: Option<f64>,
+}
+
+pub struct ArchiveUploader {
+    slot_manager: Arc<SlotManager>,
+    part_store: Arc<PartStore>,
+    archive_writer: PutBlobArchiveWriter,
+    config: ArchiveTieringConfig,
+    clock: SharedClock,
+}
+
+impl ArchiveUploader {
+    pub fn new(
+        slot_manager: Arc<SlotManager>,
+        part_store: Arc<PartStore>,
+        archive_writer: PutBlobArchiveWriter,
+        config: ArchiveTieringConfig,
+    ) -> Self {
+        Self {
+            slot_manager,
+            part_store,
+            archive_writer,
+            config,
+            clock: system_clock(),
+        }
+    }
+
+    /// Ages blobs against `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn config(&self) -> &ArchiveTieringConfig {
+        &self.config
+    }
+
+    /// Tiers the blobs of `slot_ids` that `rules` want transitioned, then
+    /// relieves the disk if it is past the high mark.
+    pub async fn run_once(
+        &self,
+        slot_ids: &[u16],
+        rules: &[LifecycleRule],
+    ) -> Result<ArchiveUploadReport> {
+        let mut report = ArchiveUploadReport::default();
+
+        if rules
+            .iter()
+            .any(|rule| rule.transition_after_days.is_some())
+        {
+            for slot_id in slot_ids {
+                if let Err(error) = self.tier_due_blobs(*slot_id, rules, &mut report).await {
+                    tracing::warn!("tiering failed for slot {}: {}", slot_id, error);
+                }
+            }
+        }
+
+        if let Some(high) = self.config.disk_high_percent {
+            report.disk_used_percent = disk_used_percent(self.part_store.base_path()).await;
+            if self.config.evict_local && report.disk_used_percent.is_some_and(|used| used > high) {
+                self.relieve_disk(slot_ids, &mut report).await?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn tier_due_blobs(
+        &self,
+        slot_id: u16,
+        rules: &[LifecycleRule],
+        report: &mut ArchiveUploadReport,
+    ) -> Result<()> {
+        let store = self.ensure_store(slot_id).await?;
+        let prefix = common_prefix(rules);
+        let now = self.clock.now();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let heads = store.list_heads(&prefix, TIERING_PAGE_SIZE, false, cursor.as_deref())?;
+            let full = heads.len() >= TIERING_PAGE_SIZE;
+            cursor = heads.last().map(|head| head.path.clone());
+
+            for head in heads {
+                let written = head.updated_at;
+                let Some(meta) = head.meta.filter(|_| head.head_kind == HeadKind::Meta) else {
+                    continue;
+                };
+                if due_action(rules, &meta.path, written, now) == Some(LifecycleAction::Transition)
+                {
+                    self.tier_blob(&store, &meta, report).await;
+                }
+            }
+
+            if !full {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Tiers blobs oldest first, a batch per slot in turn, until the disk is
+    /// below the low mark or nothing is left.
+    async fn relieve_disk(&self, slot_ids: &[u16], report: &mut ArchiveUploadReport) -> Result<()> {
+        let mut walks: Vec<(MetadataStore, Option<BlobMeta>)> = Vec::new();
+        for slot_id in slot_ids {
+            walks.push((self.ensure_store(*slot_id).await?, None));
+        }
+
+        while !walks.is_empty() {
+            let mut index = 0;
+            while index < walks.len() {
+                let (store, cursor) = &walks[index];
+                let metas = store.list_meta_updates_after(
+                    cursor
+                        .as_ref()
+                        .map(|meta| meta.updated_at.to_rfc3339())
+                        .as_deref(),
+                    cursor.as_ref().map(|meta| meta.path.as_str()),
+                    cursor.as_ref().map(|meta| meta.generation),
+                    TIERING_BATCH_SIZE,
+                )?;
+                for meta in &metas {
+                    self.tier_blob(store, meta, report).await;
+                }
+
+                if metas.len() < TIERING_BATCH_SIZE {
+                    walks.swap_remove(index);
+                } else {
+                    walks[index].1 = metas.last().cloned();
+                    index += 1;
+                }
+            }
+
+            let used = disk_used_percent(self.part_store.base_path()).await;
+            if used.is_none_or(|used| used <= self.config.disk_low_percent) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Uploads the parts of `meta` the archive lacks and, when configured,
+    /// removes the local copies it holds. Failures are counted and logged.
+    async fn tier_blob(
+        &self,
+        store: &MetadataStore,
+        meta: &BlobMeta,
+        report: &mut ArchiveUploadReport,
+    ) {
+        if let Err(error) = self.upload_parts(store, meta, report).await {
+            report.failed += 1;
+            tracing::warn!(
+                "tiering upload failed: slot={} path={} generation={} error={}",
+                meta.slot_id,
+                meta.path,
+                meta.generation,
+                error
+            );
+            return;
+        }
+        if !self.config.evict_local {
+            return;
+        }
+
+        match evict_archived_parts(&self.part_store, store, meta, false).await {
+            Ok(evicted) => {
+                report.evicted_parts += evicted.parts;
+                report.freed_bytes += evicted.freed_bytes;
+            }
+            Err(error) => {
+                report.failed += 1;
+                tracing::warn!(
+                    "tiering eviction failed: slot={} path={} generation={} error={}",
+                    meta.slot_id,
+                    meta.path,
+                    meta.generation,
+                    error
+                );
+            }
+        }
+    }
+
+    async fn upload_parts(
+        &self,
+        store: &MetadataStore,
+        meta: &BlobMeta,
+        report: &mut ArchiveUploadReport,
+    ) -> Result<()> {
+        // With eager sync the blob object is on its way; uploading the parts
+        // as well would only store the bytes twice.
+        if self.config.eager_sync || meta.archive_url.is_some() {
+            return Ok(());
+        }
+
+        for entry in store.list_part_entries(&meta.path, meta.generation)? {
+            if entry.archive_url.is_some() {
+                continue;
+            }
+            let part_path = match entry.external_path.as_deref() {
+                Some(external_path) => PathBuf::from(external_path),
+                None => self.part_store.part_path(
+                    meta.slot_id,
+                    &meta.path,
+                    meta.generation,
+                    entry.part_no,
+                    &entry.sha256,
+                )?,
+            };
+            let bytes = match tokio::fs::read(&part_path).await {
+                Ok(bytes) => bytes,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+            verify_hash(&bytes, &entry.sha256)?;
+
+            let archive_url = self
+                .archive_writer
+                .write_part(&entry.sha256, &bytes)
+                .await?;
+            store.upsert_part_entry(
+                &meta.path,
+                meta.generation,
+                entry.part_no,
+                &entry.sha256,
+                entry.size_bytes,
+                entry.external_path.as_deref(),
+                Some(&archive_url),
+            )?;
+            report.uploaded_parts += 1;
+            report.uploaded_bytes += bytes.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
+
+/// Use of the filesystem holding `path`, in percent, from `df`. `None` when
+/// `df` is missing or its output cannot be read.
+async fn disk_used_percent(path: &Path) -> Option<f64> {
+    let output = tokio::process::Command::new("df")
+        .arg("-Pk")
+        .arg(path)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_df_used_percent(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Reads used and available blocks from the data line of `df -P` output.
+fn parse_df_used_percent(output: &str) -> Option<f64> {
+    let fields: Vec<&str> = output.lines().nth(1)?.split_whitespace().collect();
+    let used: f64 = fields.get(2)?.parse().ok()?;
+    let available: f64 = fields.get(3)?.parse().ok()?;
+    let total = used + available;
+    (total > 0.0).then(|| used / total * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ArchiveListPage, ArchiveStore, ContentHeaders, ManualClock, PART_SIZE, PartIndexState,
+        compute_hash, is_archived_part_url,
+    };
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryArchive {
+        objects: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    #[async_trait]
+    impl ArchiveStore for MemoryArchive {
+        async fn list_blobs_page(
+            &self,
+            _list_key: &str,
+            _cursor: Option<&str>,
+            _limit: usize,
+        ) -> Result<ArchiveListPage> {
+            Ok(ArchiveListPage {
+                entries: Vec::new(),
+                next_cursor: None,
+            })
+        }
+
+        async fn read_range(&self, object_key: &str, start: u64, end: u64) -> Result<Bytes> {
+            let objects = self.objects.lock().unwrap();
+            let object = &objects[object_key];
+            Ok(Bytes::copy_from_slice(
+                &object[start as usize..=end as usize],
+            ))
+        }
+
+        async fn write_blob(&self, object_key: &str, body: &[u8]) -> Result<()> {
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(object_key.to_string(), body.to_vec());
+            Ok(())
+        }
+
+        async fn ping(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn archive_url_for_key(&self, object_key: &str) -> String {
+            format!("mem://archive/{}", object_key)
+        }
+    }
+
+    async fn write_blob(
+        part_store: &PartStore,
+        store: &MetadataStore,
+        path: &str,
+        body: &[u8],
+    ) -> PathBuf {
+        let sha256 = compute_hash(body);
+        let stored = part_store
+            .put_part(1, path, 1, 0, &sha256, Bytes::copy_from_slice(body))
+            .await
+            .unwrap();
+        store
+            .upsert_part_entry(
+                path,
+                1,
+                0,
+                &sha256,
+                body.len() as u64,
+                Some(stored.part_path.to_string_lossy().as_ref()),
+                None,
+            )
+            .unwrap();
+        let meta = BlobMeta {
+            path: path.to_string(),
+            slot_id: 1,
+            generation: 1,
+            version: 1,
+            size_bytes: body.len() as u64,
+            etag: sha256,
+            part_size: PART_SIZE as u64,
+            part_count: 1,
+            part_index_state: PartIndexState::Complete,
+            archive_url: None,
+            updated_at: chrono::Utc::now(),
+            metadata: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            content: ContentHeaders::default(),
+        };
+        let payload = serde_json::to_vec(&meta).unwrap();
+        store
+            .upsert_meta_with_payload(&meta, &payload, &compute_hash(&payload))
+            .unwrap();
+        stored.part_path
+    }
+
+    #[tokio::test]
+    async fn due_parts_are_uploaded_once_and_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().join("meta")).unwrap());
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+        let part_store = Arc::new(PartStore::new(dir.path().join("parts")).unwrap());
+
+        let cold = write_blob(&part_store, &store, "logs/a", b"cold bytes").await;
+        let warm = write_blob(&part_store, &store, "tmp/b", b"warm bytes").await;
+
+        let archive = Arc::new(MemoryArchive::default());
+        let clock = Arc::new(ManualClock::starting_now());
+        clock.advance(chrono::Duration::days(2));
+        let uploader = ArchiveUploader::new(
+            slot_manager,
+            part_store,
+            PutBlobArchiveWriter::new(archive.clone(), "rimio/archive"),
+            ArchiveTieringConfig {
+                eager_sync: false,
+                ..ArchiveTieringConfig::default()
+            },
+        )
+        .with_clock(clock);
+        let rules = vec![LifecycleRule {
+            id: "logs".to_string(),
+            prefix: "logs/".to_string(),
+            expire_after_days: None,
+            transition_after_days: Some(1),
+        }];
+
+        let report = uploader.run_once(&[1], &rules).await.unwrap();
+        assert_eq!(report.uploaded_parts, 1);
+        assert_eq!(report.evicted_parts, 1);
+        assert_eq!(report.freed_bytes, 10);
+        assert!(!cold.exists());
+        assert!(warm.exists());
+
+        let entry = store.get_part_entry("logs/a", 1, 0).unwrap().unwrap();
+        let archive_url = entry.archive_url.unwrap();
+        assert!(is_archived_part_url(&archive_url));
+        let key = format!("rimio/archive/parts/{}", compute_hash(b"cold bytes"));
+        assert_eq!(archive.objects.lock().unwrap()[&key], b"cold bytes");
+
+        let report = uploader.run_once(&[1], &rules).await.unwrap();
+        assert_eq!(report.uploaded_parts + report.evicted_parts, 0);
+    }
+
+    #[test]
+    fn df_output_gives_the_used_share() {
+        let output = "Filesystem     1024-blocks     Used Available Capacity Mounted on\n\
+                      /dev/nvme0n1p1   1000000   750000    250000      75% /data\n";
+        assert_eq!(parse_df_used_percent(output), Some(75.0));
+        assert_eq!(parse_df_used_percent("Filesystem\n"), None);
+        assert_eq!(parse_df_used_percent(""), None);
+    }
+
+    #[test]
+    fn tiering_defaults_keep_eager_sync() {
+        let config: ArchiveTieringConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, ArchiveTieringConfig::default());
+        assert!(config.eager_sync && config.evict_local);
+        assert!(config.disk_high_percent.is_none());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::error::{Result, RimError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A credential the HTTP API accepts, and what it may reach.
+///
+/// A key is either a static secret, of which only the sha256 is kept, or a
+/// signing secret for HS256 JWTs whose `kid` header names the key; it may be
+/// both. Keys are kept in the registry so every node checks requests against
+/// the same set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    /// sha256 (hex) of the secret clients send.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_sha256: Option<String>,
+    /// HMAC secret of the JWTs this key signs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwt_secret: Option<String>,
+    /// A request needs one grant that matches it.
+    #[serde(default)]
+    pub allow: Vec<ApiKeyGrant>,
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+/// Requests to paths under `prefix` with one of `methods`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyGrant {
+    pub prefix: String,
+    /// Empty allows every method. `GET` also allows `HEAD`.
+    #[serde(default)]
+    pub methods: Vec<String>,
+}
+
+impl ApiKey {
+    pub fn allows(&self, method: &str, path: &str) -> bool {
+        !self.disabled && self.allow.iter().any(|grant| grant.allows(method, path))
+    }
+}
+
+impl ApiKeyGrant {
+    pub fn allows(&self, method: &str, path: &str) -> bool {
+        if !path.starts_with(&self.prefix) {
+            return false;
+        }
+        self.methods.is_empty()
+            || self.methods.iter().any(|allowed| {
+                allowed.eq_ignore_ascii_case(method)
+                    || (allowed.eq_ignore_ascii_case("GET") && method.eq_ignore_ascii_case("HEAD"))
+            })
+    }
+}
+
+/// Checks that key ids are unique, that every key carries a credential and
+/// that grants name absolute path prefixes.
+pub fn validate_api_keys(keys: &[ApiKey]) -> Result<()> {
+    let mut ids = HashSet::new();
+    for key in keys {
+        if key.id.trim().is_empty() {
+            return Err(RimError::InvalidRequest(
+                "api key id must not be empty".to_string(),
+            ));
+        }
+        if !ids.insert(key.id.as_str()) {
+            return Err(RimError::InvalidRequest(format!(
+                "duplicate api key id: {}",
+                key.id
+            )));
+        }
+        if let Some(hash) = key.secret_sha256.as_deref()
+            && (hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Err(RimError::InvalidRequest(format!(
+                "api key {}: secret_sha256 must be 64 hex characters",
+                key.id
+            )));
+        }
+        if key.jwt_secret.as_deref().is_some_and(str::is_empty) {
+            return Err(RimError::InvalidRequest(format!(
+                "api key {}: jwt_secret must not be empty",
+                key.id
+            )));
+        }
+        if key.secret_sha256.is_none() && key.jwt_secret.is_none() {
+            return Err(RimError::InvalidRequest(format!(
+                "api key {} needs secret_sha256 or jwt_secret",
+                key.id
+            )));
+        }
+        if let Some(grant) = key
+            .allow
+            .iter()
+            .find(|grant| !grant.prefix.starts_with('/'))
+        {
+            return Err(RimError::InvalidRequest(format!(
+                "api key {}: grant prefix must start with '/': {}",
+                key.id, grant.prefix
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(allow: Vec<ApiKeyGrant>) -> ApiKey {
+        ApiKey {
+            id: "edge".to_string(),
+            secret_sha256: Some("a".repeat(64)),
+            jwt_secret: None,
+            allow,
+            disabled: false,
+        }
+    }
+
+    #[test]
+    fn grants_match_prefix_and_method() {
+        let key = key(vec![ApiKeyGrant {
+            prefix: "/_/api/v1/blobs/uploads/".to_string(),
+            methods: vec!["get".to_string(), "PUT".to_string()],
+        }]);
+
+        assert!(key.allows("PUT", "/_/api/v1/blobs/uploads/a.bin"));
+        assert!(key.allows("HEAD", "/_/api/v1/blobs/uploads/a.bin"));
+        assert!(!key.allows("DELETE", "/_/api/v1/blobs/uploads/a.bin"));
+        assert!(!key.allows("GET", "/_/api/v1/blobs/logs/a.bin"));
+        assert!(
+            !ApiKey {
+                disabled: true,
+                ..key
+            }
+            .allows("GET", "/_/api/v1/blobs/uploads/a.bin")
+        );
+    }
+
+    #[test]
+    fn validation_rejects_unusable_keys() {
+        let grant = ApiKeyGrant {
+            prefix: "/".to_string(),
+            methods: Vec::new(),
+        };
+        assert!(validate_api_keys(&[key(vec![grant.clone()])]).is_ok());
+        assert!(validate_api_keys(&[key(vec![grant.clone()]), key(vec![grant.clone()])]).is_err());
+        assert!(
+            validate_api_keys(&[ApiKey {
+                secret_sha256: None,
+                ..key(vec![grant.clone()])
+            }])
+            .is_err()
+        );
+        assert!(
+            validate_api_keys(&[key(vec![ApiKeyGrant {
+                prefix: "blobs/".to_string(),
+                methods: Vec::new(),
+            }])])
+            .is_err()
+        );
+    }
+}
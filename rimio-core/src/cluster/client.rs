@@ -1,17 +1,44 @@
-use super::types::ReplicatedPart;
+use super::grpc::{
+    ApplyHeadsRequest, DecideTransactionRequest, GetHeadRequest, GetPartRequest, HeadRecord,
+    InternalGrpcConfig, InternalGrpcTransport, PrepareTransactionRequest, PutHeadRequest,
+    PutPartRequest, http_status,
+};
+use super::peer_latency::{PeerCall, PeerLatencyTracker};
+use super::placement::{PlacementMap, SLOT_EPOCH_HEADER};
+use super::protocol::{
+    CAP_DELETE_PREFIX, CAP_HEAD_BATCH, CAP_HEAD_CHAIN, CAP_PART_LINK, CAP_PART_PROBE,
+    CAP_PLACEMENT_RELOAD, CAP_PRUNE_VERSIONS, CAP_SLOT_HANDOFF, CAP_SLOT_HEAT, CAP_SLOT_STATS,
+    CAP_SLOT_TRANSFER, CAP_TOMBSTONE_BATCH, PeerProtocol, PeerProtocolTable, cluster_key_headers,
+};
+use super::slot_heat::SlotHeatReport;
+use super::types::{PartSource, ReplicatedHead, ReplicatedPart};
 use crate::{
-    BlobHead, BlobMeta, HeadKind, NodeInfo, Registry, Result, RimError, TombstoneMeta, compute_hash,
+    BlobHead, BlobMeta, DeletePrefixSlotReport, HeadChainReport, HeadKind, HeadWrite, HealHeadItem,
+    HealTombstoneItem, NodeInfo, PruneVersionsOperationResult, Registry, Result, RimError,
+    SlotStats, SlotTransferManifest, SlotTransferOperationResult, TombstoneMeta, TransactionPeers,
+    TransactionState, Vote, compute_hash,
 };
 use chrono::Utc;
+use futures_util::{StreamExt, TryStreamExt, stream};
 use reqwest::{
-    Client, Url,
-    header::{self, HeaderMap},
+    Client, RequestBuilder, Url,
+    header::{self, HeaderMap, HeaderValue},
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tonic::Status;
 
 const PART_INDEX_SENTINEL_SHA256: &str = "_";
 
+/// Parts in flight per replica. Part bodies are shared slices of the blob, so
+/// this bounds the requests, not copies of the data.
+const PART_UPLOAD_CONCURRENCY: usize = 4;
+
+/// Parts at least this large are probed before upload; below it the extra
+/// round trip costs about as much as resending the bytes.
+const PART_PROBE_MIN_BYTES: u64 = 1024 * 1024;
+
 #[derive(Debug, Clone, Serialize)]
 struct InternalHeadApplyRequest {
     head_kind: String,
@@ -21,6 +48,142 @@ struct InternalHeadApplyRequest {
     tombstone: Option<TombstoneMeta>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+struct InternalHeadBatchApplyItem {
+    path: String,
+    head_kind: String,
+    generation: i64,
+    head_sha256: String,
+    meta: Option<BlobMeta>,
+    tombstone: Option<TombstoneMeta>,
+}
+
+impl InternalHeadBatchApplyItem {
+    fn from_write(write: &HeadWrite) -> Self {
+        let (head_kind, meta, tombstone) = match write {
+            HeadWrite::Meta { meta, .. } => ("meta", Some(meta.clone()), None),
+            HeadWrite::Tombstone { tombstone, .. } => ("tombstone", None, Some(tombstone.clone())),
+        };
+
+        Self {
+            path: write.path().to_string(),
+            head_kind: head_kind.to_string(),
+            generation: write.generation(),
+            head_sha256: write.head_sha256().to_string(),
+            meta,
+            tombstone,
+        }
+    }
+}
+
+impl InternalHeadBatchApplyItem {
+    fn into_single(self) -> (String, InternalHeadApplyRequest) {
+        (
+            self.path,
+            InternalHeadApplyRequest {
+                head_kind: self.head_kind,
+                generation: self.generation,
+                head_sha256: self.head_sha256,
+                meta: self.meta,
+                tombstone: self.tombstone,
+            },
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct InternalHeadBatchApplyRequest {
+    heads: Vec<InternalHeadBatchApplyItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct InternalPrepareRequest<'a> {
+    peers: &'a TransactionPeers,
+    heads: Vec<InternalHeadBatchApplyItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InternalTransactionStateResponse {
+    state: TransactionState,
+}
+
+#[derive(Debug, Deserialize)]
+struct InternalPrepareResponse {
+    vote: String,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    locked_by: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct InternalLinkPartsRequest<'a> {
+    source: &'a PartSource,
+    path: &'a str,
+    generation: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct InternalPullRequest<'a> {
+    source_node_id: &'a str,
+    blob_paths: [&'a str; 1],
+}
+
+#[derive(Debug, Serialize)]
+struct InternalDeletePrefixRequest<'a> {
+    prefix: &'a str,
+    page_size: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct InternalHandoffPullRequest<'a> {
+    source_node_id: &'a str,
+    blob_paths: &'a [String],
+    replacing: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct InternalSlotTransferPullRequest<'a> {
+    source_node_id: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct InternalSlotletsResponse {
+    slotlets: Vec<InternalSlotlet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InternalSlotlet {
+    prefix: String,
+}
+
+#[derive(Debug, Serialize)]
+struct InternalHealHeadsRequest<'a> {
+    prefixes: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct InternalHealHeadsResponse {
+    heads: Vec<HealHeadItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct InternalHealTombstonesRequest<'a> {
+    paths: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct InternalHealTombstonesResponse {
+    tombstones: Vec<HealTombstoneItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InternalPullResponse {
+    repaired_objects: usize,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct InternalHeadResponsePayload {
     found: bool,
@@ -41,14 +204,186 @@ pub struct ClusterPartPayload {
 pub struct ClusterClient {
     client: Client,
     registry: Arc<dyn Registry>,
+    placement: Arc<PlacementMap>,
+    protocols: Arc<PeerProtocolTable>,
+    latency: Arc<PeerLatencyTracker>,
+    grpc: Option<Arc<InternalGrpcTransport>>,
+    cluster_key: Option<String>,
 }
 
 impl ClusterClient {
     pub fn new(registry: Arc<dyn Registry>) -> Self {
+        let client = Client::builder()
+            .default_headers(PeerProtocol::local_headers())
+            .build()
+            .unwrap_or_default();
+
         Self {
-            client: Client::new(),
+            client,
+            placement: Arc::new(PlacementMap::new(registry.clone())),
             registry,
+            protocols: Arc::new(PeerProtocolTable::new()),
+            latency: Arc::new(PeerLatencyTracker::new()),
+            grpc: None,
+            cluster_key: None,
+        }
+    }
+
+    /// Sends head, part and transaction calls over the internal gRPC
+    /// transport to peers that advertise a gRPC address.
+    pub fn with_grpc(mut self, config: Option<&InternalGrpcConfig>) -> Self {
+        self.grpc = config.map(|config| {
+            Arc::new(
+                InternalGrpcTransport::new(config).with_cluster_key(self.cluster_key.as_deref()),
+            )
+        });
+        self
+    }
+
+    /// Presents `cluster_key` on every call to a peer, which nodes with auth
+    /// enabled require on their internal routes.
+    pub fn with_cluster_key(mut self, cluster_key: Option<&str>) -> Self {
+        let mut headers = PeerProtocol::local_headers();
+        headers.extend(cluster_key_headers(cluster_key));
+        self.client = Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap_or_default();
+        self.cluster_key = cluster_key.map(str::to_string);
+        // The transport is not shared yet while the client is being built.
+        if let Some(grpc) = self.grpc.take() {
+            self.grpc = Some(match Arc::try_unwrap(grpc) {
+                Ok(grpc) => Arc::new(grpc.with_cluster_key(cluster_key)),
+                Err(grpc) => grpc,
+            });
+        }
+        self
+    }
+
+    /// Placement map used to stamp internal writes with the slot epoch.
+    pub fn placement(&self) -> &Arc<PlacementMap> {
+        &self.placement
+    }
+
+    /// Protocols learned from peers through the handshake.
+    pub fn protocols(&self) -> &Arc<PeerProtocolTable> {
+        &self.protocols
+    }
+
+    /// Latency of the head and part calls this node made to its peers.
+    pub fn latency(&self) -> &Arc<PeerLatencyTracker> {
+        &self.latency
+    }
+
+    /// Sends an internal head or part call and records its latency against
+    /// the peer and the slot. Transport failures and 5xx answers count as
+    /// errors.
+    async fn send_timed(
+        &self,
+        call: PeerCall,
+        node_id: &str,
+        slot_id: u16,
+        request: RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let started = Instant::now();
+        let result = request.send().await;
+        let ok = result
+            .as_ref()
+            .is_ok_and(|response| !response.status().is_server_error());
+        self.latency
+            .record(node_id, slot_id, call, started.elapsed(), ok);
+        result.map_err(|error| RimError::Http(error.to_string()))
+    }
+
+    /// Like [`Self::send_timed`] for a gRPC call; statuses that map to 5xx
+    /// count as errors.
+    async fn grpc_timed<T>(
+        &self,
+        call: PeerCall,
+        node_id: &str,
+        slot_id: u16,
+        request: impl std::future::Future<Output = std::result::Result<T, Status>>,
+    ) -> std::result::Result<T, Status> {
+        let started = Instant::now();
+        let result = request.await;
+        let ok = result
+            .as_ref()
+            .map_or_else(|status| !http_status(status).is_server_error(), |_| true);
+        self.latency
+            .record(node_id, slot_id, call, started.elapsed(), ok);
+        result
+    }
+
+    /// The gRPC transport and the peer's gRPC address, when both ends run
+    /// the internal gRPC transport.
+    fn grpc_route(&self, node: &NodeInfo) -> Option<(&InternalGrpcTransport, String)> {
+        let grpc = self.grpc.as_deref()?;
+        grpc.address(node)
+            .map(|address| (grpc, address.to_string()))
+    }
+
+    async fn grpc_peer(&self, node_id: &str) -> Result<Option<(&InternalGrpcTransport, String)>> {
+        if self.grpc.is_none() {
+            return Ok(None);
+        }
+        let node = self.resolve_node(node_id).await?;
+        Ok(self.grpc_route(&node))
+    }
+
+    /// Turns a failed gRPC write into the error the HTTP route would have
+    /// produced, invalidating the slot placement on a fencing rejection.
+    async fn grpc_write_error(
+        &self,
+        slot_id: u16,
+        status: Status,
+        describe: impl FnOnce(reqwest::StatusCode) -> String,
+    ) -> RimError {
+        let code = http_status(&status);
+        self.note_rejection(slot_id, code).await;
+        RimError::Http(format!("{} message={}", describe(code), status.message()))
+    }
+
+    /// Returns the internal protocol a peer speaks, asking it when the cached
+    /// answer is missing or stale. A peer without the handshake route
+    /// predates versioning and is treated as [`PeerProtocol::legacy`].
+    pub async fn peer_protocol(&self, node_id: &str) -> Result<PeerProtocol> {
+        if let Some(protocol) = self.protocols.get(node_id).await {
+            return Ok(protocol);
+        }
+
+        let node = self.resolve_node(node_id).await?;
+        let url = format!("http://{}/internal/v1/protocol", node.address);
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        let protocol = if response.status() == reqwest::StatusCode::NOT_FOUND {
+            PeerProtocol::legacy()
+        } else if response.status().is_success() {
+            response
+                .json()
+                .await
+                .map_err(|error| RimError::Http(error.to_string()))?
+        } else {
+            return Err(RimError::Http(format!(
+                "protocol handshake failed: node={} status={}",
+                node_id,
+                response.status()
+            )));
+        };
+
+        if !protocol.is_compatible() {
+            return Err(RimError::Http(format!(
+                "peer protocol too old: node={} version={}",
+                node_id, protocol.version
+            )));
         }
+
+        self.protocols.record(node_id, protocol.clone()).await;
+        Ok(protocol)
     }
 
     pub async fn replicate_meta_write(
@@ -63,41 +398,25 @@ impl ClusterClient {
         head_sha256: &str,
     ) -> Result<()> {
         let target = self.resolve_node(target_node_id).await?;
+        self.put_replica_parts(&target.node_id, slot_id, path, write_id, generation, parts)
+            .await?;
 
-        for part in parts {
-            let part_url = self
-                .internal_part_url_by_sha(
-                    &target.node_id,
-                    slot_id,
-                    &part.sha256,
-                    path,
-                    generation,
-                    part.part_no,
-                )
-                .await?;
-
-            let response = self
-                .client
-                .put(part_url)
-                .header("x-rimio-write-id", write_id)
-                .header("x-rimio-generation", generation.to_string())
-                .header("x-rimio-part-no", part.part_no.to_string())
-                .header("x-rimio-part-length", part.length.to_string())
-                .header(header::CONTENT_TYPE, "application/octet-stream")
-                .body(part.data.clone())
-                .send()
-                .await
-                .map_err(|error| RimError::Http(error.to_string()))?;
-
-            if !response.status().is_success() {
-                return Err(RimError::Http(format!(
-                    "replica part write failed: node={} status={} part_no={} path={}",
-                    target.node_id,
-                    response.status(),
-                    part.part_no,
-                    path
-                )));
-            }
+        if let Some((grpc, address)) = self.grpc_route(&target) {
+            let head = HeadRecord::new(path, "meta", generation, head_sha256, Some(meta), None)?;
+            let result = self
+                .put_head_grpc(grpc, &address, &target.node_id, slot_id, write_id, head)
+                .await;
+            return match result {
+                Ok(()) => Ok(()),
+                Err(status) => Err(self
+                    .grpc_write_error(slot_id, status, |code| {
+                        format!(
+                            "replica head write failed: node={} status={} path={}",
+                            target.node_id, code, path
+                        )
+                    })
+                    .await),
+            };
         }
 
         let head_url = self
@@ -111,17 +430,22 @@ impl ClusterClient {
             tombstone: None,
         };
 
-        let response = self
+        let request = self
             .client
             .put(head_url)
+            .header(
+                SLOT_EPOCH_HEADER,
+                self.placement.epoch(slot_id).await.to_string(),
+            )
             .header("x-rimio-write-id", write_id)
             .header(header::CONTENT_TYPE, "application/json")
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|error| RimError::Http(error.to_string()))?;
+            .json(&payload);
+        let response = self
+            .send_timed(PeerCall::HeadWrite, &target.node_id, slot_id, request)
+            .await?;
 
         if !response.status().is_success() {
+            self.note_rejection(slot_id, response.status()).await;
             return Err(RimError::Http(format!(
                 "replica head write failed: node={} status={} path={}",
                 target.node_id,
@@ -133,159 +457,1348 @@ impl ClusterClient {
         Ok(())
     }
 
-    pub async fn replicate_tombstone_write(
+    /// Ships every part of the batch, then applies all heads on the replica
+    /// in one metadata transaction.
+    pub async fn replicate_head_batch(
         &self,
         target_node_id: &str,
         slot_id: u16,
-        path: &str,
         write_id: &str,
-        generation: i64,
-        tombstone: &TombstoneMeta,
-        head_sha256: &str,
+        heads: &[ReplicatedHead],
+    ) -> Result<()> {
+        self.stage_head_parts(target_node_id, slot_id, write_id, heads)
+            .await?;
+        self.commit_head_batch(target_node_id, slot_id, write_id, heads)
+            .await
+    }
+
+    /// Uploads the parts referenced by `heads` without making any head
+    /// visible. Heads linked to another version have the replica index that
+    /// version's parts for them instead.
+    pub async fn stage_head_parts(
+        &self,
+        target_node_id: &str,
+        slot_id: u16,
+        write_id: &str,
+        heads: &[ReplicatedHead],
     ) -> Result<()> {
         let target = self.resolve_node(target_node_id).await?;
 
-        let head_url = self
-            .internal_head_url(&target.node_id, slot_id, path)
+        for head in heads {
+            if let Some(source) = &head.linked_from {
+                self.link_replica_parts(
+                    &target,
+                    slot_id,
+                    write_id,
+                    source,
+                    head.write.path(),
+                    head.write.generation(),
+                )
+                .await?;
+                continue;
+            }
+            self.put_replica_parts(
+                &target.node_id,
+                slot_id,
+                head.write.path(),
+                write_id,
+                head.write.generation(),
+                &head.parts,
+            )
             .await?;
-        let payload = InternalHeadApplyRequest {
-            head_kind: "tombstone".to_string(),
-            generation,
-            head_sha256: head_sha256.to_string(),
-            meta: None,
-            tombstone: Some(tombstone.clone()),
-        };
+        }
 
-        let response = self
+        Ok(())
+    }
+
+    async fn link_replica_parts(
+        &self,
+        target: &NodeInfo,
+        slot_id: u16,
+        write_id: &str,
+        source: &PartSource,
+        path: &str,
+        generation: i64,
+    ) -> Result<()> {
+        if !self
+            .peer_protocol(&target.node_id)
+            .await?
+            .supports(CAP_PART_LINK)
+        {
+            return Err(RimError::Http(format!(
+                "replica cannot link parts: node={} path={}",
+                target.node_id, path
+            )));
+        }
+
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/parts/link",
+            target.address, slot_id
+        );
+        let request = self
             .client
-            .put(head_url)
+            .post(url)
+            .header(
+                SLOT_EPOCH_HEADER,
+                self.placement.epoch(slot_id).await.to_string(),
+            )
             .header("x-rimio-write-id", write_id)
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|error| RimError::Http(error.to_string()))?;
+            .json(&InternalLinkPartsRequest {
+                source,
+                path,
+                generation,
+            });
+        let response = self
+            .send_timed(PeerCall::PartWrite, &target.node_id, slot_id, request)
+            .await?;
 
         if !response.status().is_success() {
+            self.note_rejection(slot_id, response.status()).await;
             return Err(RimError::Http(format!(
-                "replica tombstone write failed: node={} status={} path={}",
+                "replica part link failed: node={} status={} path={} source={}@{}",
                 target.node_id,
                 response.status(),
-                path
+                path,
+                source.path,
+                source.generation
             )));
         }
-
         Ok(())
     }
 
-    pub async fn replicate_archive_meta_update(
+    /// Applies `heads` on the replica in one metadata transaction. The parts
+    /// must already be staged there.
+    pub async fn commit_head_batch(
         &self,
         target_node_id: &str,
         slot_id: u16,
-        path: &str,
-        meta: &BlobMeta,
+        write_id: &str,
+        heads: &[ReplicatedHead],
     ) -> Result<()> {
         let target = self.resolve_node(target_node_id).await?;
+        if let Some((grpc, address)) = self.grpc_route(&target) {
+            let request = ApplyHeadsRequest {
+                slot_id: slot_id.into(),
+                epoch: self.placement.epoch(slot_id).await,
+                write_id: write_id.to_string(),
+                heads: heads
+                    .iter()
+                    .map(|head| HeadRecord::from_write(&head.write))
+                    .collect::<Result<_>>()?,
+            };
+            let result = self
+                .grpc_timed(
+                    PeerCall::HeadWrite,
+                    &target.node_id,
+                    slot_id,
+                    grpc.apply_heads(&address, request),
+                )
+                .await;
+            return match result {
+                Ok(_) => Ok(()),
+                Err(status) => Err(self
+                    .grpc_write_error(slot_id, status, |code| {
+                        format!(
+                            "replica head batch write failed: node={} status={} slot={} heads={}",
+                            target.node_id,
+                            code,
+                            slot_id,
+                            heads.len()
+                        )
+                    })
+                    .await),
+            };
+        }
 
-        let head_url = self
-            .internal_head_url(&target.node_id, slot_id, path)
-            .await?;
+        if !self
+            .peer_protocol(&target.node_id)
+            .await?
+            .supports(CAP_HEAD_BATCH)
+        {
+            return self
+                .commit_heads_one_by_one(&target.node_id, slot_id, write_id, heads)
+                .await;
+        }
 
-        let payload_bytes = serde_json::to_vec(meta)?;
-        let payload = InternalHeadApplyRequest {
-            head_kind: "meta".to_string(),
-            generation: meta.generation,
-            head_sha256: compute_hash(&payload_bytes),
-            meta: Some(meta.clone()),
-            tombstone: None,
+        let url = Url::parse(&format!(
+            "http://{}/internal/v1/slots/{}/heads/batch",
+            target.address, slot_id
+        ))
+        .map_err(|error| RimError::Http(error.to_string()))?;
+
+        let payload = InternalHeadBatchApplyRequest {
+            heads: heads
+                .iter()
+                .map(|head| InternalHeadBatchApplyItem::from_write(&head.write))
+                .collect(),
         };
 
-        let response = self
+        let request = self
             .client
-            .put(head_url)
+            .put(url)
             .header(
-                "x-rimio-write-id",
-                format!("archive-sync-{}", ulid::Ulid::new()),
+                SLOT_EPOCH_HEADER,
+                self.placement.epoch(slot_id).await.to_string(),
             )
+            .header("x-rimio-write-id", write_id)
             .header(header::CONTENT_TYPE, "application/json")
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|error| RimError::Http(error.to_string()))?;
+            .json(&payload);
+        let response = self
+            .send_timed(PeerCall::HeadWrite, &target.node_id, slot_id, request)
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            // The peer was downgraded since we last asked.
+            self.protocols.forget(&target.node_id).await;
+            return self
+                .commit_heads_one_by_one(&target.node_id, slot_id, write_id, heads)
+                .await;
+        }
 
         if !response.status().is_success() {
+            self.note_rejection(slot_id, response.status()).await;
             return Err(RimError::Http(format!(
-                "archive replica head write failed: node={} status={} path={}",
+                "replica head batch write failed: node={} status={} slot={} heads={}",
                 target.node_id,
                 response.status(),
-                path
+                slot_id,
+                heads.len()
             )));
         }
 
         Ok(())
     }
 
-    pub async fn fetch_remote_head(
+    /// Asks a replica to vote on `heads` for transaction `txn_id`. The parts
+    /// must already be staged there; on yes the replica holds the heads until
+    /// [`Self::commit_transaction`] or [`Self::abort_transaction`].
+    pub async fn prepare_transaction(
         &self,
-        source_node_id: &str,
+        target_node_id: &str,
         slot_id: u16,
-        path: &str,
-    ) -> Result<Option<BlobHead>> {
-        let head_url = self
-            .internal_head_url(source_node_id, slot_id, path)
-            .await?;
-        let response = self
-            .client
-            .get(head_url)
-            .send()
-            .await
-            .map_err(|error| RimError::Http(error.to_string()))?;
+        txn_id: &str,
+        peers: &TransactionPeers,
+        heads: &[ReplicatedHead],
+    ) -> Result<Vote> {
+        let target = self.resolve_node(target_node_id).await?;
+        if let Some((grpc, address)) = self.grpc_route(&target) {
+            let request = PrepareTransactionRequest {
+                slot_id: slot_id.into(),
+                epoch: self.placement.epoch(slot_id).await,
+                txn_id: txn_id.to_string(),
+                coordinator: peers.coordinator.clone(),
+                participants: peers.participants.clone(),
+                heads: heads
+                    .iter()
+                    .map(|head| HeadRecord::from_write(&head.write))
+                    .collect::<Result<_>>()?,
+            };
+            let result = self
+                .grpc_timed(
+                    PeerCall::HeadWrite,
+                    &target.node_id,
+                    slot_id,
+                    grpc.prepare_transaction(&address, request),
+                )
+                .await;
+            return match result {
+                Ok(response) if response.vote_yes => Ok(Vote::Yes),
+                Ok(response) if !response.locked_by.is_empty() => Ok(Vote::Locked {
+                    holder: response.locked_by,
+                    reason: response.reason,
+                }),
+                Ok(response) if response.reason.is_empty() => {
+                    Ok(Vote::No("replica voted no".to_string()))
+                }
+                Ok(response) => Ok(Vote::No(response.reason)),
+                Err(status) => Err(self
+                    .grpc_write_error(slot_id, status, |code| {
+                        format!(
+                            "replica transaction prepare failed: node={} status={} slot={} txn={}",
+                            target.node_id, code, slot_id, txn_id
+                        )
+                    })
+                    .await),
+            };
+        }
+
+        let url = self.transaction_url(&target, slot_id, txn_id, "prepare")?;
+
+        let payload = InternalPrepareRequest {
+            peers,
+            heads: heads
+                .iter()
+                .map(|head| InternalHeadBatchApplyItem::from_write(&head.write))
+                .collect(),
+        };
+
+        let request = self
+            .client
+            .post(url)
+            .header(
+                SLOT_EPOCH_HEADER,
+                self.placement.epoch(slot_id).await.to_string(),
+            )
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(&payload);
+        let response = self
+            .send_timed(PeerCall::HeadWrite, &target.node_id, slot_id, request)
+            .await?;
+
+        if !response.status().is_success() {
+            self.note_rejection(slot_id, response.status()).await;
+            return Err(RimError::Http(format!(
+                "replica transaction prepare failed: node={} status={} slot={} txn={}",
+                target.node_id,
+                response.status(),
+                slot_id,
+                txn_id
+            )));
+        }
+
+        let payload: InternalPrepareResponse = response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        let reason = payload
+            .reason
+            .unwrap_or_else(|| "replica voted no".to_string());
+        match (payload.vote.as_str(), payload.locked_by) {
+            ("yes", _) => Ok(Vote::Yes),
+            (_, Some(holder)) => Ok(Vote::Locked { holder, reason }),
+            _ => Ok(Vote::No(reason)),
+        }
+    }
+
+    /// Commits the batch a replica prepared for `txn_id`.
+    pub async fn commit_transaction(
+        &self,
+        target_node_id: &str,
+        slot_id: u16,
+        txn_id: &str,
+    ) -> Result<()> {
+        self.decide_transaction(target_node_id, slot_id, txn_id, "commit")
+            .await
+    }
+
+    /// Releases the batch a replica prepared for `txn_id`.
+    pub async fn abort_transaction(
+        &self,
+        target_node_id: &str,
+        slot_id: u16,
+        txn_id: &str,
+    ) -> Result<()> {
+        self.decide_transaction(target_node_id, slot_id, txn_id, "abort")
+            .await
+    }
+
+    /// Asks a peer what it knows about `txn_id`.
+    pub async fn fetch_transaction_state(
+        &self,
+        node_id: &str,
+        txn_id: &str,
+    ) -> Result<TransactionState> {
+        let node = self.resolve_node(node_id).await?;
+        let url = Url::parse(&format!(
+            "http://{}/internal/v1/transactions/{}",
+            node.address, txn_id
+        ))
+        .map_err(|error| RimError::Http(error.to_string()))?;
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal transaction state fetch failed: node={} status={} txn={}",
+                node_id,
+                response.status(),
+                txn_id
+            )));
+        }
+
+        let payload: InternalTransactionStateResponse = response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        Ok(payload.state)
+    }
+
+    async fn decide_transaction(
+        &self,
+        target_node_id: &str,
+        slot_id: u16,
+        txn_id: &str,
+        decision: &str,
+    ) -> Result<()> {
+        let target = self.resolve_node(target_node_id).await?;
+        let status = if let Some((grpc, address)) = self.grpc_route(&target) {
+            let request = DecideTransactionRequest {
+                slot_id: slot_id.into(),
+                txn_id: txn_id.to_string(),
+                commit: decision == "commit",
+            };
+            match self
+                .grpc_timed(
+                    PeerCall::HeadWrite,
+                    &target.node_id,
+                    slot_id,
+                    grpc.decide_transaction(&address, request),
+                )
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err(status) => http_status(&status),
+            }
+        } else {
+            let url = self.transaction_url(&target, slot_id, txn_id, decision)?;
+            let request = self.client.post(url);
+            let response = self
+                .send_timed(PeerCall::HeadWrite, &target.node_id, slot_id, request)
+                .await?;
+            response.status()
+        };
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let message = format!(
+            "replica transaction {} failed: node={} status={} slot={} txn={}",
+            decision, target.node_id, status, slot_id, txn_id
+        );
+        if status.is_client_error() {
+            // The replica lost or never had the batch; retrying will not help.
+            return Err(RimError::InvalidRequest(message));
+        }
+        Err(RimError::Http(message))
+    }
+
+    fn transaction_url(
+        &self,
+        target: &NodeInfo,
+        slot_id: u16,
+        txn_id: &str,
+        action: &str,
+    ) -> Result<Url> {
+        Url::parse(&format!(
+            "http://{}/internal/v1/slots/{}/transactions/{}/{}",
+            target.address, slot_id, txn_id, action
+        ))
+        .map_err(|error| RimError::Http(error.to_string()))
+    }
+
+    /// Applies heads on a peer without batch support, one request each. The
+    /// heads become visible one by one instead of atomically.
+    async fn commit_heads_one_by_one(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        write_id: &str,
+        heads: &[ReplicatedHead],
+    ) -> Result<()> {
+        tracing::warn!(
+            "peer lacks {}, applying heads one by one: node={} slot={} heads={}",
+            CAP_HEAD_BATCH,
+            node_id,
+            slot_id,
+            heads.len()
+        );
+
+        for head in heads {
+            let (path, payload) = InternalHeadBatchApplyItem::from_write(&head.write).into_single();
+            let head_url = self.internal_head_url(node_id, slot_id, &path).await?;
+            let request = self
+                .client
+                .put(head_url)
+                .header(
+                    SLOT_EPOCH_HEADER,
+                    self.placement.epoch(slot_id).await.to_string(),
+                )
+                .header("x-rimio-write-id", write_id)
+                .header(header::CONTENT_TYPE, "application/json")
+                .json(&payload);
+            let response = self
+                .send_timed(PeerCall::HeadWrite, node_id, slot_id, request)
+                .await?;
+
+            if !response.status().is_success() {
+                self.note_rejection(slot_id, response.status()).await;
+                return Err(RimError::Http(format!(
+                    "replica head write failed: node={} status={} path={}",
+                    node_id,
+                    response.status(),
+                    path
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn replicate_tombstone_write(
+        &self,
+        target_node_id: &str,
+        slot_id: u16,
+        path: &str,
+        write_id: &str,
+        generation: i64,
+        tombstone: &TombstoneMeta,
+        head_sha256: &str,
+    ) -> Result<()> {
+        let target = self.resolve_node(target_node_id).await?;
+
+        if let Some((grpc, address)) = self.grpc_route(&target) {
+            let head = HeadRecord::new(
+                path,
+                "tombstone",
+                generation,
+                head_sha256,
+                None,
+                Some(tombstone),
+            )?;
+            let result = self
+                .put_head_grpc(grpc, &address, &target.node_id, slot_id, write_id, head)
+                .await;
+            return match result {
+                Ok(()) => Ok(()),
+                Err(status) => Err(self
+                    .grpc_write_error(slot_id, status, |code| {
+                        format!(
+                            "replica tombstone write failed: node={} status={} path={}",
+                            target.node_id, code, path
+                        )
+                    })
+                    .await),
+            };
+        }
+
+        let head_url = self
+            .internal_head_url(&target.node_id, slot_id, path)
+            .await?;
+        let payload = InternalHeadApplyRequest {
+            head_kind: "tombstone".to_string(),
+            generation,
+            head_sha256: head_sha256.to_string(),
+            meta: None,
+            tombstone: Some(tombstone.clone()),
+        };
+
+        let request = self
+            .client
+            .put(head_url)
+            .header(
+                SLOT_EPOCH_HEADER,
+                self.placement.epoch(slot_id).await.to_string(),
+            )
+            .header("x-rimio-write-id", write_id)
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(&payload);
+        let response = self
+            .send_timed(PeerCall::HeadWrite, &target.node_id, slot_id, request)
+            .await?;
+
+        if !response.status().is_success() {
+            self.note_rejection(slot_id, response.status()).await;
+            return Err(RimError::Http(format!(
+                "replica tombstone write failed: node={} status={} path={}",
+                target.node_id,
+                response.status(),
+                path
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn replicate_archive_meta_update(
+        &self,
+        target_node_id: &str,
+        slot_id: u16,
+        path: &str,
+        meta: &BlobMeta,
+    ) -> Result<()> {
+        let target = self.resolve_node(target_node_id).await?;
+
+        let head_url = self
+            .internal_head_url(&target.node_id, slot_id, path)
+            .await?;
+
+        let payload_bytes = serde_json::to_vec(meta)?;
+        let payload = InternalHeadApplyRequest {
+            head_kind: "meta".to_string(),
+            generation: meta.generation,
+            head_sha256: compute_hash(&payload_bytes),
+            meta: Some(meta.clone()),
+            tombstone: None,
+        };
+
+        let request = self
+            .client
+            .put(head_url)
+            .header(
+                SLOT_EPOCH_HEADER,
+                self.placement.epoch(slot_id).await.to_string(),
+            )
+            .header(
+                "x-rimio-write-id",
+                format!("archive-sync-{}", ulid::Ulid::new()),
+            )
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(&payload);
+        let response = self
+            .send_timed(PeerCall::HeadWrite, &target.node_id, slot_id, request)
+            .await?;
+
+        if !response.status().is_success() {
+            self.note_rejection(slot_id, response.status()).await;
+            return Err(RimError::Http(format!(
+                "archive replica head write failed: node={} status={} path={}",
+                target.node_id,
+                response.status(),
+                path
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn fetch_remote_head(
+        &self,
+        source_node_id: &str,
+        slot_id: u16,
+        path: &str,
+    ) -> Result<Option<BlobHead>> {
+        if let Some((grpc, address)) = self.grpc_peer(source_node_id).await? {
+            let request = GetHeadRequest {
+                slot_id: slot_id.into(),
+                path: path.to_string(),
+            };
+            let response = self
+                .grpc_timed(
+                    PeerCall::HeadRead,
+                    source_node_id,
+                    slot_id,
+                    grpc.get_head(&address, request),
+                )
+                .await
+                .map_err(|status| {
+                    RimError::Http(format!(
+                        "internal head fetch failed: node={} status={} path={} message={}",
+                        source_node_id,
+                        http_status(&status),
+                        path,
+                        status.message()
+                    ))
+                })?;
+            let Some(head) = response.head else {
+                return Ok(None);
+            };
+            let head_kind = match head.head_kind.as_str() {
+                "meta" => HeadKind::Meta,
+                "tombstone" => HeadKind::Tombstone,
+                _ => return Err(RimError::Internal("invalid remote head kind".to_string())),
+            };
+            let (meta, tombstone) = head.documents()?;
+            return Ok(Some(BlobHead {
+                path: path.to_string(),
+                generation: head.generation,
+                head_kind,
+                head_sha256: head.head_sha256,
+                updated_at: Utc::now(),
+                meta,
+                tombstone,
+            }));
+        }
+
+        let head_url = self
+            .internal_head_url(source_node_id, slot_id, path)
+            .await?;
+        let request = self.client.get(head_url);
+        let response = self
+            .send_timed(PeerCall::HeadRead, source_node_id, slot_id, request)
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal head fetch failed: node={} status={} path={}",
+                source_node_id,
+                response.status(),
+                path
+            )));
+        }
+
+        let payload: InternalHeadResponsePayload = response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !payload.found {
+            return Ok(None);
+        }
+
+        let head_kind = match payload.head_kind.as_deref() {
+            Some("meta") => HeadKind::Meta,
+            Some("tombstone") => HeadKind::Tombstone,
+            _ => return Err(RimError::Internal("invalid remote head kind".to_string())),
+        };
+
+        let generation = payload
+            .generation
+            .ok_or_else(|| RimError::Internal("missing remote generation".to_string()))?;
+
+        let head_sha256 = payload
+            .head_sha256
+            .ok_or_else(|| RimError::Internal("missing remote head_sha256".to_string()))?;
+
+        Ok(Some(BlobHead {
+            path: path.to_string(),
+            generation,
+            head_kind,
+            head_sha256,
+            updated_at: Utc::now(),
+            meta: payload.meta,
+            tombstone: payload.tombstone,
+        }))
+    }
+
+    pub async fn fetch_slot_stats(&self, node_id: &str, slot_id: u16) -> Result<SlotStats> {
+        let node = self.resolve_node(node_id).await?;
+        let protocol = self.peer_protocol(&node.node_id).await?;
+        if !protocol.supports(CAP_SLOT_STATS) {
+            return Err(RimError::Http(format!(
+                "peer does not report slot stats: node={} protocol_version={}",
+                node_id, protocol.version
+            )));
+        }
+
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/stats",
+            node.address, slot_id
+        );
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal slot stats fetch failed: node={} status={} slot={}",
+                node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))
+    }
+
+    /// Fetches the traffic a peer served per slot.
+    pub async fn fetch_slot_heat(&self, node_id: &str) -> Result<SlotHeatReport> {
+        let node = self.resolve_node(node_id).await?;
+        let protocol = self.peer_protocol(&node.node_id).await?;
+        if !protocol.supports(CAP_SLOT_HEAT) {
+            return Err(RimError::Http(format!(
+                "peer does not report slot heat: node={} protocol_version={}",
+                node_id, protocol.version
+            )));
+        }
+
+        let url = format!("http://{}/internal/v1/heat", node.address);
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal slot heat fetch failed: node={} status={}",
+                node_id,
+                response.status()
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))
+    }
+
+    /// Asks a replica to prune the versions of `slot_id` that its retention
+    /// policy no longer keeps.
+    pub async fn prune_versions(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        dry_run: bool,
+    ) -> Result<PruneVersionsOperationResult> {
+        let node = self.resolve_node(node_id).await?;
+        let protocol = self.peer_protocol(&node.node_id).await?;
+        if !protocol.supports(CAP_PRUNE_VERSIONS) {
+            return Err(RimError::Http(format!(
+                "peer does not prune versions: node={} protocol_version={}",
+                node_id, protocol.version
+            )));
+        }
+
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/prune?dry_run={}",
+            node.address, slot_id, dry_run
+        );
+
+        let response = self
+            .client
+            .post(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal prune failed: node={} status={} slot={}",
+                node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))
+    }
+
+    /// Asks the write lease holder of `slot_id` to walk a prefix delete
+    /// through the slot, and returns its report of the slot.
+    pub async fn delete_prefix_in_slot(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        prefix: &str,
+        page_size: usize,
+    ) -> Result<DeletePrefixSlotReport> {
+        let node = self.resolve_node(node_id).await?;
+        let protocol = self.peer_protocol(&node.node_id).await?;
+        if !protocol.supports(CAP_DELETE_PREFIX) {
+            return Err(RimError::Http(format!(
+                "peer does not delete prefixes: node={} protocol_version={}",
+                node_id, protocol.version
+            )));
+        }
+
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/delete-prefix",
+            node.address, slot_id
+        );
+
+        let response = self
+            .client
+            .post(url)
+            .json(&InternalDeletePrefixRequest { prefix, page_size })
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal prefix delete failed: node={} status={} slot={}",
+                node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))
+    }
+
+    /// Asks a replica to verify its head chains for `slot_id`.
+    pub async fn verify_head_chain(&self, node_id: &str, slot_id: u16) -> Result<HeadChainReport> {
+        let node = self.resolve_node(node_id).await?;
+        let protocol = self.peer_protocol(&node.node_id).await?;
+        if !protocol.supports(CAP_HEAD_CHAIN) {
+            return Err(RimError::Http(format!(
+                "peer does not verify head chains: node={} protocol_version={}",
+                node_id, protocol.version
+            )));
+        }
+
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/head-chain",
+            node.address, slot_id
+        );
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal head chain verification failed: node={} status={} slot={}",
+                node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))
+    }
+
+    /// Asks a replica to pull the current head of `path`, and any parts it
+    /// lacks, from `source_node_id`.
+    pub async fn request_replica_pull(
+        &self,
+        target_node_id: &str,
+        slot_id: u16,
+        path: &str,
+        source_node_id: &str,
+    ) -> Result<()> {
+        let target = self.resolve_node(target_node_id).await?;
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/heal/repair",
+            target.address, slot_id
+        );
+
+        let response = self
+            .client
+            .post(url)
+            .json(&InternalPullRequest {
+                source_node_id,
+                blob_paths: [path],
+            })
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "replica pull failed: node={} status={} slot={} path={}",
+                target.node_id,
+                response.status(),
+                slot_id,
+                path
+            )));
+        }
+
+        let payload: InternalPullResponse = response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        if payload.repaired_objects == 0 {
+            return Err(RimError::Http(format!(
+                "replica pull failed: node={} slot={} path={} errors={}",
+                target.node_id,
+                slot_id,
+                path,
+                payload.errors.join("; ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Lists every head a node holds for `slot_id`, tombstones included.
+    pub async fn fetch_slot_heads(&self, node_id: &str, slot_id: u16) -> Result<Vec<HealHeadItem>> {
+        let node = self.resolve_node(node_id).await?;
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/heal/slotlets?prefix_len=2",
+            node.address, slot_id
+        );
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal slotlets fetch failed: node={} status={} slot={}",
+                node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+        let slotlets: InternalSlotletsResponse = response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        if slotlets.slotlets.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let prefixes: Vec<String> = slotlets
+            .slotlets
+            .into_iter()
+            .map(|slotlet| slotlet.prefix)
+            .collect();
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/heal/heads",
+            node.address, slot_id
+        );
+        let response = self
+            .client
+            .post(url)
+            .json(&InternalHealHeadsRequest {
+                prefixes: &prefixes,
+            })
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal heads fetch failed: node={} status={} slot={}",
+                node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+        let heads: InternalHealHeadsResponse = response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        Ok(heads.heads)
+    }
+
+    /// Fetches, in one round trip, the tombstones `node_id` holds as the
+    /// current head of any of `paths`. Paths that are live or unknown there
+    /// are left out.
+    pub async fn fetch_tombstones(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        paths: &[String],
+    ) -> Result<Vec<HealTombstoneItem>> {
+        if !self
+            .peer_protocol(node_id)
+            .await?
+            .supports(CAP_TOMBSTONE_BATCH)
+        {
+            return Err(RimError::Http(format!(
+                "peer cannot hand out tombstone batches: node={}",
+                node_id
+            )));
+        }
+
+        let node = self.resolve_node(node_id).await?;
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/heal/tombstones",
+            node.address, slot_id
+        );
+        let request = self
+            .client
+            .post(url)
+            .json(&InternalHealTombstonesRequest { paths });
+        let response = self
+            .send_timed(PeerCall::HeadRead, node_id, slot_id, request)
+            .await?;
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "internal tombstones fetch failed: node={} status={} slot={}",
+                node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+
+        let payload: InternalHealTombstonesResponse = response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        Ok(payload.tombstones)
+    }
+
+    /// Asks `node_id` to reload the placement of `slot_id` if it has not
+    /// seen `epoch` yet, so a freeze or thaw takes effect there without
+    /// waiting for the next refresh.
+    pub async fn reload_peer_placement(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        epoch: u64,
+    ) -> Result<()> {
+        let node = self.resolve_node(node_id).await?;
+        let protocol = self.peer_protocol(&node.node_id).await?;
+        if !protocol.supports(CAP_PLACEMENT_RELOAD) {
+            return Err(RimError::Http(format!(
+                "peer cannot reload placement on request: node={} protocol_version={}",
+                node_id, protocol.version
+            )));
+        }
+
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/placement?epoch={}",
+            node.address, slot_id, epoch
+        );
+        let response = self
+            .client
+            .post(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "placement reload failed: node={} status={} slot={}",
+                node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Asks `target_node_id` to copy `paths` of `slot_id` from
+    /// `source_node_id` ahead of replacing `replacing` in the slot's replica
+    /// set. Returns the per-path errors of the paths it could not copy.
+    pub async fn request_handoff_pull(
+        &self,
+        target_node_id: &str,
+        slot_id: u16,
+        source_node_id: &str,
+        replacing: &str,
+        paths: &[String],
+    ) -> Result<Vec<String>> {
+        let target = self.resolve_node(target_node_id).await?;
+        let protocol = self.peer_protocol(&target.node_id).await?;
+        if !protocol.supports(CAP_SLOT_HANDOFF) {
+            return Err(RimError::Http(format!(
+                "peer does not take slot handoffs: node={} protocol_version={}",
+                target_node_id, protocol.version
+            )));
+        }
 
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/heal/repair",
+            target.address, slot_id
+        );
+        let response = self
+            .client
+            .post(url)
+            .json(&InternalHandoffPullRequest {
+                source_node_id,
+                blob_paths: paths,
+                replacing,
+            })
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "handoff pull failed: node={} status={} slot={}",
+                target.node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+
+        let payload: InternalPullResponse = response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        Ok(payload.errors)
+    }
+
+    /// Asks `node_id` to export `slot_id` for a bulk transfer.
+    pub async fn export_slot(&self, node_id: &str, slot_id: u16) -> Result<SlotTransferManifest> {
+        let node = self.slot_transfer_peer(node_id).await?;
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/transfer/exports",
+            node.address, slot_id
+        );
+
+        let response = self
+            .client
+            .post(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "slot export failed: node={} status={} slot={}",
+                node_id,
+                response.status(),
+                slot_id
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))
+    }
+
+    /// Returns an export `node_id` made earlier, or `None` once it is gone.
+    pub async fn fetch_slot_export(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        transfer_id: &str,
+    ) -> Result<Option<SlotTransferManifest>> {
+        let node = self.slot_transfer_peer(node_id).await?;
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/transfer/exports/{}",
+            node.address, slot_id, transfer_id
+        );
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
         if response.status() == reqwest::StatusCode::NOT_FOUND {
             return Ok(None);
         }
-
         if !response.status().is_success() {
             return Err(RimError::Http(format!(
-                "internal head fetch failed: node={} status={} path={}",
-                source_node_id,
+                "slot export fetch failed: node={} status={} slot={}",
+                node_id,
                 response.status(),
-                path
+                slot_id
             )));
         }
 
-        let payload: InternalHeadResponsePayload = response
+        response
             .json()
             .await
+            .map(Some)
+            .map_err(|error| RimError::Http(error.to_string()))
+    }
+
+    /// Opens file `name` of an export, starting at byte `offset`.
+    pub async fn fetch_slot_export_file(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        transfer_id: &str,
+        name: &str,
+        offset: u64,
+    ) -> Result<reqwest::Response> {
+        let node = self.resolve_node(node_id).await?;
+        let mut url = Url::parse(&format!(
+            "http://{}/internal/v1/slots/{}/transfer/exports/{}/file",
+            node.address, slot_id, transfer_id
+        ))
+        .map_err(|error| RimError::Http(error.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("name", name)
+            .append_pair("offset", &offset.to_string());
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
             .map_err(|error| RimError::Http(error.to_string()))?;
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "slot export file fetch failed: node={} status={} slot={} file={}",
+                node_id,
+                response.status(),
+                slot_id,
+                name
+            )));
+        }
 
-        if !payload.found {
-            return Ok(None);
+        Ok(response)
+    }
+
+    /// Asks `target_node_id` to copy all of `slot_id` from `source_node_id`
+    /// in bulk. The target must not hold any of the slot yet.
+    pub async fn request_slot_transfer(
+        &self,
+        target_node_id: &str,
+        slot_id: u16,
+        source_node_id: &str,
+    ) -> Result<SlotTransferOperationResult> {
+        let target = self.slot_transfer_peer(target_node_id).await?;
+        let url = format!(
+            "http://{}/internal/v1/slots/{}/transfer/pull",
+            target.address, slot_id
+        );
+
+        let response = self
+            .client
+            .post(url)
+            .json(&InternalSlotTransferPullRequest { source_node_id })
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(RimError::Http(format!(
+                "slot transfer failed: node={} status={} slot={} body={}",
+                target_node_id, status, slot_id, body
+            )));
         }
 
-        let head_kind = match payload.head_kind.as_deref() {
-            Some("meta") => HeadKind::Meta,
-            Some("tombstone") => HeadKind::Tombstone,
-            _ => return Err(RimError::Internal("invalid remote head kind".to_string())),
-        };
+        response
+            .json()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))
+    }
 
-        let generation = payload
-            .generation
-            .ok_or_else(|| RimError::Internal("missing remote generation".to_string()))?;
+    async fn slot_transfer_peer(&self, node_id: &str) -> Result<NodeInfo> {
+        let node = self.resolve_node(node_id).await?;
+        let protocol = self.peer_protocol(&node.node_id).await?;
+        if !protocol.supports(CAP_SLOT_TRANSFER) {
+            return Err(RimError::Http(format!(
+                "peer does not transfer slots: node={} protocol_version={}",
+                node_id, protocol.version
+            )));
+        }
+        Ok(node)
+    }
 
-        let head_sha256 = payload
-            .head_sha256
-            .ok_or_else(|| RimError::Internal("missing remote head_sha256".to_string()))?;
+    /// Checks that a peer answers its health endpoint within `timeout`.
+    pub async fn probe_node(&self, node: &NodeInfo, timeout: Duration) -> Result<()> {
+        let url = format!("http://{}/_/api/v1/healthz", node.address);
 
-        Ok(Some(BlobHead {
-            path: path.to_string(),
-            generation,
-            head_kind,
-            head_sha256,
-            updated_at: Utc::now(),
-            meta: payload.meta,
-            tombstone: payload.tombstone,
-        }))
+        let response = self
+            .client
+            .get(url)
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(RimError::Http(format!(
+                "peer health check failed: node={} status={}",
+                node.node_id,
+                response.status()
+            )));
+        }
+
+        Ok(())
     }
 
     pub async fn fetch_part_by_sha(
@@ -297,11 +1810,28 @@ impl ClusterClient {
         generation: i64,
         part_no: u32,
     ) -> Result<ClusterPartPayload> {
+        if let Some((grpc, address)) = self.grpc_peer(source_node_id).await? {
+            return self
+                .fetch_part_grpc(
+                    grpc,
+                    &address,
+                    source_node_id,
+                    GetPartRequest {
+                        slot_id: slot_id.into(),
+                        path: path.to_string(),
+                        generation,
+                        part_no,
+                        sha256: sha256.to_string(),
+                    },
+                )
+                .await;
+        }
+
         let part_url = self
             .internal_part_url_by_sha(source_node_id, slot_id, sha256, path, generation, part_no)
             .await?;
 
-        self.fetch_part_payload(source_node_id, part_url, path, part_no)
+        self.fetch_part_payload(source_node_id, slot_id, part_url, path, part_no)
             .await
     }
 
@@ -313,27 +1843,113 @@ impl ClusterClient {
         generation: i64,
         part_no: u32,
     ) -> Result<ClusterPartPayload> {
+        if let Some((grpc, address)) = self.grpc_peer(source_node_id).await? {
+            return self
+                .fetch_part_grpc(
+                    grpc,
+                    &address,
+                    source_node_id,
+                    GetPartRequest {
+                        slot_id: slot_id.into(),
+                        path: path.to_string(),
+                        generation,
+                        part_no,
+                        sha256: String::new(),
+                    },
+                )
+                .await;
+        }
+
         let part_url = self
             .internal_part_url_by_index(source_node_id, slot_id, path, generation, part_no)
             .await?;
 
-        self.fetch_part_payload(source_node_id, part_url, path, part_no)
+        self.fetch_part_payload(source_node_id, slot_id, part_url, path, part_no)
+            .await
+    }
+
+    async fn fetch_part_grpc(
+        &self,
+        grpc: &InternalGrpcTransport,
+        address: &str,
+        source_node_id: &str,
+        request: GetPartRequest,
+    ) -> Result<ClusterPartPayload> {
+        let slot_id = request.slot_id as u16;
+        let part_no = request.part_no;
+        let path = request.path.clone();
+        let part = self
+            .grpc_timed(
+                PeerCall::PartRead,
+                source_node_id,
+                slot_id,
+                grpc.get_part(address, request),
+            )
             .await
+            .map_err(|status| {
+                RimError::Http(format!(
+                    "failed to fetch part_no {} from source {}: status={} path={} message={}",
+                    part_no,
+                    source_node_id,
+                    http_status(&status),
+                    path,
+                    status.message()
+                ))
+            })?;
+
+        // The same headers the HTTP route answers with.
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/octet-stream"),
+        );
+        if let Ok(value) = HeaderValue::from_str(&part.sha256) {
+            headers.insert("x-rimio-sha256", value);
+        }
+        Ok(ClusterPartPayload {
+            headers,
+            bytes: part.data,
+        })
+    }
+
+    /// Applies one head on a peer over gRPC.
+    async fn put_head_grpc(
+        &self,
+        grpc: &InternalGrpcTransport,
+        address: &str,
+        node_id: &str,
+        slot_id: u16,
+        write_id: &str,
+        head: HeadRecord,
+    ) -> std::result::Result<(), Status> {
+        let request = PutHeadRequest {
+            slot_id: slot_id.into(),
+            epoch: self.placement.epoch(slot_id).await,
+            write_id: write_id.to_string(),
+            head: Some(head),
+        };
+        self.grpc_timed(
+            PeerCall::HeadWrite,
+            node_id,
+            slot_id,
+            grpc.put_head(address, request),
+        )
+        .await
+        .map(|_| ())
     }
 
     async fn fetch_part_payload(
         &self,
         source_node_id: &str,
+        slot_id: u16,
         part_url: Url,
         path: &str,
         part_no: u32,
     ) -> Result<ClusterPartPayload> {
+        let request = self.client.get(part_url);
         let response = self
-            .client
-            .get(part_url)
-            .send()
-            .await
-            .map_err(|error| RimError::Http(error.to_string()))?;
+            .send_timed(PeerCall::PartRead, source_node_id, slot_id, request)
+            .await?;
 
         if !response.status().is_success() {
             return Err(RimError::Http(format!(
@@ -354,10 +1970,230 @@ impl ClusterClient {
         Ok(ClusterPartPayload { headers, bytes })
     }
 
+    async fn put_replica_parts(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        path: &str,
+        write_id: &str,
+        generation: i64,
+        parts: &[ReplicatedPart],
+    ) -> Result<()> {
+        stream::iter(parts)
+            .map(Ok)
+            .try_for_each_concurrent(PART_UPLOAD_CONCURRENCY, |part| {
+                self.put_replica_part(node_id, slot_id, path, write_id, generation, part)
+            })
+            .await
+    }
+
+    /// Whether `node_id` already holds this part of `path` at `generation`
+    /// with the given hash. Peers that cannot answer count as missing it.
+    pub async fn has_part(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        sha256: &str,
+        path: &str,
+        generation: i64,
+        part_no: u32,
+    ) -> Result<bool> {
+        if !self.peer_protocol(node_id).await?.supports(CAP_PART_PROBE) {
+            return Ok(false);
+        }
+
+        let part_url = self
+            .internal_part_url_by_sha(node_id, slot_id, sha256, path, generation, part_no)
+            .await?;
+        let response = self
+            .send_timed(
+                PeerCall::PartRead,
+                node_id,
+                slot_id,
+                self.client.head(part_url),
+            )
+            .await?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(response
+                .headers()
+                .get("x-rimio-sha256")
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|held| held == sha256)),
+            status => Err(RimError::Http(format!(
+                "internal part probe failed: node={} status={} part_no={} path={}",
+                node_id, status, part_no, path
+            ))),
+        }
+    }
+
+    /// The sha256 of part `part_no` of `path` at `generation` when `node_id`
+    /// has its bytes on disk, `None` when it does not. Peers that cannot
+    /// answer probes fail, leaving the caller to guess.
+    pub async fn probe_part(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        path: &str,
+        generation: i64,
+        part_no: u32,
+    ) -> Result<Option<String>> {
+        if !self.peer_protocol(node_id).await?.supports(CAP_PART_PROBE) {
+            return Err(RimError::Http(format!(
+                "peer cannot answer part probes: node={}",
+                node_id
+            )));
+        }
+
+        let part_url = self
+            .internal_part_url_by_index(node_id, slot_id, path, generation, part_no)
+            .await?;
+        let response = self
+            .send_timed(
+                PeerCall::PartRead,
+                node_id,
+                slot_id,
+                self.client.head(part_url),
+            )
+            .await?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(response
+                .headers()
+                .get("x-rimio-sha256")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)),
+            status => Err(RimError::Http(format!(
+                "internal part probe failed: node={} status={} part_no={} path={}",
+                node_id, status, part_no, path
+            ))),
+        }
+    }
+
+    async fn put_replica_part(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        path: &str,
+        write_id: &str,
+        generation: i64,
+        part: &ReplicatedPart,
+    ) -> Result<()> {
+        // A retried write or a repair often finds the part already there.
+        if part.length >= PART_PROBE_MIN_BYTES
+            && self
+                .has_part(
+                    node_id,
+                    slot_id,
+                    &part.sha256,
+                    path,
+                    generation,
+                    part.part_no,
+                )
+                .await
+                .unwrap_or(false)
+        {
+            tracing::debug!(
+                "replica already holds part: node={} part_no={} path={}",
+                node_id,
+                part.part_no,
+                path
+            );
+            return Ok(());
+        }
+
+        if let Some((grpc, address)) = self.grpc_peer(node_id).await? {
+            let request = PutPartRequest {
+                slot_id: slot_id.into(),
+                epoch: self.placement.epoch(slot_id).await,
+                write_id: write_id.to_string(),
+                path: path.to_string(),
+                generation,
+                part_no: part.part_no,
+                sha256: part.sha256.clone(),
+                data: part.data.clone(),
+            };
+            let result = self
+                .grpc_timed(
+                    PeerCall::PartWrite,
+                    node_id,
+                    slot_id,
+                    grpc.put_part(&address, request),
+                )
+                .await;
+            return match result {
+                Ok(_) => Ok(()),
+                Err(status) => Err(self
+                    .grpc_write_error(slot_id, status, |code| {
+                        format!(
+                            "replica part write failed: node={} status={} part_no={} path={}",
+                            node_id, code, part.part_no, path
+                        )
+                    })
+                    .await),
+            };
+        }
+
+        let part_url = self
+            .internal_part_url_by_sha(
+                node_id,
+                slot_id,
+                &part.sha256,
+                path,
+                generation,
+                part.part_no,
+            )
+            .await?;
+
+        let request = self
+            .client
+            .put(part_url)
+            .header(
+                SLOT_EPOCH_HEADER,
+                self.placement.epoch(slot_id).await.to_string(),
+            )
+            .header("x-rimio-write-id", write_id)
+            .header("x-rimio-generation", generation.to_string())
+            .header("x-rimio-part-no", part.part_no.to_string())
+            .header("x-rimio-part-length", part.length.to_string())
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(part.data.clone());
+        let response = self
+            .send_timed(PeerCall::PartWrite, node_id, slot_id, request)
+            .await?;
+
+        if !response.status().is_success() {
+            self.note_rejection(slot_id, response.status()).await;
+            return Err(RimError::Http(format!(
+                "replica part write failed: node={} status={} part_no={} path={}",
+                node_id,
+                response.status(),
+                part.part_no,
+                path
+            )));
+        }
+
+        Ok(())
+    }
+
     pub fn client(&self) -> &Client {
         &self.client
     }
 
+    /// Lists every node currently registered in the group.
+    pub async fn nodes(&self) -> Result<Vec<NodeInfo>> {
+        self.registry.get_nodes().await
+    }
+
+    /// A replica answering 409 may know a newer placement than we cached.
+    async fn note_rejection(&self, slot_id: u16, status: reqwest::StatusCode) {
+        if status == reqwest::StatusCode::CONFLICT {
+            self.placement.invalidate(slot_id).await;
+        }
+    }
+
     async fn internal_head_url(&self, node_id: &str, slot_id: u16, path: &str) -> Result<Url> {
         let node = self.resolve_node(node_id).await?;
         let mut url = Url::parse(&format!(
@@ -0,0 +1,417 @@
+use super::placement::PlacementMap;
+use crate::{Node, NodeStatus, Registry, Result, RimError};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::interval;
+
+#[derive(Debug, Clone)]
+pub struct DiskHealthConfig {
+    pub interval: Duration,
+    pub smartctl_path: String,
+    /// Reallocated sectors at which a disk counts as failing.
+    pub reallocated_failing: u64,
+    /// Pending (unreadable, not yet reallocated) sectors at which a disk
+    /// counts as failing.
+    pub pending_failing: u64,
+    /// Wear level, in percent of rated endurance, at which flash counts as
+    /// degraded and failing.
+    pub wear_degraded_percent: u64,
+    pub wear_failing_percent: u64,
+    pub temperature_degraded_celsius: i64,
+}
+
+impl Default for DiskHealthConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(600),
+            smartctl_path: "smartctl".to_string(),
+            reallocated_failing: 100,
+            pending_failing: 8,
+            wear_degraded_percent: 80,
+            wear_failing_percent: 95,
+            temperature_degraded_celsius: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MonitoredDisk {
+    pub path: PathBuf,
+    pub smart_device: String,
+    pub drain_on_failure: bool,
+}
+
+/// The SMART indicators we act on, from `smartctl --json -a`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SmartReport {
+    pub passed: Option<bool>,
+    pub temperature_celsius: Option<i64>,
+    pub reallocated_sectors: Option<u64>,
+    pub pending_sectors: Option<u64>,
+    pub media_errors: Option<u64>,
+    pub wear_percent_used: Option<u64>,
+}
+
+impl SmartReport {
+    pub fn from_smartctl_json(doc: &Value) -> Self {
+        let attribute = |id: u64| {
+            doc.pointer("/ata_smart_attributes/table")
+                .and_then(Value::as_array)
+                .and_then(|table| {
+                    table
+                        .iter()
+                        .find(|row| row.get("id").and_then(Value::as_u64) == Some(id))
+                })
+        };
+        let raw = |id: u64| {
+            attribute(id)
+                .and_then(|row| row.pointer("/raw/value"))
+                .and_then(Value::as_u64)
+        };
+        let nvme = |field: &str| {
+            doc.pointer(&format!("/nvme_smart_health_information_log/{}", field))
+                .and_then(Value::as_u64)
+        };
+
+        // SATA SSDs report wear as a normalized value counting down from 100
+        // (177 Wear_Leveling_Count, 233 Media_Wearout_Indicator).
+        let ata_wear = [177, 233].into_iter().find_map(|id| {
+            attribute(id)
+                .and_then(|row| row.get("value"))
+                .and_then(Value::as_u64)
+                .map(|remaining| 100u64.saturating_sub(remaining.min(100)))
+        });
+
+        Self {
+            passed: doc.pointer("/smart_status/passed").and_then(Value::as_bool),
+            temperature_celsius: doc.pointer("/temperature/current").and_then(Value::as_i64),
+            reallocated_sectors: raw(5),
+            pending_sectors: match (raw(197), raw(198)) {
+                (None, None) => None,
+                (pending, uncorrectable) => Some(pending.unwrap_or(0) + uncorrectable.unwrap_or(0)),
+            },
+            media_errors: nvme("media_errors"),
+            wear_percent_used: nvme("percentage_used").or(ata_wear),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiskHealth {
+    Unknown,
+    Healthy,
+    Degraded,
+    Failing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskHealthReport {
+    pub path: String,
+    pub device: String,
+    pub health: DiskHealth,
+    pub reasons: Vec<String>,
+    pub smart: Option<SmartReport>,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl DiskHealthConfig {
+    pub fn classify(&self, smart: &SmartReport) -> (DiskHealth, Vec<String>) {
+        let mut failing = Vec::new();
+        let mut degraded = Vec::new();
+
+        if smart.passed == Some(false) {
+            failing.push("smart self-assessment failed".to_string());
+        }
+        if let Some(count) = smart.reallocated_sectors.filter(|count| *count > 0) {
+            let reason = format!("reallocated_sectors={}", count);
+            if count >= self.reallocated_failing {
+                failing.push(reason);
+            } else {
+                degraded.push(reason);
+            }
+        }
+        if let Some(count) = smart.pending_sectors.filter(|count| *count > 0) {
+            let reason = format!("pending_sectors={}", count);
+            if count >= self.pending_failing {
+                failing.push(reason);
+            } else {
+                degraded.push(reason);
+            }
+        }
+        if let Some(count) = smart.media_errors.filter(|count| *count > 0) {
+            degraded.push(format!("media_errors={}", count));
+        }
+        if let Some(used) = smart.wear_percent_used {
+            let reason = format!("wear_percent_used={}", used);
+            if used >= self.wear_failing_percent {
+                failing.push(reason);
+            } else if used >= self.wear_degraded_percent {
+                degraded.push(reason);
+            }
+        }
+        if let Some(celsius) = smart
+            .temperature_celsius
+            .filter(|celsius| *celsius >= self.temperature_degraded_celsius)
+        {
+            degraded.push(format!("temperature_celsius={}", celsius));
+        }
+
+        if !failing.is_empty() {
+            failing.extend(degraded);
+            (DiskHealth::Failing, failing)
+        } else if !degraded.is_empty() {
+            (DiskHealth::Degraded, degraded)
+        } else {
+            (DiskHealth::Healthy, Vec::new())
+        }
+    }
+}
+
+/// Polls SMART data of the configured disks and folds it into the node's
+/// health.
+///
+/// A failing disk marks the node degraded. When the disk is configured with
+/// `drain_on_failure`, every slot this node replicates is reassigned to
+/// another node before the disk actually dies; the reconciler on the new
+/// owners picks the slots up from there.
+pub struct DiskHealthMonitor {
+    node: Arc<Node>,
+    registry: Arc<dyn Registry>,
+    placement: Arc<PlacementMap>,
+    disks: Vec<MonitoredDisk>,
+    config: DiskHealthConfig,
+    reports: RwLock<Vec<DiskHealthReport>>,
+}
+
+impl DiskHealthMonitor {
+    pub fn new(
+        node: Arc<Node>,
+        registry: Arc<dyn Registry>,
+        placement: Arc<PlacementMap>,
+        disks: Vec<MonitoredDisk>,
+        config: DiskHealthConfig,
+    ) -> Self {
+        Self {
+            node,
+            registry,
+            placement,
+            disks,
+            config,
+            reports: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn start(self: Arc<Self>) {
+        if self.disks.is_empty() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut ticker = interval(self.config.interval);
+            loop {
+                ticker.tick().await;
+                if let Err(error) = self.check_once().await {
+                    tracing::warn!("disk health loop failed: {}", error);
+                }
+            }
+        });
+    }
+
+    pub async fn reports(&self) -> Vec<DiskHealthReport> {
+        self.reports.read().await.clone()
+    }
+
+    pub async fn check_once(&self) -> Result<Vec<DiskHealthReport>> {
+        let mut reports = Vec::with_capacity(self.disks.len());
+        let mut drain = false;
+
+        for disk in &self.disks {
+            let (smart, error) = match self.read_smart(&disk.smart_device).await {
+                Ok(smart) => (Some(smart), None),
+                Err(error) => (None, Some(error.to_string())),
+            };
+            let (health, reasons) = match &smart {
+                Some(smart) => self.config.classify(smart),
+                None => (DiskHealth::Unknown, Vec::new()),
+            };
+
+            if health == DiskHealth::Failing {
+                tracing::warn!(
+                    "disk predicted to fail: path={} device={} reasons={:?}",
+                    disk.path.display(),
+                    disk.smart_device,
+                    reasons
+                );
+                drain |= disk.drain_on_failure;
+            }
+
+            reports.push(DiskHealthReport {
+                path: disk.path.display().to_string(),
+                device: disk.smart_device.clone(),
+                health,
+                reasons,
+                smart,
+                error,
+                checked_at: Utc::now(),
+            });
+        }
+
+        let status = if reports
+            .iter()
+            .any(|report| matches!(report.health, DiskHealth::Failing))
+        {
+            NodeStatus::Degraded
+        } else {
+            NodeStatus::Healthy
+        };
+        self.node.update_status(status).await;
+
+        *self.reports.write().await = reports.clone();
+
+        if drain {
+            self.drain_local_slots().await?;
+        }
+
+        Ok(reports)
+    }
+
+    async fn read_smart(&self, device: &str) -> Result<SmartReport> {
+        let output = tokio::process::Command::new(&self.config.smartctl_path)
+            .args(["--json", "-a", device])
+            .output()
+            .await?;
+
+        // smartctl sets status bits for disk problems too, so a non-zero exit
+        // still comes with a usable report.
+        let doc: Value = serde_json::from_slice(&output.stdout).map_err(|error| {
+            RimError::Internal(format!(
+                "unreadable smartctl output: device={} status={} error={}",
+                device, output.status, error
+            ))
+        })?;
+
+        Ok(SmartReport::from_smartctl_json(&doc))
+    }
+
+    /// Reassigns every slot replicated on this node to the least loaded
+    /// healthy node outside the slot's replica set.
+    async fn drain_local_slots(&self) -> Result<()> {
+        let local_node_id = self.node.node_id();
+        self.placement.refresh().await?;
+        let slots = self.placement.slots().await;
+
+        let mut load: HashMap<String, usize> = self
+            .registry
+            .get_nodes()
+            .await?
+            .into_iter()
+            .filter(|node| node.node_id != local_node_id && node.status == NodeStatus::Healthy)
+            .map(|node| (node.node_id, 0))
+            .collect();
+        for slot in &slots {
+            for replica in &slot.replicas {
+                if let Some(count) = load.get_mut(replica) {
+                    *count += 1;
+                }
+            }
+        }
+
+        let mut moved = 0usize;
+        for slot in slots
+            .into_iter()
+            .filter(|slot| slot.replicas.iter().any(|node| node == local_node_id))
+        {
+            let Some(target) = load
+                .iter()
+                .filter(|(node_id, _)| !slot.replicas.contains(node_id))
+                .min_by(|(a_id, a), (b_id, b)| a.cmp(b).then_with(|| a_id.cmp(b_id)))
+                .map(|(node_id, _)| node_id.clone())
+            else {
+                tracing::warn!(
+                    "cannot drain slot: slot={} no spare healthy node",
+                    slot.slot_id
+                );
+                continue;
+            };
+
+            let replicas: Vec<String> = slot
+                .replicas
+                .iter()
+                .map(|node| {
+                    if node == local_node_id {
+                        target.clone()
+                    } else {
+                        node.clone()
+                    }
+                })
+                .collect();
+
+            if self
+                .placement
+                .reassign(slot.slot_id, replicas)
+                .await?
+                .is_some()
+            {
+                *load.entry(target).or_default() += 1;
+                moved += 1;
+            }
+        }
+
+        if moved > 0 {
+            tracing::warn!(
+                "drained slots off failing disk: node={} slots={}",
+                local_node_id,
+                moved
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smart_indicators_drive_disk_health() {
+        let config = DiskHealthConfig::default();
+
+        let ata: Value = serde_json::json!({
+            "smart_status": {"passed": true},
+            "temperature": {"current": 41},
+            "ata_smart_attributes": {"table": [
+                {"id": 5, "value": 100, "raw": {"value": 3}},
+                {"id": 197, "value": 100, "raw": {"value": 0}},
+                {"id": 177, "value": 90, "raw": {"value": 120}}
+            ]}
+        });
+        let smart = SmartReport::from_smartctl_json(&ata);
+        assert_eq!(smart.reallocated_sectors, Some(3));
+        assert_eq!(smart.pending_sectors, Some(0));
+        assert_eq!(smart.wear_percent_used, Some(10));
+        assert_eq!(config.classify(&smart).0, DiskHealth::Degraded);
+
+        let nvme: Value = serde_json::json!({
+            "smart_status": {"passed": true},
+            "nvme_smart_health_information_log": {"percentage_used": 97, "media_errors": 0}
+        });
+        let smart = SmartReport::from_smartctl_json(&nvme);
+        assert_eq!(config.classify(&smart).0, DiskHealth::Failing);
+
+        let healthy = SmartReport {
+            passed: Some(true),
+            temperature_celsius: Some(35),
+            ..SmartReport::default()
+        };
+        assert_eq!(config.classify(&healthy).0, DiskHealth::Healthy);
+    }
+}
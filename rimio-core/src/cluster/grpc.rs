@@ -0,0 +1,844 @@
+//! Internal gRPC transport between nodes.
+//!
+//! Replication traffic (head reads, part reads and writes, head batches and
+//! two-phase commit messages) can travel over one HTTP/2 connection per peer
+//! as protobuf instead of JSON over HTTP/1.1. A node opts in by running the
+//! listener from [`serve_internal_grpc`] and advertising its address in
+//! [`NodeInfo::grpc_address`]; calls to peers that advertise none fall back to
+//! the HTTP routes. Head documents (blob meta and tombstones) travel as the
+//! JSON a slot stores them in.
+//!
+//! Every call carries the caller's deadline as `grpc-timeout`, and the
+//! listener drops work the caller has given up on. A listener started with a
+//! cluster key answers `UNAUTHENTICATED` to calls that do not present it in
+//! [`CLUSTER_KEY_HEADER`], as the internal HTTP routes do.
+
+use crate::{BlobMeta, CLUSTER_KEY_HEADER, HeadWrite, NodeInfo, Result, RimError, TombstoneMeta};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{
+    BoxFuture, Context, Poll, Service, StdError, empty_body, http, http::uri::PathAndQuery,
+};
+use tonic::metadata::{AsciiMetadataValue, MetadataValue};
+use tonic::server::{NamedService, UnaryService};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Status};
+
+/// gRPC service name of the internal transport.
+pub const INTERNAL_GRPC_SERVICE: &str = "rimio.internal.v1.Internal";
+
+const GET_HEAD: &str = "/rimio.internal.v1.Internal/GetHead";
+const GET_PART: &str = "/rimio.internal.v1.Internal/GetPart";
+const PUT_PART: &str = "/rimio.internal.v1.Internal/PutPart";
+const PUT_HEAD: &str = "/rimio.internal.v1.Internal/PutHead";
+const APPLY_HEADS: &str = "/rimio.internal.v1.Internal/ApplyHeads";
+const PREPARE_TRANSACTION: &str = "/rimio.internal.v1.Internal/PrepareTransaction";
+const DECIDE_TRANSACTION: &str = "/rimio.internal.v1.Internal/DecideTransaction";
+
+/// Node-local settings of the internal gRPC transport.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InternalGrpcConfig {
+    /// Address the listener binds, e.g. `0.0.0.0:9100`.
+    pub listen_addr: String,
+    /// Address peers dial; defaults to `listen_addr`.
+    #[serde(default)]
+    pub advertise_addr: Option<String>,
+    /// Deadline of each call to a peer.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_connect_timeout_ms() -> u64 {
+    2_000
+}
+
+impl InternalGrpcConfig {
+    pub fn advertised_addr(&self) -> &str {
+        self.advertise_addr.as_deref().unwrap_or(&self.listen_addr)
+    }
+}
+
+/// A head as it travels between nodes.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HeadRecord {
+    #[prost(string, tag = "1")]
+    pub path: String,
+    /// `meta` or `tombstone`.
+    #[prost(string, tag = "2")]
+    pub head_kind: String,
+    #[prost(int64, tag = "3")]
+    pub generation: i64,
+    #[prost(string, tag = "4")]
+    pub head_sha256: String,
+    /// JSON of the blob meta or tombstone.
+    #[prost(bytes = "vec", tag = "5")]
+    pub document: Vec<u8>,
+}
+
+impl HeadRecord {
+    pub fn new(
+        path: &str,
+        head_kind: &str,
+        generation: i64,
+        head_sha256: &str,
+        meta: Option<&BlobMeta>,
+        tombstone: Option<&TombstoneMeta>,
+    ) -> Result<Self> {
+        let document = match (meta, tombstone) {
+            (Some(meta), _) => serde_json::to_vec(meta)?,
+            (None, Some(tombstone)) => serde_json::to_vec(tombstone)?,
+            (None, None) => Vec::new(),
+        };
+
+        Ok(Self {
+            path: path.to_string(),
+            head_kind: head_kind.to_string(),
+            generation,
+            head_sha256: head_sha256.to_string(),
+            document,
+        })
+    }
+
+    pub fn from_write(write: &HeadWrite) -> Result<Self> {
+        match write {
+            HeadWrite::Meta { meta, .. } => Self::new(
+                write.path(),
+                "meta",
+                write.generation(),
+                write.head_sha256(),
+                Some(meta),
+                None,
+            ),
+            HeadWrite::Tombstone { tombstone, .. } => Self::new(
+                write.path(),
+                "tombstone",
+                write.generation(),
+                write.head_sha256(),
+                None,
+                Some(tombstone),
+            ),
+        }
+    }
+
+    /// The blob meta or tombstone the document holds, by head kind.
+    pub fn documents(&self) -> Result<(Option<BlobMeta>, Option<TombstoneMeta>)> {
+        match self.head_kind.as_str() {
+            "meta" => Ok((Some(serde_json::from_slice(&self.document)?), None)),
+            "tombstone" => Ok((None, Some(serde_json::from_slice(&self.document)?))),
+            other => Err(RimError::InvalidRequest(format!(
+                "invalid head kind: {}",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetHeadRequest {
+    #[prost(uint32, tag = "1")]
+    pub slot_id: u32,
+    #[prost(string, tag = "2")]
+    pub path: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetHeadResponse {
+    /// Unset when the peer has no head for the path.
+    #[prost(message, optional, tag = "1")]
+    pub head: Option<HeadRecord>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetPartRequest {
+    #[prost(uint32, tag = "1")]
+    pub slot_id: u32,
+    #[prost(string, tag = "2")]
+    pub path: String,
+    #[prost(int64, tag = "3")]
+    pub generation: i64,
+    #[prost(uint32, tag = "4")]
+    pub part_no: u32,
+    /// Empty to look the part up by index alone.
+    #[prost(string, tag = "5")]
+    pub sha256: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetPartResponse {
+    #[prost(string, tag = "1")]
+    pub sha256: String,
+    #[prost(bytes = "bytes", tag = "2")]
+    pub data: bytes::Bytes,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PutPartRequest {
+    #[prost(uint32, tag = "1")]
+    pub slot_id: u32,
+    /// Slot epoch the caller routed by, fenced like `x-rimio-slot-epoch`.
+    #[prost(uint64, tag = "2")]
+    pub epoch: u64,
+    #[prost(string, tag = "3")]
+    pub write_id: String,
+    #[prost(string, tag = "4")]
+    pub path: String,
+    #[prost(int64, tag = "5")]
+    pub generation: i64,
+    #[prost(uint32, tag = "6")]
+    pub part_no: u32,
+    #[prost(string, tag = "7")]
+    pub sha256: String,
+    #[prost(bytes = "bytes", tag = "8")]
+    pub data: bytes::Bytes,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PutPartResponse {
+    #[prost(bool, tag = "1")]
+    pub reused: bool,
+    #[prost(string, tag = "2")]
+    pub sha256: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PutHeadRequest {
+    #[prost(uint32, tag = "1")]
+    pub slot_id: u32,
+    #[prost(uint64, tag = "2")]
+    pub epoch: u64,
+    #[prost(string, tag = "3")]
+    pub write_id: String,
+    #[prost(message, optional, tag = "4")]
+    pub head: Option<HeadRecord>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PutHeadResponse {
+    #[prost(int64, tag = "1")]
+    pub generation: i64,
+}
+
+/// Applies every head in one metadata transaction, or none of them.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ApplyHeadsRequest {
+    #[prost(uint32, tag = "1")]
+    pub slot_id: u32,
+    #[prost(uint64, tag = "2")]
+    pub epoch: u64,
+    #[prost(string, tag = "3")]
+    pub write_id: String,
+    #[prost(message, repeated, tag = "4")]
+    pub heads: Vec<HeadRecord>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ApplyHeadsResponse {
+    #[prost(uint32, tag = "1")]
+    pub applied: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PrepareTransactionRequest {
+    #[prost(uint32, tag = "1")]
+    pub slot_id: u32,
+    #[prost(uint64, tag = "2")]
+    pub epoch: u64,
+    #[prost(string, tag = "3")]
+    pub txn_id: String,
+    #[prost(string, tag = "4")]
+    pub coordinator: String,
+    #[prost(string, repeated, tag = "5")]
+    pub participants: Vec<String>,
+    #[prost(message, repeated, tag = "6")]
+    pub heads: Vec<HeadRecord>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PrepareTransactionResponse {
+    #[prost(bool, tag = "1")]
+    pub vote_yes: bool,
+    #[prost(string, tag = "2")]
+    pub reason: String,
+    /// Transaction holding the paths when the vote is no because of it.
+    #[prost(string, tag = "3")]
+    pub locked_by: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DecideTransactionRequest {
+    #[prost(uint32, tag = "1")]
+    pub slot_id: u32,
+    #[prost(string, tag = "2")]
+    pub txn_id: String,
+    /// Commit when set, abort otherwise.
+    #[prost(bool, tag = "3")]
+    pub commit: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DecideTransactionResponse {}
+
+/// What a node answers on the internal gRPC service.
+#[async_trait]
+pub trait InternalGrpcHandler: Send + Sync + 'static {
+    async fn get_head(
+        &self,
+        request: GetHeadRequest,
+    ) -> std::result::Result<GetHeadResponse, Status>;
+
+    async fn get_part(
+        &self,
+        request: GetPartRequest,
+    ) -> std::result::Result<GetPartResponse, Status>;
+
+    async fn put_part(
+        &self,
+        request: PutPartRequest,
+    ) -> std::result::Result<PutPartResponse, Status>;
+
+    async fn put_head(
+        &self,
+        request: PutHeadRequest,
+    ) -> std::result::Result<PutHeadResponse, Status>;
+
+    async fn apply_heads(
+        &self,
+        request: ApplyHeadsRequest,
+    ) -> std::result::Result<ApplyHeadsResponse, Status>;
+
+    async fn prepare_transaction(
+        &self,
+        request: PrepareTransactionRequest,
+    ) -> std::result::Result<PrepareTransactionResponse, Status>;
+
+    async fn decide_transaction(
+        &self,
+        request: DecideTransactionRequest,
+    ) -> std::result::Result<DecideTransactionResponse, Status>;
+}
+
+/// Serves `handler` on `listen_addr` until the listener fails. With
+/// `cluster_key`, only calls presenting it are let through.
+pub async fn serve_internal_grpc<T: InternalGrpcHandler>(
+    listen_addr: SocketAddr,
+    handler: Arc<T>,
+    cluster_key: Option<String>,
+) -> Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(InternalGrpcServer {
+            handler,
+            cluster_key: cluster_key.map(Arc::from),
+        })
+        .serve(listen_addr)
+        .await
+        .map_err(|error| RimError::Http(error.to_string()))
+}
+
+struct InternalGrpcServer<T> {
+    handler: Arc<T>,
+    cluster_key: Option<Arc<str>>,
+}
+
+impl<T> InternalGrpcServer<T> {
+    /// Whether `request` presents the cluster key, compared by digest so the
+    /// time taken does not give it away.
+    fn admits<B>(&self, request: &http::Request<B>) -> bool {
+        let Some(expected) = self.cluster_key.as_deref() else {
+            return true;
+        };
+        request
+            .headers()
+            .get(CLUSTER_KEY_HEADER)
+            .is_some_and(|presented| {
+                Sha256::digest(expected.as_bytes()) == Sha256::digest(presented.as_bytes())
+            })
+    }
+}
+
+impl<T> Clone for InternalGrpcServer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            cluster_key: self.cluster_key.clone(),
+        }
+    }
+}
+
+impl<T> NamedService for InternalGrpcServer<T> {
+    const NAME: &'static str = INTERNAL_GRPC_SERVICE;
+}
+
+impl<T, B> Service<http::Request<B>> for InternalGrpcServer<T>
+where
+    T: InternalGrpcHandler,
+    B: tonic::codegen::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if !self.admits(&request) {
+            return status_only(Code::Unauthenticated);
+        }
+        let handler = self.handler.clone();
+        match request.uri().path() {
+            GET_HEAD => unary(request, move |message| async move {
+                handler.get_head(message).await
+            }),
+            GET_PART => unary(request, move |message| async move {
+                handler.get_part(message).await
+            }),
+            PUT_PART => unary(request, move |message| async move {
+                handler.put_part(message).await
+            }),
+            PUT_HEAD => unary(request, move |message| async move {
+                handler.put_head(message).await
+            }),
+            APPLY_HEADS => unary(request, move |message| async move {
+                handler.apply_heads(message).await
+            }),
+            PREPARE_TRANSACTION => unary(request, move |message| async move {
+                handler.prepare_transaction(message).await
+            }),
+            DECIDE_TRANSACTION => unary(request, move |message| async move {
+                handler.decide_transaction(message).await
+            }),
+            _ => status_only(Code::Unimplemented),
+        }
+    }
+}
+
+/// Answers a call with `code` and no message.
+fn status_only(
+    code: Code,
+) -> BoxFuture<http::Response<tonic::body::BoxBody>, std::convert::Infallible> {
+    Box::pin(async move {
+        Ok(http::Response::builder()
+            .status(200)
+            .header("grpc-status", (code as i32).to_string())
+            .header("content-type", "application/grpc")
+            .body(empty_body())
+            .unwrap_or_default())
+    })
+}
+
+/// Decodes one request message, runs `call` on it and encodes the answer.
+fn unary<B, M1, M2, F, Fut>(
+    request: http::Request<B>,
+    call: F,
+) -> BoxFuture<http::Response<tonic::body::BoxBody>, std::convert::Infallible>
+where
+    B: tonic::codegen::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+    M1: prost::Message + Default + Send + 'static,
+    M2: prost::Message + Send + 'static,
+    F: FnOnce(M1) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = std::result::Result<M2, Status>> + Send + 'static,
+{
+    Box::pin(async move {
+        let mut grpc = tonic::server::Grpc::new(ProstCodec::<M2, M1>::default());
+        Ok(grpc.unary(UnaryCall(Some(call)), request).await)
+    })
+}
+
+struct UnaryCall<F>(Option<F>);
+
+impl<M1, M2, F, Fut> UnaryService<M1> for UnaryCall<F>
+where
+    F: FnOnce(M1) -> Fut,
+    Fut: std::future::Future<Output = std::result::Result<M2, Status>> + Send + 'static,
+    M2: Send + 'static,
+{
+    type Response = M2;
+    type Future = BoxFuture<tonic::Response<M2>, Status>;
+
+    fn call(&mut self, request: tonic::Request<M1>) -> Self::Future {
+        let Some(call) = self.0.take() else {
+            return Box::pin(async { Err(Status::internal("unary call reused")) });
+        };
+        let response = call(request.into_inner());
+        Box::pin(async move { response.await.map(tonic::Response::new) })
+    }
+}
+
+/// Client side of the transport: one lazily connected HTTP/2 channel per
+/// peer address, shared by every call to that peer.
+pub struct InternalGrpcTransport {
+    timeout: Duration,
+    connect_timeout: Duration,
+    cluster_key: Option<AsciiMetadataValue>,
+    channels: RwLock<HashMap<String, Channel>>,
+}
+
+impl InternalGrpcTransport {
+    pub fn new(config: &InternalGrpcConfig) -> Self {
+        Self {
+            timeout: Duration::from_millis(config.timeout_ms.max(1)),
+            connect_timeout: Duration::from_millis(config.connect_timeout_ms.max(1)),
+            cluster_key: None,
+            channels: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Presents `cluster_key` on every call, which listeners of clusters
+    /// with auth enabled require.
+    pub fn with_cluster_key(mut self, cluster_key: Option<&str>) -> Self {
+        self.cluster_key = cluster_key.and_then(|key| MetadataValue::try_from(key).ok());
+        self
+    }
+
+    /// The peer's gRPC address, when it advertises one.
+    pub fn address<'a>(&self, node: &'a NodeInfo) -> Option<&'a str> {
+        node.grpc_address
+            .as_deref()
+            .filter(|address| !address.is_empty())
+    }
+
+    pub async fn get_head(
+        &self,
+        address: &str,
+        request: GetHeadRequest,
+    ) -> std::result::Result<GetHeadResponse, Status> {
+        self.call(address, GET_HEAD, request).await
+    }
+
+    pub async fn get_part(
+        &self,
+        address: &str,
+        request: GetPartRequest,
+    ) -> std::result::Result<GetPartResponse, Status> {
+        self.call(address, GET_PART, request).await
+    }
+
+    pub async fn put_part(
+        &self,
+        address: &str,
+        request: PutPartRequest,
+    ) -> std::result::Result<PutPartResponse, Status> {
+        self.call(address, PUT_PART, request).await
+    }
+
+    pub async fn put_head(
+        &self,
+        address: &str,
+        request: PutHeadRequest,
+    ) -> std::result::Result<PutHeadResponse, Status> {
+        self.call(address, PUT_HEAD, request).await
+    }
+
+    pub async fn apply_heads(
+        &self,
+        address: &str,
+        request: ApplyHeadsRequest,
+    ) -> std::result::Result<ApplyHeadsResponse, Status> {
+        self.call(address, APPLY_HEADS, request).await
+    }
+
+    pub async fn prepare_transaction(
+        &self,
+        address: &str,
+        request: PrepareTransactionRequest,
+    ) -> std::result::Result<PrepareTransactionResponse, Status> {
+        self.call(address, PREPARE_TRANSACTION, request).await
+    }
+
+    pub async fn decide_transaction(
+        &self,
+        address: &str,
+        request: DecideTransactionRequest,
+    ) -> std::result::Result<DecideTransactionResponse, Status> {
+        self.call(address, DECIDE_TRANSACTION, request).await
+    }
+
+    async fn call<M1, M2>(
+        &self,
+        address: &str,
+        method: &'static str,
+        message: M1,
+    ) -> std::result::Result<M2, Status>
+    where
+        M1: prost::Message + Send + Sync + 'static,
+        M2: prost::Message + Default + Send + Sync + 'static,
+    {
+        let mut grpc = tonic::client::Grpc::new(self.channel(address).await?);
+        grpc.ready()
+            .await
+            .map_err(|error| Status::unavailable(error.to_string()))?;
+
+        let mut request = tonic::Request::new(message);
+        request.set_timeout(self.timeout);
+        if let Some(cluster_key) = &self.cluster_key {
+            request
+                .metadata_mut()
+                .insert(CLUSTER_KEY_HEADER, cluster_key.clone());
+        }
+        grpc.unary(
+            request,
+            PathAndQuery::from_static(method),
+            ProstCodec::default(),
+        )
+        .await
+        .map(tonic::Response::into_inner)
+    }
+
+    async fn channel(&self, address: &str) -> std::result::Result<Channel, Status> {
+        if let Some(channel) = self.channels.read().await.get(address) {
+            return Ok(channel.clone());
+        }
+
+        let channel = Endpoint::from_shared(format!("http://{}", address))
+            .map_err(|error| Status::invalid_argument(error.to_string()))?
+            .connect_timeout(self.connect_timeout)
+            .tcp_nodelay(true)
+            .connect_lazy();
+        Ok(self
+            .channels
+            .write()
+            .await
+            .entry(address.to_string())
+            .or_insert(channel)
+            .clone())
+    }
+}
+
+/// The HTTP status an internal route would have answered with, so callers
+/// handle both transports alike.
+pub fn http_status(status: &Status) -> reqwest::StatusCode {
+    match status.code() {
+        Code::Ok => reqwest::StatusCode::OK,
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            reqwest::StatusCode::BAD_REQUEST
+        }
+        Code::NotFound => reqwest::StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => reqwest::StatusCode::CONFLICT,
+        Code::PermissionDenied => reqwest::StatusCode::FORBIDDEN,
+        Code::Unauthenticated => reqwest::StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => reqwest::StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => reqwest::StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => reqwest::StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => reqwest::StatusCode::GATEWAY_TIMEOUT,
+        Code::Cancelled | Code::Unknown | Code::Internal | Code::DataLoss => {
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// The gRPC status matching an HTTP status of the internal routes.
+pub fn grpc_status(status: reqwest::StatusCode, message: impl Into<String>) -> Status {
+    let code = match status {
+        reqwest::StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        reqwest::StatusCode::NOT_FOUND => Code::NotFound,
+        reqwest::StatusCode::CONFLICT => Code::Aborted,
+        reqwest::StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        reqwest::StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    };
+    Status::new(code, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContentHeaders, PART_SIZE, PartIndexState};
+    use chrono::Utc;
+    use std::collections::BTreeMap;
+
+    struct Echo;
+
+    #[async_trait]
+    impl InternalGrpcHandler for Echo {
+        async fn get_head(
+            &self,
+            request: GetHeadRequest,
+        ) -> std::result::Result<GetHeadResponse, Status> {
+            let meta = BlobMeta {
+                path: request.path.clone(),
+                slot_id: request.slot_id as u16,
+                generation: 7,
+                version: 7,
+                size_bytes: 3,
+                etag: "etag".to_string(),
+                part_size: PART_SIZE as u64,
+                part_count: 1,
+                part_index_state: PartIndexState::Complete,
+                archive_url: None,
+                updated_at: Utc::now(),
+                metadata: BTreeMap::new(),
+                tags: BTreeMap::new(),
+                content: ContentHeaders::default(),
+            };
+            let head = HeadRecord::new(&request.path, "meta", 7, "sha", Some(&meta), None)
+                .map_err(|error| Status::internal(error.to_string()))?;
+            Ok(GetHeadResponse { head: Some(head) })
+        }
+
+        async fn get_part(
+            &self,
+            request: GetPartRequest,
+        ) -> std::result::Result<GetPartResponse, Status> {
+            Err(Status::not_found(request.path))
+        }
+
+        async fn put_part(
+            &self,
+            request: PutPartRequest,
+        ) -> std::result::Result<PutPartResponse, Status> {
+            Ok(PutPartResponse {
+                reused: false,
+                sha256: crate::compute_hash(&request.data),
+            })
+        }
+
+        async fn put_head(
+            &self,
+            _request: PutHeadRequest,
+        ) -> std::result::Result<PutHeadResponse, Status> {
+            Err(Status::unimplemented("put_head"))
+        }
+
+        async fn apply_heads(
+            &self,
+            _request: ApplyHeadsRequest,
+        ) -> std::result::Result<ApplyHeadsResponse, Status> {
+            Err(Status::unimplemented("apply_heads"))
+        }
+
+        async fn prepare_transaction(
+            &self,
+            _request: PrepareTransactionRequest,
+        ) -> std::result::Result<PrepareTransactionResponse, Status> {
+            Err(Status::unimplemented("prepare_transaction"))
+        }
+
+        async fn decide_transaction(
+            &self,
+            _request: DecideTransactionRequest,
+        ) -> std::result::Result<DecideTransactionResponse, Status> {
+            Ok(DecideTransactionResponse {})
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_round_trip_over_one_channel() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        tokio::spawn(serve_internal_grpc(address, Arc::new(Echo), None));
+
+        let transport = InternalGrpcTransport::new(&InternalGrpcConfig {
+            listen_addr: address.to_string(),
+            advertise_addr: None,
+            timeout_ms: 5_000,
+            connect_timeout_ms: 1_000,
+        });
+        let address = address.to_string();
+
+        let mut head = None;
+        for _ in 0..50 {
+            match transport
+                .get_head(
+                    &address,
+                    GetHeadRequest {
+                        slot_id: 3,
+                        path: "a/b.txt".to_string(),
+                    },
+                )
+                .await
+            {
+                Ok(response) => {
+                    head = response.head;
+                    break;
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+        let head = head.expect("listener never answered");
+        let (meta, tombstone) = head.documents().unwrap();
+        assert_eq!(meta.unwrap().path, "a/b.txt");
+        assert!(tombstone.is_none());
+
+        let put = transport
+            .put_part(
+                &address,
+                PutPartRequest {
+                    data: bytes::Bytes::from_static(b"abc"),
+                    ..PutPartRequest::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(put.sha256, crate::compute_hash(b"abc"));
+
+        let missing = transport
+            .get_part(&address, GetPartRequest::default())
+            .await
+            .unwrap_err();
+        assert_eq!(http_status(&missing), reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(transport.channels.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn calls_without_the_cluster_key_are_refused() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        tokio::spawn(serve_internal_grpc(
+            address,
+            Arc::new(Echo),
+            Some("cluster-secret".to_string()),
+        ));
+
+        let config = InternalGrpcConfig {
+            listen_addr: address.to_string(),
+            advertise_addr: None,
+            timeout_ms: 5_000,
+            connect_timeout_ms: 1_000,
+        };
+        let keyless = InternalGrpcTransport::new(&config);
+        let guessing = InternalGrpcTransport::new(&config).with_cluster_key(Some("guess"));
+        let peer = InternalGrpcTransport::new(&config).with_cluster_key(Some("cluster-secret"));
+        let address = address.to_string();
+
+        let mut answered = false;
+        for _ in 0..50 {
+            if peer
+                .decide_transaction(&address, DecideTransactionRequest::default())
+                .await
+                .is_ok()
+            {
+                answered = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(answered, "listener never answered");
+
+        for transport in [&keyless, &guessing] {
+            let refused = transport
+                .decide_transaction(&address, DecideTransactionRequest::default())
+                .await
+                .unwrap_err();
+            assert_eq!(refused.code(), Code::Unauthenticated);
+            assert_eq!(http_status(&refused), reqwest::StatusCode::UNAUTHORIZED);
+        }
+    }
+}
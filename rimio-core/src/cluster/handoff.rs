@@ -0,0 +1,234 @@
+use super::client::ClusterClient;
+use super::placement::PlacementMap;
+use super::replication_policy::ReplicationPolicy;
+use crate::{Registry, Result, RimError, SlotInfo};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Paths a target is asked to copy per pull request.
+const HANDOFF_PULL_BATCH: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SlotHandoff {
+    pub slot_id: u16,
+    pub from: String,
+    pub to: String,
+    /// Paths copied to the new replica, catch-up included.
+    pub copied: usize,
+    /// Why the handoff stopped short of switching ownership.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Moves one replica of a slot to another node without a window where the
+/// slot has fewer up-to-date replicas.
+///
+/// The new replica copies the slot from the one it replaces: in bulk through
+/// a slot transfer when it holds nothing of the slot yet and no prefix policy
+/// applies, then path by path (a `heal/repair` with `replacing`) for whatever
+/// is still missing. The copy is checked against the source's heads, and
+/// only then is the slot reassigned, followed by a last catch-up copy of
+/// writes that raced the switch. The old replica keeps its data.
+///
+/// One mover serves every caller that moves replicas (the slot rebalancer
+/// and node decommission), so they stage moves the same way.
+pub struct SlotMover {
+    registry: Arc<dyn Registry>,
+    placement: Arc<PlacementMap>,
+    cluster_client: Arc<ClusterClient>,
+    replication_policy: ReplicationPolicy,
+}
+
+impl SlotMover {
+    pub fn new(
+        registry: Arc<dyn Registry>,
+        placement: Arc<PlacementMap>,
+        cluster_client: Arc<ClusterClient>,
+    ) -> Self {
+        Self {
+            registry,
+            placement,
+            cluster_client,
+            replication_policy: ReplicationPolicy::default(),
+        }
+    }
+
+    /// Skips paths the new replica would not hold under the policy when
+    /// copying and verifying.
+    pub fn with_replication_policy(mut self, replication_policy: ReplicationPolicy) -> Self {
+        self.replication_policy = replication_policy;
+        self
+    }
+
+    /// Copies, verifies and then reassigns one slot replica, giving up after
+    /// `copy_attempts` copy passes that still leave the new replica behind.
+    pub async fn hand_off(
+        &self,
+        slot: &SlotInfo,
+        from: &str,
+        to: &str,
+        copy_attempts: usize,
+    ) -> SlotHandoff {
+        let mut handoff = SlotHandoff {
+            slot_id: slot.slot_id,
+            from: from.to_string(),
+            to: to.to_string(),
+            copied: 0,
+            error: None,
+        };
+        let replicas: Vec<String> = slot
+            .replicas
+            .iter()
+            .map(|node| {
+                if node == from {
+                    to.to_string()
+                } else {
+                    node.clone()
+                }
+            })
+            .collect();
+
+        if self.replication_policy.is_empty() {
+            match self
+                .cluster_client
+                .request_slot_transfer(to, slot.slot_id, from)
+                .await
+            {
+                Ok(transfer) => handoff.copied += transfer.blob_count as usize,
+                Err(error) => tracing::debug!(
+                    "bulk slot transfer skipped, copying by path: slot={} to={} error={}",
+                    slot.slot_id,
+                    to,
+                    error
+                ),
+            }
+        }
+
+        let mut verified = false;
+        for _ in 0..copy_attempts.max(1) {
+            match self.copy_missing(slot.slot_id, from, to, &replicas).await {
+                Ok((0, _)) => {
+                    verified = true;
+                    break;
+                }
+                Ok((_, copied)) => handoff.copied += copied,
+                Err(error) => {
+                    handoff.error = Some(error.to_string());
+                    return handoff;
+                }
+            }
+        }
+        if !verified {
+            handoff.error = Some("new replica still behind the source after copying".to_string());
+            return handoff;
+        }
+
+        match self.registry.get_slot(slot.slot_id).await {
+            Ok(Some(current)) if current.epoch == slot.epoch => {}
+            Ok(_) => {
+                handoff.error = Some("slot placement changed during handoff".to_string());
+                return handoff;
+            }
+            Err(error) => {
+                handoff.error = Some(error.to_string());
+                return handoff;
+            }
+        }
+        match self
+            .placement
+            .reassign(slot.slot_id, replicas.clone())
+            .await
+        {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                handoff.error = Some("slot placement changed during handoff".to_string());
+                return handoff;
+            }
+            Err(error) => {
+                handoff.error = Some(error.to_string());
+                return handoff;
+            }
+        }
+
+        // Writes that reached the old replica set before the switch.
+        match self.copy_missing(slot.slot_id, from, to, &replicas).await {
+            Ok((_, copied)) => handoff.copied += copied,
+            Err(error) => tracing::warn!(
+                "slot handoff catch-up failed: slot={} from={} to={} error={}",
+                slot.slot_id,
+                from,
+                to,
+                error
+            ),
+        }
+
+        handoff
+    }
+
+    /// Asks `to` to pull every path `from` holds newer than it does. Returns
+    /// how many paths were behind before the pass and how many it copied.
+    async fn copy_missing(
+        &self,
+        slot_id: u16,
+        from: &str,
+        to: &str,
+        replicas: &[String],
+    ) -> Result<(usize, usize)> {
+        let source = self.cluster_client.fetch_slot_heads(from, slot_id).await?;
+        let target: HashMap<String, i64> = self
+            .cluster_client
+            .fetch_slot_heads(to, slot_id)
+            .await?
+            .into_iter()
+            .map(|head| (head.path, head.generation))
+            .collect();
+
+        let behind: Vec<String> = source
+            .into_iter()
+            .filter(|head| self.replication_policy.holds(&head.path, replicas, to))
+            .filter(|head| {
+                target
+                    .get(&head.path)
+                    .is_none_or(|generation| *generation < head.generation)
+            })
+            .map(|head| head.path)
+            .collect();
+
+        let mut copied = 0;
+        for batch in behind.chunks(HANDOFF_PULL_BATCH) {
+            let errors = self
+                .cluster_client
+                .request_handoff_pull(to, slot_id, from, from, batch)
+                .await?;
+            if let Some(error) = errors.first() {
+                return Err(RimError::Internal(format!(
+                    "slot copy failed: slot={} errors={} first={}",
+                    slot_id,
+                    errors.len(),
+                    error
+                )));
+            }
+            copied += batch.len();
+        }
+
+        Ok((behind.len(), copied))
+    }
+}
+
+/// Counts the slot replicas each node holds.
+pub(crate) fn slot_counts<'a>(
+    nodes: &[String],
+    slots: impl Iterator<Item = &'a SlotInfo>,
+) -> BTreeMap<String, usize> {
+    let mut counts: BTreeMap<String, usize> =
+        nodes.iter().map(|node_id| (node_id.clone(), 0)).collect();
+    for slot in slots {
+        for replica in &slot.replicas {
+            if let Some(count) = counts.get_mut(replica) {
+                *count += 1;
+            }
+        }
+    }
+    counts
+}
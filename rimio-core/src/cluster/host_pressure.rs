@@ -0,0 +1,339 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::interval;
+
+/// Limits above which a node counts as under pressure. A limit left unset is
+/// not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PressureThresholds {
+    /// Busy CPU time, in percent of all cores, since the previous sample.
+    #[serde(default)]
+    pub cpu_percent: Option<f64>,
+    /// Memory in use, in percent of `MemTotal` (`MemAvailable` counts as
+    /// free).
+    #[serde(default)]
+    pub memory_percent: Option<f64>,
+    /// One-minute load average divided by the number of cores.
+    #[serde(default)]
+    pub load_per_cpu: Option<f64>,
+}
+
+impl PressureThresholds {
+    /// Which limits `sample` is over, as readable reasons.
+    fn exceeded_by(&self, sample: &HostSample) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if let (Some(limit), Some(value)) = (self.cpu_percent, sample.cpu_percent)
+            && value > limit
+        {
+            reasons.push(format!("cpu {:.0}% > {:.0}%", value, limit));
+        }
+        if let (Some(limit), Some(value)) = (self.memory_percent, sample.memory_percent)
+            && value > limit
+        {
+            reasons.push(format!("memory {:.0}% > {:.0}%", value, limit));
+        }
+        if let (Some(limit), Some(value)) = (self.load_per_cpu, sample.load_per_cpu)
+            && value > limit
+        {
+            reasons.push(format!("load/cpu {:.2} > {:.2}", value, limit));
+        }
+        reasons
+    }
+}
+
+/// Host pressure thresholds. Past `background`, scrubs, maintenance, pruning
+/// and similar background work are skipped; past `shed`, low-priority
+/// requests are refused as well.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HostPressureConfig {
+    #[serde(default = "default_pressure_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub background: PressureThresholds,
+    #[serde(default)]
+    pub shed: PressureThresholds,
+}
+
+impl Default for HostPressureConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_pressure_interval_secs(),
+            background: PressureThresholds::default(),
+            shed: PressureThresholds::default(),
+        }
+    }
+}
+
+fn default_pressure_interval_secs() -> u64 {
+    5
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PressureLevel {
+    #[default]
+    Normal,
+    /// Background work is paused.
+    SheddingBackground,
+    /// Background work is paused and low-priority requests are refused.
+    SheddingTraffic,
+}
+
+impl PressureLevel {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::SheddingBackground,
+            2 => Self::SheddingTraffic,
+            _ => Self::Normal,
+        }
+    }
+}
+
+/// One reading of the host's resources. Values the host does not expose are
+/// `None`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HostSample {
+    pub cpu_percent: Option<f64>,
+    pub memory_percent: Option<f64>,
+    pub load_per_cpu: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HostPressureReport {
+    pub level: PressureLevel,
+    pub reasons: Vec<String>,
+    pub sample: HostSample,
+    pub sampled_at: Option<DateTime<Utc>>,
+}
+
+/// Samples host CPU, memory and load average from `/proc` and classifies the
+/// node's pressure level. On hosts without `/proc` the level stays normal.
+pub struct HostPressureMonitor {
+    config: HostPressureConfig,
+    level: AtomicU8,
+    report: RwLock<HostPressureReport>,
+    last_cpu: RwLock<Option<CpuTimes>>,
+}
+
+impl HostPressureMonitor {
+    pub fn new(config: HostPressureConfig) -> Self {
+        Self {
+            config,
+            level: AtomicU8::new(PressureLevel::Normal as u8),
+            report: RwLock::new(HostPressureReport::default()),
+            last_cpu: RwLock::new(None),
+        }
+    }
+
+    pub fn interval_secs(&self) -> u64 {
+        self.config.interval_secs.max(1)
+    }
+
+    pub fn level(&self) -> PressureLevel {
+        PressureLevel::from_u8(self.level.load(Ordering::Relaxed))
+    }
+
+    /// Whether background work should skip its current round.
+    ///
+    /// Background loops handed the monitor ask this before each round and
+    /// start nothing while it holds, trying again on their next tick. Work
+    /// already under way is not interrupted. The level is re-sampled every
+    /// `interval_secs`.
+    pub fn sheds_background(&self) -> bool {
+        self.level() >= PressureLevel::SheddingBackground
+    }
+
+    /// Whether low-priority requests should be refused.
+    pub fn sheds_traffic(&self) -> bool {
+        self.level() >= PressureLevel::SheddingTraffic
+    }
+
+    pub async fn report(&self) -> HostPressureReport {
+        self.report.read().await.clone()
+    }
+
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(self.interval_secs()));
+            loop {
+                ticker.tick().await;
+                self.sample_once().await;
+            }
+        });
+    }
+
+    pub async fn sample_once(&self) -> HostPressureReport {
+        let sample = self.sample().await;
+        let (level, reasons) = self.classify(&sample);
+
+        let previous = self.level();
+        self.level.store(level as u8, Ordering::Relaxed);
+        if level != previous {
+            tracing::warn!(
+                "host pressure changed: {:?} -> {:?} ({})",
+                previous,
+                level,
+                reasons.join(", ")
+            );
+        }
+
+        let report = HostPressureReport {
+            level,
+            reasons,
+            sample,
+            sampled_at: Some(Utc::now()),
+        };
+        *self.report.write().await = report.clone();
+        report
+    }
+
+    fn classify(&self, sample: &HostSample) -> (PressureLevel, Vec<String>) {
+        let reasons = self.config.shed.exceeded_by(sample);
+        if !reasons.is_empty() {
+            return (PressureLevel::SheddingTraffic, reasons);
+        }
+        let reasons = self.config.background.exceeded_by(sample);
+        if !reasons.is_empty() {
+            return (PressureLevel::SheddingBackground, reasons);
+        }
+        (PressureLevel::Normal, Vec::new())
+    }
+
+    async fn sample(&self) -> HostSample {
+        let cpus = std::thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1);
+
+        let cpu_percent = match tokio::fs::read_to_string("/proc/stat")
+            .await
+            .ok()
+            .and_then(|stat| CpuTimes::parse(&stat))
+        {
+            Some(current) => {
+                let mut last = self.last_cpu.write().await;
+                let percent = last.and_then(|last| current.busy_percent_since(&last));
+                *last = Some(current);
+                percent
+            }
+            None => None,
+        };
+
+        let memory_percent = tokio::fs::read_to_string("/proc/meminfo")
+            .await
+            .ok()
+            .and_then(|meminfo| memory_used_percent(&meminfo));
+
+        let load_per_cpu = tokio::fs::read_to_string("/proc/loadavg")
+            .await
+            .ok()
+            .and_then(|loadavg| loadavg.split_whitespace().next()?.parse::<f64>().ok())
+            .map(|load| load / cpus as f64);
+
+        HostSample {
+            cpu_percent,
+            memory_percent,
+            load_per_cpu,
+        }
+    }
+}
+
+/// Aggregate CPU counters from the first line of `/proc/stat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    fn parse(stat: &str) -> Option<Self> {
+        let line = stat.lines().find(|line| line.starts_with("cpu "))?;
+        let fields: Vec<u64> = line
+            .split_whitespace()
+            .skip(1)
+            .filter_map(|field| field.parse().ok())
+            .collect();
+        if fields.len() < 4 {
+            return None;
+        }
+        // user nice system idle iowait irq softirq steal ...; guest time is
+        // already counted in user.
+        let total: u64 = fields.iter().take(8).sum();
+        let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+        Some(Self {
+            busy: total.saturating_sub(idle),
+            total,
+        })
+    }
+
+    fn busy_percent_since(&self, earlier: &Self) -> Option<f64> {
+        let total = self.total.checked_sub(earlier.total)?;
+        if total == 0 {
+            return None;
+        }
+        let busy = self.busy.saturating_sub(earlier.busy);
+        Some(busy as f64 * 100.0 / total as f64)
+    }
+}
+
+fn memory_used_percent(meminfo: &str) -> Option<f64> {
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find(|line| line.starts_with(name))?
+            .split_whitespace()
+            .nth(1)?
+            .parse::<u64>()
+            .ok()
+    };
+    let total = field("MemTotal:")?;
+    let available = field("MemAvailable:")?;
+    if total == 0 {
+        return None;
+    }
+    Some(total.saturating_sub(available) as f64 * 100.0 / total as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proc_and_classifies_pressure() {
+        let earlier = CpuTimes::parse("cpu  100 0 100 800 0 0 0 0 0 0\ncpu0 1 2 3 4\n").unwrap();
+        let later = CpuTimes::parse("cpu  190 0 190 820 0 0 0 0 0 0\n").unwrap();
+        assert_eq!(later.busy_percent_since(&earlier), Some(90.0));
+
+        let meminfo = "MemTotal:       1000 kB\nMemFree:         50 kB\nMemAvailable:    250 kB\n";
+        assert_eq!(memory_used_percent(meminfo), Some(75.0));
+
+        let monitor = HostPressureMonitor::new(HostPressureConfig {
+            interval_secs: 5,
+            background: PressureThresholds {
+                cpu_percent: Some(80.0),
+                ..PressureThresholds::default()
+            },
+            shed: PressureThresholds {
+                memory_percent: Some(90.0),
+                ..PressureThresholds::default()
+            },
+        });
+        let sample = HostSample {
+            cpu_percent: Some(90.0),
+            memory_percent: Some(75.0),
+            load_per_cpu: None,
+        };
+        let (level, reasons) = monitor.classify(&sample);
+        assert_eq!(level, PressureLevel::SheddingBackground);
+        assert_eq!(reasons, vec!["cpu 90% > 80%".to_string()]);
+
+        let sample = HostSample {
+            memory_percent: Some(95.0),
+            ..sample
+        };
+        assert_eq!(monitor.classify(&sample).0, PressureLevel::SheddingTraffic);
+    }
+}
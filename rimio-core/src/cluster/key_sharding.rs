@@ -0,0 +1,99 @@
+use crate::slot_manager::{slot_for_key, slot_for_tagged_key, slot_hash_tag};
+use rimio_chunk::KeyHasher;
+use serde::{Deserialize, Serialize};
+use std::hash::Hasher;
+
+/// Spreads the keys of one bucket that share a `{tag}` over several slots.
+///
+/// Untagged keys are hashed whole and already land on scattered slots, but a
+/// tag such as `metrics/{2026-10-16}/...` sends a whole day of writes to one
+/// slot. With a rule, the tag is hashed together with a shard number derived
+/// from the rest of the key, so the tag covers up to `shards` slots.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyShardingRule {
+    /// First path component the rule applies to; for the S3 gateway this is
+    /// the bucket name.
+    pub bucket: String,
+    /// Slots each tag is spread over; 0 and 1 leave the tag on one slot.
+    pub shards: u16,
+}
+
+/// Slot of `key` under the cluster's routing: [`slot_for_key`] unless
+/// `hash_tags` is on, then the `{tag}` with [`KeyShardingRule`]s applied.
+///
+/// Every node must run with the same settings, and both change where the
+/// existing tagged keys live, so set them before the bucket is used.
+pub fn sharded_slot_for_key(
+    key: &str,
+    total_slots: u16,
+    hash_tags: bool,
+    rules: &[KeyShardingRule],
+) -> u16 {
+    let Some(tag) = slot_hash_tag(key).filter(|_| hash_tags) else {
+        return slot_for_key(key, total_slots);
+    };
+    let bucket = key.split('/').next().unwrap_or_default();
+    let Some(rule) = rules
+        .iter()
+        .find(|rule| rule.shards > 1 && rule.bucket.trim_matches('/') == bucket)
+    else {
+        return slot_for_tagged_key(key, total_slots);
+    };
+
+    let mut hasher = KeyHasher::new();
+    hasher.write_key(key);
+    let shard = hasher.finish() % rule.shards as u64;
+
+    let mut hasher = KeyHasher::new();
+    hasher.write_key(tag);
+    hasher.write_u64(shard);
+    (hasher.finish() % total_slots as u64) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn rules_spread_tags_only_in_their_bucket() {
+        let rules = vec![KeyShardingRule {
+            bucket: "metrics".to_string(),
+            shards: 8,
+        }];
+
+        let sharded: HashSet<u16> = (0..200)
+            .map(|n| sharded_slot_for_key(&format!("metrics/{{day}}/{}", n), 2048, true, &rules))
+            .collect();
+        assert!(sharded.len() > 1 && sharded.len() <= 8);
+
+        let other: HashSet<u16> = (0..200)
+            .map(|n| sharded_slot_for_key(&format!("logs/{{day}}/{}", n), 2048, true, &rules))
+            .collect();
+        assert_eq!(other.len(), 1);
+
+        assert_eq!(
+            sharded_slot_for_key("metrics/plain", 2048, true, &rules),
+            slot_for_key("metrics/plain", 2048)
+        );
+    }
+
+    #[test]
+    fn tags_are_ignored_unless_enabled() {
+        let rules = vec![KeyShardingRule {
+            bucket: "metrics".to_string(),
+            shards: 8,
+        }];
+
+        for key in ["metrics/{day}/1", "logs/{day}/1", "logs/plain"] {
+            assert_eq!(
+                sharded_slot_for_key(key, 2048, false, &rules),
+                slot_for_key(key, 2048)
+            );
+        }
+        assert_eq!(
+            sharded_slot_for_key("logs/{day}/1", 2048, true, &rules),
+            slot_for_key("day", 2048)
+        );
+    }
+}
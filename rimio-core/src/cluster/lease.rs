@@ -0,0 +1,90 @@
+use crate::{Registry, Result, SharedClock, SlotLease, system_clock};
+use chrono::Duration;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+
+/// Per-slot write leases granted through the registry.
+///
+/// The lease holder coordinates every write of a slot and runs them one at a
+/// time, which removes most generation conflicts between concurrent writers.
+/// Reads stay leaderless. A lease that is not renewed expires after its TTL,
+/// after which any replica can reclaim it.
+pub struct SlotLeaseManager {
+    registry: Arc<dyn Registry>,
+    node_id: String,
+    ttl_secs: u64,
+    leases: RwLock<HashMap<u16, SlotLease>>,
+    write_locks: Mutex<HashMap<u16, Arc<Mutex<()>>>>,
+    clock: SharedClock,
+}
+
+impl SlotLeaseManager {
+    pub fn new(registry: Arc<dyn Registry>, node_id: String, ttl_secs: u64) -> Self {
+        Self {
+            registry,
+            node_id,
+            ttl_secs: ttl_secs.max(1),
+            leases: RwLock::new(HashMap::new()),
+            write_locks: Mutex::new(HashMap::new()),
+            clock: system_clock(),
+        }
+    }
+
+    /// Judges lease expiry and renewal by `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn ttl_secs(&self) -> u64 {
+        self.ttl_secs
+    }
+
+    /// Acquires or renews the lease of a slot for this node and returns the
+    /// lease in effect, which may belong to another node.
+    ///
+    /// A lease we hold is renewed once less than half of its TTL remains.
+    pub async fn acquire(&self, slot_id: u16) -> Result<SlotLease> {
+        if let Some(lease) = self.leases.read().await.get(&slot_id)
+            && lease.holder == self.node_id
+            && lease.expires_at - self.clock.now() > Duration::seconds((self.ttl_secs / 2) as i64)
+        {
+            return Ok(lease.clone());
+        }
+
+        let lease = self
+            .registry
+            .acquire_slot_lease(slot_id, &self.node_id, self.ttl_secs)
+            .await?;
+
+        let mut leases = self.leases.write().await;
+        if lease.holder == self.node_id {
+            leases.insert(slot_id, lease.clone());
+        } else {
+            leases.remove(&slot_id);
+        }
+
+        Ok(lease)
+    }
+
+    /// Returns the unexpired lease of a slot without trying to take it.
+    pub async fn current(&self, slot_id: u16) -> Result<Option<SlotLease>> {
+        let now = self.clock.now();
+        Ok(self
+            .registry
+            .get_slot_lease(slot_id)
+            .await?
+            .filter(|lease| !lease.is_expired_at(now)))
+    }
+
+    /// Serializes writes of one slot on this node.
+    pub async fn lock_slot(&self, slot_id: u16) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.write_locks.lock().await;
+            locks.entry(slot_id).or_default().clone()
+        };
+
+        lock.lock_owned().await
+    }
+}
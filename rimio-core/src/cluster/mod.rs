@@ -1,12 +1,67 @@
+pub mod api_keys;
 pub mod client;
+pub mod disk_health;
+pub mod grpc;
+pub mod handoff;
+pub mod host_pressure;
+pub mod key_sharding;
+pub mod lease;
+pub mod peer_latency;
+pub mod placement;
+pub mod protocol;
+pub mod rebalancer;
+pub mod reconciler;
+pub mod replication_policy;
+pub mod slot_heat;
+pub mod slot_rebalancer;
 pub mod state;
 pub mod types;
 
+pub use api_keys::{ApiKey, ApiKeyGrant, validate_api_keys};
 pub use client::{ClusterClient, ClusterPartPayload};
+pub use disk_health::{
+    DiskHealth, DiskHealthConfig, DiskHealthMonitor, DiskHealthReport, MonitoredDisk, SmartReport,
+};
+pub use grpc::{
+    ApplyHeadsRequest, ApplyHeadsResponse, DecideTransactionRequest, DecideTransactionResponse,
+    GetHeadRequest, GetHeadResponse, GetPartRequest, GetPartResponse, HeadRecord,
+    INTERNAL_GRPC_SERVICE, InternalGrpcConfig, InternalGrpcHandler, InternalGrpcTransport,
+    PrepareTransactionRequest, PrepareTransactionResponse, PutHeadRequest, PutHeadResponse,
+    PutPartRequest, PutPartResponse, grpc_status, http_status, serve_internal_grpc,
+};
+pub use handoff::{SlotHandoff, SlotMover};
+pub use host_pressure::{
+    HostPressureConfig, HostPressureMonitor, HostPressureReport, HostSample, PressureLevel,
+    PressureThresholds,
+};
+pub use key_sharding::{KeyShardingRule, sharded_slot_for_key};
+pub use lease::SlotLeaseManager;
+pub use peer_latency::{
+    LATENCY_BUCKETS_MS, LatencyHistogram, LatencySummary, PeerCall, PeerLatencyItem,
+    PeerLatencyReport, PeerLatencyTracker, SlotLatencyItem,
+};
+pub use placement::{PlacementMap, SLOT_EPOCH_HEADER};
+pub use protocol::{
+    CAP_DELETE_PREFIX, CAP_HEAD_BATCH, CAP_HEAD_CHAIN, CAP_PART_LINK, CAP_PART_PROBE,
+    CAP_PLACEMENT_RELOAD, CAP_PRUNE_VERSIONS, CAP_SLOT_HANDOFF, CAP_SLOT_HEAT, CAP_SLOT_STATS,
+    CAP_SLOT_TRANSFER, CAP_TOMBSTONE_BATCH, CAP_TXN_2PC, CAP_TXN_RESOLVE, CAPABILITIES,
+    CLUSTER_KEY_HEADER, MIN_PROTOCOL_VERSION, PROTOCOL_CAPABILITIES_HEADER, PROTOCOL_VERSION,
+    PROTOCOL_VERSION_HEADER, PeerProtocol, PeerProtocolTable, cluster_key_headers,
+};
+pub use rebalancer::{HeatRebalanceConfig, HeatRebalanceReport, SlotHeatRebalancer, SlotMove};
+pub use reconciler::{SlotReconcileReport, SlotReconciler, SlotReconcilerConfig};
+pub use replication_policy::{PrefixReplicationPolicy, ReplicationPolicy};
+pub use slot_heat::{
+    HEAT_BYTES_PER_REQUEST, SLOT_HEAT_HALF_LIFE, SlotHeatItem, SlotHeatReport, SlotHeatTracker,
+    SlotTraffic,
+};
+pub use slot_rebalancer::{SlotRebalanceConfig, SlotRebalanceReport, SlotRebalancer};
 pub use state::ClusterManager;
 pub use types::{
-    ClusterArchiveConfig, ClusterArchiveRedisConfig, ClusterArchiveS3Config,
+    ClusterArchiveAzureConfig, ClusterArchiveConfig, ClusterArchiveFsConfig,
+    ClusterArchiveGcsConfig, ClusterArchiveRedisConfig, ClusterArchiveS3Config,
     ClusterArchiveS3Credentials, ClusterDiskConfig, ClusterInitRequest, ClusterInitResult,
     ClusterInitScanConfig, ClusterInitScanEntry, ClusterInitScanRedisConfig, ClusterNodeConfig,
-    ClusterReplicationConfig, ClusterState, Coordinator, ReplicatedPart,
+    ClusterReplicationConfig, ClusterState, Coordinator, PartSource, ReplicatedHead,
+    ReplicatedPart,
 };
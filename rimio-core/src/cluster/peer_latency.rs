@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds, in milliseconds, of the latency histogram buckets. Calls
+/// slower than the last bound land in an overflow bucket.
+pub const LATENCY_BUCKETS_MS: &[u64] = &[1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// Internal calls whose latency is tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerCall {
+    HeadRead,
+    HeadWrite,
+    PartRead,
+    PartWrite,
+}
+
+/// Cumulative latency distribution of one kind of call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    pub count: u64,
+    /// Calls that failed to connect or got a 5xx answer.
+    pub errors: u64,
+    pub total_ms: f64,
+    /// Calls per bucket of [`LATENCY_BUCKETS_MS`], plus the overflow bucket.
+    pub buckets: Vec<u64>,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            errors: 0,
+            total_ms: 0.0,
+            buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, elapsed: Duration, ok: bool) {
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| elapsed_ms <= *bound as f64)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total_ms += elapsed_ms;
+        if !ok {
+            self.errors += 1;
+        }
+    }
+
+    pub fn mean_ms(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total_ms / self.count as f64
+        }
+    }
+
+    /// Upper bound of the bucket holding the `quantile` call, or `None` when
+    /// it fell in the overflow bucket or nothing was recorded.
+    pub fn quantile_ms(&self, quantile: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((self.count as f64) * quantile.clamp(0.0, 1.0))
+            .ceil()
+            .max(1.0) as u64;
+        let mut seen = 0;
+        for (index, calls) in self.buckets.iter().enumerate() {
+            seen += calls;
+            if seen >= rank {
+                return LATENCY_BUCKETS_MS.get(index).copied();
+            }
+        }
+        None
+    }
+
+    fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            count: self.count,
+            errors: self.errors,
+            mean_ms: self.mean_ms(),
+            p50_ms: self.quantile_ms(0.5),
+            p99_ms: self.quantile_ms(0.99),
+            histogram: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub errors: u64,
+    pub mean_ms: f64,
+    pub p50_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+    pub histogram: LatencyHistogram,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerLatencyItem {
+    pub node_id: String,
+    pub calls: BTreeMap<PeerCall, LatencySummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotLatencyItem {
+    pub slot_id: u16,
+    pub calls: BTreeMap<PeerCall, LatencySummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerLatencyReport {
+    pub bucket_bounds_ms: Vec<u64>,
+    pub peers: Vec<PeerLatencyItem>,
+    pub slots: Vec<SlotLatencyItem>,
+}
+
+type CallHistograms = HashMap<PeerCall, LatencyHistogram>;
+
+/// Latency of internal head and part calls since startup, kept per peer and
+/// per slot, so a peer behind a degraded link stands out from the rest.
+#[derive(Debug, Default)]
+pub struct PeerLatencyTracker {
+    peers: Mutex<HashMap<String, CallHistograms>>,
+    slots: Mutex<HashMap<u16, CallHistograms>>,
+}
+
+impl PeerLatencyTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, node_id: &str, slot_id: u16, call: PeerCall, elapsed: Duration, ok: bool) {
+        if let Ok(mut peers) = self.peers.lock() {
+            peers
+                .entry(node_id.to_string())
+                .or_default()
+                .entry(call)
+                .or_default()
+                .record(elapsed, ok);
+        }
+        if let Ok(mut slots) = self.slots.lock() {
+            slots
+                .entry(slot_id)
+                .or_default()
+                .entry(call)
+                .or_default()
+                .record(elapsed, ok);
+        }
+    }
+
+    /// Mean latency of `call` to `node_id`, `None` before the first call.
+    pub fn mean_ms(&self, node_id: &str, call: PeerCall) -> Option<f64> {
+        let peers = self.peers.lock().ok()?;
+        peers
+            .get(node_id)?
+            .get(&call)
+            .filter(|histogram| histogram.count > 0)
+            .map(LatencyHistogram::mean_ms)
+    }
+
+    pub fn report(&self) -> PeerLatencyReport {
+        let mut peers: Vec<PeerLatencyItem> = self
+            .peers
+            .lock()
+            .map(|peers| {
+                peers
+                    .iter()
+                    .map(|(node_id, calls)| PeerLatencyItem {
+                        node_id: node_id.clone(),
+                        calls: summarize(calls),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        peers.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+
+        let mut slots: Vec<SlotLatencyItem> = self
+            .slots
+            .lock()
+            .map(|slots| {
+                slots
+                    .iter()
+                    .map(|(slot_id, calls)| SlotLatencyItem {
+                        slot_id: *slot_id,
+                        calls: summarize(calls),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        slots.sort_by_key(|item| item.slot_id);
+
+        PeerLatencyReport {
+            bucket_bounds_ms: LATENCY_BUCKETS_MS.to_vec(),
+            peers,
+            slots,
+        }
+    }
+}
+
+fn summarize(calls: &CallHistograms) -> BTreeMap<PeerCall, LatencySummary> {
+    calls
+        .iter()
+        .map(|(call, histogram)| (*call, histogram.summary()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_tracks_peers_and_slots() {
+        let tracker = PeerLatencyTracker::new();
+        for _ in 0..9 {
+            tracker.record("n1", 7, PeerCall::PartRead, Duration::from_millis(3), true);
+        }
+        tracker.record("n1", 7, PeerCall::PartRead, Duration::from_secs(9), false);
+        tracker.record(
+            "n2",
+            8,
+            PeerCall::HeadRead,
+            Duration::from_micros(200),
+            true,
+        );
+
+        let report = tracker.report();
+        assert_eq!(report.peers.len(), 2);
+        let part_reads = &report.peers[0].calls[&PeerCall::PartRead];
+        assert_eq!(part_reads.count, 10);
+        assert_eq!(part_reads.errors, 1);
+        assert_eq!(part_reads.p50_ms, Some(5));
+        assert_eq!(part_reads.p99_ms, None);
+        assert_eq!(*part_reads.histogram.buckets.last().unwrap(), 1);
+
+        assert_eq!(report.slots[1].slot_id, 8);
+        assert_eq!(report.slots[1].calls[&PeerCall::HeadRead].p50_ms, Some(1));
+    }
+}
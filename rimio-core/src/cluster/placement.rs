@@ -0,0 +1,209 @@
+use crate::{Registry, Result, RimError, SlotInfo};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use ulid::Ulid;
+
+/// Header carrying the coordinator's slot epoch on internal write requests.
+pub const SLOT_EPOCH_HEADER: &str = "x-rimio-slot-epoch";
+
+/// Cached view of the slot placement map stored in the registry.
+///
+/// Every slot maps to an explicit replica set carrying an epoch; routing,
+/// writes and heals consult this map instead of deriving replicas from the
+/// node list. Changes go through [`PlacementMap::reassign`], which only wins
+/// against the epoch it read.
+pub struct PlacementMap {
+    registry: Arc<dyn Registry>,
+    slots: RwLock<HashMap<u16, SlotInfo>>,
+}
+
+impl PlacementMap {
+    pub fn new(registry: Arc<dyn Registry>) -> Self {
+        Self {
+            registry,
+            slots: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Reloads the whole map from the registry and returns the slot count.
+    pub async fn refresh(&self) -> Result<usize> {
+        let slots = self.registry.get_all_slots().await?;
+        let count = slots.len();
+        *self.slots.write().await = slots;
+        Ok(count)
+    }
+
+    /// Returns the placement of a slot, reading through to the registry when
+    /// the slot is not cached yet.
+    pub async fn slot(&self, slot_id: u16) -> Result<Option<SlotInfo>> {
+        if let Some(slot) = self.slots.read().await.get(&slot_id) {
+            return Ok(Some(slot.clone()));
+        }
+
+        let slot = self.registry.get_slot(slot_id).await?;
+        if let Some(slot) = &slot {
+            self.slots.write().await.insert(slot_id, slot.clone());
+        }
+
+        Ok(slot)
+    }
+
+    /// Returns every cached slot placement.
+    pub async fn slots(&self) -> Vec<SlotInfo> {
+        self.slots.read().await.values().cloned().collect()
+    }
+
+    /// Returns the cached epoch of a slot, or 0 when the slot has no placement.
+    pub async fn epoch(&self, slot_id: u16) -> u64 {
+        match self.slot(slot_id).await {
+            Ok(Some(slot)) => slot.epoch,
+            Ok(None) => 0,
+            Err(error) => {
+                tracing::warn!(
+                    "Failed to resolve slot epoch: slot={} error={}",
+                    slot_id,
+                    error
+                );
+                0
+            }
+        }
+    }
+
+    /// Returns whether a slot is frozen for maintenance, as cached.
+    pub async fn is_frozen(&self, slot_id: u16) -> bool {
+        match self.slot(slot_id).await {
+            Ok(slot) => slot.is_some_and(|slot| slot.frozen),
+            Err(error) => {
+                tracing::warn!(
+                    "Failed to resolve slot freeze: slot={} error={}",
+                    slot_id,
+                    error
+                );
+                false
+            }
+        }
+    }
+
+    /// Returns the epoch of a slot to fence a request carrying `seen_epoch`
+    /// against. When the request has seen a newer epoch than we cached, the
+    /// slot is reloaded from the registry first.
+    pub async fn fencing_epoch(&self, slot_id: u16, seen_epoch: u64) -> Result<u64> {
+        let cached = self.slot(slot_id).await?.map(|slot| slot.epoch);
+        if cached.is_some_and(|epoch| epoch >= seen_epoch) {
+            return Ok(cached.unwrap_or_default());
+        }
+
+        let slot = self.registry.get_slot(slot_id).await?;
+        let mut slots = self.slots.write().await;
+        match slot {
+            Some(slot) => {
+                let epoch = slot.epoch;
+                slots.insert(slot_id, slot);
+                Ok(epoch)
+            }
+            None => {
+                slots.remove(&slot_id);
+                Ok(0)
+            }
+        }
+    }
+
+    /// Drops a cached slot so the next lookup reads it from the registry.
+    pub async fn invalidate(&self, slot_id: u16) {
+        self.slots.write().await.remove(&slot_id);
+    }
+
+    /// Moves a slot to a new replica set and bumps its epoch.
+    ///
+    /// Returns `None` when another writer changed the slot since it was read.
+    pub async fn reassign(&self, slot_id: u16, replicas: Vec<String>) -> Result<Option<SlotInfo>> {
+        let current = self.registry.get_slot(slot_id).await?;
+        let expected_epoch = current.as_ref().map(|slot| slot.epoch);
+
+        let next = SlotInfo {
+            slot_id,
+            primary: replicas.first().cloned().unwrap_or_default(),
+            replicas,
+            latest_seq: current
+                .as_ref()
+                .map(|slot| slot.latest_seq.clone())
+                .unwrap_or_else(|| Ulid::new().to_string()),
+            epoch: expected_epoch.unwrap_or_default() + 1,
+            frozen: current.as_ref().is_some_and(|slot| slot.frozen),
+        };
+
+        if !self
+            .registry
+            .compare_and_set_slot(&next, expected_epoch)
+            .await?
+        {
+            self.slots.write().await.remove(&slot_id);
+            return Ok(None);
+        }
+
+        tracing::info!(
+            "slot placement changed: slot={} epoch={} replicas={:?}",
+            slot_id,
+            next.epoch,
+            next.replicas
+        );
+        self.slots.write().await.insert(slot_id, next.clone());
+        Ok(Some(next))
+    }
+
+    /// Freezes or thaws a slot. The epoch is bumped with the flag, so writes
+    /// a coordinator stamped before the change are fenced by replicas that
+    /// already see it.
+    ///
+    /// Returns `None` when another writer changed the slot since it was read.
+    pub async fn set_frozen(&self, slot_id: u16, frozen: bool) -> Result<Option<SlotInfo>> {
+        let current = self
+            .registry
+            .get_slot(slot_id)
+            .await?
+            .ok_or(RimError::SlotNotFound(slot_id))?;
+        if current.frozen == frozen {
+            self.slots.write().await.insert(slot_id, current.clone());
+            return Ok(Some(current));
+        }
+
+        let next = SlotInfo {
+            epoch: current.epoch + 1,
+            frozen,
+            ..current.clone()
+        };
+        if !self
+            .registry
+            .compare_and_set_slot(&next, Some(current.epoch))
+            .await?
+        {
+            self.slots.write().await.remove(&slot_id);
+            return Ok(None);
+        }
+
+        tracing::info!(
+            "slot {}: slot={} epoch={}",
+            if frozen { "frozen" } else { "thawed" },
+            slot_id,
+            next.epoch
+        );
+        self.slots.write().await.insert(slot_id, next.clone());
+        Ok(Some(next))
+    }
+
+    /// Refreshes the cached map in the background.
+    pub fn start_refresh(self: &Arc<Self>, every: Duration) {
+        let placement = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                if let Err(error) = placement.refresh().await {
+                    tracing::warn!("Failed to refresh slot placement: {}", error);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,234 @@
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Header carrying the sender's internal protocol version.
+pub const PROTOCOL_VERSION_HEADER: &str = "x-rimio-protocol-version";
+/// Header carrying the sender's comma-separated capability list.
+pub const PROTOCOL_CAPABILITIES_HEADER: &str = "x-rimio-capabilities";
+/// Header carrying the shared cluster key that admits a peer to the
+/// `/internal/` routes of a node with auth enabled.
+pub const CLUSTER_KEY_HEADER: &str = "x-rimio-cluster-key";
+
+/// Internal protocol version spoken by this build.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// Oldest peer version this build still talks to. Nodes that predate the
+/// handshake send no version header and count as version 1.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Peer applies a list of heads in one transaction via `heads/batch`.
+pub const CAP_HEAD_BATCH: &str = "head-batch";
+/// Peer reports per-slot statistics via `stats`.
+pub const CAP_SLOT_STATS: &str = "slot-stats";
+/// Peer prunes expired versions of a slot via `prune`.
+pub const CAP_PRUNE_VERSIONS: &str = "prune-versions";
+/// Peer reports the traffic it served per slot via `heat`.
+pub const CAP_SLOT_HEAT: &str = "slot-heat";
+/// Peer holds prepared transaction batches via `transactions/:txn_id`.
+pub const CAP_TXN_2PC: &str = "txn-2pc";
+/// Peer reports what it knows about a transaction via `transactions/:txn_id`.
+pub const CAP_TXN_RESOLVE: &str = "txn-resolve";
+/// Peer copies a slot it is about to take over via `heal/repair` with
+/// `replacing`.
+pub const CAP_SLOT_HANDOFF: &str = "slot-handoff";
+/// Peer exports slots and pulls them in bulk via `transfer`.
+pub const CAP_SLOT_TRANSFER: &str = "slot-transfer";
+/// Peer verifies the head chains of a slot via `head-chain`.
+pub const CAP_HEAD_CHAIN: &str = "head-chain";
+/// Peer answers `HEAD` on `parts/:sha256` without sending the bytes.
+pub const CAP_PART_PROBE: &str = "part-probe";
+/// Peer hands out the tombstones of many paths at once via
+/// `heal/tombstones`.
+pub const CAP_TOMBSTONE_BATCH: &str = "tombstone-batch";
+/// Peer reloads the placement of a slot on request via `placement`.
+pub const CAP_PLACEMENT_RELOAD: &str = "placement-reload";
+/// Peer indexes parts it holds for one version under another via
+/// `parts/link`.
+pub const CAP_PART_LINK: &str = "part-link";
+/// Peer walks a prefix delete through a slot whose write lease it holds via
+/// `delete-prefix`.
+pub const CAP_DELETE_PREFIX: &str = "delete-prefix";
+
+/// Capabilities advertised by this build.
+pub const CAPABILITIES: &[&str] = &[
+    CAP_HEAD_BATCH,
+    CAP_SLOT_STATS,
+    CAP_PRUNE_VERSIONS,
+    CAP_SLOT_HEAT,
+    CAP_TXN_2PC,
+    CAP_TXN_RESOLVE,
+    CAP_SLOT_HANDOFF,
+    CAP_SLOT_TRANSFER,
+    CAP_HEAD_CHAIN,
+    CAP_PART_PROBE,
+    CAP_TOMBSTONE_BATCH,
+    CAP_PLACEMENT_RELOAD,
+    CAP_PART_LINK,
+    CAP_DELETE_PREFIX,
+];
+
+/// How long a learned peer protocol is trusted before it is asked again, so
+/// a peer restarted on another version during a rolling upgrade is noticed.
+const PEER_PROTOCOL_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerProtocol {
+    pub version: u32,
+    #[serde(default)]
+    pub capabilities: BTreeSet<String>,
+}
+
+impl PeerProtocol {
+    /// The protocol this build speaks.
+    pub fn local() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.iter().map(|cap| cap.to_string()).collect(),
+        }
+    }
+
+    /// The protocol of a node that predates the handshake: the routes it
+    /// always had and no optional capabilities.
+    pub fn legacy() -> Self {
+        Self {
+            version: 1,
+            capabilities: BTreeSet::new(),
+        }
+    }
+
+    /// Reads a peer's protocol from its headers, or `None` when it sent no
+    /// version header.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let version = headers
+            .get(PROTOCOL_VERSION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u32>().ok())?;
+        let capabilities = headers
+            .get(PROTOCOL_CAPABILITIES_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|cap| !cap.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            version,
+            capabilities,
+        })
+    }
+
+    /// Headers announcing the local protocol on requests and responses.
+    pub fn local_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(PROTOCOL_VERSION_HEADER, HeaderValue::from(PROTOCOL_VERSION));
+        if let Ok(value) = HeaderValue::from_str(&CAPABILITIES.join(",")) {
+            headers.insert(PROTOCOL_CAPABILITIES_HEADER, value);
+        }
+        headers
+    }
+
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        self.version >= MIN_PROTOCOL_VERSION
+    }
+}
+
+/// Headers presenting `cluster_key` to a peer; empty without one.
+pub fn cluster_key_headers(cluster_key: Option<&str>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(value) = cluster_key.and_then(|key| HeaderValue::from_str(key).ok()) {
+        headers.insert(CLUSTER_KEY_HEADER, value);
+    }
+    headers
+}
+
+/// Protocols learned from peers, keyed by node id.
+#[derive(Default)]
+pub struct PeerProtocolTable {
+    peers: RwLock<HashMap<String, (PeerProtocol, Instant)>>,
+}
+
+impl PeerProtocolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached protocol of a peer while it is fresh.
+    pub async fn get(&self, node_id: &str) -> Option<PeerProtocol> {
+        self.peers
+            .read()
+            .await
+            .get(node_id)
+            .filter(|(_, learned_at)| learned_at.elapsed() < PEER_PROTOCOL_TTL)
+            .map(|(protocol, _)| protocol.clone())
+    }
+
+    pub async fn record(&self, node_id: &str, protocol: PeerProtocol) {
+        let previous = self
+            .peers
+            .write()
+            .await
+            .insert(node_id.to_string(), (protocol.clone(), Instant::now()));
+        if previous.is_none_or(|(known, _)| known != protocol) {
+            tracing::info!(
+                "peer protocol: node={} version={} capabilities={:?}",
+                node_id,
+                protocol.version,
+                protocol.capabilities
+            );
+        }
+    }
+
+    pub async fn forget(&self, node_id: &str) {
+        self.peers.write().await.remove(node_id);
+    }
+
+    /// Every peer protocol learned so far, fresh or not.
+    pub async fn snapshot(&self) -> Vec<(String, PeerProtocol)> {
+        let mut peers: Vec<_> = self
+            .peers
+            .read()
+            .await
+            .iter()
+            .map(|(node_id, (protocol, _))| (node_id.clone(), protocol.clone()))
+            .collect();
+        peers.sort_by(|a, b| a.0.cmp(&b.0));
+        peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_round_trip_and_missing_version_is_none() {
+        let protocol = PeerProtocol::from_headers(&PeerProtocol::local_headers()).unwrap();
+        assert_eq!(protocol, PeerProtocol::local());
+        assert!(protocol.supports(CAP_HEAD_BATCH));
+
+        let mut headers = HeaderMap::new();
+        assert!(PeerProtocol::from_headers(&headers).is_none());
+
+        headers.insert(PROTOCOL_VERSION_HEADER, HeaderValue::from_static("7"));
+        headers.insert(
+            PROTOCOL_CAPABILITIES_HEADER,
+            HeaderValue::from_static(" future-thing , head-batch,"),
+        );
+        let protocol = PeerProtocol::from_headers(&headers).unwrap();
+        assert_eq!(protocol.version, 7);
+        assert!(protocol.supports("future-thing"));
+        assert!(!protocol.supports(CAP_SLOT_STATS));
+        assert!(protocol.is_compatible());
+    }
+}
@@ -0,0 +1,379 @@
+use super::client::ClusterClient;
+use super::host_pressure::HostPressureMonitor;
+use super::placement::PlacementMap;
+use super::slot_heat::SlotHeatTracker;
+use crate::{NodeStatus, Registry, Result, SlotInfo};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::interval;
+
+/// When and how far the heat rebalancer moves slots.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeatRebalanceConfig {
+    #[serde(default = "default_rebalance_interval_secs")]
+    pub interval_secs: u64,
+    /// A node counts as hot once its heat exceeds the cluster mean by this
+    /// factor.
+    #[serde(default = "default_imbalance_ratio")]
+    pub imbalance_ratio: f64,
+    /// Nodes cooler than this are never hot, so a quiet cluster stays put.
+    #[serde(default = "default_min_node_heat")]
+    pub min_node_heat: f64,
+    /// Slot moves per round; each move makes the new replica copy the slot.
+    #[serde(default = "default_max_moves")]
+    pub max_moves: usize,
+}
+
+impl Default for HeatRebalanceConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_rebalance_interval_secs(),
+            imbalance_ratio: default_imbalance_ratio(),
+            min_node_heat: default_min_node_heat(),
+            max_moves: default_max_moves(),
+        }
+    }
+}
+
+fn default_rebalance_interval_secs() -> u64 {
+    300
+}
+
+fn default_imbalance_ratio() -> f64 {
+    1.5
+}
+
+fn default_min_node_heat() -> f64 {
+    1.0
+}
+
+fn default_max_moves() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SlotMove {
+    pub slot_id: u16,
+    pub from: String,
+    pub to: String,
+    pub heat: f64,
+}
+
+/// Outcome of the latest rebalance round.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HeatRebalanceReport {
+    pub checked_at: Option<DateTime<Utc>>,
+    /// Why the round did nothing, if it was skipped.
+    pub skipped: Option<String>,
+    /// Summed heat of the slots each healthy node replicates, before moves.
+    pub node_heat: BTreeMap<String, f64>,
+    pub moves: Vec<SlotMove>,
+}
+
+/// Spreads hot slots across nodes.
+///
+/// Each round sums the slot heat every healthy node reports, charges a
+/// slot's heat to each of its replicas, and moves the hottest slot off the
+/// hottest node to the coolest node outside its replica set while that node
+/// stays over `imbalance_ratio` times the mean. Only the healthy node with the
+/// lowest id acts, so nodes do not race each other for the same slots.
+pub struct SlotHeatRebalancer {
+    local_node_id: String,
+    registry: Arc<dyn Registry>,
+    placement: Arc<PlacementMap>,
+    cluster_client: Arc<ClusterClient>,
+    tracker: Arc<SlotHeatTracker>,
+    config: HeatRebalanceConfig,
+    host_pressure: Option<Arc<HostPressureMonitor>>,
+    report: RwLock<HeatRebalanceReport>,
+}
+
+impl SlotHeatRebalancer {
+    pub fn new(
+        local_node_id: String,
+        registry: Arc<dyn Registry>,
+        placement: Arc<PlacementMap>,
+        cluster_client: Arc<ClusterClient>,
+        tracker: Arc<SlotHeatTracker>,
+        config: HeatRebalanceConfig,
+    ) -> Self {
+        Self {
+            local_node_id,
+            registry,
+            placement,
+            cluster_client,
+            tracker,
+            config,
+            host_pressure: None,
+            report: RwLock::new(HeatRebalanceReport::default()),
+        }
+    }
+
+    /// Leaves hot slots where they are while
+    /// [`HostPressureMonitor::sheds_background`] holds; the report names the
+    /// skipped round.
+    pub fn with_host_pressure(mut self, host_pressure: Option<Arc<HostPressureMonitor>>) -> Self {
+        self.host_pressure = host_pressure;
+        self
+    }
+
+    pub async fn report(&self) -> HeatRebalanceReport {
+        self.report.read().await.clone()
+    }
+
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(self.config.interval_secs.max(1)));
+            // The first tick fires at once; skip it so rates have time to build.
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(error) = self.rebalance_once().await {
+                    tracing::warn!("slot heat rebalance failed: {}", error);
+                }
+            }
+        });
+    }
+
+    pub async fn rebalance_once(&self) -> Result<HeatRebalanceReport> {
+        let report = self.plan_and_apply().await?;
+        *self.report.write().await = report.clone();
+        Ok(report)
+    }
+
+    async fn plan_and_apply(&self) -> Result<HeatRebalanceReport> {
+        let mut report = HeatRebalanceReport {
+            checked_at: Some(Utc::now()),
+            ..HeatRebalanceReport::default()
+        };
+
+        if self
+            .host_pressure
+            .as_ref()
+            .is_some_and(|pressure| pressure.sheds_background())
+        {
+            report.skipped = Some("host under pressure".to_string());
+            return Ok(report);
+        }
+
+        let mut healthy: Vec<String> = self
+            .registry
+            .get_nodes()
+            .await?
+            .into_iter()
+            .filter(|node| node.status == NodeStatus::Healthy)
+            .map(|node| node.node_id)
+            .collect();
+        healthy.sort();
+        if healthy.first() != Some(&self.local_node_id) {
+            report.skipped = Some("another node runs the rebalancer".to_string());
+            return Ok(report);
+        }
+
+        let mut slot_heat: BTreeMap<u16, f64> = BTreeMap::new();
+        for node_id in &healthy {
+            let node_report = if node_id == &self.local_node_id {
+                self.tracker.report()
+            } else {
+                match self.cluster_client.fetch_slot_heat(node_id).await {
+                    Ok(node_report) => node_report,
+                    Err(error) => {
+                        tracing::warn!(
+                            "Failed to fetch slot heat: node={} error={}",
+                            node_id,
+                            error
+                        );
+                        continue;
+                    }
+                }
+            };
+            for item in node_report.slots {
+                *slot_heat.entry(item.slot_id).or_default() += item.heat;
+            }
+        }
+
+        self.placement.refresh().await?;
+        let mut slots: BTreeMap<u16, SlotInfo> = self
+            .placement
+            .slots()
+            .await
+            .into_iter()
+            .map(|slot| (slot.slot_id, slot))
+            .collect();
+
+        let mut node_heat = heat_per_node(&healthy, slots.values(), &slot_heat);
+        report.node_heat = node_heat.clone();
+
+        while report.moves.len() < self.config.max_moves {
+            let Some(planned) = plan_move(&self.config, &slots, &slot_heat, &node_heat) else {
+                break;
+            };
+            let Some(slot) = slots.get(&planned.slot_id) else {
+                break;
+            };
+            let replicas: Vec<String> = slot
+                .replicas
+                .iter()
+                .map(|node| {
+                    if node == &planned.from {
+                        planned.to.clone()
+                    } else {
+                        node.clone()
+                    }
+                })
+                .collect();
+
+            match self.placement.reassign(planned.slot_id, replicas).await? {
+                Some(next) => {
+                    tracing::info!(
+                        "moved hot slot: slot={} from={} to={} heat={:.2}",
+                        planned.slot_id,
+                        planned.from,
+                        planned.to,
+                        planned.heat
+                    );
+                    *node_heat.entry(planned.from.clone()).or_default() -= planned.heat;
+                    *node_heat.entry(planned.to.clone()).or_default() += planned.heat;
+                    slots.insert(planned.slot_id, next);
+                    report.moves.push(planned);
+                }
+                // Someone else changed the slot; leave it for the next round.
+                None => break,
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Sums the heat of the slots each node replicates.
+fn heat_per_node<'a>(
+    nodes: &[String],
+    slots: impl Iterator<Item = &'a SlotInfo>,
+    slot_heat: &BTreeMap<u16, f64>,
+) -> BTreeMap<String, f64> {
+    let mut node_heat: BTreeMap<String, f64> =
+        nodes.iter().map(|node_id| (node_id.clone(), 0.0)).collect();
+    for slot in slots {
+        let heat = slot_heat.get(&slot.slot_id).copied().unwrap_or_default();
+        for replica in &slot.replicas {
+            if let Some(total) = node_heat.get_mut(replica) {
+                *total += heat;
+            }
+        }
+    }
+    node_heat
+}
+
+/// Picks the hottest slot on the hottest node whose move to the coolest node
+/// outside its replica set leaves both nodes cooler than the hottest one was.
+fn plan_move(
+    config: &HeatRebalanceConfig,
+    slots: &BTreeMap<u16, SlotInfo>,
+    slot_heat: &BTreeMap<u16, f64>,
+    node_heat: &BTreeMap<String, f64>,
+) -> Option<SlotMove> {
+    if node_heat.len() < 2 {
+        return None;
+    }
+    let mean = node_heat.values().sum::<f64>() / node_heat.len() as f64;
+    let (hot_node, hot_heat) = node_heat
+        .iter()
+        .max_by(|(a_id, a), (b_id, b)| a.total_cmp(b).then_with(|| b_id.cmp(a_id)))?;
+    if *hot_heat < config.min_node_heat || *hot_heat <= mean * config.imbalance_ratio {
+        return None;
+    }
+
+    let mut candidates: Vec<(u16, f64)> = slots
+        .values()
+        .filter(|slot| slot.replicas.contains(hot_node))
+        .map(|slot| {
+            (
+                slot.slot_id,
+                slot_heat.get(&slot.slot_id).copied().unwrap_or_default(),
+            )
+        })
+        .filter(|(_, heat)| *heat > 0.0)
+        .collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    for (slot_id, heat) in candidates {
+        let replicas = &slots[&slot_id].replicas;
+        let Some((target, target_heat)) = node_heat
+            .iter()
+            .filter(|(node_id, _)| !replicas.contains(node_id))
+            .min_by(|(a_id, a), (b_id, b)| a.total_cmp(b).then_with(|| a_id.cmp(b_id)))
+        else {
+            continue;
+        };
+        if target_heat + heat < *hot_heat {
+            return Some(SlotMove {
+                slot_id,
+                from: hot_node.clone(),
+                to: target.clone(),
+                heat,
+            });
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(slot_id: u16, replicas: &[&str]) -> SlotInfo {
+        SlotInfo {
+            slot_id,
+            primary: replicas[0].to_string(),
+            replicas: replicas.iter().map(|node| node.to_string()).collect(),
+            latest_seq: String::new(),
+            epoch: 1,
+            frozen: false,
+        }
+    }
+
+    #[test]
+    fn moves_hottest_slot_off_hottest_node() {
+        let config = HeatRebalanceConfig::default();
+        let nodes: Vec<String> = ["a", "b", "c", "d"].map(String::from).to_vec();
+        let slots: BTreeMap<u16, SlotInfo> = [
+            slot(0, &["a", "b"]),
+            slot(1, &["a", "c"]),
+            slot(2, &["b", "c"]),
+            slot(3, &["a", "d"]),
+        ]
+        .into_iter()
+        .map(|slot| (slot.slot_id, slot))
+        .collect();
+        let slot_heat: BTreeMap<u16, f64> = [(0, 2.0), (1, 20.0), (2, 1.0), (3, 5.0)].into();
+
+        let node_heat = heat_per_node(&nodes, slots.values(), &slot_heat);
+        assert_eq!(node_heat["a"], 27.0);
+        assert_eq!(node_heat["b"], 3.0);
+        assert_eq!(node_heat["c"], 21.0);
+        assert_eq!(node_heat["d"], 5.0);
+
+        // c already holds slot 1, so it goes to b rather than d.
+        let planned = plan_move(&config, &slots, &slot_heat, &node_heat).unwrap();
+        assert_eq!(
+            planned,
+            SlotMove {
+                slot_id: 1,
+                from: "a".to_string(),
+                to: "b".to_string(),
+                heat: 20.0,
+            }
+        );
+
+        let balanced: BTreeMap<String, f64> = [("a", 10.0), ("b", 9.0), ("c", 8.0)]
+            .map(|(node_id, heat)| (node_id.to_string(), heat))
+            .into();
+        assert!(plan_move(&config, &slots, &slot_heat, &balanced).is_none());
+    }
+}
@@ -0,0 +1,164 @@
+use super::placement::PlacementMap;
+use crate::{MetadataStore, Result, SlotManager};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::interval;
+
+#[derive(Debug, Clone)]
+pub struct SlotReconcilerConfig {
+    pub interval: Duration,
+}
+
+impl Default for SlotReconcilerConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Outcome of the latest reconcile pass.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SlotReconcileReport {
+    pub checked_at: Option<DateTime<Utc>>,
+    pub owned_slots: usize,
+    pub held_slots: usize,
+    /// Owned slots initialized by the last pass.
+    pub initialized: Vec<u16>,
+    /// Owned slots that still hold no data and wait to be filled from peers.
+    pub pending_transfers: Vec<u16>,
+    /// Slots held on disk that the placement map assigns elsewhere.
+    pub pending_gc: Vec<u16>,
+}
+
+/// Keeps locally held slots in line with the registry's placement map.
+///
+/// Each pass initializes slots placed on this node but missing locally, and
+/// flags the opposite case for garbage collection. Nothing is deleted here;
+/// transfers and GC pick their work from the report.
+pub struct SlotReconciler {
+    local_node_id: String,
+    slot_manager: Arc<SlotManager>,
+    placement: Arc<PlacementMap>,
+    config: SlotReconcilerConfig,
+    pending_transfers: RwLock<BTreeSet<u16>>,
+    report: RwLock<SlotReconcileReport>,
+}
+
+impl SlotReconciler {
+    pub fn new(
+        local_node_id: String,
+        slot_manager: Arc<SlotManager>,
+        placement: Arc<PlacementMap>,
+        config: SlotReconcilerConfig,
+    ) -> Self {
+        Self {
+            local_node_id,
+            slot_manager,
+            placement,
+            config,
+            pending_transfers: RwLock::new(BTreeSet::new()),
+            report: RwLock::new(SlotReconcileReport::default()),
+        }
+    }
+
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(self.config.interval);
+            loop {
+                ticker.tick().await;
+                if let Err(error) = self.reconcile_once().await {
+                    tracing::warn!("slot reconcile loop failed: {}", error);
+                }
+            }
+        });
+    }
+
+    pub async fn reconcile_once(&self) -> Result<SlotReconcileReport> {
+        if self.placement.refresh().await? == 0 {
+            // Without a placement map every held slot would look orphaned.
+            let report = SlotReconcileReport {
+                checked_at: Some(Utc::now()),
+                ..SlotReconcileReport::default()
+            };
+            *self.report.write().await = report.clone();
+            return Ok(report);
+        }
+
+        let owned: BTreeSet<u16> = self
+            .placement
+            .slots()
+            .await
+            .into_iter()
+            .filter(|slot| slot.replicas.iter().any(|node| node == &self.local_node_id))
+            .map(|slot| slot.slot_id)
+            .collect();
+        let held: BTreeSet<u16> = self.slot_manager.list_local_slots()?.into_iter().collect();
+
+        let mut initialized = Vec::new();
+        for slot_id in owned.difference(&held) {
+            self.slot_manager.init_slot(*slot_id).await?;
+            initialized.push(*slot_id);
+        }
+
+        let pending_transfers = {
+            let mut pending = self.pending_transfers.write().await;
+            pending.extend(initialized.iter().copied());
+            pending.retain(|slot_id| owned.contains(slot_id));
+
+            let mut filled = Vec::new();
+            for slot_id in pending.iter() {
+                if self.slot_has_data(*slot_id).await? {
+                    filled.push(*slot_id);
+                }
+            }
+            for slot_id in filled {
+                pending.remove(&slot_id);
+            }
+
+            pending.iter().copied().collect::<Vec<_>>()
+        };
+
+        let pending_gc: Vec<u16> = held.difference(&owned).copied().collect();
+
+        if !initialized.is_empty() || !pending_gc.is_empty() {
+            tracing::info!(
+                "slot reconcile: node={} owned={} held={} initialized={} pending_transfers={} pending_gc={}",
+                self.local_node_id,
+                owned.len(),
+                held.len(),
+                initialized.len(),
+                pending_transfers.len(),
+                pending_gc.len()
+            );
+        }
+
+        let report = SlotReconcileReport {
+            checked_at: Some(Utc::now()),
+            owned_slots: owned.len(),
+            held_slots: held.len(),
+            initialized,
+            pending_transfers,
+            pending_gc,
+        };
+        *self.report.write().await = report.clone();
+        Ok(report)
+    }
+
+    pub async fn last_report(&self) -> SlotReconcileReport {
+        self.report.read().await.clone()
+    }
+
+    async fn slot_has_data(&self, slot_id: u16) -> Result<bool> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        Ok(MetadataStore::new(slot)?.slot_stats()?.blob_count > 0)
+    }
+}
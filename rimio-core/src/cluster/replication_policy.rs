@@ -0,0 +1,142 @@
+use crate::NodeInfo;
+use serde::{Deserialize, Serialize};
+
+/// Replication applied to blobs under a path prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrefixReplicationPolicy {
+    /// Path prefix; a trailing glob is ignored, so `telemetry/**` and
+    /// `telemetry/` match the same paths.
+    pub prefix: String,
+    /// Keep blobs only on the node that coordinated the write.
+    #[serde(default)]
+    pub local_only: bool,
+    /// Replicate to the first `replicas` nodes of the slot's replica set
+    /// instead of all of them.
+    #[serde(default)]
+    pub replicas: Option<usize>,
+}
+
+impl PrefixReplicationPolicy {
+    fn prefix(&self) -> &str {
+        self.prefix.trim_end_matches('*')
+    }
+}
+
+/// Evaluates [`PrefixReplicationPolicy`] rules; the longest matching prefix
+/// wins and paths no rule matches go to the whole replica set.
+///
+/// Writes consult it to pick their replicas and heals to decide whether a
+/// node should hold a path at all, so every node must run with the same
+/// rules.
+#[derive(Debug, Clone, Default)]
+pub struct ReplicationPolicy {
+    rules: Vec<PrefixReplicationPolicy>,
+}
+
+impl ReplicationPolicy {
+    pub fn new(rules: Vec<PrefixReplicationPolicy>) -> Self {
+        Self { rules }
+    }
+
+    /// Whether every path goes to every replica.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rule_for(&self, path: &str) -> Option<&PrefixReplicationPolicy> {
+        self.rules
+            .iter()
+            .filter(|rule| path.starts_with(rule.prefix()))
+            .max_by_key(|rule| rule.prefix().len())
+    }
+
+    /// Narrows a slot's replica set to the nodes a write of `path` goes to.
+    /// The coordinator keeps its own copy either way.
+    pub fn write_replicas(
+        &self,
+        path: &str,
+        mut replicas: Vec<NodeInfo>,
+        local_node_id: &str,
+    ) -> Vec<NodeInfo> {
+        match self.rule_for(path) {
+            Some(rule) if rule.local_only => {
+                replicas.retain(|node| node.node_id == local_node_id);
+            }
+            Some(PrefixReplicationPolicy {
+                replicas: Some(count),
+                ..
+            }) => replicas.truncate((*count).max(1)),
+            _ => {}
+        }
+        replicas
+    }
+
+    /// Whether heal should copy `path` onto `node_id`, given the slot's
+    /// replica set in placement order. Local-only blobs are never copied.
+    pub fn holds(&self, path: &str, replica_ids: &[String], node_id: &str) -> bool {
+        match self.rule_for(path) {
+            Some(rule) if rule.local_only => false,
+            Some(PrefixReplicationPolicy {
+                replicas: Some(count),
+                ..
+            }) => replica_ids
+                .iter()
+                .take((*count).max(1))
+                .any(|replica| replica == node_id),
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NodeStatus;
+
+    fn node(node_id: &str) -> NodeInfo {
+        NodeInfo {
+            node_id: node_id.to_string(),
+            group_id: "g".to_string(),
+            address: String::new(),
+            status: NodeStatus::Healthy,
+            slots: Vec::new(),
+            grpc_address: None,
+        }
+    }
+
+    #[test]
+    fn longest_prefix_decides_writes_and_heals() {
+        let policy = ReplicationPolicy::new(vec![
+            PrefixReplicationPolicy {
+                prefix: "telemetry/**".to_string(),
+                local_only: true,
+                replicas: None,
+            },
+            PrefixReplicationPolicy {
+                prefix: "telemetry/crash/".to_string(),
+                local_only: false,
+                replicas: Some(2),
+            },
+        ]);
+        let replicas = vec![node("n1"), node("n2"), node("n3")];
+        let ids: Vec<String> = replicas.iter().map(|n| n.node_id.clone()).collect();
+
+        let local = policy.write_replicas("telemetry/cpu", replicas.clone(), "n2");
+        assert_eq!(local.len(), 1);
+        assert_eq!(local[0].node_id, "n2");
+        assert!(!policy.holds("telemetry/cpu", &ids, "n2"));
+
+        let crash = policy.write_replicas("telemetry/crash/dump", replicas.clone(), "n3");
+        assert_eq!(crash.len(), 2);
+        assert!(policy.holds("telemetry/crash/dump", &ids, "n2"));
+        assert!(!policy.holds("telemetry/crash/dump", &ids, "n3"));
+
+        assert_eq!(
+            policy
+                .write_replicas("firmware/fw.bin", replicas, "n1")
+                .len(),
+            3
+        );
+        assert!(policy.holds("firmware/fw.bin", &ids, "n3"));
+    }
+}
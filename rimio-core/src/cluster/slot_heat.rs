@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Half-life of the decaying request and byte rates: traffic from this long
+/// ago counts half as much as traffic now.
+pub const SLOT_HEAT_HALF_LIFE: Duration = Duration::from_secs(60);
+
+/// Bytes per second that weigh as much as one request per second in a
+/// slot's heat score.
+pub const HEAT_BYTES_PER_REQUEST: f64 = 1024.0 * 1024.0;
+
+/// Direction of the blob traffic recorded against a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotTraffic {
+    Read,
+    Write,
+}
+
+/// Traffic this node served for one slot: totals since startup and decaying
+/// rates.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlotHeatItem {
+    pub slot_id: u16,
+    pub reads: u64,
+    pub writes: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub requests_per_sec: f64,
+    pub bytes_per_sec: f64,
+    /// `requests_per_sec` plus `bytes_per_sec` in units of
+    /// [`HEAT_BYTES_PER_REQUEST`].
+    pub heat: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlotHeatReport {
+    pub half_life_secs: u64,
+    /// Slots with any recorded traffic, hottest first.
+    pub slots: Vec<SlotHeatItem>,
+}
+
+/// Counter that halves every [`SLOT_HEAT_HALF_LIFE`]; under a steady rate it
+/// converges to `rate * half_life / ln 2`.
+#[derive(Debug, Clone, Copy)]
+struct DecayingRate {
+    value: f64,
+    updated: Instant,
+}
+
+impl DecayingRate {
+    fn new(now: Instant) -> Self {
+        Self {
+            value: 0.0,
+            updated: now,
+        }
+    }
+
+    fn decayed(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.value * 0.5f64.powf(elapsed / SLOT_HEAT_HALF_LIFE.as_secs_f64())
+    }
+
+    fn add(&mut self, amount: f64, now: Instant) {
+        self.value = self.decayed(now) + amount;
+        self.updated = now;
+    }
+
+    fn per_sec(&self, now: Instant) -> f64 {
+        self.decayed(now) * std::f64::consts::LN_2 / SLOT_HEAT_HALF_LIFE.as_secs_f64()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SlotCounters {
+    reads: u64,
+    writes: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+    requests: DecayingRate,
+    bytes: DecayingRate,
+}
+
+impl SlotCounters {
+    fn new(now: Instant) -> Self {
+        Self {
+            reads: 0,
+            writes: 0,
+            read_bytes: 0,
+            write_bytes: 0,
+            requests: DecayingRate::new(now),
+            bytes: DecayingRate::new(now),
+        }
+    }
+
+    fn item(&self, slot_id: u16, now: Instant) -> SlotHeatItem {
+        let requests_per_sec = self.requests.per_sec(now);
+        let bytes_per_sec = self.bytes.per_sec(now);
+        SlotHeatItem {
+            slot_id,
+            reads: self.reads,
+            writes: self.writes,
+            read_bytes: self.read_bytes,
+            write_bytes: self.write_bytes,
+            requests_per_sec,
+            bytes_per_sec,
+            heat: requests_per_sec + bytes_per_sec / HEAT_BYTES_PER_REQUEST,
+        }
+    }
+}
+
+/// Blob requests and bytes this node served per slot, as the coordinator of
+/// the request. Each node only sees its own share; the rebalancer sums the
+/// reports of all nodes.
+#[derive(Debug, Default)]
+pub struct SlotHeatTracker {
+    slots: Mutex<HashMap<u16, SlotCounters>>,
+}
+
+impl SlotHeatTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, slot_id: u16, traffic: SlotTraffic, bytes: u64) {
+        self.record_at(slot_id, traffic, bytes, Instant::now());
+    }
+
+    fn record_at(&self, slot_id: u16, traffic: SlotTraffic, bytes: u64, now: Instant) {
+        let Ok(mut slots) = self.slots.lock() else {
+            return;
+        };
+        let counters = slots
+            .entry(slot_id)
+            .or_insert_with(|| SlotCounters::new(now));
+        match traffic {
+            SlotTraffic::Read => {
+                counters.reads += 1;
+                counters.read_bytes += bytes;
+            }
+            SlotTraffic::Write => {
+                counters.writes += 1;
+                counters.write_bytes += bytes;
+            }
+        }
+        counters.requests.add(1.0, now);
+        counters.bytes.add(bytes as f64, now);
+    }
+
+    pub fn report(&self) -> SlotHeatReport {
+        self.report_at(Instant::now())
+    }
+
+    fn report_at(&self, now: Instant) -> SlotHeatReport {
+        let mut slots: Vec<SlotHeatItem> = self
+            .slots
+            .lock()
+            .map(|slots| {
+                slots
+                    .iter()
+                    .map(|(slot_id, counters)| counters.item(*slot_id, now))
+                    .collect()
+            })
+            .unwrap_or_default();
+        slots.sort_by(|a, b| {
+            b.heat
+                .total_cmp(&a.heat)
+                .then_with(|| a.slot_id.cmp(&b.slot_id))
+        });
+
+        SlotHeatReport {
+            half_life_secs: SLOT_HEAT_HALF_LIFE.as_secs(),
+            slots,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_decay_and_hot_slots_sort_first() {
+        let tracker = SlotHeatTracker::new();
+        let start = Instant::now();
+        for second in 0..600 {
+            let now = start + Duration::from_secs(second);
+            tracker.record_at(3, SlotTraffic::Read, 1024 * 1024, now);
+            tracker.record_at(3, SlotTraffic::Write, 0, now);
+            if second % 10 == 0 {
+                tracker.record_at(9, SlotTraffic::Read, 0, now);
+            }
+        }
+
+        let now = start + Duration::from_secs(599);
+        let report = tracker.report_at(now);
+        assert_eq!(report.slots[0].slot_id, 3);
+        assert_eq!(report.slots[0].reads, 600);
+        assert_eq!(report.slots[0].writes, 600);
+        assert_eq!(report.slots[0].read_bytes, 600 * 1024 * 1024);
+        // Two requests and 1 MiB per second, sampled right after a tick.
+        assert!((report.slots[0].requests_per_sec - 2.0).abs() < 0.05);
+        assert!((report.slots[0].heat - 3.0).abs() < 0.1);
+        assert!((report.slots[1].requests_per_sec - 0.1).abs() < 0.05);
+
+        let later = tracker.report_at(now + SLOT_HEAT_HALF_LIFE);
+        assert!(
+            (later.slots[0].requests_per_sec - report.slots[0].requests_per_sec / 2.0).abs() < 1e-9
+        );
+    }
+}
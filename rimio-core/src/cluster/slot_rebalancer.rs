@@ -0,0 +1,320 @@
+use super::handoff::{SlotHandoff, SlotMover, slot_counts};
+use super::host_pressure::HostPressureMonitor;
+use super::placement::PlacementMap;
+use crate::{NodeStatus, Registry, Result, SlotInfo};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::interval;
+
+/// When and how many slots the membership rebalancer hands off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotRebalanceConfig {
+    #[serde(default = "default_rebalance_interval_secs")]
+    pub interval_secs: u64,
+    /// Slot handoffs per round; each one copies the slot to its new replica.
+    #[serde(default = "default_max_handoffs")]
+    pub max_handoffs: usize,
+    /// Copy and verify passes before a handoff gives up for this round.
+    #[serde(default = "default_copy_attempts")]
+    pub copy_attempts: usize,
+}
+
+impl Default for SlotRebalanceConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_rebalance_interval_secs(),
+            max_handoffs: default_max_handoffs(),
+            copy_attempts: default_copy_attempts(),
+        }
+    }
+}
+
+fn default_rebalance_interval_secs() -> u64 {
+    60
+}
+
+fn default_max_handoffs() -> usize {
+    4
+}
+
+fn default_copy_attempts() -> usize {
+    3
+}
+
+/// Outcome of the latest membership rebalance round.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SlotRebalanceReport {
+    pub checked_at: Option<DateTime<Utc>>,
+    /// Why the round did nothing, if it was skipped.
+    pub skipped: Option<String>,
+    /// Healthy nodes seen this round.
+    pub members: Vec<String>,
+    /// The healthy node set differs from the previous round.
+    pub membership_changed: bool,
+    /// Slot replicas each healthy node held before the round's handoffs.
+    pub slot_counts: BTreeMap<String, usize>,
+    pub handoffs: Vec<SlotHandoff>,
+}
+
+/// Spreads slot replicas evenly over the healthy nodes as nodes join.
+///
+/// Each round moves replicas from the most loaded node to the least loaded
+/// one until they differ by at most one, so only the slots needed to even
+/// out the counts move. Every move is staged through [`SlotMover`]. Slots
+/// with a replica on an unhealthy node are left alone. Only the healthy node
+/// with the lowest id acts.
+pub struct SlotRebalancer {
+    local_node_id: String,
+    registry: Arc<dyn Registry>,
+    placement: Arc<PlacementMap>,
+    mover: Arc<SlotMover>,
+    config: SlotRebalanceConfig,
+    host_pressure: Option<Arc<HostPressureMonitor>>,
+    members: RwLock<Option<BTreeSet<String>>>,
+    report: RwLock<SlotRebalanceReport>,
+}
+
+impl SlotRebalancer {
+    pub fn new(
+        local_node_id: String,
+        registry: Arc<dyn Registry>,
+        placement: Arc<PlacementMap>,
+        mover: Arc<SlotMover>,
+        config: SlotRebalanceConfig,
+    ) -> Self {
+        Self {
+            local_node_id,
+            registry,
+            placement,
+            mover,
+            config,
+            host_pressure: None,
+            members: RwLock::new(None),
+            report: RwLock::new(SlotRebalanceReport::default()),
+        }
+    }
+
+    /// Postpones handing replicas to joining nodes while
+    /// [`HostPressureMonitor::sheds_background`] holds; the report names the
+    /// skipped round.
+    pub fn with_host_pressure(mut self, host_pressure: Option<Arc<HostPressureMonitor>>) -> Self {
+        self.host_pressure = host_pressure;
+        self
+    }
+
+    pub async fn report(&self) -> SlotRebalanceReport {
+        self.report.read().await.clone()
+    }
+
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(self.config.interval_secs.max(1)));
+            loop {
+                ticker.tick().await;
+                if let Err(error) = self.rebalance_once().await {
+                    tracing::warn!("slot rebalance failed: {}", error);
+                }
+            }
+        });
+    }
+
+    pub async fn rebalance_once(&self) -> Result<SlotRebalanceReport> {
+        let report = self.plan_and_hand_off().await?;
+        *self.report.write().await = report.clone();
+        Ok(report)
+    }
+
+    async fn plan_and_hand_off(&self) -> Result<SlotRebalanceReport> {
+        let mut report = SlotRebalanceReport {
+            checked_at: Some(Utc::now()),
+            ..SlotRebalanceReport::default()
+        };
+
+        let healthy: BTreeSet<String> = self
+            .registry
+            .get_nodes()
+            .await?
+            .into_iter()
+            .filter(|node| node.status == NodeStatus::Healthy)
+            .map(|node| node.node_id)
+            .collect();
+        {
+            let mut members = self.members.write().await;
+            report.membership_changed = members.as_ref() != Some(&healthy);
+            if report.membership_changed {
+                tracing::info!("slot rebalancer saw membership: nodes={:?}", healthy);
+            }
+            *members = Some(healthy.clone());
+        }
+        report.members = healthy.iter().cloned().collect();
+
+        if healthy.first() != Some(&self.local_node_id) {
+            report.skipped = Some("another node runs the rebalancer".to_string());
+            return Ok(report);
+        }
+        if self
+            .host_pressure
+            .as_ref()
+            .is_some_and(|pressure| pressure.sheds_background())
+        {
+            report.skipped = Some("host under pressure".to_string());
+            return Ok(report);
+        }
+
+        self.placement.refresh().await?;
+        let slots: BTreeMap<u16, SlotInfo> = self
+            .placement
+            .slots()
+            .await
+            .into_iter()
+            .map(|slot| (slot.slot_id, slot))
+            .collect();
+        report.slot_counts = slot_counts(&report.members, slots.values());
+
+        for (slot_id, from, to) in plan_handoffs(&report.members, &slots, self.config.max_handoffs)
+        {
+            let handoff = self
+                .mover
+                .hand_off(&slots[&slot_id], &from, &to, self.config.copy_attempts)
+                .await;
+            match &handoff.error {
+                Some(error) => tracing::warn!(
+                    "slot handoff failed: slot={} from={} to={} error={}",
+                    slot_id,
+                    from,
+                    to,
+                    error
+                ),
+                None => tracing::info!(
+                    "handed off slot: slot={} from={} to={} copied={}",
+                    slot_id,
+                    from,
+                    to,
+                    handoff.copied
+                ),
+            }
+            report.handoffs.push(handoff);
+        }
+
+        Ok(report)
+    }
+}
+
+/// Plans up to `max_handoffs` replica moves `(slot, from, to)` from the most
+/// to the least loaded node, stopping once no two nodes differ by more than
+/// one replica. Slots with a replica outside `nodes` are not touched.
+fn plan_handoffs(
+    nodes: &[String],
+    slots: &BTreeMap<u16, SlotInfo>,
+    max_handoffs: usize,
+) -> Vec<(u16, String, String)> {
+    let mut counts = slot_counts(nodes, slots.values());
+    let mut replicas: BTreeMap<u16, Vec<String>> = slots
+        .values()
+        .filter(|slot| slot.replicas.iter().all(|node| counts.contains_key(node)))
+        .map(|slot| (slot.slot_id, slot.replicas.clone()))
+        .collect();
+    let mut moved = BTreeSet::new();
+    let mut plan = Vec::new();
+
+    while plan.len() < max_handoffs {
+        let Some((busiest, busiest_count)) = counts
+            .iter()
+            .max_by(|(a_id, a), (b_id, b)| a.cmp(b).then_with(|| b_id.cmp(a_id)))
+            .map(|(node_id, count)| (node_id.clone(), *count))
+        else {
+            break;
+        };
+
+        let mut targets: Vec<(String, usize)> = counts
+            .iter()
+            .filter(|(_, count)| **count + 1 < busiest_count)
+            .map(|(node_id, count)| (node_id.clone(), *count))
+            .collect();
+        targets.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+
+        let next = targets.into_iter().find_map(|(target, _)| {
+            replicas
+                .iter()
+                .find(|(slot_id, nodes)| {
+                    !moved.contains(*slot_id)
+                        && nodes.contains(&busiest)
+                        && !nodes.contains(&target)
+                })
+                .map(|(slot_id, _)| (*slot_id, target))
+        });
+        let Some((slot_id, target)) = next else {
+            break;
+        };
+
+        if let Some(nodes) = replicas.get_mut(&slot_id) {
+            for node in nodes.iter_mut() {
+                if *node == busiest {
+                    *node = target.clone();
+                }
+            }
+        }
+        *counts.entry(busiest.clone()).or_default() -= 1;
+        *counts.entry(target.clone()).or_default() += 1;
+        moved.insert(slot_id);
+        plan.push((slot_id, busiest, target));
+    }
+
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(slot_id: u16, replicas: &[&str]) -> SlotInfo {
+        SlotInfo {
+            slot_id,
+            primary: replicas[0].to_string(),
+            replicas: replicas.iter().map(|node| node.to_string()).collect(),
+            latest_seq: String::new(),
+            epoch: 1,
+            frozen: false,
+        }
+    }
+
+    #[test]
+    fn joining_node_takes_just_enough_slots() {
+        let slots: BTreeMap<u16, SlotInfo> = [
+            slot(0, &["a", "b"]),
+            slot(1, &["b", "c"]),
+            slot(2, &["c", "a"]),
+            slot(3, &["a", "b"]),
+            slot(4, &["b", "c"]),
+            slot(5, &["c", "a"]),
+            slot(6, &["a", "x"]),
+        ]
+        .into_iter()
+        .map(|slot| (slot.slot_id, slot))
+        .collect();
+        let nodes: Vec<String> = ["a", "b", "c", "d"].map(String::from).to_vec();
+
+        let plan = plan_handoffs(&nodes, &slots, 16);
+        // a holds 5 replicas, b and c 4 each; d takes three of them. Slot 6
+        // has a replica on an unknown node and stays put.
+        assert_eq!(plan.len(), 3);
+        assert!(plan.iter().all(|(_, _, to)| to == "d"));
+        assert!(plan.iter().all(|(slot_id, _, _)| *slot_id != 6));
+
+        let mut slots = slots;
+        for (slot_id, from, to) in &plan {
+            let slot = slots.get_mut(slot_id).unwrap();
+            for node in slot.replicas.iter_mut() {
+                if node == from {
+                    *node = to.clone();
+                }
+            }
+        }
+        assert!(plan_handoffs(&nodes, &slots, 16).is_empty());
+        assert_eq!(plan_handoffs(&nodes[..3], &slots, 16), Vec::new());
+    }
+}
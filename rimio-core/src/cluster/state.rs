@@ -3,10 +3,12 @@ use super::types::{
     ClusterNodeConfig, ClusterState,
 };
 use crate::{
-    ArchiveStore, BlobMeta, MetadataStore, PartIndexState, RedisArchiveStore, RegistryBuilder,
-    Result, RimError, SlotInfo, SlotManager, slot_for_key,
+    ArchiveStore, BlobMeta, ContentHeaders, MetadataStore, PartIndexState, RedisArchiveStore,
+    RegistryBuilder, Result, RimError, SlotInfo, SlotManager, normalize_blob_path,
+    sharded_slot_for_key,
 };
 use chrono::Utc;
+use std::collections::BTreeMap;
 use ulid::Ulid;
 
 #[derive(Clone)]
@@ -123,11 +125,14 @@ async fn ensure_slot_assignments(
             replicas,
             primary,
             latest_seq: Ulid::new().to_string(),
+            epoch: 1,
+            frozen: false,
         };
 
-        registry.set_slot(&slot).await?;
+        if registry.compare_and_set_slot(&slot, None).await? {
+            created += 1;
+        }
         existing.insert(slot_id, slot);
-        created += 1;
     }
 
     if created > 0 {
@@ -232,7 +237,12 @@ async fn run_optional_init_scan(
             })?;
 
             let normalized_path = normalize_blob_path(&entry.path)?;
-            let slot_id = slot_for_key(&normalized_path, state.replication.total_slots);
+            let slot_id = sharded_slot_for_key(
+                &normalized_path,
+                state.replication.total_slots,
+                state.replication.hash_tags,
+                &state.replication.key_sharding,
+            );
 
             if !slot_manager.has_slot(slot_id).await {
                 slot_manager.init_slot(slot_id).await?;
@@ -268,6 +278,9 @@ async fn run_optional_init_scan(
                 part_index_state: PartIndexState::None,
                 archive_url: Some(entry.archive_url.clone()),
                 updated_at,
+                metadata: BTreeMap::new(),
+                tags: BTreeMap::new(),
+                content: ContentHeaders::default(),
             };
 
             let applied = metadata_store.upsert_meta(&meta)?;
@@ -298,25 +311,3 @@ async fn run_optional_init_scan(
     tracing::info!("init_scan imported {} objects", imported);
     Ok(())
 }
-
-fn normalize_blob_path(path: &str) -> Result<String> {
-    let trimmed = path.trim_matches('/');
-    if trimmed.is_empty() {
-        return Err(RimError::InvalidRequest(
-            "blob path cannot be empty".to_string(),
-        ));
-    }
-
-    let mut components = Vec::new();
-    for component in trimmed.split('/') {
-        if component.is_empty() || component == "." || component == ".." {
-            return Err(RimError::InvalidRequest(format!(
-                "invalid blob path component: {}",
-                component
-            )));
-        }
-        components.push(component);
-    }
-
-    Ok(components.join("/"))
-}
@@ -1,3 +1,7 @@
+use crate::{
+    ArchiveTieringConfig, HeadWrite, KeyShardingRule, PrefixReplicationPolicy, ReadConsistency,
+    VersionRetention, WideProbeMode,
+};
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -15,6 +19,24 @@ pub struct ReplicatedPart {
     pub data: Bytes,
 }
 
+/// A head shipped to a replica as part of a batch, with the parts it references.
+#[derive(Clone)]
+pub struct ReplicatedHead {
+    pub write: HeadWrite,
+    pub parts: Vec<ReplicatedPart>,
+    /// A version whose parts the replica already holds; the head reuses
+    /// them and `parts` is empty.
+    pub linked_from: Option<PartSource>,
+}
+
+/// One version of a blob, named as the holder of parts another head reuses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartSource {
+    pub path: String,
+    pub generation: i64,
+    pub part_count: u32,
+}
+
 impl Coordinator {
     pub fn new(min_write_replicas: usize) -> Self {
         Self { min_write_replicas }
@@ -28,6 +50,10 @@ impl Coordinator {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClusterDiskConfig {
     pub path: PathBuf,
+    #[serde(default)]
+    pub smart_device: Option<String>,
+    #[serde(default)]
+    pub drain_on_failure: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +77,29 @@ impl ClusterNodeConfig {
 pub struct ClusterReplicationConfig {
     pub min_write_replicas: usize,
     pub total_slots: u16,
+    /// TTL of per-slot write leases; leases are disabled when unset.
+    #[serde(default)]
+    pub write_lease_ttl_secs: Option<u64>,
+    #[serde(default)]
+    pub wide_probe: WideProbeMode,
+    /// Replicas a read consults when the client does not ask for a level.
+    #[serde(default)]
+    pub read_consistency: ReadConsistency,
+    /// Per-prefix overrides of where blobs are replicated.
+    #[serde(default)]
+    pub prefix_policies: Vec<PrefixReplicationPolicy>,
+    /// Whether a `{tag}` in a path picks its slot instead of the whole path.
+    #[serde(default)]
+    pub hash_tags: bool,
+    /// Per-bucket spreading of tagged keys over several slots.
+    #[serde(default)]
+    pub key_sharding: Vec<KeyShardingRule>,
+    /// How long superseded versions and tombstones are kept.
+    #[serde(default)]
+    pub retention: VersionRetention,
+    /// Prefixes whose pruned part files are overwritten before unlinking.
+    #[serde(default)]
+    pub secure_delete: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,6 +107,16 @@ pub struct ClusterArchiveConfig {
     pub archive_type: String,
     pub s3: Option<ClusterArchiveS3Config>,
     pub redis: Option<ClusterArchiveRedisConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcs: Option<ClusterArchiveGcsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azure: Option<ClusterArchiveAzureConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs: Option<ClusterArchiveFsConfig>,
+    #[serde(default)]
+    pub require_write_through: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tiering: Option<ArchiveTieringConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,6 +136,34 @@ pub struct ClusterArchiveS3Credentials {
     pub secret_access_key: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterArchiveGcsConfig {
+    pub bucket: String,
+    #[serde(default)]
+    pub service_account_path: Option<String>,
+    #[serde(default)]
+    pub service_account_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterArchiveAzureConfig {
+    pub account: String,
+    pub container: String,
+    #[serde(default)]
+    pub access_key: Option<String>,
+    #[serde(default)]
+    pub sas_token: Option<String>,
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    #[serde(default)]
+    pub allow_http: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterArchiveFsConfig {
+    pub path: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClusterArchiveRedisConfig {
     pub url: String,
@@ -0,0 +1,276 @@
+//! Parallel blob downloads for clients.
+//!
+//! [`ParallelDownloader`] asks one node for the manifest of a blob, then
+//! fetches its parts with `Range` GETs spread over every node that holds
+//! them, so a large download is bounded by the client's bandwidth rather than
+//! one node's uplink. Each range carries the manifest's etag in `If-Range`:
+//! a blob overwritten mid-download fails instead of mixing two versions.
+
+use crate::{ReadBlobManifest, ReadBlobManifestPart, Result, RimError, compute_hash};
+use bytes::{Bytes, BytesMut};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use reqwest::{Client, StatusCode, header};
+use std::time::Duration;
+
+/// Part requests in flight at once when not configured.
+pub const DEFAULT_DOWNLOAD_CONCURRENCY: usize = 8;
+
+pub struct ParallelDownloader {
+    client: Client,
+    concurrency: usize,
+    timeout: Option<Duration>,
+}
+
+impl Default for ParallelDownloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParallelDownloader {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+            concurrency: DEFAULT_DOWNLOAD_CONCURRENCY,
+            timeout: None,
+        }
+    }
+
+    /// Reuses the caller's HTTP client and its connection pool.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Limits each manifest and part request, not the whole download.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The manifest of `path` as `address` sees it, or `None` when the blob
+    /// is missing or deleted.
+    pub async fn manifest(&self, address: &str, path: &str) -> Result<Option<ReadBlobManifest>> {
+        let url = format!("{}?manifest", blob_url(address, path));
+        let response = self.send(self.client.get(&url)).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::GONE => Ok(None),
+            status if status.is_success() => response
+                .json::<ReadBlobManifest>()
+                .await
+                .map(Some)
+                .map_err(|error| RimError::Http(error.to_string())),
+            status => Err(RimError::Http(format!(
+                "manifest request failed: url={} status={}",
+                url, status
+            ))),
+        }
+    }
+
+    /// Downloads the current version of `path`, asking `address` for the
+    /// manifest and the holders of each part for its bytes. Returns `None`
+    /// when the blob is missing or deleted.
+    pub async fn download(&self, address: &str, path: &str) -> Result<Option<Bytes>> {
+        let Some(manifest) = self.manifest(address, path).await? else {
+            return Ok(None);
+        };
+
+        let mut parts = stream::iter(manifest.parts.iter().enumerate())
+            .map(|(index, part)| {
+                let sources = part_sources(&manifest, part, index, address);
+                self.fetch_part(&manifest, part, sources)
+            })
+            .buffered(self.concurrency);
+
+        let mut body = BytesMut::with_capacity(manifest.size_bytes as usize);
+        while let Some(bytes) = parts.try_next().await? {
+            body.extend_from_slice(&bytes);
+        }
+        if body.len() as u64 != manifest.size_bytes {
+            return Err(RimError::Http(format!(
+                "download size mismatch: path={} expected={} actual={}",
+                manifest.path,
+                manifest.size_bytes,
+                body.len()
+            )));
+        }
+
+        Ok(Some(body.freeze()))
+    }
+
+    /// Tries each source in turn; the error of the last one is returned.
+    async fn fetch_part(
+        &self,
+        manifest: &ReadBlobManifest,
+        part: &ReadBlobManifestPart,
+        sources: Vec<String>,
+    ) -> Result<Bytes> {
+        let mut last_error = None;
+        for source in sources {
+            match self.fetch_part_from(&source, manifest, part).await {
+                Ok(bytes) => return Ok(bytes),
+                Err(error) => {
+                    tracing::debug!(
+                        "part download failed: path={} part_no={} source={} error={}",
+                        manifest.path,
+                        part.part_no,
+                        source,
+                        error
+                    );
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            RimError::PartNotFound(format!(
+                "path={} part_no={} has no source",
+                manifest.path, part.part_no
+            ))
+        }))
+    }
+
+    async fn fetch_part_from(
+        &self,
+        address: &str,
+        manifest: &ReadBlobManifest,
+        part: &ReadBlobManifestPart,
+    ) -> Result<Bytes> {
+        let range = format!(
+            "bytes={}-{}",
+            part.offset,
+            part.offset + part.length.saturating_sub(1)
+        );
+        let request = self
+            .client
+            .get(blob_url(address, &manifest.path))
+            .header(header::RANGE, range)
+            .header(header::IF_RANGE, format!("\"{}\"", manifest.etag));
+        let response = self.send(request).await?;
+
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(RimError::Http(format!(
+                "range read failed: address={} part_no={} status={} (the blob may have changed)",
+                address,
+                part.part_no,
+                response.status()
+            )));
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        if bytes.len() as u64 != part.length {
+            return Err(RimError::Http(format!(
+                "part length mismatch: part_no={} expected={} actual={}",
+                part.part_no,
+                part.length,
+                bytes.len()
+            )));
+        }
+        if let Some(expected) = part.sha256.as_deref() {
+            let actual = compute_hash(&bytes);
+            if actual != expected {
+                return Err(RimError::HashMismatch {
+                    expected: expected.to_string(),
+                    actual,
+                });
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let request = match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+        request
+            .send()
+            .await
+            .map_err(|error| RimError::Http(error.to_string()))
+    }
+}
+
+/// The addresses to try for a part: its holders, rotated by part index so
+/// consecutive parts start on different nodes, then the node that served the
+/// manifest, which can read the part from a peer or the archive.
+fn part_sources(
+    manifest: &ReadBlobManifest,
+    part: &ReadBlobManifestPart,
+    index: usize,
+    entry_address: &str,
+) -> Vec<String> {
+    let mut sources: Vec<String> = part
+        .locality
+        .iter()
+        .filter_map(|node_id| {
+            manifest
+                .nodes
+                .iter()
+                .find(|node| &node.node_id == node_id)
+                .map(|node| node.address.clone())
+        })
+        .collect();
+    if !sources.is_empty() {
+        let shift = index % sources.len();
+        sources.rotate_left(shift);
+    }
+    if !sources.iter().any(|source| source == entry_address) {
+        sources.push(entry_address.to_string());
+    }
+    sources
+}
+
+fn blob_url(address: &str, path: &str) -> String {
+    format!(
+        "http://{}/_/api/v1/blobs/{}",
+        address.trim_start_matches("http://").trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReadBlobManifestNode;
+
+    #[test]
+    fn parts_start_on_different_holders() {
+        let node = |node_id: &str, address: &str| ReadBlobManifestNode {
+            node_id: node_id.to_string(),
+            address: address.to_string(),
+        };
+        let part = |part_no: u32, locality: &[&str]| ReadBlobManifestPart {
+            part_no,
+            offset: 0,
+            length: 1,
+            sha256: None,
+            locality: locality.iter().map(|id| id.to_string()).collect(),
+        };
+        let manifest = ReadBlobManifest {
+            path: "a.bin".to_string(),
+            slot_id: 0,
+            generation: 1,
+            size_bytes: 3,
+            etag: "etag".to_string(),
+            part_size: 1,
+            archive_url: None,
+            nodes: vec![node("n1", "10.0.0.1:8400"), node("n2", "10.0.0.2:8400")],
+            parts: vec![part(0, &["n1", "n2"]), part(1, &["n1", "n2"]), part(2, &[])],
+        };
+
+        let sources =
+            |index: usize| part_sources(&manifest, &manifest.parts[index], index, "10.0.0.2:8400");
+        assert_eq!(sources(0), vec!["10.0.0.1:8400", "10.0.0.2:8400"]);
+        assert_eq!(sources(1), vec!["10.0.0.2:8400", "10.0.0.1:8400"]);
+        assert_eq!(sources(2), vec!["10.0.0.2:8400"]);
+    }
+}
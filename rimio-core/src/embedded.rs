@@ -0,0 +1,313 @@
+//! Storage engine embedded in another process.
+//!
+//! [`Rimio`] runs slots, slot metadata and part files from a local data
+//! directory without the HTTP server, a registry or peers: every slot lives
+//! on this process and writes are not replicated. The on-disk layout is the
+//! one a node uses, so a data directory written here can be served later.
+
+use crate::operations::put_blob::{BlobCommit, commit_blob, stage_blob_parts};
+use crate::{
+    BlobMeta, ContentHeaders, HeadKind, MetadataStore, PART_SIZE, PartIndexState, PartMmapConfig,
+    PartStore, Result, RimError, SharedClock, SlotManager, TOTAL_SLOTS, TombstoneMeta,
+    compute_hash, prepare_data_dir, slot_for_key, system_clock,
+};
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Node id slots are opened under in embedded mode.
+pub const EMBEDDED_NODE_ID: &str = "embedded";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddedConfig {
+    pub data_dir: PathBuf,
+    /// Must stay the same for the lifetime of the data directory.
+    #[serde(default = "default_total_slots")]
+    pub total_slots: u16,
+    #[serde(default)]
+    pub part_mmap: Option<PartMmapConfig>,
+}
+
+fn default_total_slots() -> u16 {
+    TOTAL_SLOTS
+}
+
+impl EmbeddedConfig {
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            data_dir: data_dir.into(),
+            total_slots: default_total_slots(),
+            part_mmap: None,
+        }
+    }
+}
+
+/// An embedded store; see the module docs.
+pub struct Rimio {
+    total_slots: u16,
+    slot_manager: Arc<SlotManager>,
+    part_store: Arc<PartStore>,
+    clock: SharedClock,
+    writes: Mutex<HashMap<u16, Arc<Mutex<()>>>>,
+}
+
+impl Rimio {
+    /// Opens (or creates) the store in `config.data_dir`, reopening every
+    /// slot already on disk.
+    pub async fn open(config: EmbeddedConfig) -> Result<Self> {
+        if config.total_slots == 0 {
+            return Err(RimError::Config("total_slots must be positive".to_string()));
+        }
+        prepare_data_dir(&config.data_dir)?;
+
+        let slot_manager = Arc::new(SlotManager::new(
+            EMBEDDED_NODE_ID.to_string(),
+            config.data_dir.clone(),
+        )?);
+        for slot_id in slot_manager.list_local_slots()? {
+            slot_manager.init_slot(slot_id).await?;
+        }
+        let part_store =
+            Arc::new(PartStore::new(config.data_dir.clone())?.with_mmap(config.part_mmap));
+
+        Ok(Self {
+            total_slots: config.total_slots,
+            slot_manager,
+            part_store,
+            clock: system_clock(),
+            writes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Stamps new versions and tombstones from `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn slot_manager(&self) -> &Arc<SlotManager> {
+        &self.slot_manager
+    }
+
+    pub fn part_store(&self) -> &Arc<PartStore> {
+        &self.part_store
+    }
+
+    /// Stores `body` as a new version of `path`.
+    pub async fn put(&self, path: &str, body: impl Into<Bytes>) -> Result<BlobMeta> {
+        let path = normalize_path(path)?;
+        let body = body.into();
+        let slot_id = slot_for_key(&path, self.total_slots);
+        let _write = self.lock_slot(slot_id).await;
+
+        let store = self.ensure_store(slot_id).await?;
+        let generation = store.next_generation(&path)?;
+        let parts = stage_blob_parts(&self.part_store, slot_id, &path, generation, &body).await?;
+
+        let meta = BlobMeta {
+            path: path.clone(),
+            slot_id,
+            generation,
+            version: generation,
+            size_bytes: body.len() as u64,
+            etag: compute_hash(&body),
+            part_size: PART_SIZE as u64,
+            part_count: body.len().div_ceil(PART_SIZE) as u32,
+            part_index_state: PartIndexState::Complete,
+            archive_url: None,
+            updated_at: self.clock.now(),
+            metadata: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            content: ContentHeaders::default(),
+        };
+        let meta_bytes = serde_json::to_vec(&meta)?;
+        let meta_sha = compute_hash(&meta_bytes);
+        let parts = parts.iter().map(|part| &part.local);
+        if !matches!(
+            commit_blob(&store, parts, &meta, &meta_bytes, &meta_sha, None)?,
+            BlobCommit::Applied
+        ) {
+            return Err(newer_version(&path));
+        }
+
+        Ok(meta)
+    }
+
+    /// The current version of `path`, or `None` when it is missing or
+    /// deleted.
+    pub async fn head(&self, path: &str) -> Result<Option<BlobMeta>> {
+        let path = normalize_path(path)?;
+        let Some(store) = self.store_for(&path).await? else {
+            return Ok(None);
+        };
+        Ok(store
+            .get_current_head(&path)?
+            .filter(|head| head.head_kind == HeadKind::Meta)
+            .and_then(|head| head.meta))
+    }
+
+    /// Reads the current version of `path` into memory.
+    pub async fn get(&self, path: &str) -> Result<Option<Bytes>> {
+        let Some(meta) = self.head(path).await? else {
+            return Ok(None);
+        };
+        let store = self.ensure_store(meta.slot_id).await?;
+
+        let mut parts = store.list_part_entries(&meta.path, meta.generation)?;
+        parts.sort_by_key(|part| part.part_no);
+        if parts.len() != meta.part_count as usize {
+            return Err(RimError::PartNotFound(format!(
+                "path={} generation={} parts={}/{}",
+                meta.path,
+                meta.generation,
+                parts.len(),
+                meta.part_count
+            )));
+        }
+
+        let mut body = BytesMut::with_capacity(meta.size_bytes as usize);
+        for part in parts {
+            let bytes = match part.external_path.as_deref() {
+                Some(external_path) => Bytes::from(tokio::fs::read(external_path).await?),
+                None => {
+                    self.part_store
+                        .get_part(
+                            meta.slot_id,
+                            &meta.path,
+                            meta.generation,
+                            part.part_no,
+                            &part.sha256,
+                        )
+                        .await?
+                }
+            };
+            body.extend_from_slice(&bytes);
+        }
+
+        Ok(Some(body.freeze()))
+    }
+
+    /// Deletes `path`; returns false when there was nothing to delete.
+    pub async fn delete(&self, path: &str) -> Result<bool> {
+        let path = normalize_path(path)?;
+        let slot_id = slot_for_key(&path, self.total_slots);
+        let _write = self.lock_slot(slot_id).await;
+        if self.head(&path).await?.is_none() {
+            return Ok(false);
+        }
+
+        let store = self.ensure_store(slot_id).await?;
+        let tombstone = TombstoneMeta {
+            path: path.clone(),
+            slot_id,
+            generation: store.next_generation(&path)?,
+            deleted_at: self.clock.now(),
+            reason: "embedded-delete".to_string(),
+        };
+        let tombstone_bytes = serde_json::to_vec(&tombstone)?;
+        let tombstone_sha = compute_hash(&tombstone_bytes);
+        if !store.insert_tombstone_with_payload(&tombstone, &tombstone_bytes, &tombstone_sha)? {
+            return Err(newer_version(&path));
+        }
+
+        Ok(true)
+    }
+
+    /// Up to `limit` live blobs under `prefix`, ordered by path.
+    pub async fn list(&self, prefix: &str, limit: usize) -> Result<Vec<BlobMeta>> {
+        let prefix = prefix.trim_start_matches('/');
+        let mut blobs = Vec::new();
+        for slot_id in self.slot_manager.get_assigned_slots().await {
+            let store = self.ensure_store(slot_id).await?;
+            blobs.extend(
+                store
+                    .list_heads(prefix, limit, false, None)?
+                    .into_iter()
+                    .filter_map(|head| head.meta),
+            );
+        }
+
+        blobs.sort_by(|a, b| a.path.cmp(&b.path));
+        blobs.truncate(limit);
+        Ok(blobs)
+    }
+
+    async fn store_for(&self, path: &str) -> Result<Option<MetadataStore>> {
+        let slot_id = slot_for_key(path, self.total_slots);
+        if !self.slot_manager.has_slot(slot_id).await {
+            return Ok(None);
+        }
+        self.ensure_store(slot_id).await.map(Some)
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+
+    /// Writes to one slot go one at a time, so generations never race.
+    async fn lock_slot(&self, slot_id: u16) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self.writes.lock().await.entry(slot_id).or_default().clone();
+        lock.lock_owned().await
+    }
+}
+
+fn normalize_path(path: &str) -> Result<String> {
+    let path = path.trim_start_matches('/');
+    if path.is_empty() {
+        return Err(RimError::InvalidRequest(
+            "blob path is required".to_string(),
+        ));
+    }
+    Ok(path.to_string())
+}
+
+fn newer_version(path: &str) -> RimError {
+    RimError::InvalidRequest(format!("a newer version of {} exists", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn blobs_survive_reopening_the_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = EmbeddedConfig {
+            total_slots: 8,
+            ..EmbeddedConfig::new(dir.path())
+        };
+
+        let store = Rimio::open(config.clone()).await.unwrap();
+        let first = store.put("/logs/a.txt", "one").await.unwrap();
+        let second = store.put("logs/a.txt", "two").await.unwrap();
+        assert!(second.generation > first.generation);
+        store.put("logs/b.txt", Bytes::new()).await.unwrap();
+        store.put("other/c.txt", "three").await.unwrap();
+        assert!(store.delete("logs/b.txt").await.unwrap());
+        assert!(!store.delete("logs/missing.txt").await.unwrap());
+        drop(store);
+
+        let store = Rimio::open(config).await.unwrap();
+        assert_eq!(
+            store.get("logs/a.txt").await.unwrap(),
+            Some(Bytes::from("two"))
+        );
+        assert_eq!(store.get("logs/b.txt").await.unwrap(), None);
+        let listed: Vec<String> = store
+            .list("logs/", 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|meta| meta.path)
+            .collect();
+        assert_eq!(listed, vec!["logs/a.txt".to_string()]);
+    }
+}
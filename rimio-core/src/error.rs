@@ -19,12 +19,19 @@ pub enum RimError {
     #[error("Slot not found: {0}")]
     SlotNotFound(u16),
 
+    /// The slot is frozen for maintenance and takes no writes.
+    #[error("Slot {0} is frozen for maintenance")]
+    SlotFrozen(u16),
+
     #[error("Part not found: {0}")]
     PartNotFound(String),
 
     #[error("Blob not found: {0}")]
     BlobNotFound(String),
 
+    #[error("Upload not found: {0}")]
+    UploadNotFound(String),
+
     #[error("Insufficient replicas: need {required}, found {found}")]
     InsufficientReplicas { required: usize, found: usize },
 
@@ -40,10 +47,50 @@ pub enum RimError {
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
 
+    /// A requested byte range selects nothing from a blob of `size_bytes`.
+    #[error("Range not satisfiable for a blob of {size_bytes} bytes")]
+    RangeNotSatisfiable { size_bytes: u64 },
+
+    /// The archive store kept failing transiently. Callers can wait
+    /// `retry_after` and try again, or fall back to peers.
+    #[error("Archive unavailable: {message}")]
+    ArchiveUnavailable {
+        message: String,
+        retry_after: std::time::Duration,
+    },
+
+    /// A head that breaks an invariant of stored metadata; it is refused
+    /// before it reaches the slot database.
+    #[error("Invalid metadata for {path}: {violation}")]
+    InvalidMetadata {
+        path: String,
+        violation: MetadataViolation,
+    },
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
 
+/// How a head written to a slot contradicts itself.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MetadataViolation {
+    #[error("generation {0} is negative")]
+    NegativeGeneration(i64),
+
+    #[error("part size is zero for {part_count} parts")]
+    ZeroPartSize { part_count: u32 },
+
+    /// Every part but the last is `part_size` bytes and none is empty, so a
+    /// blob has no parts only when it is empty, and its size goes past the
+    /// parts before the last.
+    #[error("{part_count} parts of {part_size} bytes cannot hold {size_bytes} bytes")]
+    PartCount {
+        size_bytes: u64,
+        part_size: u64,
+        part_count: u32,
+    },
+}
+
 impl From<etcd_client::Error> for RimError {
     fn from(err: etcd_client::Error) -> Self {
         RimError::Etcd(err.to_string())
@@ -1,22 +1,38 @@
 //! Rimio Core - Core library for lightweight object storage for edge cloud nodes
 
 pub mod archive;
+pub mod clock;
 pub mod cluster;
+pub mod download;
+pub mod embedded;
 pub mod error;
+pub mod multipart;
 pub mod node;
 pub mod operations;
 pub mod registry;
 pub mod slot_manager;
 pub mod storage;
+pub mod transaction;
 
-pub use archive::{ArchiveLifecycleConfig, ArchiveLifecycleManager};
+pub use archive::{
+    ArchiveLifecycleConfig, ArchiveLifecycleManager, ArchiveTieringConfig, ArchiveUploadReport,
+    ArchiveUploader,
+};
+pub use clock::{Clock, ManualClock, SharedClock, SystemClock, system_clock};
 pub use cluster::*;
-pub use error::{Result, RimError};
+pub use download::{DEFAULT_DOWNLOAD_CONCURRENCY, ParallelDownloader};
+pub use embedded::{EMBEDDED_NODE_ID, EmbeddedConfig, Rimio};
+pub use error::{MetadataViolation, Result, RimError};
+pub use multipart::{
+    CompleteUploadRequest, CompletedPart, MAX_UPLOAD_PART_NUMBER, MULTIPART_UPLOAD_TTL_SECS,
+    MultipartUpload, MultipartUploads,
+};
 pub use node::{Node, NodeInfo, NodeStatus};
 pub use operations::*;
 pub use registry::etcd::EtcdRegistry;
 pub use registry::redis::RedisRegistry;
 pub use registry::{DynRegistry, Registry, RegistryBuilder, SlotEvent};
+pub use rimio_chunk::{BlobManifest, Chunker, PartDigest};
 pub use rimio_meta::{
     MetaAddLearnerRequest, MetaAddLearnerResult, MetaAppendEntriesRequest, MetaAppendEntriesResult,
     MetaChangeMembershipResult, MetaClientWriteResult, MetaInstallSnapshotRequest,
@@ -26,11 +42,30 @@ pub use rimio_meta::{
     handle_global_promote_voter, handle_global_vote,
 };
 pub use slot_manager::{
-    PART_SIZE, ReplicaStatus, Slot, SlotHealth, SlotInfo, SlotManager, TOTAL_SLOTS, slot_for_key,
+    PART_SIZE, ReplicaStatus, Slot, SlotHealth, SlotInfo, SlotLease, SlotManager, TOTAL_SLOTS,
+    slot_for_key, slot_for_tagged_key,
 };
 pub use storage::{
-    ArchiveListPage, ArchiveStore, BlobHead, BlobMeta, HeadKind, MetadataStore, PartEntry,
-    PartIndexState, PartStore, PutPartResult, RedisArchiveStore, S3ArchiveStore, TombstoneMeta,
-    compute_hash, parse_redis_archive_url, parse_s3_archive_url, read_archive_range_bytes,
-    set_default_s3_archive_store, verify_hash,
+    ArchiveListPage, ArchiveRetryPolicy, ArchiveStore, AzureArchiveStore, BlobHead, BlobMeta,
+    ChangeKind, ContentHeaders, DiskScrubReport, GcsArchiveStore, HeadChainReport, HeadKind,
+    HeadSchemaMigration, HeadSchemaMigrationConfig, HeadSchemaPhase, HeadShadowReport, HeadWrite,
+    HintRecord, JobRecord, LAYOUT_VERSION, LayoutStamp, LocalFsArchiveStore, MetadataStore,
+    MetadataTransaction, MountedSnapshot, NodeStore, PartEntry, PartIndexState, PartMmapAdvice,
+    PartMmapConfig, PartStore, PartWriter, PrunedPart, PrunedVersions, PutPartResult,
+    RedisArchiveStore, RepairAttempt, RepairDeadLetter, RepairRecord, RepairStats, S3ArchiveStore,
+    SLOT_BACKUP_RETENTION, SLOT_TRANSFER_DB_FILE, SNAPSHOT_FORMAT_VERSION, ScrubConfig,
+    ScrubScheduler, SlotChange, SlotStats, SlotTransferFile, SlotTransferManifest,
+    SlotTransferStaging, SnapshotBlob, SnapshotManifest, SnapshotPart, SnapshotWriter,
+    SqliteCheckpointConfig, SqliteIntegrityConfig, SqliteMaintenance, SqliteMaintenanceConfig,
+    SqliteStats, TombstoneMeta, TransactionRecord, UploadPartRecord, UploadSession,
+    VersionRetention, archive_store_for_scheme, compute_hash, migrate_legacy_part_dirs,
+    normalize_blob_path, parse_azure_archive_url, parse_gcs_archive_url,
+    parse_local_fs_archive_url, parse_redis_archive_url, parse_s3_archive_url, prepare_data_dir,
+    read_archive_range_bytes, set_default_azure_archive_store, set_default_gcs_archive_store,
+    set_default_local_fs_archive_store, set_default_s3_archive_store, shred_part_file, verify_hash,
+};
+pub use transaction::{
+    IN_DOUBT_AFTER_SECS, InDoubtOutcome, InDoubtResolution, ParticipantVote, StagedEntry,
+    StagedTransaction, TransactionManager, TransactionPeers, TransactionState, TwoPhaseCommit,
+    TwoPhaseCommitRequest, TwoPhaseOutcome, TwoPhaseParticipant, TwoPhaseSlotResult, Vote,
 };
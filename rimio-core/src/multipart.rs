@@ -0,0 +1,395 @@
+//! Resumable multipart uploads.
+//!
+//! A client starts an upload, sends numbered parts in any order (resending a
+//! part replaces it) and completes it, which moves the parts into a new
+//! generation and commits the head in one metadata write. Sessions and
+//! received parts live in the node store and under `uploads/` on the first
+//! disk, so an upload survives restarts but must be driven through the node
+//! that started it.
+
+use crate::operations::put_blob::{StreamedBlob, StreamedCommit, StreamedPart};
+use crate::{
+    ContentHeaders, MetadataStore, NodeInfo, NodeStore, PART_SIZE, PartStore, PutBlobOperation,
+    PutBlobOperationOutcome, Result, RimError, SharedClock, SlotManager, UploadPartRecord,
+    UploadSession, WriteConsistency, compute_hash, system_clock,
+};
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Uploads left incomplete for this long are dropped with their parts.
+pub const MULTIPART_UPLOAD_TTL_SECS: i64 = 7 * 24 * 3600;
+/// Highest part number an upload accepts.
+pub const MAX_UPLOAD_PART_NUMBER: u32 = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UploadSessionPayload {
+    part_size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MultipartUpload {
+    pub upload_id: String,
+    pub path: String,
+    pub slot_id: u16,
+    /// Every part but the last must be exactly this long.
+    pub part_size: u64,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl MultipartUpload {
+    fn from_session(session: UploadSession) -> Result<Self> {
+        let payload: UploadSessionPayload = serde_json::from_value(session.payload)?;
+        Ok(Self {
+            upload_id: session.upload_id,
+            path: session.blob_path,
+            slot_id: session.slot_id,
+            part_size: payload.part_size,
+            created_at: session.created_at,
+            expires_at: session.expires_at,
+        })
+    }
+}
+
+/// A part the client expects the completed blob to contain.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletedPart {
+    pub part_number: u32,
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompleteUploadRequest {
+    pub upload_id: String,
+    pub path: String,
+    pub replicas: Vec<NodeInfo>,
+    pub local_node_id: String,
+    pub consistency: WriteConsistency,
+    /// When given, exactly these parts must have been received, with the
+    /// listed checksums.
+    pub parts: Option<Vec<CompletedPart>>,
+}
+
+pub struct MultipartUploads {
+    slot_manager: Arc<SlotManager>,
+    part_store: Arc<PartStore>,
+    node_store: Arc<NodeStore>,
+    put_blob_operation: Arc<PutBlobOperation>,
+    clock: SharedClock,
+}
+
+impl MultipartUploads {
+    pub fn new(
+        slot_manager: Arc<SlotManager>,
+        part_store: Arc<PartStore>,
+        node_store: Arc<NodeStore>,
+        put_blob_operation: Arc<PutBlobOperation>,
+    ) -> Self {
+        Self {
+            slot_manager,
+            part_store,
+            node_store,
+            put_blob_operation,
+            clock: system_clock(),
+        }
+    }
+
+    /// Dates sessions and parts by `clock`; expiry itself is judged by the
+    /// node store's clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn initiate(
+        &self,
+        slot_id: u16,
+        path: &str,
+        part_size: Option<u64>,
+    ) -> Result<MultipartUpload> {
+        let part_size = part_size.unwrap_or(PART_SIZE as u64);
+        if part_size == 0 || part_size > PART_SIZE as u64 {
+            return Err(RimError::InvalidRequest(format!(
+                "part size must be between 1 and {} bytes",
+                PART_SIZE
+            )));
+        }
+
+        let now = self.clock.now();
+        let session = UploadSession {
+            upload_id: format!("upl-{}", ulid::Ulid::new()),
+            slot_id,
+            blob_path: path.to_string(),
+            payload: serde_json::to_value(UploadSessionPayload { part_size })?,
+            created_at: now,
+            expires_at: now + Duration::seconds(MULTIPART_UPLOAD_TTL_SECS),
+        };
+        self.node_store.save_upload_session(&session)?;
+        MultipartUpload::from_session(session)
+    }
+
+    pub fn get(&self, upload_id: &str, path: &str) -> Result<MultipartUpload> {
+        let session = self
+            .node_store
+            .get_upload_session(upload_id)?
+            .filter(|session| session.blob_path == path)
+            .ok_or_else(|| RimError::UploadNotFound(upload_id.to_string()))?;
+        MultipartUpload::from_session(session)
+    }
+
+    pub fn list_parts(&self, upload_id: &str, path: &str) -> Result<Vec<UploadPartRecord>> {
+        self.get(upload_id, path)?;
+        self.node_store.list_upload_parts(upload_id)
+    }
+
+    /// Writes one part to disk as it arrives. Parts longer than the upload's
+    /// part size are refused before they are stored.
+    pub async fn upload_part<S>(
+        &self,
+        upload_id: &str,
+        path: &str,
+        part_number: u32,
+        body: S,
+    ) -> Result<UploadPartRecord>
+    where
+        S: Stream<Item = Result<Bytes>> + Send,
+    {
+        if part_number == 0 || part_number > MAX_UPLOAD_PART_NUMBER {
+            return Err(RimError::InvalidRequest(format!(
+                "part number must be between 1 and {}",
+                MAX_UPLOAD_PART_NUMBER
+            )));
+        }
+        let upload = self.get(upload_id, path)?;
+
+        let mut body = std::pin::pin!(body);
+        let mut writer = self
+            .part_store
+            .begin_upload_part(upload_id, part_number - 1)
+            .await?;
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    writer.abort().await;
+                    return Err(error);
+                }
+            };
+            if writer.len() + chunk.len() as u64 > upload.part_size {
+                writer.abort().await;
+                return Err(RimError::InvalidRequest(format!(
+                    "part {} exceeds the upload part size of {} bytes",
+                    part_number, upload.part_size
+                )));
+            }
+            writer.write(&chunk).await?;
+        }
+
+        let size_bytes = writer.len();
+        let (sha256, _) = writer.finish().await?;
+        self.part_store
+            .drop_stale_upload_parts(upload_id, part_number - 1, &sha256)
+            .await?;
+
+        let record = UploadPartRecord {
+            part_number,
+            sha256,
+            size_bytes,
+            uploaded_at: self.clock.now(),
+        };
+        self.node_store.put_upload_part(upload_id, &record)?;
+        Ok(record)
+    }
+
+    /// Stitches the received parts into a new generation of the blob.
+    pub async fn complete(
+        &self,
+        request: CompleteUploadRequest,
+    ) -> Result<PutBlobOperationOutcome> {
+        let upload = self.get(&request.upload_id, &request.path)?;
+        let parts = self.node_store.list_upload_parts(&request.upload_id)?;
+        validate_parts(&upload, &parts, request.parts.as_deref())?;
+
+        let slot = self.slot_manager.get_slot(upload.slot_id).await?;
+        let store = MetadataStore::new(slot)?;
+        let generation = store.next_generation(&upload.path)?;
+
+        let mut streamed = Vec::with_capacity(parts.len());
+        for part in &parts {
+            let part_no = part.part_number - 1;
+            let stored = self
+                .part_store
+                .adopt_upload_part(
+                    &upload.upload_id,
+                    upload.slot_id,
+                    &upload.path,
+                    generation,
+                    part_no,
+                    &part.sha256,
+                )
+                .await?;
+            streamed.push(StreamedPart {
+                part_no,
+                sha256: part.sha256.clone(),
+                length: part.size_bytes,
+                external_path: stored.part_path.to_string_lossy().to_string(),
+            });
+        }
+
+        let digests: String = parts.iter().map(|part| part.sha256.as_str()).collect();
+        let staged = StreamedBlob {
+            etag: format!("{}-{}", compute_hash(digests.as_bytes()), parts.len()),
+            size_bytes: parts.iter().map(|part| part.size_bytes).sum(),
+            part_size: upload.part_size,
+            parts: streamed,
+        };
+
+        let outcome = self
+            .put_blob_operation
+            .commit_local_parts(
+                &store,
+                StreamedCommit {
+                    path: upload.path.clone(),
+                    slot_id: upload.slot_id,
+                    generation,
+                    replicas: request.replicas,
+                    local_node_id: request.local_node_id,
+                    consistency: request.consistency,
+                    expected_generation: None,
+                    metadata: BTreeMap::new(),
+                    tags: BTreeMap::new(),
+                    content: ContentHeaders::default(),
+                },
+                staged,
+            )
+            .await?;
+
+        if matches!(outcome, PutBlobOperationOutcome::Committed(_)) {
+            self.node_store.delete_upload_session(&upload.upload_id)?;
+            self.part_store.remove_upload(&upload.upload_id).await?;
+        }
+        Ok(outcome)
+    }
+
+    pub async fn abort(&self, upload_id: &str, path: &str) -> Result<()> {
+        self.get(upload_id, path)?;
+        self.node_store.delete_upload_session(upload_id)?;
+        self.part_store.remove_upload(upload_id).await
+    }
+
+    /// Removes parts on disk whose upload expired or was finished; returns
+    /// how many uploads were cleaned up.
+    pub async fn purge_orphans(&self) -> Result<usize> {
+        let mut purged = 0;
+        for upload_id in self.part_store.upload_ids().await? {
+            if self.node_store.get_upload_session(&upload_id)?.is_none() {
+                self.part_store.remove_upload(&upload_id).await?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+}
+
+/// Parts must run from 1 without gaps, and every part but the last must fill
+/// the upload's part size, so byte offsets map onto part numbers like for
+/// any other blob.
+fn validate_parts(
+    upload: &MultipartUpload,
+    parts: &[UploadPartRecord],
+    expected: Option<&[CompletedPart]>,
+) -> Result<()> {
+    if parts.is_empty() {
+        return Err(RimError::InvalidRequest("upload has no parts".to_string()));
+    }
+
+    for (index, part) in parts.iter().enumerate() {
+        if part.part_number != index as u32 + 1 {
+            return Err(RimError::InvalidRequest(format!(
+                "part {} is missing",
+                index + 1
+            )));
+        }
+        let last = index + 1 == parts.len();
+        if part.size_bytes == 0 || (!last && part.size_bytes != upload.part_size) {
+            return Err(RimError::InvalidRequest(format!(
+                "part {} has {} bytes; all parts but the last must have {}",
+                part.part_number, part.size_bytes, upload.part_size
+            )));
+        }
+    }
+
+    if let Some(expected) = expected {
+        if expected.len() != parts.len() {
+            return Err(RimError::InvalidRequest(format!(
+                "upload has {} parts, completion lists {}",
+                parts.len(),
+                expected.len()
+            )));
+        }
+        for (part, listed) in parts.iter().zip(expected) {
+            if listed.part_number != part.part_number
+                || listed
+                    .sha256
+                    .as_deref()
+                    .is_some_and(|sha256| sha256 != part.sha256)
+            {
+                return Err(RimError::InvalidRequest(format!(
+                    "part {} does not match the uploaded part",
+                    listed.part_number
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(part_number: u32, size_bytes: u64) -> UploadPartRecord {
+        UploadPartRecord {
+            part_number,
+            sha256: format!("sha-{}", part_number),
+            size_bytes,
+            uploaded_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn completion_requires_contiguous_full_parts() {
+        let upload = MultipartUpload {
+            upload_id: "upl-1".to_string(),
+            path: "fw.bin".to_string(),
+            slot_id: 1,
+            part_size: 4,
+            created_at: Utc::now(),
+            expires_at: Utc::now(),
+        };
+
+        assert!(validate_parts(&upload, &[part(1, 4), part(2, 4), part(3, 1)], None).is_ok());
+        assert!(validate_parts(&upload, &[], None).is_err());
+        assert!(validate_parts(&upload, &[part(1, 4), part(3, 1)], None).is_err());
+        assert!(validate_parts(&upload, &[part(1, 3), part(2, 1)], None).is_err());
+
+        let listed = vec![
+            CompletedPart {
+                part_number: 1,
+                sha256: Some("sha-1".to_string()),
+            },
+            CompletedPart {
+                part_number: 2,
+                sha256: None,
+            },
+        ];
+        assert!(validate_parts(&upload, &[part(1, 4), part(2, 2)], Some(&listed)).is_ok());
+        assert!(validate_parts(&upload, &[part(1, 4)], Some(&listed)).is_err());
+    }
+}
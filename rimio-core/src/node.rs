@@ -11,6 +11,9 @@ pub struct NodeInfo {
     pub address: String,
     pub status: NodeStatus,
     pub slots: Vec<u16>,
+    /// Address of the node's internal gRPC listener, if it runs one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grpc_address: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -40,6 +43,7 @@ impl Node {
             address: bind_addr,
             status: NodeStatus::Healthy,
             slots: Vec::new(),
+            grpc_address: None,
         };
 
         Ok(Self {
@@ -71,6 +75,12 @@ impl Node {
         info.status = status;
     }
 
+    /// Advertises the internal gRPC listener to peers.
+    pub async fn set_grpc_address(&self, grpc_address: Option<String>) {
+        let mut info = self.info.write().await;
+        info.grpc_address = grpc_address;
+    }
+
     pub async fn assign_slots(&self, slots: Vec<u16>) {
         let mut info = self.info.write().await;
         info.slots = slots;
@@ -0,0 +1,598 @@
+//! Lifecycle rules: per-prefix expiration and transition to the archive.
+//!
+//! Rules are kept in the registry and apply to the whole group. Each node
+//! walks the slots it holds. On the slot's primary, live blobs older than a
+//! rule's `expire_after_days` are tombstoned through [`DeleteBlobOperation`],
+//! so the tombstone replicates like an API delete. The primary expires only
+//! while it holds the slot's write lease, and each tombstone is conditioned
+//! on the generation the walk listed, so a blob rewritten meanwhile stays.
+//! On every holder, blobs
+//! older than `transition_after_days` lose the local files of the parts the
+//! archive already has, and reads fetch those parts back from the archive.
+//! Parts that neither archive sync nor tiering has copied yet are left for a
+//! later pass. Ages count from the `updated_at` of the live version. Slots
+//! frozen for maintenance are left alone until they are thawed.
+
+use crate::{
+    BlobMeta, DeleteBlobOperation, DeleteBlobOperationOutcome, DeleteBlobOperationRequest,
+    HeadKind, MetadataStore, NodeInfo, PartStore, Result, RimError, SharedClock, SlotLeaseManager,
+    SlotManager, system_clock,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::OwnedMutexGuard;
+
+/// Heads read per step of a slot walk.
+const LIFECYCLE_PAGE_SIZE: usize = 1000;
+
+/// One lifecycle rule, applied to every blob whose path starts with
+/// `prefix`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LifecycleRule {
+    pub id: String,
+    #[serde(default)]
+    pub prefix: String,
+    /// Tombstone blobs this many days after they were written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_after_days: Option<u32>,
+    /// Keep only the archived copy of blobs this many days after they were
+    /// written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition_after_days: Option<u32>,
+}
+
+impl LifecycleRule {
+    fn matches(&self, path: &str) -> bool {
+        path.starts_with(&self.prefix)
+    }
+}
+
+/// Checks that rule ids are unique and that each rule has an action of at
+/// least one day.
+pub fn validate_lifecycle_rules(rules: &[LifecycleRule]) -> Result<()> {
+    let mut ids = HashSet::new();
+    for rule in rules {
+        if rule.id.trim().is_empty() {
+            return Err(RimError::InvalidRequest(
+                "lifecycle rule id must not be empty".to_string(),
+            ));
+        }
+        if !ids.insert(rule.id.as_str()) {
+            return Err(RimError::InvalidRequest(format!(
+                "duplicate lifecycle rule id: {}",
+                rule.id
+            )));
+        }
+        if rule.expire_after_days.is_none() && rule.transition_after_days.is_none() {
+            return Err(RimError::InvalidRequest(format!(
+                "lifecycle rule {} has no action",
+                rule.id
+            )));
+        }
+        if rule.expire_after_days == Some(0) || rule.transition_after_days == Some(0) {
+            return Err(RimError::InvalidRequest(format!(
+                "lifecycle rule {} must wait at least one day",
+                rule.id
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LifecycleAction {
+    Expire,
+    Transition,
+}
+
+/// What the rules ask of a blob written at `written`, expiration winning
+/// over transition.
+pub(crate) fn due_action(
+    rules: &[LifecycleRule],
+    path: &str,
+    written: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Option<LifecycleAction> {
+    let age_days = now.signed_duration_since(written).num_days();
+    let mut action = None;
+    for rule in rules.iter().filter(|rule| rule.matches(path)) {
+        if rule
+            .expire_after_days
+            .is_some_and(|days| age_days >= i64::from(days))
+        {
+            return Some(LifecycleAction::Expire);
+        }
+        if rule
+            .transition_after_days
+            .is_some_and(|days| age_days >= i64::from(days))
+        {
+            action = Some(LifecycleAction::Transition);
+        }
+    }
+    action
+}
+
+/// The longest prefix every rule shares, which bounds the paths to walk.
+pub(crate) fn common_prefix(rules: &[LifecycleRule]) -> String {
+    let Some(first) = rules.first() else {
+        return String::new();
+    };
+    let mut prefix = first.prefix.as_str();
+    for rule in &rules[1..] {
+        let shared = prefix
+            .char_indices()
+            .zip(rule.prefix.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(rule.prefix.len()), |((index, _), _)| index);
+        prefix = &prefix[..shared];
+    }
+    prefix.to_string()
+}
+
+#[derive(Clone)]
+pub struct ApplyLifecycleOperation {
+    slot_manager: Arc<SlotManager>,
+    part_store: Arc<PartStore>,
+    delete_blob_operation: Arc<DeleteBlobOperation>,
+    slot_leases: Option<Arc<SlotLeaseManager>>,
+    clock: SharedClock,
+}
+
+/// Whether this node may expire blobs in a slot for one page of its walk.
+enum ExpiryClaim {
+    /// It holds the write lease; the guard, if any, keeps local writes out.
+    Local(Option<OwnedMutexGuard<()>>),
+    /// Another node holds the write lease.
+    Remote(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ApplyLifecycleOperationRequest {
+    pub slot_id: u16,
+    pub rules: Vec<LifecycleRule>,
+    pub replicas: Vec<NodeInfo>,
+    /// Whether this node is the slot's primary, which alone expires blobs.
+    pub primary: bool,
+    pub local_node_id: String,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplyLifecycleOperationResult {
+    pub slot_id: u16,
+    pub dry_run: bool,
+    pub scanned: u64,
+    pub expired: u64,
+    /// Blobs whose local parts were dropped in favour of the archive.
+    pub transitioned: u64,
+    /// Blobs due for transition with parts the archive does not hold yet.
+    pub awaiting_archive: u64,
+    pub freed_bytes: u64,
+    pub failed: u64,
+}
+
+impl ApplyLifecycleOperation {
+    pub fn new(
+        slot_manager: Arc<SlotManager>,
+        part_store: Arc<PartStore>,
+        delete_blob_operation: Arc<DeleteBlobOperation>,
+    ) -> Self {
+        Self {
+            slot_manager,
+            part_store,
+            delete_blob_operation,
+            slot_leases: None,
+            clock: system_clock(),
+        }
+    }
+
+    /// Expires only while holding the slot's write lease.
+    pub fn with_slot_leases(mut self, slot_leases: Option<Arc<SlotLeaseManager>>) -> Self {
+        self.slot_leases = slot_leases;
+        self
+    }
+
+    /// Ages blobs against `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Applies the rules to the live blobs of one local slot.
+    pub async fn run(
+        &self,
+        request: ApplyLifecycleOperationRequest,
+    ) -> Result<ApplyLifecycleOperationResult> {
+        let ApplyLifecycleOperationRequest {
+            slot_id,
+            rules,
+            replicas,
+            primary,
+            local_node_id,
+            dry_run,
+        } = request;
+
+        let mut result = ApplyLifecycleOperationResult {
+            slot_id,
+            dry_run,
+            ..ApplyLifecycleOperationResult::default()
+        };
+        if rules.is_empty() {
+            return Ok(result);
+        }
+        if self.delete_blob_operation.slot_frozen(slot_id).await {
+            tracing::debug!("lifecycle skipped frozen slot {}", slot_id);
+            return Ok(result);
+        }
+
+        let store = self.ensure_store(slot_id).await?;
+        let prefix = common_prefix(&rules);
+        let now = self.clock.now();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let heads = store.list_heads(&prefix, LIFECYCLE_PAGE_SIZE, false, cursor.as_deref())?;
+            let full = heads.len() >= LIFECYCLE_PAGE_SIZE;
+            cursor = heads.last().map(|head| head.path.clone());
+            let (may_expire, _write_lock) = if primary && !dry_run {
+                match self.claim_expiry(slot_id, &local_node_id).await? {
+                    ExpiryClaim::Local(write_lock) => (true, write_lock),
+                    ExpiryClaim::Remote(holder) => {
+                        tracing::debug!(
+                            "lifecycle left expirations to write lease holder: slot={} holder={}",
+                            slot_id,
+                            holder
+                        );
+                        (false, None)
+                    }
+                }
+            } else {
+                (false, None)
+            };
+
+            for head in heads {
+                let written = head.updated_at;
+                let Some(meta) = head.meta.filter(|_| head.head_kind == HeadKind::Meta) else {
+                    continue;
+                };
+                result.scanned += 1;
+                match due_action(&rules, &meta.path, written, now) {
+                    Some(LifecycleAction::Expire) if primary => {
+                        if dry_run {
+                            result.expired += 1;
+                            continue;
+                        }
+                        if !may_expire {
+                            continue;
+                        }
+                        match self.expire(&meta, &replicas, &local_node_id).await {
+                            Ok(true) => result.expired += 1,
+                            Ok(false) => tracing::debug!(
+                                "lifecycle left a rewritten blob: slot={} path={}",
+                                slot_id,
+                                meta.path
+                            ),
+                            Err(error) => {
+                                result.failed += 1;
+                                tracing::warn!(
+                                    "lifecycle expiration failed: slot={} path={} error={}",
+                                    slot_id,
+                                    meta.path,
+                                    error
+                                );
+                            }
+                        }
+                    }
+                    Some(LifecycleAction::Expire) | None => {}
+                    Some(LifecycleAction::Transition) => {
+                        match evict_archived_parts(&self.part_store, &store, &meta, dry_run).await {
+                            Ok(evicted) => {
+                                if evicted.parts > 0 {
+                                    result.transitioned += 1;
+                                    result.freed_bytes += evicted.freed_bytes;
+                                }
+                                if evicted.unarchived > 0 {
+                                    result.awaiting_archive += 1;
+                                }
+                            }
+                            Err(error) => {
+                                result.failed += 1;
+                                tracing::warn!(
+                                    "lifecycle transition failed: slot={} path={} error={}",
+                                    slot_id,
+                                    meta.path,
+                                    error
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !full {
+                break;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Claims the slot's write lease the way [`crate::DeletePrefixOperation`]
+    /// does, so expirations never race a write committed through another
+    /// node. Without leases the primary expires on its own.
+    async fn claim_expiry(&self, slot_id: u16, local_node_id: &str) -> Result<ExpiryClaim> {
+        let Some(leases) = self.slot_leases.as_ref() else {
+            return Ok(ExpiryClaim::Local(None));
+        };
+
+        let lease = leases.acquire(slot_id).await?;
+        if lease.holder == local_node_id {
+            return Ok(ExpiryClaim::Local(Some(leases.lock_slot(slot_id).await)));
+        }
+        Ok(ExpiryClaim::Remote(lease.holder))
+    }
+
+    /// Tombstones `meta`'s blob unless its head has moved past the listed
+    /// generation, which returns `false`.
+    async fn expire(
+        &self,
+        meta: &BlobMeta,
+        replicas: &[NodeInfo],
+        local_node_id: &str,
+    ) -> Result<bool> {
+        let outcome = self
+            .delete_blob_operation
+            .run(DeleteBlobOperationRequest {
+                path: meta.path.clone(),
+                slot_id: meta.slot_id,
+                write_id: format!("lifecycle-{}", ulid::Ulid::new()),
+                replicas: replicas.to_vec(),
+                local_node_id: local_node_id.to_string(),
+                expected_generation: Some(meta.generation),
+            })
+            .await?;
+
+        match outcome {
+            DeleteBlobOperationOutcome::Committed(_) => Ok(true),
+            DeleteBlobOperationOutcome::PreconditionFailed { .. } => Ok(false),
+            DeleteBlobOperationOutcome::Conflict => Err(RimError::Internal(
+                "tombstone commit rejected by generation check".to_string(),
+            )),
+        }
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
+
+/// Local part files removed by [`evict_archived_parts`].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct EvictedParts {
+    pub parts: u64,
+    pub freed_bytes: u64,
+    /// Parts still on disk because the archive does not hold them.
+    pub unarchived: u64,
+}
+
+/// Removes the local files of the parts of `meta` that the archive holds,
+/// either within the blob's own archive object or as a part object. The part
+/// entries stay, so reads know to go to the archive.
+pub(crate) async fn evict_archived_parts(
+    part_store: &PartStore,
+    store: &MetadataStore,
+    meta: &BlobMeta,
+    dry_run: bool,
+) -> Result<EvictedParts> {
+    let mut evicted = EvictedParts::default();
+    for entry in store.list_part_entries(&meta.path, meta.generation)? {
+        let part_path = match entry.external_path.as_deref() {
+            Some(external_path) => PathBuf::from(external_path),
+            None => part_store.part_path(
+                meta.slot_id,
+                &meta.path,
+                meta.generation,
+                entry.part_no,
+                &entry.sha256,
+            )?,
+        };
+        let Ok(metadata) = tokio::fs::metadata(&part_path).await else {
+            continue;
+        };
+        if entry.archive_url.is_none() && meta.archive_url.is_none() {
+            evicted.unarchived += 1;
+            continue;
+        }
+        if !dry_run {
+            match tokio::fs::remove_file(&part_path).await {
+                Ok(()) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            }
+            if let Some(dir) = part_path.parent() {
+                let _ = tokio::fs::remove_dir(dir).await;
+            }
+        }
+        evicted.parts += 1;
+        evicted.freed_bytes += metadata.len();
+    }
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ClusterClient, ContentHeaders, Coordinator, ManualClock, PART_SIZE, PartIndexState,
+        Registry, compute_hash, registry::memory::MemoryRegistry,
+    };
+    use chrono::Duration;
+    use std::collections::BTreeMap;
+
+    struct Node {
+        _dir: tempfile::TempDir,
+        clock: Arc<ManualClock>,
+        registry: Arc<MemoryRegistry>,
+        store: MetadataStore,
+        lifecycle: ApplyLifecycleOperation,
+    }
+
+    async fn node() -> Node {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(ManualClock::starting_now());
+        let registry = Arc::new(MemoryRegistry::new().with_clock(clock.clone()));
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().join("slots")).unwrap());
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+        let part_store = Arc::new(PartStore::new(dir.path().join("parts")).unwrap());
+        let delete_blob = Arc::new(DeleteBlobOperation::new(
+            slot_manager.clone(),
+            Arc::new(Coordinator::new(1)),
+            Arc::new(ClusterClient::new(registry.clone())),
+        ));
+        let leases = Arc::new(
+            SlotLeaseManager::new(registry.clone(), "node-a".to_string(), 60)
+                .with_clock(clock.clone()),
+        );
+        let lifecycle = ApplyLifecycleOperation::new(slot_manager, part_store, delete_blob)
+            .with_slot_leases(Some(leases))
+            .with_clock(clock.clone());
+        Node {
+            _dir: dir,
+            clock,
+            registry,
+            store,
+            lifecycle,
+        }
+    }
+
+    /// Writes generation `generation` of `logs/a`.
+    fn write(store: &MetadataStore, generation: i64) -> BlobMeta {
+        let meta = BlobMeta {
+            path: "logs/a".to_string(),
+            slot_id: 1,
+            generation,
+            version: generation,
+            size_bytes: 0,
+            etag: compute_hash(b""),
+            part_size: PART_SIZE as u64,
+            part_count: 0,
+            part_index_state: PartIndexState::Complete,
+            archive_url: None,
+            updated_at: Utc::now(),
+            metadata: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            content: ContentHeaders::default(),
+        };
+        assert!(store.upsert_meta(&meta).unwrap());
+        meta
+    }
+
+    fn request() -> ApplyLifecycleOperationRequest {
+        ApplyLifecycleOperationRequest {
+            slot_id: 1,
+            rules: vec![rule("logs/", Some(7), None)],
+            replicas: Vec::new(),
+            primary: true,
+            local_node_id: "node-a".to_string(),
+            dry_run: false,
+        }
+    }
+
+    fn head_kind(store: &MetadataStore) -> HeadKind {
+        store.get_current_head("logs/a").unwrap().unwrap().head_kind
+    }
+
+    #[tokio::test]
+    async fn expiry_leaves_blobs_rewritten_after_the_walk_listed_them() {
+        let node = node().await;
+        let listed = write(&node.store, 1);
+        write(&node.store, 2);
+
+        let expired = node.lifecycle.expire(&listed, &[], "node-a").await.unwrap();
+
+        assert!(!expired);
+        assert_eq!(head_kind(&node.store), HeadKind::Meta);
+    }
+
+    #[tokio::test]
+    async fn expires_under_the_write_lease_only() {
+        let node = node().await;
+        write(&node.store, 1);
+        node.clock.advance(Duration::days(30));
+        node.registry
+            .acquire_slot_lease(1, "node-b", 60)
+            .await
+            .unwrap();
+
+        let result = node.lifecycle.run(request()).await.unwrap();
+        assert_eq!(result.expired, 0);
+        assert_eq!(head_kind(&node.store), HeadKind::Meta);
+
+        node.clock.advance(Duration::seconds(61));
+        let result = node.lifecycle.run(request()).await.unwrap();
+        assert_eq!(result.expired, 1);
+        assert_eq!(head_kind(&node.store), HeadKind::Tombstone);
+    }
+
+    fn rule(prefix: &str, expire: Option<u32>, transition: Option<u32>) -> LifecycleRule {
+        LifecycleRule {
+            id: prefix.to_string(),
+            prefix: prefix.to_string(),
+            expire_after_days: expire,
+            transition_after_days: transition,
+        }
+    }
+
+    #[test]
+    fn expiration_wins_over_transition() {
+        let now = Utc::now();
+        let rules = vec![
+            rule("logs/", None, Some(30)),
+            rule("logs/debug/", Some(7), None),
+        ];
+
+        let due = |path: &str, days: i64| due_action(&rules, path, now - Duration::days(days), now);
+        assert_eq!(due("logs/app.log", 3), None);
+        assert_eq!(due("logs/app.log", 30), Some(LifecycleAction::Transition));
+        assert_eq!(due("logs/debug/x.log", 7), Some(LifecycleAction::Expire));
+        assert_eq!(due("logs/debug/x.log", 40), Some(LifecycleAction::Expire));
+        assert_eq!(due("other/x.log", 400), None);
+    }
+
+    #[test]
+    fn walks_only_the_shared_prefix() {
+        assert_eq!(
+            common_prefix(&[
+                rule("logs/app/", Some(1), None),
+                rule("logs/db/", Some(1), None)
+            ]),
+            "logs/"
+        );
+        assert_eq!(
+            common_prefix(&[rule("logs/", Some(1), None), rule("tmp/", Some(1), None)]),
+            ""
+        );
+        assert_eq!(common_prefix(&[rule("logs/", Some(1), None)]), "logs/");
+    }
+
+    #[test]
+    fn rules_need_an_action_and_unique_ids() {
+        assert!(validate_lifecycle_rules(&[rule("a/", Some(1), None)]).is_ok());
+        assert!(validate_lifecycle_rules(&[rule("a/", None, None)]).is_err());
+        assert!(validate_lifecycle_rules(&[rule("a/", Some(0), None)]).is_err());
+        assert!(
+            validate_lifecycle_rules(&[rule("a/", Some(1), None), rule("a/", None, Some(2))])
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use super::put_blob::{StagedPart, index_parts, link_parts, stage_blob_parts};
+use crate::{
+    BlobMeta, ClusterClient, ContentHeaders, Coordinator, HeadWrite, MetadataStore, PART_SIZE,
+    PartIndexState, PartSource, PartStore, ReplicatedHead, Result, RimError, SharedClock,
+    SlotManager, TombstoneMeta, compute_hash, system_clock,
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct CommitBatchOperation {
+    slot_manager: Arc<SlotManager>,
+    part_store: Arc<PartStore>,
+    coordinator: Arc<Coordinator>,
+    cluster_client: Arc<ClusterClient>,
+    clock: SharedClock,
+}
+
+#[derive(Debug, Clone)]
+pub enum CommitBatchEntry {
+    Put {
+        path: String,
+        body: Bytes,
+    },
+    Delete {
+        path: String,
+    },
+    /// A version of `path` with the content, headers, metadata and tags of
+    /// `source`, a version in the same slot, over the parts every replica
+    /// already holds for it; no data is copied.
+    Link {
+        path: String,
+        source: Box<BlobMeta>,
+    },
+}
+
+impl CommitBatchEntry {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Put { path, .. } | Self::Delete { path } | Self::Link { path, .. } => path,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommitBatchOperationRequest {
+    pub slot_id: u16,
+    pub write_id: String,
+    pub entries: Vec<CommitBatchEntry>,
+    pub replicas: Vec<crate::NodeInfo>,
+    pub local_node_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommitBatchItem {
+    pub path: String,
+    pub deleted: bool,
+    pub generation: i64,
+    pub etag: Option<String>,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommitBatchOperationResult {
+    pub items: Vec<CommitBatchItem>,
+    pub committed_replicas: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum CommitBatchOperationOutcome {
+    Committed(CommitBatchOperationResult),
+    Conflict,
+}
+
+impl CommitBatchOperation {
+    pub fn new(
+        slot_manager: Arc<SlotManager>,
+        part_store: Arc<PartStore>,
+        coordinator: Arc<Coordinator>,
+        cluster_client: Arc<ClusterClient>,
+    ) -> Self {
+        Self {
+            slot_manager,
+            part_store,
+            coordinator,
+            cluster_client,
+            clock: system_clock(),
+        }
+    }
+
+    /// Stamps the batch's versions and tombstones from `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Commits writes and deletes to several paths of one slot atomically.
+    ///
+    /// Parts are staged first; all heads then land in a single metadata
+    /// transaction, so readers observe either none or all of the batch.
+    pub async fn run(
+        &self,
+        request: CommitBatchOperationRequest,
+    ) -> Result<CommitBatchOperationOutcome> {
+        let CommitBatchOperationRequest {
+            slot_id,
+            write_id,
+            entries,
+            replicas,
+            local_node_id,
+        } = request;
+
+        if entries.is_empty() {
+            return Err(RimError::InvalidRequest(
+                "batch cannot be empty".to_string(),
+            ));
+        }
+
+        let mut seen = HashSet::new();
+        for entry in &entries {
+            if !seen.insert(entry.path()) {
+                return Err(RimError::InvalidRequest(format!(
+                    "duplicate path in batch: {}",
+                    entry.path()
+                )));
+            }
+        }
+
+        let store = self.ensure_store(slot_id).await?;
+
+        let (heads, items) =
+            stage_batch_heads(&self.part_store, &store, slot_id, entries, self.clock.now()).await?;
+
+        let writes: Vec<HeadWrite> = heads.iter().map(|head| head.write.clone()).collect();
+        if !store.apply_head_batch(&writes, true)? {
+            return Ok(CommitBatchOperationOutcome::Conflict);
+        }
+
+        let quorum = self.coordinator.write_quorum(replicas.len());
+        let mut committed_replicas = 1usize;
+
+        let remote_replicas: Vec<&crate::NodeInfo> = replicas
+            .iter()
+            .filter(|node| node.node_id != local_node_id.as_str())
+            .collect();
+        let replica_writes = remote_replicas.iter().map(|replica| {
+            self.cluster_client
+                .replicate_head_batch(&replica.node_id, slot_id, &write_id, &heads)
+        });
+
+        for (replica, result) in remote_replicas.iter().zip(join_all(replica_writes).await) {
+            match result {
+                Ok(()) => committed_replicas += 1,
+                Err(error) => {
+                    tracing::warn!(
+                        "Replica batch write failed: node={} slot={} heads={} error={}",
+                        replica.node_id,
+                        slot_id,
+                        heads.len(),
+                        error
+                    );
+                }
+            }
+        }
+
+        if committed_replicas < quorum {
+            return Err(RimError::InsufficientReplicas {
+                required: quorum,
+                found: committed_replicas,
+            });
+        }
+
+        Ok(CommitBatchOperationOutcome::Committed(
+            CommitBatchOperationResult {
+                items,
+                committed_replicas,
+            },
+        ))
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
+
+/// Stages parts for every entry and builds the heads to commit, assigning each
+/// path its next local generation and stamping it with `now`.
+pub(crate) async fn stage_batch_heads(
+    part_store: &PartStore,
+    store: &MetadataStore,
+    slot_id: u16,
+    entries: Vec<CommitBatchEntry>,
+    now: DateTime<Utc>,
+) -> Result<(Vec<ReplicatedHead>, Vec<CommitBatchItem>)> {
+    let mut heads = Vec::with_capacity(entries.len());
+    let mut items = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        let generation = store.next_generation(entry.path())?;
+
+        match entry {
+            CommitBatchEntry::Put { path, body } => {
+                let staged =
+                    stage_blob_parts(part_store, slot_id, &path, generation, &body).await?;
+                store.with_transaction(|tx| {
+                    index_parts(tx, &path, generation, staged.iter().map(|part| &part.local))
+                })?;
+                let parts: Vec<_> = staged.iter().map(StagedPart::replicated).collect();
+
+                let etag = compute_hash(&body);
+                let meta = BlobMeta {
+                    path: path.clone(),
+                    slot_id,
+                    generation,
+                    version: generation,
+                    size_bytes: body.len() as u64,
+                    etag: etag.clone(),
+                    part_size: PART_SIZE as u64,
+                    part_count: parts.len() as u32,
+                    part_index_state: PartIndexState::Complete,
+                    archive_url: None,
+                    updated_at: now,
+                    metadata: BTreeMap::new(),
+                    tags: BTreeMap::new(),
+                    content: ContentHeaders::default(),
+                };
+
+                items.push(CommitBatchItem {
+                    path,
+                    deleted: false,
+                    generation,
+                    etag: Some(etag),
+                    size_bytes: body.len() as u64,
+                });
+                heads.push(ReplicatedHead {
+                    write: HeadWrite::meta(meta)?,
+                    parts,
+                    linked_from: None,
+                });
+            }
+            CommitBatchEntry::Link { path, source } => {
+                let parts = PartSource {
+                    path: source.path.clone(),
+                    generation: source.generation,
+                    part_count: source.part_count,
+                };
+                if !link_parts(store, &parts, &path, generation)? {
+                    return Err(RimError::PartNotFound(format!(
+                        "parts of {} generation {} are not all held here",
+                        source.path, source.generation
+                    )));
+                }
+
+                let meta = BlobMeta {
+                    path: path.clone(),
+                    slot_id,
+                    generation,
+                    version: generation,
+                    archive_url: None,
+                    updated_at: now,
+                    ..*source
+                };
+                items.push(CommitBatchItem {
+                    path,
+                    deleted: false,
+                    generation,
+                    etag: Some(meta.etag.clone()),
+                    size_bytes: meta.size_bytes,
+                });
+                heads.push(ReplicatedHead {
+                    write: HeadWrite::meta(meta)?,
+                    parts: Vec::new(),
+                    linked_from: Some(parts),
+                });
+            }
+            CommitBatchEntry::Delete { path } => {
+                let tombstone = TombstoneMeta {
+                    path: path.clone(),
+                    slot_id,
+                    generation,
+                    deleted_at: now,
+                    reason: "api-batch-delete".to_string(),
+                };
+
+                items.push(CommitBatchItem {
+                    path,
+                    deleted: true,
+                    generation,
+                    etag: None,
+                    size_bytes: 0,
+                });
+                heads.push(ReplicatedHead {
+                    write: HeadWrite::tombstone(tombstone)?,
+                    parts: Vec::new(),
+                    linked_from: None,
+                });
+            }
+        }
+    }
+
+    Ok((heads, items))
+}
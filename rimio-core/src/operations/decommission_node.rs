@@ -0,0 +1,165 @@
+use crate::cluster::handoff::slot_counts;
+use crate::{NodeStatus, PlacementMap, Registry, Result, SlotHandoff, SlotMover};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Copy and verify passes per slot before a decommission leaves the slot for
+/// the next call.
+const DECOMMISSION_COPY_ATTEMPTS: usize = 3;
+
+/// Drains a node so it can be shut down without losing replicas.
+pub struct DecommissionNodeOperation {
+    registry: Arc<dyn Registry>,
+    placement: Arc<PlacementMap>,
+    mover: Arc<SlotMover>,
+    left: RwLock<BTreeSet<String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DecommissionNodeOperationRequest {
+    pub node_id: String,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DecommissionNodeOperationResult {
+    pub node_id: String,
+    pub dry_run: bool,
+    /// Slot moves planned (dry run) or attempted by this call.
+    pub handoffs: Vec<SlotHandoff>,
+    /// Slots still placed on the node afterwards.
+    pub remaining_slots: Vec<u16>,
+    /// Moved slots whose replica set came out smaller than it was.
+    pub under_replicated: Vec<u16>,
+    /// The node was removed from the registry and may be shut down.
+    pub deregistered: bool,
+}
+
+impl DecommissionNodeOperation {
+    pub fn new(
+        registry: Arc<dyn Registry>,
+        placement: Arc<PlacementMap>,
+        mover: Arc<SlotMover>,
+    ) -> Self {
+        Self {
+            registry,
+            placement,
+            mover,
+            left: RwLock::new(BTreeSet::new()),
+        }
+    }
+
+    /// Whether this operation deregistered `node_id`; the node must stop
+    /// registering itself from then on.
+    pub async fn has_left(&self, node_id: &str) -> bool {
+        self.left.read().await.contains(node_id)
+    }
+
+    /// Moves every slot replica off the node to the least loaded healthy node
+    /// outside the slot's replica set, checks that no slot still names the
+    /// node or lost a replica, and only then deregisters it. Slots that fail
+    /// to move stay put; calling again picks them up.
+    pub async fn run(
+        &self,
+        request: DecommissionNodeOperationRequest,
+    ) -> Result<DecommissionNodeOperationResult> {
+        let DecommissionNodeOperationRequest { node_id, dry_run } = request;
+        let mut result = DecommissionNodeOperationResult {
+            node_id: node_id.clone(),
+            dry_run,
+            ..DecommissionNodeOperationResult::default()
+        };
+
+        let remaining: Vec<String> = self
+            .registry
+            .get_nodes()
+            .await?
+            .into_iter()
+            .filter(|node| node.status == NodeStatus::Healthy && node.node_id != node_id)
+            .map(|node| node.node_id)
+            .collect();
+
+        self.placement.refresh().await?;
+        let mut slots = self.placement.slots().await;
+        slots.sort_by_key(|slot| slot.slot_id);
+        let mut counts = slot_counts(&remaining, slots.iter());
+        let mut replica_counts = BTreeMap::new();
+
+        for slot in slots.iter().filter(|slot| slot.replicas.contains(&node_id)) {
+            replica_counts.insert(slot.slot_id, slot.replicas.len());
+            let target = counts
+                .iter()
+                .filter(|(candidate, _)| !slot.replicas.contains(candidate))
+                .min_by(|(a_id, a), (b_id, b)| a.cmp(b).then_with(|| a_id.cmp(b_id)))
+                .map(|(candidate, _)| candidate.clone());
+            let Some(target) = target else {
+                result.handoffs.push(SlotHandoff {
+                    slot_id: slot.slot_id,
+                    from: node_id.clone(),
+                    to: String::new(),
+                    copied: 0,
+                    error: Some("no spare healthy node outside the replica set".to_string()),
+                });
+                continue;
+            };
+            *counts.entry(target.clone()).or_default() += 1;
+
+            let handoff = if dry_run {
+                SlotHandoff {
+                    slot_id: slot.slot_id,
+                    from: node_id.clone(),
+                    to: target,
+                    copied: 0,
+                    error: None,
+                }
+            } else {
+                self.mover
+                    .hand_off(slot, &node_id, &target, DECOMMISSION_COPY_ATTEMPTS)
+                    .await
+            };
+            if let Some(error) = &handoff.error {
+                tracing::warn!(
+                    "decommission handoff failed: node={} slot={} to={} error={}",
+                    node_id,
+                    slot.slot_id,
+                    handoff.to,
+                    error
+                );
+            }
+            result.handoffs.push(handoff);
+        }
+
+        if dry_run {
+            result.remaining_slots = replica_counts.keys().copied().collect();
+            return Ok(result);
+        }
+
+        self.placement.refresh().await?;
+        for slot in self.placement.slots().await {
+            if slot.replicas.contains(&node_id) {
+                result.remaining_slots.push(slot.slot_id);
+            } else if let Some(expected) = replica_counts.get(&slot.slot_id) {
+                let distinct: BTreeSet<&String> = slot.replicas.iter().collect();
+                if distinct.len() < *expected {
+                    result.under_replicated.push(slot.slot_id);
+                }
+            }
+        }
+        result.remaining_slots.sort_unstable();
+        result.under_replicated.sort_unstable();
+
+        if result.remaining_slots.is_empty() && result.under_replicated.is_empty() {
+            self.left.write().await.insert(node_id.clone());
+            if let Err(error) = self.registry.deregister_node(&node_id).await {
+                self.left.write().await.remove(&node_id);
+                return Err(error);
+            }
+            tracing::info!("decommissioned node: node={}", node_id);
+            result.deregistered = true;
+        }
+
+        Ok(result)
+    }
+}
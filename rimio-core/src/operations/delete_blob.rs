@@ -1,8 +1,9 @@
+use super::put_blob::{BlobCommit, freshest_head, live_generation};
 use crate::{
-    ClusterClient, Coordinator, MetadataStore, Result, RimError, SlotManager, TombstoneMeta,
-    compute_hash,
+    BlobHead, ClusterClient, Coordinator, MetadataStore, NodeInfo, ReplicationPolicy, Result,
+    RimError, SharedClock, SlotManager, TombstoneMeta, compute_hash, system_clock,
 };
-use chrono::Utc;
+use futures_util::future::join_all;
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -10,6 +11,8 @@ pub struct DeleteBlobOperation {
     slot_manager: Arc<SlotManager>,
     coordinator: Arc<Coordinator>,
     cluster_client: Arc<ClusterClient>,
+    clock: SharedClock,
+    replication_policy: ReplicationPolicy,
 }
 
 #[derive(Debug, Clone)]
@@ -19,6 +22,9 @@ pub struct DeleteBlobOperationRequest {
     pub write_id: String,
     pub replicas: Vec<crate::NodeInfo>,
     pub local_node_id: String,
+    /// Tombstone only while the live version of `path` is at this
+    /// generation, the newest a write quorum of replicas reports.
+    pub expected_generation: Option<i64>,
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +37,10 @@ pub struct DeleteBlobOperationResult {
 pub enum DeleteBlobOperationOutcome {
     Committed(DeleteBlobOperationResult),
     Conflict,
+    /// `expected_generation` did not match; nothing was written.
+    PreconditionFailed {
+        current_generation: i64,
+    },
 }
 
 impl DeleteBlobOperation {
@@ -43,9 +53,41 @@ impl DeleteBlobOperation {
             slot_manager,
             coordinator,
             cluster_client,
+            clock: system_clock(),
+            replication_policy: ReplicationPolicy::default(),
         }
     }
 
+    /// Dates tombstones by `clock`, which tombstone retention ages them by.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Narrows the replicas of each delete by the path's prefix policy, the
+    /// same way writes of the path are.
+    pub fn with_replication_policy(mut self, replication_policy: ReplicationPolicy) -> Self {
+        self.replication_policy = replication_policy;
+        self
+    }
+
+    /// Whether `slot_id` is frozen for maintenance, as the placement map
+    /// has it cached.
+    pub async fn slot_frozen(&self, slot_id: u16) -> bool {
+        self.cluster_client.placement().is_frozen(slot_id).await
+    }
+
+    /// Tombstones `path` on this node and on every replica, and succeeds
+    /// once a write quorum of replicas has it. A frozen slot is refused
+    /// before anything is written, since its replicas would refuse the
+    /// tombstone and leave only this node's copy behind.
+    ///
+    /// This node keeps a tombstone either way, like a write's coordinator
+    /// keeps its copy, but only counts towards the quorum as a replica. When
+    /// it is not one, its own slot may not have seen the blob, so the
+    /// tombstone's generation is taken above the newest head a quorum of
+    /// replicas reports; a lower one would be stored but never shadow the
+    /// live version.
     pub async fn run(
         &self,
         request: DeleteBlobOperationRequest,
@@ -56,58 +98,106 @@ impl DeleteBlobOperation {
             write_id,
             replicas,
             local_node_id,
+            expected_generation,
         } = request;
+        if self.slot_frozen(slot_id).await {
+            return Err(RimError::SlotFrozen(slot_id));
+        }
+        let replicas = self
+            .replication_policy
+            .write_replicas(&path, replicas, &local_node_id);
+        let local_replica =
+            replicas.is_empty() || replicas.iter().any(|node| node.node_id == local_node_id);
+        let remote_replicas: Vec<&NodeInfo> = replicas
+            .iter()
+            .filter(|node| node.node_id != local_node_id.as_str())
+            .collect();
+        let quorum = self.coordinator.write_quorum(replicas.len());
 
         let store = self.ensure_store(slot_id).await?;
-        let generation = store.next_generation(&path)?;
+        let mut generation = store.next_generation(&path)?;
+        // With an expected generation, the check runs against the newest
+        // head among this node and a quorum of replicas, and the local
+        // tombstone is then conditioned on the local head the check saw.
+        let mut local_expected = None;
+        if !local_replica || expected_generation.is_some() {
+            let local = store.get_current_head(&path)?;
+            let heads = join_all(remote_replicas.iter().map(|replica| {
+                self.cluster_client
+                    .fetch_remote_head(&replica.node_id, slot_id, &path)
+            }))
+            .await;
+            if !local_replica {
+                generation = generation.max(generation_above(&heads, quorum)?);
+            }
+            if let Some(expected) = expected_generation {
+                local_expected = Some(live_generation(local.clone()));
+                let freshest = freshest_head(local, local_replica, heads, quorum)?;
+                let current_generation = live_generation(freshest.clone());
+                if current_generation != expected {
+                    return Ok(DeleteBlobOperationOutcome::PreconditionFailed {
+                        current_generation,
+                    });
+                }
+                generation = generation.max(freshest.map_or(0, |head| head.generation) + 1);
+            }
+        }
 
         let tombstone = TombstoneMeta {
             path: path.clone(),
             slot_id,
             generation,
-            deleted_at: Utc::now(),
+            deleted_at: self.clock.now(),
             reason: "api-delete".to_string(),
         };
 
         let tombstone_bytes = serde_json::to_vec(&tombstone)?;
         let tombstone_sha = compute_hash(&tombstone_bytes);
 
-        let applied =
-            store.insert_tombstone_with_payload(&tombstone, &tombstone_bytes, &tombstone_sha)?;
-        if !applied {
-            return Ok(DeleteBlobOperationOutcome::Conflict);
+        let commit = store.with_transaction(|tx| {
+            if let Some(expected) = local_expected {
+                let current_generation = live_generation(tx.get_current_head(&path)?);
+                if current_generation != expected {
+                    return Ok(BlobCommit::PreconditionFailed { current_generation });
+                }
+            }
+            if !tx.insert_tombstone_with_payload(&tombstone, &tombstone_bytes, &tombstone_sha)? {
+                return Ok(BlobCommit::Superseded);
+            }
+            Ok(BlobCommit::Applied)
+        })?;
+        match commit {
+            BlobCommit::Applied => {}
+            BlobCommit::Superseded => return Ok(DeleteBlobOperationOutcome::Conflict),
+            BlobCommit::PreconditionFailed { current_generation } => {
+                return Ok(DeleteBlobOperationOutcome::PreconditionFailed { current_generation });
+            }
         }
 
-        let quorum = self.coordinator.write_quorum(replicas.len());
-        let mut committed_replicas = 1usize;
-
-        for replica in replicas
-            .iter()
-            .filter(|node| node.node_id != local_node_id.as_str())
-        {
-            let response = self
-                .cluster_client
-                .replicate_tombstone_write(
-                    &replica.node_id,
-                    slot_id,
-                    &path,
-                    &write_id,
-                    generation,
-                    &tombstone,
-                    &tombstone_sha,
-                )
-                .await;
-
-            if response.is_ok() {
-                committed_replicas += 1;
-            } else if let Err(error) = response {
-                tracing::warn!(
-                    "Replica tombstone write failed: node={} slot={} path={} error={}",
-                    replica.node_id,
-                    slot_id,
-                    path,
-                    error
-                );
+        let mut committed_replicas = usize::from(local_replica);
+        let replica_writes = remote_replicas.iter().map(|replica| {
+            self.cluster_client.replicate_tombstone_write(
+                &replica.node_id,
+                slot_id,
+                &path,
+                &write_id,
+                generation,
+                &tombstone,
+                &tombstone_sha,
+            )
+        });
+        for (replica, response) in remote_replicas.iter().zip(join_all(replica_writes).await) {
+            match response {
+                Ok(()) => committed_replicas += 1,
+                Err(error) => {
+                    tracing::warn!(
+                        "Replica tombstone write failed: node={} slot={} path={} error={}",
+                        replica.node_id,
+                        slot_id,
+                        path,
+                        error
+                    );
+                }
             }
         }
 
@@ -135,3 +225,95 @@ impl DeleteBlobOperation {
         MetadataStore::new(slot)
     }
 }
+
+/// The generation after the newest head among replica answers; fails unless
+/// at least `quorum` replicas answered, since only then does the answer
+/// cover every committed version.
+fn generation_above(heads: &[Result<Option<BlobHead>>], quorum: usize) -> Result<i64> {
+    let answered: Vec<i64> = heads
+        .iter()
+        .filter_map(|head| head.as_ref().ok())
+        .map(|head| head.as_ref().map_or(0, |head| head.generation))
+        .collect();
+    if answered.len() < quorum {
+        return Err(RimError::InsufficientReplicas {
+            required: quorum,
+            found: answered.len(),
+        });
+    }
+    Ok(answered.into_iter().max().unwrap_or(0) + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadKind, Registry, SlotInfo, registry::memory::MemoryRegistry};
+
+    fn head(generation: i64) -> Result<Option<BlobHead>> {
+        Ok(Some(BlobHead {
+            path: "a.bin".to_string(),
+            generation,
+            head_kind: HeadKind::Meta,
+            head_sha256: String::new(),
+            updated_at: chrono::Utc::now(),
+            meta: None,
+            tombstone: None,
+        }))
+    }
+
+    #[test]
+    fn remote_generation_needs_a_quorum_of_answers() {
+        let unreachable = || Err(RimError::Http("connection refused".to_string()));
+
+        assert_eq!(
+            generation_above(&[head(4), Ok(None), unreachable()], 2).unwrap(),
+            5
+        );
+        assert_eq!(generation_above(&[Ok(None), Ok(None)], 2).unwrap(), 1);
+        assert!(matches!(
+            generation_above(&[head(4), unreachable(), unreachable()], 2),
+            Err(RimError::InsufficientReplicas {
+                required: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn frozen_slot_is_refused_before_the_local_tombstone() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Arc::new(MemoryRegistry::new());
+        registry
+            .set_slot(&SlotInfo {
+                slot_id: 3,
+                replicas: vec!["node-a".to_string()],
+                primary: "node-a".to_string(),
+                latest_seq: String::new(),
+                epoch: 1,
+                frozen: true,
+            })
+            .await
+            .unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().join("slots")).unwrap());
+        let operation = DeleteBlobOperation::new(
+            slot_manager.clone(),
+            Arc::new(Coordinator::new(1)),
+            Arc::new(ClusterClient::new(registry)),
+        );
+
+        let outcome = operation
+            .run(DeleteBlobOperationRequest {
+                path: "a.bin".to_string(),
+                slot_id: 3,
+                write_id: "delete-1".to_string(),
+                replicas: Vec::new(),
+                local_node_id: "node-a".to_string(),
+                expected_generation: None,
+            })
+            .await;
+
+        assert!(matches!(outcome, Err(RimError::SlotFrozen(3))));
+        assert!(!slot_manager.has_slot(3).await);
+    }
+}
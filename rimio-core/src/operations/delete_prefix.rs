@@ -0,0 +1,613 @@
+//! Deleting every blob under a prefix, the namespace's `rm -r`.
+//!
+//! Paths hash to slots, so a prefix is spread over all of them. Each slot is
+//! walked in path order a page at a time, from this node's copy when it is a
+//! replica and from the first replica that answers otherwise, and every live
+//! path found is tombstoned through [`DeleteBlobOperation`]. The cursor is
+//! the last path handled, which a tombstone never moves, so deleting while
+//! iterating neither skips nor revisits paths. Blobs written under the prefix
+//! behind the cursor while the walk runs are left alone. Slots frozen for
+//! maintenance are skipped whole and reported, so a rerun after the thaw
+//! finishes them.
+//!
+//! With write leases on, each page is deleted under the slot's lease like any
+//! other write of the slot. A slot whose lease another node holds is handed
+//! to that node, which walks it itself and answers with its report.
+
+use crate::{
+    ClusterClient, DeleteBlobOperation, DeleteBlobOperationOutcome, DeleteBlobOperationRequest,
+    HeadKind, MetadataStore, NodeInfo, Result, RimError, SlotLeaseManager, SlotManager,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::OwnedMutexGuard;
+
+/// Paths listed and deleted per step of a slot walk.
+pub const DEFAULT_DELETE_PREFIX_PAGE_SIZE: usize = 500;
+
+/// Failed paths kept per slot in the report; the count covers all of them.
+const MAX_REPORTED_FAILURES: usize = 20;
+
+#[derive(Clone)]
+pub struct DeletePrefixOperation {
+    slot_manager: Arc<SlotManager>,
+    delete_blob_operation: Arc<DeleteBlobOperation>,
+    cluster_client: Arc<ClusterClient>,
+    slot_leases: Option<Arc<SlotLeaseManager>>,
+}
+
+struct ListedPage {
+    paths: Vec<String>,
+    /// Where the next page starts, `None` once the slot is exhausted.
+    next_cursor: Option<String>,
+}
+
+/// Who deletes in a slot next.
+enum SlotClaim {
+    /// This node, holding the slot's write lock when leases are on.
+    Local(Option<OwnedMutexGuard<()>>),
+    /// The node holding the slot's write lease.
+    Remote(String),
+}
+
+/// A slot to walk with its replicas.
+#[derive(Debug, Clone)]
+pub struct DeletePrefixSlot {
+    pub slot_id: u16,
+    pub replicas: Vec<NodeInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeletePrefixOperationRequest {
+    pub prefix: String,
+    pub slots: Vec<DeletePrefixSlot>,
+    /// Count the live blobs under the prefix without deleting them.
+    pub dry_run: bool,
+    pub page_size: usize,
+    pub local_node_id: String,
+    /// Set when another node handed the walk over; slots whose lease this
+    /// node does not hold then fail instead of being handed on again.
+    pub forwarded: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeletePrefixSlotReport {
+    pub slot_id: u16,
+    pub matched: u64,
+    pub deleted: u64,
+    pub failed: u64,
+    /// Up to the first few paths that could not be deleted, with the reason.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<String>,
+    /// Set when the slot could not be listed or its lease holder could not
+    /// walk it; the rest of its blobs were not touched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeletePrefixOperationResult {
+    pub prefix: String,
+    pub dry_run: bool,
+    pub matched: u64,
+    pub deleted: u64,
+    pub failed: u64,
+    /// Slots that could not be listed or handed to their lease holder.
+    pub unreachable_slots: Vec<u16>,
+    /// Slots skipped because they are frozen for maintenance.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub frozen_slots: Vec<u16>,
+    /// Slots with at least one match, failure or listing error.
+    pub slots: Vec<DeletePrefixSlotReport>,
+}
+
+/// Where a walk stands, reported after every page.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeletePrefixProgress {
+    pub slot_id: u16,
+    pub slots_done: usize,
+    pub slots_total: usize,
+    pub matched: u64,
+    pub deleted: u64,
+    pub failed: u64,
+    /// Where the walk of the slot resumes, `None` once it is done.
+    pub cursor: Option<String>,
+}
+
+impl DeletePrefixOperation {
+    pub fn new(
+        slot_manager: Arc<SlotManager>,
+        delete_blob_operation: Arc<DeleteBlobOperation>,
+        cluster_client: Arc<ClusterClient>,
+    ) -> Self {
+        Self {
+            slot_manager,
+            delete_blob_operation,
+            cluster_client,
+            slot_leases: None,
+        }
+    }
+
+    /// Deletes in each slot under its write lease, handing slots leased to
+    /// other nodes over to them.
+    pub fn with_slot_leases(mut self, slot_leases: Option<Arc<SlotLeaseManager>>) -> Self {
+        self.slot_leases = slot_leases;
+        self
+    }
+
+    pub async fn run(
+        &self,
+        request: DeletePrefixOperationRequest,
+    ) -> Result<DeletePrefixOperationResult> {
+        self.run_with_progress(request, |_| {}).await
+    }
+
+    /// Like [`Self::run`], calling `progress` after each page of each slot.
+    pub async fn run_with_progress(
+        &self,
+        request: DeletePrefixOperationRequest,
+        mut progress: impl FnMut(&DeletePrefixProgress),
+    ) -> Result<DeletePrefixOperationResult> {
+        let DeletePrefixOperationRequest {
+            prefix,
+            slots,
+            dry_run,
+            page_size,
+            local_node_id,
+            forwarded,
+        } = request;
+
+        if prefix.is_empty() {
+            return Err(RimError::InvalidRequest(
+                "prefix must not be empty".to_string(),
+            ));
+        }
+        let page_size = page_size.max(1);
+
+        let mut result = DeletePrefixOperationResult {
+            prefix: prefix.clone(),
+            dry_run,
+            ..DeletePrefixOperationResult::default()
+        };
+        let slots_total = slots.len();
+
+        for (index, slot) in slots.into_iter().enumerate() {
+            if self.delete_blob_operation.slot_frozen(slot.slot_id).await {
+                tracing::info!(
+                    "prefix delete skipped frozen slot: slot={} prefix={}",
+                    slot.slot_id,
+                    prefix
+                );
+                result.frozen_slots.push(slot.slot_id);
+                continue;
+            }
+            let mut report = DeletePrefixSlotReport {
+                slot_id: slot.slot_id,
+                ..DeletePrefixSlotReport::default()
+            };
+            let mut cursor: Option<String> = None;
+
+            loop {
+                let page = match self
+                    .list_page(&slot, &prefix, cursor.as_deref(), page_size, &local_node_id)
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(error) => {
+                        tracing::warn!(
+                            "prefix delete could not list slot: slot={} prefix={} error={}",
+                            slot.slot_id,
+                            prefix,
+                            error
+                        );
+                        report.error = Some(error.to_string());
+                        result.unreachable_slots.push(slot.slot_id);
+                        break;
+                    }
+                };
+                let handled = !page.paths.is_empty();
+                cursor = page.next_cursor;
+                let claim = if handled && !dry_run {
+                    self.claim_slot(&slot, &local_node_id).await
+                } else {
+                    Ok(SlotClaim::Local(None))
+                };
+                let handed_over = match claim {
+                    Ok(SlotClaim::Local(_write_lock)) => {
+                        for path in page.paths {
+                            report.matched += 1;
+                            if dry_run {
+                                continue;
+                            }
+                            match self.delete(&slot, &path, &local_node_id).await {
+                                Ok(()) => report.deleted += 1,
+                                Err(reason) => {
+                                    report.failed += 1;
+                                    if report.failures.len() < MAX_REPORTED_FAILURES {
+                                        report.failures.push(format!("{}: {}", path, reason));
+                                    }
+                                }
+                            }
+                        }
+                        Ok(())
+                    }
+                    Ok(SlotClaim::Remote(holder)) if forwarded => Err(RimError::Internal(format!(
+                        "slot write lease held by {}",
+                        holder
+                    ))),
+                    Ok(SlotClaim::Remote(holder)) => {
+                        self.hand_over(&slot, &holder, &prefix, page_size, &mut report)
+                            .await
+                    }
+                    Err(error) => Err(error),
+                };
+                if let Err(error) = handed_over {
+                    tracing::warn!(
+                        "prefix delete could not claim slot: slot={} prefix={} error={}",
+                        slot.slot_id,
+                        prefix,
+                        error
+                    );
+                    report.error = Some(error.to_string());
+                    result.unreachable_slots.push(slot.slot_id);
+                    cursor = None;
+                }
+                if handled {
+                    progress(&DeletePrefixProgress {
+                        slot_id: slot.slot_id,
+                        slots_done: index,
+                        slots_total,
+                        matched: result.matched + report.matched,
+                        deleted: result.deleted + report.deleted,
+                        failed: result.failed + report.failed,
+                        cursor: cursor.clone(),
+                    });
+                }
+                if cursor.is_none() {
+                    break;
+                }
+            }
+
+            result.matched += report.matched;
+            result.deleted += report.deleted;
+            result.failed += report.failed;
+            if report.matched > 0 || report.error.is_some() {
+                result.slots.push(report);
+            }
+        }
+
+        progress(&DeletePrefixProgress {
+            slot_id: result.slots.last().map_or(0, |slot| slot.slot_id),
+            slots_done: slots_total,
+            slots_total,
+            matched: result.matched,
+            deleted: result.deleted,
+            failed: result.failed,
+            cursor: None,
+        });
+        tracing::info!(
+            "Prefix delete finished: prefix={} dry_run={} matched={} deleted={} failed={} unreachable_slots={} frozen_slots={}",
+            prefix,
+            dry_run,
+            result.matched,
+            result.deleted,
+            result.failed,
+            result.unreachable_slots.len(),
+            result.frozen_slots.len()
+        );
+
+        Ok(result)
+    }
+
+    /// Claims the write lease of a slot the way a write of one of its paths
+    /// would.
+    async fn claim_slot(&self, slot: &DeletePrefixSlot, local_node_id: &str) -> Result<SlotClaim> {
+        let Some(leases) = self.slot_leases.as_ref() else {
+            return Ok(SlotClaim::Local(None));
+        };
+
+        let lease = if slot
+            .replicas
+            .iter()
+            .any(|node| node.node_id == local_node_id)
+        {
+            Some(leases.acquire(slot.slot_id).await?)
+        } else {
+            leases.current(slot.slot_id).await?
+        };
+        let holder = match lease {
+            Some(lease) => lease.holder,
+            None => slot
+                .replicas
+                .first()
+                .map(|node| node.node_id.clone())
+                .ok_or_else(|| {
+                    RimError::Internal(format!("slot {} has no replicas", slot.slot_id))
+                })?,
+        };
+
+        if holder == local_node_id {
+            return Ok(SlotClaim::Local(Some(leases.lock_slot(slot.slot_id).await)));
+        }
+        Ok(SlotClaim::Remote(holder))
+    }
+
+    /// Has `holder` walk the slot from the start and adds its report to
+    /// `report`. Paths this node already deleted no longer match there.
+    async fn hand_over(
+        &self,
+        slot: &DeletePrefixSlot,
+        holder: &str,
+        prefix: &str,
+        page_size: usize,
+        report: &mut DeletePrefixSlotReport,
+    ) -> Result<()> {
+        let remote = self
+            .cluster_client
+            .delete_prefix_in_slot(holder, slot.slot_id, prefix, page_size)
+            .await?;
+        report.matched += remote.matched;
+        report.deleted += remote.deleted;
+        report.failed += remote.failed;
+        let room = MAX_REPORTED_FAILURES.saturating_sub(report.failures.len());
+        report
+            .failures
+            .extend(remote.failures.into_iter().take(room));
+        match remote.error {
+            Some(error) => Err(RimError::Http(format!(
+                "lease holder {} failed the slot: {}",
+                holder, error
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// The next live paths under `prefix` after `cursor`, in path order.
+    async fn list_page(
+        &self,
+        slot: &DeletePrefixSlot,
+        prefix: &str,
+        cursor: Option<&str>,
+        page_size: usize,
+        local_node_id: &str,
+    ) -> Result<ListedPage> {
+        // Slot listings match with LIKE, which also treats `_` and `%` in the
+        // prefix as wildcards, so every path is checked again here.
+        if slot
+            .replicas
+            .iter()
+            .any(|node| node.node_id == local_node_id)
+        {
+            let store = self.ensure_store(slot.slot_id).await?;
+            let heads = store.list_heads(prefix, page_size, false, cursor)?;
+            let next_cursor = (heads.len() >= page_size)
+                .then(|| heads.last().map(|head| head.path.clone()))
+                .flatten();
+            return Ok(ListedPage {
+                paths: heads
+                    .into_iter()
+                    .filter(|head| head.head_kind == HeadKind::Meta)
+                    .map(|head| head.path)
+                    .filter(|path| path.starts_with(prefix))
+                    .collect(),
+                next_cursor,
+            });
+        }
+
+        let mut last_error = None;
+        for node in &slot.replicas {
+            match self
+                .cluster_client
+                .fetch_slot_heads(&node.node_id, slot.slot_id)
+                .await
+            {
+                Ok(mut heads) => {
+                    heads.retain(|head| {
+                        head.path.starts_with(prefix)
+                            && cursor.is_none_or(|cursor| head.path.as_str() > cursor)
+                    });
+                    heads.sort_by(|a, b| {
+                        a.path
+                            .cmp(&b.path)
+                            .then_with(|| b.generation.cmp(&a.generation))
+                    });
+                    heads.dedup_by(|a, b| a.path == b.path);
+                    let paths: Vec<String> = heads
+                        .into_iter()
+                        .filter(|head| head.head_kind == "meta")
+                        .take(page_size)
+                        .map(|head| head.path)
+                        .collect();
+                    let next_cursor = (paths.len() >= page_size)
+                        .then(|| paths.last().cloned())
+                        .flatten();
+                    return Ok(ListedPage { paths, next_cursor });
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            RimError::Internal(format!("slot {} has no replicas", slot.slot_id))
+        }))
+    }
+
+    async fn delete(
+        &self,
+        slot: &DeletePrefixSlot,
+        path: &str,
+        local_node_id: &str,
+    ) -> std::result::Result<(), String> {
+        let outcome = self
+            .delete_blob_operation
+            .run(DeleteBlobOperationRequest {
+                path: path.to_string(),
+                slot_id: slot.slot_id,
+                write_id: format!("delete-{}", ulid::Ulid::new()),
+                replicas: slot.replicas.clone(),
+                local_node_id: local_node_id.to_string(),
+                expected_generation: None,
+            })
+            .await;
+
+        match outcome {
+            Ok(DeleteBlobOperationOutcome::Committed(_)) => Ok(()),
+            Ok(
+                DeleteBlobOperationOutcome::Conflict
+                | DeleteBlobOperationOutcome::PreconditionFailed { .. },
+            ) => Err("tombstone commit rejected by generation check".to_string()),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ContentHeaders, Coordinator, NodeStatus, PartStore, PutBlobOperation,
+        PutBlobOperationOutcome, PutBlobOperationRequest, Registry, WriteConsistency,
+        registry::memory::MemoryRegistry,
+    };
+    use bytes::Bytes;
+
+    struct Node {
+        _dir: tempfile::TempDir,
+        registry: Arc<MemoryRegistry>,
+        put: PutBlobOperation,
+        delete_prefix: DeletePrefixOperation,
+    }
+
+    fn node() -> Node {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Arc::new(MemoryRegistry::new());
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().join("slots")).unwrap());
+        let part_store = Arc::new(PartStore::new(dir.path().join("parts")).unwrap());
+        let coordinator = Arc::new(Coordinator::new(1));
+        let cluster_client = Arc::new(ClusterClient::new(registry.clone()));
+        let put = PutBlobOperation::new(
+            slot_manager.clone(),
+            part_store,
+            coordinator.clone(),
+            cluster_client.clone(),
+            None,
+        );
+        let delete_blob = Arc::new(DeleteBlobOperation::new(
+            slot_manager.clone(),
+            coordinator,
+            cluster_client.clone(),
+        ));
+        let leases = Arc::new(SlotLeaseManager::new(
+            registry.clone(),
+            "node-a".to_string(),
+            60,
+        ));
+        let delete_prefix = DeletePrefixOperation::new(slot_manager, delete_blob, cluster_client)
+            .with_slot_leases(Some(leases));
+        Node {
+            _dir: dir,
+            registry,
+            put,
+            delete_prefix,
+        }
+    }
+
+    fn slot(slot_id: u16) -> DeletePrefixSlot {
+        DeletePrefixSlot {
+            slot_id,
+            replicas: vec![NodeInfo {
+                node_id: "node-a".to_string(),
+                group_id: "default".to_string(),
+                address: "127.0.0.1:1".to_string(),
+                status: NodeStatus::Healthy,
+                slots: Vec::new(),
+                grpc_address: None,
+            }],
+        }
+    }
+
+    async fn put(node: &Node, slot: &DeletePrefixSlot, path: &str) {
+        let outcome = node
+            .put
+            .run(PutBlobOperationRequest {
+                path: path.to_string(),
+                slot_id: slot.slot_id,
+                write_id: format!("put-{}", path),
+                body: Bytes::from_static(b"hello"),
+                replicas: slot.replicas.clone(),
+                local_node_id: "node-a".to_string(),
+                consistency: WriteConsistency::Quorum,
+                expected_generation: None,
+                metadata: Default::default(),
+                tags: Default::default(),
+                content: ContentHeaders::default(),
+            })
+            .await
+            .unwrap();
+        assert!(matches!(outcome, PutBlobOperationOutcome::Committed(_)));
+    }
+
+    fn request(slots: Vec<DeletePrefixSlot>, forwarded: bool) -> DeletePrefixOperationRequest {
+        DeletePrefixOperationRequest {
+            prefix: "logs/".to_string(),
+            slots,
+            dry_run: false,
+            page_size: 10,
+            local_node_id: "node-a".to_string(),
+            forwarded,
+        }
+    }
+
+    #[tokio::test]
+    async fn deletes_in_slots_whose_lease_it_claims() {
+        let node = node();
+        put(&node, &slot(1), "logs/a").await;
+        put(&node, &slot(1), "logs/b").await;
+
+        let result = node
+            .delete_prefix
+            .run(request(vec![slot(1)], false))
+            .await
+            .unwrap();
+
+        assert_eq!(result.deleted, 2);
+        assert!(result.unreachable_slots.is_empty());
+        let lease = node.registry.get_slot_lease(1).await.unwrap().unwrap();
+        assert_eq!(lease.holder, "node-a");
+    }
+
+    #[tokio::test]
+    async fn handed_over_walk_leaves_slots_leased_elsewhere_alone() {
+        let node = node();
+        put(&node, &slot(2), "logs/a").await;
+        node.registry
+            .acquire_slot_lease(2, "node-b", 60)
+            .await
+            .unwrap();
+
+        let result = node
+            .delete_prefix
+            .run(request(vec![slot(2)], true))
+            .await
+            .unwrap();
+
+        assert_eq!(result.deleted, 0);
+        assert_eq!(result.unreachable_slots, vec![2]);
+        assert!(
+            result.slots[0]
+                .error
+                .as_deref()
+                .is_some_and(|error| error.contains("node-b"))
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Finding data nothing will read, by cross-checking the registry's slot
+//! placement, the slot databases of this node and the part files on its disk.
+//!
+//! The report only looks; every finding comes with the commands an operator
+//! would run to clean it up once they agree with it.
+
+use crate::{MetadataStore, PlacementMap, Registry, Result, RimError, Slot, SlotManager};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Paths listed per finding; the counts cover the rest.
+pub const GARBAGE_SAMPLE_PATHS: usize = 20;
+
+/// Part files younger than this may belong to a write that has not indexed
+/// them yet, so they are never reported as dangling.
+const DANGLING_PART_GRACE: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GarbageKind {
+    /// A slot held on this node that the placement map assigns elsewhere.
+    UnownedData,
+    /// A slot no registered node owns.
+    OwnerlessSlot,
+    /// Part files of an owned slot that no part entry of its database names.
+    DanglingParts,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GarbageFinding {
+    pub kind: GarbageKind,
+    pub slot_id: u16,
+    /// Registered nodes the placement map assigns the slot to.
+    pub owners: Vec<String>,
+    /// Files on this node the finding covers, and their size.
+    pub files: u64,
+    pub bytes: u64,
+    /// Up to [`GARBAGE_SAMPLE_PATHS`] of those files, or the slot directory.
+    pub paths: Vec<String>,
+    /// Suggested commands, in the order to run them.
+    pub remediation: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GarbageReport {
+    pub node_id: String,
+    pub checked_at: DateTime<Utc>,
+    pub owned_slots: usize,
+    pub held_slots: usize,
+    /// Bytes on this node covered by the findings.
+    pub garbage_bytes: u64,
+    pub findings: Vec<GarbageFinding>,
+}
+
+#[derive(Clone)]
+pub struct GarbageReportOperation {
+    slot_manager: Arc<SlotManager>,
+    placement: Arc<PlacementMap>,
+    registry: Arc<dyn Registry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GarbageReportOperationRequest {
+    pub local_node_id: String,
+    pub total_slots: u16,
+}
+
+impl GarbageReportOperation {
+    pub fn new(
+        slot_manager: Arc<SlotManager>,
+        placement: Arc<PlacementMap>,
+        registry: Arc<dyn Registry>,
+    ) -> Self {
+        Self {
+            slot_manager,
+            placement,
+            registry,
+        }
+    }
+
+    pub async fn run(&self, request: GarbageReportOperationRequest) -> Result<GarbageReport> {
+        let GarbageReportOperationRequest {
+            local_node_id,
+            total_slots,
+        } = request;
+
+        if self.placement.refresh().await? == 0 {
+            // Without a placement map every slot would look ownerless.
+            return Err(RimError::InvalidRequest(
+                "the registry holds no slot placement yet".to_string(),
+            ));
+        }
+        let addresses: HashMap<String, String> = self
+            .registry
+            .get_nodes()
+            .await?
+            .into_iter()
+            .map(|node| (node.node_id, node.address))
+            .collect();
+        let owners: HashMap<u16, Vec<String>> = self
+            .placement
+            .slots()
+            .await
+            .into_iter()
+            .map(|slot| {
+                let owners = slot
+                    .replicas
+                    .into_iter()
+                    .filter(|node_id| addresses.contains_key(node_id))
+                    .collect();
+                (slot.slot_id, owners)
+            })
+            .collect();
+        let held: BTreeSet<u16> = self.slot_manager.list_local_slots()?.into_iter().collect();
+
+        let mut findings = Vec::new();
+        let mut owned_slots = 0;
+        for slot_id in 0..total_slots {
+            let slot_owners = owners.get(&slot_id).cloned().unwrap_or_default();
+            if slot_owners.contains(&local_node_id) {
+                owned_slots += 1;
+            }
+            if !slot_owners.is_empty() {
+                continue;
+            }
+
+            let mut finding = GarbageFinding {
+                kind: GarbageKind::OwnerlessSlot,
+                slot_id,
+                owners: Vec::new(),
+                files: 0,
+                bytes: 0,
+                paths: Vec::new(),
+                remediation: vec![format!(
+                    "reassign slot {} to live nodes in the registry, or enable slot_rebalance to do it",
+                    slot_id
+                )],
+            };
+            if held.contains(&slot_id) {
+                let dir = self.slot(slot_id).await?.data_path.clone();
+                (finding.files, finding.bytes) = dir_usage(&dir)?;
+                finding.paths.push(dir.display().to_string());
+                finding.remediation.push(format!(
+                    "keep {} until an owner holds slot {}: it may be the only copy",
+                    dir.display(),
+                    slot_id
+                ));
+            }
+            findings.push(finding);
+        }
+
+        for slot_id in held.iter().copied() {
+            let slot_owners = owners.get(&slot_id).cloned().unwrap_or_default();
+            if slot_owners.contains(&local_node_id) {
+                let slot = self.slot(slot_id).await?;
+                findings.extend(dangling_parts(&slot, slot_owners)?);
+            } else if !slot_owners.is_empty() || slot_id >= total_slots {
+                findings.push(
+                    self.unowned_data(slot_id, slot_owners, &addresses, &local_node_id)
+                        .await?,
+                );
+            }
+        }
+
+        let report = GarbageReport {
+            node_id: local_node_id,
+            checked_at: Utc::now(),
+            owned_slots,
+            held_slots: held.len(),
+            garbage_bytes: findings.iter().map(|finding| finding.bytes).sum(),
+            findings,
+        };
+        if !report.findings.is_empty() {
+            tracing::info!(
+                "garbage report: node={} findings={} bytes={}",
+                report.node_id,
+                report.findings.len(),
+                report.garbage_bytes
+            );
+        }
+        Ok(report)
+    }
+
+    async fn unowned_data(
+        &self,
+        slot_id: u16,
+        owners: Vec<String>,
+        addresses: &HashMap<String, String>,
+        local_node_id: &str,
+    ) -> Result<GarbageFinding> {
+        let dir = self.slot(slot_id).await?.data_path.clone();
+        let (files, bytes) = dir_usage(&dir)?;
+
+        let mut remediation = Vec::new();
+        for owner in &owners {
+            let address = &addresses[owner];
+            remediation.push(format!(
+                "curl -s http://{}/_/api/v1/slots/{}",
+                address, slot_id
+            ));
+            remediation.push(format!(
+                "curl -X POST http://{}/internal/v1/slots/{}/transfer/pull -H 'content-type: application/json' -d '{{\"source_node_id\":\"{}\"}}'",
+                address, slot_id, local_node_id
+            ));
+        }
+        remediation.push(format!("rm -rf {}", dir.display()));
+
+        Ok(GarbageFinding {
+            kind: GarbageKind::UnownedData,
+            slot_id,
+            owners,
+            files,
+            bytes,
+            paths: vec![dir.display().to_string()],
+            remediation,
+        })
+    }
+
+    async fn slot(&self, slot_id: u16) -> Result<Arc<Slot>> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+        self.slot_manager.get_slot(slot_id).await
+    }
+}
+
+/// Part files of `slot` that none of its part entries point at. Staging
+/// files and recent parts are left out: a write may still index them.
+fn dangling_parts(slot: &Arc<Slot>, owners: Vec<String>) -> Result<Option<GarbageFinding>> {
+    let referenced = MetadataStore::new(slot.clone())?.part_external_paths()?;
+    let settled = SystemTime::now()
+        .checked_sub(DANGLING_PART_GRACE)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut finding = GarbageFinding {
+        kind: GarbageKind::DanglingParts,
+        slot_id: slot.slot_id,
+        owners,
+        files: 0,
+        bytes: 0,
+        paths: Vec::new(),
+        remediation: Vec::new(),
+    };
+    walk_files(&slot.parts_dir(), &mut |path, metadata| {
+        let staging = path.extension().is_some_and(|extension| extension == "tmp");
+        let recent = metadata.modified().is_ok_and(|modified| modified > settled);
+        let path = path.to_string_lossy();
+        if staging || recent || referenced.contains(path.as_ref()) {
+            return;
+        }
+        finding.files += 1;
+        finding.bytes += metadata.len();
+        if finding.paths.len() < GARBAGE_SAMPLE_PATHS {
+            finding.paths.push(path.into_owned());
+        }
+    })?;
+    if finding.files == 0 {
+        return Ok(None);
+    }
+
+    finding.remediation = finding
+        .paths
+        .iter()
+        .map(|path| format!("rm -- {}", path))
+        .collect();
+    if finding.files > finding.paths.len() as u64 {
+        finding.remediation.push(format!(
+            "run the report again for the other {} files",
+            finding.files - finding.paths.len() as u64
+        ));
+    }
+    Ok(Some(finding))
+}
+
+fn dir_usage(dir: &Path) -> Result<(u64, u64)> {
+    let (mut files, mut bytes) = (0, 0);
+    walk_files(dir, &mut |_, metadata| {
+        files += 1;
+        bytes += metadata.len();
+    })?;
+    Ok((files, bytes))
+}
+
+fn walk_files(dir: &Path, visit: &mut impl FnMut(&Path, &std::fs::Metadata)) -> Result<()> {
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                visit(&entry.path(), &metadata);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PartStore, compute_hash};
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn dangling_parts_skip_indexed_and_recent_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap());
+        slot_manager.init_slot(1).await.unwrap();
+        let part_store = PartStore::new(dir.path().to_path_buf()).unwrap();
+
+        let indexed = Bytes::from_static(b"indexed");
+        let indexed_path = part_store
+            .put_part(1, "a/b", 1, 0, &compute_hash(&indexed), indexed.clone())
+            .await
+            .unwrap()
+            .part_path;
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+        store
+            .upsert_part_entry(
+                "a/b",
+                1,
+                0,
+                &compute_hash(&indexed),
+                indexed.len() as u64,
+                Some(indexed_path.to_string_lossy().as_ref()),
+                None,
+            )
+            .unwrap();
+
+        let stray = Bytes::from_static(b"stray");
+        let stray_path = part_store
+            .put_part(1, "a/c", 1, 0, &compute_hash(&stray), stray.clone())
+            .await
+            .unwrap()
+            .part_path;
+
+        let slot = slot_manager.get_slot(1).await.unwrap();
+        let owners = vec!["node-a".to_string()];
+        assert!(dangling_parts(&slot, owners.clone()).unwrap().is_none());
+
+        let old = SystemTime::now() - 2 * DANGLING_PART_GRACE;
+        for path in [&indexed_path, &stray_path] {
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+        }
+        let finding = dangling_parts(&slot, owners).unwrap().unwrap();
+        assert_eq!(finding.files, 1);
+        assert_eq!(finding.bytes, stray.len() as u64);
+        assert_eq!(
+            finding.paths,
+            vec![stray_path.to_string_lossy().to_string()]
+        );
+        assert_eq!(finding.remediation.len(), 1);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{HeadKind, MetadataStore, Result, SlotManager};
+use serde::Deserialize;
 use std::collections::HashSet;
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
@@ -14,7 +15,7 @@ pub struct HealHeadsOperationRequest {
     pub prefixes: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct HealHeadItem {
     pub path: String,
     pub head_kind: String,
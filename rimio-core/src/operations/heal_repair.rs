@@ -1,7 +1,11 @@
 use crate::operations::read_blob::ReadBlobOperation;
-use crate::{Result, RimError};
+use crate::{MAX_HEAL_TOMBSTONE_PATHS, Result, normalize_blob_path};
+use std::collections::HashSet;
 use std::sync::Arc;
 
+/// Paths asked about per tombstone batch.
+const TOMBSTONE_BATCH_SIZE: usize = MAX_HEAL_TOMBSTONE_PATHS;
+
 #[derive(Clone)]
 pub struct HealRepairOperation {
     read_blob_operation: Arc<ReadBlobOperation>,
@@ -13,6 +17,10 @@ pub struct HealRepairOperationRequest {
     pub source_node_id: String,
     pub blob_paths: Vec<String>,
     pub dry_run: bool,
+    /// The slot's replica set in placement order, checked against the
+    /// replication policy of each path.
+    pub replicas: Vec<String>,
+    pub local_node_id: String,
 }
 
 #[derive(Debug, Clone)]
@@ -38,12 +46,25 @@ impl HealRepairOperation {
             source_node_id,
             blob_paths,
             dry_run,
+            replicas,
+            local_node_id,
         } = request;
+        let policy = self.read_blob_operation.replication_policy();
+        // Other replicas may hold parts too; the repair picks the cheapest
+        // source for each one.
+        let mut sources = vec![source_node_id.clone()];
+        sources.extend(
+            replicas
+                .iter()
+                .filter(|node_id| **node_id != source_node_id && **node_id != local_node_id)
+                .cloned(),
+        );
 
         let mut repaired_objects = 0usize;
         let mut skipped_objects = 0usize;
         let mut errors = Vec::new();
 
+        let mut pending = Vec::with_capacity(blob_paths.len());
         for raw_path in blob_paths {
             let path = match normalize_blob_path(&raw_path) {
                 Ok(path) => path,
@@ -54,11 +75,51 @@ impl HealRepairOperation {
                 }
             };
 
+            if !policy.holds(&path, &replicas, &local_node_id) {
+                skipped_objects += 1;
+                errors.push(format!("{}: not placed here by replication policy", path));
+                continue;
+            }
+
             if dry_run {
                 skipped_objects += 1;
                 continue;
             }
 
+            pending.push(path);
+        }
+
+        // Deleted paths take one round trip per batch rather than one per
+        // path; whatever the source could not batch goes path by path. A
+        // single path is cheaper to fetch directly.
+        let mut deleted = HashSet::new();
+        if pending.len() > 1 {
+            for batch in pending.chunks(TOMBSTONE_BATCH_SIZE) {
+                match self
+                    .read_blob_operation
+                    .apply_remote_tombstones(&source_node_id, slot_id, batch)
+                    .await
+                {
+                    Ok(paths) => deleted.extend(paths),
+                    Err(error) => {
+                        tracing::debug!(
+                            "tombstone batch unavailable, repairing path by path: slot={} source={} error={}",
+                            slot_id,
+                            source_node_id,
+                            error
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+        repaired_objects += deleted.len();
+
+        for path in pending {
+            if deleted.contains(&path) {
+                continue;
+            }
+
             let remote_head = match self
                 .read_blob_operation
                 .fetch_remote_head(&source_node_id, slot_id, &path)
@@ -79,7 +140,7 @@ impl HealRepairOperation {
 
             match self
                 .read_blob_operation
-                .repair_path_from_head(&source_node_id, slot_id, &path, &remote_head)
+                .repair_path_from_head(&sources, slot_id, &path, &remote_head)
                 .await
             {
                 Ok(_) => repaired_objects += 1,
@@ -97,25 +158,3 @@ impl HealRepairOperation {
         })
     }
 }
-
-fn normalize_blob_path(path: &str) -> Result<String> {
-    let trimmed = path.trim_matches('/');
-    if trimmed.is_empty() {
-        return Err(RimError::InvalidRequest(
-            "blob path cannot be empty".to_string(),
-        ));
-    }
-
-    let mut components = Vec::new();
-    for component in trimmed.split('/') {
-        if component.is_empty() || component == "." || component == ".." {
-            return Err(RimError::InvalidRequest(format!(
-                "invalid blob path component: {}",
-                component
-            )));
-        }
-        components.push(component);
-    }
-
-    Ok(components.join("/"))
-}
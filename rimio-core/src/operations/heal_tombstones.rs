@@ -0,0 +1,153 @@
+use crate::{HeadKind, MetadataStore, Result, RimError, SlotManager, TombstoneMeta};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Most paths one tombstone batch may ask about.
+pub const MAX_HEAL_TOMBSTONE_PATHS: usize = 1000;
+
+#[derive(Clone)]
+pub struct HealTombstonesOperation {
+    slot_manager: Arc<SlotManager>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealTombstonesOperationRequest {
+    pub slot_id: u16,
+    pub paths: Vec<String>,
+}
+
+/// A tombstone that is the current head of its path, with what a replica
+/// needs to apply it as is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealTombstoneItem {
+    pub path: String,
+    pub generation: i64,
+    pub head_sha256: String,
+    pub tombstone: TombstoneMeta,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealTombstonesOperationResult {
+    pub slot_id: u16,
+    pub tombstones: Vec<HealTombstoneItem>,
+}
+
+impl HealTombstonesOperation {
+    pub fn new(slot_manager: Arc<SlotManager>) -> Self {
+        Self { slot_manager }
+    }
+
+    /// Returns the paths among `paths` whose current head is a tombstone.
+    pub async fn run(
+        &self,
+        request: HealTombstonesOperationRequest,
+    ) -> Result<HealTombstonesOperationResult> {
+        let HealTombstonesOperationRequest { slot_id, paths } = request;
+        if paths.len() > MAX_HEAL_TOMBSTONE_PATHS {
+            return Err(RimError::InvalidRequest(format!(
+                "at most {} paths per tombstone batch",
+                MAX_HEAL_TOMBSTONE_PATHS
+            )));
+        }
+
+        let store = self.ensure_store(slot_id).await?;
+        let mut tombstones = Vec::new();
+        for path in paths {
+            let Some(head) = store.get_current_head(&path)? else {
+                continue;
+            };
+            if head.head_kind != HeadKind::Tombstone {
+                continue;
+            }
+            let Some(tombstone) = head.tombstone else {
+                continue;
+            };
+            tombstones.push(HealTombstoneItem {
+                path,
+                generation: head.generation,
+                head_sha256: head.head_sha256,
+                tombstone,
+            });
+        }
+
+        Ok(HealTombstonesOperationResult {
+            slot_id,
+            tombstones,
+        })
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlobMeta, ContentHeaders, PART_SIZE, PartIndexState};
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn only_deleted_paths_are_handed_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap());
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        for path in ["a", "b"] {
+            store
+                .upsert_meta(&BlobMeta {
+                    path: path.to_string(),
+                    slot_id: 1,
+                    generation: 1,
+                    version: 1,
+                    size_bytes: 0,
+                    etag: String::new(),
+                    part_size: PART_SIZE as u64,
+                    part_count: 0,
+                    part_index_state: PartIndexState::Complete,
+                    archive_url: None,
+                    updated_at: chrono::Utc::now(),
+                    metadata: BTreeMap::new(),
+                    tags: BTreeMap::new(),
+                    content: ContentHeaders::default(),
+                })
+                .unwrap();
+        }
+        store
+            .insert_tombstone(&TombstoneMeta {
+                path: "b".to_string(),
+                slot_id: 1,
+                generation: 2,
+                deleted_at: chrono::Utc::now(),
+                reason: "delete".to_string(),
+            })
+            .unwrap();
+
+        let operation = HealTombstonesOperation::new(slot_manager);
+        let result = operation
+            .run(HealTombstonesOperationRequest {
+                slot_id: 1,
+                paths: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.tombstones.len(), 1);
+        assert_eq!(result.tombstones[0].path, "b");
+        assert_eq!(result.tombstones[0].generation, 2);
+
+        let too_many = operation
+            .run(HealTombstonesOperationRequest {
+                slot_id: 1,
+                paths: vec![String::new(); MAX_HEAL_TOMBSTONE_PATHS + 1],
+            })
+            .await;
+        assert!(matches!(too_many, Err(RimError::InvalidRequest(_))));
+    }
+}
@@ -24,6 +24,13 @@ pub struct InternalPartPayload {
     pub sha256: String,
 }
 
+/// What a probe found out about a part without reading it.
+#[derive(Debug, Clone)]
+pub struct InternalPartProbe {
+    pub sha256: String,
+    pub size_bytes: u64,
+}
+
 #[derive(Debug, Clone)]
 pub enum InternalGetPartOperationOutcome {
     Found(InternalPartPayload),
@@ -122,6 +129,74 @@ impl InternalGetPartOperation {
         ))
     }
 
+    /// Looks the part up like [`Self::run`] but only checks that its bytes
+    /// are on disk, so callers can learn whether a transfer is needed.
+    pub async fn probe(
+        &self,
+        request: InternalGetPartOperationRequest,
+    ) -> Result<Option<InternalPartProbe>> {
+        let InternalGetPartOperationRequest {
+            slot_id,
+            sha256,
+            path,
+            generation,
+            part_no,
+        } = request;
+
+        let store = self.ensure_store(slot_id).await?;
+
+        if let (Some(path), Some(generation), Some(part_no)) =
+            (path.as_deref(), generation, part_no)
+        {
+            if let Some(entry) = store.get_part_entry(path, generation, part_no)? {
+                let stored =
+                    self.part_store
+                        .part_exists(slot_id, path, generation, part_no, &entry.sha256);
+                let external = entry
+                    .external_path
+                    .as_deref()
+                    .is_some_and(|external_path| Path::new(external_path).exists());
+                if stored || external {
+                    return Ok(Some(InternalPartProbe {
+                        sha256: entry.sha256,
+                        size_bytes: entry.size_bytes,
+                    }));
+                }
+            }
+
+            if let Some(sha256) = normalized_sha256(sha256.as_deref())
+                && let Some(part_path) = self
+                    .part_store
+                    .part_path(slot_id, path, generation, part_no, sha256)
+                    .ok()
+                    .filter(|part_path| part_path.exists())
+            {
+                return Ok(Some(InternalPartProbe {
+                    sha256: sha256.to_string(),
+                    size_bytes: file_len(&part_path).await,
+                }));
+            }
+
+            return Ok(None);
+        }
+
+        let Some(lookup_sha) = normalized_sha256(sha256.as_deref()) else {
+            return Ok(None);
+        };
+        let Some(external_path) = store.find_part_external_path(lookup_sha, path.as_deref())?
+        else {
+            return Ok(None);
+        };
+        if !Path::new(&external_path).exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(InternalPartProbe {
+            sha256: lookup_sha.to_string(),
+            size_bytes: file_len(Path::new(&external_path)).await,
+        }))
+    }
+
     async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
         if !self.slot_manager.has_slot(slot_id).await {
             self.slot_manager.init_slot(slot_id).await?;
@@ -132,6 +207,13 @@ impl InternalGetPartOperation {
     }
 }
 
+async fn file_len(path: &Path) -> u64 {
+    tokio::fs::metadata(path)
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
+
 fn normalized_sha256(value: Option<&str>) -> Option<&str> {
     let value = value?;
     let trimmed = value.trim();
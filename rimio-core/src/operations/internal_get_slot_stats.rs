@@ -0,0 +1,32 @@
+use crate::{MetadataStore, Result, SlotManager, SlotStats};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct InternalGetSlotStatsOperation {
+    slot_manager: Arc<SlotManager>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InternalGetSlotStatsOperationRequest {
+    pub slot_id: u16,
+}
+
+impl InternalGetSlotStatsOperation {
+    pub fn new(slot_manager: Arc<SlotManager>) -> Self {
+        Self { slot_manager }
+    }
+
+    pub async fn run(&self, request: InternalGetSlotStatsOperationRequest) -> Result<SlotStats> {
+        let store = self.ensure_store(request.slot_id).await?;
+        store.slot_stats()
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
@@ -1,10 +1,14 @@
-use crate::{BlobMeta, MetadataStore, Result, RimError, SlotManager, TombstoneMeta, compute_hash};
-use chrono::Utc;
+use crate::{
+    BlobMeta, HeadWrite, MetadataStore, Result, RimError, SharedClock, SlotManager, TombstoneMeta,
+    compute_hash, system_clock,
+};
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct InternalPutHeadOperation {
     slot_manager: Arc<SlotManager>,
+    clock: SharedClock,
 }
 
 #[derive(Debug, Clone)]
@@ -26,7 +30,16 @@ pub struct InternalPutHeadOperationResult {
 
 impl InternalPutHeadOperation {
     pub fn new(slot_manager: Arc<SlotManager>) -> Self {
-        Self { slot_manager }
+        Self {
+            slot_manager,
+            clock: system_clock(),
+        }
+    }
+
+    /// Stamps received heads with the local time of `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
     }
 
     pub async fn run(
@@ -44,69 +57,38 @@ impl InternalPutHeadOperation {
         } = request;
 
         let store = self.ensure_store(slot_id).await?;
-
-        match head_kind.as_str() {
-            "meta" => {
-                let mut meta = meta.ok_or_else(|| {
-                    RimError::InvalidRequest("meta payload is required".to_string())
-                })?;
-
-                if let Some(path) = query_path {
-                    meta.path = path;
-                }
-
-                meta.slot_id = slot_id;
-                meta.generation = generation;
-                if meta.version == 0 {
-                    meta.version = meta.generation;
-                }
-                meta.updated_at = Utc::now();
-
-                let inline_data = serde_json::to_vec(&meta)?;
-                let head_sha = if head_sha256.is_empty() {
-                    compute_hash(&inline_data)
-                } else {
-                    head_sha256
-                };
-
-                store.upsert_meta_with_payload(&meta, &inline_data, &head_sha)?;
-
-                Ok(InternalPutHeadOperationResult {
-                    head_kind: "meta".to_string(),
-                    generation: meta.generation,
-                })
+        let write = build_head_write(
+            slot_id,
+            query_path,
+            &head_kind,
+            generation,
+            head_sha256,
+            meta,
+            tombstone,
+            self.clock.now(),
+        )?;
+
+        match &write {
+            HeadWrite::Meta {
+                meta,
+                inline_data,
+                head_sha256,
+            } => {
+                store.upsert_meta_with_payload(meta, inline_data, head_sha256)?;
             }
-            "tombstone" => {
-                let mut tombstone = tombstone.ok_or_else(|| {
-                    RimError::InvalidRequest("tombstone payload is required".to_string())
-                })?;
-
-                if let Some(path) = query_path {
-                    tombstone.path = path;
-                }
-
-                tombstone.slot_id = slot_id;
-                tombstone.generation = generation;
-                tombstone.deleted_at = Utc::now();
-
-                let inline_data = serde_json::to_vec(&tombstone)?;
-                let head_sha = if head_sha256.is_empty() {
-                    compute_hash(&inline_data)
-                } else {
-                    head_sha256
-                };
-
-                store.insert_tombstone_with_payload(&tombstone, &inline_data, &head_sha)?;
-
-                Ok(InternalPutHeadOperationResult {
-                    head_kind: "tombstone".to_string(),
-                    generation: tombstone.generation,
-                })
+            HeadWrite::Tombstone {
+                tombstone,
+                inline_data,
+                head_sha256,
+            } => {
+                store.insert_tombstone_with_payload(tombstone, inline_data, head_sha256)?;
             }
-            _ => Err(RimError::InvalidRequest(
-                "head_kind must be meta or tombstone".to_string(),
-            )),
         }
+
+        Ok(InternalPutHeadOperationResult {
+            head_kind: head_kind_name(&write).to_string(),
+            generation: write.generation(),
+        })
     }
 
     async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
@@ -118,3 +100,84 @@ impl InternalPutHeadOperation {
         MetadataStore::new(slot)
     }
 }
+
+/// Normalizes a replicated head payload into a write for the local slot,
+/// stamped with `now`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn build_head_write(
+    slot_id: u16,
+    path: Option<String>,
+    head_kind: &str,
+    generation: i64,
+    head_sha256: String,
+    meta: Option<BlobMeta>,
+    tombstone: Option<TombstoneMeta>,
+    now: DateTime<Utc>,
+) -> Result<HeadWrite> {
+    match head_kind {
+        "meta" => {
+            let mut meta = meta
+                .ok_or_else(|| RimError::InvalidRequest("meta payload is required".to_string()))?;
+
+            if let Some(path) = path {
+                meta.path = path;
+            }
+
+            meta.slot_id = slot_id;
+            meta.generation = generation;
+            if meta.version == 0 {
+                meta.version = meta.generation;
+            }
+            meta.updated_at = now;
+
+            let inline_data = serde_json::to_vec(&meta)?;
+            let head_sha256 = if head_sha256.is_empty() {
+                compute_hash(&inline_data)
+            } else {
+                head_sha256
+            };
+
+            Ok(HeadWrite::Meta {
+                meta,
+                inline_data,
+                head_sha256,
+            })
+        }
+        "tombstone" => {
+            let mut tombstone = tombstone.ok_or_else(|| {
+                RimError::InvalidRequest("tombstone payload is required".to_string())
+            })?;
+
+            if let Some(path) = path {
+                tombstone.path = path;
+            }
+
+            tombstone.slot_id = slot_id;
+            tombstone.generation = generation;
+            tombstone.deleted_at = now;
+
+            let inline_data = serde_json::to_vec(&tombstone)?;
+            let head_sha256 = if head_sha256.is_empty() {
+                compute_hash(&inline_data)
+            } else {
+                head_sha256
+            };
+
+            Ok(HeadWrite::Tombstone {
+                tombstone,
+                inline_data,
+                head_sha256,
+            })
+        }
+        _ => Err(RimError::InvalidRequest(
+            "head_kind must be meta or tombstone".to_string(),
+        )),
+    }
+}
+
+pub(crate) fn head_kind_name(write: &HeadWrite) -> &'static str {
+    match write {
+        HeadWrite::Meta { .. } => "meta",
+        HeadWrite::Tombstone { .. } => "tombstone",
+    }
+}
@@ -0,0 +1,95 @@
+use super::internal_put_head::build_head_write;
+use crate::{
+    BlobMeta, MetadataStore, Result, RimError, SharedClock, SlotManager, TombstoneMeta,
+    system_clock,
+};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct InternalPutHeadBatchOperation {
+    slot_manager: Arc<SlotManager>,
+    clock: SharedClock,
+}
+
+#[derive(Debug, Clone)]
+pub struct InternalPutHeadBatchItem {
+    pub path: String,
+    pub head_kind: String,
+    pub generation: i64,
+    pub head_sha256: String,
+    pub meta: Option<BlobMeta>,
+    pub tombstone: Option<TombstoneMeta>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InternalPutHeadBatchOperationRequest {
+    pub slot_id: u16,
+    pub heads: Vec<InternalPutHeadBatchItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InternalPutHeadBatchOperationResult {
+    pub applied: bool,
+    pub head_count: usize,
+}
+
+impl InternalPutHeadBatchOperation {
+    pub fn new(slot_manager: Arc<SlotManager>) -> Self {
+        Self {
+            slot_manager,
+            clock: system_clock(),
+        }
+    }
+
+    /// Stamps received heads with the local time of `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub async fn run(
+        &self,
+        request: InternalPutHeadBatchOperationRequest,
+    ) -> Result<InternalPutHeadBatchOperationResult> {
+        let InternalPutHeadBatchOperationRequest { slot_id, heads } = request;
+
+        if heads.is_empty() {
+            return Err(RimError::InvalidRequest(
+                "head batch cannot be empty".to_string(),
+            ));
+        }
+
+        let store = self.ensure_store(slot_id).await?;
+
+        let now = self.clock.now();
+        let mut writes = Vec::with_capacity(heads.len());
+        for item in heads {
+            writes.push(build_head_write(
+                slot_id,
+                Some(item.path),
+                &item.head_kind,
+                item.generation,
+                item.head_sha256,
+                item.meta,
+                item.tombstone,
+                now,
+            )?);
+        }
+
+        let applied = store.apply_head_batch(&writes, false)?;
+
+        Ok(InternalPutHeadBatchOperationResult {
+            applied,
+            head_count: writes.len(),
+        })
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{MetadataStore, PartStore, Result, RimError, SlotManager, compute_hash};
+use super::put_blob::link_parts;
+use crate::{MetadataStore, PartSource, PartStore, Result, RimError, SlotManager, compute_hash};
 use bytes::Bytes;
 use std::sync::Arc;
 
@@ -18,6 +19,15 @@ pub struct InternalPutPartOperationRequest {
     pub body: Bytes,
 }
 
+/// Reuses the parts of `source` for `generation` of `path`, in one slot.
+#[derive(Debug, Clone)]
+pub struct InternalLinkPartsOperationRequest {
+    pub slot_id: u16,
+    pub source: PartSource,
+    pub path: String,
+    pub generation: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct InternalPutPartOperationResult {
     pub reused: bool,
@@ -76,6 +86,13 @@ impl InternalPutPartOperation {
         })
     }
 
+    /// Indexes the parts this node holds for the source version under the
+    /// new one. Returns `false` when it does not hold them all.
+    pub async fn link(&self, request: InternalLinkPartsOperationRequest) -> Result<bool> {
+        let store = self.ensure_store(request.slot_id).await?;
+        link_parts(&store, &request.source, &request.path, request.generation)
+    }
+
     async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
         if !self.slot_manager.has_slot(slot_id).await {
             self.slot_manager.init_slot(slot_id).await?;
@@ -1,5 +1,6 @@
-use crate::{HeadKind, MetadataStore, Result, SlotManager};
-use chrono::Utc;
+use crate::{BlobHead, HeadKind, MetadataStore, Result, RimError, SlotManager};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashSet};
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -13,6 +14,65 @@ pub struct ListBlobsOperationRequest {
     pub limit: usize,
     pub cursor: Option<String>,
     pub include_deleted: bool,
+    /// Only list blobs carrying every one of these tags; deleted blobs never
+    /// match a non-empty filter.
+    pub tags: BTreeMap<String, String>,
+    pub order_by: ListOrder,
+    /// Only list heads written at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only list heads written before this time.
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// How a listing orders its items, which also decides what the cursor holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListOrder {
+    /// By path; the cursor is the last path listed.
+    #[default]
+    Path,
+    /// By the time the current head was written, oldest first, then by path;
+    /// the cursor is `<updated_at>|<path>` of the last item.
+    UpdatedAt,
+}
+
+impl ListOrder {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "path" => Ok(Self::Path),
+            "updated_at" => Ok(Self::UpdatedAt),
+            other => Err(RimError::InvalidRequest(format!(
+                "unsupported list order: {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl ListBlobsOperationRequest {
+    /// Rejects a cursor that does not fit `order_by` and an empty time
+    /// window, so callers can answer 400 before streaming anything.
+    pub fn validate(&self) -> Result<()> {
+        if let (Some(since), Some(until)) = (self.since, self.until)
+            && since >= until
+        {
+            return Err(RimError::InvalidRequest(
+                "since must be earlier than until".to_string(),
+            ));
+        }
+        if self.order_by == ListOrder::UpdatedAt
+            && let Some(cursor) = self.cursor.as_deref()
+        {
+            parse_updated_at_cursor(cursor)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -23,11 +83,14 @@ pub struct ListBlobItem {
     pub size_bytes: u64,
     pub deleted: bool,
     pub updated_at: chrono::DateTime<Utc>,
+    pub tags: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ListBlobsOperationResult {
     pub items: Vec<ListBlobItem>,
+    /// Where the next page starts: the sort key of the last item, which
+    /// listings never repeat within a page walk. `None` once nothing is left.
     pub next_cursor: Option<String>,
 }
 
@@ -40,12 +103,18 @@ impl ListBlobsOperation {
         &self,
         request: ListBlobsOperationRequest,
     ) -> Result<ListBlobsOperationResult> {
+        request.validate()?;
         let ListBlobsOperationRequest {
             prefix,
             limit,
             cursor,
             include_deleted,
+            tags,
+            order_by,
+            since,
+            until,
         } = request;
+        let filter = HeadFilter { tags, since, until };
 
         let slots = self.slot_manager.get_assigned_slots().await;
         let mut heads = Vec::new();
@@ -64,11 +133,14 @@ impl ListBlobsOperation {
                 }
             };
 
-            let list = match store.list_heads(
+            let list = match list_slot_heads(
+                &store,
                 &prefix,
-                limit.saturating_mul(2),
+                limit,
                 include_deleted,
+                order_by,
                 cursor.as_deref(),
+                &filter,
             ) {
                 Ok(list) => list,
                 Err(error) => {
@@ -80,12 +152,24 @@ impl ListBlobsOperation {
             heads.extend(list);
         }
 
-        heads.sort_by(|a, b| a.path.cmp(&b.path));
-        heads.dedup_by(|a, b| a.path == b.path);
+        match order_by {
+            ListOrder::Path => {
+                heads.sort_by(|a, b| a.path.cmp(&b.path));
+                heads.dedup_by(|a, b| a.path == b.path);
+            }
+            ListOrder::UpdatedAt => {
+                heads.sort_by(|a, b| (a.updated_at, &a.path).cmp(&(b.updated_at, &b.path)));
+                let mut seen = HashSet::new();
+                heads.retain(|head| seen.insert(head.path.clone()));
+            }
+        }
+        // Every slot read past `limit` heads unless it ran out, so more than
+        // `limit` heads in total is exactly when another page exists.
+        let truncated = heads.len() > limit;
 
         let mut items = Vec::new();
         for head in heads.into_iter().take(limit) {
-            let (etag, size_bytes, deleted) = match head.head_kind {
+            let (etag, size_bytes, deleted, tags) = match head.head_kind {
                 HeadKind::Meta => {
                     let meta = head.meta.clone();
                     (
@@ -94,9 +178,10 @@ impl ListBlobsOperation {
                             .unwrap_or_default(),
                         meta.as_ref().map(|item| item.size_bytes).unwrap_or(0),
                         false,
+                        meta.map(|item| item.tags).unwrap_or_default(),
                     )
                 }
-                HeadKind::Tombstone => (String::new(), 0, true),
+                HeadKind::Tombstone => (String::new(), 0, true, BTreeMap::new()),
             };
 
             items.push(ListBlobItem {
@@ -106,11 +191,290 @@ impl ListBlobsOperation {
                 size_bytes,
                 deleted,
                 updated_at: head.updated_at,
+                tags,
             });
         }
 
-        let next_cursor = items.last().map(|item| item.path.clone());
+        let next_cursor = if truncated {
+            items.last().map(|item| match order_by {
+                ListOrder::Path => item.path.clone(),
+                ListOrder::UpdatedAt => format!("{}|{}", item.updated_at.to_rfc3339(), item.path),
+            })
+        } else {
+            None
+        };
 
         Ok(ListBlobsOperationResult { items, next_cursor })
     }
 }
+
+/// What a head must match to be listed besides the prefix.
+struct HeadFilter {
+    tags: BTreeMap<String, String>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+}
+
+impl HeadFilter {
+    fn matches(&self, head: &BlobHead) -> bool {
+        self.since.is_none_or(|since| head.updated_at >= since)
+            && self.until.is_none_or(|until| head.updated_at < until)
+            && has_tags(head, &self.tags)
+    }
+}
+
+/// Reads up to twice `limit` heads of one slot past `cursor`. With a filter
+/// the index cannot answer it keeps paging until more than `limit` heads
+/// match or the slot is exhausted, so a sparse match further on is not cut
+/// off by the window.
+fn list_slot_heads(
+    store: &MetadataStore,
+    prefix: &str,
+    limit: usize,
+    include_deleted: bool,
+    order_by: ListOrder,
+    cursor: Option<&str>,
+    filter: &HeadFilter,
+) -> Result<Vec<BlobHead>> {
+    let window = limit.saturating_mul(2);
+    let page = |cursor: Option<&BlobHead>, first: Option<&str>| -> Result<Vec<BlobHead>> {
+        match order_by {
+            ListOrder::Path => {
+                let after = cursor.map(|head| head.path.as_str()).or(first);
+                store.list_heads(prefix, window, include_deleted, after)
+            }
+            ListOrder::UpdatedAt => {
+                let after = match cursor {
+                    Some(head) => Some((head.updated_at, head.path.clone())),
+                    None => first.map(parse_updated_at_cursor).transpose()?,
+                };
+                store.list_heads_by_updated_at(
+                    prefix,
+                    window,
+                    include_deleted,
+                    after.as_ref().map(|(at, path)| (*at, path.as_str())),
+                    filter.since,
+                    filter.until,
+                )
+            }
+        }
+    };
+
+    let indexed =
+        order_by == ListOrder::UpdatedAt || (filter.since.is_none() && filter.until.is_none());
+    if filter.tags.is_empty() && indexed {
+        return page(None, cursor);
+    }
+
+    let mut matched = Vec::new();
+    let mut last: Option<BlobHead> = None;
+    loop {
+        let heads = page(last.as_ref(), cursor)?;
+        let exhausted = heads.len() < window;
+        last = heads.last().cloned();
+        matched.extend(heads.into_iter().filter(|head| filter.matches(head)));
+        if exhausted || matched.len() > limit {
+            return Ok(matched);
+        }
+    }
+}
+
+fn has_tags(head: &BlobHead, tags: &BTreeMap<String, String>) -> bool {
+    tags.is_empty()
+        || head.meta.as_ref().is_some_and(|meta| {
+            tags.iter()
+                .all(|(key, value)| meta.tags.get(key) == Some(value))
+        })
+}
+
+fn parse_updated_at_cursor(cursor: &str) -> Result<(DateTime<Utc>, String)> {
+    cursor
+        .split_once('|')
+        .and_then(|(updated_at, path)| {
+            let updated_at = DateTime::parse_from_rfc3339(updated_at).ok()?;
+            Some((updated_at.with_timezone(&Utc), path.to_string()))
+        })
+        .ok_or_else(|| RimError::InvalidRequest(format!("invalid list cursor: {}", cursor)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlobMeta, Clock, ContentHeaders, PartIndexState, compute_hash};
+
+    fn meta(path: &str, slot_id: u16) -> BlobMeta {
+        BlobMeta {
+            path: path.to_string(),
+            slot_id,
+            generation: 1,
+            version: 1,
+            size_bytes: 0,
+            etag: compute_hash(b""),
+            part_size: 1024,
+            part_count: 0,
+            part_index_state: PartIndexState::Complete,
+            archive_url: None,
+            updated_at: Utc::now(),
+            metadata: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            content: ContentHeaders::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_end_with_no_cursor_and_skip_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap());
+        for slot_id in [1, 2] {
+            slot_manager.init_slot(slot_id).await.unwrap();
+        }
+        let paths: Vec<String> = (0..6).map(|index| format!("logs/{}", index)).collect();
+        for (index, path) in paths.iter().enumerate() {
+            let slot_id = 1 + (index % 2) as u16;
+            let store = MetadataStore::new(slot_manager.get_slot(slot_id).await.unwrap()).unwrap();
+            store.upsert_meta(&meta(path, slot_id)).unwrap();
+        }
+        let operation = ListBlobsOperation::new(slot_manager);
+
+        let mut listed = Vec::new();
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let page = operation
+                .run(ListBlobsOperationRequest {
+                    prefix: "logs/".to_string(),
+                    limit: 3,
+                    cursor,
+                    include_deleted: false,
+                    tags: BTreeMap::new(),
+                    order_by: ListOrder::Path,
+                    since: None,
+                    until: None,
+                })
+                .await
+                .unwrap();
+            pages += 1;
+            listed.extend(page.items.into_iter().map(|item| item.path));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        assert_eq!(listed, paths);
+        assert_eq!(pages, 2);
+    }
+
+    #[tokio::test]
+    async fn updated_at_order_lists_rewrites_last_and_honours_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap());
+        for slot_id in [1, 2] {
+            slot_manager.init_slot(slot_id).await.unwrap();
+        }
+        let writes = [("logs/a", 1), ("logs/b", 2), ("logs/c", 1), ("logs/a", 1)];
+        for (generation, (path, slot_id)) in writes.into_iter().enumerate() {
+            let store = MetadataStore::new(slot_manager.get_slot(slot_id).await.unwrap()).unwrap();
+            let mut meta = meta(path, slot_id);
+            meta.generation = 1 + generation as i64;
+            store.upsert_meta(&meta).unwrap();
+        }
+        let operation = ListBlobsOperation::new(slot_manager);
+        let request = |cursor, since| ListBlobsOperationRequest {
+            prefix: "logs/".to_string(),
+            limit: 2,
+            cursor,
+            include_deleted: false,
+            tags: BTreeMap::new(),
+            order_by: ListOrder::UpdatedAt,
+            since,
+            until: None,
+        };
+
+        let first = operation.run(request(None, None)).await.unwrap();
+        let paths: Vec<&str> = first.items.iter().map(|item| item.path.as_str()).collect();
+        assert_eq!(paths, ["logs/b", "logs/c"]);
+        let second = operation
+            .run(request(first.next_cursor.clone(), None))
+            .await
+            .unwrap();
+        let paths: Vec<&str> = second.items.iter().map(|item| item.path.as_str()).collect();
+        assert_eq!(paths, ["logs/a"]);
+        assert!(second.next_cursor.is_none());
+
+        let since = Some(first.items[1].updated_at);
+        let recent = operation.run(request(None, since)).await.unwrap();
+        let paths: Vec<&str> = recent.items.iter().map(|item| item.path.as_str()).collect();
+        assert_eq!(paths, ["logs/c", "logs/a"]);
+
+        let bad_cursor = operation
+            .run(request(Some("logs/a".to_string()), None))
+            .await;
+        assert!(matches!(bad_cursor, Err(RimError::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn time_window_bounds_listing_in_either_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap());
+        slot_manager.init_slot(1).await.unwrap();
+        let start = DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let clock = Arc::new(crate::ManualClock::new(start));
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+        for path in ["logs/d", "logs/c", "logs/b", "logs/a"] {
+            store
+                .upsert_meta(&BlobMeta {
+                    updated_at: clock.now(),
+                    ..meta(path, 1)
+                })
+                .unwrap();
+            clock.advance(chrono::Duration::minutes(1));
+        }
+        let operation = ListBlobsOperation::new(slot_manager);
+        let request = |order_by, since, until| ListBlobsOperationRequest {
+            prefix: "logs/".to_string(),
+            limit: 10,
+            cursor: None,
+            include_deleted: false,
+            tags: BTreeMap::new(),
+            order_by,
+            since,
+            until,
+        };
+        let since = Some(start + chrono::Duration::minutes(1));
+        let until = Some(start + chrono::Duration::minutes(3));
+
+        let by_path = operation
+            .run(request(ListOrder::Path, since, until))
+            .await
+            .unwrap();
+        let paths: Vec<&str> = by_path
+            .items
+            .iter()
+            .map(|item| item.path.as_str())
+            .collect();
+        assert_eq!(paths, ["logs/b", "logs/c"]);
+        assert!(by_path.next_cursor.is_none());
+
+        let by_time = operation
+            .run(request(ListOrder::UpdatedAt, since, until))
+            .await
+            .unwrap();
+        let paths: Vec<&str> = by_time
+            .items
+            .iter()
+            .map(|item| item.path.as_str())
+            .collect();
+        assert_eq!(paths, ["logs/c", "logs/b"]);
+
+        let empty = operation.run(request(ListOrder::Path, until, until)).await;
+        assert!(matches!(empty, Err(RimError::InvalidRequest(_))));
+    }
+}
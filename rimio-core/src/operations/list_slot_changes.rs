@@ -0,0 +1,42 @@
+use crate::{MetadataStore, Result, RimError, SlotChange, SlotManager};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct ListSlotChangesOperation {
+    slot_manager: Arc<SlotManager>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListSlotChangesOperationRequest {
+    pub slot_id: u16,
+    /// Only changes with a larger `seq`; 0 starts from the beginning.
+    pub since: i64,
+    pub limit: usize,
+}
+
+impl ListSlotChangesOperation {
+    pub fn new(slot_manager: Arc<SlotManager>) -> Self {
+        Self { slot_manager }
+    }
+
+    /// Reads the change feed of a slot this node holds. The feed is local to
+    /// the replica: another replica numbers the same changes differently.
+    pub async fn run(&self, request: ListSlotChangesOperationRequest) -> Result<Vec<SlotChange>> {
+        if !self.slot_manager.has_slot(request.slot_id).await {
+            return Err(RimError::SlotNotFound(request.slot_id));
+        }
+
+        let slot = self.slot_manager.get_slot(request.slot_id).await?;
+        MetadataStore::new(slot)?.list_changes(request.since, request.limit)
+    }
+
+    /// Where a reader that skips the history of a slot starts.
+    pub async fn latest_seq(&self, slot_id: u16) -> Result<i64> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            return Err(RimError::SlotNotFound(slot_id));
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)?.latest_change_seq()
+    }
+}
@@ -1,20 +1,53 @@
+pub mod apply_lifecycle;
+pub mod commit_batch;
+pub mod decommission_node;
 pub mod delete_blob;
+pub mod delete_prefix;
+pub mod garbage_report;
 pub mod heal_heads;
 pub mod heal_repair;
 pub mod heal_slotlets;
+pub mod heal_tombstones;
 pub mod init_cluster;
 pub mod internal_get_head;
 pub mod internal_get_part;
+pub mod internal_get_slot_stats;
 pub mod internal_put_head;
+pub mod internal_put_head_batch;
 pub mod internal_put_part;
 pub mod list_blobs;
+pub mod list_slot_changes;
+pub mod prune_versions;
 pub mod put_blob;
+pub mod put_blob_tags;
 pub mod read_blob;
+pub mod rename_blob;
+pub mod slot_transfer;
+pub mod verify_head_chain;
 
+pub use apply_lifecycle::{
+    ApplyLifecycleOperation, ApplyLifecycleOperationRequest, ApplyLifecycleOperationResult,
+    LifecycleRule, validate_lifecycle_rules,
+};
+pub use commit_batch::{
+    CommitBatchEntry, CommitBatchItem, CommitBatchOperation, CommitBatchOperationOutcome,
+    CommitBatchOperationRequest, CommitBatchOperationResult,
+};
+pub use decommission_node::{
+    DecommissionNodeOperation, DecommissionNodeOperationRequest, DecommissionNodeOperationResult,
+};
 pub use delete_blob::{
     DeleteBlobOperation, DeleteBlobOperationOutcome, DeleteBlobOperationRequest,
     DeleteBlobOperationResult,
 };
+pub use delete_prefix::{
+    DEFAULT_DELETE_PREFIX_PAGE_SIZE, DeletePrefixOperation, DeletePrefixOperationRequest,
+    DeletePrefixOperationResult, DeletePrefixProgress, DeletePrefixSlot, DeletePrefixSlotReport,
+};
+pub use garbage_report::{
+    GARBAGE_SAMPLE_PATHS, GarbageFinding, GarbageKind, GarbageReport, GarbageReportOperation,
+    GarbageReportOperationRequest,
+};
 pub use heal_heads::{
     HealHeadItem, HealHeadsOperation, HealHeadsOperationRequest, HealHeadsOperationResult,
 };
@@ -23,6 +56,10 @@ pub use heal_slotlets::{
     HealSlotletItem, HealSlotletsOperation, HealSlotletsOperationRequest,
     HealSlotletsOperationResult,
 };
+pub use heal_tombstones::{
+    HealTombstoneItem, HealTombstonesOperation, HealTombstonesOperationRequest,
+    HealTombstonesOperationResult, MAX_HEAL_TOMBSTONE_PATHS,
+};
 pub use init_cluster::InitClusterOperation;
 pub use internal_get_head::{
     InternalGetHeadOperation, InternalGetHeadOperationOutcome, InternalGetHeadOperationRequest,
@@ -30,22 +67,49 @@ pub use internal_get_head::{
 };
 pub use internal_get_part::{
     InternalGetPartOperation, InternalGetPartOperationOutcome, InternalGetPartOperationRequest,
-    InternalPartPayload,
+    InternalPartPayload, InternalPartProbe,
+};
+pub use internal_get_slot_stats::{
+    InternalGetSlotStatsOperation, InternalGetSlotStatsOperationRequest,
 };
 pub use internal_put_head::{
     InternalPutHeadOperation, InternalPutHeadOperationRequest, InternalPutHeadOperationResult,
 };
+pub use internal_put_head_batch::{
+    InternalPutHeadBatchItem, InternalPutHeadBatchOperation, InternalPutHeadBatchOperationRequest,
+    InternalPutHeadBatchOperationResult,
+};
 pub use internal_put_part::{
-    InternalPutPartOperation, InternalPutPartOperationRequest, InternalPutPartOperationResult,
+    InternalLinkPartsOperationRequest, InternalPutPartOperation, InternalPutPartOperationRequest,
+    InternalPutPartOperationResult,
 };
 pub use list_blobs::{
     ListBlobItem, ListBlobsOperation, ListBlobsOperationRequest, ListBlobsOperationResult,
+    ListOrder,
+};
+pub use list_slot_changes::{ListSlotChangesOperation, ListSlotChangesOperationRequest};
+pub use prune_versions::{
+    PruneVersionsOperation, PruneVersionsOperationRequest, PruneVersionsOperationResult,
 };
 pub use put_blob::{
-    PutBlobArchiveWriter, PutBlobOperation, PutBlobOperationOutcome, PutBlobOperationRequest,
-    PutBlobOperationResult,
+    MAX_BLOB_TAGS, MAX_USER_METADATA_BYTES, PutBlobArchiveWriter, PutBlobOperation,
+    PutBlobOperationOutcome, PutBlobOperationRequest, PutBlobOperationResult, PutBlobStreamRequest,
+    WriteConsistency, is_archived_part_url, validate_blob_tags, validate_user_metadata,
+};
+pub use put_blob_tags::{
+    PutBlobTagsOperation, PutBlobTagsOperationOutcome, PutBlobTagsOperationRequest,
 };
 pub use read_blob::{
+    ReadBlobManifest, ReadBlobManifestNode, ReadBlobManifestOutcome, ReadBlobManifestPart,
     ReadBlobOperation, ReadBlobOperationOutcome, ReadBlobOperationRequest, ReadBlobOperationResult,
-    ReadByteRange,
+    ReadBlobStream, ReadBlobStreamOutcome, ReadByteRange, ReadConsistency, ReadRangeSpec,
+    WideProbeMode,
+};
+pub use rename_blob::{
+    RenameBlobOperation, RenameBlobOperationOutcome, RenameBlobOperationRequest,
+    RenameBlobOperationResult, RenameBlobTarget,
+};
+pub use slot_transfer::{
+    SlotTransferOperation, SlotTransferOperationRequest, SlotTransferOperationResult,
 };
+pub use verify_head_chain::{VerifyHeadChainOperation, VerifyHeadChainOperationRequest};
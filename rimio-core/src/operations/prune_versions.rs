@@ -0,0 +1,131 @@
+use crate::{
+    MetadataStore, Result, SharedClock, SlotManager, VersionRetention, shred_part_file,
+    system_clock,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct PruneVersionsOperation {
+    slot_manager: Arc<SlotManager>,
+    retention: VersionRetention,
+    secure_delete: Vec<String>,
+    clock: SharedClock,
+}
+
+#[derive(Debug, Clone)]
+pub struct PruneVersionsOperationRequest {
+    pub slot_id: u16,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PruneVersionsOperationResult {
+    pub slot_id: u16,
+    pub dry_run: bool,
+    pub pruned_versions: u64,
+    pub pruned_paths: u64,
+    pub removed_parts: u64,
+    /// Removed parts that were overwritten first under the secure-delete
+    /// prefixes.
+    #[serde(default)]
+    pub shredded_parts: u64,
+    pub freed_bytes: u64,
+}
+
+impl PruneVersionsOperation {
+    pub fn new(slot_manager: Arc<SlotManager>, retention: VersionRetention) -> Self {
+        Self {
+            slot_manager,
+            retention,
+            secure_delete: Vec::new(),
+            clock: system_clock(),
+        }
+    }
+
+    /// Overwrites the part files of blobs under these prefixes before they
+    /// are unlinked; a trailing glob is ignored, as in prefix policies.
+    pub fn with_secure_delete(mut self, prefixes: Vec<String>) -> Self {
+        self.secure_delete = prefixes;
+        self
+    }
+
+    /// Ages versions and tombstones against `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn retention(&self) -> &VersionRetention {
+        &self.retention
+    }
+
+    fn shreds(&self, blob_path: &str) -> bool {
+        self.secure_delete
+            .iter()
+            .any(|prefix| blob_path.starts_with(prefix.trim_end_matches('*')))
+    }
+
+    /// Drops the versions of one local slot that the retention policy no
+    /// longer keeps, then deletes the part files only they referenced.
+    pub async fn run(
+        &self,
+        request: PruneVersionsOperationRequest,
+    ) -> Result<PruneVersionsOperationResult> {
+        let mut result = PruneVersionsOperationResult {
+            slot_id: request.slot_id,
+            dry_run: request.dry_run,
+            ..PruneVersionsOperationResult::default()
+        };
+        if !self.retention.is_enabled() || !self.slot_manager.has_slot(request.slot_id).await {
+            return Ok(result);
+        }
+
+        let slot = self.slot_manager.get_slot(request.slot_id).await?;
+        let store = MetadataStore::new(slot)?;
+        let pruned = store.prune_versions(&self.retention, self.clock.now(), request.dry_run)?;
+
+        result.pruned_versions = pruned.versions;
+        result.pruned_paths = pruned.paths;
+        result.removed_parts = pruned.parts.len() as u64;
+        result.freed_bytes = pruned.part_bytes;
+        if request.dry_run {
+            return Ok(result);
+        }
+
+        for part in &pruned.parts {
+            let part_path = Path::new(&part.path);
+            if self.shreds(&part.blob_path) {
+                if let Err(error) = shred_part_file(part_path).await {
+                    // Unlinking would leave the data readable on disk.
+                    tracing::warn!(
+                        "Failed to shred pruned part {}, left in place: {}",
+                        part_path.display(),
+                        error
+                    );
+                    continue;
+                }
+                result.shredded_parts += 1;
+            }
+            match tokio::fs::remove_file(part_path).await {
+                Ok(()) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => {
+                    tracing::warn!(
+                        "Failed to remove pruned part {}: {}",
+                        part_path.display(),
+                        error
+                    );
+                    continue;
+                }
+            }
+            // The generation directory goes once its last part is removed.
+            if let Some(dir) = part_path.parent() {
+                let _ = tokio::fs::remove_dir(dir).await;
+            }
+        }
+
+        Ok(result)
+    }
+}
@@ -1,9 +1,14 @@
 use crate::{
-    ArchiveStore, BlobMeta, ClusterClient, Coordinator, MetadataStore, PART_SIZE, PartIndexState,
-    PartStore, ReplicatedPart, Result, RimError, SlotManager, compute_hash,
+    ArchiveStore, BlobHead, BlobMeta, ClusterClient, ContentHeaders, Coordinator, HeadKind,
+    MetadataStore, MetadataTransaction, PART_SIZE, PartIndexState, PartSource, PartStore,
+    ReplicatedPart, ReplicationPolicy, Result, RimError, SharedClock, SlotManager, compute_hash,
+    system_clock,
 };
 use bytes::Bytes;
-use chrono::Utc;
+use futures_util::future::join_all;
+use futures_util::{Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -34,6 +39,128 @@ impl PutBlobArchiveWriter {
         self.store.write_blob(&object_key, body).await?;
         Ok(self.store.archive_url_for_key(&object_key))
     }
+
+    /// Stores one part under its sha256, so every copy of the same bytes
+    /// shares the object.
+    pub async fn write_part(&self, sha256: &str, body: &[u8]) -> Result<String> {
+        let prefix = self.key_prefix.trim_matches('/');
+        let object_key = if prefix.is_empty() {
+            format!("{}/{}", ARCHIVE_PARTS_DIR, sha256)
+        } else {
+            format!("{}/{}/{}", prefix, ARCHIVE_PARTS_DIR, sha256)
+        };
+        self.store.write_blob(&object_key, body).await?;
+        Ok(self.store.archive_url_for_key(&object_key))
+    }
+}
+
+const ARCHIVE_PARTS_DIR: &str = "parts";
+
+/// Whether `archive_url` names a single part written by
+/// [`PutBlobArchiveWriter::write_part`] rather than a whole blob, whose keys
+/// always end in `g.<generation>`.
+pub fn is_archived_part_url(archive_url: &str) -> bool {
+    let mut segments = archive_url.trim_end_matches('/').rsplit('/');
+    let sha256 = segments.next().unwrap_or_default();
+    segments.next() == Some(ARCHIVE_PARTS_DIR)
+        && sha256.len() == 64
+        && sha256.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Most bytes of user metadata, keys and values together, one version may
+/// carry.
+pub const MAX_USER_METADATA_BYTES: usize = 2048;
+/// Most tags one version may carry.
+pub const MAX_BLOB_TAGS: usize = 10;
+const MAX_TAG_KEY_LEN: usize = 128;
+const MAX_TAG_VALUE_LEN: usize = 256;
+
+/// Checks user metadata before it is stored. Keys are sent back as header
+/// names, so they must be lowercase letters, digits, `-` or `_`; values must
+/// be printable ASCII.
+pub fn validate_user_metadata(metadata: &BTreeMap<String, String>) -> Result<()> {
+    let mut total = 0;
+    for (key, value) in metadata {
+        if key.is_empty()
+            || !key.bytes().all(|byte| {
+                byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-' || byte == b'_'
+            })
+        {
+            return Err(RimError::InvalidRequest(format!(
+                "invalid metadata key: {:?}",
+                key
+            )));
+        }
+        if !value.bytes().all(|byte| (0x20..0x7f).contains(&byte)) {
+            return Err(RimError::InvalidRequest(format!(
+                "metadata {} must be printable ASCII",
+                key
+            )));
+        }
+        total += key.len() + value.len();
+    }
+    if total > MAX_USER_METADATA_BYTES {
+        return Err(RimError::InvalidRequest(format!(
+            "metadata is {} bytes, at most {} are allowed",
+            total, MAX_USER_METADATA_BYTES
+        )));
+    }
+    Ok(())
+}
+
+/// Checks a tag set before it is stored: at most [`MAX_BLOB_TAGS`] tags,
+/// keys of 1 to 128 characters without `=`, values of at most 256, and no
+/// control characters.
+pub fn validate_blob_tags(tags: &BTreeMap<String, String>) -> Result<()> {
+    if tags.len() > MAX_BLOB_TAGS {
+        return Err(RimError::InvalidRequest(format!(
+            "{} tags given, at most {} are allowed",
+            tags.len(),
+            MAX_BLOB_TAGS
+        )));
+    }
+    for (key, value) in tags {
+        if key.is_empty() || key.chars().count() > MAX_TAG_KEY_LEN || key.contains('=') {
+            return Err(RimError::InvalidRequest(format!(
+                "invalid tag key: {:?}",
+                key
+            )));
+        }
+        if value.chars().count() > MAX_TAG_VALUE_LEN {
+            return Err(RimError::InvalidRequest(format!(
+                "tag {} is longer than {} characters",
+                key, MAX_TAG_VALUE_LEN
+            )));
+        }
+        if key.chars().chain(value.chars()).any(char::is_control) {
+            return Err(RimError::InvalidRequest(format!(
+                "tag {} contains control characters",
+                key
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// How many replicas must hold a write before it is acknowledged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteConsistency {
+    /// Parts are pushed to every replica and a write quorum must confirm.
+    #[default]
+    Quorum,
+    /// Acknowledged once the coordinator has committed locally; replicas pull
+    /// the blob from the coordinator in the background.
+    One,
+}
+
+impl WriteConsistency {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "quorum" => Some(Self::Quorum),
+            "one" => Some(Self::One),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -43,6 +170,8 @@ pub struct PutBlobOperation {
     coordinator: Arc<Coordinator>,
     cluster_client: Arc<ClusterClient>,
     archive_writer: Option<PutBlobArchiveWriter>,
+    replication_policy: ReplicationPolicy,
+    clock: SharedClock,
 }
 
 #[derive(Debug, Clone)]
@@ -53,6 +182,32 @@ pub struct PutBlobOperationRequest {
     pub body: Bytes,
     pub replicas: Vec<crate::NodeInfo>,
     pub local_node_id: String,
+    pub consistency: WriteConsistency,
+    /// Compare-and-swap: commit only while the live version of `path` is at
+    /// this generation, 0 meaning the blob must be missing or deleted. The
+    /// live version is the newest a write quorum of replicas reports.
+    pub expected_generation: Option<i64>,
+    /// User metadata stored with the version; see [`validate_user_metadata`].
+    pub metadata: BTreeMap<String, String>,
+    /// Tag set of the version; see [`validate_blob_tags`].
+    pub tags: BTreeMap<String, String>,
+    /// `Content-Type` and the like, sent back on reads.
+    pub content: ContentHeaders,
+}
+
+/// A PUT whose body is consumed as a stream; see [`PutBlobOperation::run_stream`].
+#[derive(Debug, Clone)]
+pub struct PutBlobStreamRequest {
+    pub path: String,
+    pub slot_id: u16,
+    pub replicas: Vec<crate::NodeInfo>,
+    pub local_node_id: String,
+    pub consistency: WriteConsistency,
+    /// See [`PutBlobOperationRequest::expected_generation`].
+    pub expected_generation: Option<i64>,
+    pub metadata: BTreeMap<String, String>,
+    pub tags: BTreeMap<String, String>,
+    pub content: ContentHeaders,
 }
 
 #[derive(Debug, Clone)]
@@ -61,12 +216,19 @@ pub struct PutBlobOperationResult {
     pub etag: String,
     pub size_bytes: u64,
     pub committed_replicas: usize,
+    /// Nodes holding the committed generation when the write was
+    /// acknowledged, the coordinator first.
+    pub acked_replicas: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum PutBlobOperationOutcome {
     Committed(PutBlobOperationResult),
     Conflict,
+    /// `expected_generation` did not match; nothing was written.
+    PreconditionFailed {
+        current_generation: i64,
+    },
 }
 
 impl PutBlobOperation {
@@ -83,9 +245,23 @@ impl PutBlobOperation {
             coordinator,
             cluster_client,
             archive_writer,
+            replication_policy: ReplicationPolicy::default(),
+            clock: system_clock(),
         }
     }
 
+    /// Stamps `updated_at` of new versions from `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Narrows the replicas of each write by the path's prefix policy.
+    pub fn with_replication_policy(mut self, replication_policy: ReplicationPolicy) -> Self {
+        self.replication_policy = replication_policy;
+        self
+    }
+
     pub async fn run(&self, request: PutBlobOperationRequest) -> Result<PutBlobOperationOutcome> {
         let PutBlobOperationRequest {
             path,
@@ -94,55 +270,42 @@ impl PutBlobOperation {
             body,
             replicas,
             local_node_id,
+            consistency,
+            expected_generation,
+            metadata,
+            tags,
+            content,
         } = request;
+        validate_user_metadata(&metadata)?;
+        validate_blob_tags(&tags)?;
+        let replicas = self
+            .replication_policy
+            .write_replicas(&path, replicas, &local_node_id);
 
         let store = self.ensure_store(slot_id).await?;
-        let generation = store.next_generation(&path)?;
-        let etag = compute_hash(&body);
-
-        let mut replicated_parts: Vec<ReplicatedPart> = Vec::new();
-
-        let mut offset = 0usize;
-        let mut part_no = 0u32;
-        while offset < body.len() {
-            let end = (offset + PART_SIZE).min(body.len());
-            let part_body = body.slice(offset..end);
-            let part_sha = compute_hash(&part_body);
-
-            let put_result = self
-                .part_store
-                .put_part(
-                    slot_id,
-                    &path,
-                    generation,
-                    part_no,
-                    &part_sha,
-                    part_body.clone(),
-                )
-                .await?;
-
-            let external_path = put_result.part_path.to_string_lossy().to_string();
-            let part_len = (end - offset) as u64;
-            store.upsert_part_entry(
+        let (generation, expected_generation) = match self
+            .start_write(
+                &store,
+                slot_id,
                 &path,
+                &replicas,
+                &local_node_id,
+                expected_generation,
+            )
+            .await?
+        {
+            WriteStart::Ready {
                 generation,
-                part_no,
-                &part_sha,
-                part_len,
-                Some(external_path.as_str()),
-                None,
-            )?;
-
-            replicated_parts.push(ReplicatedPart {
-                part_no,
-                sha256: part_sha,
-                length: part_len,
-                data: part_body,
-            });
+                expected_generation,
+            } => (generation, expected_generation),
+            WriteStart::PreconditionFailed { current_generation } => {
+                return Ok(PutBlobOperationOutcome::PreconditionFailed { current_generation });
+            }
+        };
+        let etag = compute_hash(&body);
 
-            offset = end;
-            part_no += 1;
-        }
+        let staged_parts =
+            stage_blob_parts(&self.part_store, slot_id, &path, generation, &body).await?;
 
         let part_count = if body.is_empty() {
             0
@@ -166,55 +329,100 @@ impl PutBlobOperation {
             part_count,
             part_index_state: PartIndexState::Complete,
             archive_url,
-            updated_at: Utc::now(),
+            updated_at: self.clock.now(),
+            metadata,
+            tags,
+            content,
         };
 
         let meta_bytes = serde_json::to_vec(&meta)?;
         let meta_sha = compute_hash(&meta_bytes);
 
-        let applied = store.upsert_meta_with_payload(&meta, &meta_bytes, &meta_sha)?;
-        if !applied {
-            return Ok(PutBlobOperationOutcome::Conflict);
+        let commit = commit_blob(
+            &store,
+            staged_parts.iter().map(|part| &part.local),
+            &meta,
+            &meta_bytes,
+            &meta_sha,
+            expected_generation,
+        )?;
+        match commit {
+            BlobCommit::Applied => {}
+            BlobCommit::Superseded => {
+                self.remove_generation(slot_id, &path, generation).await;
+                return Ok(PutBlobOperationOutcome::Conflict);
+            }
+            BlobCommit::PreconditionFailed { current_generation } => {
+                self.remove_generation(slot_id, &path, generation).await;
+                return Ok(PutBlobOperationOutcome::PreconditionFailed { current_generation });
+            }
         }
+        let replicated_parts: Vec<ReplicatedPart> =
+            staged_parts.iter().map(StagedPart::replicated).collect();
 
-        let quorum = self.coordinator.write_quorum(replicas.len());
-        let mut committed_replicas = 1usize;
-
-        for replica in replicas
+        let remote_replicas: Vec<&crate::NodeInfo> = replicas
             .iter()
             .filter(|node| node.node_id != local_node_id.as_str())
-        {
-            let write_result = self
-                .cluster_client
-                .replicate_meta_write(
-                    &replica.node_id,
-                    slot_id,
-                    &path,
-                    &write_id,
-                    generation,
-                    &replicated_parts,
-                    &meta,
-                    &meta_sha,
-                )
-                .await;
-
-            if write_result.is_ok() {
-                committed_replicas += 1;
-            } else if let Err(error) = write_result {
-                tracing::warn!(
-                    "Replica write failed: node={} slot={} path={} error={}",
-                    replica.node_id,
-                    slot_id,
-                    path,
-                    error
-                );
+            .collect();
+
+        if consistency == WriteConsistency::One {
+            let source_node_id = local_node_id.clone();
+            let cluster_client = self.cluster_client.clone();
+            let targets: Vec<String> = remote_replicas
+                .iter()
+                .map(|node| node.node_id.clone())
+                .collect();
+            let path = path.clone();
+            tokio::spawn(async move {
+                pull_to_replicas(&cluster_client, &source_node_id, &targets, slot_id, &path).await;
+            });
+
+            return Ok(PutBlobOperationOutcome::Committed(PutBlobOperationResult {
+                generation,
+                etag,
+                size_bytes: body.len() as u64,
+                committed_replicas: 1,
+                acked_replicas: vec![local_node_id],
+            }));
+        }
+
+        let quorum = self.coordinator.write_quorum(replicas.len());
+        let mut acked_replicas = vec![local_node_id.clone()];
+
+        // Replicas receive the same part buffers concurrently, so a large
+        // blob costs about one upload instead of one per replica.
+        let replica_writes = remote_replicas.iter().map(|replica| {
+            self.cluster_client.replicate_meta_write(
+                &replica.node_id,
+                slot_id,
+                &path,
+                &write_id,
+                generation,
+                &replicated_parts,
+                &meta,
+                &meta_sha,
+            )
+        });
+
+        for (replica, write_result) in remote_replicas.iter().zip(join_all(replica_writes).await) {
+            match write_result {
+                Ok(()) => acked_replicas.push(replica.node_id.clone()),
+                Err(error) => {
+                    tracing::warn!(
+                        "Replica write failed: node={} slot={} path={} error={}",
+                        replica.node_id,
+                        slot_id,
+                        path,
+                        error
+                    );
+                }
             }
         }
 
-        if committed_replicas < quorum {
+        if acked_replicas.len() < quorum {
             return Err(RimError::InsufficientReplicas {
                 required: quorum,
-                found: committed_replicas,
+                found: acked_replicas.len(),
             });
         }
 
@@ -222,10 +430,331 @@ impl PutBlobOperation {
             generation,
             etag,
             size_bytes: body.len() as u64,
-            committed_replicas,
+            committed_replicas: acked_replicas.len(),
+            acked_replicas,
+        }))
+    }
+
+    /// Ingests a body stream part by part, so memory stays bounded by the
+    /// stream's chunk size however large the blob is.
+    ///
+    /// Every part is synced to disk before the head commits; a stream that
+    /// fails midway leaves no head and its staged parts are removed. Replicas
+    /// then pull the blob from this node: with [`WriteConsistency::Quorum`]
+    /// the pulls are awaited and counted against the write quorum. Streamed
+    /// blobs are archived by the archive lifecycle manager rather than
+    /// written through.
+    pub async fn run_stream<S>(
+        &self,
+        request: PutBlobStreamRequest,
+        body: S,
+    ) -> Result<PutBlobOperationOutcome>
+    where
+        S: Stream<Item = Result<Bytes>> + Send,
+    {
+        let PutBlobStreamRequest {
+            path,
+            slot_id,
+            replicas,
+            local_node_id,
+            consistency,
+            expected_generation,
+            metadata,
+            tags,
+            content,
+        } = request;
+        validate_user_metadata(&metadata)?;
+        validate_blob_tags(&tags)?;
+        let replicas = self
+            .replication_policy
+            .write_replicas(&path, replicas, &local_node_id);
+
+        let store = self.ensure_store(slot_id).await?;
+        let (generation, expected_generation) = match self
+            .start_write(
+                &store,
+                slot_id,
+                &path,
+                &replicas,
+                &local_node_id,
+                expected_generation,
+            )
+            .await?
+        {
+            WriteStart::Ready {
+                generation,
+                expected_generation,
+            } => (generation, expected_generation),
+            WriteStart::PreconditionFailed { current_generation } => {
+                return Ok(PutBlobOperationOutcome::PreconditionFailed { current_generation });
+            }
+        };
+
+        let staged = match self.stream_parts(slot_id, &path, generation, body).await {
+            Ok(staged) => staged,
+            Err(error) => {
+                self.remove_generation(slot_id, &path, generation).await;
+                return Err(error);
+            }
+        };
+
+        self.commit_local_parts(
+            &store,
+            StreamedCommit {
+                path,
+                slot_id,
+                generation,
+                replicas,
+                local_node_id,
+                consistency,
+                expected_generation,
+                metadata,
+                tags,
+                content,
+            },
+            staged,
+        )
+        .await
+    }
+
+    /// Indexes parts already written under `generation`, commits the head
+    /// and has the other replicas pull the blob from this node.
+    pub(crate) async fn commit_local_parts(
+        &self,
+        store: &MetadataStore,
+        commit: StreamedCommit,
+        staged: StreamedBlob,
+    ) -> Result<PutBlobOperationOutcome> {
+        let StreamedCommit {
+            path,
+            slot_id,
+            generation,
+            replicas,
+            local_node_id,
+            consistency,
+            expected_generation,
+            metadata,
+            tags,
+            content,
+        } = commit;
+        let replicas = self
+            .replication_policy
+            .write_replicas(&path, replicas, &local_node_id);
+
+        let meta = BlobMeta {
+            path: path.clone(),
+            slot_id,
+            generation,
+            version: generation,
+            size_bytes: staged.size_bytes,
+            etag: staged.etag.clone(),
+            part_size: staged.part_size,
+            part_count: staged.parts.len() as u32,
+            part_index_state: PartIndexState::Complete,
+            archive_url: None,
+            updated_at: self.clock.now(),
+            metadata,
+            tags,
+            content,
+        };
+
+        let meta_bytes = serde_json::to_vec(&meta)?;
+        let meta_sha = compute_hash(&meta_bytes);
+        match commit_blob(
+            store,
+            &staged.parts,
+            &meta,
+            &meta_bytes,
+            &meta_sha,
+            expected_generation,
+        )? {
+            BlobCommit::Applied => {}
+            BlobCommit::Superseded => {
+                self.remove_generation(slot_id, &path, generation).await;
+                return Ok(PutBlobOperationOutcome::Conflict);
+            }
+            BlobCommit::PreconditionFailed { current_generation } => {
+                self.remove_generation(slot_id, &path, generation).await;
+                return Ok(PutBlobOperationOutcome::PreconditionFailed { current_generation });
+            }
+        }
+
+        let targets: Vec<String> = replicas
+            .iter()
+            .filter(|node| node.node_id != local_node_id.as_str())
+            .map(|node| node.node_id.clone())
+            .collect();
+
+        if consistency == WriteConsistency::One {
+            let source_node_id = local_node_id.clone();
+            let cluster_client = self.cluster_client.clone();
+            let path = path.clone();
+            tokio::spawn(async move {
+                pull_to_replicas(&cluster_client, &source_node_id, &targets, slot_id, &path).await;
+            });
+
+            return Ok(PutBlobOperationOutcome::Committed(PutBlobOperationResult {
+                generation,
+                etag: staged.etag,
+                size_bytes: staged.size_bytes,
+                committed_replicas: 1,
+                acked_replicas: vec![local_node_id],
+            }));
+        }
+
+        let quorum = self.coordinator.write_quorum(replicas.len());
+        let mut acked_replicas = vec![local_node_id.clone()];
+        let pulls = targets.iter().map(|target| {
+            self.cluster_client
+                .request_replica_pull(target, slot_id, &path, &local_node_id)
+        });
+        for (target, result) in targets.iter().zip(join_all(pulls).await) {
+            match result {
+                Ok(()) => acked_replicas.push(target.clone()),
+                Err(error) => {
+                    tracing::warn!(
+                        "Replica pull failed: node={} slot={} path={} error={}",
+                        target,
+                        slot_id,
+                        path,
+                        error
+                    );
+                }
+            }
+        }
+
+        if acked_replicas.len() < quorum {
+            return Err(RimError::InsufficientReplicas {
+                required: quorum,
+                found: acked_replicas.len(),
+            });
+        }
+
+        Ok(PutBlobOperationOutcome::Committed(PutBlobOperationResult {
+            generation,
+            etag: staged.etag,
+            size_bytes: staged.size_bytes,
+            committed_replicas: acked_replicas.len(),
+            acked_replicas,
         }))
     }
 
+    async fn stream_parts<S>(
+        &self,
+        slot_id: u16,
+        path: &str,
+        generation: i64,
+        body: S,
+    ) -> Result<StreamedBlob>
+    where
+        S: Stream<Item = Result<Bytes>> + Send,
+    {
+        let mut body = std::pin::pin!(body);
+        let mut blob_hasher = Sha256::new();
+        let mut size_bytes = 0u64;
+        let mut parts = Vec::new();
+        let mut writer = None;
+
+        while let Some(chunk) = body.next().await {
+            let mut chunk = chunk?;
+            blob_hasher.update(&chunk);
+            size_bytes += chunk.len() as u64;
+
+            while !chunk.is_empty() {
+                let part = match writer.as_mut() {
+                    Some(part) => part,
+                    None => writer.insert(
+                        self.part_store
+                            .begin_part(slot_id, path, generation, parts.len() as u32)
+                            .await?,
+                    ),
+                };
+
+                let room = PART_SIZE - part.len() as usize;
+                let piece = chunk.split_to(room.min(chunk.len()));
+                part.write(&piece).await?;
+
+                if part.len() as usize == PART_SIZE
+                    && let Some(full) = writer.take()
+                {
+                    parts.push(StreamedPart::finish(full).await?);
+                }
+            }
+        }
+
+        if let Some(last) = writer.take() {
+            parts.push(StreamedPart::finish(last).await?);
+        }
+
+        Ok(StreamedBlob {
+            etag: hex::encode(blob_hasher.finalize()),
+            size_bytes,
+            part_size: PART_SIZE as u64,
+            parts,
+        })
+    }
+
+    /// Picks the generation of a write and, for a conditional one, checks
+    /// `expected_generation` before the body is staged. A replica may hold a
+    /// version this node has not seen, so the check is against the newest
+    /// head among this node and a quorum of replicas, and the generation is
+    /// taken above it. The local head commit is then conditioned on the local
+    /// head the check saw, so a write racing in on this node still fails it.
+    async fn start_write(
+        &self,
+        store: &MetadataStore,
+        slot_id: u16,
+        path: &str,
+        replicas: &[crate::NodeInfo],
+        local_node_id: &str,
+        expected_generation: Option<i64>,
+    ) -> Result<WriteStart> {
+        let generation = store.next_generation(path)?;
+        let Some(expected) = expected_generation else {
+            return Ok(WriteStart::Ready {
+                generation,
+                expected_generation: None,
+            });
+        };
+
+        let local = store.get_current_head(path)?;
+        let local_generation = live_generation(local.clone());
+        let remote_heads = join_all(
+            replicas
+                .iter()
+                .filter(|node| node.node_id != local_node_id)
+                .map(|node| {
+                    self.cluster_client
+                        .fetch_remote_head(&node.node_id, slot_id, path)
+                }),
+        )
+        .await;
+        let local_replica =
+            replicas.is_empty() || replicas.iter().any(|node| node.node_id == local_node_id);
+        let freshest = freshest_head(
+            local,
+            local_replica,
+            remote_heads,
+            self.coordinator.write_quorum(replicas.len()),
+        )?;
+
+        let current_generation = live_generation(freshest.clone());
+        if current_generation != expected {
+            return Ok(WriteStart::PreconditionFailed { current_generation });
+        }
+        Ok(WriteStart::Ready {
+            generation: generation.max(freshest.map_or(0, |head| head.generation) + 1),
+            expected_generation: Some(local_generation),
+        })
+    }
+
+    /// Drops the part files staged under a generation that will not commit.
+    async fn remove_generation(&self, slot_id: u16, path: &str, generation: i64) {
+        if let Ok(dir) = self.part_store.generation_dir(slot_id, path, generation) {
+            let _ = tokio::fs::remove_dir_all(dir).await;
+        }
+    }
+
     async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
         if !self.slot_manager.has_slot(slot_id).await {
             self.slot_manager.init_slot(slot_id).await?;
@@ -235,3 +764,477 @@ impl PutBlobOperation {
         MetadataStore::new(slot)
     }
 }
+
+/// Where a blob whose parts are already on local disk gets committed.
+pub(crate) struct StreamedCommit {
+    pub(crate) path: String,
+    pub(crate) slot_id: u16,
+    pub(crate) generation: i64,
+    pub(crate) replicas: Vec<crate::NodeInfo>,
+    pub(crate) local_node_id: String,
+    pub(crate) consistency: WriteConsistency,
+    pub(crate) expected_generation: Option<i64>,
+    pub(crate) metadata: BTreeMap<String, String>,
+    pub(crate) tags: BTreeMap<String, String>,
+    pub(crate) content: ContentHeaders,
+}
+
+/// Where a write starts from; see [`PutBlobOperation::start_write`].
+enum WriteStart {
+    Ready {
+        generation: i64,
+        /// What the local head commit is conditioned on.
+        expected_generation: Option<i64>,
+    },
+    PreconditionFailed {
+        current_generation: i64,
+    },
+}
+
+pub(crate) struct StreamedBlob {
+    pub(crate) etag: String,
+    pub(crate) size_bytes: u64,
+    pub(crate) part_size: u64,
+    pub(crate) parts: Vec<StreamedPart>,
+}
+
+pub(crate) struct StreamedPart {
+    pub(crate) part_no: u32,
+    pub(crate) sha256: String,
+    pub(crate) length: u64,
+    pub(crate) external_path: String,
+}
+
+impl StreamedPart {
+    async fn finish(writer: crate::PartWriter) -> Result<Self> {
+        let part_no = writer.part_no();
+        let length = writer.len();
+        let (sha256, put_result) = writer.finish().await?;
+        Ok(Self {
+            part_no,
+            sha256,
+            length,
+            external_path: put_result.part_path.to_string_lossy().to_string(),
+        })
+    }
+}
+
+/// Has every target pull a freshly committed path from the coordinator. A
+/// target that cannot reach the coordinator retries from a replica that
+/// already completed its pull.
+async fn pull_to_replicas(
+    cluster_client: &ClusterClient,
+    local_node_id: &str,
+    targets: &[String],
+    slot_id: u16,
+    path: &str,
+) {
+    let pulls = targets
+        .iter()
+        .map(|target| cluster_client.request_replica_pull(target, slot_id, path, local_node_id));
+    let results = join_all(pulls).await;
+
+    let completed = targets
+        .iter()
+        .zip(&results)
+        .find(|(_, result)| result.is_ok())
+        .map(|(target, _)| target.as_str());
+
+    for (target, result) in targets.iter().zip(results) {
+        let Err(error) = result else {
+            continue;
+        };
+
+        let retried = match completed {
+            Some(source) => cluster_client
+                .request_replica_pull(target, slot_id, path, source)
+                .await
+                .is_ok(),
+            None => false,
+        };
+
+        if !retried {
+            tracing::warn!(
+                "Replica pull failed: node={} slot={} path={} error={}",
+                target,
+                slot_id,
+                path,
+                error
+            );
+        }
+    }
+}
+
+/// A part of an in-memory body, written to the local part store.
+pub(crate) struct StagedPart {
+    pub(crate) local: StreamedPart,
+    pub(crate) data: Bytes,
+}
+
+impl StagedPart {
+    pub(crate) fn replicated(&self) -> ReplicatedPart {
+        ReplicatedPart {
+            part_no: self.local.part_no,
+            sha256: self.local.sha256.clone(),
+            length: self.local.length,
+            data: self.data.clone(),
+        }
+    }
+}
+
+/// Writes the parts of `body` to the local part store. They are indexed
+/// with [`index_parts`], and stay invisible until a head referencing them
+/// commits.
+pub(crate) async fn stage_blob_parts(
+    part_store: &PartStore,
+    slot_id: u16,
+    path: &str,
+    generation: i64,
+    body: &Bytes,
+) -> Result<Vec<StagedPart>> {
+    let mut staged_parts = Vec::new();
+
+    let mut offset = 0usize;
+    let mut part_no = 0u32;
+    while offset < body.len() {
+        let end = (offset + PART_SIZE).min(body.len());
+        let part_body = body.slice(offset..end);
+        let part_sha = compute_hash(&part_body);
+
+        let put_result = part_store
+            .put_part(
+                slot_id,
+                path,
+                generation,
+                part_no,
+                &part_sha,
+                part_body.clone(),
+            )
+            .await?;
+
+        staged_parts.push(StagedPart {
+            local: StreamedPart {
+                part_no,
+                sha256: part_sha,
+                length: (end - offset) as u64,
+                external_path: put_result.part_path.to_string_lossy().to_string(),
+            },
+            data: part_body,
+        });
+
+        offset = end;
+        part_no += 1;
+    }
+
+    Ok(staged_parts)
+}
+
+/// Indexes parts written under `generation` of `path`.
+pub(crate) fn index_parts<'a>(
+    tx: &MetadataTransaction<'_>,
+    path: &str,
+    generation: i64,
+    parts: impl IntoIterator<Item = &'a StreamedPart>,
+) -> Result<()> {
+    for part in parts {
+        tx.upsert_part_entry(
+            path,
+            generation,
+            part.part_no,
+            &part.sha256,
+            part.length,
+            Some(part.external_path.as_str()),
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// The parts of `source` when every one of them is on disk here.
+pub(crate) fn held_parts(
+    store: &MetadataStore,
+    source: &PartSource,
+) -> Result<Option<Vec<StreamedPart>>> {
+    let mut parts = Vec::with_capacity(source.part_count as usize);
+    for part_no in 0..source.part_count {
+        let Some(entry) = store.get_part_entry(&source.path, source.generation, part_no)? else {
+            return Ok(None);
+        };
+        let Some(external_path) = entry
+            .external_path
+            .filter(|external_path| std::path::Path::new(external_path).exists())
+        else {
+            return Ok(None);
+        };
+        parts.push(StreamedPart {
+            part_no,
+            sha256: entry.sha256,
+            length: entry.size_bytes,
+            external_path,
+        });
+    }
+    Ok(Some(parts))
+}
+
+/// Indexes the parts of `source` under `generation` of `path` as well, so
+/// both versions read the same files. Returns `false`, indexing nothing,
+/// unless every part of `source` is on disk here.
+pub(crate) fn link_parts(
+    store: &MetadataStore,
+    source: &PartSource,
+    path: &str,
+    generation: i64,
+) -> Result<bool> {
+    let Some(parts) = held_parts(store, source)? else {
+        return Ok(false);
+    };
+    store.with_transaction(|tx| index_parts(tx, path, generation, &parts))?;
+    Ok(true)
+}
+
+pub(crate) enum BlobCommit {
+    Applied,
+    /// A newer head is already in place.
+    Superseded,
+    PreconditionFailed {
+        current_generation: i64,
+    },
+}
+
+/// The generation a compare-and-swap compares against: that of the live
+/// version, or 0 when the blob is missing or deleted.
+pub(crate) fn live_generation(head: Option<BlobHead>) -> i64 {
+    head.filter(|head| head.head_kind == HeadKind::Meta)
+        .map_or(0, |head| head.generation)
+}
+
+/// The newest of this node's head and the replicas' answers; fails unless
+/// `quorum` replicas answered, this node counting when it is one, since only
+/// then does the answer cover every committed version.
+pub(crate) fn freshest_head(
+    local: Option<BlobHead>,
+    local_replica: bool,
+    remote: Vec<Result<Option<BlobHead>>>,
+    quorum: usize,
+) -> Result<Option<BlobHead>> {
+    let mut answered = usize::from(local_replica);
+    let mut freshest = local;
+    for head in remote.into_iter().filter_map(|head| head.ok()) {
+        answered += 1;
+        if let Some(head) = head
+            && freshest
+                .as_ref()
+                .is_none_or(|freshest| freshest.generation < head.generation)
+        {
+            freshest = Some(head);
+        }
+    }
+    if answered < quorum {
+        return Err(RimError::InsufficientReplicas {
+            required: quorum,
+            found: answered,
+        });
+    }
+    Ok(freshest)
+}
+
+/// Indexes the parts of `meta` and commits it as the head in one
+/// transaction. Returns [`BlobCommit::Superseded`], indexing nothing, when a
+/// newer head is already in place.
+/// With `expected_generation`, nothing is written unless the live version
+/// is still at that generation.
+pub(crate) fn commit_blob<'a>(
+    store: &MetadataStore,
+    parts: impl IntoIterator<Item = &'a StreamedPart>,
+    meta: &BlobMeta,
+    meta_bytes: &[u8],
+    meta_sha: &str,
+    expected_generation: Option<i64>,
+) -> Result<BlobCommit> {
+    store.with_transaction(|tx| {
+        if let Some(expected) = expected_generation {
+            let current_generation = live_generation(tx.get_current_head(&meta.path)?);
+            if current_generation != expected {
+                return Ok(BlobCommit::PreconditionFailed { current_generation });
+            }
+        }
+        if !tx.upsert_meta_with_payload(meta, meta_bytes, meta_sha)? {
+            return Ok(BlobCommit::Superseded);
+        }
+        index_parts(tx, &meta.path, meta.generation, parts)?;
+        Ok(BlobCommit::Applied)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(generation: i64) -> BlobMeta {
+        BlobMeta {
+            path: "cfg.json".to_string(),
+            slot_id: 1,
+            generation,
+            version: generation,
+            size_bytes: 0,
+            etag: compute_hash(b""),
+            part_size: PART_SIZE as u64,
+            part_count: 0,
+            part_index_state: PartIndexState::Complete,
+            archive_url: None,
+            updated_at: chrono::Utc::now(),
+            metadata: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            content: ContentHeaders::default(),
+        }
+    }
+
+    fn commit(store: &MetadataStore, generation: i64, expected: Option<i64>) -> BlobCommit {
+        let meta = meta(generation);
+        let meta_bytes = serde_json::to_vec(&meta).unwrap();
+        let meta_sha = compute_hash(&meta_bytes);
+        commit_blob(store, [], &meta, &meta_bytes, &meta_sha, expected).unwrap()
+    }
+
+    #[test]
+    fn archived_part_urls_are_told_from_blob_urls() {
+        let sha256 = compute_hash(b"part");
+        assert!(is_archived_part_url(&format!(
+            "s3://bucket/rimio/archive/parts/{}",
+            sha256
+        )));
+        assert!(!is_archived_part_url("s3://bucket/rimio/archive/parts/g.3"));
+        assert!(!is_archived_part_url(&format!(
+            "s3://bucket/rimio/archive/{}/g.1",
+            sha256
+        )));
+    }
+
+    #[tokio::test]
+    async fn expected_generation_guards_the_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        assert!(matches!(commit(&store, 1, Some(0)), BlobCommit::Applied));
+        assert!(matches!(
+            commit(&store, 2, Some(0)),
+            BlobCommit::PreconditionFailed {
+                current_generation: 1
+            }
+        ));
+        assert!(matches!(commit(&store, 2, Some(1)), BlobCommit::Applied));
+        assert!(matches!(
+            commit(&store, 3, Some(1)),
+            BlobCommit::PreconditionFailed {
+                current_generation: 2
+            }
+        ));
+        assert_eq!(
+            store
+                .get_current_head("cfg.json")
+                .unwrap()
+                .unwrap()
+                .generation,
+            2
+        );
+    }
+
+    #[test]
+    fn conditional_writes_compare_against_the_freshest_quorum_head() {
+        let head = |generation, head_kind| -> Result<Option<BlobHead>> {
+            Ok(Some(BlobHead {
+                path: "cfg.json".to_string(),
+                generation,
+                head_kind,
+                head_sha256: String::new(),
+                updated_at: chrono::Utc::now(),
+                meta: None,
+                tombstone: None,
+            }))
+        };
+        let unreachable = || Err(RimError::Http("connection refused".to_string()));
+
+        // A replica committed generation 4 this node has not seen yet.
+        let local = head(3, HeadKind::Meta).unwrap();
+        let freshest = freshest_head(
+            local.clone(),
+            true,
+            vec![head(4, HeadKind::Meta), unreachable()],
+            2,
+        )
+        .unwrap();
+        assert_eq!(live_generation(freshest), 4);
+
+        let freshest =
+            freshest_head(local.clone(), true, vec![head(5, HeadKind::Tombstone)], 2).unwrap();
+        assert_eq!(freshest.as_ref().map(|head| head.generation), Some(5));
+        assert_eq!(live_generation(freshest), 0);
+
+        assert!(matches!(
+            freshest_head(
+                local,
+                false,
+                vec![head(4, HeadKind::Meta), unreachable()],
+                2
+            ),
+            Err(RimError::InsufficientReplicas {
+                required: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn user_metadata_and_tags_are_checked() {
+        let metadata = BTreeMap::from([("owner-id".to_string(), "team a".to_string())]);
+        assert!(validate_user_metadata(&metadata).is_ok());
+        for key in ["", "Owner", "owner id"] {
+            let metadata = BTreeMap::from([(key.to_string(), "x".to_string())]);
+            assert!(validate_user_metadata(&metadata).is_err(), "{:?}", key);
+        }
+        let oversized = BTreeMap::from([("big".to_string(), "x".repeat(MAX_USER_METADATA_BYTES))]);
+        assert!(validate_user_metadata(&oversized).is_err());
+
+        let tags = BTreeMap::from([("env".to_string(), "prod".to_string())]);
+        assert!(validate_blob_tags(&tags).is_ok());
+        let tags = BTreeMap::from([("a=b".to_string(), String::new())]);
+        assert!(validate_blob_tags(&tags).is_err());
+        let tags: BTreeMap<String, String> = (0..=MAX_BLOB_TAGS)
+            .map(|index| (format!("k{}", index), String::new()))
+            .collect();
+        assert!(validate_blob_tags(&tags).is_err());
+    }
+
+    #[tokio::test]
+    async fn metadata_tags_and_content_headers_are_kept_in_the_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        let plain = serde_json::to_value(meta(1)).unwrap();
+        for field in ["metadata", "tags", "content_type"] {
+            assert!(plain.get(field).is_none(), "{}", field);
+        }
+
+        let mut tagged = meta(1);
+        tagged
+            .metadata
+            .insert("owner".to_string(), "alice".to_string());
+        tagged.tags.insert("env".to_string(), "prod".to_string());
+        tagged.content.content_type = Some("text/plain".to_string());
+        let meta_bytes = serde_json::to_vec(&tagged).unwrap();
+        let meta_sha = compute_hash(&meta_bytes);
+        commit_blob(&store, [], &tagged, &meta_bytes, &meta_sha, None).unwrap();
+
+        let head = store.get_current_head("cfg.json").unwrap().unwrap();
+        let stored = head.meta.unwrap();
+        assert_eq!(stored.metadata, tagged.metadata);
+        assert_eq!(stored.tags, tagged.tags);
+        assert_eq!(stored.content, tagged.content);
+    }
+}
@@ -0,0 +1,112 @@
+//! Replacing the tag set of a blob.
+//!
+//! Tags live in the head of a version, so a new tag set is a new version
+//! with the same body, metadata and content headers. It is committed against
+//! the generation it was read from, and a concurrent write turns it into a
+//! conflict instead of undoing that write.
+
+use super::put_blob::validate_blob_tags;
+use crate::{
+    NodeInfo, PutBlobOperation, PutBlobOperationOutcome, PutBlobOperationRequest,
+    PutBlobOperationResult, ReadBlobOperation, ReadBlobOperationOutcome, ReadBlobOperationRequest,
+    ReadConsistency, Result, WriteConsistency,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct PutBlobTagsOperation {
+    read_blob_operation: Arc<ReadBlobOperation>,
+    put_blob_operation: Arc<PutBlobOperation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PutBlobTagsOperationRequest {
+    pub path: String,
+    pub slot_id: u16,
+    pub replicas: Vec<NodeInfo>,
+    pub local_node_id: String,
+    /// The new tag set; empty removes every tag.
+    pub tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum PutBlobTagsOperationOutcome {
+    Updated(PutBlobOperationResult),
+    NotFound,
+    Deleted,
+    /// The blob changed between reading it and writing the new tags.
+    Conflict,
+}
+
+impl PutBlobTagsOperation {
+    pub fn new(
+        read_blob_operation: Arc<ReadBlobOperation>,
+        put_blob_operation: Arc<PutBlobOperation>,
+    ) -> Self {
+        Self {
+            read_blob_operation,
+            put_blob_operation,
+        }
+    }
+
+    pub async fn run(
+        &self,
+        request: PutBlobTagsOperationRequest,
+    ) -> Result<PutBlobTagsOperationOutcome> {
+        let PutBlobTagsOperationRequest {
+            path,
+            slot_id,
+            replicas,
+            local_node_id,
+            tags,
+        } = request;
+        validate_blob_tags(&tags)?;
+
+        let current = self
+            .read_blob_operation
+            .run(ReadBlobOperationRequest {
+                slot_id,
+                path: path.clone(),
+                replicas: replicas.clone(),
+                local_node_id: local_node_id.clone(),
+                include_body: true,
+                range: None,
+                if_range: None,
+                consistency: ReadConsistency::Quorum,
+            })
+            .await?;
+        let current = match current {
+            ReadBlobOperationOutcome::Found(current) => current,
+            ReadBlobOperationOutcome::NotFound => return Ok(PutBlobTagsOperationOutcome::NotFound),
+            ReadBlobOperationOutcome::Deleted => return Ok(PutBlobTagsOperationOutcome::Deleted),
+        };
+
+        let outcome = self
+            .put_blob_operation
+            .run(PutBlobOperationRequest {
+                path,
+                slot_id,
+                write_id: format!("tags-{}", ulid::Ulid::new()),
+                body: current.body.unwrap_or_default(),
+                replicas,
+                local_node_id,
+                consistency: WriteConsistency::Quorum,
+                expected_generation: Some(current.meta.generation),
+                metadata: current.meta.metadata,
+                tags,
+                content: current.meta.content,
+            })
+            .await?;
+
+        Ok(match outcome {
+            PutBlobOperationOutcome::Committed(result) => {
+                PutBlobTagsOperationOutcome::Updated(result)
+            }
+            PutBlobOperationOutcome::Conflict
+            | PutBlobOperationOutcome::PreconditionFailed { .. } => {
+                PutBlobTagsOperationOutcome::Conflict
+            }
+        })
+    }
+}
@@ -1,33 +1,161 @@
 use crate::{
-    BlobHead, BlobMeta, ClusterClient, HeadKind, MetadataStore, NodeInfo, PART_SIZE, PartStore,
-    Result, RimError, SlotManager, compute_hash,
+    BlobHead, BlobMeta, ClusterClient, HeadKind, HeadWrite, MetadataStore, NodeInfo, NodeStore,
+    PART_SIZE, PartStore, PeerCall, RepairAttempt, RepairDeadLetter, ReplicationPolicy, Result,
+    RimError, SlotManager, compute_hash, is_archived_part_url,
 };
 use bytes::Bytes;
+use futures_util::future::join_all;
+use futures_util::stream::{self, BoxStream, StreamExt};
 use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 
 #[derive(Clone)]
 pub struct ReadBlobOperation {
     slot_manager: Arc<SlotManager>,
     part_store: Arc<PartStore>,
     cluster_client: Arc<ClusterClient>,
+    wide_probe: WideProbeMode,
+    replication_policy: ReplicationPolicy,
+    repair_log: Option<Arc<NodeStore>>,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// What a read does when no replica of the slot knows the path.
+///
+/// Data can sit outside the current replica set during rebalancing or after a
+/// misconfiguration, so before answering 404 the read may probe every
+/// registered node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WideProbeMode {
+    /// Only the replica set is consulted.
+    #[default]
+    Off,
+    /// Probe all nodes and serve the blob from whichever node has it.
+    Probe,
+    /// Probe all nodes and copy the blob onto this node when found.
+    Repair,
+}
+
+/// How many replicas must answer a read before its head is trusted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadConsistency {
+    /// Serve the head this node has, asking other replicas only when it has
+    /// none.
+    #[default]
+    One,
+    /// Ask every replica and serve the freshest head once a majority has
+    /// answered.
+    Quorum,
+    /// Like `Quorum`, but every replica must answer.
+    All,
+}
+
+impl ReadConsistency {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "one" => Some(Self::One),
+            "quorum" => Some(Self::Quorum),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+
+    /// Answers needed from a replica set of `replicas` nodes.
+    fn required(self, replicas: usize) -> usize {
+        match self {
+            Self::One => 1,
+            Self::Quorum => replicas / 2 + 1,
+            Self::All => replicas.max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ReadByteRange {
     pub start: u64,
     pub end: u64,
 }
 
+/// A byte range as a client asks for it, before the blob size is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadRangeSpec {
+    /// `bytes=start-end`; an end past the blob is cut to its last byte.
+    Bounded { start: u64, end: u64 },
+    /// `bytes=start-`: from `start` to the end of the blob.
+    From { start: u64 },
+    /// `bytes=-length`: the last `length` bytes.
+    Suffix { length: u64 },
+}
+
+impl ReadRangeSpec {
+    /// Parses a `Range` header value. Only a single `bytes` range is
+    /// supported.
+    pub fn parse(value: &str) -> Result<Self> {
+        let invalid = |message: &str| RimError::InvalidRequest(message.to_string());
+        let Some(raw) = value.trim().strip_prefix("bytes=") else {
+            return Err(invalid("only bytes= range is supported"));
+        };
+        if raw.contains(',') {
+            return Err(invalid("multiple ranges are not supported"));
+        }
+
+        let Some((start, end)) = raw.split_once('-') else {
+            return Err(invalid("invalid range"));
+        };
+        let (start, end) = (start.trim(), end.trim());
+        let number = |raw: &str, message: &str| raw.parse::<u64>().map_err(|_| invalid(message));
+
+        match (start.is_empty(), end.is_empty()) {
+            (true, true) => Err(invalid("invalid range")),
+            (true, false) => Ok(Self::Suffix {
+                length: number(end, "invalid suffix length")?,
+            }),
+            (false, true) => Ok(Self::From {
+                start: number(start, "invalid range start")?,
+            }),
+            (false, false) => {
+                let start = number(start, "invalid range start")?;
+                let end = number(end, "invalid range end")?;
+                if start > end {
+                    return Err(invalid("range start must be <= range end"));
+                }
+                Ok(Self::Bounded { start, end })
+            }
+        }
+    }
+
+    /// The bytes this spec selects from a blob of `size_bytes`, or `None`
+    /// when it selects nothing.
+    pub fn resolve(self, size_bytes: u64) -> Option<ReadByteRange> {
+        let last = size_bytes.checked_sub(1)?;
+        let (start, end) = match self {
+            Self::Bounded { start, end } => (start, end.min(last)),
+            Self::From { start } => (start, last),
+            Self::Suffix { length: 0 } => return None,
+            Self::Suffix { length } => (size_bytes.saturating_sub(length), last),
+        };
+        (start <= end).then_some(ReadByteRange { start, end })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ReadBlobOperationRequest {
     pub slot_id: u16,
     pub path: String,
     pub replicas: Vec<NodeInfo>,
     pub local_node_id: String,
+    /// When false only the head is resolved and no part is fetched.
     pub include_body: bool,
-    pub range: Option<ReadByteRange>,
+    pub range: Option<ReadRangeSpec>,
+    /// `If-Range`: the range is only served while the blob still matches
+    /// this entity tag or Last-Modified date; otherwise the whole blob is.
+    pub if_range: Option<String>,
+    pub consistency: ReadConsistency,
 }
 
 #[derive(Debug, Clone)]
@@ -35,127 +163,560 @@ pub struct ReadBlobOperationResult {
     pub meta: BlobMeta,
     pub body: Option<Bytes>,
     pub body_range: Option<ReadByteRange>,
+    /// Whether `body_range` is the requested range rather than the whole
+    /// blob.
+    pub partial: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum ReadBlobOperationOutcome {
-    Found(ReadBlobOperationResult),
+    Found(Box<ReadBlobOperationResult>),
+    NotFound,
+    Deleted,
+}
+
+pub struct ReadBlobStream {
+    pub meta: BlobMeta,
+    pub body_range: Option<ReadByteRange>,
+    /// Whether `body_range` is the requested range rather than the whole
+    /// blob.
+    pub partial: bool,
+    pub body: BoxStream<'static, Result<Bytes>>,
+}
+
+pub enum ReadBlobStreamOutcome {
+    Found(Box<ReadBlobStream>),
+    NotFound,
+    Deleted,
+}
+
+/// Where each part of the current version lives, so a client can fetch
+/// parts itself with range reads against any node that holds them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadBlobManifest {
+    pub path: String,
+    pub slot_id: u16,
+    pub generation: i64,
+    pub size_bytes: u64,
+    pub etag: String,
+    pub part_size: u64,
+    pub archive_url: Option<String>,
+    /// Nodes named in the parts' locality.
+    pub nodes: Vec<ReadBlobManifestNode>,
+    pub parts: Vec<ReadBlobManifestPart>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadBlobManifestNode {
+    pub node_id: String,
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadBlobManifestPart {
+    pub part_no: u32,
+    /// Byte offset of the part in the blob.
+    pub offset: u64,
+    pub length: u64,
+    /// Unknown when the serving node has not indexed the part.
+    pub sha256: Option<String>,
+    /// Ids of the nodes that hold this generation of the part.
+    pub locality: Vec<String>,
+}
+
+pub enum ReadBlobManifestOutcome {
+    Found(ReadBlobManifest),
+    NotFound,
+    Deleted,
+}
+
+enum Located {
+    Found(Box<LocatedBlob>),
     NotFound,
     Deleted,
 }
 
+struct LocatedBlob {
+    slot_id: u16,
+    path: String,
+    meta: BlobMeta,
+    peers: Vec<NodeInfo>,
+    range: Option<ReadRangeSpec>,
+}
+
+/// Nodes a wide probe asks at once.
+const WIDE_PROBE_CONCURRENCY: usize = 8;
+
+/// How long a wide probe waits for one node's head.
+const WIDE_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Permanent failures of one blob generation before its repair is parked.
+const REPAIR_PARK_AFTER_FAILURES: u32 = 3;
+
+/// Whether a failed part fetch means the source has no good copy, rather
+/// than that it could not be reached.
+fn is_permanent_repair_error(error: &RimError) -> bool {
+    matches!(
+        error,
+        RimError::PartNotFound(_) | RimError::BlobNotFound(_) | RimError::HashMismatch { .. }
+    )
+}
+
+/// Where a repair can fetch a missing part from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PartSource {
+    /// A file on this node with the same bytes, kept for another path or
+    /// generation.
+    LocalCopy(String),
+    Peer(String),
+    Archive(String),
+}
+
+/// What a repair knows about one missing part before fetching it.
+#[derive(Debug, Clone, Default)]
+struct PartAvailability {
+    part_no: u32,
+    sha256: Option<String>,
+    local_copy: Option<String>,
+    /// Peers whose probe found the part on disk.
+    holders: Vec<String>,
+    /// Peers that could not be probed and may still have the part.
+    unprobed: Vec<String>,
+    archive_url: Option<String>,
+}
+
+/// A missing part and its sources, cheapest first.
+#[derive(Debug, Clone)]
+struct PartRepair {
+    part_no: u32,
+    sha256: Option<String>,
+    sources: Vec<PartSource>,
+}
+
+/// Orders the sources of every missing part. Holders are ranked by their
+/// mean latency, `peer_cost`, scaled by the parts already given to them, so
+/// equally fast peers share the transfer and a slow one only gets parts
+/// nobody else has. Peers without latency samples cost as much as the
+/// average measured holder.
+fn assign_part_sources(
+    parts: Vec<PartAvailability>,
+    peer_cost: impl Fn(&str) -> Option<f64>,
+) -> Vec<PartRepair> {
+    let mut assigned: HashMap<String, usize> = HashMap::new();
+
+    parts
+        .into_iter()
+        .map(|part| {
+            let measured: Vec<f64> = part
+                .holders
+                .iter()
+                .filter_map(|node_id| peer_cost(node_id))
+                .collect();
+            let unmeasured_cost = if measured.is_empty() {
+                1.0
+            } else {
+                measured.iter().sum::<f64>() / measured.len() as f64
+            };
+            let cost = |node_id: &String| {
+                let load = assigned.get(node_id).copied().unwrap_or(0) as f64;
+                peer_cost(node_id)
+                    .unwrap_or(unmeasured_cost)
+                    .max(f64::EPSILON)
+                    * (1.0 + load)
+            };
+
+            let mut holders = part.holders;
+            holders.sort_by(|a, b| cost(a).total_cmp(&cost(b)).then_with(|| a.cmp(b)));
+
+            let mut sources = Vec::new();
+            sources.extend(part.local_copy.map(PartSource::LocalCopy));
+            sources.extend(holders.into_iter().map(PartSource::Peer));
+            sources.extend(part.unprobed.into_iter().map(PartSource::Peer));
+            sources.extend(part.archive_url.map(PartSource::Archive));
+
+            if let Some(PartSource::Peer(node_id)) = sources.first() {
+                *assigned.entry(node_id.clone()).or_default() += 1;
+            }
+            PartRepair {
+                part_no: part.part_no,
+                sha256: part.sha256,
+                sources,
+            }
+        })
+        .collect()
+}
+
 impl ReadBlobOperation {
     pub fn new(
         slot_manager: Arc<SlotManager>,
         part_store: Arc<PartStore>,
         cluster_client: Arc<ClusterClient>,
+        wide_probe: WideProbeMode,
     ) -> Self {
         Self {
             slot_manager,
             part_store,
             cluster_client,
+            wide_probe,
+            replication_policy: ReplicationPolicy::default(),
+            repair_log: None,
         }
     }
 
+    /// Records every part fetch a repair makes in the node's repair history,
+    /// and parks repairs that keep finding no good copy of a part.
+    pub fn with_repair_log(mut self, node_store: Arc<NodeStore>) -> Self {
+        self.repair_log = Some(node_store);
+        self
+    }
+
+    /// Keeps repairs from copying paths the prefix policy places elsewhere.
+    pub fn with_replication_policy(mut self, replication_policy: ReplicationPolicy) -> Self {
+        self.replication_policy = replication_policy;
+        self
+    }
+
+    pub fn replication_policy(&self) -> &ReplicationPolicy {
+        &self.replication_policy
+    }
+
     pub async fn run(&self, request: ReadBlobOperationRequest) -> Result<ReadBlobOperationOutcome> {
+        let include_body = request.include_body;
+        let located = match self.locate(request).await? {
+            Located::Found(located) => located,
+            Located::NotFound => return Ok(ReadBlobOperationOutcome::NotFound),
+            Located::Deleted => return Ok(ReadBlobOperationOutcome::Deleted),
+        };
+        let LocatedBlob {
+            slot_id,
+            path,
+            meta,
+            peers,
+            range,
+        } = *located;
+
+        if !include_body {
+            return Ok(ReadBlobOperationOutcome::Found(Box::new(
+                ReadBlobOperationResult {
+                    meta,
+                    body: None,
+                    body_range: None,
+                    partial: false,
+                },
+            )));
+        }
+
+        let Some((body_range, partial)) = resolve_body_range(&meta, range)? else {
+            return Ok(ReadBlobOperationOutcome::Found(Box::new(
+                ReadBlobOperationResult {
+                    meta,
+                    body: Some(Bytes::new()),
+                    body_range: None,
+                    partial: false,
+                },
+            )));
+        };
+
+        let part_size = meta.part_size.max(1);
+        let mut body = Vec::with_capacity((body_range.end - body_range.start + 1) as usize);
+        for part_no in body_range.start / part_size..=body_range.end / part_size {
+            let bytes = self
+                .read_part_slice(&peers, slot_id, &path, &meta, part_no, body_range)
+                .await?;
+            body.extend_from_slice(&bytes);
+        }
+
+        Ok(ReadBlobOperationOutcome::Found(Box::new(
+            ReadBlobOperationResult {
+                meta,
+                body: Some(Bytes::from(body)),
+                body_range: Some(body_range),
+                partial,
+            },
+        )))
+    }
+
+    /// Like [`Self::run`], but yields the body part by part so only one part
+    /// is held in memory. The first part is read before returning, so a blob
+    /// whose data cannot be found still fails before any byte is sent;
+    /// failures on later parts end the stream with an error.
+    pub async fn run_stream(
+        &self,
+        request: ReadBlobOperationRequest,
+    ) -> Result<ReadBlobStreamOutcome> {
+        let located = match self.locate(request).await? {
+            Located::Found(located) => located,
+            Located::NotFound => return Ok(ReadBlobStreamOutcome::NotFound),
+            Located::Deleted => return Ok(ReadBlobStreamOutcome::Deleted),
+        };
+
+        let Some((body_range, partial)) = resolve_body_range(&located.meta, located.range)? else {
+            return Ok(ReadBlobStreamOutcome::Found(Box::new(ReadBlobStream {
+                meta: located.meta,
+                body_range: None,
+                partial: false,
+                body: stream::empty().boxed(),
+            })));
+        };
+
+        let part_size = located.meta.part_size.max(1);
+        let first_part = body_range.start / part_size;
+        let last_part = body_range.end / part_size;
+        let first = self
+            .read_part_slice(
+                &located.peers,
+                located.slot_id,
+                &located.path,
+                &located.meta,
+                first_part,
+                body_range,
+            )
+            .await?;
+
+        let meta = located.meta.clone();
+        let rest = stream::unfold(
+            (self.clone(), located, first_part + 1),
+            move |(operation, located, part_no)| async move {
+                if part_no > last_part {
+                    return None;
+                }
+                let result = operation
+                    .read_part_slice(
+                        &located.peers,
+                        located.slot_id,
+                        &located.path,
+                        &located.meta,
+                        part_no,
+                        body_range,
+                    )
+                    .await;
+                let next = if result.is_ok() {
+                    part_no + 1
+                } else {
+                    last_part + 1
+                };
+                Some((result, (operation, located, next)))
+            },
+        );
+
+        Ok(ReadBlobStreamOutcome::Found(Box::new(ReadBlobStream {
+            meta,
+            body_range: Some(body_range),
+            partial,
+            body: stream::once(async move { Ok(first) }).chain(rest).boxed(),
+        })))
+    }
+
+    /// Lists the parts of the current version with their offsets and the
+    /// nodes holding them. A peer counts as holding every part when its head
+    /// is at the same generation; this node counts for the parts it indexed.
+    pub async fn manifest(
+        &self,
+        request: ReadBlobOperationRequest,
+    ) -> Result<ReadBlobManifestOutcome> {
+        let local_node = request
+            .replicas
+            .iter()
+            .find(|node| node.node_id == request.local_node_id)
+            .cloned();
+        let located = match self.locate(request).await? {
+            Located::Found(located) => located,
+            Located::NotFound => return Ok(ReadBlobManifestOutcome::NotFound),
+            Located::Deleted => return Ok(ReadBlobManifestOutcome::Deleted),
+        };
+        let LocatedBlob {
+            slot_id,
+            path,
+            meta,
+            peers,
+            range: _,
+        } = *located;
+
+        let local_entries = if self.slot_manager.has_slot(slot_id).await {
+            self.ensure_store(slot_id)
+                .await?
+                .list_part_entries(&path, meta.generation)?
+        } else {
+            Vec::new()
+        };
+
+        let heads = join_all(
+            peers
+                .iter()
+                .map(|peer| self.fetch_remote_head(&peer.node_id, slot_id, &path)),
+        )
+        .await;
+        let holders: Vec<&NodeInfo> = peers
+            .iter()
+            .zip(heads)
+            .filter_map(|(peer, head)| match head {
+                Ok(Some(head))
+                    if head.head_kind == HeadKind::Meta && head.generation == meta.generation =>
+                {
+                    Some(peer)
+                }
+                Ok(_) => None,
+                Err(error) => {
+                    tracing::debug!(
+                        "manifest head probe failed: node={} slot={} path={} error={}",
+                        peer.node_id,
+                        slot_id,
+                        path,
+                        error
+                    );
+                    None
+                }
+            })
+            .collect();
+
+        let part_size = meta.part_size.max(1);
+        let part_count = if meta.part_count > 0 {
+            meta.part_count
+        } else {
+            meta.size_bytes.div_ceil(part_size) as u32
+        };
+        let parts = (0..part_count)
+            .map(|part_no| {
+                let offset = part_no as u64 * part_size;
+                let local = local_entries.iter().find(|entry| entry.part_no == part_no);
+                let locality = local_node
+                    .iter()
+                    .filter(|_| local.is_some())
+                    .chain(holders.iter().copied())
+                    .map(|node| node.node_id.clone())
+                    .collect();
+                ReadBlobManifestPart {
+                    part_no,
+                    offset,
+                    length: part_size.min(meta.size_bytes.saturating_sub(offset)),
+                    sha256: local.map(|entry| entry.sha256.clone()),
+                    locality,
+                }
+            })
+            .collect();
+
+        let nodes = local_node
+            .iter()
+            .filter(|_| !local_entries.is_empty())
+            .chain(holders.iter().copied())
+            .map(|node| ReadBlobManifestNode {
+                node_id: node.node_id.clone(),
+                address: node.address.clone(),
+            })
+            .collect();
+
+        Ok(ReadBlobManifestOutcome::Found(ReadBlobManifest {
+            path,
+            slot_id,
+            generation: meta.generation,
+            size_bytes: meta.size_bytes,
+            etag: meta.etag,
+            part_size,
+            archive_url: meta.archive_url,
+            nodes,
+            parts,
+        }))
+    }
+
+    /// Finds the current head of the requested path, probing every node when
+    /// the replicas do not know it.
+    async fn locate(&self, request: ReadBlobOperationRequest) -> Result<Located> {
         let ReadBlobOperationRequest {
             slot_id,
             path,
             replicas,
             local_node_id,
-            include_body,
+            include_body: _,
             range,
+            if_range,
+            consistency,
         } = request;
 
-        let head = self
-            .ensure_head_available(slot_id, &path, &replicas, &local_node_id)
-            .await?;
-        let Some(head) = head else {
-            return Ok(ReadBlobOperationOutcome::NotFound);
+        let head = if consistency == ReadConsistency::One {
+            self.ensure_head_available(slot_id, &path, &replicas, &local_node_id)
+                .await?
+        } else {
+            self.freshest_replica_head(slot_id, &path, &replicas, &local_node_id, consistency)
+                .await?
+        };
+        let (head, probed_node) = match head {
+            Some(head) => (head, None),
+            None => match self
+                .probe_all_nodes(slot_id, &path, &replicas, &local_node_id)
+                .await?
+            {
+                Some((head, node)) => (head, Some(node)),
+                None => return Ok(Located::NotFound),
+            },
         };
 
         if head.head_kind == HeadKind::Tombstone {
-            return Ok(ReadBlobOperationOutcome::Deleted);
+            return Ok(Located::Deleted);
         }
 
         let meta = head
             .meta
             .ok_or_else(|| RimError::Internal("meta payload missing".to_string()))?;
 
-        if !include_body {
-            return Ok(ReadBlobOperationOutcome::Found(ReadBlobOperationResult {
-                meta,
-                body: None,
-                body_range: None,
-            }));
-        }
-
-        if meta.size_bytes == 0 {
-            if range.is_some() {
-                return Err(RimError::InvalidRequest(
-                    "range not satisfiable for empty blob".to_string(),
-                ));
-            }
-
-            return Ok(ReadBlobOperationOutcome::Found(ReadBlobOperationResult {
-                meta,
-                body: Some(Bytes::new()),
-                body_range: None,
-            }));
-        }
-
-        let body_range = resolve_effective_range(meta.size_bytes, range)?;
-        let part_size = meta.part_size.max(1);
-
-        let first_part = body_range.start / part_size;
-        let last_part = body_range.end / part_size;
-
-        let peer_nodes: Vec<NodeInfo> = replicas
+        let peers: Vec<NodeInfo> = replicas
             .into_iter()
             .filter(|node| node.node_id != local_node_id)
+            .chain(probed_node)
             .collect();
+        let range = range.filter(|_| {
+            if_range
+                .as_deref()
+                .is_none_or(|validator| if_range_matches(validator, &meta))
+        });
 
-        let mut body = Vec::with_capacity((body_range.end - body_range.start + 1) as usize);
-        for part_no_u64 in first_part..=last_part {
-            let part_no = u32::try_from(part_no_u64)
-                .map_err(|_| RimError::Internal(format!("part index overflow: {}", part_no_u64)))?;
+        Ok(Located::Found(Box::new(LocatedBlob {
+            slot_id,
+            path,
+            meta,
+            peers,
+            range,
+        })))
+    }
 
-            let bytes = self
-                .read_part_bytes(&peer_nodes, slot_id, &path, &meta, part_no)
-                .await?;
+    /// Reads one part and cuts it down to the part of `body_range` it holds.
+    async fn read_part_slice(
+        &self,
+        peers: &[NodeInfo],
+        slot_id: u16,
+        path: &str,
+        meta: &BlobMeta,
+        part_no_u64: u64,
+        body_range: ReadByteRange,
+    ) -> Result<Bytes> {
+        let part_no = u32::try_from(part_no_u64)
+            .map_err(|_| RimError::Internal(format!("part index overflow: {}", part_no_u64)))?;
 
-            let part_start = part_no_u64 * part_size;
-            let slice_start = if part_no_u64 == first_part {
-                (body_range.start - part_start) as usize
-            } else {
-                0
-            };
-            let slice_end_exclusive = if part_no_u64 == last_part {
-                ((body_range.end - part_start) + 1) as usize
-            } else {
-                bytes.len()
-            };
+        let bytes = self
+            .read_part_bytes(peers, slot_id, path, meta, part_no)
+            .await?;
 
-            if slice_start > slice_end_exclusive || slice_end_exclusive > bytes.len() {
-                return Err(RimError::Internal(format!(
-                    "invalid part slice: path={} generation={} part_no={} start={} end={} len={}",
-                    path,
-                    meta.generation,
-                    part_no,
-                    slice_start,
-                    slice_end_exclusive,
-                    bytes.len()
-                )));
-            }
+        let part_size = meta.part_size.max(1);
+        let part_start = part_no_u64 * part_size;
+        let slice_start = body_range.start.saturating_sub(part_start) as usize;
+        let slice_end_exclusive = if part_no_u64 == body_range.end / part_size {
+            ((body_range.end - part_start) + 1) as usize
+        } else {
+            bytes.len()
+        };
 
-            body.extend_from_slice(&bytes[slice_start..slice_end_exclusive]);
+        if slice_start > slice_end_exclusive || slice_end_exclusive > bytes.len() {
+            return Err(RimError::Internal(format!(
+                "invalid part slice: path={} generation={} part_no={} start={} end={} len={}",
+                path,
+                meta.generation,
+                part_no,
+                slice_start,
+                slice_end_exclusive,
+                bytes.len()
+            )));
         }
 
-        Ok(ReadBlobOperationOutcome::Found(ReadBlobOperationResult {
-            meta,
-            body: Some(Bytes::from(body)),
-            body_range: Some(body_range),
-        }))
+        Ok(bytes.slice(slice_start..slice_end_exclusive))
     }
 
     pub async fn fetch_remote_head(
@@ -216,10 +777,63 @@ impl ReadBlobOperation {
         Ok(())
     }
 
-    pub async fn repair_path_from_head(
+    /// Applies in one transaction the tombstones `source_node_id` holds for
+    /// `paths`, fetched in one round trip, and returns the paths deleted.
+    /// Tombstones carry no parts, so a replica that missed a mass delete
+    /// catches up without a head fetch per path.
+    pub async fn apply_remote_tombstones(
         &self,
         source_node_id: &str,
         slot_id: u16,
+        paths: &[String],
+    ) -> Result<Vec<String>> {
+        let items = self
+            .cluster_client
+            .fetch_tombstones(source_node_id, slot_id, paths)
+            .await?;
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut writes = Vec::with_capacity(items.len());
+        let mut applied = Vec::with_capacity(items.len());
+        for item in items {
+            let mut tombstone = item.tombstone;
+            tombstone.path = item.path.clone();
+            tombstone.slot_id = slot_id;
+            tombstone.generation = item.generation;
+            writes.push(HeadWrite::Tombstone {
+                inline_data: serde_json::to_vec(&tombstone)?,
+                tombstone,
+                head_sha256: item.head_sha256,
+            });
+            applied.push(item.path);
+        }
+
+        let store = self.ensure_store(slot_id).await?;
+        if !store.apply_head_batch(&writes, false)? {
+            return Err(RimError::Internal(format!(
+                "tombstone batch rejected: slot={} tombstones={}",
+                slot_id,
+                writes.len()
+            )));
+        }
+        Ok(applied)
+    }
+
+    /// Copies `remote_head` of `path` and the parts this node lacks.
+    ///
+    /// `sources` are the peers known to have the head, the one it was read
+    /// from first. Before anything moves, each missing part is given a source
+    /// list, cheapest first: a file this node already has with the same bytes,
+    /// then peers that hold the part by their part latency and the parts
+    /// already given to them, then peers that could not be probed, then the
+    /// archive. Each part is then fetched once, from the first of its sources
+    /// that delivers it.
+    pub async fn repair_path_from_head(
+        &self,
+        sources: &[String],
+        slot_id: u16,
         path: &str,
         remote_head: &BlobHead,
     ) -> Result<()> {
@@ -229,64 +843,348 @@ impl ReadBlobOperation {
                 .clone()
                 .ok_or_else(|| RimError::Internal("missing meta payload".to_string()))?;
 
-            let store = self.ensure_store(slot_id).await?;
+            if let Some(parked) = self.parked_repair(slot_id, path, meta.generation) {
+                return Err(RimError::Internal(format!(
+                    "repair parked after {} failures: path={} generation={} last_error={}",
+                    parked.failures, path, meta.generation, parked.last_error
+                )));
+            }
+
+            let repairs = self
+                .plan_part_repairs(sources, slot_id, path, &meta)
+                .await?;
+            if !repairs.is_empty() {
+                tracing::debug!(
+                    "repair plan. slot={} path={} generation={} parts={} sources={:?}",
+                    slot_id,
+                    path,
+                    meta.generation,
+                    repairs.len(),
+                    repairs
+                        .iter()
+                        .map(|repair| repair.sources.first())
+                        .collect::<Vec<_>>()
+                );
+            }
+            for repair in &repairs {
+                if let Err(error) = self.fetch_planned_part(slot_id, path, &meta, repair).await {
+                    self.record_repair_failure(slot_id, path, meta.generation, &error);
+                    return Err(error);
+                }
+            }
+            if !repairs.is_empty()
+                && let Some(repair_log) = self.repair_log.as_ref()
+                && let Err(error) = repair_log.clear_repair_failures(slot_id, path, meta.generation)
+            {
+                tracing::warn!("failed to clear repair failures of {}: {}", path, error);
+            }
+        }
+
+        self.apply_remote_head_locally(slot_id, path, remote_head)
+            .await
+    }
+
+    /// Finds the parts of `meta` missing on this node and where each can
+    /// come from.
+    async fn plan_part_repairs(
+        &self,
+        sources: &[String],
+        slot_id: u16,
+        path: &str,
+        meta: &BlobMeta,
+    ) -> Result<Vec<PartRepair>> {
+        let store = self.ensure_store(slot_id).await?;
+        let mut missing = Vec::new();
+
+        for part_no in 0..meta.part_count {
+            let entry = store.get_part_entry(path, meta.generation, part_no)?;
+            let already_local = entry.as_ref().is_some_and(|entry| {
+                if let Some(external_path) = entry.external_path.as_deref() {
+                    Path::new(external_path).exists()
+                } else {
+                    self.part_store.part_exists(
+                        slot_id,
+                        path,
+                        meta.generation,
+                        part_no,
+                        &entry.sha256,
+                    )
+                }
+            });
+            if already_local {
+                continue;
+            }
+
+            let probes = join_all(sources.iter().map(|node_id| async move {
+                (
+                    node_id,
+                    self.cluster_client
+                        .probe_part(node_id, slot_id, path, meta.generation, part_no)
+                        .await,
+                )
+            }))
+            .await;
+
+            let mut part = PartAvailability {
+                part_no,
+                sha256: entry.as_ref().map(|entry| entry.sha256.clone()),
+                archive_url: entry
+                    .as_ref()
+                    .and_then(|entry| entry.archive_url.clone())
+                    .or_else(|| meta.archive_url.clone()),
+                ..PartAvailability::default()
+            };
+            for (node_id, probe) in probes {
+                match probe {
+                    Ok(Some(sha256)) => {
+                        part.sha256.get_or_insert(sha256);
+                        part.holders.push(node_id.clone());
+                    }
+                    Ok(None) => {}
+                    Err(_) => part.unprobed.push(node_id.clone()),
+                }
+            }
+            if let Some(sha256) = part.sha256.as_deref() {
+                part.local_copy = store
+                    .find_part_external_path(sha256, None)?
+                    .filter(|local_path| Path::new(local_path).exists());
+            }
+            missing.push(part);
+        }
+
+        let latency = self.cluster_client.latency();
+        Ok(assign_part_sources(missing, |node_id| {
+            latency.mean_ms(node_id, PeerCall::PartRead)
+        }))
+    }
+
+    /// Stores one missing part from the first of its sources that has it.
+    /// Every source tried is recorded in the repair history when the
+    /// operation has one.
+    async fn fetch_planned_part(
+        &self,
+        slot_id: u16,
+        path: &str,
+        meta: &BlobMeta,
+        repair: &PartRepair,
+    ) -> Result<()> {
+        // A source that was down says nothing about the data; only when every
+        // source answered that it has no good copy is the failure permanent.
+        let mut last_error = None;
+        let mut last_transient = None;
+
+        for source in &repair.sources {
+            let started = Instant::now();
+            let fetched = self
+                .fetch_part_from_source(slot_id, path, meta, repair, source)
+                .await;
+            self.record_repair(path, meta, repair, source, started, &fetched);
+
+            match fetched {
+                Ok(_) => return Ok(()),
+                Err(error) => {
+                    tracing::warn!(
+                        "repair source failed. source={:?} slot={} path={} part_no={} error={}",
+                        source,
+                        slot_id,
+                        path,
+                        repair.part_no,
+                        error
+                    );
+                    if is_permanent_repair_error(&error) {
+                        last_error = Some(error);
+                    } else {
+                        last_transient = Some(error);
+                    }
+                }
+            }
+        }
+
+        Err(last_transient.or(last_error).unwrap_or_else(|| {
+            RimError::PartNotFound(format!(
+                "no source for part: path={} generation={} part_no={}",
+                path, meta.generation, repair.part_no
+            ))
+        }))
+    }
 
-            for part_no in 0..meta.part_count {
-                let already_local = match store.get_part_entry(path, meta.generation, part_no)? {
-                    Some(entry) => {
-                        if let Some(external_path) = entry.external_path {
-                            Path::new(&external_path).exists()
-                        } else {
-                            self.part_store.part_exists(
+    /// Fetches one part from `source`, checks it and stores it; returns the
+    /// bytes stored.
+    async fn fetch_part_from_source(
+        &self,
+        slot_id: u16,
+        path: &str,
+        meta: &BlobMeta,
+        repair: &PartRepair,
+        source: &PartSource,
+    ) -> Result<u64> {
+        let expected_sha256 = repair.sha256.as_deref();
+        let (bytes, headers) = match source {
+            PartSource::LocalCopy(local_path) => {
+                (Bytes::from(tokio::fs::read(local_path).await?), None)
+            }
+            PartSource::Peer(node_id) => {
+                let payload = match expected_sha256 {
+                    Some(sha256) => {
+                        self.cluster_client
+                            .fetch_part_by_sha(
+                                node_id,
                                 slot_id,
+                                sha256,
                                 path,
                                 meta.generation,
-                                part_no,
-                                &entry.sha256,
+                                repair.part_no,
                             )
-                        }
+                            .await?
+                    }
+                    None => {
+                        self.cluster_client
+                            .fetch_part_by_index(
+                                node_id,
+                                slot_id,
+                                path,
+                                meta.generation,
+                                repair.part_no,
+                            )
+                            .await?
                     }
-                    None => false,
                 };
-
-                if already_local {
-                    continue;
-                }
-
-                let payload = self
-                    .cluster_client
-                    .fetch_part_by_index(source_node_id, slot_id, path, meta.generation, part_no)
-                    .await?;
-
-                let sha256 = resolve_part_sha256(Some(&payload.headers), &payload.bytes, None);
-                let bytes = payload.bytes;
-
-                let put_result = self
-                    .part_store
-                    .put_part(
+                (payload.bytes, Some(payload.headers))
+            }
+            PartSource::Archive(archive_url) => {
+                let bytes = self
+                    .fetch_part_from_archive_and_store(
                         slot_id,
                         path,
-                        meta.generation,
-                        part_no,
-                        &sha256,
-                        bytes.clone(),
+                        meta,
+                        repair.part_no,
+                        expected_sha256,
+                        archive_url,
                     )
                     .await?;
+                return Ok(bytes.len() as u64);
+            }
+        };
 
-                store.upsert_part_entry(
-                    path,
-                    meta.generation,
-                    part_no,
-                    &sha256,
-                    bytes.len() as u64,
-                    Some(put_result.part_path.to_string_lossy().as_ref()),
-                    None,
-                )?;
+        let sha256 = resolve_part_sha256(headers.as_ref(), &bytes, expected_sha256);
+        if let Some(expected) = expected_sha256
+            && sha256 != expected
+        {
+            return Err(RimError::HashMismatch {
+                expected: expected.to_string(),
+                actual: sha256,
+            });
+        }
+
+        let put_result = self
+            .part_store
+            .put_part(
+                slot_id,
+                path,
+                meta.generation,
+                repair.part_no,
+                &sha256,
+                bytes.clone(),
+            )
+            .await?;
+        let store = self.ensure_store(slot_id).await?;
+        store.upsert_part_entry(
+            path,
+            meta.generation,
+            repair.part_no,
+            &sha256,
+            bytes.len() as u64,
+            Some(put_result.part_path.to_string_lossy().as_ref()),
+            None,
+        )?;
+        Ok(bytes.len() as u64)
+    }
+
+    fn record_repair(
+        &self,
+        path: &str,
+        meta: &BlobMeta,
+        repair: &PartRepair,
+        source: &PartSource,
+        started: Instant,
+        fetched: &Result<u64>,
+    ) {
+        let Some(repair_log) = self.repair_log.as_ref() else {
+            return;
+        };
+
+        let (source_kind, source) = match source {
+            PartSource::LocalCopy(local_path) => ("local_copy", local_path),
+            PartSource::Peer(node_id) => ("peer", node_id),
+            PartSource::Archive(archive_url) => ("archive", archive_url),
+        };
+        let attempt = RepairAttempt {
+            slot_id: meta.slot_id,
+            blob_path: path.to_string(),
+            generation: meta.generation,
+            part_no: repair.part_no,
+            source_kind: source_kind.to_string(),
+            source: source.clone(),
+            bytes: *fetched.as_ref().unwrap_or(&0),
+            duration_ms: started.elapsed().as_millis() as u64,
+            ok: fetched.is_ok(),
+            error: fetched.as_ref().err().map(|error| error.to_string()),
+        };
+        if let Err(error) = repair_log.record_repair(&attempt) {
+            tracing::warn!("failed to record repair of {}: {}", path, error);
+        }
+    }
+
+    /// The dead letter of a repair parked in the repair log, if any.
+    pub fn parked_repair(
+        &self,
+        slot_id: u16,
+        path: &str,
+        generation: i64,
+    ) -> Option<RepairDeadLetter> {
+        let repair_log = self.repair_log.as_ref()?;
+        match repair_log.parked_repair(slot_id, path, generation) {
+            Ok(parked) => parked,
+            Err(error) => {
+                tracing::warn!("failed to read dead letter of {}: {}", path, error);
+                None
             }
         }
+    }
+
+    /// Counts a permanent repair failure, parking the repair once it has
+    /// failed `REPAIR_PARK_AFTER_FAILURES` times.
+    fn record_repair_failure(&self, slot_id: u16, path: &str, generation: i64, error: &RimError) {
+        let Some(repair_log) = self.repair_log.as_ref() else {
+            return;
+        };
+        if !is_permanent_repair_error(error) {
+            return;
+        }
 
-        self.apply_remote_head_locally(slot_id, path, remote_head)
-            .await
+        match repair_log.record_repair_failure(
+            slot_id,
+            path,
+            generation,
+            &error.to_string(),
+            REPAIR_PARK_AFTER_FAILURES,
+        ) {
+            Ok(true) => tracing::warn!(
+                "repair parked in dead-letter list. slot={} path={} generation={} error={}",
+                slot_id,
+                path,
+                generation,
+                error
+            ),
+            Ok(false) => {}
+            Err(record_error) => {
+                tracing::warn!(
+                    "failed to record repair failure of {}: {}",
+                    path,
+                    record_error
+                )
+            }
+        }
     }
 
     async fn ensure_head_available(
@@ -312,6 +1210,262 @@ impl ReadBlobOperation {
         Ok(None)
     }
 
+    /// Asks every replica for its head of `path` and returns the one with the
+    /// highest generation, failing when fewer replicas answer than
+    /// `consistency` requires. Replicas found missing or behind are brought
+    /// up to date in the background.
+    async fn freshest_replica_head(
+        &self,
+        slot_id: u16,
+        path: &str,
+        replicas: &[NodeInfo],
+        local_node_id: &str,
+        consistency: ReadConsistency,
+    ) -> Result<Option<BlobHead>> {
+        let store = self.ensure_store(slot_id).await?;
+        let mut answers = vec![(local_node_id.to_string(), store.get_current_head(path)?)];
+        let local_is_replica = replicas.iter().any(|node| node.node_id == local_node_id);
+
+        let remote_heads = join_all(
+            replicas
+                .iter()
+                .filter(|node| node.node_id != local_node_id)
+                .map(|node| async move {
+                    (
+                        node.node_id.clone(),
+                        self.fetch_remote_head(&node.node_id, slot_id, path).await,
+                    )
+                }),
+        )
+        .await;
+        for (node_id, result) in remote_heads {
+            match result {
+                Ok(head) => answers.push((node_id, head)),
+                Err(error) => {
+                    tracing::warn!(
+                        "replica head read failed. node={} slot={} path={} error={}",
+                        node_id,
+                        slot_id,
+                        path,
+                        error
+                    );
+                }
+            }
+        }
+
+        let required = consistency.required(replicas.len());
+        let found = answers.len() - usize::from(!local_is_replica);
+        if found < required {
+            return Err(RimError::InsufficientReplicas { required, found });
+        }
+
+        let Some((source_node_id, freshest)) = answers
+            .iter()
+            .filter_map(|(node_id, head)| head.as_ref().map(|head| (node_id, head)))
+            .max_by_key(|(_, head)| head.generation)
+        else {
+            return Ok(None);
+        };
+
+        let replica_ids: Vec<String> = replicas.iter().map(|node| node.node_id.clone()).collect();
+        let stale: Vec<String> = answers
+            .iter()
+            .filter(|(node_id, head)| {
+                (node_id != local_node_id || local_is_replica)
+                    && head
+                        .as_ref()
+                        .is_none_or(|head| head.generation < freshest.generation)
+                    && self.replication_policy.holds(path, &replica_ids, node_id)
+            })
+            .map(|(node_id, _)| node_id.clone())
+            .collect();
+        if !stale.is_empty() {
+            let mut sources = vec![source_node_id.clone()];
+            sources.extend(
+                answers
+                    .iter()
+                    .filter(|(node_id, head)| {
+                        node_id != source_node_id
+                            && node_id != local_node_id
+                            && head
+                                .as_ref()
+                                .is_some_and(|head| head.generation == freshest.generation)
+                    })
+                    .map(|(node_id, _)| node_id.clone()),
+            );
+            self.spawn_read_repair(
+                sources,
+                stale,
+                slot_id,
+                path.to_string(),
+                freshest.clone(),
+                local_node_id.to_string(),
+            );
+        }
+
+        Ok(Some(freshest.clone()))
+    }
+
+    /// Copies `head` onto each stale replica: this node repairs itself from
+    /// `sources`, the replicas holding it, and other replicas are asked to
+    /// pull from the first of them.
+    fn spawn_read_repair(
+        &self,
+        sources: Vec<String>,
+        stale: Vec<String>,
+        slot_id: u16,
+        path: String,
+        head: BlobHead,
+        local_node_id: String,
+    ) {
+        let operation = self.clone();
+        tokio::spawn(async move {
+            for node_id in stale {
+                if node_id == local_node_id
+                    && operation
+                        .parked_repair(slot_id, &path, head.generation)
+                        .is_some()
+                {
+                    tracing::debug!(
+                        "read repair skipped, parked. slot={} path={} generation={}",
+                        slot_id,
+                        path,
+                        head.generation
+                    );
+                    continue;
+                }
+                let result = if node_id == local_node_id {
+                    operation
+                        .repair_path_from_head(&sources, slot_id, &path, &head)
+                        .await
+                } else {
+                    operation
+                        .cluster_client
+                        .request_replica_pull(&node_id, slot_id, &path, &sources[0])
+                        .await
+                };
+                match result {
+                    Ok(()) => tracing::info!(
+                        "read repair applied. node={} slot={} path={} generation={}",
+                        node_id,
+                        slot_id,
+                        path,
+                        head.generation
+                    ),
+                    Err(error) => tracing::warn!(
+                        "read repair failed. node={} slot={} path={} error={}",
+                        node_id,
+                        slot_id,
+                        path,
+                        error
+                    ),
+                }
+            }
+        });
+    }
+
+    /// Asks every registered node outside the replica set for the path.
+    ///
+    /// Up to [`WIDE_PROBE_CONCURRENCY`] nodes are asked at once and the first
+    /// head found wins; nodes that fail or take longer than
+    /// [`WIDE_PROBE_TIMEOUT`] are skipped. In repair mode the blob found is
+    /// copied onto this node so later reads no longer need the probe.
+    async fn probe_all_nodes(
+        &self,
+        slot_id: u16,
+        path: &str,
+        replicas: &[NodeInfo],
+        local_node_id: &str,
+    ) -> Result<Option<(BlobHead, NodeInfo)>> {
+        if self.wide_probe == WideProbeMode::Off {
+            return Ok(None);
+        }
+
+        let nodes: Vec<NodeInfo> = self
+            .cluster_client
+            .nodes()
+            .await?
+            .into_iter()
+            .filter(|node| {
+                node.node_id != local_node_id
+                    && !replicas
+                        .iter()
+                        .any(|replica| replica.node_id == node.node_id)
+            })
+            .collect();
+        let mut probes = stream::iter(nodes)
+            .map(|node| async move {
+                let head = tokio::time::timeout(
+                    WIDE_PROBE_TIMEOUT,
+                    self.fetch_remote_head(&node.node_id, slot_id, path),
+                )
+                .await
+                .unwrap_or_else(|_| {
+                    Err(RimError::Http(format!(
+                        "wide probe timed out: node={}",
+                        node.node_id
+                    )))
+                });
+                (node, head)
+            })
+            .buffer_unordered(WIDE_PROBE_CONCURRENCY);
+
+        while let Some((node, head)) = probes.next().await {
+            let remote_head = match head {
+                Ok(Some(remote_head)) => remote_head,
+                Ok(None) => continue,
+                Err(error) => {
+                    tracing::warn!(
+                        "wide probe failed. node={} slot={} path={} error={}",
+                        node.node_id,
+                        slot_id,
+                        path,
+                        error
+                    );
+                    continue;
+                }
+            };
+
+            tracing::info!(
+                "wide probe found path outside replica set. node={} slot={} path={} generation={}",
+                node.node_id,
+                slot_id,
+                path,
+                remote_head.generation
+            );
+
+            let replica_ids: Vec<String> = replicas
+                .iter()
+                .map(|replica| replica.node_id.clone())
+                .collect();
+            if self.wide_probe == WideProbeMode::Repair
+                && self
+                    .replication_policy
+                    .holds(path, &replica_ids, local_node_id)
+                && let Err(error) = self
+                    .repair_path_from_head(
+                        std::slice::from_ref(&node.node_id),
+                        slot_id,
+                        path,
+                        &remote_head,
+                    )
+                    .await
+            {
+                tracing::warn!(
+                    "wide probe repair failed. node={} slot={} path={} error={}",
+                    node.node_id,
+                    slot_id,
+                    path,
+                    error
+                );
+            }
+
+            return Ok(Some((remote_head, node)));
+        }
+
+        Ok(None)
+    }
+
     async fn read_part_bytes(
         &self,
         peers: &[NodeInfo],
@@ -321,6 +1475,7 @@ impl ReadBlobOperation {
         part_no: u32,
     ) -> Result<Bytes> {
         let store = self.ensure_store(slot_id).await?;
+        let mut archive_error = None;
 
         if let Some(entry) = store.get_part_entry(path, meta.generation, part_no)? {
             if let Ok(local) = self
@@ -361,6 +1516,7 @@ impl ReadBlobOperation {
                             archive_url,
                             error
                         );
+                        archive_error = Some(error);
                     }
                 }
             }
@@ -374,7 +1530,8 @@ impl ReadBlobOperation {
                     part_no,
                     Some(entry.sha256.as_str()),
                 )
-                .await;
+                .await
+                .map_err(|error| prefer_archive_unavailable(error, archive_error));
         }
 
         if let Some(archive_url) = meta.archive_url.as_deref() {
@@ -393,12 +1550,14 @@ impl ReadBlobOperation {
                         archive_url,
                         error
                     );
+                    archive_error = Some(error);
                 }
             }
         }
 
         self.fetch_part_from_peers_and_store(peers, slot_id, path, meta.generation, part_no, None)
             .await
+            .map_err(|error| prefer_archive_unavailable(error, archive_error))
     }
 
     async fn read_local_part(
@@ -443,6 +1602,12 @@ impl ReadBlobOperation {
         archive_url: &str,
     ) -> Result<Bytes> {
         let (range_start, range_end) = part_byte_range(meta, part_no)?;
+        // Tiered parts are objects of their own rather than ranges of the blob.
+        let (range_start, range_end) = if is_archived_part_url(archive_url) {
+            (0, range_end - range_start)
+        } else {
+            (range_start, range_end)
+        };
         let bytes = fetch_archive_range_bytes(archive_url, range_start, range_end).await?;
 
         let expected_length = (range_end - range_start + 1) as usize;
@@ -566,27 +1731,44 @@ impl ReadBlobOperation {
     }
 }
 
-fn resolve_effective_range(
-    size_bytes: u64,
-    requested: Option<ReadByteRange>,
-) -> Result<ReadByteRange> {
-    match requested {
-        Some(range) => {
-            if range.start > range.end || range.end >= size_bytes {
-                return Err(RimError::InvalidRequest(format!(
-                    "range not satisfiable: start={} end={} size={}",
-                    range.start, range.end, size_bytes
-                )));
-            }
-            Ok(range)
-        }
-        None => Ok(ReadByteRange {
-            start: 0,
-            end: size_bytes - 1,
-        }),
+/// The range of a body read and whether it is a requested range, or `None`
+/// for a whole empty blob. A requested range selecting nothing fails with
+/// [`RimError::RangeNotSatisfiable`].
+fn resolve_body_range(
+    meta: &BlobMeta,
+    range: Option<ReadRangeSpec>,
+) -> Result<Option<(ReadByteRange, bool)>> {
+    let size_bytes = meta.size_bytes;
+    match range {
+        Some(spec) => spec
+            .resolve(size_bytes)
+            .map(|range| Some((range, true)))
+            .ok_or(RimError::RangeNotSatisfiable { size_bytes }),
+        None if size_bytes == 0 => Ok(None),
+        None => Ok(Some((
+            ReadByteRange {
+                start: 0,
+                end: size_bytes - 1,
+            },
+            false,
+        ))),
     }
 }
 
+/// An `If-Range` validator holds when it is the blob's strong entity tag or
+/// its Last-Modified date to the second; weak tags never hold.
+fn if_range_matches(validator: &str, meta: &BlobMeta) -> bool {
+    let validator = validator.trim();
+    if validator.starts_with('"') || validator.starts_with("W/") {
+        return validator
+            .strip_prefix('"')
+            .and_then(|tag| tag.strip_suffix('"'))
+            == Some(meta.etag.as_str());
+    }
+    chrono::DateTime::parse_from_rfc2822(validator)
+        .is_ok_and(|date| date.timestamp() == meta.updated_at.timestamp())
+}
+
 fn resolve_part_sha256(
     headers: Option<&HeaderMap>,
     body: &[u8],
@@ -632,6 +1814,98 @@ fn part_byte_range(meta: &BlobMeta, part_no: u32) -> Result<(u64, u64)> {
     Ok((start, end))
 }
 
+/// When neither peers nor the archive could serve a part, reports a
+/// transiently unavailable archive over the peer error, so callers learn that
+/// waiting may help.
+fn prefer_archive_unavailable(peer_error: RimError, archive_error: Option<RimError>) -> RimError {
+    match archive_error {
+        Some(error @ RimError::ArchiveUnavailable { .. }) => error,
+        _ => peer_error,
+    }
+}
+
 async fn fetch_archive_range_bytes(archive_url: &str, start: u64, end: u64) -> Result<Bytes> {
     crate::read_archive_range_bytes(archive_url, start, end).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_specs_resolve_against_the_blob_size() {
+        let parse = |value| ReadRangeSpec::parse(value).unwrap();
+        let range = |start, end| Some(ReadByteRange { start, end });
+
+        assert_eq!(parse("bytes=2-5").resolve(10), range(2, 5));
+        assert_eq!(parse("bytes=4-100").resolve(10), range(4, 9));
+        assert_eq!(parse("bytes=7-").resolve(10), range(7, 9));
+        assert_eq!(parse("bytes=-3").resolve(10), range(7, 9));
+        assert_eq!(parse("bytes=-30").resolve(10), range(0, 9));
+        assert_eq!(parse("bytes=10-").resolve(10), None);
+        assert_eq!(parse("bytes=-0").resolve(10), None);
+        assert_eq!(parse("bytes=0-0").resolve(0), None);
+
+        assert!(ReadRangeSpec::parse("bytes=5-2").is_err());
+        assert!(ReadRangeSpec::parse("bytes=0-1,4-5").is_err());
+        assert!(ReadRangeSpec::parse("items=0-1").is_err());
+    }
+
+    #[test]
+    fn repair_parts_come_once_from_the_cheapest_source() {
+        let peers = |nodes: &[&str]| nodes.iter().map(|node| node.to_string()).collect();
+        let part = |part_no, holders: &[&str]| PartAvailability {
+            part_no,
+            holders: peers(holders),
+            ..PartAvailability::default()
+        };
+        let cost = |node_id: &str| match node_id {
+            "fast-a" | "fast-b" => Some(2.0),
+            "slow" => Some(40.0),
+            _ => None,
+        };
+        let first = |repairs: &[PartRepair]| -> Vec<PartSource> {
+            repairs
+                .iter()
+                .map(|repair| repair.sources[0].clone())
+                .collect()
+        };
+        let peer = |node: &str| PartSource::Peer(node.to_string());
+
+        let repairs = assign_part_sources(
+            vec![
+                part(0, &["slow", "fast-a", "fast-b"]),
+                part(1, &["slow", "fast-a", "fast-b"]),
+                part(2, &["slow", "fast-a", "fast-b"]),
+                part(3, &["slow"]),
+            ],
+            cost,
+        );
+        assert_eq!(
+            first(&repairs),
+            vec![peer("fast-a"), peer("fast-b"), peer("fast-a"), peer("slow")]
+        );
+        assert_eq!(repairs[0].sources.len(), 3);
+
+        let repairs = assign_part_sources(
+            vec![PartAvailability {
+                part_no: 0,
+                sha256: Some("abc".to_string()),
+                local_copy: Some("/data/other".to_string()),
+                holders: peers(&["fast-a"]),
+                unprobed: peers(&["old"]),
+                archive_url: Some("s3://bucket/blob".to_string()),
+            }],
+            cost,
+        );
+        assert_eq!(
+            repairs[0].sources,
+            vec![
+                PartSource::LocalCopy("/data/other".to_string()),
+                peer("fast-a"),
+                peer("old"),
+                PartSource::Archive("s3://bucket/blob".to_string()),
+            ]
+        );
+    }
+}
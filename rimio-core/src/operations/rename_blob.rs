@@ -0,0 +1,672 @@
+//! Renaming a blob.
+//!
+//! Within one slot the destination becomes a new head over the source's
+//! parts, metadata, tags and content headers, written by the same two-phase
+//! commit as the source tombstone, so readers see either the old or the new
+//! name and never both or neither, and no body is copied. That needs every
+//! part of the source on this node and replicas that can link parts.
+//! Otherwise, and always across slots, the rename runs in two steps:
+//!
+//! 1. the source is streamed into the destination, provided the destination
+//!    is still at the generation the rename started from;
+//! 2. the source is tombstoned, provided it is still at the generation that
+//!    was copied.
+//!
+//! When step 2 fails the destination is put back: the version it replaced is
+//! linked again as a new head, or a destination that did not exist is
+//! deleted. Between the steps both names resolve to the blob, so a crash
+//! there leaves a copy rather than losing data.
+
+use super::put_blob::held_parts;
+use crate::{
+    BlobHead, BlobMeta, ClusterClient, CommitBatchEntry, CommitBatchItem, HeadKind, MetadataStore,
+    NodeInfo, PartSource, PutBlobOperation, PutBlobOperationOutcome, PutBlobStreamRequest,
+    ReadBlobOperation, ReadBlobOperationRequest, ReadBlobStreamOutcome, ReadConsistency, Result,
+    RimError, SlotManager, TwoPhaseCommit, TwoPhaseCommitRequest, TwoPhaseOutcome,
+    TwoPhaseParticipant, WriteConsistency, cluster::CAP_PART_LINK,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct RenameBlobOperation {
+    slot_manager: Arc<SlotManager>,
+    read_blob_operation: Arc<ReadBlobOperation>,
+    put_blob_operation: Arc<PutBlobOperation>,
+    two_phase_commit: Arc<TwoPhaseCommit>,
+    cluster_client: Arc<ClusterClient>,
+}
+
+/// One side of a rename: a path with its slot and the slot's replicas.
+#[derive(Debug, Clone)]
+pub struct RenameBlobTarget {
+    pub slot_id: u16,
+    pub path: String,
+    pub replicas: Vec<NodeInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenameBlobOperationRequest {
+    pub source: RenameBlobTarget,
+    pub destination: RenameBlobTarget,
+    /// Replace an existing destination instead of refusing the rename.
+    pub overwrite: bool,
+    pub local_node_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenameBlobOperationResult {
+    pub destination: CommitBatchItem,
+    /// Generation of the tombstone left at the source.
+    pub source_generation: i64,
+    /// Whether both heads were committed by one transaction.
+    pub atomic: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum RenameBlobOperationOutcome {
+    Renamed(RenameBlobOperationResult),
+    SourceNotFound,
+    DestinationExists {
+        generation: i64,
+    },
+    /// Either path changed while renaming or a slot missed its write quorum;
+    /// neither path was changed.
+    Conflict,
+}
+
+impl RenameBlobOperation {
+    pub fn new(
+        slot_manager: Arc<SlotManager>,
+        read_blob_operation: Arc<ReadBlobOperation>,
+        put_blob_operation: Arc<PutBlobOperation>,
+        two_phase_commit: Arc<TwoPhaseCommit>,
+        cluster_client: Arc<ClusterClient>,
+    ) -> Self {
+        Self {
+            slot_manager,
+            read_blob_operation,
+            put_blob_operation,
+            two_phase_commit,
+            cluster_client,
+        }
+    }
+
+    pub async fn run(
+        &self,
+        request: RenameBlobOperationRequest,
+    ) -> Result<RenameBlobOperationOutcome> {
+        let RenameBlobOperationRequest {
+            source,
+            destination,
+            overwrite,
+            local_node_id,
+        } = request;
+
+        if source.path == destination.path {
+            return Err(RimError::InvalidRequest(
+                "source and destination are the same path".to_string(),
+            ));
+        }
+
+        let Some(source_meta) = live_meta(self.sync_local_head(&source, &local_node_id).await?)
+        else {
+            return Ok(RenameBlobOperationOutcome::SourceNotFound);
+        };
+        let source_generation = source_meta.generation;
+        let replaced = live_meta(self.sync_local_head(&destination, &local_node_id).await?);
+        let destination_generation = replaced.as_ref().map_or(0, |meta| meta.generation);
+        if destination_generation > 0 && !overwrite {
+            return Ok(RenameBlobOperationOutcome::DestinationExists {
+                generation: destination_generation,
+            });
+        }
+
+        if source.slot_id == destination.slot_id
+            && self.can_link(&source, &source_meta, &local_node_id).await?
+        {
+            let entries = vec![
+                CommitBatchEntry::Link {
+                    path: destination.path.clone(),
+                    source: Box::new(source_meta),
+                },
+                CommitBatchEntry::Delete {
+                    path: source.path.clone(),
+                },
+            ];
+            let expected = BTreeMap::from([
+                (destination.path.clone(), destination_generation),
+                (source.path.clone(), source_generation),
+            ]);
+            let Some(items) = self
+                .commit(&destination, entries, expected, &local_node_id)
+                .await?
+            else {
+                return Ok(RenameBlobOperationOutcome::Conflict);
+            };
+            return renamed(items, &destination.path, &source.path, true);
+        }
+
+        let Some(written) = self
+            .copy(
+                &source,
+                source_generation,
+                &destination,
+                destination_generation,
+                &local_node_id,
+            )
+            .await?
+        else {
+            return Ok(RenameBlobOperationOutcome::Conflict);
+        };
+        let written_generation = written.generation;
+
+        let delete = vec![CommitBatchEntry::Delete {
+            path: source.path.clone(),
+        }];
+        let expected = BTreeMap::from([(source.path.clone(), source_generation)]);
+        let removed = self.commit(&source, delete, expected, &local_node_id).await;
+        if let Ok(Some(mut items)) = removed {
+            items.push(written);
+            return renamed(items, &destination.path, &source.path, false);
+        }
+
+        let rollback = match replaced {
+            Some(meta) => CommitBatchEntry::Link {
+                path: destination.path.clone(),
+                source: Box::new(meta),
+            },
+            None => CommitBatchEntry::Delete {
+                path: destination.path.clone(),
+            },
+        };
+        let expected = BTreeMap::from([(destination.path.clone(), written_generation)]);
+        match self
+            .commit(&destination, vec![rollback], expected, &local_node_id)
+            .await
+        {
+            Ok(Some(_)) => {
+                tracing::info!(
+                    "Rename rolled back: source={} destination={}",
+                    source.path,
+                    destination.path
+                );
+                removed.map(|_| RenameBlobOperationOutcome::Conflict)
+            }
+            Ok(None) | Err(_) => Err(RimError::Internal(format!(
+                "rename could not remove the source nor roll back the destination: source={} destination={} generation={}",
+                source.path, destination.path, written_generation
+            ))),
+        }
+    }
+
+    /// Brings this node's head of the target up to the freshest replica's, so
+    /// the generation checks of the commit compare against the live version
+    /// even when this node is not a replica or is behind.
+    async fn sync_local_head(
+        &self,
+        target: &RenameBlobTarget,
+        local_node_id: &str,
+    ) -> Result<Option<BlobHead>> {
+        let store = self.ensure_store(target.slot_id).await?;
+        let mut freshest = store.get_current_head(&target.path)?;
+        let mut behind = false;
+
+        for node in target
+            .replicas
+            .iter()
+            .filter(|node| node.node_id != local_node_id)
+        {
+            let remote = match self
+                .read_blob_operation
+                .fetch_remote_head(&node.node_id, target.slot_id, &target.path)
+                .await
+            {
+                Ok(remote) => remote,
+                Err(error) => {
+                    tracing::debug!(
+                        "rename head read failed: node={} path={} error={}",
+                        node.node_id,
+                        target.path,
+                        error
+                    );
+                    continue;
+                }
+            };
+            if let Some(remote) = remote
+                && freshest
+                    .as_ref()
+                    .is_none_or(|head| head.generation < remote.generation)
+            {
+                freshest = Some(remote);
+                behind = true;
+            }
+        }
+
+        if behind && let Some(head) = &freshest {
+            self.read_blob_operation
+                .apply_remote_head_locally(target.slot_id, &target.path, head)
+                .await?;
+        }
+        Ok(freshest)
+    }
+
+    /// Whether the destination can take the source's parts as they are:
+    /// this node holds all of them and every other replica can link them.
+    async fn can_link(
+        &self,
+        target: &RenameBlobTarget,
+        meta: &BlobMeta,
+        local_node_id: &str,
+    ) -> Result<bool> {
+        let store = self.ensure_store(target.slot_id).await?;
+        let source = PartSource {
+            path: meta.path.clone(),
+            generation: meta.generation,
+            part_count: meta.part_count,
+        };
+        if held_parts(&store, &source)?.is_none() {
+            return Ok(false);
+        }
+        for node in target
+            .replicas
+            .iter()
+            .filter(|node| node.node_id != local_node_id)
+        {
+            let links = self
+                .cluster_client
+                .peer_protocol(&node.node_id)
+                .await
+                .is_ok_and(|protocol| protocol.supports(CAP_PART_LINK));
+            if !links {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Streams the source into the destination with its metadata, tags and
+    /// content headers. `None` once the source is no longer at
+    /// `source_generation` or the destination moved past
+    /// `destination_generation`.
+    async fn copy(
+        &self,
+        source: &RenameBlobTarget,
+        source_generation: i64,
+        destination: &RenameBlobTarget,
+        destination_generation: i64,
+        local_node_id: &str,
+    ) -> Result<Option<CommitBatchItem>> {
+        let outcome = self
+            .read_blob_operation
+            .run_stream(ReadBlobOperationRequest {
+                slot_id: source.slot_id,
+                path: source.path.clone(),
+                replicas: source.replicas.clone(),
+                local_node_id: local_node_id.to_string(),
+                include_body: true,
+                range: None,
+                if_range: None,
+                consistency: ReadConsistency::One,
+            })
+            .await?;
+        let stream = match outcome {
+            ReadBlobStreamOutcome::Found(stream) if stream.meta.generation == source_generation => {
+                stream
+            }
+            _ => return Ok(None),
+        };
+        let meta = stream.meta;
+
+        let outcome = self
+            .put_blob_operation
+            .run_stream(
+                PutBlobStreamRequest {
+                    path: destination.path.clone(),
+                    slot_id: destination.slot_id,
+                    replicas: destination.replicas.clone(),
+                    local_node_id: local_node_id.to_string(),
+                    consistency: WriteConsistency::Quorum,
+                    expected_generation: Some(destination_generation),
+                    metadata: meta.metadata,
+                    tags: meta.tags,
+                    content: meta.content,
+                },
+                stream.body,
+            )
+            .await?;
+
+        Ok(match outcome {
+            PutBlobOperationOutcome::Committed(result) => Some(CommitBatchItem {
+                path: destination.path.clone(),
+                deleted: false,
+                generation: result.generation,
+                etag: Some(result.etag),
+                size_bytes: result.size_bytes,
+            }),
+            PutBlobOperationOutcome::Conflict
+            | PutBlobOperationOutcome::PreconditionFailed { .. } => None,
+        })
+    }
+
+    /// Commits `entries` to the target's slot, or returns `None` when the
+    /// transaction was aborted or a path moved past its expected generation.
+    async fn commit(
+        &self,
+        target: &RenameBlobTarget,
+        entries: Vec<CommitBatchEntry>,
+        expected_generations: BTreeMap<String, i64>,
+        local_node_id: &str,
+    ) -> Result<Option<Vec<CommitBatchItem>>> {
+        let outcome = self
+            .two_phase_commit
+            .perform_2pc(TwoPhaseCommitRequest {
+                txn_id: format!("txn-{}", ulid::Ulid::new()),
+                participants: vec![TwoPhaseParticipant {
+                    slot_id: target.slot_id,
+                    replicas: target.replicas.clone(),
+                    entries,
+                    expected_generations,
+                }],
+                local_node_id: local_node_id.to_string(),
+            })
+            .await?;
+
+        Ok(match outcome {
+            TwoPhaseOutcome::Committed(results) => Some(
+                results
+                    .into_iter()
+                    .flat_map(|result| result.items)
+                    .collect(),
+            ),
+            TwoPhaseOutcome::Aborted(_) | TwoPhaseOutcome::Conflict => None,
+        })
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
+
+fn live_meta(head: Option<BlobHead>) -> Option<BlobMeta> {
+    head.filter(|head| head.head_kind == HeadKind::Meta)
+        .and_then(|head| head.meta)
+}
+
+fn renamed(
+    items: Vec<CommitBatchItem>,
+    destination: &str,
+    source: &str,
+    atomic: bool,
+) -> Result<RenameBlobOperationOutcome> {
+    let source_generation = items
+        .iter()
+        .find(|item| item.path == source)
+        .map_or(0, |item| item.generation);
+    let destination = items
+        .into_iter()
+        .find(|item| item.path == destination)
+        .ok_or_else(|| RimError::Internal("rename committed without destination".to_string()))?;
+    Ok(RenameBlobOperationOutcome::Renamed(
+        RenameBlobOperationResult {
+            destination,
+            source_generation,
+            atomic,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ContentHeaders, Coordinator, NodeStatus, PartStore, ReadBlobOperationOutcome,
+        WideProbeMode, registry::memory::MemoryRegistry,
+    };
+    use bytes::Bytes;
+
+    struct Node {
+        _dir: tempfile::TempDir,
+        put: Arc<PutBlobOperation>,
+        read: Arc<ReadBlobOperation>,
+        rename: RenameBlobOperation,
+    }
+
+    fn node() -> Node {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().join("slots")).unwrap());
+        let part_store = Arc::new(PartStore::new(dir.path().join("parts")).unwrap());
+        let coordinator = Arc::new(Coordinator::new(2));
+        let cluster_client = Arc::new(ClusterClient::new(Arc::new(MemoryRegistry::new())));
+        let read = Arc::new(ReadBlobOperation::new(
+            slot_manager.clone(),
+            part_store.clone(),
+            cluster_client.clone(),
+            WideProbeMode::Off,
+        ));
+        let put = Arc::new(PutBlobOperation::new(
+            slot_manager.clone(),
+            part_store.clone(),
+            coordinator.clone(),
+            cluster_client.clone(),
+            None,
+        ));
+        let two_phase_commit = Arc::new(TwoPhaseCommit::new(
+            slot_manager.clone(),
+            part_store,
+            coordinator,
+            cluster_client.clone(),
+        ));
+        let rename = RenameBlobOperation::new(
+            slot_manager,
+            read.clone(),
+            put.clone(),
+            two_phase_commit,
+            cluster_client,
+        );
+        Node {
+            _dir: dir,
+            put,
+            read,
+            rename,
+        }
+    }
+
+    fn replica(node_id: &str) -> NodeInfo {
+        NodeInfo {
+            node_id: node_id.to_string(),
+            group_id: "default".to_string(),
+            address: "127.0.0.1:1".to_string(),
+            status: NodeStatus::Healthy,
+            slots: Vec::new(),
+            grpc_address: None,
+        }
+    }
+
+    fn target(slot_id: u16, path: &str) -> RenameBlobTarget {
+        RenameBlobTarget {
+            slot_id,
+            path: path.to_string(),
+            replicas: vec![replica("node-a")],
+        }
+    }
+
+    fn request(
+        source: RenameBlobTarget,
+        destination: RenameBlobTarget,
+        overwrite: bool,
+    ) -> RenameBlobOperationRequest {
+        RenameBlobOperationRequest {
+            source,
+            destination,
+            overwrite,
+            local_node_id: "node-a".to_string(),
+        }
+    }
+
+    async fn put(node: &Node, target: &RenameBlobTarget, body: &'static str, owner: &str) {
+        let outcome = node
+            .put
+            .run_stream(
+                PutBlobStreamRequest {
+                    path: target.path.clone(),
+                    slot_id: target.slot_id,
+                    replicas: target.replicas.clone(),
+                    local_node_id: "node-a".to_string(),
+                    consistency: WriteConsistency::Quorum,
+                    expected_generation: None,
+                    metadata: BTreeMap::from([("owner".to_string(), owner.to_string())]),
+                    tags: BTreeMap::from([("env".to_string(), "prod".to_string())]),
+                    content: ContentHeaders {
+                        content_type: Some("text/plain".to_string()),
+                        ..ContentHeaders::default()
+                    },
+                },
+                futures_util::stream::iter([Ok(Bytes::from(body))]),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(outcome, PutBlobOperationOutcome::Committed(_)));
+    }
+
+    async fn read(node: &Node, target: &RenameBlobTarget) -> Option<(BlobMeta, Bytes)> {
+        let outcome = node
+            .read
+            .run(ReadBlobOperationRequest {
+                slot_id: target.slot_id,
+                path: target.path.clone(),
+                replicas: target.replicas.clone(),
+                local_node_id: "node-a".to_string(),
+                include_body: true,
+                range: None,
+                if_range: None,
+                consistency: ReadConsistency::One,
+            })
+            .await
+            .unwrap();
+        match outcome {
+            ReadBlobOperationOutcome::Found(result) => {
+                Some((result.meta, result.body.unwrap_or_default()))
+            }
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn same_slot_renames_link_the_parts_in_one_transaction() {
+        let node = node();
+        let (source, destination) = (target(1, "incoming/a.tmp"), target(1, "clips/a.txt"));
+        put(&node, &source, "clip", "alice").await;
+
+        let outcome = node
+            .rename
+            .run(request(source.clone(), destination.clone(), false))
+            .await
+            .unwrap();
+        let RenameBlobOperationOutcome::Renamed(result) = outcome else {
+            panic!("rename failed: {:?}", outcome);
+        };
+        assert!(result.atomic);
+        assert!(result.source_generation > 0);
+
+        let (meta, body) = read(&node, &destination).await.unwrap();
+        assert_eq!(body, Bytes::from("clip"));
+        assert_eq!(meta.metadata["owner"], "alice");
+        assert_eq!(meta.tags["env"], "prod");
+        assert_eq!(meta.content.content_type.as_deref(), Some("text/plain"));
+        assert!(read(&node, &source).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn cross_slot_renames_stream_the_blob_with_its_meta() {
+        let node = node();
+        let (source, destination) = (target(1, "incoming/a.tmp"), target(2, "clips/a.txt"));
+        put(&node, &source, "clip", "alice").await;
+
+        let outcome = node
+            .rename
+            .run(request(source.clone(), destination.clone(), false))
+            .await
+            .unwrap();
+        let RenameBlobOperationOutcome::Renamed(result) = outcome else {
+            panic!("rename failed: {:?}", outcome);
+        };
+        assert!(!result.atomic);
+
+        let (meta, body) = read(&node, &destination).await.unwrap();
+        assert_eq!(body, Bytes::from("clip"));
+        assert_eq!(meta.metadata["owner"], "alice");
+        assert_eq!(meta.tags["env"], "prod");
+        assert_eq!(meta.content.content_type.as_deref(), Some("text/plain"));
+        assert!(read(&node, &source).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn existing_destinations_are_kept_without_overwrite() {
+        let node = node();
+        let (source, destination) = (target(1, "incoming/a.tmp"), target(1, "clips/a.txt"));
+        put(&node, &source, "new", "alice").await;
+        put(&node, &destination, "old", "bob").await;
+
+        let outcome = node
+            .rename
+            .run(request(source.clone(), destination.clone(), false))
+            .await
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            RenameBlobOperationOutcome::DestinationExists { generation } if generation > 0
+        ));
+        assert_eq!(read(&node, &source).await.unwrap().1, Bytes::from("new"));
+        assert_eq!(
+            read(&node, &destination).await.unwrap().1,
+            Bytes::from("old")
+        );
+
+        let outcome = node
+            .rename
+            .run(request(source, destination.clone(), true))
+            .await
+            .unwrap();
+        assert!(matches!(outcome, RenameBlobOperationOutcome::Renamed(_)));
+        assert_eq!(
+            read(&node, &destination).await.unwrap().1,
+            Bytes::from("new")
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_source_removal_restores_the_replaced_destination() {
+        let node = node();
+        let source = target(1, "incoming/a.tmp");
+        let destination = target(2, "clips/a.txt");
+        put(&node, &source, "new", "alice").await;
+        put(&node, &destination, "old", "bob").await;
+        let replaced = read(&node, &destination).await.unwrap().0.generation;
+
+        // A second source replica that never answers leaves the tombstone
+        // short of its quorum.
+        let mut unreachable = source.clone();
+        unreachable.replicas.push(replica("node-x"));
+        let outcome = node
+            .rename
+            .run(request(unreachable, destination.clone(), true))
+            .await
+            .unwrap();
+        assert!(matches!(outcome, RenameBlobOperationOutcome::Conflict));
+
+        assert_eq!(read(&node, &source).await.unwrap().1, Bytes::from("new"));
+        let (meta, body) = read(&node, &destination).await.unwrap();
+        assert_eq!(body, Bytes::from("old"));
+        // Written over by the copy, then linked back to the replaced parts.
+        assert!(meta.generation > replaced + 1);
+        assert_eq!(meta.metadata["owner"], "bob");
+        assert_eq!(meta.tags["env"], "prod");
+    }
+}
@@ -0,0 +1,231 @@
+use crate::storage::slot_transfer::{export_file_path, export_slot, load_export};
+use crate::{
+    ClusterClient, MetadataStore, Result, RimError, SlotManager, SlotTransferFile,
+    SlotTransferManifest, SlotTransferStaging,
+};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Pulls a whole slot from a healthy replica: the exported slot database and
+/// part files, instead of copying path by path. Only a slot that holds
+/// nothing yet is filled this way.
+pub struct SlotTransferOperation {
+    slot_manager: Arc<SlotManager>,
+    cluster_client: Arc<ClusterClient>,
+    running: Mutex<HashMap<u16, Arc<Mutex<()>>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SlotTransferOperationRequest {
+    pub slot_id: u16,
+    pub source_node_id: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlotTransferOperationResult {
+    pub slot_id: u16,
+    pub source_node_id: String,
+    pub transfer_id: String,
+    pub files: usize,
+    pub total_bytes: u64,
+    /// Bytes fetched by this call; less than `total_bytes` when resumed.
+    pub downloaded_bytes: u64,
+    pub resumed: bool,
+    /// Paths the slot holds after the transfer, tombstones included.
+    pub blob_count: u64,
+}
+
+impl SlotTransferOperation {
+    pub fn new(slot_manager: Arc<SlotManager>, cluster_client: Arc<ClusterClient>) -> Self {
+        Self {
+            slot_manager,
+            cluster_client,
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Exports a local slot for a peer to pull.
+    pub async fn export(&self, slot_id: u16) -> Result<SlotTransferManifest> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            return Err(RimError::SlotNotFound(slot_id));
+        }
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        let store = MetadataStore::new(slot.clone())?;
+        export_slot(&store, &slot)
+    }
+
+    /// Returns an export made by [`Self::export`] while it is kept.
+    pub async fn export_manifest(
+        &self,
+        slot_id: u16,
+        transfer_id: &str,
+    ) -> Result<Option<SlotTransferManifest>> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            return Ok(None);
+        }
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        load_export(&slot, transfer_id)
+    }
+
+    /// Where file `name` of an export is read from.
+    pub async fn export_file(
+        &self,
+        slot_id: u16,
+        transfer_id: &str,
+        name: &str,
+    ) -> Result<std::path::PathBuf> {
+        let Some(manifest) = self.export_manifest(slot_id, transfer_id).await? else {
+            return Err(RimError::PartNotFound(format!(
+                "slot transfer not found: slot={} transfer={}",
+                slot_id, transfer_id
+            )));
+        };
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        export_file_path(&slot, &manifest, name)
+    }
+
+    /// Copies `slot_id` from the source, resuming an interrupted transfer
+    /// from the same source while the source still keeps its export.
+    pub async fn run(
+        &self,
+        request: SlotTransferOperationRequest,
+    ) -> Result<SlotTransferOperationResult> {
+        let SlotTransferOperationRequest {
+            slot_id,
+            source_node_id,
+        } = request;
+        let lock = self
+            .running
+            .lock()
+            .await
+            .entry(slot_id)
+            .or_default()
+            .clone();
+        let Ok(_running) = lock.try_lock_owned() else {
+            return Err(RimError::InvalidRequest(format!(
+                "slot transfer already running: slot={}",
+                slot_id
+            )));
+        };
+
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        let store = MetadataStore::new(slot.clone())?;
+        if store.slot_stats()?.blob_count > 0 {
+            return Err(RimError::InvalidRequest(format!(
+                "slot already holds data: slot={}",
+                slot_id
+            )));
+        }
+
+        let mut resumed = false;
+        let mut staging = None;
+        if let Some(previous) = SlotTransferStaging::resume(&slot)?
+            && previous.source_node_id() == source_node_id
+            && self
+                .cluster_client
+                .fetch_slot_export(&source_node_id, slot_id, &previous.manifest().transfer_id)
+                .await?
+                .is_some()
+        {
+            resumed = true;
+            staging = Some(previous);
+        }
+        let mut staging = match staging {
+            Some(staging) => staging,
+            None => {
+                let manifest = self
+                    .cluster_client
+                    .export_slot(&source_node_id, slot_id)
+                    .await?;
+                SlotTransferStaging::start(&slot, &source_node_id, manifest)?
+            }
+        };
+
+        let manifest = staging.manifest().clone();
+        let mut downloaded_bytes = 0;
+        for file in staging.pending() {
+            downloaded_bytes += self
+                .download(
+                    &staging,
+                    &source_node_id,
+                    slot_id,
+                    &manifest.transfer_id,
+                    &file,
+                )
+                .await?;
+            staging.complete_file(&file.name)?;
+        }
+        staging.install(&store, &slot)?;
+
+        let result = SlotTransferOperationResult {
+            slot_id,
+            source_node_id,
+            transfer_id: manifest.transfer_id.clone(),
+            files: manifest.files.len(),
+            total_bytes: manifest.total_bytes(),
+            downloaded_bytes,
+            resumed,
+            blob_count: store.slot_stats()?.blob_count,
+        };
+        tracing::info!(
+            "slot transferred: slot={} source={} transfer={} files={} bytes={} resumed={}",
+            slot_id,
+            result.source_node_id,
+            result.transfer_id,
+            result.files,
+            result.total_bytes,
+            resumed
+        );
+        Ok(result)
+    }
+
+    /// Appends the rest of `file` to its staged copy and returns the bytes
+    /// fetched.
+    async fn download(
+        &self,
+        staging: &SlotTransferStaging,
+        source_node_id: &str,
+        slot_id: u16,
+        transfer_id: &str,
+        file: &SlotTransferFile,
+    ) -> Result<u64> {
+        let path = staging.file_path(&file.name);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut offset = staging.received_bytes(&file.name);
+        if offset > file.size_bytes {
+            tokio::fs::remove_file(&path).await?;
+            offset = 0;
+        }
+        if offset == file.size_bytes && path.exists() {
+            return Ok(0);
+        }
+
+        let response = self
+            .cluster_client
+            .fetch_slot_export_file(source_node_id, slot_id, transfer_id, &file.name, offset)
+            .await?;
+        let mut out = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let mut body = response.bytes_stream();
+        let mut fetched = 0;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|error| RimError::Http(error.to_string()))?;
+            out.write_all(&chunk).await?;
+            fetched += chunk.len() as u64;
+        }
+        out.sync_all().await?;
+        Ok(fetched)
+    }
+}
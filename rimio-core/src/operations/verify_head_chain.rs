@@ -0,0 +1,41 @@
+use crate::{HeadChainReport, MetadataStore, Result, SlotManager};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct VerifyHeadChainOperation {
+    slot_manager: Arc<SlotManager>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyHeadChainOperationRequest {
+    pub slot_id: u16,
+}
+
+impl VerifyHeadChainOperation {
+    pub fn new(slot_manager: Arc<SlotManager>) -> Self {
+        Self { slot_manager }
+    }
+
+    pub async fn run(&self, request: VerifyHeadChainOperationRequest) -> Result<HeadChainReport> {
+        let store = self.ensure_store(request.slot_id).await?;
+        let report = store.verify_head_chain()?;
+        if !report.is_clean() {
+            tracing::warn!(
+                "head chain verification failed: slot={} broken={} samples={:?}",
+                request.slot_id,
+                report.broken,
+                report.samples
+            );
+        }
+        Ok(report)
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
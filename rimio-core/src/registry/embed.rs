@@ -1,8 +1,10 @@
 use crate::error::{Result, RimError};
 use crate::node::{NodeInfo, NodeStatus};
-use crate::registry::Registry;
-use crate::slot_manager::{ReplicaStatus, SlotHealth, SlotInfo};
+use crate::registry::{Registry, stored_slot_epoch};
+use crate::slot_manager::{ReplicaStatus, SlotHealth, SlotInfo, SlotLease};
+use crate::{ApiKey, LifecycleRule, SharedClock, system_clock};
 use async_trait::async_trait;
+use reqwest::header::HeaderMap;
 use rimio_meta::{MetaError, MetaKv, MetaKvOptions, MetaMemberState};
 use std::collections::HashMap;
 
@@ -33,10 +35,26 @@ fn slots_prefix() -> &'static str {
     "slots/"
 }
 
+fn lease_key(slot_id: u16) -> String {
+    format!("leases/{}", slot_id)
+}
+
 fn bootstrap_key() -> &'static str {
     "bootstrap/state"
 }
 
+fn lifecycle_key() -> &'static str {
+    "lifecycle/rules"
+}
+
+fn api_keys_key() -> &'static str {
+    "auth/api_keys"
+}
+
+fn upload_tokens_prefix() -> &'static str {
+    "auth/upload_tokens/"
+}
+
 fn map_member_status(state: MetaMemberState) -> NodeStatus {
     match state {
         MetaMemberState::Alive => NodeStatus::Healthy,
@@ -45,13 +63,17 @@ fn map_member_status(state: MetaMemberState) -> NodeStatus {
     }
 }
 
-fn is_health_expired(last_updated: chrono::DateTime<chrono::Utc>) -> bool {
-    chrono::Utc::now().signed_duration_since(last_updated) > chrono::Duration::seconds(60)
+fn is_health_expired(
+    now: chrono::DateTime<chrono::Utc>,
+    last_updated: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    now.signed_duration_since(last_updated) > chrono::Duration::seconds(60)
 }
 
 pub struct EmbedRegistry {
     namespace: String,
     kv: MetaKv,
+    clock: SharedClock,
 }
 
 impl EmbedRegistry {
@@ -62,6 +84,7 @@ impl EmbedRegistry {
         advertise_addr: Option<&str>,
         seeds: Vec<String>,
         transport: Option<&str>,
+        peer_headers: HeaderMap,
     ) -> Result<Self> {
         let namespace = namespace.trim().to_string();
         if namespace.is_empty() {
@@ -77,11 +100,21 @@ impl EmbedRegistry {
             advertise_addr: advertise_addr.map(str::to_string),
             seeds,
             transport: transport.map(str::to_string),
+            peer_headers,
         };
 
         let kv = MetaKv::new(options).await.map_err(map_meta_error)?;
 
-        Ok(Self { namespace, kv })
+        Ok(Self {
+            namespace,
+            kv,
+            clock: system_clock(),
+        })
+    }
+
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
     }
 }
 
@@ -108,6 +141,16 @@ impl Registry for EmbedRegistry {
         Ok(())
     }
 
+    async fn deregister_node(&self, node_id: &str) -> Result<()> {
+        self.kv
+            .delete(&node_key(node_id))
+            .await
+            .map_err(map_meta_error)?;
+        self.kv.sync_once().await.map_err(map_meta_error)?;
+
+        Ok(())
+    }
+
     async fn get_slot(&self, slot_id: u16) -> Result<Option<SlotInfo>> {
         let key = slot_key(slot_id);
         let value = self.kv.get(&key).await.map_err(map_meta_error)?;
@@ -127,6 +170,24 @@ impl Registry for EmbedRegistry {
         self.kv.put(&key, &value).await.map_err(map_meta_error)
     }
 
+    async fn compare_and_set_slot(
+        &self,
+        info: &SlotInfo,
+        expected_epoch: Option<u64>,
+    ) -> Result<bool> {
+        let key = slot_key(info.slot_id);
+        let current = self.kv.get(&key).await.map_err(map_meta_error)?;
+        if stored_slot_epoch(current.as_deref())? != expected_epoch {
+            return Ok(false);
+        }
+
+        let value = serde_json::to_vec(info)?;
+        self.kv
+            .compare_and_swap(&key, current.as_deref(), &value)
+            .await
+            .map_err(map_meta_error)
+    }
+
     async fn get_all_slots(&self) -> Result<HashMap<u16, SlotInfo>> {
         let items = self
             .kv
@@ -154,10 +215,11 @@ impl Registry for EmbedRegistry {
         let prefix = health_prefix(slot_id);
         let items = self.kv.list_prefix(&prefix).await.map_err(map_meta_error)?;
 
+        let now = self.clock.now();
         let mut healths = Vec::new();
         for (_key, data) in items {
             if let Ok(health) = serde_json::from_slice::<SlotHealth>(&data)
-                && !is_health_expired(health.last_updated)
+                && !is_health_expired(now, health.last_updated)
             {
                 healths.push(health);
             }
@@ -193,10 +255,22 @@ impl Registry for EmbedRegistry {
 
     async fn get_nodes(&self) -> Result<Vec<NodeInfo>> {
         let members = self.kv.members().await.map_err(map_meta_error)?;
+        // Membership carries only the HTTP address; the rest of what a node
+        // registered is read from its record.
+        let grpc_addresses: HashMap<String, String> = self
+            .kv
+            .list_prefix(&node_key(""))
+            .await
+            .map_err(map_meta_error)?
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_slice::<NodeInfo>(&value).ok())
+            .filter_map(|node| Some((node.node_id, node.grpc_address?)))
+            .collect();
 
         Ok(members
             .into_iter()
             .map(|member| NodeInfo {
+                grpc_address: grpc_addresses.get(&member.node_id).cloned(),
                 node_id: member.node_id,
                 group_id: member.namespace,
                 address: member.address,
@@ -220,4 +294,121 @@ impl Registry for EmbedRegistry {
         self.kv.sync_once().await.map_err(map_meta_error)?;
         Ok(created)
     }
+
+    async fn acquire_slot_lease(
+        &self,
+        slot_id: u16,
+        node_id: &str,
+        ttl_secs: u64,
+    ) -> Result<SlotLease> {
+        let key = lease_key(slot_id);
+        let current = self.kv.get(&key).await.map_err(map_meta_error)?;
+        let existing = match &current {
+            Some(data) => Some(serde_json::from_slice::<SlotLease>(data)?),
+            None => None,
+        };
+
+        let now = self.clock.now();
+        let term = match &existing {
+            Some(lease) if lease.is_held_by_at(node_id, now) => lease.term,
+            Some(lease) if !lease.is_expired_at(now) => return Ok(lease.clone()),
+            Some(lease) => lease.term + 1,
+            None => 1,
+        };
+
+        let lease = SlotLease {
+            slot_id,
+            holder: node_id.to_string(),
+            term,
+            expires_at: now + chrono::Duration::seconds(ttl_secs as i64),
+        };
+        let value = serde_json::to_vec(&lease)?;
+
+        let swapped = self
+            .kv
+            .compare_and_swap(&key, current.as_deref(), &value)
+            .await
+            .map_err(map_meta_error)?;
+        if swapped {
+            return Ok(lease);
+        }
+
+        self.get_slot_lease(slot_id)
+            .await?
+            .ok_or_else(|| RimError::Internal(format!("slot lease vanished: slot={}", slot_id)))
+    }
+
+    async fn get_slot_lease(&self, slot_id: u16) -> Result<Option<SlotLease>> {
+        let value = self
+            .kv
+            .get(&lease_key(slot_id))
+            .await
+            .map_err(map_meta_error)?;
+
+        match value {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_lifecycle_rules(&self) -> Result<Vec<LifecycleRule>> {
+        match self.kv.get(lifecycle_key()).await.map_err(map_meta_error)? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn set_lifecycle_rules(&self, rules: &[LifecycleRule]) -> Result<()> {
+        let value = serde_json::to_vec(rules)?;
+        self.kv
+            .put(lifecycle_key(), &value)
+            .await
+            .map_err(map_meta_error)
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>> {
+        match self.kv.get(api_keys_key()).await.map_err(map_meta_error)? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn set_api_keys(&self, keys: &[ApiKey]) -> Result<()> {
+        let value = serde_json::to_vec(keys)?;
+        self.kv
+            .put(api_keys_key(), &value)
+            .await
+            .map_err(map_meta_error)
+    }
+
+    /// Each used token is kept under its id with its expiry until
+    /// `purge_upload_tokens` drops it.
+    async fn consume_upload_token(&self, token_id: &str, ttl_secs: u64) -> Result<bool> {
+        let expires_at = self.clock.now() + chrono::Duration::seconds(ttl_secs as i64);
+        self.kv
+            .put_if_absent(
+                &format!("{}{}", upload_tokens_prefix(), token_id),
+                &serde_json::to_vec(&expires_at)?,
+            )
+            .await
+            .map_err(map_meta_error)
+    }
+
+    async fn purge_upload_tokens(&self) -> Result<usize> {
+        let now = self.clock.now();
+        let used = self
+            .kv
+            .list_prefix(upload_tokens_prefix())
+            .await
+            .map_err(map_meta_error)?;
+        let mut purged = 0;
+        for (key, value) in used {
+            let expires_at: chrono::DateTime<chrono::Utc> = serde_json::from_slice(&value)?;
+            if expires_at <= now {
+                self.kv.delete(&key).await.map_err(map_meta_error)?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
 }
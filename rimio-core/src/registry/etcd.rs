@@ -1,7 +1,8 @@
 use crate::error::Result;
 use crate::node::NodeInfo;
-use crate::registry::{Registry, SlotEvent};
-use crate::slot_manager::{ReplicaStatus, SlotHealth, SlotInfo};
+use crate::registry::{Registry, SlotEvent, stored_slot_epoch};
+use crate::slot_manager::{ReplicaStatus, SlotHealth, SlotInfo, SlotLease};
+use crate::{ApiKey, LifecycleRule};
 use async_trait::async_trait;
 use etcd_client::{Client, GetOptions, PutOptions};
 use std::collections::HashMap;
@@ -33,10 +34,26 @@ impl EtcdRegistry {
         format!("{}/health/{}/{}", self.prefix, slot_id, node_id)
     }
 
+    fn lease_key(&self, slot_id: u16) -> String {
+        format!("{}/leases/{}", self.prefix, slot_id)
+    }
+
     fn bootstrap_key(&self) -> String {
         format!("{}/bootstrap/state", self.prefix)
     }
 
+    fn lifecycle_key(&self) -> String {
+        format!("{}/lifecycle/rules", self.prefix)
+    }
+
+    fn api_keys_key(&self) -> String {
+        format!("{}/auth/api_keys", self.prefix)
+    }
+
+    fn upload_token_key(&self, token_id: &str) -> String {
+        format!("{}/auth/upload_tokens/{}", self.prefix, token_id)
+    }
+
     /// Watch for slot changes (simplified - just fetches periodically)
     pub async fn watch_slots(&self) -> Result<tokio::sync::mpsc::Receiver<SlotEvent>> {
         let (tx, rx) = tokio::sync::mpsc::channel(100);
@@ -84,6 +101,13 @@ impl Registry for EtcdRegistry {
         Ok(())
     }
 
+    async fn deregister_node(&self, node_id: &str) -> Result<()> {
+        let mut client = self.client.clone();
+        client.delete(self.node_key(node_id), None).await?;
+
+        Ok(())
+    }
+
     async fn get_slot(&self, slot_id: u16) -> Result<Option<SlotInfo>> {
         let key = self.slot_key(slot_id);
         let mut client = self.client.clone();
@@ -107,6 +131,36 @@ impl Registry for EtcdRegistry {
         Ok(())
     }
 
+    async fn compare_and_set_slot(
+        &self,
+        info: &SlotInfo,
+        expected_epoch: Option<u64>,
+    ) -> Result<bool> {
+        use etcd_client::{Compare, CompareOp, Txn, TxnOp};
+
+        let key = self.slot_key(info.slot_id);
+        let mut client = self.client.clone();
+        let resp = client.get(key.as_str(), None).await?;
+        let current = resp.kvs().first();
+        if stored_slot_epoch(current.map(|kv| kv.value()))? != expected_epoch {
+            return Ok(false);
+        }
+
+        // Guard against a concurrent writer between our read and the put.
+        let guard = match current {
+            Some(kv) => Compare::mod_revision(key.as_str(), CompareOp::Equal, kv.mod_revision()),
+            None => Compare::create_revision(key.as_str(), CompareOp::Equal, 0),
+        };
+        let value = serde_json::to_vec(info)?;
+        let transaction =
+            Txn::new()
+                .when([guard])
+                .and_then([TxnOp::put(key.as_str(), value, None)]);
+
+        let response = client.txn(transaction).await?;
+        Ok(response.succeeded())
+    }
+
     async fn get_all_slots(&self) -> Result<HashMap<u16, SlotInfo>> {
         let prefix = format!("{}/slots/", self.prefix);
         let mut client = self.client.clone();
@@ -204,4 +258,123 @@ impl Registry for EtcdRegistry {
     async fn set_bootstrap_state_if_absent(&self, payload: &[u8]) -> Result<bool> {
         self.create_bootstrap_bytes_if_absent(payload).await
     }
+
+    async fn acquire_slot_lease(
+        &self,
+        slot_id: u16,
+        node_id: &str,
+        ttl_secs: u64,
+    ) -> Result<SlotLease> {
+        use etcd_client::{Compare, CompareOp, Txn, TxnOp};
+
+        let key = self.lease_key(slot_id);
+        let mut client = self.client.clone();
+        let grant = client.lease_grant(ttl_secs as i64, None).await?;
+        let put = TxnOp::put(
+            key.as_str(),
+            node_id,
+            Some(PutOptions::new().with_lease(grant.id())),
+        );
+
+        // Take the key when nobody holds it, or renew it when we already do.
+        let transaction = Txn::new()
+            .when([Compare::create_revision(key.as_str(), CompareOp::Equal, 0)])
+            .and_then([put.clone()])
+            .or_else([TxnOp::txn(
+                Txn::new()
+                    .when([Compare::value(key.as_str(), CompareOp::Equal, node_id)])
+                    .and_then([put]),
+            )]);
+        client.txn(transaction).await?;
+
+        let lease = self.get_slot_lease(slot_id).await?;
+        if lease.as_ref().is_none_or(|lease| lease.holder != node_id) {
+            let _ = client.lease_revoke(grant.id()).await;
+        }
+
+        lease.ok_or_else(|| {
+            crate::error::RimError::Internal(format!("slot lease vanished: slot={}", slot_id))
+        })
+    }
+
+    async fn get_slot_lease(&self, slot_id: u16) -> Result<Option<SlotLease>> {
+        let key = self.lease_key(slot_id);
+        let mut client = self.client.clone();
+        let resp = client.get(key, None).await?;
+
+        let Some(kv) = resp.kvs().first() else {
+            return Ok(None);
+        };
+
+        let ttl_secs = client.lease_time_to_live(kv.lease(), None).await?.ttl();
+
+        Ok(Some(SlotLease {
+            slot_id,
+            holder: String::from_utf8_lossy(kv.value()).into_owned(),
+            term: kv.create_revision() as u64,
+            expires_at: chrono::Utc::now() + chrono::Duration::seconds(ttl_secs.max(0)),
+        }))
+    }
+
+    async fn get_lifecycle_rules(&self) -> Result<Vec<LifecycleRule>> {
+        let mut client = self.client.clone();
+        let response = client.get(self.lifecycle_key(), None).await?;
+
+        match response.kvs().first() {
+            Some(kv) => Ok(serde_json::from_slice(kv.value())?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn set_lifecycle_rules(&self, rules: &[LifecycleRule]) -> Result<()> {
+        let value = serde_json::to_vec(rules)?;
+        let mut client = self.client.clone();
+        client.put(self.lifecycle_key(), value, None).await?;
+
+        Ok(())
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let mut client = self.client.clone();
+        let response = client.get(self.api_keys_key(), None).await?;
+
+        match response.kvs().first() {
+            Some(kv) => Ok(serde_json::from_slice(kv.value())?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn set_api_keys(&self, keys: &[ApiKey]) -> Result<()> {
+        let value = serde_json::to_vec(keys)?;
+        let mut client = self.client.clone();
+        client.put(self.api_keys_key(), value, None).await?;
+
+        Ok(())
+    }
+
+    async fn consume_upload_token(&self, token_id: &str, ttl_secs: u64) -> Result<bool> {
+        use etcd_client::{Compare, CompareOp, Txn, TxnOp};
+
+        let key = self.upload_token_key(token_id);
+        let mut client = self.client.clone();
+        let grant = client.lease_grant(ttl_secs.max(1) as i64, None).await?;
+        let transaction = Txn::new()
+            .when([Compare::create_revision(key.as_str(), CompareOp::Equal, 0)])
+            .and_then([TxnOp::put(
+                key.as_str(),
+                "1",
+                Some(PutOptions::new().with_lease(grant.id())),
+            )]);
+        let consumed = client.txn(transaction).await?.succeeded();
+        if !consumed {
+            let _ = client.lease_revoke(grant.id()).await;
+        }
+
+        Ok(consumed)
+    }
+
+    /// Used tokens are bound to an etcd lease, so there is nothing to sweep.
+    async fn purge_upload_tokens(&self) -> Result<usize> {
+        Ok(0)
+    }
 }
@@ -1,5 +1,8 @@
-use super::{Registry, embed::EmbedRegistry, etcd::EtcdRegistry, redis::RedisRegistry};
-use crate::{Result, RimError};
+use super::{
+    Registry, embed::EmbedRegistry, etcd::EtcdRegistry, memory::MemoryRegistry,
+    redis::RedisRegistry,
+};
+use crate::{Result, RimError, cluster_key_headers};
 use std::sync::Arc;
 
 #[derive(Debug, Clone, Default)]
@@ -13,6 +16,7 @@ pub struct RegistryBuilder {
     embed_bind_addr: Option<String>,
     embed_advertise_addr: Option<String>,
     embed_seeds: Option<Vec<String>>,
+    cluster_key: Option<String>,
 }
 
 impl RegistryBuilder {
@@ -65,6 +69,12 @@ impl RegistryBuilder {
         self
     }
 
+    /// Presented to peers by the embed registry's raft traffic.
+    pub fn cluster_key(mut self, cluster_key: Option<String>) -> Self {
+        self.cluster_key = cluster_key;
+        self
+    }
+
     fn resolve_namespace(&self) -> Result<String> {
         let namespace = self
             .namespace
@@ -183,10 +193,12 @@ impl RegistryBuilder {
                     advertise_addr.as_deref(),
                     seeds,
                     Some(transport.as_str()),
+                    cluster_key_headers(self.cluster_key.as_deref()),
                 )
                 .await?;
                 Ok(Arc::new(registry))
             }
+            "memory" => Ok(MemoryRegistry::shared(&namespace)),
             other => Err(RimError::Config(format!(
                 "unsupported registry backend: {}",
                 other
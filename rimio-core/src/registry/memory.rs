@@ -0,0 +1,328 @@
+use crate::error::Result;
+use crate::node::NodeInfo;
+use crate::registry::Registry;
+use crate::slot_manager::{ReplicaStatus, SlotHealth, SlotInfo, SlotLease};
+use crate::{ApiKey, LifecycleRule, SharedClock, system_clock};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Registries handed out per namespace, so every builder of this process
+/// sees the same state.
+static MEMORY_REGISTRIES: OnceLock<Mutex<HashMap<String, Arc<MemoryRegistry>>>> = OnceLock::new();
+
+fn is_health_expired(
+    now: chrono::DateTime<chrono::Utc>,
+    last_updated: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    now.signed_duration_since(last_updated) > chrono::Duration::seconds(60)
+}
+
+#[derive(Default)]
+struct MemoryState {
+    nodes: HashMap<String, NodeInfo>,
+    slots: HashMap<u16, SlotInfo>,
+    health: HashMap<(u16, String), SlotHealth>,
+    leases: HashMap<u16, SlotLease>,
+    lease_terms: HashMap<u16, u64>,
+    bootstrap: Option<Vec<u8>>,
+    lifecycle_rules: Vec<LifecycleRule>,
+    api_keys: Vec<ApiKey>,
+    /// Expiry of each used upload token.
+    used_upload_tokens: HashMap<String, chrono::DateTime<chrono::Utc>>,
+}
+
+/// Registry kept in the memory of this process, for single-node development
+/// and tests. Nothing survives a restart and no other process can join.
+pub struct MemoryRegistry {
+    state: Mutex<MemoryState>,
+    clock: SharedClock,
+}
+
+impl Default for MemoryRegistry {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(MemoryState::default()),
+            clock: system_clock(),
+        }
+    }
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The registry of `namespace` in this process, created on first use.
+    pub fn shared(namespace: &str) -> Arc<Self> {
+        let registries = MEMORY_REGISTRIES.get_or_init(|| Mutex::new(HashMap::new()));
+        let mut registries = registries.lock().unwrap_or_else(|error| error.into_inner());
+        registries
+            .entry(namespace.to_string())
+            .or_insert_with(|| Arc::new(Self::new()))
+            .clone()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+#[async_trait]
+impl Registry for MemoryRegistry {
+    async fn register_node(&self, node: &NodeInfo) -> Result<()> {
+        self.state()
+            .nodes
+            .insert(node.node_id.clone(), node.clone());
+        Ok(())
+    }
+
+    async fn deregister_node(&self, node_id: &str) -> Result<()> {
+        self.state().nodes.remove(node_id);
+        Ok(())
+    }
+
+    async fn get_slot(&self, slot_id: u16) -> Result<Option<SlotInfo>> {
+        Ok(self.state().slots.get(&slot_id).cloned())
+    }
+
+    async fn set_slot(&self, info: &SlotInfo) -> Result<()> {
+        self.state().slots.insert(info.slot_id, info.clone());
+        Ok(())
+    }
+
+    async fn compare_and_set_slot(
+        &self,
+        info: &SlotInfo,
+        expected_epoch: Option<u64>,
+    ) -> Result<bool> {
+        let mut state = self.state();
+        let current = state.slots.get(&info.slot_id).map(|slot| slot.epoch);
+        if current != expected_epoch {
+            return Ok(false);
+        }
+        state.slots.insert(info.slot_id, info.clone());
+        Ok(true)
+    }
+
+    async fn get_all_slots(&self) -> Result<HashMap<u16, SlotInfo>> {
+        Ok(self.state().slots.clone())
+    }
+
+    async fn report_health(&self, health: &SlotHealth) -> Result<()> {
+        self.state()
+            .health
+            .insert((health.slot_id, health.node_id.clone()), health.clone());
+        Ok(())
+    }
+
+    async fn get_slot_health(&self, slot_id: u16) -> Result<Vec<SlotHealth>> {
+        let now = self.clock.now();
+        Ok(self
+            .state()
+            .health
+            .values()
+            .filter(|health| {
+                health.slot_id == slot_id && !is_health_expired(now, health.last_updated)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn get_healthy_replicas(&self, slot_id: u16) -> Result<Vec<(String, String)>> {
+        let healthy: Vec<(String, String)> = self
+            .get_slot_health(slot_id)
+            .await?
+            .into_iter()
+            .filter(|health| health.status == ReplicaStatus::Healthy)
+            .map(|health| (health.node_id, health.seq))
+            .collect();
+
+        let Some(latest_seq) = healthy.iter().map(|(_, seq)| seq.clone()).max() else {
+            return Ok(Vec::new());
+        };
+
+        Ok(healthy
+            .into_iter()
+            .filter(|(_, seq)| seq == &latest_seq)
+            .collect())
+    }
+
+    async fn get_nodes(&self) -> Result<Vec<NodeInfo>> {
+        Ok(self.state().nodes.values().cloned().collect())
+    }
+
+    async fn get_bootstrap_state(&self) -> Result<Option<Vec<u8>>> {
+        Ok(self.state().bootstrap.clone())
+    }
+
+    async fn set_bootstrap_state_if_absent(&self, payload: &[u8]) -> Result<bool> {
+        let mut state = self.state();
+        if state.bootstrap.is_some() {
+            return Ok(false);
+        }
+        state.bootstrap = Some(payload.to_vec());
+        Ok(true)
+    }
+
+    async fn acquire_slot_lease(
+        &self,
+        slot_id: u16,
+        node_id: &str,
+        ttl_secs: u64,
+    ) -> Result<SlotLease> {
+        let now = self.clock.now();
+        let mut state = self.state();
+        let current = state
+            .leases
+            .get(&slot_id)
+            .filter(|lease| !lease.is_expired_at(now))
+            .cloned();
+        if let Some(lease) = &current
+            && lease.holder != node_id
+        {
+            return Ok(lease.clone());
+        }
+
+        let term = match current {
+            Some(lease) => lease.term,
+            None => {
+                let term = state.lease_terms.entry(slot_id).or_default();
+                *term += 1;
+                *term
+            }
+        };
+        let lease = SlotLease {
+            slot_id,
+            holder: node_id.to_string(),
+            term,
+            expires_at: now + chrono::Duration::seconds(ttl_secs as i64),
+        };
+        state.leases.insert(slot_id, lease.clone());
+        Ok(lease)
+    }
+
+    async fn get_slot_lease(&self, slot_id: u16) -> Result<Option<SlotLease>> {
+        let now = self.clock.now();
+        Ok(self
+            .state()
+            .leases
+            .get(&slot_id)
+            .filter(|lease| !lease.is_expired_at(now))
+            .cloned())
+    }
+
+    async fn get_lifecycle_rules(&self) -> Result<Vec<LifecycleRule>> {
+        Ok(self.state().lifecycle_rules.clone())
+    }
+
+    async fn set_lifecycle_rules(&self, rules: &[LifecycleRule]) -> Result<()> {
+        self.state().lifecycle_rules = rules.to_vec();
+        Ok(())
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>> {
+        Ok(self.state().api_keys.clone())
+    }
+
+    async fn set_api_keys(&self, keys: &[ApiKey]) -> Result<()> {
+        self.state().api_keys = keys.to_vec();
+        Ok(())
+    }
+
+    async fn consume_upload_token(&self, token_id: &str, ttl_secs: u64) -> Result<bool> {
+        let now = self.clock.now();
+        let mut state = self.state();
+        if state
+            .used_upload_tokens
+            .get(token_id)
+            .is_some_and(|expires_at| *expires_at > now)
+        {
+            return Ok(false);
+        }
+        state.used_upload_tokens.insert(
+            token_id.to_string(),
+            now + chrono::Duration::seconds(ttl_secs as i64),
+        );
+        Ok(true)
+    }
+
+    async fn purge_upload_tokens(&self) -> Result<usize> {
+        let now = self.clock.now();
+        let mut state = self.state();
+        let before = state.used_upload_tokens.len();
+        state
+            .used_upload_tokens
+            .retain(|_, expires_at| *expires_at > now);
+        Ok(before - state.used_upload_tokens.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn leases_change_term_only_when_the_holder_changes() {
+        let clock = Arc::new(crate::ManualClock::starting_now());
+        let registry = MemoryRegistry::new().with_clock(clock.clone());
+
+        let first = registry.acquire_slot_lease(3, "node-a", 60).await.unwrap();
+        assert_eq!((first.holder.as_str(), first.term), ("node-a", 1));
+        let renewed = registry.acquire_slot_lease(3, "node-a", 60).await.unwrap();
+        assert_eq!(renewed.term, 1);
+        let refused = registry.acquire_slot_lease(3, "node-b", 60).await.unwrap();
+        assert_eq!(refused.holder, "node-a");
+
+        clock.advance(chrono::Duration::seconds(59));
+        assert!(registry.get_slot_lease(3).await.unwrap().is_some());
+        clock.advance(chrono::Duration::seconds(1));
+        assert!(registry.get_slot_lease(3).await.unwrap().is_none());
+        let taken = registry.acquire_slot_lease(3, "node-b", 60).await.unwrap();
+        assert_eq!(
+            (taken.holder.as_str(), taken.term),
+            ("node-b", first.term + 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn upload_tokens_are_consumed_once_until_they_expire() {
+        let registry = MemoryRegistry::new();
+
+        assert!(registry.consume_upload_token("t1", 60).await.unwrap());
+        assert!(!registry.consume_upload_token("t1", 60).await.unwrap());
+        assert!(registry.consume_upload_token("t2", 60).await.unwrap());
+
+        assert!(registry.consume_upload_token("t3", 0).await.unwrap());
+        assert!(registry.consume_upload_token("t3", 60).await.unwrap());
+
+        assert!(registry.consume_upload_token("t4", 0).await.unwrap());
+        assert_eq!(registry.purge_upload_tokens().await.unwrap(), 1);
+        assert!(!registry.consume_upload_token("t1", 60).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn shared_registries_are_per_namespace() {
+        let registry = MemoryRegistry::shared("memory-test-a");
+        assert!(
+            registry
+                .set_bootstrap_state_if_absent(b"state")
+                .await
+                .unwrap()
+        );
+
+        let again = MemoryRegistry::shared("memory-test-a");
+        assert!(!again.set_bootstrap_state_if_absent(b"other").await.unwrap());
+        assert_eq!(
+            again.get_bootstrap_state().await.unwrap().unwrap(),
+            b"state"
+        );
+        let other = MemoryRegistry::shared("memory-test-b");
+        assert!(other.get_bootstrap_state().await.unwrap().is_none());
+    }
+}
@@ -6,11 +6,13 @@
 pub mod embed;
 pub mod etcd;
 pub mod factory;
+pub mod memory;
 pub mod redis;
 
 use crate::error::Result;
 use crate::node::NodeInfo;
-use crate::slot_manager::{SlotHealth, SlotInfo};
+use crate::slot_manager::{SlotHealth, SlotInfo, SlotLease};
+use crate::{ApiKey, LifecycleRule};
 use async_trait::async_trait;
 use std::collections::HashMap;
 
@@ -22,12 +24,23 @@ pub trait Registry: Send + Sync {
     /// Register a node in the registry
     async fn register_node(&self, node: &NodeInfo) -> Result<()>;
 
+    /// Remove a node from the registry
+    async fn deregister_node(&self, node_id: &str) -> Result<()>;
+
     /// Get slot routing information
     async fn get_slot(&self, slot_id: u16) -> Result<Option<SlotInfo>>;
 
     /// Set slot routing information
     async fn set_slot(&self, info: &SlotInfo) -> Result<()>;
 
+    /// Set slot routing information only if the stored epoch still equals
+    /// `expected_epoch` (`None` meaning the slot is unassigned)
+    async fn compare_and_set_slot(
+        &self,
+        info: &SlotInfo,
+        expected_epoch: Option<u64>,
+    ) -> Result<bool>;
+
     /// Get all slot routing information
     async fn get_all_slots(&self) -> Result<HashMap<u16, SlotInfo>>;
 
@@ -48,6 +61,46 @@ pub trait Registry: Send + Sync {
 
     /// Persist bootstrap state only if absent (first-wins)
     async fn set_bootstrap_state_if_absent(&self, payload: &[u8]) -> Result<bool>;
+
+    /// Acquire or renew the write lease of a slot for `node_id`.
+    ///
+    /// Returns the lease in effect afterwards; when another node holds an
+    /// unexpired lease, that lease is returned unchanged.
+    async fn acquire_slot_lease(
+        &self,
+        slot_id: u16,
+        node_id: &str,
+        ttl_secs: u64,
+    ) -> Result<SlotLease>;
+
+    /// Get the current write lease of a slot, if any
+    async fn get_slot_lease(&self, slot_id: u16) -> Result<Option<SlotLease>>;
+
+    /// Get the lifecycle rules of the group; none when never set
+    async fn get_lifecycle_rules(&self) -> Result<Vec<LifecycleRule>>;
+
+    /// Replace the lifecycle rules of the group
+    async fn set_lifecycle_rules(&self, rules: &[LifecycleRule]) -> Result<()>;
+
+    /// Get the API keys of the group; none when never set
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>>;
+
+    /// Replace the API keys of the group
+    async fn set_api_keys(&self, keys: &[ApiKey]) -> Result<()>;
+
+    /// Mark an upload token as used for `ttl_secs`; false when it already was
+    async fn consume_upload_token(&self, token_id: &str, ttl_secs: u64) -> Result<bool>;
+
+    /// Drop used upload tokens past their expiry; returns how many were dropped
+    async fn purge_upload_tokens(&self) -> Result<usize>;
+}
+
+/// Reads the epoch of a stored slot entry.
+pub(crate) fn stored_slot_epoch(data: Option<&[u8]>) -> Result<Option<u64>> {
+    match data {
+        Some(data) => Ok(Some(serde_json::from_slice::<SlotInfo>(data)?.epoch)),
+        None => Ok(None),
+    }
 }
 
 /// Type alias for dynamic registry
@@ -1,12 +1,43 @@
 use crate::error::{Result, RimError};
 use crate::node::NodeInfo;
-use crate::registry::Registry;
-use crate::slot_manager::{ReplicaStatus, SlotHealth, SlotInfo};
+use crate::registry::{Registry, stored_slot_epoch};
+use crate::slot_manager::{ReplicaStatus, SlotHealth, SlotInfo, SlotLease};
+use crate::{ApiKey, LifecycleRule};
 use async_trait::async_trait;
 use redis::{AsyncCommands, Client};
 use std::collections::HashMap;
 use tokio::sync::Mutex;
 
+/// Sets KEYS[1] to ARGV[2] only if it still holds ARGV[1] (empty meaning
+/// absent). Returns 1 on success.
+const COMPARE_AND_SET_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if ARGV[1] == '' then
+  if current then return 0 end
+elseif current ~= ARGV[1] then
+  return 0
+end
+redis.call('SET', KEYS[1], ARGV[2])
+return 1
+"#;
+
+/// Grants or renews a lease held under KEYS[1], bumping the term in KEYS[2]
+/// whenever the holder changes. Returns `{holder, pttl_ms, term}`.
+const ACQUIRE_LEASE_SCRIPT: &str = r#"
+local holder = redis.call('GET', KEYS[1])
+if holder and holder ~= ARGV[1] then
+  return {holder, redis.call('PTTL', KEYS[1]), redis.call('GET', KEYS[2]) or '0'}
+end
+local term
+if holder then
+  term = redis.call('GET', KEYS[2]) or '0'
+else
+  term = tostring(redis.call('INCR', KEYS[2]))
+end
+redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+return {ARGV[1], tonumber(ARGV[2]), term}
+"#;
+
 /// Redis-based registry implementation
 pub struct RedisRegistry {
     conn: Mutex<redis::aio::MultiplexedConnection>,
@@ -62,10 +93,30 @@ impl RedisRegistry {
         format!("{}:health:{}:*", self.prefix, slot_id)
     }
 
+    fn lease_key(&self, slot_id: u16) -> String {
+        format!("{}:leases:{}", self.prefix, slot_id)
+    }
+
+    fn lease_term_key(&self, slot_id: u16) -> String {
+        format!("{}:leases:{}:term", self.prefix, slot_id)
+    }
+
     fn bootstrap_key(&self) -> String {
         format!("{}:bootstrap:state", self.prefix)
     }
 
+    fn lifecycle_key(&self) -> String {
+        format!("{}:lifecycle:rules", self.prefix)
+    }
+
+    fn api_keys_key(&self) -> String {
+        format!("{}:auth:api_keys", self.prefix)
+    }
+
+    fn upload_token_key(&self, token_id: &str) -> String {
+        format!("{}:auth:upload_tokens:{}", self.prefix, token_id)
+    }
+
     pub async fn get_bootstrap_bytes(&self) -> Result<Option<Vec<u8>>> {
         let mut conn = self.conn.lock().await;
         let key = self.bootstrap_key();
@@ -104,6 +155,15 @@ impl Registry for RedisRegistry {
         Ok(())
     }
 
+    async fn deregister_node(&self, node_id: &str) -> Result<()> {
+        let mut conn = self.conn.lock().await;
+        let _: () = conn.del(self.node_key(node_id)).await.map_err(|e| {
+            RimError::Internal(format!("Failed to deregister node in Redis: {}", e))
+        })?;
+
+        Ok(())
+    }
+
     async fn get_slot(&self, slot_id: u16) -> Result<Option<SlotInfo>> {
         let mut conn = self.conn.lock().await;
         let key = self.slot_key(slot_id);
@@ -135,6 +195,34 @@ impl Registry for RedisRegistry {
         Ok(())
     }
 
+    async fn compare_and_set_slot(
+        &self,
+        info: &SlotInfo,
+        expected_epoch: Option<u64>,
+    ) -> Result<bool> {
+        let mut conn = self.conn.lock().await;
+        let key = self.slot_key(info.slot_id);
+
+        let current: Option<Vec<u8>> = conn
+            .get(&key)
+            .await
+            .map_err(|e| RimError::Internal(format!("Failed to get slot from Redis: {}", e)))?;
+        if stored_slot_epoch(current.as_deref())? != expected_epoch {
+            return Ok(false);
+        }
+
+        let value = serde_json::to_vec(info)?;
+        let swapped: i64 = redis::Script::new(COMPARE_AND_SET_SCRIPT)
+            .key(&key)
+            .arg(current.unwrap_or_default())
+            .arg(value)
+            .invoke_async(&mut *conn)
+            .await
+            .map_err(|e| RimError::Internal(format!("Failed to set slot in Redis: {}", e)))?;
+
+        Ok(swapped == 1)
+    }
+
     async fn get_all_slots(&self) -> Result<HashMap<u16, SlotInfo>> {
         let mut conn = self.conn.lock().await;
         let pattern = self.slots_pattern();
@@ -245,4 +333,126 @@ impl Registry for RedisRegistry {
     async fn set_bootstrap_state_if_absent(&self, payload: &[u8]) -> Result<bool> {
         self.set_bootstrap_bytes_if_absent(payload).await
     }
+
+    async fn acquire_slot_lease(
+        &self,
+        slot_id: u16,
+        node_id: &str,
+        ttl_secs: u64,
+    ) -> Result<SlotLease> {
+        let mut conn = self.conn.lock().await;
+
+        let (holder, pttl_ms, term): (String, i64, String) =
+            redis::Script::new(ACQUIRE_LEASE_SCRIPT)
+                .key(self.lease_key(slot_id))
+                .key(self.lease_term_key(slot_id))
+                .arg(node_id)
+                .arg(ttl_secs.saturating_mul(1000))
+                .invoke_async(&mut *conn)
+                .await
+                .map_err(|e| {
+                    RimError::Internal(format!("Failed to acquire slot lease in Redis: {}", e))
+                })?;
+
+        Ok(SlotLease {
+            slot_id,
+            holder,
+            term: term.parse().unwrap_or_default(),
+            expires_at: chrono::Utc::now() + chrono::Duration::milliseconds(pttl_ms.max(0)),
+        })
+    }
+
+    async fn get_slot_lease(&self, slot_id: u16) -> Result<Option<SlotLease>> {
+        let mut conn = self.conn.lock().await;
+        let key = self.lease_key(slot_id);
+
+        let holder: Option<String> = conn.get(&key).await.map_err(|e| {
+            RimError::Internal(format!("Failed to get slot lease from Redis: {}", e))
+        })?;
+        let Some(holder) = holder else {
+            return Ok(None);
+        };
+
+        let pttl_ms: i64 = conn.pttl(&key).await.map_err(|e| {
+            RimError::Internal(format!("Failed to get slot lease from Redis: {}", e))
+        })?;
+        let term: Option<u64> = conn.get(self.lease_term_key(slot_id)).await.map_err(|e| {
+            RimError::Internal(format!("Failed to get slot lease from Redis: {}", e))
+        })?;
+
+        Ok(Some(SlotLease {
+            slot_id,
+            holder,
+            term: term.unwrap_or_default(),
+            expires_at: chrono::Utc::now() + chrono::Duration::milliseconds(pttl_ms.max(0)),
+        }))
+    }
+
+    async fn get_lifecycle_rules(&self) -> Result<Vec<LifecycleRule>> {
+        let mut conn = self.conn.lock().await;
+        let data: Option<Vec<u8>> = conn.get(self.lifecycle_key()).await.map_err(|e| {
+            RimError::Internal(format!("Failed to get lifecycle rules from Redis: {}", e))
+        })?;
+
+        match data {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn set_lifecycle_rules(&self, rules: &[LifecycleRule]) -> Result<()> {
+        let value = serde_json::to_vec(rules)?;
+        let mut conn = self.conn.lock().await;
+        let _: () = conn.set(self.lifecycle_key(), value).await.map_err(|e| {
+            RimError::Internal(format!("Failed to set lifecycle rules in Redis: {}", e))
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let mut conn = self.conn.lock().await;
+        let data: Option<Vec<u8>> = conn
+            .get(self.api_keys_key())
+            .await
+            .map_err(|e| RimError::Internal(format!("Failed to get API keys from Redis: {}", e)))?;
+
+        match data {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn set_api_keys(&self, keys: &[ApiKey]) -> Result<()> {
+        let value = serde_json::to_vec(keys)?;
+        let mut conn = self.conn.lock().await;
+        let _: () = conn
+            .set(self.api_keys_key(), value)
+            .await
+            .map_err(|e| RimError::Internal(format!("Failed to set API keys in Redis: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn consume_upload_token(&self, token_id: &str, ttl_secs: u64) -> Result<bool> {
+        let mut conn = self.conn.lock().await;
+        let reply: Option<String> = redis::cmd("SET")
+            .arg(self.upload_token_key(token_id))
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl_secs.max(1).saturating_mul(1000))
+            .query_async(&mut *conn)
+            .await
+            .map_err(|e| {
+                RimError::Internal(format!("Failed to record upload token in Redis: {}", e))
+            })?;
+
+        Ok(reply.is_some())
+    }
+
+    /// Used tokens carry a Redis TTL, so there is nothing to sweep.
+    async fn purge_upload_tokens(&self) -> Result<usize> {
+        Ok(0)
+    }
 }
@@ -1,13 +1,14 @@
 use crate::error::{Result, RimError};
+pub use rimio_chunk::{PART_SIZE, slot_for_key, slot_for_tagged_key, slot_hash_tag};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
 use tokio::sync::RwLock;
 use ulid::Ulid;
 
 pub const TOTAL_SLOTS: u16 = 2048;
-pub const PART_SIZE: usize = 64 * 1024 * 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SlotInfo {
@@ -15,6 +16,12 @@ pub struct SlotInfo {
     pub replicas: Vec<String>,
     pub primary: String,
     pub latest_seq: String,
+    /// Placement version of the slot, bumped on every replica set change.
+    #[serde(default)]
+    pub epoch: u64,
+    /// Frozen for maintenance: reads are served, writes are refused.
+    #[serde(default)]
+    pub frozen: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +33,26 @@ pub struct SlotHealth {
     pub last_updated: chrono::DateTime<chrono::Utc>,
 }
 
+/// Write lease of a slot. While unexpired, only `holder` coordinates writes
+/// for the slot; `term` increases every time the lease changes hands.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SlotLease {
+    pub slot_id: u16,
+    pub holder: String,
+    pub term: u64,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl SlotLease {
+    pub fn is_expired_at(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires_at <= now
+    }
+
+    pub fn is_held_by_at(&self, node_id: &str, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.holder == node_id && !self.is_expired_at(now)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ReplicaStatus {
     Healthy,
@@ -37,12 +64,17 @@ pub struct SlotManager {
     node_id: String,
     data_dir: PathBuf,
     slots: Arc<RwLock<HashMap<u16, Slot>>>,
+    chain_heads: bool,
 }
 
 pub struct Slot {
     pub slot_id: u16,
     pub seq: Arc<RwLock<Ulid>>,
     pub data_path: PathBuf,
+    /// Writes to the slot database that gave up on a lock held elsewhere.
+    pub busy_errors: Arc<AtomicU64>,
+    /// Record every head write in the slot's head chain.
+    pub chain_heads: bool,
 }
 
 impl SlotManager {
@@ -53,18 +85,29 @@ impl SlotManager {
             node_id,
             data_dir,
             slots: Arc::new(RwLock::new(HashMap::new())),
+            chain_heads: false,
         })
     }
 
+    /// Chains the heads written to every slot of this node, so tampering
+    /// with past metadata can be found by
+    /// [`MetadataStore::verify_head_chain`](crate::MetadataStore::verify_head_chain).
+    pub fn with_head_chain(mut self, enabled: bool) -> Self {
+        self.chain_heads = enabled;
+        self
+    }
+
     pub async fn init_slot(&self, slot_id: u16) -> Result<()> {
         let slot_path = self.data_dir.join("slots").join(slot_id.to_string());
         std::fs::create_dir_all(&slot_path)?;
-        std::fs::create_dir_all(slot_path.join("blobs"))?;
+        std::fs::create_dir_all(slot_path.join("parts"))?;
 
         let slot = Slot {
             slot_id,
             seq: Arc::new(RwLock::new(Ulid::new())),
             data_path: slot_path,
+            busy_errors: Arc::new(AtomicU64::new(0)),
+            chain_heads: self.chain_heads,
         };
 
         let mut slots = self.slots.write().await;
@@ -83,6 +126,8 @@ impl SlotManager {
                     slot_id: slot.slot_id,
                     seq: Arc::clone(&slot.seq),
                     data_path: slot.data_path.clone(),
+                    busy_errors: Arc::clone(&slot.busy_errors),
+                    chain_heads: slot.chain_heads,
                 })
             })
             .ok_or(RimError::SlotNotFound(slot_id))
@@ -111,6 +156,33 @@ impl SlotManager {
         Ok(*seq)
     }
 
+    /// Lists slots with a data directory on disk, initialized or not.
+    pub fn list_local_slots(&self) -> Result<Vec<u16>> {
+        let slots_dir = self.data_dir.join("slots");
+        if !slots_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut slot_ids = Vec::new();
+        for entry in std::fs::read_dir(slots_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            if let Some(slot_id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<u16>().ok())
+            {
+                slot_ids.push(slot_id);
+            }
+        }
+
+        slot_ids.sort_unstable();
+        Ok(slot_ids)
+    }
+
     pub async fn get_assigned_slots(&self) -> Vec<u16> {
         let slots = self.slots.read().await;
         slots.keys().copied().collect()
@@ -122,17 +194,19 @@ impl Slot {
         self.data_path.join("meta.sqlite3")
     }
 
-    pub fn blobs_dir(&self) -> PathBuf {
-        self.data_path.join("blobs")
+    pub fn meta_wal_path(&self) -> PathBuf {
+        self.data_path.join("meta.sqlite3-wal")
     }
-}
 
-pub fn slot_for_key(key: &str, total_slots: u16) -> u16 {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
+    pub fn parts_dir(&self) -> PathBuf {
+        self.data_path.join("parts")
+    }
+
+    pub fn backups_dir(&self) -> PathBuf {
+        self.data_path.join("backups")
+    }
 
-    let mut hasher = DefaultHasher::new();
-    key.hash(&mut hasher);
-    let hash = hasher.finish();
-    (hash % total_slots as u64) as u16
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.data_path.join("quarantine")
+    }
 }
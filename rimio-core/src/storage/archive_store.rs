@@ -2,12 +2,23 @@ use crate::{Result, RimError};
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures_util::StreamExt;
-use object_store::ObjectStore;
 use object_store::aws::AmazonS3Builder;
+use object_store::azure::MicrosoftAzureBuilder;
+use object_store::gcp::GoogleCloudStorageBuilder;
 use object_store::path::Path as ObjectPath;
+use object_store::{ClientOptions, ObjectStore, RetryConfig};
+use rand::Rng;
 use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
 use reqwest::Url;
-use std::sync::{Arc, OnceLock};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::OnceCell;
 
 #[derive(Debug, Clone)]
 pub struct ArchiveListPage {
@@ -51,12 +62,122 @@ pub trait ArchiveStore: Send + Sync {
 
     async fn write_blob(&self, object_key: &str, body: &[u8]) -> Result<()>;
 
+    /// Checks that the backend answers, in a single attempt without retries.
+    async fn ping(&self) -> Result<()>;
+
     fn archive_url_for_key(&self, object_key: &str) -> String;
+
+    /// The object key an archive URL names, the inverse of
+    /// [`ArchiveStore::archive_url_for_key`]. Fails for URLs this store did
+    /// not hand out, such as another bucket's.
+    fn object_key_for_url(&self, archive_url: &Url) -> Result<String> {
+        archive_url
+            .as_str()
+            .strip_prefix(&self.archive_url_for_key(""))
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .ok_or_else(|| {
+                RimError::InvalidRequest(format!(
+                    "archive_url does not belong to this archive: {}",
+                    archive_url
+                ))
+            })
+    }
+}
+
+/// Backoff for archive requests that fail transiently (timeouts, dropped
+/// connections, 5xx and throttling responses).
+///
+/// Once the attempts are used up the request fails with
+/// [`RimError::ArchiveUnavailable`], carrying how long the caller should wait
+/// before trying the archive again.
+#[derive(Debug, Clone)]
+pub struct ArchiveRetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for ArchiveRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+enum ArchiveAttemptError {
+    /// Worth retrying; `throttled` marks a store asking us to slow down.
+    Transient {
+        message: String,
+        throttled: bool,
+    },
+    Fatal(RimError),
+}
+
+impl ArchiveRetryPolicy {
+    fn single_attempt(&self) -> Self {
+        Self {
+            max_attempts: 1,
+            ..self.clone()
+        }
+    }
+
+    /// Full jitter over the capped exponential backoff. Throttled attempts
+    /// wait at least half of the cap.
+    fn backoff(&self, attempt: u32, throttled: bool) -> Duration {
+        let cap = self
+            .base_delay
+            .saturating_mul(1u32 << attempt.min(16))
+            .min(self.max_delay);
+        let floor = if throttled { cap / 2 } else { Duration::ZERO };
+        rand::thread_rng().gen_range(floor..=cap)
+    }
+
+    async fn run<T, F, Fut>(&self, operation: &str, mut attempt_once: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = std::result::Result<T, ArchiveAttemptError>>,
+    {
+        let mut attempt = 0u32;
+        loop {
+            let (message, throttled) = match attempt_once().await {
+                Ok(value) => return Ok(value),
+                Err(ArchiveAttemptError::Fatal(error)) => return Err(error),
+                Err(ArchiveAttemptError::Transient { message, throttled }) => (message, throttled),
+            };
+
+            attempt += 1;
+            let delay = self.backoff(attempt, throttled);
+            if attempt >= self.max_attempts.max(1) {
+                return Err(RimError::ArchiveUnavailable {
+                    message: format!(
+                        "{} failed after {} attempts: {}",
+                        operation, attempt, message
+                    ),
+                    retry_after: delay,
+                });
+            }
+
+            tracing::warn!(
+                "archive request failed, retrying: operation={} attempt={} delay_ms={} error={}",
+                operation,
+                attempt,
+                delay.as_millis(),
+                message
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
 }
 
 pub struct RedisArchiveStore {
     client: redis::Client,
+    conn: OnceCell<ConnectionManager>,
     base_url: String,
+    retry: ArchiveRetryPolicy,
 }
 
 impl RedisArchiveStore {
@@ -68,9 +189,45 @@ impl RedisArchiveStore {
 
         Ok(Self {
             client,
+            conn: OnceCell::new(),
             base_url: normalized,
+            retry: ArchiveRetryPolicy::default(),
         })
     }
+
+    pub fn with_retry_policy(mut self, retry: ArchiveRetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Returns the shared connection, which reconnects by itself after
+    /// failures.
+    async fn connection(&self) -> std::result::Result<ConnectionManager, ArchiveAttemptError> {
+        self.conn
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .cloned()
+            .map_err(|error| classify_redis_error("connection", error))
+    }
+}
+
+fn classify_redis_error(command: &str, error: redis::RedisError) -> ArchiveAttemptError {
+    let message = format!("archive redis {} failed: {}", command, error);
+    let throttled = matches!(
+        error.kind(),
+        redis::ErrorKind::BusyLoadingError | redis::ErrorKind::TryAgain
+    );
+
+    if throttled
+        || error.is_io_error()
+        || error.is_timeout()
+        || error.is_connection_dropped()
+        || error.is_connection_refusal()
+    {
+        ArchiveAttemptError::Transient { message, throttled }
+    } else {
+        ArchiveAttemptError::Fatal(RimError::Internal(message))
+    }
 }
 
 #[async_trait]
@@ -107,20 +264,15 @@ impl ArchiveStore for RedisArchiveStore {
         let end_isize = isize::try_from(end)
             .map_err(|_| RimError::InvalidRequest(format!("archive page too large: {}", end)))?;
 
-        let mut conn = self
-            .client
-            .get_multiplexed_async_connection()
-            .await
-            .map_err(|error| {
-                RimError::Internal(format!("archive redis connection failed: {}", error))
-            })?;
-
-        let entries: Vec<String> = conn
-            .lrange(list_key, start_isize, end_isize)
-            .await
-            .map_err(|error| {
-                RimError::Internal(format!("archive redis LRANGE failed: {}", error))
-            })?;
+        let entries: Vec<String> = self
+            .retry
+            .run("redis LRANGE", || async {
+                let mut conn = self.connection().await?;
+                conn.lrange(list_key, start_isize, end_isize)
+                    .await
+                    .map_err(|error| classify_redis_error("LRANGE", error))
+            })
+            .await?;
 
         let next_cursor = if entries.len() >= limit {
             Some((start + entries.len()).to_string())
@@ -135,47 +287,50 @@ impl ArchiveStore for RedisArchiveStore {
     }
 
     async fn read_range(&self, object_key: &str, start: u64, end: u64) -> Result<Bytes> {
-        let mut conn = self
-            .client
-            .get_multiplexed_async_connection()
-            .await
-            .map_err(|error| {
-                RimError::Internal(format!("archive redis connection failed: {}", error))
-            })?;
-
         let start_i64 = i64::try_from(start)
             .map_err(|_| RimError::Internal(format!("invalid redis range start: {}", start)))?;
         let end_i64 = i64::try_from(end)
             .map_err(|_| RimError::Internal(format!("invalid redis range end: {}", end)))?;
 
-        let payload: Vec<u8> = redis::cmd("GETRANGE")
-            .arg(object_key)
-            .arg(start_i64)
-            .arg(end_i64)
-            .query_async(&mut conn)
-            .await
-            .map_err(|error| {
-                RimError::Internal(format!("archive redis GETRANGE failed: {}", error))
-            })?;
+        let payload: Vec<u8> = self
+            .retry
+            .run("redis GETRANGE", || async {
+                let mut conn = self.connection().await?;
+                redis::cmd("GETRANGE")
+                    .arg(object_key)
+                    .arg(start_i64)
+                    .arg(end_i64)
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|error| classify_redis_error("GETRANGE", error))
+            })
+            .await?;
 
         Ok(Bytes::from(payload))
     }
 
     async fn write_blob(&self, object_key: &str, body: &[u8]) -> Result<()> {
-        let mut conn = self
-            .client
-            .get_multiplexed_async_connection()
+        self.retry
+            .run("redis SET", || async {
+                let mut conn = self.connection().await?;
+                conn.set::<_, _, ()>(object_key, body)
+                    .await
+                    .map_err(|error| classify_redis_error("SET", error))
+            })
             .await
-            .map_err(|error| {
-                RimError::Internal(format!("archive redis connection failed: {}", error))
-            })?;
+    }
 
-        let _: () = conn
-            .set(object_key, body)
+    async fn ping(&self) -> Result<()> {
+        self.retry
+            .single_attempt()
+            .run("redis PING", || async {
+                let mut conn = self.connection().await?;
+                redis::cmd("PING")
+                    .query_async::<_, ()>(&mut conn)
+                    .await
+                    .map_err(|error| classify_redis_error("PING", error))
+            })
             .await
-            .map_err(|error| RimError::Internal(format!("archive redis SET failed: {}", error)))?;
-
-        Ok(())
     }
 
     fn archive_url_for_key(&self, object_key: &str) -> String {
@@ -184,8 +339,238 @@ impl ArchiveStore for RedisArchiveStore {
     }
 }
 
-pub struct S3ArchiveStore {
+/// A bucket behind one of the object store backends (S3, GCS, Azure Blob).
+/// Requests are signed by the backend client; retries and the classification
+/// of failures are ours.
+struct CloudArchive {
     store: Arc<dyn ObjectStore>,
+    /// Names the backend in errors, e.g. `s3`.
+    backend: &'static str,
+    retry: ArchiveRetryPolicy,
+}
+
+/// Client options shared by the object store backends. Retries are ours:
+/// the client's own policy keeps retrying for minutes and hides the status
+/// codes we classify failures by.
+fn cloud_client_options() -> (ClientOptions, RetryConfig) {
+    let client_options = ClientOptions::new()
+        .with_connect_timeout(Duration::from_secs(5))
+        .with_timeout(Duration::from_secs(30))
+        .with_pool_idle_timeout(Duration::from_secs(90));
+    let retry = RetryConfig {
+        max_retries: 0,
+        ..RetryConfig::default()
+    };
+    (client_options, retry)
+}
+
+impl CloudArchive {
+    fn new(store: Arc<dyn ObjectStore>, backend: &'static str) -> Self {
+        Self {
+            store,
+            backend,
+            retry: ArchiveRetryPolicy::default(),
+        }
+    }
+
+    fn object_path(&self, object_key: &str) -> Result<ObjectPath> {
+        let key = object_key.trim_matches('/');
+        if key.is_empty() {
+            return Err(RimError::InvalidRequest(
+                "archive object key cannot be empty".to_string(),
+            ));
+        }
+
+        ObjectPath::parse(key).map_err(|error| {
+            RimError::InvalidRequest(format!(
+                "invalid archive object key '{}': {}",
+                object_key, error
+            ))
+        })
+    }
+
+    fn classify(&self, operation: &str, error: object_store::Error) -> ArchiveAttemptError {
+        classify_cloud_error(self.backend, operation, error)
+    }
+
+    async fn list_blobs_page(
+        &self,
+        list_key: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ArchiveListPage> {
+        if limit == 0 {
+            return Ok(ArchiveListPage {
+                entries: Vec::new(),
+                next_cursor: None,
+            });
+        }
+
+        let offset = cursor
+            .map(|value| {
+                value.parse::<usize>().map_err(|_| {
+                    RimError::InvalidRequest(format!(
+                        "invalid archive list cursor '{}': expected numeric offset",
+                        value
+                    ))
+                })
+            })
+            .transpose()?
+            .unwrap_or(0);
+
+        let prefix = list_key.trim_matches('/');
+        let prefix_path = if prefix.is_empty() {
+            None
+        } else {
+            Some(ObjectPath::from(prefix.to_string()))
+        };
+
+        self.retry
+            .run(&format!("{} list", self.backend), || async {
+                let mut stream = self.store.list(prefix_path.as_ref());
+
+                let mut skipped = 0usize;
+                let mut entries = Vec::with_capacity(limit);
+                let mut has_more = false;
+
+                while let Some(item) = stream.next().await {
+                    let meta = item.map_err(|error| self.classify("list", error))?;
+
+                    if skipped < offset {
+                        skipped += 1;
+                        continue;
+                    }
+
+                    if entries.len() < limit {
+                        entries.push(meta.location.to_string());
+                        continue;
+                    }
+
+                    has_more = true;
+                    break;
+                }
+
+                let next_cursor = if has_more {
+                    Some((offset + entries.len()).to_string())
+                } else {
+                    None
+                };
+
+                Ok(ArchiveListPage {
+                    entries,
+                    next_cursor,
+                })
+            })
+            .await
+    }
+
+    async fn read_range(&self, object_key: &str, start: u64, end: u64) -> Result<Bytes> {
+        if end < start {
+            return Err(RimError::InvalidRequest(format!(
+                "invalid archive range: start={} end={}",
+                start, end
+            )));
+        }
+
+        let path = self.object_path(object_key)?;
+        let start_usize = usize::try_from(start).map_err(|_| {
+            RimError::InvalidRequest(format!("archive range start too large: {}", start))
+        })?;
+        let end_exclusive_u64 = end
+            .checked_add(1)
+            .ok_or_else(|| RimError::InvalidRequest("archive range overflow".to_string()))?;
+        let end_exclusive = usize::try_from(end_exclusive_u64).map_err(|_| {
+            RimError::InvalidRequest(format!("archive range end too large: {}", end))
+        })?;
+
+        self.retry
+            .run(&format!("{} get_range", self.backend), || async {
+                self.store
+                    .get_range(&path, start_usize..end_exclusive)
+                    .await
+                    .map_err(|error| self.classify("get_range", error))
+            })
+            .await
+    }
+
+    async fn write_blob(&self, object_key: &str, body: &[u8]) -> Result<()> {
+        let path = self.object_path(object_key)?;
+        let payload = Bytes::copy_from_slice(body);
+
+        self.retry
+            .run(&format!("{} put", self.backend), || async {
+                self.store
+                    .put(&path, payload.clone().into())
+                    .await
+                    .map(|_| ())
+                    .map_err(|error| self.classify("put", error))
+            })
+            .await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.retry
+            .single_attempt()
+            .run(&format!("{} list", self.backend), || async {
+                match self.store.list(None).next().await {
+                    Some(Err(error)) => Err(self.classify("list", error)),
+                    _ => Ok(()),
+                }
+            })
+            .await
+    }
+}
+
+fn classify_cloud_error(
+    backend: &str,
+    operation: &str,
+    error: object_store::Error,
+) -> ArchiveAttemptError {
+    let message = format!("archive {} {} failed: {}", backend, operation, error);
+
+    let object_store::Error::Generic { source, .. } = &error else {
+        return ArchiveAttemptError::Fatal(RimError::Internal(message));
+    };
+
+    match cloud_response_status(source.as_ref()) {
+        Some(status) if status == 429 || status >= 500 => ArchiveAttemptError::Transient {
+            message,
+            throttled: status == 429 || status == 503,
+        },
+        Some(_) => ArchiveAttemptError::Fatal(RimError::Internal(message)),
+        None if cloud_transport_failed(source.as_ref()) => ArchiveAttemptError::Transient {
+            message,
+            throttled: false,
+        },
+        None => ArchiveAttemptError::Fatal(RimError::Internal(message)),
+    }
+}
+
+/// object_store keeps its HTTP error type private; the status only shows up
+/// in the message ("... with status 503: ...").
+fn cloud_response_status(error: &(dyn std::error::Error + 'static)) -> Option<u16> {
+    let message = error.to_string();
+    let (_, rest) = message.split_once("with status ")?;
+    rest.get(..3)?.parse().ok()
+}
+
+fn cloud_transport_failed(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+            return error.is_timeout()
+                || error.is_connect()
+                || error.is_request()
+                || error.is_body();
+        }
+        current = error.source();
+    }
+
+    false
+}
+
+pub struct S3ArchiveStore {
+    archive: CloudArchive,
     bucket: String,
 }
 
@@ -212,11 +597,14 @@ impl S3ArchiveStore {
             ));
         }
 
+        let (client_options, retry) = cloud_client_options();
         let mut builder = AmazonS3Builder::new()
             .with_bucket_name(bucket_trimmed)
             .with_region(region_trimmed)
             .with_access_key_id(access_key_id)
-            .with_secret_access_key(secret_access_key);
+            .with_secret_access_key(secret_access_key)
+            .with_client_options(client_options)
+            .with_retry(retry);
 
         if let Some(endpoint) = endpoint.map(str::trim).filter(|value| !value.is_empty()) {
             builder = builder.with_endpoint(endpoint);
@@ -231,16 +619,315 @@ impl S3ArchiveStore {
             .map_err(|error| RimError::Config(format!("archive s3 config error: {}", error)))?;
 
         Ok(Self {
-            store: Arc::new(store),
+            archive: CloudArchive::new(Arc::new(store), "s3"),
             bucket: bucket_trimmed.to_string(),
         })
     }
 
+    pub fn with_retry_policy(mut self, retry: ArchiveRetryPolicy) -> Self {
+        self.archive.retry = retry;
+        self
+    }
+
     pub fn bucket(&self) -> &str {
         &self.bucket
     }
+}
 
-    fn object_path(&self, object_key: &str) -> Result<ObjectPath> {
+#[async_trait]
+impl ArchiveStore for S3ArchiveStore {
+    async fn list_blobs_page(
+        &self,
+        list_key: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ArchiveListPage> {
+        self.archive.list_blobs_page(list_key, cursor, limit).await
+    }
+
+    async fn read_range(&self, object_key: &str, start: u64, end: u64) -> Result<Bytes> {
+        self.archive.read_range(object_key, start, end).await
+    }
+
+    async fn write_blob(&self, object_key: &str, body: &[u8]) -> Result<()> {
+        self.archive.write_blob(object_key, body).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.archive.ping().await
+    }
+
+    fn archive_url_for_key(&self, object_key: &str) -> String {
+        let key = object_key.trim_start_matches('/');
+        format!("s3://{}/{}", self.bucket, key)
+    }
+
+    fn object_key_for_url(&self, archive_url: &Url) -> Result<String> {
+        let (bucket, key) = parse_s3_archive_url(archive_url)?;
+        check_archive_bucket(&bucket, &self.bucket)?;
+        Ok(key)
+    }
+}
+
+/// An archive in a Google Cloud Storage bucket. Without a service account
+/// the client uses the instance's application default credentials.
+pub struct GcsArchiveStore {
+    archive: CloudArchive,
+    bucket: String,
+}
+
+impl GcsArchiveStore {
+    pub fn new(
+        bucket: &str,
+        service_account_path: Option<&str>,
+        service_account_key: Option<&str>,
+    ) -> Result<Self> {
+        let bucket_trimmed = bucket.trim();
+        if bucket_trimmed.is_empty() {
+            return Err(RimError::Config(
+                "archive gcs bucket cannot be empty".to_string(),
+            ));
+        }
+
+        let (client_options, retry) = cloud_client_options();
+        let mut builder = GoogleCloudStorageBuilder::new()
+            .with_bucket_name(bucket_trimmed)
+            .with_client_options(client_options)
+            .with_retry(retry);
+
+        if let Some(path) = service_account_path
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+        {
+            builder = builder.with_service_account_path(path);
+        }
+
+        if let Some(key) = service_account_key
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+        {
+            builder = builder.with_service_account_key(key);
+        }
+
+        let store = builder
+            .build()
+            .map_err(|error| RimError::Config(format!("archive gcs config error: {}", error)))?;
+
+        Ok(Self {
+            archive: CloudArchive::new(Arc::new(store), "gcs"),
+            bucket: bucket_trimmed.to_string(),
+        })
+    }
+
+    pub fn with_retry_policy(mut self, retry: ArchiveRetryPolicy) -> Self {
+        self.archive.retry = retry;
+        self
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+}
+
+#[async_trait]
+impl ArchiveStore for GcsArchiveStore {
+    async fn list_blobs_page(
+        &self,
+        list_key: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ArchiveListPage> {
+        self.archive.list_blobs_page(list_key, cursor, limit).await
+    }
+
+    async fn read_range(&self, object_key: &str, start: u64, end: u64) -> Result<Bytes> {
+        self.archive.read_range(object_key, start, end).await
+    }
+
+    async fn write_blob(&self, object_key: &str, body: &[u8]) -> Result<()> {
+        self.archive.write_blob(object_key, body).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.archive.ping().await
+    }
+
+    fn archive_url_for_key(&self, object_key: &str) -> String {
+        let key = object_key.trim_start_matches('/');
+        format!("gs://{}/{}", self.bucket, key)
+    }
+
+    fn object_key_for_url(&self, archive_url: &Url) -> Result<String> {
+        let (bucket, key) = parse_gcs_archive_url(archive_url)?;
+        check_archive_bucket(&bucket, &self.bucket)?;
+        Ok(key)
+    }
+}
+
+/// An archive in an Azure Blob Storage container, authorized by the
+/// account's access key, a SAS token or, without either, the instance's
+/// managed identity.
+pub struct AzureArchiveStore {
+    archive: CloudArchive,
+    container: String,
+}
+
+impl AzureArchiveStore {
+    pub fn new(
+        account: &str,
+        container: &str,
+        access_key: Option<&str>,
+        sas_token: Option<&str>,
+        endpoint: Option<&str>,
+        allow_http: bool,
+    ) -> Result<Self> {
+        let account_trimmed = account.trim();
+        if account_trimmed.is_empty() {
+            return Err(RimError::Config(
+                "archive azure account cannot be empty".to_string(),
+            ));
+        }
+
+        let container_trimmed = container.trim();
+        if container_trimmed.is_empty() {
+            return Err(RimError::Config(
+                "archive azure container cannot be empty".to_string(),
+            ));
+        }
+
+        let (client_options, retry) = cloud_client_options();
+        let mut builder = MicrosoftAzureBuilder::new()
+            .with_account(account_trimmed)
+            .with_container_name(container_trimmed)
+            .with_client_options(client_options)
+            .with_retry(retry);
+
+        if let Some(access_key) = access_key.map(str::trim).filter(|value| !value.is_empty()) {
+            builder = builder.with_access_key(access_key);
+        }
+
+        if let Some(sas_token) = sas_token.map(str::trim).filter(|value| !value.is_empty()) {
+            builder = builder.with_sas_authorization(parse_sas_token(sas_token)?);
+        }
+
+        if let Some(endpoint) = endpoint.map(str::trim).filter(|value| !value.is_empty()) {
+            builder = builder.with_endpoint(endpoint.to_string());
+        }
+
+        if allow_http {
+            builder = builder.with_allow_http(true);
+        }
+
+        let store = builder
+            .build()
+            .map_err(|error| RimError::Config(format!("archive azure config error: {}", error)))?;
+
+        Ok(Self {
+            archive: CloudArchive::new(Arc::new(store), "azure"),
+            container: container_trimmed.to_string(),
+        })
+    }
+
+    pub fn with_retry_policy(mut self, retry: ArchiveRetryPolicy) -> Self {
+        self.archive.retry = retry;
+        self
+    }
+
+    pub fn container(&self) -> &str {
+        &self.container
+    }
+}
+
+/// Splits a SAS token (`sv=...&sig=...`, with or without the leading `?`)
+/// into its query pairs.
+fn parse_sas_token(token: &str) -> Result<Vec<(String, String)>> {
+    let parsed = Url::parse(&format!("http://sas/?{}", token.trim_start_matches('?')))
+        .map_err(|error| RimError::Config(format!("invalid archive azure sas token: {}", error)))?;
+    Ok(parsed
+        .query_pairs()
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect())
+}
+
+#[async_trait]
+impl ArchiveStore for AzureArchiveStore {
+    async fn list_blobs_page(
+        &self,
+        list_key: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ArchiveListPage> {
+        self.archive.list_blobs_page(list_key, cursor, limit).await
+    }
+
+    async fn read_range(&self, object_key: &str, start: u64, end: u64) -> Result<Bytes> {
+        self.archive.read_range(object_key, start, end).await
+    }
+
+    async fn write_blob(&self, object_key: &str, body: &[u8]) -> Result<()> {
+        self.archive.write_blob(object_key, body).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.archive.ping().await
+    }
+
+    fn archive_url_for_key(&self, object_key: &str) -> String {
+        let key = object_key.trim_start_matches('/');
+        format!("az://{}/{}", self.container, key)
+    }
+
+    fn object_key_for_url(&self, archive_url: &Url) -> Result<String> {
+        let (container, key) = parse_azure_archive_url(archive_url)?;
+        check_archive_bucket(&container, &self.container)?;
+        Ok(key)
+    }
+}
+
+/// An archive in a directory, typically a big slow disk or an NFS/USB mount
+/// at sites without cloud connectivity. Objects are plain files under
+/// `root`, addressed by `file://` URLs.
+pub struct LocalFsArchiveStore {
+    root: PathBuf,
+    retry: ArchiveRetryPolicy,
+}
+
+impl LocalFsArchiveStore {
+    /// `root` must already exist: creating it would quietly fill the local
+    /// disk when the mount is missing.
+    pub fn new(root: &str) -> Result<Self> {
+        let root = Path::new(root.trim());
+        if !root.is_absolute() {
+            return Err(RimError::Config(format!(
+                "archive fs path must be absolute: {}",
+                root.display()
+            )));
+        }
+        if !root.is_dir() {
+            return Err(RimError::Config(format!(
+                "archive fs path is not a directory: {}",
+                root.display()
+            )));
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            retry: ArchiveRetryPolicy::default(),
+        })
+    }
+
+    pub fn with_retry_policy(mut self, retry: ArchiveRetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Maps an object key onto a file under the root, refusing keys that
+    /// would leave it.
+    fn file_path(&self, object_key: &str) -> Result<PathBuf> {
         let key = object_key.trim_matches('/');
         if key.is_empty() {
             return Err(RimError::InvalidRequest(
@@ -248,17 +935,110 @@ impl S3ArchiveStore {
             ));
         }
 
-        ObjectPath::parse(key).map_err(|error| {
-            RimError::InvalidRequest(format!(
-                "invalid archive object key '{}': {}",
-                object_key, error
-            ))
-        })
+        let mut path = self.root.clone();
+        for segment in key.split('/') {
+            if segment.is_empty() || segment == "." || segment == ".." {
+                return Err(RimError::InvalidRequest(format!(
+                    "invalid archive object key: {}",
+                    object_key
+                )));
+            }
+            path.push(segment);
+        }
+        Ok(path)
+    }
+}
+
+/// Files under `dir` as object keys relative to `root`, sorted so offsets
+/// stay stable between pages. Hidden files are in-flight writes.
+fn list_fs_keys(root: &Path, dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                keys.push(relative.to_string_lossy().into_owned());
+            }
+        }
+    }
+    keys.sort();
+    Ok(keys)
+}
+
+/// Writes through a hidden temp file, synced and renamed into place, so
+/// readers never see a partial object.
+fn write_fs_object(path: &Path, body: &[u8]) -> std::io::Result<()> {
+    let dir = path.parent().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "object has no parent")
+    })?;
+    std::fs::create_dir_all(dir)?;
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = dir.join(format!(".{}.{:016x}.tmp", name, rand::random::<u64>()));
+    let result = (|| {
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(body)?;
+        file.sync_all()?;
+        std::fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+async fn read_fs_range(path: &Path, start: u64, end: u64) -> std::io::Result<Bytes> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(std::io::SeekFrom::Start(start)).await?;
+    let len = end.saturating_sub(start).saturating_add(1);
+    let mut payload = Vec::new();
+    file.take(len).read_to_end(&mut payload).await?;
+    Ok(Bytes::from(payload))
+}
+
+/// A missing object or a refused path will not fix itself; anything else
+/// (stale NFS handles, I/O errors from a flaky mount) is worth retrying.
+fn classify_fs_error(operation: &str, path: &Path, error: std::io::Error) -> ArchiveAttemptError {
+    let message = format!(
+        "archive fs {} failed: {}: {}",
+        operation,
+        path.display(),
+        error
+    );
+    match error.kind() {
+        std::io::ErrorKind::NotFound
+        | std::io::ErrorKind::PermissionDenied
+        | std::io::ErrorKind::InvalidInput => {
+            ArchiveAttemptError::Fatal(RimError::Internal(message))
+        }
+        _ => ArchiveAttemptError::Transient {
+            message,
+            throttled: false,
+        },
     }
 }
 
 #[async_trait]
-impl ArchiveStore for S3ArchiveStore {
+impl ArchiveStore for LocalFsArchiveStore {
     async fn list_blobs_page(
         &self,
         list_key: &str,
@@ -285,38 +1065,35 @@ impl ArchiveStore for S3ArchiveStore {
             .unwrap_or(0);
 
         let prefix = list_key.trim_matches('/');
-        let prefix_path = if prefix.is_empty() {
-            None
+        let dir = if prefix.is_empty() {
+            self.root.clone()
         } else {
-            Some(ObjectPath::from(prefix.to_string()))
+            self.file_path(prefix)?
         };
 
-        let mut stream = self.store.list(prefix_path.as_ref());
-
-        let mut skipped = 0usize;
-        let mut entries = Vec::with_capacity(limit);
-        let mut has_more = false;
-
-        while let Some(item) = stream.next().await {
-            let meta = item.map_err(|error| {
-                RimError::Internal(format!("archive s3 list failed: {}", error))
-            })?;
-
-            if skipped < offset {
-                skipped += 1;
-                continue;
-            }
-
-            if entries.len() < limit {
-                entries.push(meta.location.to_string());
-                continue;
-            }
-
-            has_more = true;
-            break;
-        }
+        let keys = self
+            .retry
+            .run("fs list", || {
+                let root = self.root.clone();
+                let dir = dir.clone();
+                async move {
+                    tokio::task::spawn_blocking(move || {
+                        list_fs_keys(&root, &dir)
+                            .map_err(|error| classify_fs_error("list", &dir, error))
+                    })
+                    .await
+                    .map_err(|error| {
+                        ArchiveAttemptError::Fatal(RimError::Internal(format!(
+                            "archive fs list task failed: {}",
+                            error
+                        )))
+                    })?
+                }
+            })
+            .await?;
 
-        let next_cursor = if has_more {
+        let entries: Vec<String> = keys.iter().skip(offset).take(limit).cloned().collect();
+        let next_cursor = if offset + entries.len() < keys.len() {
             Some((offset + entries.len()).to_string())
         } else {
             None
@@ -336,73 +1113,160 @@ impl ArchiveStore for S3ArchiveStore {
             )));
         }
 
-        let path = self.object_path(object_key)?;
-        let start_usize = usize::try_from(start).map_err(|_| {
-            RimError::InvalidRequest(format!("archive range start too large: {}", start))
-        })?;
-        let end_exclusive_u64 = end
-            .checked_add(1)
-            .ok_or_else(|| RimError::InvalidRequest("archive range overflow".to_string()))?;
-        let end_exclusive = usize::try_from(end_exclusive_u64).map_err(|_| {
-            RimError::InvalidRequest(format!("archive range end too large: {}", end))
-        })?;
-
-        self.store
-            .get_range(&path, start_usize..end_exclusive)
+        let path = self.file_path(object_key)?;
+        self.retry
+            .run("fs read", || async {
+                read_fs_range(&path, start, end)
+                    .await
+                    .map_err(|error| classify_fs_error("read", &path, error))
+            })
             .await
-            .map_err(|error| RimError::Internal(format!("archive s3 get_range failed: {}", error)))
     }
 
     async fn write_blob(&self, object_key: &str, body: &[u8]) -> Result<()> {
-        let path = self.object_path(object_key)?;
+        let path = self.file_path(object_key)?;
         let payload = Bytes::copy_from_slice(body);
 
-        self.store
-            .put(&path, payload.into())
+        self.retry
+            .run("fs write", || {
+                let path = path.clone();
+                let payload = payload.clone();
+                async move {
+                    tokio::task::spawn_blocking(move || {
+                        write_fs_object(&path, &payload)
+                            .map_err(|error| classify_fs_error("write", &path, error))
+                    })
+                    .await
+                    .map_err(|error| {
+                        ArchiveAttemptError::Fatal(RimError::Internal(format!(
+                            "archive fs write task failed: {}",
+                            error
+                        )))
+                    })?
+                }
+            })
             .await
-            .map_err(|error| RimError::Internal(format!("archive s3 put failed: {}", error)))?;
+    }
 
-        Ok(())
+    async fn ping(&self) -> Result<()> {
+        self.retry
+            .single_attempt()
+            .run("fs stat", || async {
+                match tokio::fs::metadata(&self.root).await {
+                    Ok(metadata) if metadata.is_dir() => Ok(()),
+                    Ok(_) => Err(ArchiveAttemptError::Fatal(RimError::Internal(format!(
+                        "archive fs path is not a directory: {}",
+                        self.root.display()
+                    )))),
+                    Err(error) => Err(classify_fs_error("stat", &self.root, error)),
+                }
+            })
+            .await
     }
 
     fn archive_url_for_key(&self, object_key: &str) -> String {
         let key = object_key.trim_start_matches('/');
-        format!("s3://{}/{}", self.bucket, key)
+        let path = self.root.join(key);
+        Url::from_file_path(&path)
+            .map(String::from)
+            .unwrap_or_else(|_| format!("file://{}", path.display()))
+    }
+
+    fn object_key_for_url(&self, archive_url: &Url) -> Result<String> {
+        parse_local_fs_archive_url(archive_url, &self.root)
     }
 }
 
-static DEFAULT_S3_ARCHIVE_STORE: OnceLock<Arc<S3ArchiveStore>> = OnceLock::new();
+/// Archive stores by URL scheme, consulted when reading parts back.
+static ARCHIVE_SCHEMES: OnceLock<RwLock<HashMap<String, Arc<dyn ArchiveStore>>>> = OnceLock::new();
+
+/// Redis archive stores by base URL, so reads reuse their connection.
+static REDIS_ARCHIVE_STORES: OnceLock<Mutex<HashMap<String, Arc<RedisArchiveStore>>>> =
+    OnceLock::new();
+
+/// Routes archive URLs with `scheme` to `store`, so parts archived by a
+/// store of a downstream crate can be read back without patching this one.
+/// A later registration for the same scheme replaces the earlier one.
+/// `redis://` URLs carry their own connection and need no registration,
+/// though registering `redis` takes precedence.
+pub fn register_archive_scheme(scheme: &str, store: Arc<dyn ArchiveStore>) {
+    let mut schemes = ARCHIVE_SCHEMES
+        .get_or_init(|| RwLock::new(HashMap::new()))
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    schemes.insert(scheme.to_ascii_lowercase(), store);
+}
+
+/// The store registered for `scheme`, if any.
+pub fn archive_store_for_scheme(scheme: &str) -> Option<Arc<dyn ArchiveStore>> {
+    ARCHIVE_SCHEMES
+        .get()?
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(&scheme.to_ascii_lowercase())
+        .cloned()
+}
 
 pub fn set_default_s3_archive_store(store: Arc<S3ArchiveStore>) {
-    let _ = DEFAULT_S3_ARCHIVE_STORE.set(store);
+    register_archive_scheme("s3", store);
+}
+
+pub fn set_default_gcs_archive_store(store: Arc<GcsArchiveStore>) {
+    register_archive_scheme("gs", store);
+}
+
+pub fn set_default_azure_archive_store(store: Arc<AzureArchiveStore>) {
+    register_archive_scheme("az", store);
+}
+
+pub fn set_default_local_fs_archive_store(store: Arc<LocalFsArchiveStore>) {
+    register_archive_scheme("file", store);
+}
+
+fn check_archive_bucket(bucket: &str, configured_bucket: &str) -> Result<()> {
+    if bucket != configured_bucket {
+        return Err(RimError::Config(format!(
+            "archive_url bucket '{}' does not match configured bucket '{}'",
+            bucket, configured_bucket
+        )));
+    }
+    Ok(())
+}
+
+fn shared_redis_archive_store(redis_url: &str) -> Result<Arc<RedisArchiveStore>> {
+    let mut stores = REDIS_ARCHIVE_STORES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .map_err(|_| RimError::Internal("archive redis store cache poisoned".to_string()))?;
+
+    if let Some(store) = stores.get(redis_url) {
+        return Ok(store.clone());
+    }
+
+    let store = Arc::new(RedisArchiveStore::new(redis_url)?);
+    stores.insert(redis_url.to_string(), store.clone());
+    Ok(store)
 }
 
 pub async fn read_archive_range_bytes(archive_url: &str, start: u64, end: u64) -> Result<Bytes> {
     let parsed = Url::parse(archive_url)
         .map_err(|error| RimError::InvalidRequest(format!("invalid archive_url: {}", error)))?;
 
+    if let Some(store) = archive_store_for_scheme(parsed.scheme()) {
+        let key = store.object_key_for_url(&parsed)?;
+        return store.read_range(&key, start, end).await;
+    }
+
     match parsed.scheme() {
         "redis" => {
             let (redis_url, key) = parse_redis_archive_url(&parsed)?;
-            let store = RedisArchiveStore::new(redis_url.as_str())?;
-            store.read_range(&key, start, end).await
-        }
-        "s3" => {
-            let (bucket, key) = parse_s3_archive_url(&parsed)?;
-            let store = DEFAULT_S3_ARCHIVE_STORE.get().cloned().ok_or_else(|| {
-                RimError::Config("s3 archive is not configured for runtime read path".to_string())
-            })?;
-
-            if store.bucket() != bucket {
-                return Err(RimError::Config(format!(
-                    "archive_url bucket '{}' does not match configured bucket '{}'",
-                    bucket,
-                    store.bucket()
-                )));
-            }
-
+            let store = shared_redis_archive_store(redis_url.as_str())?;
             store.read_range(&key, start, end).await
         }
+        "s3" | "gs" | "az" | "file" => Err(RimError::Config(format!(
+            "{} archive is not configured for runtime read path",
+            parsed.scheme()
+        ))),
         scheme => Err(RimError::InvalidRequest(format!(
             "unsupported archive_url scheme: {}",
             scheme
@@ -479,23 +1343,70 @@ pub fn parse_redis_archive_url(parsed: &Url) -> Result<(String, String)> {
 }
 
 pub fn parse_s3_archive_url(parsed: &Url) -> Result<(String, String)> {
-    if parsed.scheme() != "s3" {
+    parse_bucket_archive_url(parsed, "s3", "s3")
+}
+
+/// Splits `gs://bucket/key` into the bucket and the object key.
+pub fn parse_gcs_archive_url(parsed: &Url) -> Result<(String, String)> {
+    parse_bucket_archive_url(parsed, "gs", "gcs")
+}
+
+/// Splits `az://container/key` into the container and the object key.
+pub fn parse_azure_archive_url(parsed: &Url) -> Result<(String, String)> {
+    parse_bucket_archive_url(parsed, "az", "azure")
+}
+
+/// The object key of a `file://` URL under the configured archive root.
+pub fn parse_local_fs_archive_url(parsed: &Url, root: &Path) -> Result<String> {
+    if parsed.scheme() != "file" {
         return Err(RimError::InvalidRequest(format!(
-            "not an s3 archive url: {}",
+            "not an fs archive url: {}",
             parsed
         )));
     }
 
+    let path = parsed
+        .to_file_path()
+        .map_err(|_| RimError::InvalidRequest(format!("invalid fs archive_url: {}", parsed)))?;
+    let relative = path.strip_prefix(root).map_err(|_| {
+        RimError::Config(format!(
+            "archive_url path '{}' is outside the configured archive '{}'",
+            path.display(),
+            root.display()
+        ))
+    })?;
+
+    let key = relative
+        .components()
+        .map(|component| match component {
+            std::path::Component::Normal(segment) => segment.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|segments| !segments.is_empty())
+        .ok_or_else(|| RimError::InvalidRequest(format!("invalid fs archive_url: {}", parsed)))?;
+    Ok(key.join("/"))
+}
+
+fn parse_bucket_archive_url(parsed: &Url, scheme: &str, backend: &str) -> Result<(String, String)> {
+    if parsed.scheme() != scheme {
+        return Err(RimError::InvalidRequest(format!(
+            "not an {} archive url: {}",
+            backend, parsed
+        )));
+    }
+
     let bucket = parsed
         .host_str()
-        .ok_or_else(|| RimError::InvalidRequest("s3 archive_url missing bucket".to_string()))?
+        .ok_or_else(|| RimError::InvalidRequest(format!("{} archive_url missing bucket", backend)))?
         .to_string();
 
     let key = parsed.path().trim_matches('/').to_string();
     if key.is_empty() {
-        return Err(RimError::InvalidRequest(
-            "s3 archive_url missing object key".to_string(),
-        ));
+        return Err(RimError::InvalidRequest(format!(
+            "{} archive_url missing object key",
+            backend
+        )));
     }
 
     Ok((bucket, key))
@@ -521,3 +1432,183 @@ fn normalize_base_redis_url(url: &str) -> Result<String> {
 
     Ok(trimmed.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_retry_policy_gives_up_with_archive_unavailable() {
+        let policy = ArchiveRetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(4),
+        };
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<()> = policy
+            .run("test", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(ArchiveAttemptError::Transient {
+                    message: "timeout".to_string(),
+                    throttled: false,
+                })
+            })
+            .await;
+
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        match result {
+            Err(RimError::ArchiveUnavailable { retry_after, .. }) => {
+                assert!(retry_after <= policy.max_delay);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let fatal: Result<()> = policy
+            .run("test", || async {
+                Err(ArchiveAttemptError::Fatal(RimError::Internal(
+                    "denied".to_string(),
+                )))
+            })
+            .await;
+        assert!(matches!(fatal, Err(RimError::Internal(_))));
+    }
+
+    #[test]
+    fn bucket_archive_urls_split_into_bucket_and_key() {
+        let gcs = Url::parse("gs://cold/rimio/archive/parts/ab").unwrap();
+        assert_eq!(
+            parse_gcs_archive_url(&gcs).unwrap(),
+            ("cold".to_string(), "rimio/archive/parts/ab".to_string())
+        );
+        let azure = Url::parse("az://cold/rimio/archive/a").unwrap();
+        assert_eq!(
+            parse_azure_archive_url(&azure).unwrap(),
+            ("cold".to_string(), "rimio/archive/a".to_string())
+        );
+        assert!(parse_azure_archive_url(&gcs).is_err());
+        assert!(parse_gcs_archive_url(&Url::parse("gs://cold/").unwrap()).is_err());
+
+        assert_eq!(
+            parse_sas_token("?sv=2022-11-02&sig=a%2Bb").unwrap(),
+            vec![
+                ("sv".to_string(), "2022-11-02".to_string()),
+                ("sig".to_string(), "a+b".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn local_fs_archive_reads_ranges_and_pages_listings() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalFsArchiveStore::new(dir.path().to_str().unwrap()).unwrap();
+
+        store
+            .write_blob("rimio/archive/b", b"second")
+            .await
+            .unwrap();
+        store
+            .write_blob("rimio/archive/a", b"hello world")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.read_range("rimio/archive/a", 6, 10).await.unwrap(),
+            Bytes::from_static(b"world")
+        );
+        assert_eq!(
+            store.read_range("rimio/archive/a", 6, 99).await.unwrap(),
+            Bytes::from_static(b"world")
+        );
+        assert!(store.write_blob("../escape", b"x").await.is_err());
+
+        let page = store
+            .list_blobs_page("rimio/archive", None, 1)
+            .await
+            .unwrap();
+        assert_eq!(page.entries, vec!["rimio/archive/a".to_string()]);
+        let page = store
+            .list_blobs_page("rimio/archive", page.next_cursor.as_deref(), 1)
+            .await
+            .unwrap();
+        assert_eq!(page.entries, vec!["rimio/archive/b".to_string()]);
+        assert!(page.next_cursor.is_none());
+
+        let url = Url::parse(&store.archive_url_for_key("rimio/archive/a")).unwrap();
+        assert_eq!(
+            parse_local_fs_archive_url(&url, store.root()).unwrap(),
+            "rimio/archive/a"
+        );
+        assert!(parse_local_fs_archive_url(&url, Path::new("/elsewhere")).is_err());
+        store.ping().await.unwrap();
+    }
+
+    /// Keeps objects in memory under `mem://<name>/<key>`.
+    struct MemoryArchive {
+        name: &'static str,
+        objects: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    #[async_trait]
+    impl ArchiveStore for MemoryArchive {
+        async fn list_blobs_page(
+            &self,
+            _list_key: &str,
+            _cursor: Option<&str>,
+            _limit: usize,
+        ) -> Result<ArchiveListPage> {
+            Ok(ArchiveListPage {
+                entries: Vec::new(),
+                next_cursor: None,
+            })
+        }
+
+        async fn read_range(&self, object_key: &str, start: u64, end: u64) -> Result<Bytes> {
+            let objects = self.objects.lock().unwrap();
+            let body = objects
+                .get(object_key)
+                .ok_or_else(|| RimError::BlobNotFound(object_key.to_string()))?;
+            Ok(Bytes::copy_from_slice(
+                &body[start as usize..=(end as usize).min(body.len() - 1)],
+            ))
+        }
+
+        async fn write_blob(&self, object_key: &str, body: &[u8]) -> Result<()> {
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(object_key.to_string(), body.to_vec());
+            Ok(())
+        }
+
+        async fn ping(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn archive_url_for_key(&self, object_key: &str) -> String {
+            format!("mem://{}/{}", self.name, object_key)
+        }
+    }
+
+    #[tokio::test]
+    async fn registered_scheme_serves_archive_reads() {
+        let store = Arc::new(MemoryArchive {
+            name: "cold",
+            objects: Mutex::new(HashMap::new()),
+        });
+        store.write_blob("parts/ab", b"archived").await.unwrap();
+        let url = store.archive_url_for_key("parts/ab");
+
+        assert!(read_archive_range_bytes(&url, 0, 3).await.is_err());
+        register_archive_scheme("mem", store.clone());
+        assert_eq!(
+            read_archive_range_bytes(&url, 0, 3).await.unwrap(),
+            Bytes::from_static(b"arch")
+        );
+        assert!(
+            read_archive_range_bytes("mem://warm/parts/ab", 0, 3)
+                .await
+                .is_err()
+        );
+    }
+}
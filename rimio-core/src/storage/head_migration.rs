@@ -0,0 +1,252 @@
+use crate::{
+    HeadSchemaPhase, HeadShadowReport, HostPressureMonitor, MetadataStore, Result, SlotManager,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::interval;
+
+#[derive(Debug, Clone)]
+pub struct HeadSchemaMigrationConfig {
+    pub interval: Duration,
+    /// Phase every assigned slot is moved towards; `Legacy` rolls slots back.
+    pub target: HeadSchemaPhase,
+    /// Paths copied per backfill transaction.
+    pub backfill_batch: usize,
+}
+
+impl Default for HeadSchemaMigrationConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(600),
+            target: HeadSchemaPhase::Legacy,
+            backfill_batch: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SlotHeadSchemaStatus {
+    pub slot_id: u16,
+    pub phase: HeadSchemaPhase,
+    pub backfilled: bool,
+    pub last_report: Option<HeadShadowReport>,
+}
+
+/// Moves slots between head table layouts without a stop-the-world window.
+///
+/// A slot first dual-writes every head into both layouts while the existing
+/// heads are backfilled in small transactions. A verification pass then
+/// compares both layouts; only a clean pass lets the slot cut its reads over.
+/// A cut-over slot keeps dual-writing and is re-verified on every pass, and
+/// any difference drops it back to reading the legacy layout.
+pub struct HeadSchemaMigration {
+    slot_manager: Arc<SlotManager>,
+    config: HeadSchemaMigrationConfig,
+    reports: RwLock<BTreeMap<u16, HeadShadowReport>>,
+    host_pressure: Option<Arc<HostPressureMonitor>>,
+}
+
+impl HeadSchemaMigration {
+    pub fn new(slot_manager: Arc<SlotManager>, config: HeadSchemaMigrationConfig) -> Self {
+        Self {
+            slot_manager,
+            config,
+            reports: RwLock::new(BTreeMap::new()),
+            host_pressure: None,
+        }
+    }
+
+    /// Defers backfill and verification passes while
+    /// [`HostPressureMonitor::sheds_background`] holds; slots keep
+    /// dual-writing meanwhile.
+    pub fn with_host_pressure(mut self, host_pressure: Option<Arc<HostPressureMonitor>>) -> Self {
+        self.host_pressure = host_pressure;
+        self
+    }
+
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(self.config.interval);
+            loop {
+                ticker.tick().await;
+                if self
+                    .host_pressure
+                    .as_ref()
+                    .is_some_and(|pressure| pressure.sheds_background())
+                {
+                    continue;
+                }
+                if let Err(error) = self.run_once().await {
+                    tracing::warn!("head schema migration loop failed: {}", error);
+                }
+            }
+        });
+    }
+
+    pub async fn run_once(&self) -> Result<()> {
+        for slot_id in self.slot_manager.get_assigned_slots().await {
+            if let Err(error) = self.migrate_slot(slot_id).await {
+                tracing::warn!(
+                    "head schema migration failed: slot={} error={}",
+                    slot_id,
+                    error
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn status(&self) -> Result<Vec<SlotHeadSchemaStatus>> {
+        let reports = self.reports.read().await.clone();
+        let mut statuses = Vec::new();
+        for slot_id in self.slot_manager.get_assigned_slots().await {
+            let store = MetadataStore::new(self.slot_manager.get_slot(slot_id).await?)?;
+            statuses.push(SlotHeadSchemaStatus {
+                slot_id,
+                phase: store.head_schema_phase()?,
+                backfilled: store.head_shadow_backfilled()?,
+                last_report: reports.get(&slot_id).cloned(),
+            });
+        }
+        statuses.sort_by_key(|status| status.slot_id);
+        Ok(statuses)
+    }
+
+    pub async fn migrate_slot(&self, slot_id: u16) -> Result<HeadSchemaPhase> {
+        let store = MetadataStore::new(self.slot_manager.get_slot(slot_id).await?)?;
+        let mut phase = store.head_schema_phase()?;
+
+        if self.config.target == HeadSchemaPhase::Legacy {
+            if phase != HeadSchemaPhase::Legacy {
+                store.set_head_schema_phase(HeadSchemaPhase::Legacy)?;
+                tracing::info!("head schema rolled back: slot={}", slot_id);
+            }
+            return Ok(HeadSchemaPhase::Legacy);
+        }
+
+        if phase == HeadSchemaPhase::Legacy {
+            store.set_head_schema_phase(HeadSchemaPhase::DualWrite)?;
+            phase = HeadSchemaPhase::DualWrite;
+            tracing::info!("head schema dual-write enabled: slot={}", slot_id);
+        }
+
+        if !store.head_shadow_backfilled()? {
+            let mut cursor = None;
+            loop {
+                cursor =
+                    store.backfill_head_shadow(cursor.as_deref(), self.config.backfill_batch)?;
+                if cursor.is_none() {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+        }
+
+        let report = store.verify_head_shadow()?;
+        self.reports.write().await.insert(slot_id, report.clone());
+
+        if !report.is_clean() {
+            tracing::warn!(
+                "head schema verification failed: slot={} missing={} mismatched={} samples={:?}",
+                slot_id,
+                report.missing,
+                report.mismatched,
+                report.samples
+            );
+            if phase == HeadSchemaPhase::Cutover {
+                store.set_head_schema_phase(HeadSchemaPhase::DualWrite)?;
+                phase = HeadSchemaPhase::DualWrite;
+            }
+            return Ok(phase);
+        }
+
+        if self.config.target == HeadSchemaPhase::Cutover && phase != HeadSchemaPhase::Cutover {
+            store.set_head_schema_phase(HeadSchemaPhase::Cutover)?;
+            phase = HeadSchemaPhase::Cutover;
+            tracing::info!(
+                "head schema cut over: slot={} heads={}",
+                slot_id,
+                report.checked
+            );
+        }
+
+        Ok(phase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlobMeta, ContentHeaders, PartIndexState, TombstoneMeta};
+    use chrono::Utc;
+
+    fn meta(path: &str, generation: i64) -> BlobMeta {
+        BlobMeta {
+            path: path.to_string(),
+            slot_id: 1,
+            generation,
+            version: generation,
+            size_bytes: 1,
+            etag: format!("etag-{}", generation),
+            part_size: 1,
+            part_count: 1,
+            part_index_state: PartIndexState::Complete,
+            archive_url: None,
+            updated_at: Utc::now(),
+            metadata: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            content: ContentHeaders::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn slots_backfill_verify_and_cut_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap());
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        store.upsert_meta(&meta("a", 1)).unwrap();
+        store.upsert_meta(&meta("a", 2)).unwrap();
+        store.upsert_meta(&meta("b", 1)).unwrap();
+
+        let migration = HeadSchemaMigration::new(
+            slot_manager.clone(),
+            HeadSchemaMigrationConfig {
+                target: HeadSchemaPhase::Cutover,
+                backfill_batch: 1,
+                ..HeadSchemaMigrationConfig::default()
+            },
+        );
+        assert_eq!(
+            migration.migrate_slot(1).await.unwrap(),
+            HeadSchemaPhase::Cutover
+        );
+
+        store
+            .insert_tombstone(&TombstoneMeta {
+                path: "b".to_string(),
+                slot_id: 1,
+                generation: 2,
+                deleted_at: Utc::now(),
+                reason: "test".to_string(),
+            })
+            .unwrap();
+        let head = store.get_current_head("b").unwrap().unwrap();
+        assert_eq!(head.generation, 2);
+        assert_eq!(store.get_current_head("a").unwrap().unwrap().generation, 2);
+        assert!(store.verify_head_shadow().unwrap().is_clean());
+
+        let rollback = HeadSchemaMigration::new(slot_manager, HeadSchemaMigrationConfig::default());
+        assert_eq!(
+            rollback.migrate_slot(1).await.unwrap(),
+            HeadSchemaPhase::Legacy
+        );
+        assert!(!store.head_shadow_backfilled().unwrap());
+    }
+}
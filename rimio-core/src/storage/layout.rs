@@ -0,0 +1,142 @@
+//! Versioned on-disk layout of a data directory.
+//!
+//! Every disk carries a `LAYOUT` stamp. On startup the stamp is read and the
+//! migrations between the stamped and the current version run in order, each
+//! one re-stamping the disk when it finishes so an interrupted upgrade
+//! resumes where it stopped. A disk stamped by a newer release is refused.
+//!
+//! Versions:
+//! - 1: slots, parts and `archive/sync_cursor.sqlite3` (unstamped).
+//! - 2: archive cursors live in the node store (`node/node.sqlite3`).
+//! - 3: part directories are keyed by the sha256 of the blob path
+//!   (`slots/{slot_id}/parts/..`) instead of the path itself (`blobs/..`).
+
+use crate::{NodeStore, Result, RimError, migrate_legacy_part_dirs};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const LAYOUT_VERSION: u32 = 3;
+
+const LAYOUT_FILE: &str = "LAYOUT";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutStamp {
+    pub version: u32,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Brings a data directory to [`LAYOUT_VERSION`] and returns the version it
+/// was found at.
+pub fn prepare_data_dir(data_dir: &Path) -> Result<u32> {
+    std::fs::create_dir_all(data_dir)?;
+
+    let found = match read_stamp(data_dir)? {
+        Some(stamp) => stamp.version,
+        None if holds_data(data_dir)? => 1,
+        None => LAYOUT_VERSION,
+    };
+
+    if found > LAYOUT_VERSION {
+        return Err(RimError::Config(format!(
+            "data directory {} has layout v{}, newer than the supported v{}; refusing to start",
+            data_dir.display(),
+            found,
+            LAYOUT_VERSION
+        )));
+    }
+
+    for version in found..LAYOUT_VERSION {
+        migrate(data_dir, version)?;
+        write_stamp(data_dir, version + 1)?;
+        tracing::info!(
+            "data layout migrated: dir={} from=v{} to=v{}",
+            data_dir.display(),
+            version,
+            version + 1
+        );
+    }
+
+    if found == LAYOUT_VERSION {
+        write_stamp(data_dir, LAYOUT_VERSION)?;
+    }
+
+    Ok(found)
+}
+
+pub fn read_stamp(data_dir: &Path) -> Result<Option<LayoutStamp>> {
+    let path = data_dir.join(LAYOUT_FILE);
+    match std::fs::read(&path) {
+        Ok(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn write_stamp(data_dir: &Path, version: u32) -> Result<()> {
+    let stamp = LayoutStamp {
+        version,
+        updated_at: Utc::now(),
+    };
+    let path = data_dir.join(LAYOUT_FILE);
+    let staging = path.with_extension("tmp");
+    std::fs::write(&staging, serde_json::to_vec_pretty(&stamp)?)?;
+    std::fs::rename(&staging, &path)?;
+    Ok(())
+}
+
+/// Unstamped directories predate versioning when they already hold data.
+fn holds_data(data_dir: &Path) -> Result<bool> {
+    Ok(data_dir.join("slots").exists() || data_dir.join("archive").exists())
+}
+
+fn migrate(data_dir: &Path, from: u32) -> Result<()> {
+    match from {
+        1 => {
+            let node_store = NodeStore::open(data_dir)?;
+            crate::archive::import_legacy_cursors(data_dir, &node_store)
+        }
+        2 => {
+            let moved = migrate_legacy_part_dirs(data_dir)?;
+            tracing::info!(
+                "part directories rehashed: dir={} parts={}",
+                data_dir.display(),
+                moved
+            );
+            Ok(())
+        }
+        _ => Err(RimError::Internal(format!(
+            "no data layout migration from v{}",
+            from
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_is_stamped_migrated_and_guarded() {
+        let fresh = tempfile::tempdir().unwrap();
+        assert_eq!(prepare_data_dir(fresh.path()).unwrap(), LAYOUT_VERSION);
+        assert_eq!(
+            read_stamp(fresh.path()).unwrap().unwrap().version,
+            LAYOUT_VERSION
+        );
+
+        let legacy = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(legacy.path().join("slots").join("1")).unwrap();
+        assert_eq!(prepare_data_dir(legacy.path()).unwrap(), 1);
+        assert_eq!(
+            read_stamp(legacy.path()).unwrap().unwrap().version,
+            LAYOUT_VERSION
+        );
+
+        write_stamp(fresh.path(), LAYOUT_VERSION + 1).unwrap();
+        assert!(matches!(
+            prepare_data_dir(fresh.path()),
+            Err(RimError::Config(_))
+        ));
+    }
+}
@@ -1,12 +1,22 @@
-use crate::error::{Result, RimError};
+use crate::error::{MetadataViolation, Result, RimError};
 use crate::slot_manager::{PART_SIZE, Slot};
 use crate::storage::compute_hash;
+use crate::{SharedClock, system_clock};
 use chrono::{DateTime, Utc};
-use rusqlite::{Connection, OptionalExtension, params};
+use rusqlite::{Connection, ErrorCode, OptionalExtension, TransactionBehavior, params};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 
+/// Backups kept per slot; older ones are pruned after each new backup.
+pub const SLOT_BACKUP_RETENTION: usize = 3;
+
+/// Problems `PRAGMA integrity_check` lists before it stops.
+const INTEGRITY_CHECK_MAX_PROBLEMS: usize = 100;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum PartIndexState {
@@ -21,6 +31,138 @@ impl Default for PartIndexState {
     }
 }
 
+/// Phases of moving heads from `file_entries`, which keeps every head row, to
+/// `blob_heads`, which keeps only the current head of each path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeadSchemaPhase {
+    #[default]
+    Legacy,
+    /// Head writes go to both layouts; reads use `file_entries`.
+    DualWrite,
+    /// Head writes go to both layouts; point reads use `blob_heads`.
+    Cutover,
+}
+
+impl HeadSchemaPhase {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Legacy => "legacy",
+            Self::DualWrite => "dual_write",
+            Self::Cutover => "cutover",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "legacy" => Ok(Self::Legacy),
+            "dual_write" => Ok(Self::DualWrite),
+            "cutover" => Ok(Self::Cutover),
+            other => Err(RimError::Internal(format!(
+                "unknown head schema phase: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// What a change did to a path's current head.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The path had no live head before.
+    Create,
+    /// A newer version replaced a live head.
+    Update,
+    /// The path's head became a tombstone.
+    Delete,
+}
+
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "create" => Ok(Self::Create),
+            "update" => Ok(Self::Update),
+            "delete" => Ok(Self::Delete),
+            other => Err(RimError::Internal(format!(
+                "unknown change kind: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// One entry of a slot's change feed. `seq` only grows, so a reader resumes
+/// from the last one it saw.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotChange {
+    pub seq: i64,
+    pub path: String,
+    pub kind: ChangeKind,
+    pub generation: i64,
+    pub head_sha256: String,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Differences between the current heads of both layouts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HeadShadowReport {
+    pub checked: u64,
+    pub missing: u64,
+    pub mismatched: u64,
+    /// A few of the differing paths.
+    pub samples: Vec<String>,
+    pub verified_at: Option<DateTime<Utc>>,
+}
+
+impl HeadShadowReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing == 0 && self.mismatched == 0
+    }
+}
+
+const HEAD_SHADOW_SAMPLES: usize = 8;
+const HEAD_CHAIN_SAMPLES: usize = 8;
+const HEAD_PHASE_SETTING: &str = "head_schema_phase";
+const HEAD_BACKFILLED_SETTING: &str = "head_schema_backfilled";
+
+/// Result of walking the head chains of a slot.
+///
+/// Each chained path keeps a log of the heads it had, every record carrying
+/// the hash of the one before it. A record that was altered, removed or
+/// reordered breaks the links after it, and a current head that differs
+/// from the last record shows metadata changed outside the write path.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HeadChainReport {
+    pub paths: u64,
+    pub records: u64,
+    /// Paths whose chain or current head does not check out.
+    pub broken: u64,
+    /// Paths with a head but no chain, such as those last written before
+    /// chaining was enabled.
+    pub unchained: u64,
+    /// A few of the broken paths, with what was wrong.
+    pub samples: Vec<String>,
+    /// Hash over the last record of every chained path. Replicas that
+    /// applied the same writes report the same digest.
+    pub digest: String,
+    pub verified_at: Option<DateTime<Utc>>,
+}
+
+impl HeadChainReport {
+    pub fn is_clean(&self) -> bool {
+        self.broken == 0
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlobMeta {
     pub path: String,
@@ -39,6 +181,26 @@ pub struct BlobMeta {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub archive_url: Option<String>,
     pub updated_at: DateTime<Utc>,
+    /// User metadata given with the write, returned as headers on reads.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// Tag set of the version; listings can filter on it.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub content: ContentHeaders,
+}
+
+/// HTTP headers describing the body, given with the write and sent back on
+/// reads.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentHeaders {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +230,146 @@ pub struct BlobHead {
     pub tombstone: Option<TombstoneMeta>,
 }
 
+/// How long superseded versions and tombstones are kept. Nothing is pruned
+/// by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRetention {
+    /// Versions kept per path, the current one included.
+    #[serde(default)]
+    pub keep_last_versions: Option<usize>,
+    /// Superseded versions older than this are dropped.
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+    /// Paths deleted longer ago than this are forgotten entirely. Replicas
+    /// that missed the delete for longer can bring the blob back.
+    #[serde(default)]
+    pub tombstone_max_age_secs: Option<u64>,
+}
+
+impl VersionRetention {
+    pub fn is_enabled(&self) -> bool {
+        self.keep_last_versions.is_some()
+            || self.max_age_secs.is_some()
+            || self.tombstone_max_age_secs.is_some()
+    }
+}
+
+/// Rows removed by [`MetadataStore::prune_versions`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrunedVersions {
+    pub versions: u64,
+    /// Paths whose tombstone expired and that were removed entirely.
+    pub paths: u64,
+    /// Part files no remaining row refers to; the caller deletes them.
+    pub parts: Vec<PrunedPart>,
+    pub part_bytes: u64,
+}
+
+/// A part file left behind by pruning, with the blob it belonged to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrunedPart {
+    pub blob_path: String,
+    pub path: String,
+}
+
+/// Size and freshness of the data one replica holds for a slot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotStats {
+    pub slot_id: u16,
+    pub blob_count: u64,
+    pub data_size_bytes: u64,
+    pub last_write_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub sqlite: SqliteStats,
+}
+
+/// File-level health of a slot database.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SqliteStats {
+    pub file_size_bytes: u64,
+    pub wal_size_bytes: u64,
+    pub page_size: u64,
+    pub page_count: u64,
+    pub free_pages: u64,
+    /// Writes that failed with `SQLITE_BUSY` since the slot was loaded.
+    pub busy_errors: u64,
+    pub last_vacuum_at: Option<DateTime<Utc>>,
+    pub last_checkpoint_at: Option<DateTime<Utc>>,
+    /// When `PRAGMA integrity_check` last found the database sound.
+    #[serde(default)]
+    pub last_integrity_check_at: Option<DateTime<Utc>>,
+}
+
+impl SqliteStats {
+    pub fn free_bytes(&self) -> u64 {
+        self.free_pages * self.page_size
+    }
+}
+
+/// A head prepared for commit, together with its serialized payload.
+#[derive(Debug, Clone)]
+pub enum HeadWrite {
+    Meta {
+        meta: BlobMeta,
+        inline_data: Vec<u8>,
+        head_sha256: String,
+    },
+    Tombstone {
+        tombstone: TombstoneMeta,
+        inline_data: Vec<u8>,
+        head_sha256: String,
+    },
+}
+
+impl HeadWrite {
+    pub fn meta(meta: BlobMeta) -> Result<Self> {
+        let inline_data = serde_json::to_vec(&meta)?;
+        let head_sha256 = compute_hash(&inline_data);
+        Ok(Self::Meta {
+            meta,
+            inline_data,
+            head_sha256,
+        })
+    }
+
+    pub fn tombstone(tombstone: TombstoneMeta) -> Result<Self> {
+        let inline_data = serde_json::to_vec(&tombstone)?;
+        let head_sha256 = compute_hash(&inline_data);
+        Ok(Self::Tombstone {
+            tombstone,
+            inline_data,
+            head_sha256,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Meta { meta, .. } => &meta.path,
+            Self::Tombstone { tombstone, .. } => &tombstone.path,
+        }
+    }
+
+    pub fn generation(&self) -> i64 {
+        match self {
+            Self::Meta { meta, .. } => meta.generation,
+            Self::Tombstone { tombstone, .. } => tombstone.generation,
+        }
+    }
+
+    pub fn head_kind(&self) -> HeadKind {
+        match self {
+            Self::Meta { .. } => HeadKind::Meta,
+            Self::Tombstone { .. } => HeadKind::Tombstone,
+        }
+    }
+
+    pub fn head_sha256(&self) -> &str {
+        match self {
+            Self::Meta { head_sha256, .. } | Self::Tombstone { head_sha256, .. } => head_sha256,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PartEntry {
     pub blob_path: String,
@@ -80,8 +382,69 @@ pub struct PartEntry {
     pub archive_url: Option<String>,
 }
 
+/// Size SQLite truncates a slot WAL back to whenever a checkpoint resets
+/// it, so automatic checkpoints give disk space back too.
+const SLOT_WAL_SIZE_LIMIT_BYTES: i64 = 4 * 1024 * 1024;
+
+/// Writes inside [`MetadataStore::with_transaction`].
+pub struct MetadataTransaction<'a> {
+    store: &'a MetadataStore,
+    conn: &'a Connection,
+}
+
+impl MetadataTransaction<'_> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn upsert_part_entry(
+        &self,
+        blob_path: &str,
+        generation: i64,
+        part_no: u32,
+        sha256: &str,
+        size_bytes: u64,
+        external_path: Option<&str>,
+        archive_url: Option<&str>,
+    ) -> Result<()> {
+        self.store.write_part_entry_on(
+            self.conn,
+            blob_path,
+            generation,
+            part_no,
+            sha256,
+            size_bytes,
+            external_path,
+            archive_url,
+        )
+    }
+
+    /// The current head as this transaction sees it.
+    pub fn get_current_head(&self, blob_path: &str) -> Result<Option<BlobHead>> {
+        self.store.current_head_on(self.conn, blob_path)
+    }
+
+    pub fn upsert_meta_with_payload(
+        &self,
+        meta: &BlobMeta,
+        inline_data: &[u8],
+        head_sha256: &str,
+    ) -> Result<bool> {
+        self.store
+            .upsert_meta_on(self.conn, meta, inline_data, head_sha256)
+    }
+
+    pub fn insert_tombstone_with_payload(
+        &self,
+        tombstone: &TombstoneMeta,
+        inline_data: &[u8],
+        head_sha256: &str,
+    ) -> Result<bool> {
+        self.store
+            .insert_tombstone_on(self.conn, tombstone, inline_data, head_sha256)
+    }
+}
+
 pub struct MetadataStore {
     slot: Arc<Slot>,
+    clock: SharedClock,
 }
 
 struct HeadRow {
@@ -93,13 +456,60 @@ struct HeadRow {
     inline_data: Vec<u8>,
 }
 
+impl HeadRow {
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            blob_path: row.get(0)?,
+            file_kind: row.get(1)?,
+            generation: row.get(2)?,
+            sha256: row.get(3)?,
+            updated_at: row.get(4)?,
+            inline_data: row.get(5)?,
+        })
+    }
+}
+
+/// One record of a path's head chain.
+struct ChainLink {
+    blob_path: String,
+    seq: i64,
+    head_kind: String,
+    generation: i64,
+    head_sha256: String,
+    prev_hash: String,
+    chain_hash: String,
+}
+
+impl ChainLink {
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            blob_path: row.get(0)?,
+            seq: row.get(1)?,
+            head_kind: row.get(2)?,
+            generation: row.get(3)?,
+            head_sha256: row.get(4)?,
+            prev_hash: row.get(5)?,
+            chain_hash: row.get(6)?,
+        })
+    }
+}
+
 impl MetadataStore {
     pub fn new(slot: Arc<Slot>) -> Result<Self> {
-        let store = Self { slot };
+        let store = Self {
+            slot,
+            clock: system_clock(),
+        };
         store.init_schema()?;
         Ok(store)
     }
 
+    /// Stamps rows with `clock` instead of the system time.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn slot_id(&self) -> u16 {
         self.slot.slot_id
     }
@@ -108,6 +518,7 @@ impl MetadataStore {
         let db_path = self.slot.meta_db_path();
         let conn = Connection::open(&db_path)?;
         conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "journal_size_limit", SLOT_WAL_SIZE_LIMIT_BYTES)?;
         conn.busy_timeout(Duration::from_secs(5))?;
         Ok(conn)
     }
@@ -138,11 +549,17 @@ impl MetadataStore {
             [],
         )?;
 
-        if !Self::has_column(&conn, "file_entries", "archive_url")? {
+        let has_archive_url = Self::has_column(&conn, "file_entries", "archive_url")?;
+        let has_part_no = Self::has_column(&conn, "file_entries", "part_no")?;
+        if !has_archive_url || !has_part_no {
+            self.backup_on(&conn, "migration", SLOT_BACKUP_RETENTION)?;
+        }
+
+        if !has_archive_url {
             conn.execute("ALTER TABLE file_entries ADD COLUMN archive_url TEXT", [])?;
         }
 
-        if !Self::has_column(&conn, "file_entries", "part_no")? {
+        if !has_part_no {
             conn.execute("ALTER TABLE file_entries ADD COLUMN part_no INTEGER", [])?;
         }
 
@@ -170,6 +587,71 @@ impl MetadataStore {
             [],
         )?;
 
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_file_entries_updated_at
+             ON file_entries(slot_id, updated_at, blob_path)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS slot_maintenance (
+                task TEXT PRIMARY KEY,
+                finished_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS slot_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS blob_heads (
+                slot_id INTEGER NOT NULL,
+                blob_path TEXT NOT NULL,
+                head_kind TEXT NOT NULL CHECK(head_kind IN ('meta', 'tombstone')),
+                generation INTEGER NOT NULL,
+                sha256 TEXT NOT NULL,
+                inline_data BLOB NOT NULL,
+                updated_at TEXT NOT NULL,
+                PRIMARY KEY(slot_id, blob_path)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS head_chain (
+                slot_id INTEGER NOT NULL,
+                blob_path TEXT NOT NULL,
+                seq INTEGER NOT NULL,
+                head_kind TEXT NOT NULL,
+                generation INTEGER NOT NULL,
+                head_sha256 TEXT NOT NULL,
+                prev_hash TEXT NOT NULL,
+                chain_hash TEXT NOT NULL,
+                recorded_at TEXT NOT NULL,
+                PRIMARY KEY(slot_id, blob_path, seq)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS slot_changes (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                slot_id INTEGER NOT NULL,
+                blob_path TEXT NOT NULL,
+                change_kind TEXT NOT NULL CHECK(change_kind IN ('create', 'update', 'delete')),
+                generation INTEGER NOT NULL,
+                head_sha256 TEXT NOT NULL,
+                recorded_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
         Ok(())
     }
 
@@ -216,7 +698,56 @@ impl MetadataStore {
         archive_url: Option<&str>,
     ) -> Result<()> {
         let conn = self.get_conn()?;
-        let now = Utc::now().to_rfc3339();
+        self.track_busy(self.write_part_entry_on(
+            &conn,
+            blob_path,
+            generation,
+            part_no,
+            sha256,
+            size_bytes,
+            external_path,
+            archive_url,
+        ))
+    }
+
+    /// Runs `write` in one immediate SQLite transaction, committed when it
+    /// returns `Ok` and rolled back otherwise. Grouping the part entries and
+    /// head of a blob this way costs one fsync instead of one per row, and a
+    /// crash never leaves half of them behind.
+    pub fn with_transaction<T>(
+        &self,
+        write: impl FnOnce(&MetadataTransaction<'_>) -> Result<T>,
+    ) -> Result<T> {
+        self.track_busy(self.run_transaction(write))
+    }
+
+    fn run_transaction<T>(
+        &self,
+        write: impl FnOnce(&MetadataTransaction<'_>) -> Result<T>,
+    ) -> Result<T> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let value = write(&MetadataTransaction {
+            store: self,
+            conn: &tx,
+        })?;
+        tx.commit()?;
+        Ok(value)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_part_entry_on(
+        &self,
+        conn: &Connection,
+        blob_path: &str,
+        generation: i64,
+        part_no: u32,
+        sha256: &str,
+        size_bytes: u64,
+        external_path: Option<&str>,
+        archive_url: Option<&str>,
+    ) -> Result<()> {
+        let now = self.clock.now().to_rfc3339();
         let file_name = format!("g.{}/part.{:08}.{}", generation, part_no, sha256);
 
         conn.execute(
@@ -366,7 +897,19 @@ impl MetadataStore {
         head_sha256: &str,
     ) -> Result<bool> {
         let conn = self.get_conn()?;
-        let now = Utc::now().to_rfc3339();
+        self.track_busy(self.upsert_meta_on(&conn, meta, inline_data, head_sha256))
+    }
+
+    fn upsert_meta_on(
+        &self,
+        conn: &Connection,
+        meta: &BlobMeta,
+        inline_data: &[u8],
+        head_sha256: &str,
+    ) -> Result<bool> {
+        validate_meta(meta)?;
+        let before = self.current_head_row_on(conn, &meta.path)?;
+        let now = self.clock.now().to_rfc3339();
 
         let affected = conn.execute(
             "INSERT INTO file_entries (
@@ -406,6 +949,24 @@ impl MetadataStore {
             ],
         )?;
 
+        if affected > 0 && self.head_schema_phase_on(conn)? >= HeadSchemaPhase::DualWrite {
+            self.shadow_head_on(
+                conn,
+                &HeadRow {
+                    blob_path: meta.path.clone(),
+                    file_kind: "meta".to_string(),
+                    generation: meta.generation,
+                    sha256: head_sha256.to_string(),
+                    updated_at: now,
+                    inline_data: inline_data.to_vec(),
+                },
+            )?;
+        }
+        if affected > 0 {
+            self.chain_current_head_on(conn, &meta.path)?;
+            self.record_change_on(conn, &meta.path, before.as_ref())?;
+        }
+
         Ok(affected > 0)
     }
 
@@ -423,7 +984,19 @@ impl MetadataStore {
         head_sha256: &str,
     ) -> Result<bool> {
         let conn = self.get_conn()?;
-        let now = Utc::now().to_rfc3339();
+        self.track_busy(self.insert_tombstone_on(&conn, tombstone, inline_data, head_sha256))
+    }
+
+    fn insert_tombstone_on(
+        &self,
+        conn: &Connection,
+        tombstone: &TombstoneMeta,
+        inline_data: &[u8],
+        head_sha256: &str,
+    ) -> Result<bool> {
+        validate_tombstone(tombstone)?;
+        let before = self.current_head_row_on(conn, &tombstone.path)?;
+        let now = self.clock.now().to_rfc3339();
         let file_name = format!("tombstone.{}", head_sha256);
 
         let affected = conn.execute(
@@ -462,50 +1035,878 @@ impl MetadataStore {
             ],
         )?;
 
+        if affected > 0 && self.head_schema_phase_on(conn)? >= HeadSchemaPhase::DualWrite {
+            self.shadow_head_on(
+                conn,
+                &HeadRow {
+                    blob_path: tombstone.path.clone(),
+                    file_kind: "tombstone".to_string(),
+                    generation: tombstone.generation,
+                    sha256: head_sha256.to_string(),
+                    updated_at: now,
+                    inline_data: inline_data.to_vec(),
+                },
+            )?;
+        }
+        if affected > 0 {
+            self.chain_current_head_on(conn, &tombstone.path)?;
+            self.record_change_on(conn, &tombstone.path, before.as_ref())?;
+        }
+
         Ok(affected > 0)
     }
 
-    pub fn get_current_head(&self, blob_path: &str) -> Result<Option<BlobHead>> {
-        let conn = self.get_conn()?;
+    /// Applies a group of head writes in a single SQLite transaction.
+    ///
+    /// Returns `false` and leaves the slot untouched when any write is rejected.
+    /// With `require_newer`, every write must carry a generation strictly above
+    /// the current head of its path, which is what a coordinator wants; replicas
+    /// re-applying a batch pass `false` so retries stay idempotent.
+    pub fn apply_head_batch(&self, writes: &[HeadWrite], require_newer: bool) -> Result<bool> {
+        self.track_busy(self.write_head_batch(writes, require_newer))
+    }
 
-        let row: Option<HeadRow> = conn
-            .query_row(
-                "SELECT blob_path, file_kind, generation, sha256, updated_at, inline_data
-                 FROM file_entries
-                 WHERE slot_id = ?1
-                   AND blob_path = ?2
-                   AND file_kind IN ('meta', 'tombstone')
-                 ORDER BY generation DESC,
-                          CASE file_kind WHEN 'tombstone' THEN 1 ELSE 0 END DESC,
-                          pk DESC
-                 LIMIT 1",
-                params![self.slot.slot_id as i64, blob_path],
-                |row| {
-                    Ok(HeadRow {
-                        blob_path: row.get(0)?,
-                        file_kind: row.get(1)?,
-                        generation: row.get(2)?,
-                        sha256: row.get(3)?,
-                        updated_at: row.get(4)?,
-                        inline_data: row.get(5)?,
-                    })
-                },
-            )
-            .optional()?;
+    fn write_head_batch(&self, writes: &[HeadWrite], require_newer: bool) -> Result<bool> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+
+        for write in writes {
+            if require_newer {
+                let current: Option<i64> = tx
+                    .query_row(
+                        "SELECT MAX(generation)
+                         FROM file_entries
+                         WHERE slot_id = ?1
+                           AND blob_path = ?2
+                           AND file_kind IN ('meta', 'tombstone')",
+                        params![self.slot.slot_id as i64, write.path()],
+                        |row| row.get(0),
+                    )
+                    .optional()?
+                    .flatten();
+
+                if current.is_some_and(|current| current >= write.generation()) {
+                    return Ok(false);
+                }
+            }
 
-        match row {
-            Some(row) => self.decode_head_row(row),
-            None => Ok(None),
+            let applied = match write {
+                HeadWrite::Meta {
+                    meta,
+                    inline_data,
+                    head_sha256,
+                } => self.upsert_meta_on(&tx, meta, inline_data, head_sha256)?,
+                HeadWrite::Tombstone {
+                    tombstone,
+                    inline_data,
+                    head_sha256,
+                } => self.insert_tombstone_on(&tx, tombstone, inline_data, head_sha256)?,
+            };
+
+            if !applied {
+                return Ok(false);
+            }
         }
+
+        tx.commit()?;
+        Ok(true)
     }
 
-    pub fn list_heads(
-        &self,
-        prefix: &str,
-        limit: usize,
-        include_deleted: bool,
-        cursor: Option<&str>,
-    ) -> Result<Vec<BlobHead>> {
+    /// Counts live paths and stored part bytes, and finds the latest head write.
+    pub fn slot_stats(&self) -> Result<SlotStats> {
+        let conn = self.get_conn()?;
+        let slot_id = self.slot.slot_id as i64;
+
+        let (blob_count, last_write_at): (i64, Option<String>) = conn.query_row(
+            "SELECT COUNT(DISTINCT blob_path), MAX(updated_at)
+             FROM file_entries
+             WHERE slot_id = ?1 AND file_kind IN ('meta', 'tombstone')",
+            params![slot_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let data_size_bytes: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(size_bytes), 0)
+             FROM file_entries
+             WHERE slot_id = ?1 AND file_kind = 'part'",
+            params![slot_id],
+            |row| row.get(0),
+        )?;
+
+        Ok(SlotStats {
+            slot_id: self.slot.slot_id,
+            blob_count: blob_count.max(0) as u64,
+            data_size_bytes: data_size_bytes.max(0) as u64,
+            last_write_at: last_write_at.as_deref().map(parse_rfc3339).transpose()?,
+            sqlite: self.sqlite_stats_on(&conn)?,
+        })
+    }
+
+    pub fn sqlite_stats(&self) -> Result<SqliteStats> {
+        let conn = self.get_conn()?;
+        self.sqlite_stats_on(&conn)
+    }
+
+    fn sqlite_stats_on(&self, conn: &Connection) -> Result<SqliteStats> {
+        let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        let page_count: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let free_pages: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+
+        Ok(SqliteStats {
+            file_size_bytes: file_len(&self.slot.meta_db_path()),
+            wal_size_bytes: file_len(&self.slot.meta_wal_path()),
+            page_size: page_size.max(0) as u64,
+            page_count: page_count.max(0) as u64,
+            free_pages: free_pages.max(0) as u64,
+            busy_errors: self.slot.busy_errors.load(Ordering::Relaxed),
+            last_vacuum_at: Self::maintenance_finished_at(conn, "vacuum")?,
+            last_checkpoint_at: Self::maintenance_finished_at(conn, "checkpoint")?,
+            last_integrity_check_at: Self::maintenance_finished_at(conn, "integrity_check")?,
+        })
+    }
+
+    /// Folds the WAL back into the database file and truncates it.
+    pub fn checkpoint(&self) -> Result<()> {
+        let conn = self.get_conn()?;
+        let busy: i64 = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+        if busy != 0 {
+            self.slot.busy_errors.fetch_add(1, Ordering::Relaxed);
+            return Err(RimError::Internal(format!(
+                "wal checkpoint blocked by readers: slot={}",
+                self.slot.slot_id
+            )));
+        }
+
+        Self::record_maintenance(&conn, "checkpoint", self.clock.now())
+    }
+
+    /// Rewrites the database file to give free pages back to the filesystem.
+    pub fn vacuum(&self) -> Result<()> {
+        let conn = self.get_conn()?;
+        self.track_busy(conn.execute_batch("VACUUM").map_err(RimError::from))?;
+        Self::record_maintenance(&conn, "vacuum", self.clock.now())
+    }
+
+    /// Runs `PRAGMA integrity_check` and returns the problems it reports,
+    /// none when the database is sound. A database too damaged to be read
+    /// reports the error it fails with.
+    pub fn integrity_check(&self) -> Result<Vec<String>> {
+        match self.integrity_problems() {
+            Err(error) if is_corruption_error(&error) => Ok(vec![error.to_string()]),
+            result => result,
+        }
+    }
+
+    fn integrity_problems(&self) -> Result<Vec<String>> {
+        let conn = self.get_conn()?;
+        let problems = {
+            let mut stmt = conn.prepare(&format!(
+                "PRAGMA integrity_check({})",
+                INTEGRITY_CHECK_MAX_PROBLEMS
+            ))?;
+            stmt.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        if problems.len() == 1 && problems[0] == "ok" {
+            Self::record_maintenance(&conn, "integrity_check", self.clock.now())?;
+            return Ok(Vec::new());
+        }
+        Ok(problems)
+    }
+
+    /// Moves the database of `slot` and its WAL into the slot's
+    /// `quarantine/` directory, so the next open starts from an empty
+    /// database. Takes the slot rather than a store since a corrupt database
+    /// may not open at all. The copy is named after `now`.
+    pub fn quarantine(slot: &Slot, now: DateTime<Utc>) -> Result<PathBuf> {
+        let quarantine_dir = slot.quarantine_dir();
+        std::fs::create_dir_all(&quarantine_dir)?;
+        let target =
+            quarantine_dir.join(format!("meta-{}.sqlite3", now.format("%Y%m%dT%H%M%S%.6fZ")));
+
+        let db_path = slot.meta_db_path();
+        for (from, to) in [
+            (db_path.clone(), target.clone()),
+            (slot.meta_wal_path(), target.with_extension("sqlite3-wal")),
+            (
+                db_path.with_extension("sqlite3-shm"),
+                target.with_extension("sqlite3-shm"),
+            ),
+        ] {
+            match std::fs::rename(&from, &to) {
+                Ok(()) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        tracing::warn!(
+            "slot database quarantined: slot={} path={}",
+            slot.slot_id,
+            target.display()
+        );
+        Ok(target)
+    }
+
+    /// Snapshots the slot database into the slot's `backups/` directory with
+    /// `VACUUM INTO`, then prunes all but the newest `keep` snapshots.
+    ///
+    /// Taken before operations that rewrite the file (migrations, vacuum,
+    /// transfers) so a failure halfway can be rolled back with
+    /// [`MetadataStore::restore_backup`].
+    pub fn backup(&self, reason: &str, keep: usize) -> Result<PathBuf> {
+        let conn = self.get_conn()?;
+        self.backup_on(&conn, reason, keep)
+    }
+
+    fn backup_on(&self, conn: &Connection, reason: &str, keep: usize) -> Result<PathBuf> {
+        let backups_dir = self.slot.backups_dir();
+        std::fs::create_dir_all(&backups_dir)?;
+
+        let reason: String = reason
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let target = backups_dir.join(format!(
+            "meta-{}-{}.sqlite3",
+            self.clock.now().format("%Y%m%dT%H%M%S%.6fZ"),
+            reason
+        ));
+
+        self.track_busy(
+            conn.execute(
+                "VACUUM INTO ?1",
+                params![target.to_string_lossy().into_owned()],
+            )
+            .map_err(RimError::from),
+        )?;
+
+        let backups = self.list_backups()?;
+        for stale in backups
+            .iter()
+            .take(backups.len().saturating_sub(keep.max(1)))
+        {
+            std::fs::remove_file(stale)?;
+        }
+
+        tracing::info!(
+            "slot database backed up: slot={} path={}",
+            self.slot.slot_id,
+            target.display()
+        );
+        Ok(target)
+    }
+
+    /// Returns the slot's backups, oldest first.
+    pub fn list_backups(&self) -> Result<Vec<PathBuf>> {
+        let backups_dir = self.slot.backups_dir();
+        if !backups_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut backups = Vec::new();
+        for entry in std::fs::read_dir(&backups_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "sqlite3") {
+                backups.push(path);
+            }
+        }
+
+        backups.sort();
+        Ok(backups)
+    }
+
+    /// Replaces the slot database with a backup. Writes made after the
+    /// backup are lost; callers must keep the slot out of service meanwhile.
+    pub fn restore_backup(&self, backup: &Path) -> Result<()> {
+        if !backup.starts_with(self.slot.backups_dir()) || !backup.is_file() {
+            return Err(RimError::InvalidRequest(format!(
+                "not a backup of slot {}: {}",
+                self.slot.slot_id,
+                backup.display()
+            )));
+        }
+
+        let staging = self.slot.meta_db_path().with_extension("sqlite3.restore");
+        std::fs::copy(backup, &staging)?;
+        self.replace_database(&staging)?;
+
+        tracing::warn!(
+            "slot database restored: slot={} from={}",
+            self.slot.slot_id,
+            backup.display()
+        );
+        Ok(())
+    }
+
+    /// Writes a consistent copy of the slot database to `target` with
+    /// `VACUUM INTO`, for shipping the slot to another node.
+    pub fn export_to(&self, target: &Path) -> Result<()> {
+        let conn = self.get_conn()?;
+        self.track_busy(
+            conn.execute(
+                "VACUUM INTO ?1",
+                params![target.to_string_lossy().into_owned()],
+            )
+            .map_err(RimError::from),
+        )?;
+        Ok(())
+    }
+
+    /// Replaces the slot database with one copied from another node, moving
+    /// `staged` into place. Same caveats as [`MetadataStore::restore_backup`].
+    pub fn install_copy(&self, staged: &Path) -> Result<()> {
+        self.replace_database(staged)?;
+        tracing::info!(
+            "slot database installed: slot={} from={}",
+            self.slot.slot_id,
+            staged.display()
+        );
+        Ok(())
+    }
+
+    fn replace_database(&self, staging: &Path) -> Result<()> {
+        let db_path = self.slot.meta_db_path();
+        for sidecar in [
+            self.slot.meta_wal_path(),
+            db_path.with_extension("sqlite3-shm"),
+        ] {
+            match std::fs::remove_file(&sidecar) {
+                Ok(()) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+        std::fs::rename(staging, &db_path)?;
+        Ok(())
+    }
+
+    /// Points part entries stored under `from` at the same relative path
+    /// under `to`. Part paths are absolute, so a database copied from another
+    /// node still names that node's slot directory.
+    pub fn rebase_part_paths(&self, from: &str, to: &str) -> Result<u64> {
+        let conn = self.get_conn()?;
+        let changed = self.track_busy(
+            conn.execute(
+                "UPDATE file_entries
+                 SET external_path = ?2 || substr(external_path, length(?1) + 1)
+                 WHERE slot_id = ?3
+                   AND external_path IS NOT NULL
+                   AND substr(external_path, 1, length(?1)) = ?1",
+                params![from, to, self.slot.slot_id as i64],
+            )
+            .map_err(RimError::from),
+        )?;
+        Ok(changed as u64)
+    }
+
+    fn maintenance_finished_at(conn: &Connection, task: &str) -> Result<Option<DateTime<Utc>>> {
+        let finished_at: Option<String> = conn
+            .query_row(
+                "SELECT finished_at FROM slot_maintenance WHERE task = ?1",
+                params![task],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        finished_at.as_deref().map(parse_rfc3339).transpose()
+    }
+
+    fn record_maintenance(conn: &Connection, task: &str, finished_at: DateTime<Utc>) -> Result<()> {
+        conn.execute(
+            "INSERT INTO slot_maintenance (task, finished_at) VALUES (?1, ?2)
+             ON CONFLICT(task) DO UPDATE SET finished_at = excluded.finished_at",
+            params![task, finished_at.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    fn track_busy<T>(&self, result: Result<T>) -> Result<T> {
+        if let Err(RimError::Database(rusqlite::Error::SqliteFailure(error, _))) = &result
+            && matches!(
+                error.code,
+                ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked
+            )
+        {
+            self.slot.busy_errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        result
+    }
+
+    pub fn get_current_head(&self, blob_path: &str) -> Result<Option<BlobHead>> {
+        let conn = self.get_conn()?;
+        self.current_head_on(&conn, blob_path)
+    }
+
+    fn current_head_on(&self, conn: &Connection, blob_path: &str) -> Result<Option<BlobHead>> {
+        match self.current_head_row_on(conn, blob_path)? {
+            Some(row) => self.decode_head_row(row),
+            None => Ok(None),
+        }
+    }
+
+    fn current_head_row_on(&self, conn: &Connection, blob_path: &str) -> Result<Option<HeadRow>> {
+        if self.head_schema_phase_on(conn)? == HeadSchemaPhase::Cutover {
+            self.shadow_head_row_on(conn, blob_path)
+        } else {
+            self.legacy_head_row_on(conn, blob_path)
+        }
+    }
+
+    /// Appends the current head of `blob_path` to its chain unless it is
+    /// already the last record, so a write that lost to a newer head, or a
+    /// retried one, leaves the chain as it was.
+    fn chain_current_head_on(&self, conn: &Connection, blob_path: &str) -> Result<()> {
+        if !self.slot.chain_heads {
+            return Ok(());
+        }
+        let Some(head) = self.current_head_row_on(conn, blob_path)? else {
+            return Ok(());
+        };
+
+        let last = Self::last_chain_link_on(conn, self.slot.slot_id, blob_path)?;
+        if let Some(last) = &last
+            && last.head_kind == head.file_kind
+            && last.generation == head.generation
+            && last.head_sha256 == head.sha256
+        {
+            return Ok(());
+        }
+
+        let seq = last.as_ref().map_or(1, |last| last.seq + 1);
+        let prev_hash = last.map(|last| last.chain_hash).unwrap_or_default();
+        let chain_hash = chain_link_hash(
+            &prev_hash,
+            blob_path,
+            &head.file_kind,
+            head.generation,
+            &head.sha256,
+        );
+        conn.execute(
+            "INSERT INTO head_chain (
+                slot_id, blob_path, seq, head_kind, generation, head_sha256,
+                prev_hash, chain_hash, recorded_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                self.slot.slot_id as i64,
+                blob_path,
+                seq,
+                head.file_kind,
+                head.generation,
+                head.sha256,
+                prev_hash,
+                chain_hash,
+                self.clock.now().to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Appends the current head of `blob_path` to the slot's change feed
+    /// unless it is still `before`, so a write that lost to a newer head, or
+    /// a retried one, records nothing.
+    fn record_change_on(
+        &self,
+        conn: &Connection,
+        blob_path: &str,
+        before: Option<&HeadRow>,
+    ) -> Result<()> {
+        let Some(head) = self.current_head_row_on(conn, blob_path)? else {
+            return Ok(());
+        };
+        if let Some(before) = before
+            && before.file_kind == head.file_kind
+            && before.generation == head.generation
+            && before.sha256 == head.sha256
+        {
+            return Ok(());
+        }
+
+        let kind = match (
+            before.map(|row| row.file_kind.as_str()),
+            head.file_kind.as_str(),
+        ) {
+            (_, "tombstone") => ChangeKind::Delete,
+            (Some("meta"), _) => ChangeKind::Update,
+            _ => ChangeKind::Create,
+        };
+        conn.execute(
+            "INSERT INTO slot_changes (
+                slot_id, blob_path, change_kind, generation, head_sha256, recorded_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.slot.slot_id as i64,
+                blob_path,
+                kind.as_str(),
+                head.generation,
+                head.sha256,
+                self.clock.now().to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Reads up to `limit` entries of the change feed after `after_seq`, in
+    /// the order the slot applied them.
+    pub fn list_changes(&self, after_seq: i64, limit: usize) -> Result<Vec<SlotChange>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT seq, blob_path, change_kind, generation, head_sha256, recorded_at
+             FROM slot_changes
+             WHERE slot_id = ?1 AND seq > ?2
+             ORDER BY seq ASC
+             LIMIT ?3",
+        )?;
+        let mut rows = stmt.query(params![
+            self.slot.slot_id as i64,
+            after_seq,
+            limit.min(i64::MAX as usize) as i64,
+        ])?;
+
+        let mut changes = Vec::new();
+        while let Some(row) = rows.next()? {
+            changes.push(SlotChange {
+                seq: row.get(0)?,
+                path: row.get(1)?,
+                kind: ChangeKind::parse(&row.get::<_, String>(2)?)?,
+                generation: row.get(3)?,
+                head_sha256: row.get(4)?,
+                recorded_at: parse_rfc3339(&row.get::<_, String>(5)?)?,
+            });
+        }
+        Ok(changes)
+    }
+
+    /// `seq` of the newest change-feed entry, 0 when the feed is empty.
+    pub fn latest_change_seq(&self) -> Result<i64> {
+        let conn = self.get_conn()?;
+        Ok(conn.query_row(
+            "SELECT COALESCE(MAX(seq), 0) FROM slot_changes WHERE slot_id = ?1",
+            params![self.slot.slot_id as i64],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn last_chain_link_on(
+        conn: &Connection,
+        slot_id: u16,
+        blob_path: &str,
+    ) -> Result<Option<ChainLink>> {
+        Ok(conn
+            .query_row(
+                "SELECT blob_path, seq, head_kind, generation, head_sha256, prev_hash, chain_hash
+                 FROM head_chain
+                 WHERE slot_id = ?1 AND blob_path = ?2
+                 ORDER BY seq DESC
+                 LIMIT 1",
+                params![slot_id as i64, blob_path],
+                ChainLink::from_row,
+            )
+            .optional()?)
+    }
+
+    /// Walks the head chain of every path in the slot and checks each one
+    /// ends at the path's current head.
+    pub fn verify_head_chain(&self) -> Result<HeadChainReport> {
+        let conn = self.get_conn()?;
+        let links = {
+            let mut stmt = conn.prepare(
+                "SELECT blob_path, seq, head_kind, generation, head_sha256, prev_hash, chain_hash
+                 FROM head_chain
+                 WHERE slot_id = ?1
+                 ORDER BY blob_path ASC, seq ASC",
+            )?;
+            stmt.query_map(params![self.slot.slot_id as i64], ChainLink::from_row)?
+                .collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let mut report = HeadChainReport::default();
+        let mut tips = String::new();
+        for chain in links.chunk_by(|a, b| a.blob_path == b.blob_path) {
+            let blob_path = &chain[0].blob_path;
+            report.paths += 1;
+            report.records += chain.len() as u64;
+
+            let mut problem = None;
+            let mut prev_hash = "";
+            for link in chain {
+                if link.prev_hash != prev_hash {
+                    problem = Some(format!("link broken at seq {}", link.seq));
+                    break;
+                }
+                let expected = chain_link_hash(
+                    &link.prev_hash,
+                    &link.blob_path,
+                    &link.head_kind,
+                    link.generation,
+                    &link.head_sha256,
+                );
+                if link.chain_hash != expected {
+                    problem = Some(format!("record altered at seq {}", link.seq));
+                    break;
+                }
+                prev_hash = &link.chain_hash;
+            }
+
+            let last = &chain[chain.len() - 1];
+            if problem.is_none() {
+                problem = match self.current_head_row_on(&conn, blob_path)? {
+                    None => Some("chained head is missing".to_string()),
+                    Some(head) if compute_hash(&head.inline_data) != head.sha256 => {
+                        Some("head payload does not match its hash".to_string())
+                    }
+                    Some(head)
+                        if head.file_kind != last.head_kind
+                            || head.generation != last.generation
+                            || head.sha256 != last.head_sha256 =>
+                    {
+                        Some(format!(
+                            "head generation {} is not the chain's last record",
+                            head.generation
+                        ))
+                    }
+                    Some(_) => None,
+                };
+            }
+
+            if let Some(problem) = problem {
+                report.broken += 1;
+                if report.samples.len() < HEAD_CHAIN_SAMPLES {
+                    report.samples.push(format!("{}: {}", blob_path, problem));
+                }
+            }
+            tips.push_str(blob_path);
+            tips.push('\n');
+            tips.push_str(&last.chain_hash);
+            tips.push('\n');
+        }
+
+        report.unchained = conn.query_row(
+            "SELECT COUNT(DISTINCT blob_path)
+             FROM file_entries
+             WHERE slot_id = ?1
+               AND file_kind IN ('meta', 'tombstone')
+               AND blob_path NOT IN (SELECT blob_path FROM head_chain WHERE slot_id = ?1)",
+            params![self.slot.slot_id as i64],
+            |row| row.get::<_, i64>(0),
+        )? as u64;
+        report.digest = compute_hash(tips.as_bytes());
+        report.verified_at = Some(self.clock.now());
+        Ok(report)
+    }
+
+    fn legacy_head_row_on(&self, conn: &Connection, blob_path: &str) -> Result<Option<HeadRow>> {
+        Ok(conn
+            .query_row(
+                "SELECT blob_path, file_kind, generation, sha256, updated_at, inline_data
+                 FROM file_entries
+                 WHERE slot_id = ?1
+                   AND blob_path = ?2
+                   AND file_kind IN ('meta', 'tombstone')
+                 ORDER BY generation DESC,
+                          CASE file_kind WHEN 'tombstone' THEN 1 ELSE 0 END DESC,
+                          pk DESC
+                 LIMIT 1",
+                params![self.slot.slot_id as i64, blob_path],
+                HeadRow::from_row,
+            )
+            .optional()?)
+    }
+
+    fn shadow_head_row_on(&self, conn: &Connection, blob_path: &str) -> Result<Option<HeadRow>> {
+        Ok(conn
+            .query_row(
+                "SELECT blob_path, head_kind, generation, sha256, updated_at, inline_data
+                 FROM blob_heads
+                 WHERE slot_id = ?1 AND blob_path = ?2",
+                params![self.slot.slot_id as i64, blob_path],
+                HeadRow::from_row,
+            )
+            .optional()?)
+    }
+
+    /// Writes a head into `blob_heads` unless the stored one wins, using the
+    /// same precedence as `file_entries`: higher generation, then tombstone
+    /// over meta, then the later write.
+    fn shadow_head_on(&self, conn: &Connection, row: &HeadRow) -> Result<()> {
+        conn.execute(
+            "INSERT INTO blob_heads (
+                slot_id, blob_path, head_kind, generation, sha256, inline_data, updated_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT(slot_id, blob_path) DO UPDATE SET
+                head_kind = excluded.head_kind,
+                generation = excluded.generation,
+                sha256 = excluded.sha256,
+                inline_data = excluded.inline_data,
+                updated_at = excluded.updated_at
+            WHERE excluded.generation > blob_heads.generation
+               OR (excluded.generation = blob_heads.generation
+                   AND NOT (blob_heads.head_kind = 'tombstone' AND excluded.head_kind = 'meta'))",
+            params![
+                self.slot.slot_id as i64,
+                row.blob_path,
+                row.file_kind,
+                row.generation,
+                row.sha256,
+                row.inline_data,
+                row.updated_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn head_schema_phase(&self) -> Result<HeadSchemaPhase> {
+        let conn = self.get_conn()?;
+        self.head_schema_phase_on(&conn)
+    }
+
+    fn head_schema_phase_on(&self, conn: &Connection) -> Result<HeadSchemaPhase> {
+        match Self::slot_setting(conn, HEAD_PHASE_SETTING)? {
+            Some(value) => HeadSchemaPhase::parse(&value),
+            None => Ok(HeadSchemaPhase::Legacy),
+        }
+    }
+
+    /// Moves the slot to another head layout phase. Going back to `Legacy`
+    /// stops the shadow writes, so the next dual-write phase backfills again.
+    pub fn set_head_schema_phase(&self, phase: HeadSchemaPhase) -> Result<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        Self::set_slot_setting(&tx, HEAD_PHASE_SETTING, phase.as_str())?;
+        if phase == HeadSchemaPhase::Legacy {
+            tx.execute(
+                "DELETE FROM slot_settings WHERE key = ?1",
+                params![HEAD_BACKFILLED_SETTING],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn head_shadow_backfilled(&self) -> Result<bool> {
+        let conn = self.get_conn()?;
+        Ok(Self::slot_setting(&conn, HEAD_BACKFILLED_SETTING)?.is_some())
+    }
+
+    /// Copies the current heads of up to `limit` paths after `after` into
+    /// `blob_heads`. Returns the last path copied, or `None` once every path
+    /// has been; the backfill is then recorded as done.
+    ///
+    /// Heads written concurrently are not overwritten, since shadow writes
+    /// only ever move a path forward.
+    pub fn backfill_head_shadow(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Option<String>> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+
+        let paths = Self::head_paths_on(&tx, self.slot.slot_id, after, limit)?;
+        for path in &paths {
+            if let Some(row) = self.legacy_head_row_on(&tx, path)? {
+                self.shadow_head_on(&tx, &row)?;
+            }
+        }
+
+        let last = if paths.len() < limit {
+            Self::set_slot_setting(&tx, HEAD_BACKFILLED_SETTING, &self.clock.now().to_rfc3339())?;
+            None
+        } else {
+            paths.last().cloned()
+        };
+
+        tx.commit()?;
+        Ok(last)
+    }
+
+    /// Compares the current head of every path across both layouts.
+    pub fn verify_head_shadow(&self) -> Result<HeadShadowReport> {
+        let conn = self.get_conn()?;
+        let mut report = HeadShadowReport::default();
+
+        for path in Self::head_paths_on(&conn, self.slot.slot_id, None, usize::MAX)? {
+            report.checked += 1;
+            let legacy = self.legacy_head_row_on(&conn, &path)?;
+            let shadow = self.shadow_head_row_on(&conn, &path)?;
+
+            let differs = match (&legacy, &shadow) {
+                (Some(_), None) => {
+                    report.missing += 1;
+                    true
+                }
+                (Some(legacy), Some(shadow))
+                    if legacy.file_kind != shadow.file_kind
+                        || legacy.generation != shadow.generation
+                        || legacy.sha256 != shadow.sha256 =>
+                {
+                    report.mismatched += 1;
+                    true
+                }
+                _ => false,
+            };
+
+            if differs && report.samples.len() < HEAD_SHADOW_SAMPLES {
+                report.samples.push(path);
+            }
+        }
+
+        report.verified_at = Some(self.clock.now());
+        Ok(report)
+    }
+
+    fn head_paths_on(
+        conn: &Connection,
+        slot_id: u16,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT blob_path
+             FROM file_entries
+             WHERE slot_id = ?1
+               AND file_kind IN ('meta', 'tombstone')
+               AND blob_path > ?2
+             ORDER BY blob_path ASC
+             LIMIT ?3",
+        )?;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let paths = stmt
+            .query_map(
+                params![slot_id as i64, after.unwrap_or_default(), limit],
+                |row| row.get(0),
+            )?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(paths)
+    }
+
+    fn slot_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
+        Ok(conn
+            .query_row(
+                "SELECT value FROM slot_settings WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn set_slot_setting(conn: &Connection, key: &str, value: &str) -> Result<()> {
+        conn.execute(
+            "INSERT INTO slot_settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_heads(
+        &self,
+        prefix: &str,
+        limit: usize,
+        include_deleted: bool,
+        cursor: Option<&str>,
+    ) -> Result<Vec<BlobHead>> {
         let conn = self.get_conn()?;
         let pattern = format!("{}%", prefix);
 
@@ -576,6 +1977,92 @@ impl MetadataStore {
         Ok(selected)
     }
 
+    /// Lists current heads under `prefix` ordered by when they were written,
+    /// oldest first, with `since` inclusive and `until` exclusive. `after` is
+    /// the `(updated_at, path)` of the last head already seen. Rows of older
+    /// generations are skipped, so a path rewritten after the cursor shows up
+    /// again later instead of being listed twice in one walk.
+    pub fn list_heads_by_updated_at(
+        &self,
+        prefix: &str,
+        limit: usize,
+        include_deleted: bool,
+        after: Option<(DateTime<Utc>, &str)>,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<BlobHead>> {
+        let conn = self.get_conn()?;
+        let pattern = format!("{}%", prefix);
+        let (after_updated_at, after_path) = match after {
+            Some((updated_at, path)) => (Some(updated_at.to_rfc3339()), Some(path)),
+            None => (None, None),
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT blob_path, file_kind, generation, sha256, updated_at, inline_data
+             FROM file_entries
+             WHERE slot_id = ?1
+               AND blob_path LIKE ?2
+               AND file_kind IN ('meta', 'tombstone')
+               AND (?3 IS NULL OR updated_at > ?3 OR (updated_at = ?3 AND blob_path > ?4))
+               AND (?5 IS NULL OR updated_at >= ?5)
+               AND (?6 IS NULL OR updated_at < ?6)
+             ORDER BY updated_at ASC, blob_path ASC",
+        )?;
+        let mut rows = stmt.query(params![
+            self.slot.slot_id as i64,
+            pattern,
+            after_updated_at,
+            after_path,
+            since.map(|at| at.to_rfc3339()),
+            until.map(|at| at.to_rfc3339()),
+        ])?;
+
+        let mut selected = Vec::new();
+        while let Some(row) = rows.next()? {
+            let row = HeadRow::from_row(row)?;
+            let is_current = self
+                .legacy_head_row_on(&conn, &row.blob_path)?
+                .is_some_and(|head| {
+                    head.generation == row.generation
+                        && head.file_kind == row.file_kind
+                        && head.updated_at == row.updated_at
+                });
+            if !is_current {
+                continue;
+            }
+
+            if let Some(head) = self.decode_head_row(row)? {
+                if !include_deleted && head.head_kind == HeadKind::Tombstone {
+                    continue;
+                }
+
+                selected.push(head);
+                if selected.len() >= limit {
+                    break;
+                }
+            }
+        }
+
+        Ok(selected)
+    }
+
+    /// Every part file the slot's part entries point at.
+    pub fn part_external_paths(&self) -> Result<HashSet<String>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT external_path
+             FROM file_entries
+             WHERE slot_id = ?1
+               AND file_kind = 'part'
+               AND external_path IS NOT NULL",
+        )?;
+        let paths = stmt
+            .query_map(params![self.slot.slot_id as i64], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(paths)
+    }
+
     pub fn find_part_external_path(
         &self,
         sha256: &str,
@@ -619,6 +2106,112 @@ impl MetadataStore {
         Ok(path)
     }
 
+    /// Hard-deletes the versions `retention` no longer keeps, in one
+    /// transaction. With `dry_run` the slot is left untouched and only the
+    /// counts are reported.
+    pub fn prune_versions(
+        &self,
+        retention: &VersionRetention,
+        now: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<PrunedVersions> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+
+        let rows = {
+            let mut stmt = tx.prepare(
+                "SELECT blob_path, file_kind, generation, updated_at, external_path, size_bytes
+                 FROM file_entries
+                 WHERE slot_id = ?1
+                 ORDER BY blob_path ASC",
+            )?;
+            let mut rows = stmt.query(params![self.slot.slot_id as i64])?;
+            let mut parsed = Vec::new();
+            while let Some(row) = rows.next()? {
+                parsed.push(VersionRow {
+                    blob_path: row.get(0)?,
+                    file_kind: row.get(1)?,
+                    generation: row.get(2)?,
+                    updated_at: parse_rfc3339(&row.get::<_, String>(3)?)?,
+                    external_path: row.get(4)?,
+                    size_bytes: row.get::<_, i64>(5)? as u64,
+                });
+            }
+            parsed
+        };
+
+        let mut pruned = PrunedVersions::default();
+        for path_rows in rows.chunk_by(|a, b| a.blob_path == b.blob_path) {
+            let blob_path = &path_rows[0].blob_path;
+            let (generations, whole_path) = expired_generations(path_rows, retention, now);
+            if generations.is_empty() {
+                continue;
+            }
+
+            if whole_path {
+                pruned.paths += 1;
+            } else {
+                pruned.versions += generations.len() as u64;
+            }
+            for row in path_rows
+                .iter()
+                .filter(|row| row.file_kind == "part" && generations.contains(&row.generation))
+            {
+                if let Some(external_path) = &row.external_path {
+                    pruned.parts.push(PrunedPart {
+                        blob_path: blob_path.clone(),
+                        path: external_path.clone(),
+                    });
+                    pruned.part_bytes += row.size_bytes;
+                }
+            }
+            if dry_run {
+                continue;
+            }
+
+            for generation in &generations {
+                tx.execute(
+                    "DELETE FROM file_entries
+                     WHERE slot_id = ?1 AND blob_path = ?2 AND generation = ?3",
+                    params![self.slot.slot_id as i64, blob_path, generation],
+                )?;
+            }
+            if whole_path {
+                tx.execute(
+                    "DELETE FROM blob_heads WHERE slot_id = ?1 AND blob_path = ?2",
+                    params![self.slot.slot_id as i64, blob_path],
+                )?;
+                tx.execute(
+                    "DELETE FROM head_chain WHERE slot_id = ?1 AND blob_path = ?2",
+                    params![self.slot.slot_id as i64, blob_path],
+                )?;
+            }
+        }
+
+        if !dry_run {
+            let mut still_used = tx.prepare(
+                "SELECT 1 FROM file_entries
+                 WHERE slot_id = ?1 AND file_kind = 'part' AND external_path = ?2
+                 LIMIT 1",
+            )?;
+            let mut kept = Vec::with_capacity(pruned.parts.len());
+            for part in pruned.parts.drain(..) {
+                // Generations that reused a part share its file.
+                if kept.iter().any(|seen: &PrunedPart| seen.path == part.path) {
+                    continue;
+                }
+                if !still_used.exists(params![self.slot.slot_id as i64, part.path])? {
+                    kept.push(part);
+                }
+            }
+            pruned.parts = kept;
+            drop(still_used);
+            tx.commit()?;
+        }
+
+        Ok(pruned)
+    }
+
     pub fn list_meta_updates_after(
         &self,
         cursor_updated_at: Option<&str>,
@@ -772,12 +2365,547 @@ impl MetadataStore {
     }
 }
 
+struct VersionRow {
+    blob_path: String,
+    file_kind: String,
+    generation: i64,
+    updated_at: DateTime<Utc>,
+    external_path: Option<String>,
+    size_bytes: u64,
+}
+
+/// Generations of `rows` (all of one path) that `retention` drops, or every
+/// generation once the path's tombstone has expired. The current head and
+/// anything newer, such as parts of a write in flight, are always kept.
+fn expired_generations(
+    rows: &[VersionRow],
+    retention: &VersionRetention,
+    now: DateTime<Utc>,
+) -> (Vec<i64>, bool) {
+    let Some(head) = rows
+        .iter()
+        .filter(|row| row.file_kind != "part")
+        .max_by_key(|row| row.generation)
+    else {
+        return (Vec::new(), false);
+    };
+
+    if head.file_kind == "tombstone"
+        && let Some(max_age) = retention.tombstone_max_age_secs
+        && head.updated_at < now - chrono::Duration::seconds(max_age as i64)
+    {
+        let mut generations: Vec<i64> = rows.iter().map(|row| row.generation).collect();
+        generations.sort_unstable();
+        generations.dedup();
+        return (generations, true);
+    }
+
+    let mut older: Vec<(i64, DateTime<Utc>)> = Vec::new();
+    for row in rows.iter().filter(|row| row.generation < head.generation) {
+        match older
+            .iter_mut()
+            .find(|(generation, _)| *generation == row.generation)
+        {
+            Some((_, updated_at)) => *updated_at = (*updated_at).max(row.updated_at),
+            None => older.push((row.generation, row.updated_at)),
+        }
+    }
+    older.sort_by_key(|(generation, _)| std::cmp::Reverse(*generation));
+
+    let expired = older
+        .into_iter()
+        .enumerate()
+        .filter(|(index, (_, updated_at))| {
+            let beyond_count = retention
+                .keep_last_versions
+                .is_some_and(|keep| index + 1 >= keep.max(1));
+            let too_old = retention.max_age_secs.is_some_and(|max_age| {
+                *updated_at < now - chrono::Duration::seconds(max_age as i64)
+            });
+            beyond_count || too_old
+        })
+        .map(|(_, (generation, _))| generation)
+        .collect();
+    (expired, false)
+}
+
+/// Hash of a chain record; it covers the previous record's hash, so changing
+/// any record changes every hash after it.
+fn chain_link_hash(
+    prev_hash: &str,
+    blob_path: &str,
+    head_kind: &str,
+    generation: i64,
+    head_sha256: &str,
+) -> String {
+    compute_hash(
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            prev_hash, blob_path, head_kind, generation, head_sha256
+        )
+        .as_bytes(),
+    )
+}
+
+/// Refuses a head that could not have come from a correct write, so reads
+/// never meet it. Heads only move forward: writing an older generation than
+/// the current one is not an error but a replay, and is dropped.
+fn validate_meta(meta: &BlobMeta) -> Result<()> {
+    let violation = if meta.generation < 0 {
+        Some(MetadataViolation::NegativeGeneration(meta.generation))
+    } else if meta.part_count > 0 && meta.part_size == 0 {
+        Some(MetadataViolation::ZeroPartSize {
+            part_count: meta.part_count,
+        })
+    } else if (meta.part_count == 0) != (meta.size_bytes == 0)
+        || (meta.part_count as u64)
+            .saturating_sub(1)
+            .saturating_mul(meta.part_size)
+            >= meta.size_bytes.max(1)
+    {
+        Some(MetadataViolation::PartCount {
+            size_bytes: meta.size_bytes,
+            part_size: meta.part_size,
+            part_count: meta.part_count,
+        })
+    } else {
+        None
+    };
+    match violation {
+        Some(violation) => Err(RimError::InvalidMetadata {
+            path: meta.path.clone(),
+            violation,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn validate_tombstone(tombstone: &TombstoneMeta) -> Result<()> {
+    if tombstone.generation < 0 {
+        return Err(RimError::InvalidMetadata {
+            path: tombstone.path.clone(),
+            violation: MetadataViolation::NegativeGeneration(tombstone.generation),
+        });
+    }
+    Ok(())
+}
+
+/// Whether `error` means the database file itself is damaged.
+pub(crate) fn is_corruption_error(error: &RimError) -> bool {
+    matches!(
+        error,
+        RimError::Database(rusqlite::Error::SqliteFailure(error, _))
+            if matches!(error.code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+    )
+}
+
 fn parse_rfc3339(value: &str) -> Result<DateTime<Utc>> {
     let parsed = DateTime::parse_from_rfc3339(value)
         .map_err(|error| RimError::Internal(format!("invalid RFC3339 timestamp: {}", error)))?;
     Ok(parsed.with_timezone(&Utc))
 }
 
+fn file_len(path: &std::path::Path) -> u64 {
+    std::fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
+
 fn default_part_size() -> u64 {
     PART_SIZE as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SlotManager;
+
+    fn meta(path: &str, generation: i64) -> BlobMeta {
+        BlobMeta {
+            path: path.to_string(),
+            slot_id: 1,
+            generation,
+            version: generation,
+            size_bytes: 0,
+            etag: compute_hash(b""),
+            part_size: default_part_size(),
+            part_count: 0,
+            part_index_state: PartIndexState::Complete,
+            archive_url: None,
+            updated_at: Utc::now(),
+            metadata: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            content: ContentHeaders::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_transactions_leave_no_part_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        let failed: Result<()> = store.with_transaction(|tx| {
+            tx.upsert_part_entry("fw.bin", 1, 0, "aa", 4, Some("/p0"), None)?;
+            Err(RimError::Internal("interrupted".to_string()))
+        });
+        assert!(failed.is_err());
+        assert!(store.list_part_entries("fw.bin", 1).unwrap().is_empty());
+
+        let applied = store
+            .with_transaction(|tx| {
+                tx.upsert_part_entry("fw.bin", 1, 0, "aa", 4, Some("/p0"), None)?;
+                tx.upsert_part_entry("fw.bin", 1, 1, "bb", 4, Some("/p1"), None)?;
+                let head = meta("fw.bin", 1);
+                let payload = serde_json::to_vec(&head)?;
+                tx.upsert_meta_with_payload(&head, &payload, &compute_hash(&payload))
+            })
+            .unwrap();
+        assert!(applied);
+        assert_eq!(store.list_part_entries("fw.bin", 1).unwrap().len(), 2);
+        assert_eq!(
+            store
+                .get_current_head("fw.bin")
+                .unwrap()
+                .unwrap()
+                .generation,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_head_batch_is_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        store.upsert_meta(&meta("group/a", 1)).unwrap();
+
+        let stale = vec![
+            HeadWrite::meta(meta("group/a", 1)).unwrap(),
+            HeadWrite::meta(meta("group/b", 1)).unwrap(),
+        ];
+        assert!(!store.apply_head_batch(&stale, true).unwrap());
+        assert!(store.get_current_head("group/b").unwrap().is_none());
+
+        let fresh = vec![
+            HeadWrite::meta(meta("group/a", 2)).unwrap(),
+            HeadWrite::tombstone(TombstoneMeta {
+                path: "group/b".to_string(),
+                slot_id: 1,
+                generation: 1,
+                deleted_at: Utc::now(),
+                reason: "test".to_string(),
+            })
+            .unwrap(),
+        ];
+        assert!(store.apply_head_batch(&fresh, true).unwrap());
+
+        let head_a = store.get_current_head("group/a").unwrap().unwrap();
+        assert_eq!(head_a.generation, 2);
+        let head_b = store.get_current_head("group/b").unwrap().unwrap();
+        assert_eq!(head_b.head_kind, HeadKind::Tombstone);
+    }
+
+    #[tokio::test]
+    async fn head_chain_detects_rewritten_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager = SlotManager::new("node-a".to_string(), dir.path().to_path_buf())
+            .unwrap()
+            .with_head_chain(true);
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        let retried = meta("audit/a", 2);
+        store.upsert_meta(&meta("audit/a", 1)).unwrap();
+        store.upsert_meta(&retried).unwrap();
+        store.upsert_meta(&retried).unwrap();
+        store.upsert_meta(&meta("audit/b", 1)).unwrap();
+
+        let report = store.verify_head_chain().unwrap();
+        assert!(report.is_clean());
+        assert_eq!((report.paths, report.records), (2, 3));
+
+        let conn = store.get_conn().unwrap();
+        conn.execute(
+            "UPDATE head_chain SET generation = 5 WHERE blob_path = 'audit/a' AND seq = 1",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE file_entries SET inline_data = X'7B7D' WHERE blob_path = 'audit/b'",
+            [],
+        )
+        .unwrap();
+
+        let report = store.verify_head_chain().unwrap();
+        assert_eq!(report.broken, 2);
+        assert!(report.samples[0].starts_with("audit/a: record altered"));
+    }
+
+    #[tokio::test]
+    async fn test_prune_versions_keeps_current_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        for generation in 1..=3 {
+            store
+                .upsert_part_entry("fw.bin", generation, 0, "aa", 4, Some("/p"), None)
+                .unwrap();
+            store.upsert_meta(&meta("fw.bin", generation)).unwrap();
+        }
+        store
+            .upsert_part_entry("fw.bin", 4, 0, "bb", 4, Some("/staged"), None)
+            .unwrap();
+
+        let retention = VersionRetention {
+            keep_last_versions: Some(2),
+            ..VersionRetention::default()
+        };
+        let dry = store.prune_versions(&retention, Utc::now(), true).unwrap();
+        assert_eq!(dry.versions, 1);
+        assert_eq!(store.list_part_entries("fw.bin", 1).unwrap().len(), 1);
+
+        let pruned = store.prune_versions(&retention, Utc::now(), false).unwrap();
+        assert_eq!(pruned.versions, 1);
+        assert!(pruned.parts.is_empty());
+        assert!(store.list_part_entries("fw.bin", 1).unwrap().is_empty());
+        assert_eq!(store.list_part_entries("fw.bin", 2).unwrap().len(), 1);
+        assert_eq!(store.list_part_entries("fw.bin", 4).unwrap().len(), 1);
+        assert_eq!(
+            store
+                .get_current_head("fw.bin")
+                .unwrap()
+                .unwrap()
+                .generation,
+            3
+        );
+
+        store
+            .insert_tombstone(&TombstoneMeta {
+                path: "fw.bin".to_string(),
+                slot_id: 1,
+                generation: 5,
+                deleted_at: Utc::now(),
+                reason: "test".to_string(),
+            })
+            .unwrap();
+        let forget = VersionRetention {
+            tombstone_max_age_secs: Some(60),
+            ..VersionRetention::default()
+        };
+        let later = Utc::now() + chrono::Duration::seconds(120);
+        let pruned = store.prune_versions(&forget, later, false).unwrap();
+        assert_eq!(pruned.paths, 1);
+        assert!(store.get_current_head("fw.bin").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_maintenance_is_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        store.upsert_meta(&meta("group/a", 1)).unwrap();
+
+        let before = store.sqlite_stats().unwrap();
+        assert!(before.page_count > 0);
+        assert!(before.last_vacuum_at.is_none());
+        assert_eq!(before.busy_errors, 0);
+
+        store.checkpoint().unwrap();
+        store.vacuum().unwrap();
+
+        let after = store.slot_stats().unwrap().sqlite;
+        assert!(after.last_checkpoint_at.is_some());
+        assert!(after.last_vacuum_at.is_some());
+        assert_eq!(after.free_pages, 0);
+    }
+
+    #[tokio::test]
+    async fn test_backup_retention_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        store.upsert_meta(&meta("group/a", 1)).unwrap();
+        let first = store.backup("transfer", 2).unwrap();
+        store.backup("vacuum", 2).unwrap();
+        store.backup("vacuum", 2).unwrap();
+
+        let backups = store.list_backups().unwrap();
+        assert_eq!(backups.len(), 2);
+        assert!(!backups.contains(&first));
+
+        store.upsert_meta(&meta("group/b", 1)).unwrap();
+        store.restore_backup(&backups[0]).unwrap();
+        assert!(store.get_current_head("group/a").unwrap().is_some());
+        assert!(store.get_current_head("group/b").unwrap().is_none());
+
+        assert!(store.restore_backup(&dir.path().join("elsewhere")).is_err());
+    }
+
+    #[tokio::test]
+    async fn corrupt_databases_are_reported_and_quarantined() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let slot = slot_manager.get_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot.clone()).unwrap();
+        store.upsert_meta(&meta("group/a", 1)).unwrap();
+        store.checkpoint().unwrap();
+
+        assert!(store.integrity_check().unwrap().is_empty());
+        assert!(
+            store
+                .sqlite_stats()
+                .unwrap()
+                .last_integrity_check_at
+                .is_some()
+        );
+
+        let mut bytes = std::fs::read(slot.meta_db_path()).unwrap();
+        bytes[..100].fill(0xff);
+        std::fs::write(slot.meta_db_path(), bytes).unwrap();
+        assert!(!store.integrity_check().unwrap().is_empty());
+
+        let quarantined = MetadataStore::quarantine(&slot, Utc::now()).unwrap();
+        assert!(quarantined.starts_with(slot.quarantine_dir()));
+        assert!(quarantined.is_file());
+        let store = MetadataStore::new(slot).unwrap();
+        assert!(store.integrity_check().unwrap().is_empty());
+        assert!(store.get_current_head("group/a").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn impossible_heads_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        let sized = |size_bytes, part_size, part_count| BlobMeta {
+            size_bytes,
+            part_size,
+            part_count,
+            ..meta("fw.bin", 1)
+        };
+        for head in [
+            sized(8, 4, 2),
+            sized(9, 4, 2),
+            sized(10, 4, 1),
+            sized(0, 4, 0),
+        ] {
+            store.upsert_meta(&head).unwrap();
+        }
+        for (head, violation) in [
+            (
+                sized(4, 4, 2),
+                MetadataViolation::PartCount {
+                    size_bytes: 4,
+                    part_size: 4,
+                    part_count: 2,
+                },
+            ),
+            (
+                sized(4, 4, 0),
+                MetadataViolation::PartCount {
+                    size_bytes: 4,
+                    part_size: 4,
+                    part_count: 0,
+                },
+            ),
+            (
+                sized(4, 0, 1),
+                MetadataViolation::ZeroPartSize { part_count: 1 },
+            ),
+            (
+                meta("fw.bin", -1),
+                MetadataViolation::NegativeGeneration(-1),
+            ),
+        ] {
+            match store.upsert_meta(&head) {
+                Err(RimError::InvalidMetadata {
+                    path,
+                    violation: found,
+                }) => {
+                    assert_eq!(path, "fw.bin");
+                    assert_eq!(found, violation);
+                }
+                other => panic!("expected {:?}, got {:?}", violation, other),
+            }
+        }
+
+        let tombstone = TombstoneMeta {
+            path: "fw.bin".to_string(),
+            slot_id: 1,
+            generation: -2,
+            deleted_at: Utc::now(),
+            reason: "test".to_string(),
+        };
+        assert!(matches!(
+            store.insert_tombstone(&tombstone),
+            Err(RimError::InvalidMetadata { .. })
+        ));
+        assert_eq!(
+            store.get_current_head("fw.bin").unwrap().unwrap().head_kind,
+            HeadKind::Meta
+        );
+    }
+
+    #[tokio::test]
+    async fn change_feed_records_each_head_change_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap();
+        slot_manager.init_slot(1).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap()).unwrap();
+
+        let first = meta("fw.bin", 1);
+        store.upsert_meta(&first).unwrap();
+        store.upsert_meta(&first).unwrap();
+        store.upsert_meta(&meta("fw.bin", 2)).unwrap();
+        store.upsert_meta(&meta("fw.bin", 1)).unwrap();
+        store
+            .insert_tombstone(&TombstoneMeta {
+                path: "fw.bin".to_string(),
+                slot_id: 1,
+                generation: 3,
+                deleted_at: Utc::now(),
+                reason: "test".to_string(),
+            })
+            .unwrap();
+
+        let changes = store.list_changes(0, 100).unwrap();
+        let kinds: Vec<(ChangeKind, i64)> = changes
+            .iter()
+            .map(|change| (change.kind, change.generation))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (ChangeKind::Create, 1),
+                (ChangeKind::Update, 2),
+                (ChangeKind::Delete, 3)
+            ]
+        );
+
+        let rest = store.list_changes(changes[0].seq, 1).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].seq, changes[1].seq);
+        assert_eq!(store.latest_change_seq().unwrap(), changes[2].seq);
+    }
+}
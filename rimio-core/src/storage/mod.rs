@@ -3,14 +3,48 @@
 //! Provides filesystem part storage and metadata management.
 
 pub mod archive_store;
+pub mod head_migration;
+pub mod layout;
 pub mod metadata_store;
+pub mod node_store;
 pub mod part_store;
+pub mod scrub;
+pub mod slot_transfer;
+pub mod snapshot;
+pub mod sqlite_maintenance;
 
 pub use archive_store::{
-    ArchiveListPage, ArchiveStore, RedisArchiveStore, S3ArchiveStore, parse_redis_archive_url,
-    parse_s3_archive_url, read_archive_range_bytes, set_default_s3_archive_store,
+    ArchiveListPage, ArchiveRetryPolicy, ArchiveStore, AzureArchiveStore, GcsArchiveStore,
+    LocalFsArchiveStore, RedisArchiveStore, S3ArchiveStore, archive_store_for_scheme,
+    parse_azure_archive_url, parse_gcs_archive_url, parse_local_fs_archive_url,
+    parse_redis_archive_url, parse_s3_archive_url, read_archive_range_bytes,
+    register_archive_scheme, set_default_azure_archive_store, set_default_gcs_archive_store,
+    set_default_local_fs_archive_store, set_default_s3_archive_store,
 };
+pub use head_migration::{HeadSchemaMigration, HeadSchemaMigrationConfig, SlotHeadSchemaStatus};
+pub use layout::{LAYOUT_VERSION, LayoutStamp, prepare_data_dir};
 pub use metadata_store::{
-    BlobHead, BlobMeta, HeadKind, MetadataStore, PartEntry, PartIndexState, TombstoneMeta,
+    BlobHead, BlobMeta, ChangeKind, ContentHeaders, HeadChainReport, HeadKind, HeadSchemaPhase,
+    HeadShadowReport, HeadWrite, MetadataStore, MetadataTransaction, PartEntry, PartIndexState,
+    PrunedPart, PrunedVersions, SLOT_BACKUP_RETENTION, SlotChange, SlotStats, SqliteStats,
+    TombstoneMeta, VersionRetention,
+};
+pub use node_store::{
+    HintRecord, JobRecord, NodeStore, RepairAttempt, RepairDeadLetter, RepairRecord, RepairStats,
+    TransactionRecord, UploadPartRecord, UploadSession,
+};
+pub use part_store::{
+    PartMmapAdvice, PartMmapConfig, PartStore, PartWriter, PutPartResult, compute_hash,
+    migrate_legacy_part_dirs, normalize_blob_path, shred_part_file, verify_hash,
+};
+pub use scrub::{DiskScrubReport, ScrubConfig, ScrubScheduler};
+pub use slot_transfer::{
+    SLOT_TRANSFER_DB_FILE, SlotTransferFile, SlotTransferManifest, SlotTransferStaging,
+};
+pub use snapshot::{
+    MountedSnapshot, SNAPSHOT_FORMAT_VERSION, SnapshotBlob, SnapshotManifest, SnapshotPart,
+    SnapshotWriter,
+};
+pub use sqlite_maintenance::{
+    SqliteCheckpointConfig, SqliteIntegrityConfig, SqliteMaintenance, SqliteMaintenanceConfig,
 };
-pub use part_store::{PartStore, PutPartResult, compute_hash, verify_hash};
@@ -0,0 +1,1174 @@
+//! Durable node-local state.
+//!
+//! One SQLite database per node, next to (not inside) the slot databases, for
+//! state that belongs to the node rather than to a slot: settings, background
+//! cursors, hinted writes, jobs, upload sessions, idempotency records, the
+//! history of part repairs, the repairs parked after failing for good and the
+//! two-phase commit log.
+
+use crate::{Result, SharedClock, system_clock};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A write a replica missed, to be handed to it once it is back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HintRecord {
+    pub hint_id: i64,
+    pub target_node_id: String,
+    pub slot_id: u16,
+    pub blob_path: String,
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub job_id: String,
+    pub kind: String,
+    pub state: String,
+    #[serde(default)]
+    pub payload: Value,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub upload_id: String,
+    pub slot_id: u16,
+    pub blob_path: String,
+    #[serde(default)]
+    pub payload: Value,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A part received for a multipart upload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadPartRecord {
+    pub part_number: u32,
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+/// How long repair history is kept before `purge_expired` drops it.
+const REPAIR_HISTORY_RETENTION_DAYS: i64 = 30;
+
+/// One attempt to fetch a missing part from one source during a repair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairAttempt {
+    pub slot_id: u16,
+    pub blob_path: String,
+    pub generation: i64,
+    pub part_no: u32,
+    /// `local_copy`, `peer` or `archive`.
+    pub source_kind: String,
+    /// The node, file or archive URL the part was fetched from.
+    pub source: String,
+    pub bytes: u64,
+    pub duration_ms: u64,
+    pub ok: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairRecord {
+    pub repair_id: i64,
+    #[serde(flatten)]
+    pub attempt: RepairAttempt,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Repair attempts of one source kind, summed over a window.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairStats {
+    pub source_kind: String,
+    pub attempts: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Bytes stored by the attempts that succeeded.
+    pub bytes: u64,
+    pub total_duration_ms: u64,
+}
+
+/// A repair of one blob generation that failed for want of a good copy.
+/// Once parked, the repair is no longer attempted until it is released.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairDeadLetter {
+    pub slot_id: u16,
+    pub blob_path: String,
+    pub generation: i64,
+    pub failures: u32,
+    pub last_error: String,
+    pub first_failed_at: DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+    #[serde(default)]
+    pub parked_at: Option<DateTime<Utc>>,
+}
+
+/// How long decided two-phase commit records are kept. Prepared batches are
+/// kept until decided.
+pub(crate) const TRANSACTION_LOG_RETENTION_DAYS: i64 = 7;
+
+/// A two-phase commit record of one transaction: a batch this node prepared
+/// for a slot and, once decided, its outcome.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub txn_id: String,
+    pub slot_id: u16,
+    /// `prepared`, `committed`, `aborted`, or `rejected` when the commit
+    /// failed the generation check.
+    pub state: String,
+    /// The prepared batch; `Null` once decided.
+    #[serde(default)]
+    pub payload: Value,
+    pub recorded_at: DateTime<Utc>,
+}
+
+pub struct NodeStore {
+    db_path: PathBuf,
+    clock: SharedClock,
+}
+
+impl NodeStore {
+    /// Opens the node database under `data_dir`, creating it when missing.
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        let node_dir = data_dir.join("node");
+        std::fs::create_dir_all(&node_dir)?;
+
+        let store = Self {
+            db_path: node_dir.join("node.sqlite3"),
+            clock: system_clock(),
+        };
+        store.init_schema()?;
+        Ok(store)
+    }
+
+    /// Stamps rows and expires upload sessions and idempotency records by
+    /// `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    fn get_conn(&self) -> Result<Connection> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        Ok(conn)
+    }
+
+    fn init_schema(&self) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS node_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS node_cursors (
+                scope TEXT NOT NULL,
+                slot_id INTEGER NOT NULL,
+                cursor TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                PRIMARY KEY(scope, slot_id)
+            );
+            CREATE TABLE IF NOT EXISTS hint_queue (
+                hint_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                target_node_id TEXT NOT NULL,
+                slot_id INTEGER NOT NULL,
+                blob_path TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL,
+                UNIQUE(target_node_id, slot_id, blob_path)
+            );
+            CREATE TABLE IF NOT EXISTS jobs (
+                job_id TEXT PRIMARY KEY,
+                kind TEXT NOT NULL,
+                state TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                error TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_jobs_kind ON jobs(kind, created_at);
+            CREATE TABLE IF NOT EXISTS upload_sessions (
+                upload_id TEXT PRIMARY KEY,
+                slot_id INTEGER NOT NULL,
+                blob_path TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS upload_parts (
+                upload_id TEXT NOT NULL,
+                part_number INTEGER NOT NULL,
+                sha256 TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                uploaded_at TEXT NOT NULL,
+                PRIMARY KEY(upload_id, part_number)
+            );
+            CREATE TABLE IF NOT EXISTS idempotency_records (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS repairs (
+                repair_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                slot_id INTEGER NOT NULL,
+                blob_path TEXT NOT NULL,
+                generation INTEGER NOT NULL,
+                part_no INTEGER NOT NULL,
+                source_kind TEXT NOT NULL,
+                source TEXT NOT NULL,
+                bytes INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                ok INTEGER NOT NULL,
+                error TEXT,
+                created_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_repairs_created ON repairs(created_at);
+            CREATE TABLE IF NOT EXISTS repair_dead_letters (
+                slot_id INTEGER NOT NULL,
+                blob_path TEXT NOT NULL,
+                generation INTEGER NOT NULL,
+                failures INTEGER NOT NULL,
+                last_error TEXT NOT NULL,
+                first_failed_at TEXT NOT NULL,
+                last_failed_at TEXT NOT NULL,
+                parked_at TEXT,
+                PRIMARY KEY(slot_id, blob_path, generation)
+            );
+            CREATE TABLE IF NOT EXISTS transaction_records (
+                txn_id TEXT NOT NULL,
+                slot_id INTEGER NOT NULL,
+                state TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                recorded_at TEXT NOT NULL,
+                PRIMARY KEY(txn_id, slot_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_transaction_records_state
+                ON transaction_records(state);
+            CREATE TABLE IF NOT EXISTS transaction_decisions (
+                txn_id TEXT PRIMARY KEY,
+                state TEXT NOT NULL,
+                decided_at TEXT NOT NULL
+            );",
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.get_conn()?;
+        Ok(conn
+            .query_row(
+                "SELECT value FROM node_settings WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO node_settings (key, value, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(key) DO UPDATE SET
+                value = excluded.value,
+                updated_at = excluded.updated_at",
+            params![key, value, self.clock.now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Loads the cursor a background task left for a slot, e.g. how far
+    /// heal or archive sync got.
+    pub fn load_cursor<T: for<'de> Deserialize<'de>>(
+        &self,
+        scope: &str,
+        slot_id: u16,
+    ) -> Result<Option<T>> {
+        let conn = self.get_conn()?;
+        let raw: Option<String> = conn
+            .query_row(
+                "SELECT cursor FROM node_cursors WHERE scope = ?1 AND slot_id = ?2",
+                params![scope, slot_id as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(raw.map(|raw| serde_json::from_str(&raw)).transpose()?)
+    }
+
+    pub fn save_cursor<T: Serialize>(&self, scope: &str, slot_id: u16, cursor: &T) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO node_cursors (scope, slot_id, cursor, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(scope, slot_id) DO UPDATE SET
+                cursor = excluded.cursor,
+                updated_at = excluded.updated_at",
+            params![
+                scope,
+                slot_id as i64,
+                serde_json::to_string(cursor)?,
+                self.clock.now().to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Queues a hint for `target_node_id`. Hints are keyed by blob path, so
+    /// repeated misses of the same blob collapse into one.
+    pub fn enqueue_hint(&self, target_node_id: &str, slot_id: u16, blob_path: &str) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO hint_queue (target_node_id, slot_id, blob_path, created_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                target_node_id,
+                slot_id as i64,
+                blob_path,
+                self.clock.now().to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the oldest hints queued for a node.
+    pub fn pending_hints(&self, target_node_id: &str, limit: usize) -> Result<Vec<HintRecord>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT hint_id, target_node_id, slot_id, blob_path, attempts, created_at
+             FROM hint_queue
+             WHERE target_node_id = ?1
+             ORDER BY hint_id ASC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![target_node_id, limit as i64], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })?;
+
+        let mut hints = Vec::new();
+        for row in rows {
+            let (hint_id, target_node_id, slot_id, blob_path, attempts, created_at) = row?;
+            hints.push(HintRecord {
+                hint_id,
+                target_node_id,
+                slot_id: slot_id as u16,
+                blob_path,
+                attempts: attempts as u32,
+                created_at: self.parse_timestamp(&created_at),
+            });
+        }
+
+        Ok(hints)
+    }
+
+    pub fn record_hint_attempt(&self, hint_id: i64) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE hint_queue SET attempts = attempts + 1 WHERE hint_id = ?1",
+            params![hint_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn complete_hint(&self, hint_id: i64) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "DELETE FROM hint_queue WHERE hint_id = ?1",
+            params![hint_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn upsert_job(&self, job: &JobRecord) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO jobs (job_id, kind, state, payload, error, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(job_id) DO UPDATE SET
+                state = excluded.state,
+                payload = excluded.payload,
+                error = excluded.error,
+                updated_at = excluded.updated_at",
+            params![
+                job.job_id,
+                job.kind,
+                job.state,
+                serde_json::to_string(&job.payload)?,
+                job.error,
+                job.created_at.to_rfc3339(),
+                job.updated_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_job(&self, job_id: &str) -> Result<Option<JobRecord>> {
+        Ok(self
+            .query_jobs("WHERE job_id = ?1", params![job_id])?
+            .into_iter()
+            .next())
+    }
+
+    /// Lists jobs of one kind, oldest first.
+    pub fn list_jobs(&self, kind: &str) -> Result<Vec<JobRecord>> {
+        self.query_jobs("WHERE kind = ?1 ORDER BY created_at ASC", params![kind])
+    }
+
+    fn query_jobs(&self, filter: &str, args: impl rusqlite::Params) -> Result<Vec<JobRecord>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT job_id, kind, state, payload, error, created_at, updated_at FROM jobs {}",
+            filter
+        ))?;
+        let rows = stmt.query_map(args, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+            ))
+        })?;
+
+        let mut jobs = Vec::new();
+        for row in rows {
+            let (job_id, kind, state, payload, error, created_at, updated_at) = row?;
+            jobs.push(JobRecord {
+                job_id,
+                kind,
+                state,
+                payload: serde_json::from_str(&payload)?,
+                error,
+                created_at: self.parse_timestamp(&created_at),
+                updated_at: self.parse_timestamp(&updated_at),
+            });
+        }
+
+        Ok(jobs)
+    }
+
+    pub fn save_upload_session(&self, session: &UploadSession) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO upload_sessions (upload_id, slot_id, blob_path, payload, created_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(upload_id) DO UPDATE SET
+                payload = excluded.payload,
+                expires_at = excluded.expires_at",
+            params![
+                session.upload_id,
+                session.slot_id as i64,
+                session.blob_path,
+                serde_json::to_string(&session.payload)?,
+                session.created_at.to_rfc3339(),
+                session.expires_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns an upload session unless it has expired.
+    pub fn get_upload_session(&self, upload_id: &str) -> Result<Option<UploadSession>> {
+        let conn = self.get_conn()?;
+        let row = conn
+            .query_row(
+                "SELECT slot_id, blob_path, payload, created_at, expires_at
+                 FROM upload_sessions
+                 WHERE upload_id = ?1 AND expires_at > ?2",
+                params![upload_id, self.clock.now().to_rfc3339()],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, String>(4)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        let Some((slot_id, blob_path, payload, created_at, expires_at)) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(UploadSession {
+            upload_id: upload_id.to_string(),
+            slot_id: slot_id as u16,
+            blob_path,
+            payload: serde_json::from_str(&payload)?,
+            created_at: self.parse_timestamp(&created_at),
+            expires_at: self.parse_timestamp(&expires_at),
+        }))
+    }
+
+    pub fn delete_upload_session(&self, upload_id: &str) -> Result<bool> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM upload_parts WHERE upload_id = ?1",
+            params![upload_id],
+        )?;
+        let deleted = tx.execute(
+            "DELETE FROM upload_sessions WHERE upload_id = ?1",
+            params![upload_id],
+        )?;
+        tx.commit()?;
+        Ok(deleted > 0)
+    }
+
+    /// Records a received part, replacing an earlier upload of its number.
+    pub fn put_upload_part(&self, upload_id: &str, part: &UploadPartRecord) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO upload_parts (upload_id, part_number, sha256, size_bytes, uploaded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(upload_id, part_number) DO UPDATE SET
+                sha256 = excluded.sha256,
+                size_bytes = excluded.size_bytes,
+                uploaded_at = excluded.uploaded_at",
+            params![
+                upload_id,
+                part.part_number as i64,
+                part.sha256,
+                part.size_bytes as i64,
+                part.uploaded_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the parts received for an upload, by part number.
+    pub fn list_upload_parts(&self, upload_id: &str) -> Result<Vec<UploadPartRecord>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT part_number, sha256, size_bytes, uploaded_at
+             FROM upload_parts
+             WHERE upload_id = ?1
+             ORDER BY part_number",
+        )?;
+        let rows = stmt.query_map(params![upload_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+
+        let mut parts = Vec::new();
+        for row in rows {
+            let (part_number, sha256, size_bytes, uploaded_at) = row?;
+            parts.push(UploadPartRecord {
+                part_number: part_number as u32,
+                sha256,
+                size_bytes: size_bytes as u64,
+                uploaded_at: self.parse_timestamp(&uploaded_at),
+            });
+        }
+        Ok(parts)
+    }
+
+    pub fn put_idempotency_record(&self, key: &str, value: &str, ttl: Duration) -> Result<()> {
+        let now = self.clock.now();
+        let expires_at = now + ChronoDuration::from_std(ttl).unwrap_or(ChronoDuration::zero());
+
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO idempotency_records (key, value, created_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(key) DO UPDATE SET
+                value = excluded.value,
+                created_at = excluded.created_at,
+                expires_at = excluded.expires_at",
+            params![key, value, now.to_rfc3339(), expires_at.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_idempotency_record(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.get_conn()?;
+        Ok(conn
+            .query_row(
+                "SELECT value FROM idempotency_records WHERE key = ?1 AND expires_at > ?2",
+                params![key, self.clock.now().to_rfc3339()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Appends a repair attempt to the history and returns its id.
+    pub fn record_repair(&self, attempt: &RepairAttempt) -> Result<i64> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO repairs (slot_id, blob_path, generation, part_no, source_kind, source,
+                                  bytes, duration_ms, ok, error, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                attempt.slot_id as i64,
+                attempt.blob_path,
+                attempt.generation,
+                attempt.part_no as i64,
+                attempt.source_kind,
+                attempt.source,
+                attempt.bytes as i64,
+                attempt.duration_ms as i64,
+                attempt.ok,
+                attempt.error,
+                self.clock.now().to_rfc3339(),
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Lists repair attempts newest first, starting below `before_id` when
+    /// given; `failed_only` keeps the attempts that did not deliver the part.
+    pub fn list_repairs(
+        &self,
+        before_id: Option<i64>,
+        failed_only: bool,
+        limit: usize,
+    ) -> Result<Vec<RepairRecord>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT repair_id, slot_id, blob_path, generation, part_no, source_kind, source,
+                    bytes, duration_ms, ok, error, created_at
+             FROM repairs
+             WHERE repair_id < ?1 AND (?2 = 0 OR ok = 0)
+             ORDER BY repair_id DESC
+             LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(
+            params![before_id.unwrap_or(i64::MAX), failed_only, limit as i64],
+            |row| {
+                Ok(RepairRecord {
+                    repair_id: row.get(0)?,
+                    attempt: RepairAttempt {
+                        slot_id: row.get::<_, i64>(1)? as u16,
+                        blob_path: row.get(2)?,
+                        generation: row.get(3)?,
+                        part_no: row.get::<_, i64>(4)? as u32,
+                        source_kind: row.get(5)?,
+                        source: row.get(6)?,
+                        bytes: row.get::<_, i64>(7)? as u64,
+                        duration_ms: row.get::<_, i64>(8)? as u64,
+                        ok: row.get(9)?,
+                        error: row.get(10)?,
+                    },
+                    created_at: self.parse_timestamp(&row.get::<_, String>(11)?),
+                })
+            },
+        )?;
+
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Sums the repair attempts made since `since`, per source kind.
+    pub fn repair_stats(&self, since: DateTime<Utc>) -> Result<Vec<RepairStats>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT source_kind,
+                    COUNT(*),
+                    SUM(ok),
+                    SUM(CASE WHEN ok = 1 THEN bytes ELSE 0 END),
+                    SUM(duration_ms)
+             FROM repairs
+             WHERE created_at >= ?1
+             GROUP BY source_kind
+             ORDER BY source_kind",
+        )?;
+        let rows = stmt.query_map(params![since.to_rfc3339()], |row| {
+            let attempts = row.get::<_, i64>(1)? as u64;
+            let succeeded = row.get::<_, i64>(2)? as u64;
+            Ok(RepairStats {
+                source_kind: row.get(0)?,
+                attempts,
+                succeeded,
+                failed: attempts - succeeded,
+                bytes: row.get::<_, i64>(3)? as u64,
+                total_duration_ms: row.get::<_, i64>(4)? as u64,
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Counts a repair of `blob_path` at `generation` that failed with no
+    /// good copy anywhere, parking it once it has failed `park_after` times.
+    /// Returns whether the repair is parked.
+    pub fn record_repair_failure(
+        &self,
+        slot_id: u16,
+        blob_path: &str,
+        generation: i64,
+        error: &str,
+        park_after: u32,
+    ) -> Result<bool> {
+        let conn = self.get_conn()?;
+        let now = self.clock.now().to_rfc3339();
+        conn.execute(
+            "INSERT INTO repair_dead_letters (slot_id, blob_path, generation, failures,
+                                              last_error, first_failed_at, last_failed_at)
+             VALUES (?1, ?2, ?3, 1, ?4, ?5, ?5)
+             ON CONFLICT(slot_id, blob_path, generation) DO UPDATE SET
+                failures = failures + 1,
+                last_error = excluded.last_error,
+                last_failed_at = excluded.last_failed_at",
+            params![slot_id as i64, blob_path, generation, error, now],
+        )?;
+        conn.execute(
+            "UPDATE repair_dead_letters SET parked_at = ?4
+             WHERE slot_id = ?1 AND blob_path = ?2 AND generation = ?3
+               AND parked_at IS NULL AND failures >= ?5",
+            params![
+                slot_id as i64,
+                blob_path,
+                generation,
+                now,
+                park_after as i64
+            ],
+        )?;
+        Ok(conn
+            .query_row(
+                "SELECT parked_at IS NOT NULL FROM repair_dead_letters
+                 WHERE slot_id = ?1 AND blob_path = ?2 AND generation = ?3",
+                params![slot_id as i64, blob_path, generation],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(false))
+    }
+
+    /// Forgets the failures of `blob_path` up to `generation` once a repair
+    /// of it succeeded.
+    pub fn clear_repair_failures(
+        &self,
+        slot_id: u16,
+        blob_path: &str,
+        generation: i64,
+    ) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "DELETE FROM repair_dead_letters
+             WHERE slot_id = ?1 AND blob_path = ?2 AND generation <= ?3",
+            params![slot_id as i64, blob_path, generation],
+        )?;
+        Ok(())
+    }
+
+    /// The dead letter of `blob_path` at `generation`, if its repair is
+    /// parked.
+    pub fn parked_repair(
+        &self,
+        slot_id: u16,
+        blob_path: &str,
+        generation: i64,
+    ) -> Result<Option<RepairDeadLetter>> {
+        Ok(self
+            .query_dead_letters(
+                "WHERE slot_id = ?1 AND blob_path = ?2 AND generation = ?3
+                   AND parked_at IS NOT NULL",
+                params![slot_id as i64, blob_path, generation],
+            )?
+            .into_iter()
+            .next())
+    }
+
+    /// Lists parked repairs, most recently failed first.
+    pub fn list_dead_letters(&self, limit: usize) -> Result<Vec<RepairDeadLetter>> {
+        self.query_dead_letters(
+            "WHERE parked_at IS NOT NULL ORDER BY last_failed_at DESC LIMIT ?1",
+            params![limit as i64],
+        )
+    }
+
+    /// Releases the parked repairs of `blob_path`, so the next read or heal
+    /// tries again; returns how many were released.
+    pub fn release_dead_letters(&self, slot_id: u16, blob_path: &str) -> Result<usize> {
+        let conn = self.get_conn()?;
+        Ok(conn.execute(
+            "DELETE FROM repair_dead_letters WHERE slot_id = ?1 AND blob_path = ?2",
+            params![slot_id as i64, blob_path],
+        )?)
+    }
+
+    fn query_dead_letters(
+        &self,
+        filter: &str,
+        args: impl rusqlite::Params,
+    ) -> Result<Vec<RepairDeadLetter>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT slot_id, blob_path, generation, failures, last_error, first_failed_at,
+                    last_failed_at, parked_at
+             FROM repair_dead_letters {}",
+            filter
+        ))?;
+        let rows = stmt.query_map(args, |row| {
+            Ok(RepairDeadLetter {
+                slot_id: row.get::<_, i64>(0)? as u16,
+                blob_path: row.get(1)?,
+                generation: row.get(2)?,
+                failures: row.get::<_, i64>(3)? as u32,
+                last_error: row.get(4)?,
+                first_failed_at: self.parse_timestamp(&row.get::<_, String>(5)?),
+                last_failed_at: self.parse_timestamp(&row.get::<_, String>(6)?),
+                parked_at: row
+                    .get::<_, Option<String>>(7)?
+                    .map(|value| self.parse_timestamp(&value)),
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Records a batch prepared here for a slot, or its outcome, replacing
+    /// what was recorded for the slot before.
+    pub fn save_transaction_record(&self, record: &TransactionRecord) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO transaction_records (txn_id, slot_id, state, payload, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(txn_id, slot_id) DO UPDATE SET
+                state = excluded.state,
+                payload = excluded.payload,
+                recorded_at = excluded.recorded_at",
+            params![
+                record.txn_id,
+                record.slot_id as i64,
+                record.state,
+                serde_json::to_string(&record.payload)?,
+                record.recorded_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the records of `txn_id`, or with `None` every record in
+    /// `state`, oldest first.
+    pub fn list_transaction_records(
+        &self,
+        txn_id: Option<&str>,
+        state: Option<&str>,
+    ) -> Result<Vec<TransactionRecord>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT txn_id, slot_id, state, payload, recorded_at
+             FROM transaction_records
+             WHERE (?1 IS NULL OR txn_id = ?1) AND (?2 IS NULL OR state = ?2)
+             ORDER BY recorded_at, txn_id, slot_id",
+        )?;
+        let rows = stmt.query_map(params![txn_id, state], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let (txn_id, slot_id, state, payload, recorded_at) = row?;
+            records.push(TransactionRecord {
+                txn_id,
+                slot_id: slot_id as u16,
+                state,
+                payload: serde_json::from_str(&payload)?,
+                recorded_at: self.parse_timestamp(&recorded_at),
+            });
+        }
+        Ok(records)
+    }
+
+    /// Records what this node, as coordinator, decided for `txn_id`.
+    pub fn save_transaction_decision(&self, txn_id: &str, state: &str) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO transaction_decisions (txn_id, state, decided_at)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT(txn_id) DO UPDATE SET
+                state = excluded.state,
+                decided_at = excluded.decided_at",
+            params![txn_id, state, self.clock.now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_transaction_decision(&self, txn_id: &str) -> Result<Option<String>> {
+        let conn = self.get_conn()?;
+        Ok(conn
+            .query_row(
+                "SELECT state FROM transaction_decisions WHERE txn_id = ?1",
+                params![txn_id],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// A stored timestamp, or the clock's now when it does not parse.
+    fn parse_timestamp(&self, value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .map(|value| value.with_timezone(&Utc))
+            .unwrap_or_else(|_| self.clock.now())
+    }
+
+    /// Drops expired idempotency records and upload sessions, and repair
+    /// history, dead letters and decided transactions past their retention;
+    /// returns how many records and sessions were removed.
+    pub fn purge_expired(&self) -> Result<usize> {
+        let conn = self.get_conn()?;
+        let now = self.clock.now().to_rfc3339();
+        let records = conn.execute(
+            "DELETE FROM idempotency_records WHERE expires_at <= ?1",
+            params![now],
+        )?;
+        let sessions = conn.execute(
+            "DELETE FROM upload_sessions WHERE expires_at <= ?1",
+            params![now],
+        )?;
+        conn.execute(
+            "DELETE FROM upload_parts
+             WHERE upload_id NOT IN (SELECT upload_id FROM upload_sessions)",
+            [],
+        )?;
+        let history_cutoff =
+            (self.clock.now() - ChronoDuration::days(REPAIR_HISTORY_RETENTION_DAYS)).to_rfc3339();
+        conn.execute(
+            "DELETE FROM repairs WHERE created_at < ?1",
+            params![history_cutoff],
+        )?;
+        conn.execute(
+            "DELETE FROM repair_dead_letters WHERE last_failed_at < ?1",
+            params![history_cutoff],
+        )?;
+        let transaction_cutoff =
+            (self.clock.now() - ChronoDuration::days(TRANSACTION_LOG_RETENTION_DAYS)).to_rfc3339();
+        conn.execute(
+            "DELETE FROM transaction_records WHERE state != 'prepared' AND recorded_at < ?1",
+            params![transaction_cutoff],
+        )?;
+        conn.execute(
+            "DELETE FROM transaction_decisions WHERE decided_at < ?1",
+            params![transaction_cutoff],
+        )?;
+        Ok(records + sessions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn node_store_keeps_state_across_reopen() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        {
+            let store = NodeStore::open(dir.path()).expect("store");
+            store.set_setting("format", "2").expect("setting");
+            store.save_cursor("heal", 3, &"a/b").expect("cursor");
+            store.enqueue_hint("node-2", 3, "a/b").expect("hint");
+            store.enqueue_hint("node-2", 3, "a/b").expect("hint again");
+            store
+                .put_idempotency_record("w1", "{}", Duration::from_secs(60))
+                .expect("record");
+            store
+                .put_idempotency_record("w2", "{}", Duration::ZERO)
+                .expect("expired record");
+        }
+
+        let store = NodeStore::open(dir.path()).expect("reopen");
+        assert_eq!(store.get_setting("format").unwrap().as_deref(), Some("2"));
+        assert_eq!(
+            store.load_cursor::<String>("heal", 3).unwrap().as_deref(),
+            Some("a/b")
+        );
+        assert!(store.load_cursor::<String>("heal", 4).unwrap().is_none());
+
+        let hints = store.pending_hints("node-2", 10).expect("hints");
+        assert_eq!(hints.len(), 1);
+        store.complete_hint(hints[0].hint_id).expect("complete");
+        assert!(store.pending_hints("node-2", 10).unwrap().is_empty());
+
+        assert!(store.get_idempotency_record("w1").unwrap().is_some());
+        assert!(store.get_idempotency_record("w2").unwrap().is_none());
+        assert_eq!(store.purge_expired().expect("purge"), 1);
+    }
+
+    #[test]
+    fn records_expire_on_the_store_clock() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let clock = Arc::new(crate::ManualClock::starting_now());
+        let store = NodeStore::open(dir.path())
+            .expect("store")
+            .with_clock(clock.clone());
+
+        store
+            .put_idempotency_record("w1", "{}", Duration::from_secs(60))
+            .expect("record");
+        clock.advance(ChronoDuration::seconds(59));
+        assert!(store.get_idempotency_record("w1").unwrap().is_some());
+        assert_eq!(store.purge_expired().expect("purge"), 0);
+
+        clock.advance(ChronoDuration::seconds(2));
+        assert!(store.get_idempotency_record("w1").unwrap().is_none());
+        assert_eq!(store.purge_expired().expect("purge"), 1);
+    }
+
+    #[test]
+    fn repair_history_is_listed_and_summed_per_source() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let clock = Arc::new(crate::ManualClock::starting_now());
+        let store = NodeStore::open(dir.path())
+            .expect("store")
+            .with_clock(clock.clone());
+        let attempt = |source_kind: &str, ok: bool| RepairAttempt {
+            slot_id: 3,
+            blob_path: "a/b".to_string(),
+            generation: 1,
+            part_no: 0,
+            source_kind: source_kind.to_string(),
+            source: "node-2".to_string(),
+            bytes: if ok { 10 } else { 0 },
+            duration_ms: 5,
+            ok,
+            error: (!ok).then(|| "timeout".to_string()),
+        };
+
+        let started = crate::Clock::now(clock.as_ref());
+        store
+            .record_repair(&attempt("peer", false))
+            .expect("record");
+        store.record_repair(&attempt("peer", true)).expect("record");
+        store
+            .record_repair(&attempt("archive", true))
+            .expect("record");
+
+        let repairs = store.list_repairs(None, false, 10).expect("list");
+        assert_eq!(repairs.len(), 3);
+        assert_eq!(repairs[0].attempt.source_kind, "archive");
+        let older = store
+            .list_repairs(Some(repairs[1].repair_id), false, 10)
+            .expect("page");
+        assert_eq!(older.len(), 1);
+        let failed = store.list_repairs(None, true, 10).expect("failed");
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempt.error.as_deref(), Some("timeout"));
+
+        let stats = store.repair_stats(started).expect("stats");
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[1].source_kind, "peer");
+        assert_eq!((stats[1].attempts, stats[1].failed), (2, 1));
+        assert_eq!(stats[1].bytes, 10);
+
+        clock.advance(ChronoDuration::days(REPAIR_HISTORY_RETENTION_DAYS + 1));
+        store.purge_expired().expect("purge");
+        assert!(store.list_repairs(None, false, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn prepared_transactions_are_kept_until_decided() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let clock = Arc::new(crate::ManualClock::starting_now());
+        let store = NodeStore::open(dir.path())
+            .expect("store")
+            .with_clock(clock.clone());
+        let record = |txn_id: &str, state: &str| TransactionRecord {
+            txn_id: txn_id.to_string(),
+            slot_id: 3,
+            state: state.to_string(),
+            payload: serde_json::json!({"heads": []}),
+            recorded_at: crate::Clock::now(clock.as_ref()),
+        };
+
+        store
+            .save_transaction_record(&record("txn-1", "prepared"))
+            .expect("prepare");
+        store
+            .save_transaction_record(&record("txn-2", "prepared"))
+            .expect("prepare");
+        store
+            .save_transaction_record(&record("txn-2", "committed"))
+            .expect("commit");
+        store
+            .save_transaction_decision("txn-2", "committed")
+            .expect("decision");
+
+        let prepared = store
+            .list_transaction_records(None, Some("prepared"))
+            .expect("list");
+        assert_eq!(prepared, vec![record("txn-1", "prepared")]);
+        assert_eq!(
+            store.get_transaction_decision("txn-2").unwrap().as_deref(),
+            Some("committed")
+        );
+
+        clock.advance(ChronoDuration::days(TRANSACTION_LOG_RETENTION_DAYS + 1));
+        store.purge_expired().expect("purge");
+        assert!(
+            store
+                .list_transaction_records(Some("txn-2"), None)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(store.get_transaction_decision("txn-2").unwrap().is_none());
+        assert_eq!(
+            store
+                .list_transaction_records(Some("txn-1"), None)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn repairs_are_parked_after_repeated_failures() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = NodeStore::open(dir.path()).expect("store");
+
+        assert!(!store.record_repair_failure(3, "a/b", 2, "gone", 2).unwrap());
+        assert!(store.parked_repair(3, "a/b", 2).unwrap().is_none());
+        assert!(store.record_repair_failure(3, "a/b", 2, "gone", 2).unwrap());
+
+        let parked = store.parked_repair(3, "a/b", 2).unwrap().expect("parked");
+        assert_eq!(parked.failures, 2);
+        assert_eq!(store.list_dead_letters(10).unwrap(), vec![parked]);
+        assert!(store.parked_repair(3, "a/b", 3).unwrap().is_none());
+
+        assert_eq!(store.release_dead_letters(3, "a/b").unwrap(), 1);
+        assert!(store.list_dead_letters(10).unwrap().is_empty());
+
+        store.record_repair_failure(3, "a/c", 1, "gone", 1).unwrap();
+        store.clear_repair_failures(3, "a/c", 1).unwrap();
+        assert!(store.list_dead_letters(10).unwrap().is_empty());
+    }
+}
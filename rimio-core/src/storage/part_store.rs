@@ -1,5 +1,6 @@
 use crate::error::{Result, RimError};
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 use tokio::fs;
@@ -11,16 +12,65 @@ pub struct PutPartResult {
     pub reused: bool,
 }
 
+/// Serves small parts from a read-only memory map instead of reading them
+/// into a fresh buffer, so concurrent reads of a hot part share the page
+/// cache rather than each holding a copy.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PartMmapConfig {
+    /// Larger parts are read into a buffer as usual.
+    #[serde(default = "default_mmap_max_part_bytes")]
+    pub max_part_bytes: u64,
+    #[serde(default)]
+    pub advice: PartMmapAdvice,
+}
+
+impl Default for PartMmapConfig {
+    fn default() -> Self {
+        Self {
+            max_part_bytes: default_mmap_max_part_bytes(),
+            advice: PartMmapAdvice::default(),
+        }
+    }
+}
+
+fn default_mmap_max_part_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+/// `madvise` hint applied to every mapped part.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartMmapAdvice {
+    Normal,
+    /// Read ahead aggressively; suits whole-object downloads.
+    Sequential,
+    /// Fault the whole part in as soon as it is mapped.
+    #[default]
+    WillNeed,
+}
+
 /// PartStore stores external blob data as indexed part files:
-/// `slots/{slot_id}/blobs/{blob_path}/g.{generation}/part.{index:08}.{sha256}`.
+/// `slots/{slot_id}/parts/{h[0..2]}/{h[2..4]}/{h}/g.{generation}/part.{index:08}.{sha256}`,
+/// where `h` is the sha256 of the normalized blob path. Hashing keeps the
+/// tree two levels deep with bounded name lengths however long the keys are.
 pub struct PartStore {
     base_path: PathBuf,
+    mmap: Option<PartMmapConfig>,
 }
 
 impl PartStore {
     pub fn new(base_path: PathBuf) -> Result<Self> {
         std::fs::create_dir_all(&base_path)?;
-        Ok(Self { base_path })
+        Ok(Self {
+            base_path,
+            mmap: None,
+        })
+    }
+
+    /// Enables memory-mapped reads of small parts.
+    pub fn with_mmap(mut self, config: Option<PartMmapConfig>) -> Self {
+        self.mmap = config;
+        self
     }
 
     pub fn base_path(&self) -> &Path {
@@ -64,6 +114,40 @@ impl PartStore {
         })
     }
 
+    /// Starts a part whose content arrives in chunks. The part is written to
+    /// a staging file and only gets its final name, which carries its sha256,
+    /// in [`PartWriter::finish`].
+    pub async fn begin_part(
+        &self,
+        slot_id: u16,
+        blob_path: &str,
+        generation: i64,
+        part_no: u32,
+    ) -> Result<PartWriter> {
+        let dir = self.generation_dir(slot_id, blob_path, generation)?;
+        Self::begin_part_in(dir, part_no).await
+    }
+
+    async fn begin_part_in(dir: PathBuf, part_no: u32) -> Result<PartWriter> {
+        fs::create_dir_all(&dir).await?;
+
+        let staging_path = dir.join(format!(
+            "{}.{}.tmp",
+            Self::part_file_name(part_no, "staging"),
+            ulid::Ulid::new()
+        ));
+        let file = fs::File::create(&staging_path).await?;
+
+        Ok(PartWriter {
+            final_dir: dir,
+            staging_path,
+            file,
+            hasher: Sha256::new(),
+            part_no,
+            len: 0,
+        })
+    }
+
     pub async fn get_part(
         &self,
         slot_id: u16,
@@ -80,6 +164,16 @@ impl PartStore {
             )));
         }
 
+        if let Some(config) = self.mmap {
+            let path = part_path.clone();
+            let mapped = tokio::task::spawn_blocking(move || map_part(&path, config))
+                .await
+                .map_err(|error| RimError::Internal(error.to_string()))??;
+            if let Some(bytes) = mapped {
+                return Ok(bytes);
+            }
+        }
+
         let bytes = fs::read(part_path).await?;
         Ok(Bytes::from(bytes))
     }
@@ -120,15 +214,15 @@ impl PartStore {
     }
 
     pub fn blob_dir(&self, slot_id: u16, blob_path: &str) -> Result<PathBuf> {
-        let mut path = self
+        let hash = compute_hash(normalize_blob_path(blob_path)?.as_bytes());
+        Ok(self
             .base_path
             .join("slots")
             .join(slot_id.to_string())
-            .join("blobs");
-        for component in normalize_blob_path(blob_path)?.split('/') {
-            path.push(component);
-        }
-        Ok(path)
+            .join(PARTS_DIR)
+            .join(&hash[0..2])
+            .join(&hash[2..4])
+            .join(hash))
     }
 
     pub fn generation_dir(
@@ -145,34 +239,321 @@ impl PartStore {
     pub fn part_file_name(part_no: u32, sha256: &str) -> String {
         format!("part.{:08}.{}", part_no, sha256)
     }
+
+    /// Directory holding the parts of an unfinished multipart upload.
+    pub fn upload_dir(&self, upload_id: &str) -> PathBuf {
+        self.base_path.join(UPLOADS_DIR).join(upload_id)
+    }
+
+    /// Starts a part of a multipart upload. Finishing it drops any earlier
+    /// upload of the same part number.
+    pub async fn begin_upload_part(&self, upload_id: &str, part_no: u32) -> Result<PartWriter> {
+        Self::begin_part_in(self.upload_dir(upload_id), part_no).await
+    }
+
+    /// Removes every stored copy of `part_no` in an upload except `keep_sha256`.
+    pub async fn drop_stale_upload_parts(
+        &self,
+        upload_id: &str,
+        part_no: u32,
+        keep_sha256: &str,
+    ) -> Result<()> {
+        let prefix = format!("part.{:08}.", part_no);
+        let keep = Self::part_file_name(part_no, keep_sha256);
+        let mut entries = fs::read_dir(self.upload_dir(upload_id)).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(&prefix) && !name.ends_with(".tmp") && name != keep {
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves an uploaded part into the generation it is completed as. A part
+    /// already moved by an interrupted earlier attempt is accepted as is.
+    pub async fn adopt_upload_part(
+        &self,
+        upload_id: &str,
+        slot_id: u16,
+        blob_path: &str,
+        generation: i64,
+        part_no: u32,
+        sha256: &str,
+    ) -> Result<PutPartResult> {
+        let source = self
+            .upload_dir(upload_id)
+            .join(Self::part_file_name(part_no, sha256));
+        let part_path = self.part_path(slot_id, blob_path, generation, part_no, sha256)?;
+        if let Some(parent) = part_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        match fs::rename(&source, &part_path).await {
+            Ok(()) => Ok(PutPartResult {
+                part_path,
+                reused: false,
+            }),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound && part_path.exists() => {
+                Ok(PutPartResult {
+                    part_path,
+                    reused: true,
+                })
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub async fn remove_upload(&self, upload_id: &str) -> Result<()> {
+        match fs::remove_dir_all(self.upload_dir(upload_id)).await {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Ids of every upload with parts on disk.
+    pub async fn upload_ids(&self) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        let mut entries = match fs::read_dir(self.base_path.join(UPLOADS_DIR)).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(ids),
+            Err(error) => return Err(error.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                ids.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        Ok(ids)
+    }
 }
 
-fn normalize_blob_path(input: &str) -> Result<String> {
-    let trimmed = input.trim_matches('/');
-    if trimmed.is_empty() {
-        return Err(RimError::InvalidRequest(
-            "blob path cannot be empty".to_string(),
-        ));
+/// A part file being written chunk by chunk; see [`PartStore::begin_part`].
+pub struct PartWriter {
+    final_dir: PathBuf,
+    staging_path: PathBuf,
+    file: fs::File,
+    hasher: Sha256,
+    part_no: u32,
+    len: u64,
+}
+
+impl PartWriter {
+    pub fn part_no(&self) -> u32 {
+        self.part_no
     }
 
-    let mut parts = Vec::new();
-    for part in trimmed.split('/') {
-        if part.is_empty() || part == "." || part == ".." {
-            return Err(RimError::InvalidRequest(format!(
-                "invalid blob path component: {}",
-                part
-            )));
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.file.write_all(data).await?;
+        self.hasher.update(data);
+        self.len += data.len() as u64;
+        Ok(())
+    }
+
+    /// Syncs the part and moves it to its final name. Returns the part's
+    /// sha256 with the usual put result.
+    pub async fn finish(mut self) -> Result<(String, PutPartResult)> {
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+        drop(self.file);
+
+        let sha256 = hex::encode(self.hasher.finalize());
+        let part_path = self
+            .final_dir
+            .join(PartStore::part_file_name(self.part_no, &sha256));
+        if part_path.exists() {
+            fs::remove_file(&self.staging_path).await?;
+            return Ok((
+                sha256,
+                PutPartResult {
+                    part_path,
+                    reused: true,
+                },
+            ));
+        }
+
+        fs::rename(&self.staging_path, &part_path).await?;
+        Ok((
+            sha256,
+            PutPartResult {
+                part_path,
+                reused: false,
+            },
+        ))
+    }
+
+    /// Drops the staging file of a part that will not be finished.
+    pub async fn abort(self) {
+        drop(self.file);
+        let _ = fs::remove_file(&self.staging_path).await;
+    }
+}
+
+/// Maps a part read-only, or returns `None` when it is empty or larger than
+/// the configured threshold.
+fn map_part(path: &Path, config: PartMmapConfig) -> Result<Option<Bytes>> {
+    let file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    if len == 0 || len > config.max_part_bytes {
+        return Ok(None);
+    }
+
+    // SAFETY: part files are written under a staging name and renamed into
+    // place, then never modified; deletes and scrub quarantines unlink or
+    // rename them, which leaves an existing mapping intact.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    #[cfg(unix)]
+    {
+        let advice = match config.advice {
+            PartMmapAdvice::Normal => memmap2::Advice::Normal,
+            PartMmapAdvice::Sequential => memmap2::Advice::Sequential,
+            PartMmapAdvice::WillNeed => memmap2::Advice::WillNeed,
+        };
+        if let Err(error) = map.advise(advice) {
+            tracing::debug!("madvise failed: path={} error={}", path.display(), error);
+        }
+    }
+
+    Ok(Some(Bytes::from_owner(map)))
+}
+
+const PARTS_DIR: &str = "parts";
+const UPLOADS_DIR: &str = "uploads";
+const LEGACY_BLOBS_DIR: &str = "blobs";
+
+/// Moves every part from the legacy `slots/{slot_id}/blobs/{blob_path}/...`
+/// tree into the hashed layout and returns how many parts were moved.
+///
+/// Parts are renamed one at a time, so an interrupted run resumes where it
+/// stopped. Staging files of unfinished writes are dropped, and legacy
+/// directories are removed once empty.
+pub fn migrate_legacy_part_dirs(data_dir: &Path) -> Result<u64> {
+    let store = PartStore::new(data_dir.to_path_buf())?;
+    let slots_dir = data_dir.join("slots");
+    if !slots_dir.exists() {
+        return Ok(0);
+    }
+
+    let mut moved = 0u64;
+    for entry in std::fs::read_dir(&slots_dir)? {
+        let entry = entry?;
+        let Some(slot_id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u16>().ok())
+        else {
+            continue;
+        };
+
+        let legacy_dir = entry.path().join(LEGACY_BLOBS_DIR);
+        if !legacy_dir.is_dir() {
+            continue;
+        }
+
+        let mut pending = vec![(legacy_dir.clone(), Vec::<String>::new())];
+        while let Some((dir, components)) = pending.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                if !entry.file_type()?.is_dir() {
+                    continue;
+                }
+
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let generation = name
+                    .strip_prefix("g.")
+                    .and_then(|value| value.parse::<i64>().ok());
+                if let Some(generation) = generation
+                    && !components.is_empty()
+                {
+                    let target =
+                        store.generation_dir(slot_id, &components.join("/"), generation)?;
+                    moved += move_part_files(&entry.path(), &target)?;
+                }
+
+                // A generation directory may also hold deeper blob paths
+                // (e.g. `a/g.1/b`), so it is walked like any other.
+                let mut child = components.clone();
+                child.push(name);
+                pending.push((entry.path(), child));
+            }
+        }
+
+        let leftover = prune_empty_dirs(&legacy_dir)?;
+        if leftover > 0 {
+            tracing::warn!(
+                "legacy part directory not empty after migration: dir={} files={}",
+                legacy_dir.display(),
+                leftover
+            );
+        }
+    }
+
+    Ok(moved)
+}
+
+fn move_part_files(from: &Path, to: &Path) -> Result<u64> {
+    let mut moved = 0u64;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "tmp") {
+            std::fs::remove_file(&path)?;
+            continue;
+        }
+
+        std::fs::create_dir_all(to)?;
+        let target = to.join(entry.file_name());
+        if target.exists() {
+            // Part names carry their sha256, so an existing target holds the
+            // same bytes.
+            std::fs::remove_file(&path)?;
+        } else {
+            std::fs::rename(&path, &target)?;
+            moved += 1;
+        }
+    }
+    Ok(moved)
+}
+
+/// Removes empty directories under and including `dir`; returns how many
+/// files were left behind.
+fn prune_empty_dirs(dir: &Path) -> Result<u64> {
+    let mut leftover = 0u64;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            leftover += prune_empty_dirs(&entry.path())?;
+        } else {
+            leftover += 1;
         }
-        parts.push(part);
     }
+    if leftover == 0 {
+        std::fs::remove_dir(dir)?;
+    }
+    Ok(leftover)
+}
 
-    Ok(parts.join("/"))
+/// [`rimio_chunk::normalize_blob_path`] with its error as a request error.
+pub fn normalize_blob_path(path: &str) -> Result<String> {
+    rimio_chunk::normalize_blob_path(path)
+        .map_err(|error| RimError::InvalidRequest(error.to_string()))
 }
 
 pub fn compute_hash(data: &[u8]) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    hex::encode(hasher.finalize())
+    rimio_chunk::sha256_hex(data)
 }
 
 pub fn verify_hash(data: &[u8], expected_hash: &str) -> Result<()> {
@@ -186,6 +567,29 @@ pub fn verify_hash(data: &[u8], expected_hash: &str) -> Result<()> {
     Ok(())
 }
 
+/// Overwrites a part file with random bytes and syncs it, so the blocks it
+/// held no longer carry the data once it is unlinked. A file already gone
+/// counts as shredded.
+pub async fn shred_part_file(path: &Path) -> Result<()> {
+    const CHUNK_BYTES: usize = 1024 * 1024;
+
+    let mut file = match fs::OpenOptions::new().write(true).open(path).await {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    let mut remaining = file.metadata().await?.len();
+    let mut buffer = vec![0u8; CHUNK_BYTES];
+    while remaining > 0 {
+        let chunk = remaining.min(CHUNK_BYTES as u64) as usize;
+        rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut buffer[..chunk]);
+        file.write_all(&buffer[..chunk]).await?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all().await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -225,4 +629,98 @@ mod tests {
         store.delete_blob_parts(slot_id, blob_path).await.unwrap();
         assert!(!store.part_exists(slot_id, blob_path, generation, part_no, &sha));
     }
+
+    #[tokio::test]
+    async fn test_part_writer_streams_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = PartStore::new(dir.path().to_path_buf()).unwrap();
+
+        let mut writer = store.begin_part(7, "a/big.bin", 1, 0).await.unwrap();
+        writer.write(b"hello-").await.unwrap();
+        writer.write(b"world").await.unwrap();
+        assert_eq!(writer.len(), 11);
+
+        let (sha, put) = writer.finish().await.unwrap();
+        assert_eq!(sha, compute_hash(b"hello-world"));
+        assert!(!put.reused);
+        let read = store.get_part(7, "a/big.bin", 1, 0, &sha).await.unwrap();
+        assert_eq!(read, Bytes::from("hello-world"));
+
+        let generation_dir = store.generation_dir(7, "a/big.bin", 1).unwrap();
+        assert_eq!(std::fs::read_dir(generation_dir).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_mmap_reads_small_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = PartStore::new(dir.path().to_path_buf())
+            .unwrap()
+            .with_mmap(Some(PartMmapConfig {
+                max_part_bytes: 8,
+                advice: PartMmapAdvice::Sequential,
+            }));
+
+        for body in [Bytes::from("small"), Bytes::from("larger than eight")] {
+            let sha = compute_hash(&body);
+            store
+                .put_part(1, "fw.bin", 1, 0, &sha, body.clone())
+                .await
+                .unwrap();
+            let read = store.get_part(1, "fw.bin", 1, 0, &sha).await.unwrap();
+            assert_eq!(read, body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_legacy_part_dirs_migrate_to_hashed_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("slots").join("3").join("blobs");
+        let parent = compute_hash(b"parent");
+        let child = compute_hash(b"child");
+        std::fs::create_dir_all(legacy.join("a").join("g.1").join("b").join("g.2")).unwrap();
+        std::fs::write(
+            legacy
+                .join("a")
+                .join("g.1")
+                .join(PartStore::part_file_name(0, &parent)),
+            b"parent",
+        )
+        .unwrap();
+        std::fs::write(legacy.join("a").join("g.1").join("part.x.tmp"), b"").unwrap();
+        std::fs::write(
+            legacy
+                .join("a")
+                .join("g.1")
+                .join("b")
+                .join("g.2")
+                .join(PartStore::part_file_name(0, &child)),
+            b"child",
+        )
+        .unwrap();
+
+        assert_eq!(migrate_legacy_part_dirs(dir.path()).unwrap(), 2);
+        assert!(!legacy.exists());
+
+        let store = PartStore::new(dir.path().to_path_buf()).unwrap();
+        let read = store.get_part(3, "a", 1, 0, &parent).await.unwrap();
+        assert_eq!(read, Bytes::from("parent"));
+        let read = store.get_part(3, "a/g.1/b", 2, 0, &child).await.unwrap();
+        assert_eq!(read, Bytes::from("child"));
+        assert_eq!(migrate_legacy_part_dirs(dir.path()).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn shredding_overwrites_a_part_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("part.00000000.aa");
+        let body = vec![7u8; 3 * 1024 * 1024 + 5];
+        std::fs::write(&path, &body).unwrap();
+
+        shred_part_file(&path).await.unwrap();
+        let shredded = std::fs::read(&path).unwrap();
+        assert_eq!(shredded.len(), body.len());
+        assert_ne!(shredded, body);
+
+        shred_part_file(&dir.path().join("missing")).await.unwrap();
+    }
 }
@@ -0,0 +1,329 @@
+use crate::{HostPressureMonitor, NodeStore, Result, SharedClock, compute_hash, system_clock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone)]
+pub struct ScrubConfig {
+    /// Pause between the end of one pass over a disk and the next.
+    pub interval: Duration,
+    /// Read budget of each disk's stream, so a pass never saturates a spindle.
+    pub bytes_per_sec: u64,
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(24 * 3600),
+            bytes_per_sec: 32 * 1024 * 1024,
+        }
+    }
+}
+
+/// Progress of the scrub stream of one disk.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiskScrubReport {
+    pub disk: String,
+    pub running: bool,
+    pub passes: u64,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub parts_checked: u64,
+    pub bytes_checked: u64,
+    /// Parts quarantined in the current (or last) pass.
+    pub corrupt_parts: Vec<String>,
+}
+
+/// Verifies part files against the sha256 in their names, one stream per
+/// disk, each paced to its own byte rate.
+///
+/// A corrupt part is renamed to `*.corrupt`; reads then miss it locally and
+/// refetch it from peers or the archive.
+pub struct ScrubScheduler {
+    disks: Vec<PathBuf>,
+    node_store: Arc<NodeStore>,
+    config: ScrubConfig,
+    reports: RwLock<HashMap<PathBuf, DiskScrubReport>>,
+    host_pressure: Option<Arc<HostPressureMonitor>>,
+    clock: SharedClock,
+}
+
+/// How soon a pass skipped under host pressure is retried.
+const PRESSURE_RETRY: Duration = Duration::from_secs(60);
+
+impl ScrubScheduler {
+    pub fn new(disks: Vec<PathBuf>, node_store: Arc<NodeStore>, config: ScrubConfig) -> Self {
+        let reports = disks
+            .iter()
+            .map(|disk| {
+                let report = node_store
+                    .get_setting(&report_key(disk))
+                    .ok()
+                    .flatten()
+                    .and_then(|raw| serde_json::from_str::<DiskScrubReport>(&raw).ok())
+                    .map(|report| DiskScrubReport {
+                        running: false,
+                        ..report
+                    })
+                    .unwrap_or_else(|| DiskScrubReport {
+                        disk: disk.display().to_string(),
+                        ..DiskScrubReport::default()
+                    });
+                (disk.clone(), report)
+            })
+            .collect();
+
+        Self {
+            disks,
+            node_store,
+            config,
+            reports: RwLock::new(reports),
+            host_pressure: None,
+            clock: system_clock(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Postpones disk passes while [`HostPressureMonitor::sheds_background`]
+    /// holds, checking again every minute.
+    pub fn with_host_pressure(mut self, host_pressure: Option<Arc<HostPressureMonitor>>) -> Self {
+        self.host_pressure = host_pressure;
+        self
+    }
+
+    pub fn start(self: Arc<Self>) {
+        for disk in self.disks.clone() {
+            let scheduler = self.clone();
+            tokio::spawn(async move {
+                // Resume the schedule of the last pass instead of rescanning on
+                // every restart.
+                let last_pass = scheduler
+                    .reports
+                    .read()
+                    .await
+                    .get(&disk)
+                    .and_then(|report| report.finished_at);
+                if let Some(elapsed) =
+                    last_pass.and_then(|at| (scheduler.clock.now() - at).to_std().ok())
+                    && let Some(wait) = scheduler.config.interval.checked_sub(elapsed)
+                {
+                    tokio::time::sleep(wait).await;
+                }
+
+                loop {
+                    if scheduler
+                        .host_pressure
+                        .as_ref()
+                        .is_some_and(|pressure| pressure.sheds_background())
+                    {
+                        tokio::time::sleep(PRESSURE_RETRY).await;
+                        continue;
+                    }
+                    if let Err(error) = scheduler.scrub_disk(&disk).await {
+                        tracing::warn!("scrub failed: disk={} error={}", disk.display(), error);
+                    }
+                    tokio::time::sleep(scheduler.config.interval).await;
+                }
+            });
+        }
+    }
+
+    pub async fn reports(&self) -> Vec<DiskScrubReport> {
+        let reports = self.reports.read().await;
+        self.disks
+            .iter()
+            .filter_map(|disk| reports.get(disk).cloned())
+            .collect()
+    }
+
+    /// Runs one full pass over a disk.
+    pub async fn scrub_disk(&self, disk: &Path) -> Result<DiskScrubReport> {
+        self.update(disk, |report| {
+            report.running = true;
+            report.started_at = Some(self.clock.now());
+            report.finished_at = None;
+            report.parts_checked = 0;
+            report.bytes_checked = 0;
+            report.corrupt_parts.clear();
+        })
+        .await;
+
+        let result = self.scrub_parts(disk).await;
+
+        let report = self
+            .update(disk, |report| {
+                report.running = false;
+                if result.is_ok() {
+                    report.passes += 1;
+                    report.finished_at = Some(self.clock.now());
+                }
+            })
+            .await;
+        result?;
+
+        self.node_store
+            .set_setting(&report_key(disk), &serde_json::to_string(&report)?)?;
+        if !report.corrupt_parts.is_empty() {
+            tracing::warn!(
+                "scrub quarantined corrupt parts: disk={} count={}",
+                disk.display(),
+                report.corrupt_parts.len()
+            );
+        }
+
+        Ok(report)
+    }
+
+    async fn scrub_parts(&self, disk: &Path) -> Result<()> {
+        let started = Instant::now();
+        let mut budget_used = 0u64;
+        let mut pending = vec![disk.join("slots")];
+
+        while let Some(dir) = pending.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let Some(expected) = part_sha256(&path) else {
+                    continue;
+                };
+
+                let data = match tokio::fs::read(&path).await {
+                    Ok(data) => data,
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(error) => return Err(error.into()),
+                };
+
+                let corrupt = compute_hash(&data) != expected;
+                if corrupt {
+                    tokio::fs::rename(&path, path.with_extension(format!("{}.corrupt", expected)))
+                        .await?;
+                }
+
+                let size = data.len() as u64;
+                self.update(disk, |report| {
+                    report.parts_checked += 1;
+                    report.bytes_checked += size;
+                    if corrupt {
+                        report.corrupt_parts.push(path.display().to_string());
+                    }
+                })
+                .await;
+
+                budget_used += size;
+                self.pace(started, budget_used).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sleeps until `bytes` fit into the disk's byte rate since `started`.
+    async fn pace(&self, started: Instant, bytes: u64) {
+        if self.config.bytes_per_sec == 0 {
+            return;
+        }
+
+        let due = Duration::from_secs_f64(bytes as f64 / self.config.bytes_per_sec as f64);
+        if let Some(wait) = due.checked_sub(started.elapsed()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    async fn update(
+        &self,
+        disk: &Path,
+        apply: impl FnOnce(&mut DiskScrubReport),
+    ) -> DiskScrubReport {
+        let mut reports = self.reports.write().await;
+        let report = reports
+            .entry(disk.to_path_buf())
+            .or_insert_with(|| DiskScrubReport {
+                disk: disk.display().to_string(),
+                ..DiskScrubReport::default()
+            });
+        apply(report);
+        report.clone()
+    }
+}
+
+fn report_key(disk: &Path) -> String {
+    format!("scrub.report:{}", disk.display())
+}
+
+/// Returns the sha256 of a committed part file (`part.{index}.{sha256}`);
+/// staging and quarantined files have extra name components.
+fn part_sha256(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let mut components = name.split('.');
+    let (Some("part"), Some(_), Some(sha256), None) = (
+        components.next(),
+        components.next(),
+        components.next(),
+        components.next(),
+    ) else {
+        return None;
+    };
+
+    Some(sha256.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PartStore;
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn scrub_quarantines_corrupt_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let part_store = PartStore::new(dir.path().to_path_buf()).unwrap();
+        let good = Bytes::from_static(b"good part");
+        let bad = Bytes::from_static(b"bad part");
+
+        part_store
+            .put_part(1, "a/b", 1, 0, &compute_hash(&good), good.clone())
+            .await
+            .unwrap();
+        let bad_path = part_store
+            .put_part(1, "a/b", 1, 1, &compute_hash(&bad), bad.clone())
+            .await
+            .unwrap()
+            .part_path;
+        std::fs::write(&bad_path, b"flipped").unwrap();
+
+        let node_store = Arc::new(NodeStore::open(dir.path()).unwrap());
+        let scheduler = ScrubScheduler::new(
+            vec![dir.path().to_path_buf()],
+            node_store,
+            ScrubConfig::default(),
+        );
+
+        let report = scheduler.scrub_disk(dir.path()).await.unwrap();
+        assert_eq!(report.parts_checked, 2);
+        assert_eq!(report.corrupt_parts.len(), 1);
+        assert_eq!(report.passes, 1);
+        assert!(!bad_path.exists());
+
+        let again = scheduler.scrub_disk(dir.path()).await.unwrap();
+        assert_eq!(again.parts_checked, 1);
+        assert!(again.corrupt_parts.is_empty());
+    }
+}
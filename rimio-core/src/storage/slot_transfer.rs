@@ -0,0 +1,534 @@
+//! Bulk copy of a slot to a new replica.
+//!
+//! The source exports a consistent copy of the slot database and lists it,
+//! together with the slot's part files, in a manifest. The target downloads
+//! each file into `incoming/` under its slot directory, keeping a checkpoint
+//! of the files it finished so an interrupted transfer resumes where it
+//! stopped. Every file is checked against its sha256, the file list against
+//! the manifest digest, and only then is the copy moved into place. Writes
+//! made after the export are left to heal.
+
+use crate::{MetadataStore, Result, RimError, Slot};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the exported slot database in a transfer.
+pub const SLOT_TRANSFER_DB_FILE: &str = "meta.sqlite3";
+
+const TRANSFERS_DIR: &str = "transfers";
+const INCOMING_DIR: &str = "incoming";
+const MANIFEST_FILE: &str = "manifest.json";
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+/// Exports a source keeps per slot; older ones are removed on the next.
+const TRANSFER_EXPORT_RETENTION: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotTransferFile {
+    /// Path relative to the slot directory.
+    pub name: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotTransferManifest {
+    pub transfer_id: String,
+    pub slot_id: u16,
+    /// Slot directory on the source; part paths in the database start here.
+    pub source_root: String,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<SlotTransferFile>,
+    /// sha256 over every file's name, size and sha256, in manifest order.
+    pub digest: String,
+}
+
+impl SlotTransferManifest {
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|file| file.size_bytes).sum()
+    }
+
+    pub fn file(&self, name: &str) -> Option<&SlotTransferFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    /// Checks the file list against the digest and that every name stays
+    /// inside the slot directory.
+    pub fn verify(&self) -> Result<()> {
+        for file in &self.files {
+            validate_name(&file.name)?;
+        }
+        let actual = manifest_digest(&self.files);
+        if actual != self.digest {
+            return Err(RimError::HashMismatch {
+                expected: self.digest.clone(),
+                actual,
+            });
+        }
+        if self.file(SLOT_TRANSFER_DB_FILE).is_none() {
+            return Err(RimError::InvalidRequest(format!(
+                "slot transfer {} has no database",
+                self.transfer_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub fn manifest_digest(files: &[SlotTransferFile]) -> String {
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(format!(
+            "{} {} {}\n",
+            file.name, file.size_bytes, file.sha256
+        ));
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Exports `slot` for a transfer: a copy of its database plus the part files
+/// present once the copy was taken, which covers every part it references.
+pub fn export_slot(store: &MetadataStore, slot: &Slot) -> Result<SlotTransferManifest> {
+    let transfer_id = format!("xfer-{}", ulid::Ulid::new());
+    let dir = slot.data_path.join(TRANSFERS_DIR).join(&transfer_id);
+    std::fs::create_dir_all(&dir)?;
+
+    let db_copy = dir.join(SLOT_TRANSFER_DB_FILE);
+    store.export_to(&db_copy)?;
+    let mut files = vec![SlotTransferFile {
+        name: SLOT_TRANSFER_DB_FILE.to_string(),
+        size_bytes: std::fs::metadata(&db_copy)?.len(),
+        sha256: file_sha256(&db_copy)?,
+    }];
+    list_part_files(&slot.data_path, &slot.parts_dir(), &mut files)?;
+
+    let manifest = SlotTransferManifest {
+        transfer_id,
+        slot_id: slot.slot_id,
+        source_root: slot.data_path.to_string_lossy().into_owned(),
+        created_at: Utc::now(),
+        digest: manifest_digest(&files),
+        files,
+    };
+    std::fs::write(dir.join(MANIFEST_FILE), serde_json::to_vec(&manifest)?)?;
+    prune_exports(&slot.data_path.join(TRANSFERS_DIR))?;
+
+    tracing::info!(
+        "slot exported for transfer: slot={} transfer={} files={} bytes={}",
+        slot.slot_id,
+        manifest.transfer_id,
+        manifest.files.len(),
+        manifest.total_bytes()
+    );
+    Ok(manifest)
+}
+
+/// Returns an export made earlier by [`export_slot`], if it is still kept.
+pub fn load_export(slot: &Slot, transfer_id: &str) -> Result<Option<SlotTransferManifest>> {
+    validate_name(transfer_id)?;
+    let path = slot
+        .data_path
+        .join(TRANSFERS_DIR)
+        .join(transfer_id)
+        .join(MANIFEST_FILE);
+    match std::fs::read(path) {
+        Ok(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Where the source reads `name` of an export from. Only files listed in the
+/// manifest are served.
+pub fn export_file_path(
+    slot: &Slot,
+    manifest: &SlotTransferManifest,
+    name: &str,
+) -> Result<PathBuf> {
+    if manifest.file(name).is_none() {
+        return Err(RimError::PartNotFound(format!(
+            "{} is not part of slot transfer {}",
+            name, manifest.transfer_id
+        )));
+    }
+    if name == SLOT_TRANSFER_DB_FILE {
+        return Ok(slot
+            .data_path
+            .join(TRANSFERS_DIR)
+            .join(&manifest.transfer_id)
+            .join(SLOT_TRANSFER_DB_FILE));
+    }
+    Ok(slot.data_path.join(name))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SlotTransferCheckpoint {
+    source_node_id: String,
+    manifest: SlotTransferManifest,
+    completed: BTreeSet<String>,
+}
+
+/// A transfer being received into `incoming/` of the target slot.
+pub struct SlotTransferStaging {
+    dir: PathBuf,
+    checkpoint: SlotTransferCheckpoint,
+}
+
+impl SlotTransferStaging {
+    /// Picks up the checkpoint of an interrupted transfer, if any.
+    pub fn resume(slot: &Slot) -> Result<Option<Self>> {
+        let dir = slot.data_path.join(INCOMING_DIR);
+        let raw = match std::fs::read(dir.join(CHECKPOINT_FILE)) {
+            Ok(raw) => raw,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let checkpoint: SlotTransferCheckpoint = serde_json::from_slice(&raw)?;
+        Ok(Some(Self { dir, checkpoint }))
+    }
+
+    /// Starts receiving `manifest` from `source_node_id`, dropping whatever
+    /// an earlier transfer left behind.
+    pub fn start(
+        slot: &Slot,
+        source_node_id: &str,
+        manifest: SlotTransferManifest,
+    ) -> Result<Self> {
+        manifest.verify()?;
+        Self::discard(slot)?;
+        let staging = Self {
+            dir: slot.data_path.join(INCOMING_DIR),
+            checkpoint: SlotTransferCheckpoint {
+                source_node_id: source_node_id.to_string(),
+                manifest,
+                completed: BTreeSet::new(),
+            },
+        };
+        std::fs::create_dir_all(&staging.dir)?;
+        staging.save()?;
+        Ok(staging)
+    }
+
+    pub fn discard(slot: &Slot) -> Result<()> {
+        match std::fs::remove_dir_all(slot.data_path.join(INCOMING_DIR)) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn source_node_id(&self) -> &str {
+        &self.checkpoint.source_node_id
+    }
+
+    pub fn manifest(&self) -> &SlotTransferManifest {
+        &self.checkpoint.manifest
+    }
+
+    /// Files not received and checked yet, in manifest order.
+    pub fn pending(&self) -> Vec<SlotTransferFile> {
+        self.checkpoint
+            .manifest
+            .files
+            .iter()
+            .filter(|file| !self.checkpoint.completed.contains(&file.name))
+            .cloned()
+            .collect()
+    }
+
+    /// Where `name` is downloaded to; appending to it resumes the download.
+    pub fn file_path(&self, name: &str) -> PathBuf {
+        self.dir.join("files").join(name)
+    }
+
+    /// Bytes of `name` already on disk.
+    pub fn received_bytes(&self, name: &str) -> u64 {
+        std::fs::metadata(self.file_path(name))
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
+
+    /// Checks a downloaded file against the manifest and records it in the
+    /// checkpoint. A file that does not match is removed so the next attempt
+    /// downloads it again from the start.
+    pub fn complete_file(&mut self, name: &str) -> Result<()> {
+        let expected = self
+            .checkpoint
+            .manifest
+            .file(name)
+            .cloned()
+            .ok_or_else(|| {
+                RimError::InvalidRequest(format!("{} is not part of the transfer", name))
+            })?;
+        let path = self.file_path(name);
+        let checked = match std::fs::metadata(&path)?.len() {
+            size if size != expected.size_bytes => Err(RimError::Internal(format!(
+                "slot transfer file {} has {} bytes, manifest expects {}",
+                name, size, expected.size_bytes
+            ))),
+            _ => match file_sha256(&path)? {
+                actual if actual != expected.sha256 => Err(RimError::HashMismatch {
+                    expected: expected.sha256.clone(),
+                    actual,
+                }),
+                _ => Ok(()),
+            },
+        };
+        if let Err(error) = checked {
+            std::fs::remove_file(&path)?;
+            return Err(error);
+        }
+
+        self.checkpoint.completed.insert(name.to_string());
+        self.save()
+    }
+
+    /// Moves the received files into the slot: parts first, then the
+    /// database, whose part paths are rebased onto this node. The slot must
+    /// not serve writes meanwhile.
+    pub fn install(self, store: &MetadataStore, slot: &Slot) -> Result<()> {
+        let manifest = &self.checkpoint.manifest;
+        manifest.verify()?;
+        if let Some(file) = self.pending().first() {
+            return Err(RimError::Internal(format!(
+                "slot transfer {} is missing {}",
+                manifest.transfer_id, file.name
+            )));
+        }
+
+        for file in &manifest.files {
+            if file.name == SLOT_TRANSFER_DB_FILE {
+                continue;
+            }
+            let target = slot.data_path.join(&file.name);
+            if target.exists() {
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::rename(self.file_path(&file.name), &target)?;
+        }
+
+        store.install_copy(&self.file_path(SLOT_TRANSFER_DB_FILE))?;
+        let local_root = slot.data_path.to_string_lossy();
+        if manifest.source_root != local_root {
+            store.rebase_part_paths(&manifest.source_root, &local_root)?;
+        }
+
+        std::fs::remove_dir_all(&self.dir)?;
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = self.dir.join(CHECKPOINT_FILE);
+        let staging = path.with_extension("json.tmp");
+        std::fs::write(&staging, serde_json::to_vec(&self.checkpoint)?)?;
+        std::fs::rename(staging, path)?;
+        Ok(())
+    }
+}
+
+/// Lists part files under `dir`, named relative to `root`. Staging files
+/// without a final `part.{index}.{sha256}` name are skipped.
+fn list_part_files(root: &Path, dir: &Path, files: &mut Vec<SlotTransferFile>) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    let mut entries: Vec<_> = entries.collect::<std::io::Result<_>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_part_files(root, &path, files)?;
+            continue;
+        }
+        let Some(sha256) = part_file_sha256(&entry.file_name().to_string_lossy()) else {
+            continue;
+        };
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let name = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push(SlotTransferFile {
+            name,
+            size_bytes: entry.metadata()?.len(),
+            sha256,
+        });
+    }
+    Ok(())
+}
+
+fn part_file_sha256(file_name: &str) -> Option<String> {
+    let mut fields = file_name.split('.');
+    let (Some("part"), Some(index), Some(sha256), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return None;
+    };
+    (index.bytes().all(|byte| byte.is_ascii_digit())
+        && sha256.len() == 64
+        && sha256.bytes().all(|byte| byte.is_ascii_hexdigit()))
+    .then(|| sha256.to_string())
+}
+
+/// Keeps the newest exports; their ids sort by creation time.
+fn prune_exports(dir: &Path) -> Result<()> {
+    let mut exports: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    exports.sort();
+    for stale in exports
+        .iter()
+        .take(exports.len().saturating_sub(TRANSFER_EXPORT_RETENTION))
+    {
+        std::fs::remove_dir_all(stale)?;
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let path = Path::new(name);
+    let plain = !name.is_empty()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !plain {
+        return Err(RimError::InvalidRequest(format!(
+            "invalid slot transfer file name: {}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlobMeta, ContentHeaders, PartIndexState, PartStore, SlotManager, compute_hash};
+    use bytes::Bytes;
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn transferred_slot_serves_its_parts_from_the_new_root() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let source_slots =
+            SlotManager::new("node-a".to_string(), source_dir.path().to_path_buf()).unwrap();
+        source_slots.init_slot(1).await.unwrap();
+        let source_slot = source_slots.get_slot(1).await.unwrap();
+        let source_store = MetadataStore::new(source_slot.clone()).unwrap();
+
+        let data = Bytes::from_static(b"abcd");
+        let sha256 = compute_hash(&data);
+        let part = PartStore::new(source_dir.path().to_path_buf())
+            .unwrap()
+            .put_part(1, "group/a", 1, 0, &sha256, data)
+            .await
+            .unwrap();
+        source_store
+            .upsert_part_entry(
+                "group/a",
+                1,
+                0,
+                &sha256,
+                4,
+                Some(&part.part_path.to_string_lossy()),
+                None,
+            )
+            .unwrap();
+        source_store
+            .upsert_meta(&BlobMeta {
+                path: "group/a".to_string(),
+                slot_id: 1,
+                generation: 1,
+                version: 1,
+                size_bytes: 4,
+                etag: sha256.clone(),
+                part_size: 4,
+                part_count: 1,
+                part_index_state: PartIndexState::Complete,
+                archive_url: None,
+                updated_at: Utc::now(),
+                metadata: BTreeMap::new(),
+                tags: BTreeMap::new(),
+                content: ContentHeaders::default(),
+            })
+            .unwrap();
+
+        let manifest = export_slot(&source_store, &source_slot).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        assert_eq!(manifest.total_bytes(), manifest.files[0].size_bytes + 4);
+
+        let mut tampered = manifest.clone();
+        tampered.files[1].size_bytes += 1;
+        let target_dir = tempfile::tempdir().unwrap();
+        let target_slots =
+            SlotManager::new("node-b".to_string(), target_dir.path().to_path_buf()).unwrap();
+        target_slots.init_slot(1).await.unwrap();
+        let target_slot = target_slots.get_slot(1).await.unwrap();
+        assert!(SlotTransferStaging::start(&target_slot, "node-a", tampered).is_err());
+
+        let mut staging =
+            SlotTransferStaging::start(&target_slot, "node-a", manifest.clone()).unwrap();
+        for file in staging.pending() {
+            let from = export_file_path(&source_slot, &manifest, &file.name).unwrap();
+            let to = staging.file_path(&file.name);
+            std::fs::create_dir_all(to.parent().unwrap()).unwrap();
+            if file.name != SLOT_TRANSFER_DB_FILE {
+                std::fs::write(&to, b"abcX").unwrap();
+                assert!(staging.complete_file(&file.name).is_err());
+                assert_eq!(staging.received_bytes(&file.name), 0);
+            }
+            std::fs::copy(from, &to).unwrap();
+            staging.complete_file(&file.name).unwrap();
+        }
+        assert!(staging.pending().is_empty());
+
+        let resumed = SlotTransferStaging::resume(&target_slot).unwrap().unwrap();
+        assert_eq!(resumed.source_node_id(), "node-a");
+        let target_store = MetadataStore::new(target_slot.clone()).unwrap();
+        resumed.install(&target_store, &target_slot).unwrap();
+
+        assert!(SlotTransferStaging::resume(&target_slot).unwrap().is_none());
+        assert_eq!(
+            target_store
+                .get_current_head("group/a")
+                .unwrap()
+                .unwrap()
+                .generation,
+            1
+        );
+        let part_path = target_store
+            .find_part_external_path(&sha256, Some("group/a"))
+            .unwrap()
+            .unwrap();
+        assert!(Path::new(&part_path).starts_with(&target_slot.data_path));
+        assert_eq!(std::fs::read(part_path).unwrap(), b"abcd");
+    }
+}
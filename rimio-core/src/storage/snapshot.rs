@@ -0,0 +1,330 @@
+//! Static datasets served read-only from a mounted directory.
+//!
+//! A snapshot is a `manifest.json` next to a `parts/` directory holding every
+//! part file under its sha256. It needs no slots, metadata databases or
+//! registry, so it can live on a read-only mount (e.g. squashfs).
+
+use crate::{ReadByteRange, Result, RimError, compute_hash};
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::SeekFrom;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const PARTS_DIR: &str = "parts";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub format_version: u32,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub blobs: Vec<SnapshotBlob>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotBlob {
+    pub path: String,
+    pub size_bytes: u64,
+    pub etag: String,
+    pub updated_at: DateTime<Utc>,
+    pub parts: Vec<SnapshotPart>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotPart {
+    pub sha256: String,
+    pub length: u64,
+}
+
+/// A snapshot opened for reading. The manifest is held in memory; part files
+/// are read on demand.
+pub struct MountedSnapshot {
+    root: PathBuf,
+    name: String,
+    created_at: DateTime<Utc>,
+    blobs: BTreeMap<String, SnapshotBlob>,
+}
+
+impl MountedSnapshot {
+    /// Loads the manifest and checks every referenced part is present with
+    /// the expected length, so a truncated mount fails at startup rather than
+    /// on the first read.
+    pub fn open(root: &Path) -> Result<Self> {
+        let raw = std::fs::read(root.join(MANIFEST_FILE)).map_err(|error| {
+            RimError::Config(format!(
+                "cannot read snapshot manifest in {}: {}",
+                root.display(),
+                error
+            ))
+        })?;
+        let manifest: SnapshotManifest = serde_json::from_slice(&raw)?;
+        if manifest.format_version > SNAPSHOT_FORMAT_VERSION {
+            return Err(RimError::Config(format!(
+                "snapshot {} has format v{}, newer than the supported v{}",
+                root.display(),
+                manifest.format_version,
+                SNAPSHOT_FORMAT_VERSION
+            )));
+        }
+
+        let mut blobs = BTreeMap::new();
+        for blob in manifest.blobs {
+            let total: u64 = blob.parts.iter().map(|part| part.length).sum();
+            if total != blob.size_bytes {
+                return Err(RimError::Config(format!(
+                    "snapshot blob {} lists {} bytes of parts for size {}",
+                    blob.path, total, blob.size_bytes
+                )));
+            }
+            for part in &blob.parts {
+                let path = part_file(root, &part.sha256);
+                let length = std::fs::metadata(&path)
+                    .map_err(|error| {
+                        RimError::Config(format!(
+                            "snapshot part {} missing: {}",
+                            path.display(),
+                            error
+                        ))
+                    })?
+                    .len();
+                if length != part.length {
+                    return Err(RimError::Config(format!(
+                        "snapshot part {} has {} bytes, manifest expects {}",
+                        path.display(),
+                        length,
+                        part.length
+                    )));
+                }
+            }
+            blobs.insert(blob.path.clone(), blob);
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            name: manifest.name,
+            created_at: manifest.created_at,
+            blobs,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn len(&self) -> usize {
+        self.blobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blobs.is_empty()
+    }
+
+    pub fn get(&self, path: &str) -> Option<&SnapshotBlob> {
+        self.blobs.get(path)
+    }
+
+    /// Lists blobs under `prefix` in path order, resuming after `cursor`.
+    pub fn list(
+        &self,
+        prefix: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> (Vec<&SnapshotBlob>, Option<String>) {
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded(cursor.to_string()),
+            None => Bound::Included(prefix.to_string()),
+        };
+
+        let mut items: Vec<&SnapshotBlob> = self
+            .blobs
+            .range((start, Bound::Unbounded))
+            .map(|(_, blob)| blob)
+            .skip_while(|blob| !blob.path.starts_with(prefix) && blob.path.as_str() < prefix)
+            .take_while(|blob| blob.path.starts_with(prefix))
+            .take(limit.saturating_add(1))
+            .collect();
+
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|blob| blob.path.clone())
+        } else {
+            None
+        };
+
+        (items, next_cursor)
+    }
+
+    /// Reads a blob, or the requested byte range of it, from its part files.
+    pub async fn read(
+        &self,
+        path: &str,
+        range: Option<ReadByteRange>,
+    ) -> Result<Option<(Bytes, ReadByteRange)>> {
+        let Some(blob) = self.blobs.get(path) else {
+            return Ok(None);
+        };
+
+        if blob.size_bytes == 0 {
+            if range.is_some() {
+                return Err(RimError::InvalidRequest(
+                    "range not satisfiable: empty object".to_string(),
+                ));
+            }
+            return Ok(Some((Bytes::new(), ReadByteRange { start: 0, end: 0 })));
+        }
+
+        let range = match range {
+            Some(range) if range.start > range.end || range.end >= blob.size_bytes => {
+                return Err(RimError::InvalidRequest(format!(
+                    "range not satisfiable: start={} end={} size={}",
+                    range.start, range.end, blob.size_bytes
+                )));
+            }
+            Some(range) => range,
+            None => ReadByteRange {
+                start: 0,
+                end: blob.size_bytes - 1,
+            },
+        };
+
+        let mut body = BytesMut::with_capacity((range.end - range.start + 1) as usize);
+        let mut part_start = 0u64;
+        for part in &blob.parts {
+            let part_end = part_start + part.length;
+            if part_end > range.start && part_start <= range.end {
+                let from = range.start.saturating_sub(part_start);
+                let to = (range.end + 1).min(part_end) - part_start;
+
+                let mut file = tokio::fs::File::open(part_file(&self.root, &part.sha256)).await?;
+                file.seek(SeekFrom::Start(from)).await?;
+                let mut chunk = vec![0u8; (to - from) as usize];
+                file.read_exact(&mut chunk).await?;
+                body.extend_from_slice(&chunk);
+            }
+            part_start = part_end;
+        }
+
+        Ok(Some((body.freeze(), range)))
+    }
+}
+
+/// Builds a snapshot directory, e.g. when exporting a dataset for a kiosk
+/// image.
+pub struct SnapshotWriter {
+    root: PathBuf,
+    name: String,
+    part_size: u64,
+    blobs: BTreeMap<String, SnapshotBlob>,
+}
+
+impl SnapshotWriter {
+    pub fn create(root: &Path, name: impl Into<String>, part_size: u64) -> Result<Self> {
+        std::fs::create_dir_all(root.join(PARTS_DIR))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            name: name.into(),
+            part_size: part_size.max(1),
+            blobs: BTreeMap::new(),
+        })
+    }
+
+    pub fn add_blob(&mut self, path: &str, body: &[u8], updated_at: DateTime<Utc>) -> Result<()> {
+        let mut parts = Vec::new();
+        for chunk in body.chunks(self.part_size as usize) {
+            let sha256 = compute_hash(chunk);
+            let file = part_file(&self.root, &sha256);
+            if !file.exists() {
+                std::fs::write(&file, chunk)?;
+            }
+            parts.push(SnapshotPart {
+                sha256,
+                length: chunk.len() as u64,
+            });
+        }
+
+        self.blobs.insert(
+            path.to_string(),
+            SnapshotBlob {
+                path: path.to_string(),
+                size_bytes: body.len() as u64,
+                etag: compute_hash(body),
+                updated_at,
+                parts,
+            },
+        );
+        Ok(())
+    }
+
+    /// Writes the manifest; the snapshot is complete once this returns.
+    pub fn finish(self) -> Result<PathBuf> {
+        let manifest = SnapshotManifest {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            name: self.name,
+            created_at: Utc::now(),
+            blobs: self.blobs.into_values().collect(),
+        };
+        let path = self.root.join(MANIFEST_FILE);
+        let staging = path.with_extension("tmp");
+        std::fs::write(&staging, serde_json::to_vec_pretty(&manifest)?)?;
+        std::fs::rename(&staging, &path)?;
+        Ok(path)
+    }
+}
+
+fn part_file(root: &Path, sha256: &str) -> PathBuf {
+    root.join(PARTS_DIR).join(sha256)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn snapshot_round_trips_blobs_and_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = SnapshotWriter::create(dir.path(), "kiosk", 4).unwrap();
+        writer
+            .add_blob("maps/a.bin", b"0123456789", Utc::now())
+            .unwrap();
+        writer.add_blob("maps/b.bin", b"", Utc::now()).unwrap();
+        writer.add_blob("other/c.bin", b"xyz", Utc::now()).unwrap();
+        writer.finish().unwrap();
+
+        let snapshot = MountedSnapshot::open(dir.path()).unwrap();
+        assert_eq!(snapshot.name(), "kiosk");
+        assert_eq!(snapshot.len(), 3);
+
+        let (body, _) = snapshot.read("maps/a.bin", None).await.unwrap().unwrap();
+        assert_eq!(&body[..], b"0123456789");
+        let (body, range) = snapshot
+            .read("maps/a.bin", Some(ReadByteRange { start: 3, end: 8 }))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&body[..], b"345678");
+        assert_eq!((range.start, range.end), (3, 8));
+        assert!(snapshot.read("missing", None).await.unwrap().is_none());
+
+        let (page, next) = snapshot.list("maps/", 1, None);
+        assert_eq!(page[0].path, "maps/a.bin");
+        let (page, next) = snapshot.list("maps/", 1, next.as_deref());
+        assert_eq!(page[0].path, "maps/b.bin");
+        assert!(next.is_none());
+
+        std::fs::write(part_file(dir.path(), &compute_hash(b"xyz")), b"xy").unwrap();
+        assert!(matches!(
+            MountedSnapshot::open(dir.path()),
+            Err(RimError::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,497 @@
+use super::metadata_store::is_corruption_error;
+use crate::{
+    HostPressureMonitor, MetadataStore, PlacementMap, Result, RimError, SLOT_BACKUP_RETENTION,
+    SharedClock, Slot, SlotManager, SlotTransferOperation, SlotTransferOperationRequest,
+    SqliteStats, system_clock,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::interval;
+
+/// When slot WAL files are folded back into their databases.
+///
+/// SQLite checkpoints on its own every thousand pages, but only when no
+/// reader holds the WAL and without giving disk space back; these passes
+/// truncate it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SqliteCheckpointConfig {
+    #[serde(default = "default_checkpoint_interval_secs")]
+    pub interval_secs: u64,
+    /// Checkpoint once the WAL grows past this size.
+    #[serde(default = "default_checkpoint_wal_bytes")]
+    pub wal_bytes: u64,
+    /// Checkpoint a non-empty WAL at least this often, however small it is;
+    /// 0 leaves it to `wal_bytes`.
+    #[serde(default = "default_checkpoint_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+impl Default for SqliteCheckpointConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_checkpoint_interval_secs(),
+            wal_bytes: default_checkpoint_wal_bytes(),
+            max_age_secs: default_checkpoint_max_age_secs(),
+        }
+    }
+}
+
+fn default_checkpoint_interval_secs() -> u64 {
+    30
+}
+
+fn default_checkpoint_wal_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_checkpoint_max_age_secs() -> u64 {
+    3600
+}
+
+/// When slot databases are checked for corruption.
+///
+/// A check reads the whole file, so it waits until the slot has been idle for
+/// a while and the host is not under pressure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SqliteIntegrityConfig {
+    /// Check each slot this often; 0 turns checks off.
+    #[serde(default = "default_integrity_interval_secs")]
+    pub interval_secs: u64,
+    /// Only check a slot that has not been written for this long.
+    #[serde(default = "default_integrity_idle_secs")]
+    pub idle_secs: u64,
+    /// Rebuild a corrupt slot database from a peer replica. Without it
+    /// corruption is only logged.
+    #[serde(default = "default_integrity_repair")]
+    pub repair: bool,
+}
+
+impl Default for SqliteIntegrityConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_integrity_interval_secs(),
+            idle_secs: default_integrity_idle_secs(),
+            repair: default_integrity_repair(),
+        }
+    }
+}
+
+fn default_integrity_interval_secs() -> u64 {
+    7 * 24 * 3600
+}
+
+fn default_integrity_idle_secs() -> u64 {
+    600
+}
+
+fn default_integrity_repair() -> bool {
+    true
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteMaintenanceConfig {
+    /// How often slots are checked for a vacuum.
+    pub interval: Duration,
+    pub checkpoint: SqliteCheckpointConfig,
+    /// Checked on the same rounds as vacuums.
+    pub integrity: SqliteIntegrityConfig,
+    /// Vacuum when free pages reach this share of the file,
+    pub vacuum_free_ratio: f64,
+    /// and add up to at least this many bytes.
+    pub vacuum_min_free_bytes: u64,
+    /// Vacuum a slot at most once per this interval; each run rewrites the
+    /// whole file, which is what wears flash media out in the first place.
+    pub vacuum_min_interval: Duration,
+    /// Snapshots kept per slot; one is taken before every vacuum.
+    pub backup_retention: usize,
+}
+
+impl Default for SqliteMaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(300),
+            checkpoint: SqliteCheckpointConfig::default(),
+            integrity: SqliteIntegrityConfig::default(),
+            vacuum_free_ratio: 0.25,
+            vacuum_min_free_bytes: 16 * 1024 * 1024,
+            vacuum_min_interval: Duration::from_secs(24 * 3600),
+            backup_retention: SLOT_BACKUP_RETENTION,
+        }
+    }
+}
+
+/// Keeps slot databases from growing without bound.
+///
+/// SQLite only truncates the WAL on a checkpoint and never shrinks the main
+/// file by itself, so deleted heads and parts leave free pages behind. WALs
+/// are checked every `checkpoint.interval_secs`, free pages every
+/// `interval`.
+///
+/// The same rounds run `PRAGMA integrity_check` on slots that are due. A
+/// corrupt database is moved to the slot's `quarantine/` directory and the
+/// slot is copied again from a peer replica, as a new replica would be;
+/// writes made meanwhile are left to heal.
+pub struct SqliteMaintenance {
+    slot_manager: Arc<SlotManager>,
+    config: SqliteMaintenanceConfig,
+    host_pressure: Option<Arc<HostPressureMonitor>>,
+    slot_repair: Option<SlotRepair>,
+    clock: SharedClock,
+}
+
+/// What rebuilding a slot from its peers takes.
+struct SlotRepair {
+    local_node_id: String,
+    placement: Arc<PlacementMap>,
+    slot_transfer: Arc<SlotTransferOperation>,
+}
+
+impl SqliteMaintenance {
+    pub fn new(slot_manager: Arc<SlotManager>, config: SqliteMaintenanceConfig) -> Self {
+        Self {
+            slot_manager,
+            config,
+            host_pressure: None,
+            slot_repair: None,
+            clock: system_clock(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Rebuilds corrupt slot databases by pulling the slot from another
+    /// replica in the placement map.
+    pub fn with_slot_repair(
+        mut self,
+        local_node_id: String,
+        placement: Arc<PlacementMap>,
+        slot_transfer: Arc<SlotTransferOperation>,
+    ) -> Self {
+        self.slot_repair = Some(SlotRepair {
+            local_node_id,
+            placement,
+            slot_transfer,
+        });
+        self
+    }
+
+    /// Holds off checkpoints, vacuums and integrity checks while
+    /// [`HostPressureMonitor::sheds_background`] holds.
+    pub fn with_host_pressure(mut self, host_pressure: Option<Arc<HostPressureMonitor>>) -> Self {
+        self.host_pressure = host_pressure;
+        self
+    }
+
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(
+                self.config.checkpoint.interval_secs.max(1),
+            ));
+            let mut last_vacuum_pass: Option<DateTime<Utc>> = None;
+            loop {
+                ticker.tick().await;
+                if self
+                    .host_pressure
+                    .as_ref()
+                    .is_some_and(|pressure| pressure.sheds_background())
+                {
+                    continue;
+                }
+                let now = self.clock.now();
+                let vacuum = last_vacuum_pass.is_none_or(|last| {
+                    (now - last)
+                        .to_std()
+                        .is_ok_and(|elapsed| elapsed >= self.config.interval)
+                });
+                if vacuum {
+                    last_vacuum_pass = Some(now);
+                }
+                if let Err(error) = self.run_pass(vacuum).await {
+                    tracing::warn!("sqlite maintenance loop failed: {}", error);
+                }
+            }
+        });
+    }
+
+    /// Checkpoints, vacuums and checks every slot that needs it.
+    pub async fn run_once(&self) -> Result<()> {
+        self.run_pass(true).await
+    }
+
+    async fn run_pass(&self, vacuum: bool) -> Result<()> {
+        for slot_id in self.slot_manager.get_assigned_slots().await {
+            if vacuum
+                && self.config.integrity.interval_secs > 0
+                && let Err(error) = self.check_integrity(slot_id).await
+            {
+                tracing::warn!(
+                    "sqlite integrity check failed: slot={} error={}",
+                    slot_id,
+                    error
+                );
+            }
+            if let Err(error) = self.maintain_slot(slot_id, vacuum).await {
+                tracing::warn!(
+                    "sqlite maintenance failed: slot={} error={}",
+                    slot_id,
+                    error
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn maintain_slot(&self, slot_id: u16, vacuum: bool) -> Result<()> {
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        let store = MetadataStore::new(slot)?.with_clock(self.clock.clone());
+        let stats = store.sqlite_stats()?;
+
+        if self.should_checkpoint(&stats) {
+            store.checkpoint()?;
+            tracing::debug!(
+                "sqlite checkpoint: slot={} wal_bytes={}",
+                slot_id,
+                stats.wal_size_bytes
+            );
+        }
+
+        if vacuum && self.should_vacuum(&stats) {
+            store.backup("vacuum", self.config.backup_retention)?;
+            store.vacuum()?;
+            store.checkpoint()?;
+            tracing::info!(
+                "sqlite vacuum: slot={} file_bytes={} free_bytes={}",
+                slot_id,
+                stats.file_size_bytes,
+                stats.free_bytes()
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn check_integrity(&self, slot_id: u16) -> Result<()> {
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        let problems = match MetadataStore::new(slot.clone()) {
+            Ok(store) => {
+                let store = store.with_clock(self.clock.clone());
+                // Stats that cannot be read are a reason to check, not to wait.
+                if let Ok(stats) = store.slot_stats()
+                    && !self.integrity_due(&stats.sqlite, stats.last_write_at)
+                {
+                    return Ok(());
+                }
+                store.integrity_check()?
+            }
+            Err(error) if is_corruption_error(&error) => vec![error.to_string()],
+            Err(error) => return Err(error),
+        };
+        if problems.is_empty() {
+            tracing::debug!("sqlite integrity check passed: slot={}", slot_id);
+            return Ok(());
+        }
+
+        tracing::error!(
+            "slot database corrupt: slot={} problems={}",
+            slot_id,
+            problems.join("; ")
+        );
+        if !self.config.integrity.repair {
+            return Ok(());
+        }
+        let Some(repair) = self.slot_repair.as_ref() else {
+            return Ok(());
+        };
+        repair.rebuild(&slot, self.clock.now()).await
+    }
+
+    fn integrity_due(
+        &self,
+        stats: &SqliteStats,
+        last_write_at: Option<chrono::DateTime<Utc>>,
+    ) -> bool {
+        let integrity = &self.config.integrity;
+        let now = self.clock.now();
+        let elapsed_since = |at: chrono::DateTime<Utc>, secs: u64| {
+            (now - at)
+                .to_std()
+                .is_ok_and(|elapsed| elapsed >= Duration::from_secs(secs))
+        };
+        if last_write_at.is_some_and(|at| !elapsed_since(at, integrity.idle_secs)) {
+            return false;
+        }
+        stats
+            .last_integrity_check_at
+            .is_none_or(|at| elapsed_since(at, integrity.interval_secs))
+    }
+
+    fn should_checkpoint(&self, stats: &SqliteStats) -> bool {
+        let checkpoint = &self.config.checkpoint;
+        if stats.wal_size_bytes == 0 {
+            return false;
+        }
+        if stats.wal_size_bytes >= checkpoint.wal_bytes {
+            return true;
+        }
+        if checkpoint.max_age_secs == 0 {
+            return false;
+        }
+
+        match stats.last_checkpoint_at {
+            Some(last) => (self.clock.now() - last)
+                .to_std()
+                .is_ok_and(|elapsed| elapsed >= Duration::from_secs(checkpoint.max_age_secs)),
+            None => true,
+        }
+    }
+
+    fn should_vacuum(&self, stats: &SqliteStats) -> bool {
+        if stats.page_count == 0 || stats.free_bytes() < self.config.vacuum_min_free_bytes {
+            return false;
+        }
+
+        if (stats.free_pages as f64) < stats.page_count as f64 * self.config.vacuum_free_ratio {
+            return false;
+        }
+
+        match stats.last_vacuum_at {
+            Some(last) => (self.clock.now() - last)
+                .to_std()
+                .is_ok_and(|elapsed| elapsed >= self.config.vacuum_min_interval),
+            None => true,
+        }
+    }
+}
+
+impl SlotRepair {
+    /// Quarantines the slot database and pulls the slot from the first peer
+    /// replica that can serve it. A slot without peers keeps its database
+    /// for an operator to look at.
+    async fn rebuild(&self, slot: &Slot, now: DateTime<Utc>) -> Result<()> {
+        let slot_id = slot.slot_id;
+        let peers: Vec<String> = self
+            .placement
+            .slot(slot_id)
+            .await?
+            .map(|slot| slot.replicas)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|node_id| node_id != &self.local_node_id)
+            .collect();
+        if peers.is_empty() {
+            return Err(RimError::Internal(format!(
+                "no peer replica to rebuild slot {} from",
+                slot_id
+            )));
+        }
+
+        MetadataStore::quarantine(slot, now)?;
+
+        let mut last_error = None;
+        for source_node_id in peers {
+            match self
+                .slot_transfer
+                .run(SlotTransferOperationRequest {
+                    slot_id,
+                    source_node_id: source_node_id.clone(),
+                })
+                .await
+            {
+                Ok(result) => {
+                    tracing::warn!(
+                        "slot database rebuilt: slot={} source={} blobs={}",
+                        slot_id,
+                        source_node_id,
+                        result.blob_count
+                    );
+                    return Ok(());
+                }
+                Err(error) => {
+                    tracing::warn!(
+                        "slot rebuild from peer failed: slot={} source={} error={}",
+                        slot_id,
+                        source_node_id,
+                        error
+                    );
+                    last_error = Some(error);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            RimError::Internal(format!("slot {} could not be rebuilt", slot_id))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Clock, ManualClock, TombstoneMeta};
+
+    fn tombstone(path: &str, now: DateTime<Utc>) -> TombstoneMeta {
+        TombstoneMeta {
+            path: path.to_string(),
+            slot_id: 1,
+            generation: 1,
+            deleted_at: now,
+            reason: "test".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn maintenance_is_scheduled_by_the_injected_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let slot_manager =
+            Arc::new(SlotManager::new("node-a".to_string(), dir.path().to_path_buf()).unwrap());
+        slot_manager.init_slot(1).await.unwrap();
+        // Far from the system time, so a stamp taken from it would show.
+        let clock = Arc::new(ManualClock::new(Utc::now() - chrono::Duration::days(30)));
+        let config = SqliteMaintenanceConfig::default();
+        let maintenance =
+            SqliteMaintenance::new(slot_manager.clone(), config.clone()).with_clock(clock.clone());
+        let store = MetadataStore::new(slot_manager.get_slot(1).await.unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+        store
+            .insert_tombstone(&tombstone("a", clock.now()))
+            .unwrap();
+
+        // The integrity check waits for the slot to sit idle by the clock.
+        maintenance.run_once().await.unwrap();
+        assert!(
+            store
+                .sqlite_stats()
+                .unwrap()
+                .last_integrity_check_at
+                .is_none()
+        );
+        clock.advance(chrono::Duration::seconds(config.integrity.idle_secs as i64));
+        maintenance.run_once().await.unwrap();
+        let stats = store.slot_stats().unwrap();
+        assert_eq!(stats.sqlite.last_integrity_check_at, Some(clock.now()));
+        assert!(!maintenance.integrity_due(&stats.sqlite, stats.last_write_at));
+        clock.advance(chrono::Duration::seconds(
+            config.integrity.interval_secs as i64,
+        ));
+        assert!(maintenance.integrity_due(&stats.sqlite, stats.last_write_at));
+
+        // A small WAL is checkpointed once `max_age_secs` of clock time passed.
+        store.checkpoint().unwrap();
+        let stats = SqliteStats {
+            wal_size_bytes: 1,
+            ..store.sqlite_stats().unwrap()
+        };
+        assert_eq!(stats.last_checkpoint_at, Some(clock.now()));
+        assert!(!maintenance.should_checkpoint(&stats));
+        clock.advance(chrono::Duration::seconds(
+            config.checkpoint.max_age_secs as i64,
+        ));
+        assert!(maintenance.should_checkpoint(&stats));
+    }
+}
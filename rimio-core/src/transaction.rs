@@ -0,0 +1,1732 @@
+//! Cross-slot transactions.
+//!
+//! Writes are staged on the coordinating node until the client commits; the
+//! commit then runs a two-phase protocol over every slot the transaction
+//! touches. Prepare uploads parts to each replica and collects votes, commit
+//! flips all heads, one metadata transaction per slot.
+//!
+//! Participants that speak the `txn-2pc` capability hold their prepared heads
+//! until the coordinator commits or aborts them, and refuse to prepare another
+//! transaction touching the same paths meanwhile. Older peers are prepared by
+//! checking their heads from the coordinator.
+//!
+//! Two coordinators preparing the same path at once each find the other's
+//! batch on some replica. Transaction ids sort by start time, and the later
+//! transaction yields: it aborts with a conflict, while the earlier one
+//! retries the replicas it was locked out of until they are released. Without
+//! this, each could reach a small write quorum on its own replicas and commit
+//! a different head at the same generation.
+//!
+//! With a decision log, every node writes its prepared batches and their
+//! outcomes to its node store before acting on them, and the coordinator
+//! writes its commit decision before the first head is applied. A restarted
+//! node reloads the batches it still holds, and prepared batches are never
+//! dropped for their age.
+//!
+//! A participant left holding a prepared batch after its coordinator died asks
+//! the transaction's other nodes for the outcome: a batch committed or aborted
+//! anywhere is committed or aborted here. A coordinator that does not know the
+//! transaction, running or decided, never decided to commit it, so the batch
+//! is aborted. Decisions are only kept for the decision log's retention,
+//! though, so a batch prepared longer ago than that is not presumed aborted.
+//! Otherwise it stays in doubt and is asked about again later.
+
+use crate::operations::commit_batch::stage_batch_heads;
+use crate::operations::internal_put_head::build_head_write;
+use crate::operations::put_blob::live_generation;
+use crate::storage::node_store::TRANSACTION_LOG_RETENTION_DAYS;
+use crate::{
+    BlobMeta, CAP_TXN_2PC, CAP_TXN_RESOLVE, ClusterClient, CommitBatchEntry, CommitBatchItem,
+    Coordinator, HeadWrite, InternalPutHeadBatchItem, MetadataStore, NodeInfo, NodeStore,
+    PartStore, ReplicatedHead, Result, RimError, SharedClock, SlotManager, TombstoneMeta,
+    TransactionRecord, system_clock,
+};
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Staged transactions are dropped when left uncommitted for this long.
+pub const STAGED_TRANSACTION_TTL_SECS: i64 = 600;
+
+/// How long one prepare, commit or abort call to a participant may take.
+pub const TXN_RPC_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// Attempts per participant call before it counts as failed.
+pub const TXN_RPC_ATTEMPTS: u32 = 3;
+/// Pause before the first retry; doubled for each further one.
+const TXN_RPC_BACKOFF: std::time::Duration = std::time::Duration::from_millis(200);
+/// Prepare attempts on a replica locked by a later transaction, which is
+/// expected to yield, before the replica counts as voting no.
+const LOCK_WAIT_ATTEMPTS: u32 = 5;
+/// Pause before re-preparing a locked replica; doubled for each further one.
+const LOCK_WAIT_BACKOFF: std::time::Duration = std::time::Duration::from_millis(50);
+/// A prepared batch counts as in doubt once it waited this long for a
+/// decision.
+pub const IN_DOUBT_AFTER_SECS: i64 = 30;
+
+#[derive(Debug, Clone)]
+pub struct StagedTransaction {
+    pub txn_id: String,
+    pub created_at: DateTime<Utc>,
+    pub entries: BTreeMap<String, StagedEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub enum StagedEntry {
+    Put(Bytes),
+    Delete,
+}
+
+impl StagedTransaction {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now - self.created_at > Duration::seconds(STAGED_TRANSACTION_TTL_SECS)
+    }
+}
+
+/// In-memory registry of transactions staged on this node.
+pub struct TransactionManager {
+    staged: RwLock<HashMap<String, StagedTransaction>>,
+    clock: SharedClock,
+}
+
+impl Default for TransactionManager {
+    fn default() -> Self {
+        Self {
+            staged: RwLock::new(HashMap::new()),
+            clock: system_clock(),
+        }
+    }
+}
+
+impl TransactionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expires staged transactions by `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub async fn begin(&self) -> StagedTransaction {
+        let now = self.clock.now();
+        let txn = StagedTransaction {
+            txn_id: format!("txn-{}", ulid::Ulid::new()),
+            created_at: now,
+            entries: BTreeMap::new(),
+        };
+
+        let mut staged = self.staged.write().await;
+        staged.retain(|_, existing| !existing.is_expired(now));
+        staged.insert(txn.txn_id.clone(), txn.clone());
+        txn
+    }
+
+    pub async fn get(&self, txn_id: &str) -> Option<StagedTransaction> {
+        let staged = self.staged.read().await;
+        staged
+            .get(txn_id)
+            .filter(|txn| !txn.is_expired(self.clock.now()))
+            .cloned()
+    }
+
+    pub async fn stage(&self, txn_id: &str, path: String, entry: StagedEntry) -> Result<()> {
+        let mut staged = self.staged.write().await;
+        let txn = staged
+            .get_mut(txn_id)
+            .filter(|txn| !txn.is_expired(self.clock.now()))
+            .ok_or_else(|| transaction_not_found(txn_id))?;
+
+        txn.entries.insert(path, entry);
+        Ok(())
+    }
+
+    /// Removes the transaction so it cannot be committed twice.
+    pub async fn take(&self, txn_id: &str) -> Result<StagedTransaction> {
+        let mut staged = self.staged.write().await;
+        staged
+            .remove(txn_id)
+            .filter(|txn| !txn.is_expired(self.clock.now()))
+            .ok_or_else(|| transaction_not_found(txn_id))
+    }
+
+    pub async fn abort(&self, txn_id: &str) -> bool {
+        let mut staged = self.staged.write().await;
+        staged.remove(txn_id).is_some()
+    }
+}
+
+fn transaction_not_found(txn_id: &str) -> RimError {
+    RimError::InvalidRequest(format!("transaction not found: txn_id={}", txn_id))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Vote {
+    Yes,
+    No(String),
+    /// Another prepared transaction, `holder`, holds one of the paths.
+    Locked {
+        holder: String,
+        reason: String,
+    },
+}
+
+impl Vote {
+    /// Why the participant did not vote yes.
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Self::Yes => None,
+            Self::No(reason) | Self::Locked { reason, .. } => Some(reason),
+        }
+    }
+
+    /// Whether this vote locks `txn_id` out in favour of a transaction that
+    /// started earlier.
+    fn yields(&self, txn_id: &str) -> bool {
+        matches!(self, Self::Locked { holder, .. } if holder.as_str() < txn_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParticipantVote {
+    pub slot_id: u16,
+    pub node_id: String,
+    pub vote: Vote,
+}
+
+/// Writes of one slot, together with the replicas that must accept them.
+#[derive(Debug, Clone)]
+pub struct TwoPhaseParticipant {
+    pub slot_id: u16,
+    pub replicas: Vec<NodeInfo>,
+    pub entries: Vec<CommitBatchEntry>,
+    /// Live generations (0 for missing or deleted) paths must still be at on
+    /// the coordinator; the transaction ends in a conflict otherwise.
+    pub expected_generations: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TwoPhaseCommitRequest {
+    pub txn_id: String,
+    pub participants: Vec<TwoPhaseParticipant>,
+    pub local_node_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TwoPhaseSlotResult {
+    pub slot_id: u16,
+    pub items: Vec<CommitBatchItem>,
+    pub committed_replicas: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum TwoPhaseOutcome {
+    Committed(Vec<TwoPhaseSlotResult>),
+    Aborted(Vec<ParticipantVote>),
+    Conflict,
+}
+
+/// The nodes of a transaction, handed to every participant on prepare so it
+/// knows whom to ask when the coordinator goes away.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransactionPeers {
+    pub coordinator: String,
+    /// Every node holding a replica of a touched slot, coordinator included.
+    pub participants: Vec<String>,
+}
+
+/// What a node knows about a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionState {
+    /// A batch is prepared here and no decision arrived yet.
+    Prepared,
+    Committed,
+    Aborted,
+    /// Never prepared here, or forgotten since.
+    Unknown,
+}
+
+impl TransactionState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Prepared => "prepared",
+            Self::Committed => "committed",
+            Self::Aborted => "aborted",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "prepared" => Self::Prepared,
+            "committed" => Self::Committed,
+            "aborted" => Self::Aborted,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Logged instead of a batch refused by the generation check on commit, so
+/// it is not reloaded; peers asking are told nothing.
+const REJECTED_RECORD_STATE: &str = "rejected";
+
+/// How an in-doubt batch was settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InDoubtOutcome {
+    Committed,
+    Aborted,
+    /// Nobody could tell yet; the batch stays prepared.
+    InDoubt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InDoubtResolution {
+    pub txn_id: String,
+    pub slot_id: u16,
+    pub outcome: InDoubtOutcome,
+}
+
+/// Heads a participant voted yes on, waiting for the coordinator's decision.
+struct PreparedBatch {
+    writes: Vec<HeadWrite>,
+    peers: TransactionPeers,
+    prepared_at: DateTime<Utc>,
+    /// Reloaded from the decision log after a restart. Whoever prepared it
+    /// may have stopped before deciding, so it does not count as a running
+    /// transaction when peers ask.
+    reloaded: bool,
+}
+
+impl PreparedBatch {
+    fn locks(&self, path: &str) -> bool {
+        self.writes.iter().any(|write| write.path() == path)
+    }
+}
+
+/// A prepared batch as written to the decision log.
+#[derive(Serialize, Deserialize)]
+struct LoggedBatch {
+    peers: TransactionPeers,
+    heads: Vec<LoggedHead>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "head_kind", rename_all = "snake_case")]
+enum LoggedHead {
+    Meta {
+        meta: BlobMeta,
+        head_sha256: String,
+    },
+    Tombstone {
+        tombstone: TombstoneMeta,
+        head_sha256: String,
+    },
+}
+
+impl LoggedBatch {
+    fn new(batch: &PreparedBatch) -> Self {
+        let heads = batch
+            .writes
+            .iter()
+            .map(|write| match write {
+                HeadWrite::Meta {
+                    meta, head_sha256, ..
+                } => LoggedHead::Meta {
+                    meta: meta.clone(),
+                    head_sha256: head_sha256.clone(),
+                },
+                HeadWrite::Tombstone {
+                    tombstone,
+                    head_sha256,
+                    ..
+                } => LoggedHead::Tombstone {
+                    tombstone: tombstone.clone(),
+                    head_sha256: head_sha256.clone(),
+                },
+            })
+            .collect();
+        Self {
+            peers: batch.peers.clone(),
+            heads,
+        }
+    }
+
+    fn into_batch(self, prepared_at: DateTime<Utc>) -> Result<PreparedBatch> {
+        let mut writes = Vec::with_capacity(self.heads.len());
+        for head in self.heads {
+            writes.push(match head {
+                LoggedHead::Meta { meta, head_sha256 } => HeadWrite::Meta {
+                    inline_data: serde_json::to_vec(&meta)?,
+                    meta,
+                    head_sha256,
+                },
+                LoggedHead::Tombstone {
+                    tombstone,
+                    head_sha256,
+                } => HeadWrite::Tombstone {
+                    inline_data: serde_json::to_vec(&tombstone)?,
+                    tombstone,
+                    head_sha256,
+                },
+            });
+        }
+        Ok(PreparedBatch {
+            writes,
+            peers: self.peers,
+            prepared_at,
+            reloaded: true,
+        })
+    }
+}
+
+/// State of the transactions this node voted on, keyed by transaction and
+/// slot, and of those it decided as coordinator. Prepared batches are held
+/// until decided however long that takes; outcomes are kept in memory for
+/// [`STAGED_TRANSACTION_TTL_SECS`] and for longer in the decision log.
+#[derive(Default)]
+struct ParticipantLog {
+    prepared: HashMap<(String, u16), PreparedBatch>,
+    /// Recently committed batches, so a retried commit is answered again.
+    committed: HashMap<(String, u16), DateTime<Utc>>,
+    /// Recently aborted batches, so peers in doubt can learn the outcome.
+    aborted: HashMap<(String, u16), DateTime<Utc>>,
+    /// Recent decisions of the transactions this node coordinated.
+    decided: HashMap<String, (TransactionState, DateTime<Utc>)>,
+}
+
+impl ParticipantLog {
+    fn purge_expired(&mut self, now: DateTime<Utc>) {
+        let ttl = Duration::seconds(STAGED_TRANSACTION_TTL_SECS);
+        self.committed
+            .retain(|_, committed_at| now - *committed_at <= ttl);
+        self.aborted
+            .retain(|_, aborted_at| now - *aborted_at <= ttl);
+        self.decided
+            .retain(|_, (_, decided_at)| now - *decided_at <= ttl);
+    }
+
+    /// A decision wins, then commits over aborts, which win over a pending
+    /// prepare.
+    fn state(&self, txn_id: &str) -> TransactionState {
+        if let Some((decision, _)) = self.decided.get(txn_id) {
+            return *decision;
+        }
+        let matches = |key: &(String, u16)| key.0 == txn_id;
+        if self.committed.keys().any(matches) {
+            TransactionState::Committed
+        } else if self.aborted.keys().any(matches) {
+            TransactionState::Aborted
+        } else if self
+            .prepared
+            .iter()
+            .any(|(key, batch)| matches(key) && !batch.reloaded)
+        {
+            TransactionState::Prepared
+        } else {
+            TransactionState::Unknown
+        }
+    }
+}
+
+struct PreparedSlot {
+    slot_id: u16,
+    store: MetadataStore,
+    replicas: Vec<NodeInfo>,
+    /// Whether the coordinator holds a copy of the slot, which it then votes,
+    /// applies and counts like any replica.
+    local_replica: bool,
+    heads: Vec<ReplicatedHead>,
+    items: Vec<CommitBatchItem>,
+}
+
+#[derive(Clone)]
+pub struct TwoPhaseCommit {
+    slot_manager: Arc<SlotManager>,
+    part_store: Arc<PartStore>,
+    coordinator: Arc<Coordinator>,
+    cluster_client: Arc<ClusterClient>,
+    log: Arc<RwLock<ParticipantLog>>,
+    decision_log: Option<Arc<NodeStore>>,
+    clock: SharedClock,
+}
+
+impl TwoPhaseCommit {
+    pub fn new(
+        slot_manager: Arc<SlotManager>,
+        part_store: Arc<PartStore>,
+        coordinator: Arc<Coordinator>,
+        cluster_client: Arc<ClusterClient>,
+    ) -> Self {
+        Self {
+            slot_manager,
+            part_store,
+            coordinator,
+            cluster_client,
+            log: Arc::new(RwLock::new(ParticipantLog::default())),
+            decision_log: None,
+            clock: system_clock(),
+        }
+    }
+
+    /// Writes prepared batches, their outcomes and coordinator decisions to
+    /// `node_store` before acting on them, and reloads the batches still
+    /// prepared there, so a restart neither forgets a vote nor a decision.
+    pub fn with_decision_log(mut self, node_store: Arc<NodeStore>) -> Result<Self> {
+        let mut log = ParticipantLog::default();
+        for record in
+            node_store.list_transaction_records(None, Some(TransactionState::Prepared.as_str()))?
+        {
+            let batch: LoggedBatch = serde_json::from_value(record.payload)?;
+            log.prepared.insert(
+                (record.txn_id, record.slot_id),
+                batch.into_batch(record.recorded_at)?,
+            );
+        }
+        self.log = Arc::new(RwLock::new(log));
+        self.decision_log = Some(node_store);
+        Ok(self)
+    }
+
+    /// Ages prepared batches and remembered decisions by `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub async fn perform_2pc(&self, request: TwoPhaseCommitRequest) -> Result<TwoPhaseOutcome> {
+        let TwoPhaseCommitRequest {
+            txn_id,
+            participants,
+            local_node_id,
+        } = request;
+
+        let peers = TransactionPeers {
+            coordinator: local_node_id.clone(),
+            participants: participants
+                .iter()
+                .flat_map(|participant| participant.replicas.iter())
+                .map(|node| node.node_id.clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+        };
+
+        let mut prepared = Vec::with_capacity(participants.len());
+        let mut votes = Vec::new();
+        // Participants holding a prepared batch that an abort must release.
+        let mut voted_yes: Vec<(u16, String)> = Vec::new();
+        let mut abort = false;
+
+        for participant in participants {
+            let Some(slot) = self.prepare_local(participant, &local_node_id).await? else {
+                self.abort_participants(&txn_id, &local_node_id, &voted_yes)
+                    .await;
+                return Ok(TwoPhaseOutcome::Conflict);
+            };
+            let quorum = self.coordinator.write_quorum(slot.replicas.len());
+            let mut yes = 0usize;
+
+            if slot.local_replica {
+                let writes: Vec<HeadWrite> =
+                    slot.heads.iter().map(|head| head.write.clone()).collect();
+                let vote = wait_for_later(&txn_id, || {
+                    self.vote(&txn_id, slot.slot_id, &peers, writes.clone())
+                })
+                .await?;
+                if vote.yields(&txn_id) {
+                    tracing::info!(
+                        "Transaction yields to an earlier one: txn={} slot={} {}",
+                        txn_id,
+                        slot.slot_id,
+                        vote.reason().unwrap_or_default()
+                    );
+                    self.abort_participants(&txn_id, &local_node_id, &voted_yes)
+                        .await;
+                    return Ok(TwoPhaseOutcome::Conflict);
+                }
+                if vote == Vote::Yes {
+                    yes += 1;
+                    voted_yes.push((slot.slot_id, local_node_id.clone()));
+                } else {
+                    // The coordinator's own copy could not be committed anyway.
+                    abort = true;
+                }
+                votes.push(ParticipantVote {
+                    slot_id: slot.slot_id,
+                    node_id: local_node_id.clone(),
+                    vote,
+                });
+            }
+
+            for replica in slot
+                .replicas
+                .iter()
+                .filter(|node| node.node_id != local_node_id.as_str())
+            {
+                let vote = wait_for_later(&txn_id, || async {
+                    Ok(self
+                        .prepare_remote(
+                            &replica.node_id,
+                            slot.slot_id,
+                            &txn_id,
+                            &peers,
+                            &slot.heads,
+                        )
+                        .await)
+                })
+                .await?;
+                if vote.yields(&txn_id) {
+                    tracing::info!(
+                        "Transaction yields to an earlier one: txn={} slot={} node={} {}",
+                        txn_id,
+                        slot.slot_id,
+                        replica.node_id,
+                        vote.reason().unwrap_or_default()
+                    );
+                    self.abort_participants(&txn_id, &local_node_id, &voted_yes)
+                        .await;
+                    return Ok(TwoPhaseOutcome::Conflict);
+                }
+                if vote == Vote::Yes {
+                    yes += 1;
+                    voted_yes.push((slot.slot_id, replica.node_id.clone()));
+                }
+
+                votes.push(ParticipantVote {
+                    slot_id: slot.slot_id,
+                    node_id: replica.node_id.clone(),
+                    vote,
+                });
+            }
+
+            if yes < quorum {
+                abort = true;
+            }
+            prepared.push(slot);
+        }
+
+        if abort {
+            tracing::warn!(
+                "Transaction aborted in prepare: txn={} votes={}",
+                txn_id,
+                votes.len()
+            );
+            self.abort_participants(&txn_id, &local_node_id, &voted_yes)
+                .await;
+            return Ok(TwoPhaseOutcome::Aborted(votes));
+        }
+
+        for slot in prepared.iter().filter(|slot| slot.local_replica) {
+            if !heads_are_fresh(&slot.store, &slot.heads)? {
+                self.abort_participants(&txn_id, &local_node_id, &voted_yes)
+                    .await;
+                return Ok(TwoPhaseOutcome::Conflict);
+            }
+        }
+
+        // Logged before any head is applied: from here on, participants in
+        // doubt must be told to commit, even by a restarted coordinator.
+        if let Err(error) = self
+            .record_decision(&txn_id, TransactionState::Committed)
+            .await
+        {
+            self.abort_participants(&txn_id, &local_node_id, &voted_yes)
+                .await;
+            return Err(error);
+        }
+
+        let mut results = Vec::with_capacity(prepared.len());
+        for (index, slot) in prepared.into_iter().enumerate() {
+            if slot.local_replica && !self.apply_prepared(&txn_id, slot.slot_id, true).await? {
+                if index == 0 {
+                    self.abort_participants(&txn_id, &local_node_id, &voted_yes)
+                        .await;
+                    return Ok(TwoPhaseOutcome::Conflict);
+                }
+
+                return Err(RimError::Internal(format!(
+                    "transaction partially committed: txn={} failed_slot={}",
+                    txn_id, slot.slot_id
+                )));
+            }
+
+            let heads: Vec<ReplicatedHead> = slot
+                .heads
+                .iter()
+                .map(|head| ReplicatedHead {
+                    write: head.write.clone(),
+                    parts: Vec::new(),
+                    linked_from: None,
+                })
+                .collect();
+
+            let mut committed_replicas = usize::from(slot.local_replica);
+            for replica in slot
+                .replicas
+                .iter()
+                .filter(|node| node.node_id != local_node_id.as_str())
+            {
+                let node_id = replica.node_id.as_str();
+                let committed = if self.supports_2pc(node_id).await {
+                    with_retries("commit", node_id, || {
+                        self.cluster_client
+                            .commit_transaction(node_id, slot.slot_id, &txn_id)
+                    })
+                    .await
+                } else {
+                    with_retries("commit", node_id, || {
+                        self.cluster_client.commit_head_batch(
+                            node_id,
+                            slot.slot_id,
+                            &txn_id,
+                            &heads,
+                        )
+                    })
+                    .await
+                };
+
+                match committed {
+                    Ok(()) => committed_replicas += 1,
+                    Err(error) => {
+                        tracing::warn!(
+                            "Transaction commit failed on replica: txn={} node={} slot={} error={}",
+                            txn_id,
+                            node_id,
+                            slot.slot_id,
+                            error
+                        );
+                    }
+                }
+            }
+
+            results.push(TwoPhaseSlotResult {
+                slot_id: slot.slot_id,
+                items: slot.items,
+                committed_replicas,
+            });
+        }
+
+        Ok(TwoPhaseOutcome::Committed(results))
+    }
+
+    /// Participant side of prepare: votes on `heads` for `slot_id` and, on
+    /// yes, holds them until [`Self::commit_prepared`] or
+    /// [`Self::abort_prepared`]. The parts must already be staged here.
+    pub async fn prepare_participant(
+        &self,
+        txn_id: &str,
+        slot_id: u16,
+        peers: TransactionPeers,
+        heads: Vec<InternalPutHeadBatchItem>,
+    ) -> Result<Vote> {
+        if heads.is_empty() {
+            return Err(RimError::InvalidRequest(
+                "transaction batch cannot be empty".to_string(),
+            ));
+        }
+
+        let now = self.clock.now();
+        let mut writes = Vec::with_capacity(heads.len());
+        for item in heads {
+            writes.push(build_head_write(
+                slot_id,
+                Some(item.path),
+                &item.head_kind,
+                item.generation,
+                item.head_sha256,
+                item.meta,
+                item.tombstone,
+                now,
+            )?);
+        }
+
+        self.vote(txn_id, slot_id, &peers, writes).await
+    }
+
+    /// Applies the batch prepared for `txn_id` on `slot_id`. Returns `false`
+    /// when the metadata store rejected it; committing a batch again after it
+    /// was committed succeeds, so coordinators may retry.
+    pub async fn commit_prepared(&self, txn_id: &str, slot_id: u16) -> Result<bool> {
+        self.apply_prepared(txn_id, slot_id, false).await
+    }
+
+    /// Drops the batch prepared for `txn_id` on `slot_id`, if any, and
+    /// remembers the abort for peers in doubt.
+    pub async fn abort_prepared(&self, txn_id: &str, slot_id: u16) -> bool {
+        let key = (txn_id.to_string(), slot_id);
+        let mut log = self.log.write().await;
+        if let Err(error) = self.record(txn_id, slot_id, TransactionState::Aborted.as_str(), None) {
+            // A batch still logged as prepared is reloaded on restart and
+            // learns the abort again from its peers.
+            tracing::warn!(
+                "Transaction abort not logged: txn={} slot={} error={}",
+                txn_id,
+                slot_id,
+                error
+            );
+        }
+        let removed = log.prepared.remove(&key).is_some();
+        log.aborted.insert(key, self.clock.now());
+        removed
+    }
+
+    /// What this node knows about `txn_id`, answered to peers in doubt.
+    pub async fn transaction_state(&self, txn_id: &str) -> Result<TransactionState> {
+        let state = {
+            let mut log = self.log.write().await;
+            log.purge_expired(self.clock.now());
+            log.state(txn_id)
+        };
+        if matches!(
+            state,
+            TransactionState::Committed | TransactionState::Aborted
+        ) {
+            return Ok(state);
+        }
+        Ok(match self.logged_state(txn_id, None)? {
+            TransactionState::Unknown => state,
+            logged => logged,
+        })
+    }
+
+    /// Settles batches prepared here for longer than `min_age_secs` by asking
+    /// the transaction's other nodes for the outcome. With `txn_id`, only that
+    /// transaction is looked at.
+    pub async fn resolve_in_doubt(
+        &self,
+        local_node_id: &str,
+        txn_id: Option<&str>,
+        min_age_secs: i64,
+    ) -> Result<Vec<InDoubtResolution>> {
+        let now = self.clock.now();
+        let mut in_doubt: BTreeMap<String, (TransactionPeers, DateTime<Utc>, Vec<u16>)> =
+            BTreeMap::new();
+        {
+            let mut log = self.log.write().await;
+            log.purge_expired(now);
+            for ((batch_txn, slot_id), batch) in &log.prepared {
+                if txn_id.is_some_and(|txn_id| txn_id != batch_txn)
+                    || now - batch.prepared_at < Duration::seconds(min_age_secs)
+                {
+                    continue;
+                }
+                let entry = in_doubt
+                    .entry(batch_txn.clone())
+                    .or_insert_with(|| (batch.peers.clone(), batch.prepared_at, Vec::new()));
+                entry.1 = entry.1.min(batch.prepared_at);
+                entry.2.push(*slot_id);
+            }
+        }
+
+        let mut resolutions = Vec::new();
+        for (txn_id, (peers, prepared_at, slots)) in in_doubt {
+            // The coordinator's decision is logged after the prepare, so it
+            // cannot have been purged yet while the batch is younger than the
+            // log's retention.
+            let decision_kept = now - prepared_at < Duration::days(TRANSACTION_LOG_RETENTION_DAYS);
+            let outcome = match self.transaction_state(&txn_id).await? {
+                TransactionState::Committed => InDoubtOutcome::Committed,
+                TransactionState::Aborted => InDoubtOutcome::Aborted,
+                // This node coordinated the transaction and no longer runs it.
+                own if peers.coordinator == local_node_id => {
+                    decide_in_doubt(&[], Some(own), decision_kept)
+                }
+                _ => {
+                    self.ask_outcome(&txn_id, &peers, local_node_id, decision_kept)
+                        .await
+                }
+            };
+            if outcome == InDoubtOutcome::InDoubt && !decision_kept {
+                tracing::warn!(
+                    "In-doubt transaction outlived its coordinator's decision log: txn={}",
+                    txn_id
+                );
+            }
+            for slot_id in slots {
+                let outcome = match outcome {
+                    InDoubtOutcome::Committed => match self.commit_prepared(&txn_id, slot_id).await
+                    {
+                        Ok(true) => InDoubtOutcome::Committed,
+                        Ok(false) => {
+                            tracing::warn!(
+                                "In-doubt transaction rejected by generation check: txn={} slot={}",
+                                txn_id,
+                                slot_id
+                            );
+                            InDoubtOutcome::Aborted
+                        }
+                        // Decided concurrently by the coordinator.
+                        Err(RimError::InvalidRequest(_)) => continue,
+                        Err(error) => return Err(error),
+                    },
+                    InDoubtOutcome::Aborted => {
+                        self.abort_prepared(&txn_id, slot_id).await;
+                        InDoubtOutcome::Aborted
+                    }
+                    InDoubtOutcome::InDoubt => InDoubtOutcome::InDoubt,
+                };
+                tracing::info!(
+                    "Resolved in-doubt transaction: txn={} slot={} outcome={:?}",
+                    txn_id,
+                    slot_id,
+                    outcome
+                );
+                resolutions.push(InDoubtResolution {
+                    txn_id: txn_id.clone(),
+                    slot_id,
+                    outcome,
+                });
+            }
+        }
+
+        Ok(resolutions)
+    }
+
+    /// Asks every other node of the transaction what it knows.
+    /// `decision_kept` tells whether the coordinator still has its decision
+    /// if it made one.
+    async fn ask_outcome(
+        &self,
+        txn_id: &str,
+        peers: &TransactionPeers,
+        local_node_id: &str,
+        decision_kept: bool,
+    ) -> InDoubtOutcome {
+        let mut states = Vec::new();
+        let mut coordinator_state = None;
+
+        for node_id in peers
+            .participants
+            .iter()
+            .chain(std::iter::once(&peers.coordinator))
+            .collect::<BTreeSet<_>>()
+        {
+            if node_id == local_node_id {
+                continue;
+            }
+            let state = if self.supports_txn_resolve(node_id).await {
+                with_retries("status", node_id, || {
+                    self.cluster_client.fetch_transaction_state(node_id, txn_id)
+                })
+                .await
+            } else {
+                Err(RimError::Http(format!(
+                    "peer does not report transaction state: node={}",
+                    node_id
+                )))
+            };
+
+            match state {
+                Ok(state) => {
+                    if node_id == &peers.coordinator {
+                        coordinator_state = Some(state);
+                    }
+                    states.push(state);
+                }
+                Err(error) => {
+                    tracing::debug!(
+                        "In-doubt peer did not answer: txn={} node={} error={}",
+                        txn_id,
+                        node_id,
+                        error
+                    );
+                }
+            }
+        }
+
+        decide_in_doubt(&states, coordinator_state, decision_kept)
+    }
+
+    /// Votes no when another prepared transaction holds one of the paths or
+    /// a head is already at or beyond the proposed generation; otherwise
+    /// records the batch as prepared.
+    async fn vote(
+        &self,
+        txn_id: &str,
+        slot_id: u16,
+        peers: &TransactionPeers,
+        writes: Vec<HeadWrite>,
+    ) -> Result<Vote> {
+        let store = self.ensure_store(slot_id).await?;
+        let mut log = self.log.write().await;
+        log.purge_expired(self.clock.now());
+
+        for ((other_txn, other_slot), batch) in &log.prepared {
+            if *other_slot != slot_id || other_txn == txn_id {
+                continue;
+            }
+            if let Some(write) = writes.iter().find(|write| batch.locks(write.path())) {
+                return Ok(Vote::Locked {
+                    holder: other_txn.clone(),
+                    reason: format!(
+                        "path locked by transaction: path={} txn={}",
+                        write.path(),
+                        other_txn
+                    ),
+                });
+            }
+        }
+
+        for write in &writes {
+            if let Some(current) = store.get_current_head(write.path())?
+                && current.generation >= write.generation()
+            {
+                return Ok(Vote::No(format!(
+                    "stale generation: path={} proposed={} current={}",
+                    write.path(),
+                    write.generation(),
+                    current.generation
+                )));
+            }
+        }
+
+        let batch = PreparedBatch {
+            writes,
+            peers: peers.clone(),
+            prepared_at: self.clock.now(),
+            reloaded: false,
+        };
+        self.record(
+            txn_id,
+            slot_id,
+            TransactionState::Prepared.as_str(),
+            Some(&batch),
+        )?;
+        log.prepared.insert((txn_id.to_string(), slot_id), batch);
+        Ok(Vote::Yes)
+    }
+
+    async fn apply_prepared(
+        &self,
+        txn_id: &str,
+        slot_id: u16,
+        require_newer: bool,
+    ) -> Result<bool> {
+        let key = (txn_id.to_string(), slot_id);
+        let batch = {
+            let mut log = self.log.write().await;
+            log.purge_expired(self.clock.now());
+            if log.committed.contains_key(&key) {
+                return Ok(true);
+            }
+            log.prepared.remove(&key)
+        };
+        let Some(batch) = batch else {
+            if self.logged_state(txn_id, Some(slot_id))? == TransactionState::Committed {
+                return Ok(true);
+            }
+            return Err(RimError::InvalidRequest(format!(
+                "transaction not prepared: txn_id={} slot={}",
+                txn_id, slot_id
+            )));
+        };
+
+        let store = self.ensure_store(slot_id).await?;
+        let applied = store.apply_head_batch(&batch.writes, require_newer)?;
+        // Logged after the heads: a batch applied but still logged as
+        // prepared is applied again once reloaded, which changes nothing.
+        let outcome = if applied {
+            TransactionState::Committed.as_str()
+        } else {
+            REJECTED_RECORD_STATE
+        };
+        if let Err(error) = self.record(txn_id, slot_id, outcome, None) {
+            tracing::warn!(
+                "Transaction outcome not logged: txn={} slot={} error={}",
+                txn_id,
+                slot_id,
+                error
+            );
+        }
+        if applied {
+            self.log
+                .write()
+                .await
+                .committed
+                .insert(key, self.clock.now());
+        }
+        Ok(applied)
+    }
+
+    /// Writes a record of `txn_id` on `slot_id` to the decision log.
+    fn record(
+        &self,
+        txn_id: &str,
+        slot_id: u16,
+        state: &str,
+        batch: Option<&PreparedBatch>,
+    ) -> Result<()> {
+        let Some(node_store) = &self.decision_log else {
+            return Ok(());
+        };
+        let payload = match batch {
+            Some(batch) => serde_json::to_value(LoggedBatch::new(batch))?,
+            None => serde_json::Value::Null,
+        };
+        node_store.save_transaction_record(&TransactionRecord {
+            txn_id: txn_id.to_string(),
+            slot_id,
+            state: state.to_string(),
+            payload,
+            recorded_at: self.clock.now(),
+        })
+    }
+
+    /// Remembers what this node decided as coordinator, in the decision log
+    /// first.
+    async fn record_decision(&self, txn_id: &str, decision: TransactionState) -> Result<()> {
+        if let Some(node_store) = &self.decision_log {
+            node_store.save_transaction_decision(txn_id, decision.as_str())?;
+        }
+        self.log
+            .write()
+            .await
+            .decided
+            .insert(txn_id.to_string(), (decision, self.clock.now()));
+        Ok(())
+    }
+
+    /// What the decision log knows about `txn_id`, or about its batch on
+    /// `slot_id`, ranked like [`ParticipantLog::state`]. Batches logged as
+    /// prepared count as unknown, like reloaded ones.
+    fn logged_state(&self, txn_id: &str, slot_id: Option<u16>) -> Result<TransactionState> {
+        let Some(node_store) = &self.decision_log else {
+            return Ok(TransactionState::Unknown);
+        };
+        if slot_id.is_none()
+            && let Some(decision) = node_store.get_transaction_decision(txn_id)?
+        {
+            return Ok(TransactionState::parse(&decision));
+        }
+        let states: Vec<TransactionState> = node_store
+            .list_transaction_records(Some(txn_id), None)?
+            .into_iter()
+            .filter(|record| slot_id.is_none_or(|slot_id| slot_id == record.slot_id))
+            .map(|record| TransactionState::parse(&record.state))
+            .collect();
+        Ok([TransactionState::Committed, TransactionState::Aborted]
+            .into_iter()
+            .find(|state| states.contains(state))
+            .unwrap_or(TransactionState::Unknown))
+    }
+
+    /// Releases every participant that voted yes. Failures are only logged;
+    /// an unreleased batch learns the abort when it asks this node.
+    async fn abort_participants(
+        &self,
+        txn_id: &str,
+        local_node_id: &str,
+        voted_yes: &[(u16, String)],
+    ) {
+        if let Err(error) = self
+            .record_decision(txn_id, TransactionState::Aborted)
+            .await
+        {
+            tracing::warn!(
+                "Transaction abort decision not logged: txn={} error={}",
+                txn_id,
+                error
+            );
+        }
+        for (slot_id, node_id) in voted_yes {
+            if node_id == local_node_id {
+                self.abort_prepared(txn_id, *slot_id).await;
+                continue;
+            }
+            if !self.supports_2pc(node_id).await {
+                continue;
+            }
+            if let Err(error) = with_retries("abort", node_id, || {
+                self.cluster_client
+                    .abort_transaction(node_id, *slot_id, txn_id)
+            })
+            .await
+            {
+                tracing::warn!(
+                    "Transaction abort failed on replica: txn={} node={} slot={} error={}",
+                    txn_id,
+                    node_id,
+                    slot_id,
+                    error
+                );
+            }
+        }
+    }
+
+    /// Stages the participant's writes here, whose parts replicas are sent
+    /// from even when this node holds no copy of the slot. Returns `None`
+    /// when a path is no longer at its expected generation.
+    async fn prepare_local(
+        &self,
+        participant: TwoPhaseParticipant,
+        local_node_id: &str,
+    ) -> Result<Option<PreparedSlot>> {
+        let TwoPhaseParticipant {
+            slot_id,
+            replicas,
+            entries,
+            expected_generations,
+        } = participant;
+
+        let store = self.ensure_store(slot_id).await?;
+        let (heads, items) =
+            stage_batch_heads(&self.part_store, &store, slot_id, entries, self.clock.now()).await?;
+
+        // Checked after staging: a write landing in between either moved the
+        // head here or will collide with the staged generation at commit.
+        for head in &heads {
+            let Some(expected) = expected_generations.get(head.write.path()) else {
+                continue;
+            };
+            let current = store.get_current_head(head.write.path())?;
+            let current_generation = current.as_ref().map_or(0, |head| head.generation);
+            if current_generation + 1 != head.write.generation()
+                || live_generation(current) != *expected
+            {
+                return Ok(None);
+            }
+        }
+
+        let local_replica =
+            replicas.is_empty() || replicas.iter().any(|node| node.node_id == local_node_id);
+        Ok(Some(PreparedSlot {
+            slot_id,
+            store,
+            replicas,
+            local_replica,
+            heads,
+            items,
+        }))
+    }
+
+    /// Stages the parts on a replica and collects its vote. Replicas without
+    /// `txn-2pc` are checked from here instead: none of their heads may
+    /// already be at or beyond the generation we are about to commit.
+    async fn prepare_remote(
+        &self,
+        node_id: &str,
+        slot_id: u16,
+        txn_id: &str,
+        peers: &TransactionPeers,
+        heads: &[ReplicatedHead],
+    ) -> Vote {
+        if let Err(error) = with_retries("stage", node_id, || {
+            self.cluster_client
+                .stage_head_parts(node_id, slot_id, txn_id, heads)
+        })
+        .await
+        {
+            return Vote::No(error.to_string());
+        }
+
+        if self.supports_2pc(node_id).await {
+            return match with_retries("prepare", node_id, || {
+                self.cluster_client
+                    .prepare_transaction(node_id, slot_id, txn_id, peers, heads)
+            })
+            .await
+            {
+                Ok(vote) => vote,
+                Err(error) => Vote::No(error.to_string()),
+            };
+        }
+
+        for head in heads {
+            match with_retries("prepare", node_id, || {
+                self.cluster_client
+                    .fetch_remote_head(node_id, slot_id, head.write.path())
+            })
+            .await
+            {
+                Ok(Some(remote)) if remote.generation >= head.write.generation() => {
+                    return Vote::No(format!(
+                        "stale generation: path={} local={} remote={}",
+                        head.write.path(),
+                        head.write.generation(),
+                        remote.generation
+                    ));
+                }
+                Ok(_) => {}
+                Err(error) => return Vote::No(error.to_string()),
+            }
+        }
+
+        Vote::Yes
+    }
+
+    async fn supports_txn_resolve(&self, node_id: &str) -> bool {
+        self.cluster_client
+            .peer_protocol(node_id)
+            .await
+            .is_ok_and(|protocol| protocol.supports(CAP_TXN_RESOLVE))
+    }
+
+    async fn supports_2pc(&self, node_id: &str) -> bool {
+        self.cluster_client
+            .peer_protocol(node_id)
+            .await
+            .is_ok_and(|protocol| protocol.supports(CAP_TXN_2PC))
+    }
+
+    async fn ensure_store(&self, slot_id: u16) -> Result<MetadataStore> {
+        if !self.slot_manager.has_slot(slot_id).await {
+            self.slot_manager.init_slot(slot_id).await?;
+        }
+
+        let slot = self.slot_manager.get_slot(slot_id).await?;
+        MetadataStore::new(slot)
+    }
+}
+
+/// Runs a participant call with [`TXN_RPC_TIMEOUT`] per attempt, retrying
+/// timeouts and transport errors up to [`TXN_RPC_ATTEMPTS`] times. Rejections
+/// (`InvalidRequest`) are final.
+async fn with_retries<T, F, Fut>(call_name: &str, node_id: &str, mut call: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        let error = match tokio::time::timeout(TXN_RPC_TIMEOUT, call()).await {
+            Ok(Ok(value)) => return Ok(value),
+            Ok(Err(error @ RimError::InvalidRequest(_))) => return Err(error),
+            Ok(Err(error)) => error,
+            Err(_) => RimError::Http(format!(
+                "transaction {} timed out: node={}",
+                call_name, node_id
+            )),
+        };
+        if attempt >= TXN_RPC_ATTEMPTS {
+            return Err(error);
+        }
+
+        tracing::debug!(
+            "Retrying transaction {}: node={} attempt={} error={}",
+            call_name,
+            node_id,
+            attempt,
+            error
+        );
+        tokio::time::sleep(TXN_RPC_BACKOFF * 2u32.pow(attempt - 1)).await;
+        attempt += 1;
+    }
+}
+
+/// Prepares again while the participant is locked by a transaction that
+/// started later than `txn_id`: that one yields and releases its batch.
+async fn wait_for_later<F, Fut>(txn_id: &str, mut prepare: F) -> Result<Vote>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Vote>>,
+{
+    let mut attempt = 1;
+    loop {
+        let vote = prepare().await?;
+        let locked_by_later =
+            matches!(&vote, Vote::Locked { holder, .. } if holder.as_str() > txn_id);
+        if !locked_by_later || attempt >= LOCK_WAIT_ATTEMPTS {
+            return Ok(vote);
+        }
+
+        tokio::time::sleep(LOCK_WAIT_BACKOFF * 2u32.pow(attempt - 1)).await;
+        attempt += 1;
+    }
+}
+
+/// Any commit means the coordinator decided to commit, and any abort that it
+/// decided to abort. The coordinator logs a commit decision before applying
+/// anything, so once it says it neither runs nor decided the transaction, it
+/// never committed it and the batch is aborted; unless `decision_kept` is
+/// false, since then the decision may have been purged from its log.
+fn decide_in_doubt(
+    states: &[TransactionState],
+    coordinator_state: Option<TransactionState>,
+    decision_kept: bool,
+) -> InDoubtOutcome {
+    if states.contains(&TransactionState::Committed)
+        || coordinator_state == Some(TransactionState::Committed)
+    {
+        InDoubtOutcome::Committed
+    } else if states.contains(&TransactionState::Aborted)
+        || coordinator_state == Some(TransactionState::Aborted)
+        || (decision_kept && coordinator_state == Some(TransactionState::Unknown))
+    {
+        InDoubtOutcome::Aborted
+    } else {
+        InDoubtOutcome::InDoubt
+    }
+}
+
+fn heads_are_fresh(store: &MetadataStore, heads: &[ReplicatedHead]) -> Result<bool> {
+    for head in heads {
+        if let Some(current) = store.get_current_head(head.write.path())?
+            && current.generation >= head.write.generation()
+        {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn transaction_manager_take_is_single_use() {
+        let manager = TransactionManager::new();
+        let txn = manager.begin().await;
+
+        manager
+            .stage(
+                &txn.txn_id,
+                "a/1".to_string(),
+                StagedEntry::Put(Bytes::from("x")),
+            )
+            .await
+            .unwrap();
+        manager
+            .stage(&txn.txn_id, "b/2".to_string(), StagedEntry::Delete)
+            .await
+            .unwrap();
+
+        let taken = manager.take(&txn.txn_id).await.unwrap();
+        assert_eq!(taken.entries.len(), 2);
+        assert!(manager.take(&txn.txn_id).await.is_err());
+        assert!(
+            manager
+                .stage(&txn.txn_id, "c".to_string(), StagedEntry::Delete)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn staged_transactions_expire_on_the_manager_clock() {
+        let clock = Arc::new(crate::ManualClock::starting_now());
+        let manager = TransactionManager::new().with_clock(clock.clone());
+        let txn = manager.begin().await;
+
+        clock.advance(Duration::seconds(STAGED_TRANSACTION_TTL_SECS));
+        assert!(manager.get(&txn.txn_id).await.is_some());
+
+        clock.advance(Duration::seconds(1));
+        assert!(manager.get(&txn.txn_id).await.is_none());
+        assert!(
+            manager
+                .stage(&txn.txn_id, "a".to_string(), StagedEntry::Delete)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn participant_calls_retry_transport_errors_only() {
+        let mut calls = 0;
+        let value = with_retries("commit", "n2", || {
+            calls += 1;
+            let attempt = calls;
+            async move {
+                if attempt < TXN_RPC_ATTEMPTS {
+                    Err(RimError::Http("connection reset".to_string()))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(value, TXN_RPC_ATTEMPTS);
+
+        let mut calls = 0;
+        let result: Result<()> = with_retries("commit", "n2", || {
+            calls += 1;
+            async { Err(RimError::InvalidRequest("not prepared".to_string())) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn later_transactions_yield_to_earlier_ones() {
+        let locked = |holder: &str| Vote::Locked {
+            holder: holder.to_string(),
+            reason: "path locked".to_string(),
+        };
+        assert!(locked("txn-01A").yields("txn-01B"));
+        assert!(!locked("txn-01C").yields("txn-01B"));
+
+        // The later transaction releases the path after a few attempts.
+        let mut calls = 0;
+        let vote = wait_for_later("txn-01B", || {
+            calls += 1;
+            let attempt = calls;
+            async move {
+                Ok(if attempt < 3 {
+                    locked("txn-01C")
+                } else {
+                    Vote::Yes
+                })
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!((vote, calls), (Vote::Yes, 3));
+
+        let mut calls = 0;
+        let vote = wait_for_later("txn-01B", || {
+            calls += 1;
+            async { Ok(locked("txn-01A")) }
+        })
+        .await
+        .unwrap();
+        assert!(vote.yields("txn-01B"));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn in_doubt_batches_follow_any_decision_seen() {
+        use TransactionState::*;
+
+        assert_eq!(
+            decide_in_doubt(&[Prepared, Committed], None, true),
+            InDoubtOutcome::Committed
+        );
+        assert_eq!(
+            decide_in_doubt(&[Prepared], Some(Committed), true),
+            InDoubtOutcome::Committed
+        );
+        assert_eq!(
+            decide_in_doubt(&[Unknown, Aborted], Some(Prepared), true),
+            InDoubtOutcome::Aborted
+        );
+        // The coordinator still runs the transaction.
+        assert_eq!(
+            decide_in_doubt(&[Prepared, Unknown], Some(Prepared), true),
+            InDoubtOutcome::InDoubt
+        );
+        // The coordinator did not answer.
+        assert_eq!(
+            decide_in_doubt(&[Prepared], None, true),
+            InDoubtOutcome::InDoubt
+        );
+        // The coordinator never logged a commit decision.
+        assert_eq!(
+            decide_in_doubt(&[Prepared], Some(Unknown), true),
+            InDoubtOutcome::Aborted
+        );
+        // Or it did, and the decision has been purged since.
+        assert_eq!(
+            decide_in_doubt(&[Prepared], Some(Unknown), false),
+            InDoubtOutcome::InDoubt
+        );
+    }
+
+    fn two_phase_commit(dir: &std::path::Path, clock: SharedClock) -> TwoPhaseCommit {
+        let slot_manager =
+            Arc::new(SlotManager::new("node".to_string(), dir.join("slots")).unwrap());
+        let part_store = Arc::new(PartStore::new(dir.join("parts")).unwrap());
+        let cluster_client = Arc::new(ClusterClient::new(Arc::new(
+            crate::registry::memory::MemoryRegistry::new(),
+        )));
+        let node_store = NodeStore::open(dir).unwrap().with_clock(clock.clone());
+        TwoPhaseCommit::new(
+            slot_manager,
+            part_store,
+            Arc::new(Coordinator::new(2)),
+            cluster_client,
+        )
+        .with_decision_log(Arc::new(node_store))
+        .unwrap()
+        .with_clock(clock)
+    }
+
+    fn delete(path: &str, generation: i64) -> HeadWrite {
+        HeadWrite::tombstone(TombstoneMeta {
+            path: path.to_string(),
+            slot_id: 1,
+            generation,
+            deleted_at: Utc::now(),
+            reason: "test".to_string(),
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn restarted_coordinators_still_answer_their_commit_decision() {
+        let (coordinator_dir, participant_dir) =
+            (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let clock = Arc::new(crate::ManualClock::starting_now());
+        let peers = TransactionPeers {
+            coordinator: "node-a".to_string(),
+            participants: vec!["node-a".to_string(), "node-b".to_string()],
+        };
+
+        let participant = two_phase_commit(participant_dir.path(), clock.clone());
+        let vote = participant
+            .vote("txn-1", 1, &peers, vec![delete("a", 1)])
+            .await
+            .unwrap();
+        assert_eq!(vote, Vote::Yes);
+
+        // The coordinator decides, applies its own batch and stops before
+        // telling the participant.
+        let coordinator = two_phase_commit(coordinator_dir.path(), clock.clone());
+        let vote = coordinator
+            .vote("txn-1", 1, &peers, vec![delete("a", 1)])
+            .await
+            .unwrap();
+        assert_eq!(vote, Vote::Yes);
+        coordinator
+            .record_decision("txn-1", TransactionState::Committed)
+            .await
+            .unwrap();
+        assert!(coordinator.apply_prepared("txn-1", 1, true).await.unwrap());
+        drop(coordinator);
+
+        let coordinator = two_phase_commit(coordinator_dir.path(), clock.clone());
+        let answer = coordinator.transaction_state("txn-1").await.unwrap();
+        assert_eq!(answer, TransactionState::Committed);
+
+        // The participant restarts too and, long past any timeout, still
+        // holds its vote and the path.
+        drop(participant);
+        clock.advance(Duration::seconds(STAGED_TRANSACTION_TTL_SECS * 10));
+        let participant = two_phase_commit(participant_dir.path(), clock.clone());
+        let vote = participant
+            .vote("txn-2", 1, &peers, vec![delete("a", 2)])
+            .await
+            .unwrap();
+        assert!(matches!(vote, Vote::Locked { holder, .. } if holder == "txn-1"));
+
+        assert_eq!(
+            decide_in_doubt(&[TransactionState::Prepared], Some(answer), true),
+            InDoubtOutcome::Committed
+        );
+        assert!(participant.commit_prepared("txn-1", 1).await.unwrap());
+        let store = participant.ensure_store(1).await.unwrap();
+        assert_eq!(store.get_current_head("a").unwrap().unwrap().generation, 1);
+
+        // A coordinator that stopped before deciding aborts its own batch.
+        let alone = TransactionPeers {
+            coordinator: "node-a".to_string(),
+            participants: vec!["node-a".to_string()],
+        };
+        let vote = coordinator
+            .vote("txn-3", 1, &alone, vec![delete("b", 1)])
+            .await
+            .unwrap();
+        assert_eq!(vote, Vote::Yes);
+        drop(coordinator);
+
+        let coordinator = two_phase_commit(coordinator_dir.path(), clock.clone());
+        let resolutions = coordinator
+            .resolve_in_doubt("node-a", Some("txn-3"), 0)
+            .await
+            .unwrap();
+        assert_eq!(resolutions.len(), 1);
+        assert_eq!(resolutions[0].outcome, InDoubtOutcome::Aborted);
+        let answer = coordinator.transaction_state("txn-3").await.unwrap();
+        assert_eq!(answer, TransactionState::Aborted);
+    }
+
+    #[tokio::test]
+    async fn batches_outliving_the_decision_log_are_not_presumed_aborted() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(crate::ManualClock::starting_now());
+        let alone = TransactionPeers {
+            coordinator: "node-a".to_string(),
+            participants: vec!["node-a".to_string()],
+        };
+
+        // The coordinator decides to commit and stops before applying.
+        let coordinator = two_phase_commit(dir.path(), clock.clone());
+        let vote = coordinator
+            .vote("txn-1", 1, &alone, vec![delete("a", 1)])
+            .await
+            .unwrap();
+        assert_eq!(vote, Vote::Yes);
+        coordinator
+            .record_decision("txn-1", TransactionState::Committed)
+            .await
+            .unwrap();
+        drop(coordinator);
+
+        clock.advance(Duration::days(TRANSACTION_LOG_RETENTION_DAYS + 1));
+        let coordinator = two_phase_commit(dir.path(), clock.clone());
+        coordinator
+            .decision_log
+            .as_ref()
+            .unwrap()
+            .purge_expired()
+            .unwrap();
+        let answer = coordinator.transaction_state("txn-1").await.unwrap();
+        assert_eq!(answer, TransactionState::Unknown);
+
+        let resolutions = coordinator
+            .resolve_in_doubt("node-a", Some("txn-1"), 0)
+            .await
+            .unwrap();
+        assert_eq!(resolutions.len(), 1);
+        assert_eq!(resolutions[0].outcome, InDoubtOutcome::InDoubt);
+        let vote = coordinator
+            .vote("txn-2", 1, &alone, vec![delete("a", 2)])
+            .await
+            .unwrap();
+        assert!(matches!(vote, Vote::Locked { holder, .. } if holder == "txn-1"));
+    }
+
+    #[tokio::test]
+    async fn coordinators_outside_the_replica_set_keep_no_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(crate::ManualClock::starting_now());
+        let coordinator = two_phase_commit(dir.path(), clock);
+        let unreachable = NodeInfo {
+            node_id: "node-b".to_string(),
+            group_id: "default".to_string(),
+            address: "127.0.0.1:1".to_string(),
+            status: crate::NodeStatus::Healthy,
+            slots: Vec::new(),
+            grpc_address: None,
+        };
+
+        let outcome = coordinator
+            .perform_2pc(TwoPhaseCommitRequest {
+                txn_id: "txn-1".to_string(),
+                participants: vec![TwoPhaseParticipant {
+                    slot_id: 1,
+                    replicas: vec![unreachable],
+                    entries: vec![CommitBatchEntry::Delete {
+                        path: "a".to_string(),
+                    }],
+                    expected_generations: BTreeMap::new(),
+                }],
+                local_node_id: "node".to_string(),
+            })
+            .await
+            .unwrap();
+
+        // The only replica did not vote, so the coordinator alone is no
+        // quorum and neither votes nor applies the batch here.
+        let TwoPhaseOutcome::Aborted(votes) = outcome else {
+            panic!("expected an abort, got {:?}", outcome);
+        };
+        assert_eq!(votes.len(), 1);
+        assert_eq!(votes[0].node_id, "node-b");
+        let store = coordinator.ensure_store(1).await.unwrap();
+        assert!(store.get_current_head("a").unwrap().is_none());
+        assert_eq!(
+            coordinator.transaction_state("txn-1").await.unwrap(),
+            TransactionState::Aborted
+        );
+    }
+}
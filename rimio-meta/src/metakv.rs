@@ -120,13 +120,29 @@ pub struct MetaKvOptions {
     pub advertise_addr: Option<String>,
     pub seeds: Vec<String>,
     pub transport: Option<String>,
+    /// Sent on every call to a peer, e.g. the key its routes require.
+    pub peer_headers: reqwest::header::HeaderMap,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "op", rename_all = "snake_case")]
 pub enum MetaWriteRequest {
-    Put { key: String, value: Vec<u8> },
-    PutIfAbsent { key: String, value: Vec<u8> },
+    Put {
+        key: String,
+        value: Vec<u8>,
+    },
+    PutIfAbsent {
+        key: String,
+        value: Vec<u8>,
+    },
+    CompareAndSwap {
+        key: String,
+        expected: Option<Vec<u8>>,
+        value: Vec<u8>,
+    },
+    Delete {
+        key: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -288,19 +304,24 @@ where
         .map_err(|error| MetaError::Http(error.to_string()))
 }
 
+fn peer_client(headers: reqwest::header::HeaderMap) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .default_headers(headers)
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
 #[derive(Clone)]
 struct MetaNetwork {
     client: reqwest::Client,
 }
 
 impl MetaNetwork {
-    fn new() -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(5))
-            .build()
-            .unwrap_or_else(|_| reqwest::Client::new());
-
-        Self { client }
+    fn new(peer_headers: reqwest::header::HeaderMap) -> Self {
+        Self {
+            client: peer_client(peer_headers),
+        }
     }
 
     async fn send_rpc<Req, Resp, Err>(
@@ -905,6 +926,37 @@ impl RaftStateMachine<MetaTypeConfig> for Arc<MetaStateMachineStore> {
                             responses.push(MetaWriteResponse { created: true });
                         }
                     }
+                    MetaWriteRequest::CompareAndSwap {
+                        key,
+                        expected,
+                        value,
+                    } => {
+                        let existing = tx
+                            .query_row("SELECT v FROM kv WHERE k=?1", params![key], |row| {
+                                row.get::<_, Vec<u8>>(0)
+                            })
+                            .optional()
+                            .map_err(|error| StorageIOError::write_state_machine(&error))?;
+
+                        if existing != expected {
+                            responses.push(MetaWriteResponse { created: false });
+                        } else {
+                            tx.execute(
+                                "INSERT INTO kv(k, v) VALUES (?1, ?2) ON CONFLICT(k) DO UPDATE SET v=excluded.v",
+                                params![key, value],
+                            )
+                            .map_err(|error| StorageIOError::write_state_machine(&error))?;
+                            responses.push(MetaWriteResponse { created: true });
+                        }
+                    }
+                    MetaWriteRequest::Delete { key } => {
+                        let deleted = tx
+                            .execute("DELETE FROM kv WHERE k=?1", params![key])
+                            .map_err(|error| StorageIOError::write_state_machine(&error))?;
+                        responses.push(MetaWriteResponse {
+                            created: deleted > 0,
+                        });
+                    }
                 },
                 EntryPayload::Membership(membership) => {
                     last_membership = Some(StoredMembership::new(Some(entry.log_id), membership));
@@ -1159,7 +1211,7 @@ impl MetaKv {
         let db_path = metakv_db_path(namespace.as_str(), node_id.as_str(), bind_addr.as_str());
         let state_machine = Arc::new(MetaStateMachineStore::new(db_path)?);
         let log_store = MetaLogStore::<MetaTypeConfig>::default();
-        let network = MetaNetwork::new();
+        let network = MetaNetwork::new(options.peer_headers.clone());
 
         let raft_config = Arc::new(
             RaftConfig {
@@ -1183,10 +1235,7 @@ impl MetaKv {
         .await
         .map_err(|error| MetaError::Internal(format!("failed to start openraft: {}", error)))?;
 
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(5))
-            .build()
-            .unwrap_or_else(|_| reqwest::Client::new());
+        let client = peer_client(options.peer_headers);
 
         let metakv = Self {
             namespace,
@@ -1273,6 +1322,15 @@ impl MetaKv {
         .map(|_response| ())
     }
 
+    /// Removes `key`; returns whether it existed.
+    pub async fn delete(&self, key: &str) -> Result<bool> {
+        self.client_write(MetaWriteRequest::Delete {
+            key: key.to_string(),
+        })
+        .await
+        .map(|response| response.created)
+    }
+
     pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
         self.state_machine.get_value(key)
     }
@@ -1290,6 +1348,23 @@ impl MetaKv {
         .map(|response| response.created)
     }
 
+    /// Writes `value` only if the current value equals `expected` (`None`
+    /// meaning absent). Returns whether the swap happened.
+    pub async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: &[u8],
+    ) -> Result<bool> {
+        self.client_write(MetaWriteRequest::CompareAndSwap {
+            key: key.to_string(),
+            expected: expected.map(<[u8]>::to_vec),
+            value: value.to_vec(),
+        })
+        .await
+        .map(|response| response.created)
+    }
+
     async fn bootstrap_or_join(&self, seeds: Vec<String>) -> Result<()> {
         if seeds.is_empty() {
             self.initialize_single_node().await?;
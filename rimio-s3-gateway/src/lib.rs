@@ -6,7 +6,7 @@ mod util;
 pub use error::{S3Error, S3GatewayResult};
 pub use s3::{multipart_not_implemented_error, router};
 pub use types::{
-    ByteRange, DeleteObjectRequest, GetObjectRequest, GetObjectResponse, HeadObjectRequest,
-    HeadObjectResponse, ListObjectItem, ListObjectsV2Request, ListObjectsV2Response,
-    PutObjectRequest, PutObjectResponse, S3GatewayBackend,
+    ByteRange, ByteRangeSpec, DeleteObjectRequest, GetObjectRequest, GetObjectResponse,
+    HeadObjectRequest, HeadObjectResponse, ListObjectItem, ListObjectsV2Request,
+    ListObjectsV2Response, PutObjectBody, PutObjectRequest, PutObjectResponse, S3GatewayBackend,
 };
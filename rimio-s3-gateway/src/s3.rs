@@ -2,24 +2,28 @@ use crate::util::{
     decode_continuation_token, parse_range_header, quote_etag, render_list_objects_v2_xml,
 };
 use crate::{
-    DeleteObjectRequest, GetObjectRequest, HeadObjectRequest, ListObjectsV2Request,
+    DeleteObjectRequest, GetObjectRequest, HeadObjectRequest, ListObjectsV2Request, PutObjectBody,
     PutObjectRequest, S3Error, S3GatewayBackend,
 };
 use axum::{
     Router,
-    body::Bytes,
+    body::{Body, Bytes},
     extract::{Path, Query, State},
-    http::{HeaderMap, HeaderValue, StatusCode, header},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
     routing::get,
 };
 use base64::Engine;
 use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
 use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
 use md5::{Digest, Md5};
 use std::collections::HashMap;
 use std::sync::Arc;
 
+/// PutObject bodies up to this size are buffered; larger ones are streamed.
+const BUFFERED_PUT_MAX_BYTES: usize = 8 * 1024 * 1024;
+
 pub fn router<B>() -> Router<Arc<B>>
 where
     B: S3GatewayBackend,
@@ -122,7 +126,7 @@ async fn put_object<B>(
     Query(params): Query<HashMap<String, String>>,
     State(backend): State<Arc<B>>,
     headers: HeaderMap,
-    body: Bytes,
+    body: Body,
 ) -> Response
 where
     B: S3GatewayBackend,
@@ -131,6 +135,11 @@ where
         return S3Error::not_implemented("multipart upload is not implemented yet").into_response();
     }
 
+    let body = match read_put_body(&headers, body).await {
+        Ok(body) => body,
+        Err(error) => return error.into_response(),
+    };
+
     let request = match build_put_object_request(bucket, key, &headers, body) {
         Ok(request) => request,
         Err(error) => return error.into_response(),
@@ -170,16 +179,17 @@ where
             .into_response();
     }
 
-    if let Some(content_length) = request.content_length
-        && content_length != request.body.len() as u64
-    {
-        return S3Error::invalid_argument("content-length does not match request body size")
-            .into_response();
-    }
+    if let PutObjectBody::Buffered(body) = &request.body {
+        if let Some(content_length) = request.content_length
+            && content_length != body.len() as u64
+        {
+            return S3Error::invalid_argument("content-length does not match request body size")
+                .into_response();
+        }
 
-    if let Err(error) = validate_content_md5(request.content_md5.as_deref(), request.body.as_ref())
-    {
-        return error.into_response();
+        if let Err(error) = validate_content_md5(request.content_md5.as_deref(), body.as_ref()) {
+            return error.into_response();
+        }
     }
 
     let result = match backend.put_object(request).await {
@@ -287,17 +297,39 @@ where
         StatusCode::OK
     };
 
-    if let Some(content_type) = request.response_content_type.as_deref() {
-        if let Ok(value) = HeaderValue::from_str(content_type) {
-            response.headers_mut().insert(header::CONTENT_TYPE, value);
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    for (name, value) in &result.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    let overrides = [
+        (header::CACHE_CONTROL, &request.response_cache_control),
+        (
+            header::CONTENT_DISPOSITION,
+            &request.response_content_disposition,
+        ),
+        (header::CONTENT_ENCODING, &request.response_content_encoding),
+        (header::CONTENT_LANGUAGE, &request.response_content_language),
+        (header::CONTENT_TYPE, &request.response_content_type),
+        (header::EXPIRES, &request.response_expires),
+    ];
+    for (name, value) in overrides {
+        if let Some(value) = value.as_deref()
+            && let Ok(value) = HeaderValue::from_str(value)
+        {
+            response.headers_mut().insert(name, value);
         }
-    } else {
-        response.headers_mut().insert(
-            header::CONTENT_TYPE,
-            HeaderValue::from_static("application/octet-stream"),
-        );
     }
 
+    insert_user_metadata(response.headers_mut(), &result.metadata);
+
     response
         .headers_mut()
         .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
@@ -377,10 +409,35 @@ where
     if let Ok(value) = HeaderValue::from_str(&result.size_bytes.to_string()) {
         response.headers_mut().insert(header::CONTENT_LENGTH, value);
     }
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    for (name, value) in &result.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    insert_user_metadata(response.headers_mut(), &result.metadata);
 
     response
 }
 
+/// Sends the user metadata of an object back as `x-amz-meta-*` headers.
+fn insert_user_metadata(headers: &mut HeaderMap, metadata: &HashMap<String, String>) {
+    for (key, value) in metadata {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(format!("x-amz-meta-{}", key).as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+}
+
 async fn delete_object<B>(
     Path((bucket, key)): Path<(String, String)>,
     State(backend): State<Arc<B>>,
@@ -416,7 +473,7 @@ fn build_put_object_request(
     bucket: String,
     key: String,
     headers: &HeaderMap,
-    body: Bytes,
+    body: PutObjectBody,
 ) -> Result<PutObjectRequest, S3Error> {
     let content_length = header_string(headers, "content-length")
         .map(|raw| {
@@ -628,11 +685,67 @@ fn parse_bool_query(raw: &str, name: &str) -> Result<bool, S3Error> {
     }
 }
 
-fn validate_content_md5(content_md5: Option<&str>, body: &[u8]) -> Result<(), S3Error> {
-    let Some(raw) = content_md5 else {
-        return Ok(());
-    };
+/// Buffers a body whose declared length is small; streams everything else.
+async fn read_put_body(headers: &HeaderMap, body: Body) -> Result<PutObjectBody, S3Error> {
+    let content_length =
+        header_string(headers, "content-length").and_then(|raw| raw.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length <= BUFFERED_PUT_MAX_BYTES as u64) {
+        let body = axum::body::to_bytes(body, BUFFERED_PUT_MAX_BYTES)
+            .await
+            .map_err(|error| S3Error::invalid_request(error.to_string()))?;
+        return Ok(PutObjectBody::Buffered(body));
+    }
+
+    let expected_md5 = header_string(headers, "content-md5")
+        .map(|raw| decode_content_md5(&raw))
+        .transpose()?;
+    Ok(PutObjectBody::Streaming(
+        checked_body_stream(body, content_length, expected_md5).boxed(),
+    ))
+}
+
+/// Passes body chunks through, and fails at the end of the stream when the
+/// length or MD5 does not match what the headers declared.
+fn checked_body_stream(
+    body: Body,
+    content_length: Option<u64>,
+    expected_md5: Option<Vec<u8>>,
+) -> impl futures_util::Stream<Item = Result<Bytes, S3Error>> + Send {
+    let state = (body.into_data_stream(), Some(Md5::new()), 0u64);
+    futures_util::stream::unfold(state, move |(mut stream, mut hasher, received)| {
+        let expected_md5 = expected_md5.clone();
+        async move {
+            let md5 = hasher.as_mut()?;
+            match stream.next().await {
+                Some(Ok(chunk)) => {
+                    md5.update(&chunk);
+                    let received = received + chunk.len() as u64;
+                    Some((Ok(chunk), (stream, hasher, received)))
+                }
+                Some(Err(error)) => Some((
+                    Err(S3Error::invalid_request(error.to_string())),
+                    (stream, None, received),
+                )),
+                None => {
+                    let digest = hasher.take()?.finalize();
+                    let error = if content_length.is_some_and(|length| length != received) {
+                        S3Error::invalid_argument("content-length does not match request body size")
+                    } else if expected_md5
+                        .as_deref()
+                        .is_some_and(|expected| expected != digest.as_slice())
+                    {
+                        S3Error::new(StatusCode::BAD_REQUEST, "BadDigest", "Content-MD5 mismatch")
+                    } else {
+                        return None;
+                    };
+                    Some((Err(error), (stream, None, received)))
+                }
+            }
+        }
+    })
+}
 
+fn decode_content_md5(raw: &str) -> Result<Vec<u8>, S3Error> {
     let decoded = STANDARD.decode(raw.as_bytes()).map_err(|_| {
         S3Error::new(
             StatusCode::BAD_REQUEST,
@@ -649,6 +762,15 @@ fn validate_content_md5(content_md5: Option<&str>, body: &[u8]) -> Result<(), S3
         ));
     }
 
+    Ok(decoded)
+}
+
+fn validate_content_md5(content_md5: Option<&str>, body: &[u8]) -> Result<(), S3Error> {
+    let Some(raw) = content_md5 else {
+        return Ok(());
+    };
+
+    let decoded = decode_content_md5(raw)?;
     let actual = Md5::digest(body);
     if actual.as_slice() != decoded.as_slice() {
         return Err(S3Error::new(
@@ -1,6 +1,7 @@
-use crate::S3GatewayResult;
+use crate::{S3Error, S3GatewayResult};
 use async_trait::async_trait;
 use axum::body::Bytes;
+use futures_util::stream::BoxStream;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Copy)]
@@ -9,11 +10,39 @@ pub struct ByteRange {
     pub end: u64,
 }
 
-#[derive(Debug, Clone)]
+/// A `Range` request before the object size is known.
+#[derive(Debug, Clone, Copy)]
+pub enum ByteRangeSpec {
+    /// `bytes=start-end`
+    Bounded { start: u64, end: u64 },
+    /// `bytes=start-`
+    From { start: u64 },
+    /// `bytes=-length`: the last `length` bytes.
+    Suffix { length: u64 },
+}
+
+/// Small bodies are buffered; larger or unsized ones are handed to the
+/// backend as a stream, already checked against Content-Length and
+/// Content-MD5 (a mismatch surfaces as the stream's last item).
+pub enum PutObjectBody {
+    Buffered(Bytes),
+    Streaming(BoxStream<'static, Result<Bytes, S3Error>>),
+}
+
+impl std::fmt::Debug for PutObjectBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Buffered(bytes) => f.debug_tuple("Buffered").field(&bytes.len()).finish(),
+            Self::Streaming(_) => f.write_str("Streaming"),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct PutObjectRequest {
     pub bucket: String,
     pub key: String,
-    pub body: Bytes,
+    pub body: PutObjectBody,
     pub cache_control: Option<String>,
     pub content_disposition: Option<String>,
     pub content_encoding: Option<String>,
@@ -59,7 +88,7 @@ pub struct GetObjectRequest {
     pub if_unmodified_since: Option<String>,
     pub key: String,
     pub part_number: Option<u32>,
-    pub range: Option<ByteRange>,
+    pub range: Option<ByteRangeSpec>,
     pub request_payer: Option<String>,
     pub response_cache_control: Option<String>,
     pub response_content_disposition: Option<String>,
@@ -80,6 +109,11 @@ pub struct GetObjectResponse {
     pub last_modified: String,
     pub size_bytes: u64,
     pub body_range: Option<ByteRange>,
+    /// Extra headers configured for the object, e.g. `Content-Disposition`;
+    /// the request's `response-*` overrides still win over them.
+    pub headers: Vec<(String, String)>,
+    /// User metadata stored with the object, sent as `x-amz-meta-*`.
+    pub metadata: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone)]
@@ -92,6 +126,9 @@ pub struct HeadObjectRequest {
 pub struct HeadObjectResponse {
     pub etag: String,
     pub size_bytes: u64,
+    /// Headers stored with the object, e.g. `Content-Type`.
+    pub headers: Vec<(String, String)>,
+    pub metadata: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone)]
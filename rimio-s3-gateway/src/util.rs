@@ -1,9 +1,9 @@
-use crate::{ByteRange, ListObjectItem, S3Error, S3GatewayResult};
+use crate::{ByteRangeSpec, ListObjectItem, S3Error, S3GatewayResult};
 use axum::http::{HeaderMap, header};
 use base64::Engine;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 
-pub(crate) fn parse_range_header(headers: &HeaderMap) -> S3GatewayResult<Option<ByteRange>> {
+pub(crate) fn parse_range_header(headers: &HeaderMap) -> S3GatewayResult<Option<ByteRangeSpec>> {
     let Some(value) = headers.get(header::RANGE) else {
         return Ok(None);
     };
@@ -19,34 +19,49 @@ pub(crate) fn parse_range_header(headers: &HeaderMap) -> S3GatewayResult<Option<
 
     let Some(raw) = value.strip_prefix("bytes=") else {
         return Err(S3Error::invalid_argument(
-            "only bytes=start-end range format is supported",
+            "only bytes= range format is supported",
         ));
     };
-
-    let mut split = raw.splitn(2, '-');
-    let start_raw = split.next().unwrap_or_default().trim();
-    let end_raw = split.next().unwrap_or_default().trim();
-
-    if start_raw.is_empty() || end_raw.is_empty() {
+    if raw.contains(',') {
         return Err(S3Error::invalid_argument(
-            "only explicit bytes=start-end is supported",
+            "multiple ranges are not supported",
         ));
     }
 
-    let start = start_raw
-        .parse::<u64>()
-        .map_err(|_| S3Error::invalid_argument("invalid range start"))?;
-    let end = end_raw
-        .parse::<u64>()
-        .map_err(|_| S3Error::invalid_argument("invalid range end"))?;
-
-    if start > end {
-        return Err(S3Error::invalid_argument(
-            "range start must be <= range end",
-        ));
-    }
+    let Some((start_raw, end_raw)) = raw.split_once('-') else {
+        return Err(S3Error::invalid_argument("invalid Range header"));
+    };
+    let (start_raw, end_raw) = (start_raw.trim(), end_raw.trim());
+
+    let spec = match (start_raw.is_empty(), end_raw.is_empty()) {
+        (true, true) => return Err(S3Error::invalid_argument("invalid Range header")),
+        (true, false) => ByteRangeSpec::Suffix {
+            length: end_raw
+                .parse::<u64>()
+                .map_err(|_| S3Error::invalid_argument("invalid suffix length"))?,
+        },
+        (false, true) => ByteRangeSpec::From {
+            start: start_raw
+                .parse::<u64>()
+                .map_err(|_| S3Error::invalid_argument("invalid range start"))?,
+        },
+        (false, false) => {
+            let start = start_raw
+                .parse::<u64>()
+                .map_err(|_| S3Error::invalid_argument("invalid range start"))?;
+            let end = end_raw
+                .parse::<u64>()
+                .map_err(|_| S3Error::invalid_argument("invalid range end"))?;
+            if start > end {
+                return Err(S3Error::invalid_argument(
+                    "range start must be <= range end",
+                ));
+            }
+            ByteRangeSpec::Bounded { start, end }
+        }
+    };
 
-    Ok(Some(ByteRange { start, end }))
+    Ok(Some(spec))
 }
 
 pub(crate) fn decode_continuation_token(token: &str) -> S3GatewayResult<String> {
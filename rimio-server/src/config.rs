@@ -1,10 +1,16 @@
+use axum::http::{HeaderName, HeaderValue};
 use rimio_core::{
-    ClusterArchiveConfig, ClusterArchiveRedisConfig, ClusterArchiveS3Config,
-    ClusterArchiveS3Credentials, ClusterDiskConfig, ClusterInitRequest, ClusterInitScanConfig,
-    ClusterInitScanRedisConfig, ClusterNodeConfig, ClusterReplicationConfig, ClusterState,
-    RegistryBuilder, Result, RimError,
+    ApiKey, ArchiveTieringConfig, ClusterArchiveAzureConfig, ClusterArchiveConfig,
+    ClusterArchiveFsConfig, ClusterArchiveGcsConfig, ClusterArchiveRedisConfig,
+    ClusterArchiveS3Config, ClusterArchiveS3Credentials, ClusterDiskConfig, ClusterInitRequest,
+    ClusterInitScanConfig, ClusterInitScanRedisConfig, ClusterNodeConfig, ClusterReplicationConfig,
+    ClusterState, HeadSchemaPhase, HeatRebalanceConfig, HostPressureConfig, InternalGrpcConfig,
+    KeyShardingRule, PartMmapConfig, PrefixReplicationPolicy, ReadConsistency, RegistryBuilder,
+    Result, RimError, SlotRebalanceConfig, SqliteCheckpointConfig, SqliteIntegrityConfig,
+    VersionRetention, WideProbeMode, sharded_slot_for_key,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +20,68 @@ pub struct Config {
     pub archive: Option<ArchiveConfig>,
     #[serde(default)]
     pub init_scan: Option<InitScanConfig>,
+    /// Node-local; not part of the bootstrap state.
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+    /// Head table layout this node migrates its slots towards; node-local so
+    /// fleets can roll it out node by node.
+    #[serde(default)]
+    pub head_schema_target: HeadSchemaPhase,
+    /// Node-local; keeps a hash chain of the heads written to each path so
+    /// tampering with past metadata can be detected.
+    #[serde(default)]
+    pub head_chain: bool,
+    /// Node-local; serves small parts from memory maps when set.
+    #[serde(default)]
+    pub part_mmap: Option<PartMmapConfig>,
+    /// Node-local; `Cache-Control` sent with blob reads.
+    #[serde(default)]
+    pub cache_headers: Option<CacheHeadersConfig>,
+    /// Node-local; extra headers sent with blob reads.
+    #[serde(default)]
+    pub response_headers: Option<ResponseHeadersConfig>,
+    /// Node-local; enables signed checksum exports.
+    #[serde(default)]
+    pub audit_export: Option<AuditExportConfig>,
+    /// Node-local; sheds background work and low-priority requests when the
+    /// host runs short of CPU or memory.
+    #[serde(default)]
+    pub host_pressure: Option<HostPressureConfig>,
+    /// Node-local; moves hot slots off overloaded nodes. Only the healthy
+    /// node with the lowest id acts on it.
+    #[serde(default)]
+    pub heat_rebalance: Option<HeatRebalanceConfig>,
+    /// Node-local; spreads slot replicas over nodes that join, copying each
+    /// slot before it changes hands. Only the healthy node with the lowest id
+    /// acts on it.
+    #[serde(default)]
+    pub slot_rebalance: Option<SlotRebalanceConfig>,
+    /// Node-local; when slot WAL files are checkpointed.
+    #[serde(default)]
+    pub sqlite_checkpoint: Option<SqliteCheckpointConfig>,
+    /// Node-local; when slot databases are checked for corruption.
+    #[serde(default)]
+    pub sqlite_integrity: Option<SqliteIntegrityConfig>,
+    /// Node-local; serves and dials the internal gRPC transport.
+    #[serde(default)]
+    pub internal_grpc: Option<InternalGrpcConfig>,
+    /// Node-local; writes one line per HTTP request to a file, apart from
+    /// the tracing output.
+    #[serde(default)]
+    pub access_log: Option<AccessLogConfig>,
+    /// Node-local; publishes object events to webhooks or message buses.
+    #[serde(default)]
+    pub notifications: Option<NotificationsConfig>,
+    /// Node-local; serves the OCI distribution API for container images.
+    #[serde(default)]
+    pub oci: Option<OciConfig>,
+    /// Node-local; requires an API key or bearer token on requests.
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// Node-local; signs outgoing webhooks and mirrored requests and checks
+    /// the signatures of incoming ones.
+    #[serde(default)]
+    pub request_signing: Option<RequestSigningConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +102,14 @@ pub struct InitialNodeConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiskConfig {
     pub path: PathBuf,
+    /// Block device to read SMART data from (e.g. `/dev/sda`); health
+    /// monitoring is off for the disk when unset.
+    #[serde(default)]
+    pub smart_device: Option<String>,
+    /// Move this node's slots to other nodes once SMART predicts the disk
+    /// will fail.
+    #[serde(default)]
+    pub drain_on_failure: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +118,42 @@ pub struct RuntimeConfig {
     pub replication: ReplicationConfig,
     pub registry: RegistryConfig,
     pub archive: Option<ArchiveConfig>,
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+    #[serde(default)]
+    pub head_schema_target: HeadSchemaPhase,
+    #[serde(default)]
+    pub head_chain: bool,
+    #[serde(default)]
+    pub part_mmap: Option<PartMmapConfig>,
+    #[serde(default)]
+    pub cache_headers: Option<CacheHeadersConfig>,
+    #[serde(default)]
+    pub response_headers: Option<ResponseHeadersConfig>,
+    #[serde(default)]
+    pub audit_export: Option<AuditExportConfig>,
+    #[serde(default)]
+    pub host_pressure: Option<HostPressureConfig>,
+    #[serde(default)]
+    pub heat_rebalance: Option<HeatRebalanceConfig>,
+    #[serde(default)]
+    pub slot_rebalance: Option<SlotRebalanceConfig>,
+    #[serde(default)]
+    pub sqlite_checkpoint: Option<SqliteCheckpointConfig>,
+    #[serde(default)]
+    pub sqlite_integrity: Option<SqliteIntegrityConfig>,
+    #[serde(default)]
+    pub internal_grpc: Option<InternalGrpcConfig>,
+    #[serde(default)]
+    pub access_log: Option<AccessLogConfig>,
+    #[serde(default)]
+    pub notifications: Option<NotificationsConfig>,
+    #[serde(default)]
+    pub oci: Option<OciConfig>,
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    #[serde(default)]
+    pub request_signing: Option<RequestSigningConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,6 +189,8 @@ pub enum RegistryBackend {
     Etcd,
     Redis,
     Embed,
+    /// Kept in process memory; single node only, lost on restart.
+    Memory,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -106,6 +220,22 @@ pub struct ArchiveConfig {
     pub archive_type: String,
     pub s3: Option<S3Config>,
     pub redis: Option<ArchiveRedisConfig>,
+    /// Google Cloud Storage bucket, used when `archive_type` is `gcs`.
+    #[serde(default)]
+    pub gcs: Option<GcsConfig>,
+    /// Azure Blob container, used when `archive_type` is `azure`.
+    #[serde(default)]
+    pub azure: Option<AzureConfig>,
+    /// Directory, e.g. an NFS or USB mount, used when `archive_type` is `fs`.
+    #[serde(default)]
+    pub fs: Option<ArchiveFsConfig>,
+    /// Refuse writes while the archive is unreachable instead of accepting
+    /// blobs that only live on local disks.
+    #[serde(default)]
+    pub require_write_through: bool,
+    /// Moves cold parts to the archive; see [`ArchiveTieringConfig`].
+    #[serde(default)]
+    pub tiering: Option<ArchiveTieringConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -119,6 +249,13 @@ fn default_archive_redis_key_prefix() -> String {
     "rimio:archive".to_string()
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveFsConfig {
+    /// Absolute path of an existing directory; it is not created, so a
+    /// missing mount fails startup instead of filling the local disk.
+    pub path: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct S3Config {
     pub bucket: String,
@@ -136,10 +273,75 @@ pub struct S3Credentials {
     pub secret_access_key: String,
 }
 
+/// Without a service account the ambient Google credentials are used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcsConfig {
+    pub bucket: String,
+    #[serde(default)]
+    pub service_account_path: Option<String>,
+    #[serde(default)]
+    pub service_account_key: Option<String>,
+}
+
+/// Authenticates with `access_key` or `sas_token`; without either the
+/// ambient Azure credentials are used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AzureConfig {
+    pub account: String,
+    pub container: String,
+    #[serde(default)]
+    pub access_key: Option<String>,
+    #[serde(default)]
+    pub sas_token: Option<String>,
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    #[serde(default)]
+    pub allow_http: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReplicationConfig {
     pub min_write_replicas: usize,
     pub total_slots: u16,
+    /// Enables per-slot write leases with this TTL, so one replica serializes
+    /// the writes of each slot.
+    #[serde(default)]
+    pub write_lease_ttl_secs: Option<u64>,
+    /// Whether reads probe nodes outside the replica set before returning 404.
+    #[serde(default)]
+    pub wide_probe: WideProbeMode,
+    /// Default read consistency; `x-rimio-read-consistency` overrides it per
+    /// request.
+    #[serde(default)]
+    pub read_consistency: ReadConsistency,
+    /// Keeps paths under a prefix local-only or on fewer replicas; see
+    /// [`PrefixReplicationPolicy`].
+    #[serde(default)]
+    pub prefix_policies: Vec<PrefixReplicationPolicy>,
+    /// Routes paths by their `{tag}` so tagged paths share a slot. Off by
+    /// default: paths are hashed whole, and turning it on moves existing
+    /// keys that contain braces to other slots.
+    #[serde(default)]
+    pub hash_tags: bool,
+    /// Spreads tagged keys of a bucket over several slots; see
+    /// [`KeyShardingRule`]. Only applies with `hash_tags`.
+    #[serde(default)]
+    pub key_sharding: Vec<KeyShardingRule>,
+    /// Drops superseded versions and old tombstones; everything is kept when
+    /// unset.
+    #[serde(default)]
+    pub retention: VersionRetention,
+    /// Buckets or path prefixes (`medical/`) whose part files are
+    /// overwritten with random bytes before pruning unlinks them.
+    #[serde(default)]
+    pub secure_delete: Vec<String>,
+}
+
+impl ReplicationConfig {
+    /// Slot holding `path`, with the key sharding rules applied.
+    pub fn slot_for_key(&self, path: &str) -> u16 {
+        sharded_slot_for_key(path, self.total_slots, self.hash_tags, &self.key_sharding)
+    }
 }
 
 impl Default for ReplicationConfig {
@@ -147,6 +349,14 @@ impl Default for ReplicationConfig {
         Self {
             min_write_replicas: 3,
             total_slots: 2048,
+            write_lease_ttl_secs: None,
+            wide_probe: WideProbeMode::default(),
+            read_consistency: ReadConsistency::default(),
+            prefix_policies: Vec::new(),
+            hash_tags: false,
+            key_sharding: Vec::new(),
+            retention: VersionRetention::default(),
+            secure_delete: Vec::new(),
         }
     }
 }
@@ -170,12 +380,543 @@ fn default_init_scan_page_size() -> usize {
     500
 }
 
+/// Shadows a share of blob traffic to a secondary endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorConfig {
+    /// Base URL of the secondary cluster or endpoint.
+    pub endpoint: String,
+    /// Share of GET/HEAD requests mirrored, in percent.
+    #[serde(default)]
+    pub read_percent: f64,
+    /// Share of PUT/DELETE requests mirrored, in percent; off by default.
+    #[serde(default)]
+    pub write_percent: f64,
+    #[serde(default = "default_mirror_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_mirror_max_in_flight")]
+    pub max_in_flight: usize,
+    /// Larger PUTs (or ones without Content-Length) are not mirrored.
+    #[serde(default = "default_mirror_max_write_body_bytes")]
+    pub max_write_body_bytes: u64,
+}
+
+fn default_mirror_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_mirror_max_in_flight() -> usize {
+    64
+}
+
+fn default_mirror_max_write_body_bytes() -> u64 {
+    8 * 1024 * 1024
+}
+
+/// How long downstream HTTP caches may keep blob responses. The rule with
+/// the longest matching prefix wins; S3 buckets are the first path segment,
+/// so `photos/` covers the `photos` bucket.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheHeadersConfig {
+    /// Applies to paths no rule matches; no `Cache-Control` when unset.
+    #[serde(default)]
+    pub default: Option<CachePolicy>,
+    #[serde(default)]
+    pub rules: Vec<CacheRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheRule {
+    pub prefix: String,
+    #[serde(flatten)]
+    pub policy: CachePolicy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachePolicy {
+    /// `0` makes caches revalidate every response (`no-cache`).
+    pub max_age_secs: u64,
+    /// Keep responses out of shared caches such as CDNs.
+    #[serde(default)]
+    pub private: bool,
+    /// Objects under the prefix are never rewritten in place.
+    #[serde(default)]
+    pub immutable: bool,
+}
+
+impl CacheHeadersConfig {
+    pub fn cache_control(&self, path: &str) -> Option<String> {
+        self.rules
+            .iter()
+            .filter(|rule| path.starts_with(&rule.prefix))
+            .max_by_key(|rule| rule.prefix.len())
+            .map(|rule| &rule.policy)
+            .or(self.default.as_ref())
+            .map(CachePolicy::header_value)
+    }
+}
+
+impl CachePolicy {
+    fn header_value(&self) -> String {
+        if self.max_age_secs == 0 {
+            return "no-cache".to_string();
+        }
+        let scope = if self.private { "private" } else { "public" };
+        let mut value = format!("{}, max-age={}", scope, self.max_age_secs);
+        if self.immutable {
+            value.push_str(", immutable");
+        }
+        value
+    }
+}
+
+/// Headers added to blob GET and HEAD responses, such as
+/// `Content-Disposition` for downloads served straight to end users. The
+/// rule with the longest matching prefix wins, as for [`CacheHeadersConfig`];
+/// its headers replace `default` and take precedence over `Cache-Control`
+/// from `cache_headers`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResponseHeadersConfig {
+    #[serde(default)]
+    pub default: BTreeMap<String, String>,
+    #[serde(default)]
+    pub rules: Vec<ResponseHeaderRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseHeaderRule {
+    pub prefix: String,
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Headers that describe the body being sent, so neither a rule nor a
+/// query override may set them.
+const RESERVED_RESPONSE_HEADERS: &[&str] = &[
+    "accept-ranges",
+    "content-length",
+    "content-range",
+    "etag",
+    "last-modified",
+    "transfer-encoding",
+];
+
+impl ResponseHeadersConfig {
+    pub fn headers(&self, path: &str) -> &BTreeMap<String, String> {
+        self.rules
+            .iter()
+            .filter(|rule| path.starts_with(&rule.prefix))
+            .max_by_key(|rule| rule.prefix.len())
+            .map(|rule| &rule.headers)
+            .unwrap_or(&self.default)
+    }
+
+    /// Checks every configured header is a valid, non-reserved header.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let sets = std::iter::once(("default", &self.default)).chain(
+            self.rules
+                .iter()
+                .map(|rule| (rule.prefix.as_str(), &rule.headers)),
+        );
+        for (scope, headers) in sets {
+            for (name, value) in headers {
+                let parsed = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| format!("invalid response header name '{}' in {}", name, scope))?;
+                if RESERVED_RESPONSE_HEADERS.contains(&parsed.as_str()) {
+                    return Err(format!(
+                        "response header '{}' in {} cannot be configured",
+                        name, scope
+                    ));
+                }
+                HeaderValue::from_str(value).map_err(|_| {
+                    format!("invalid value for response header '{}' in {}", name, scope)
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checksum exports for external audit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditExportConfig {
+    /// HMAC-SHA256 key the export is signed with; auditors verify the
+    /// signature with the same key.
+    pub signing_key: String,
+}
+
+/// HTTP access log, kept apart from tracing output so log tooling can read
+/// it as is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessLogConfig {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// The file is rotated once it would grow past this size.
+    #[serde(default = "default_access_log_max_size_mb")]
+    pub max_size_mb: u64,
+    /// Rotated files kept next to the live one, as `<path>.1` (newest) up
+    /// to `<path>.<max_files>`.
+    #[serde(default = "default_access_log_max_files")]
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// Common Log Format.
+    #[default]
+    Common,
+    /// Common Log Format followed by the referer and user agent.
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+fn default_access_log_max_size_mb() -> u64 {
+    100
+}
+
+fn default_access_log_max_files() -> usize {
+    5
+}
+
+/// Serves the OCI distribution API (`/v2/`) on top of blob paths under
+/// `prefix`, so container runtimes can pull images from the cluster.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OciConfig {
+    #[serde(default = "default_oci_prefix")]
+    pub prefix: String,
+}
+
+impl OciConfig {
+    /// The prefix without surrounding slashes. It may not contain `{`, which
+    /// would take over the hash tags that keep a repository in one slot, and
+    /// those tags only route with `replication.hash_tags` on.
+    pub fn validate(&self, hash_tags: bool) -> std::result::Result<(), String> {
+        if !hash_tags {
+            return Err("oci requires replication.hash_tags".to_string());
+        }
+        let prefix = self.prefix.trim_matches('/');
+        if prefix.is_empty() {
+            return Err("oci.prefix cannot be empty".to_string());
+        }
+        if prefix.contains(['{', '}']) {
+            return Err(format!("oci.prefix '{}' cannot contain braces", prefix));
+        }
+        Ok(())
+    }
+}
+
+fn default_oci_prefix() -> String {
+    "oci".to_string()
+}
+
+/// Requires an API key or a JWT bearer token on every request outside
+/// `public_prefixes`. `keys` are accepted besides those kept in the registry,
+/// so an operator key can manage the rest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub keys: Vec<ApiKey>,
+    #[serde(default = "default_auth_public_prefixes")]
+    pub public_prefixes: Vec<String>,
+    /// Shared by every node, which present it on calls to each other's
+    /// `/internal/` routes. Those also admit a key granted the path.
+    #[serde(default)]
+    pub cluster_key: Option<String>,
+    /// Leaves `/internal/` open to anyone who can reach it, for clusters
+    /// whose internal routes sit on a private network.
+    #[serde(default)]
+    pub public_internal: bool,
+    /// How often the registry keys are re-read.
+    #[serde(default = "default_auth_refresh_secs")]
+    pub refresh_secs: u64,
+    /// Lets keys mint one-time tokens for uploading a single blob.
+    #[serde(default)]
+    pub upload_tokens: Option<UploadTokensConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadTokensConfig {
+    /// Signs the tokens; every node needs the same one.
+    pub secret: String,
+    /// Longest lifetime a token may be minted with.
+    #[serde(default = "default_upload_token_max_ttl_secs")]
+    pub max_ttl_secs: u64,
+}
+
+impl AuthConfig {
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        rimio_core::validate_api_keys(&self.keys).map_err(|error| error.to_string())?;
+        if let Some(prefix) = self
+            .public_prefixes
+            .iter()
+            .find(|prefix| !prefix.starts_with('/'))
+        {
+            return Err(format!(
+                "auth.public_prefixes entry '{}' must start with '/'",
+                prefix
+            ));
+        }
+        if self.refresh_secs == 0 {
+            return Err("auth.refresh_secs must be greater than 0".to_string());
+        }
+        match self.cluster_key.as_deref() {
+            Some("") => return Err("auth.cluster_key cannot be empty".to_string()),
+            Some(key) if HeaderValue::from_str(key).is_err() => {
+                return Err("auth.cluster_key must be printable ASCII".to_string());
+            }
+            None if !self.public_internal => {
+                return Err(
+                    "auth.cluster_key is required so peers can reach /internal/; \
+                     set auth.public_internal to leave those routes open instead"
+                        .to_string(),
+                );
+            }
+            _ => {}
+        }
+        if let Some(upload_tokens) = &self.upload_tokens {
+            if upload_tokens.secret.is_empty() {
+                return Err("auth.upload_tokens.secret cannot be empty".to_string());
+            }
+            if upload_tokens.max_ttl_secs == 0 {
+                return Err("auth.upload_tokens.max_ttl_secs must be greater than 0".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Health and readiness probes.
+pub(crate) fn default_auth_public_prefixes() -> Vec<String> {
+    [
+        "/health",
+        "/_/health",
+        "/_/api/v1/healthz",
+        "/_/api/v1/readyz",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect()
+}
+
+pub(crate) fn default_auth_refresh_secs() -> u64 {
+    10
+}
+
+fn default_upload_token_max_ttl_secs() -> u64 {
+    3_600
+}
+
+/// HMAC signatures on requests that cross a trust boundary. Outgoing
+/// webhooks and mirrored requests are signed with `secret` under `key_id`;
+/// incoming requests that carry a signature are checked against that key
+/// and `trusted_keys`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestSigningConfig {
+    pub key_id: String,
+    pub secret: String,
+    /// Keys of other senders, e.g. the edge clusters mirroring here.
+    #[serde(default)]
+    pub trusted_keys: Vec<SigningKey>,
+    /// Requests under these prefixes are refused unless signed.
+    #[serde(default)]
+    pub require_prefixes: Vec<String>,
+    /// How far a signature's timestamp may be from this node's clock.
+    #[serde(default = "default_signature_max_skew_secs")]
+    pub max_skew_secs: u64,
+    /// Largest body a signed request may carry; it is buffered to be checked.
+    #[serde(default = "default_signed_body_max_bytes")]
+    pub max_body_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningKey {
+    pub key_id: String,
+    pub secret: String,
+}
+
+impl RequestSigningConfig {
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let mut key_ids = std::collections::HashSet::new();
+        let keys = std::iter::once((&self.key_id, &self.secret)).chain(
+            self.trusted_keys
+                .iter()
+                .map(|key| (&key.key_id, &key.secret)),
+        );
+        for (key_id, secret) in keys {
+            if key_id.trim().is_empty() {
+                return Err("request_signing key_id cannot be empty".to_string());
+            }
+            if secret.is_empty() {
+                return Err(format!(
+                    "request_signing key '{}' has an empty secret",
+                    key_id
+                ));
+            }
+            if !key_ids.insert(key_id) {
+                return Err(format!("duplicate request_signing key_id '{}'", key_id));
+            }
+        }
+        if let Some(prefix) = self
+            .require_prefixes
+            .iter()
+            .find(|prefix| !prefix.starts_with('/'))
+        {
+            return Err(format!(
+                "request_signing.require_prefixes entry '{}' must start with '/'",
+                prefix
+            ));
+        }
+        if self.max_skew_secs == 0 {
+            return Err("request_signing.max_skew_secs must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn default_signature_max_skew_secs() -> u64 {
+    300
+}
+
+fn default_signed_body_max_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+/// Publishes object events of some prefixes to external endpoints. Each
+/// rule follows the change feed of the slots whose primary is this node and
+/// keeps its own cursor, so events are delivered at least once and, per
+/// slot, in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    #[serde(default = "default_notification_interval_ms")]
+    pub interval_ms: u64,
+    /// Change-feed entries read per slot and rule on each pass.
+    #[serde(default = "default_notification_batch_size")]
+    pub batch_size: usize,
+    /// Replicas that are not primary keep their cursor this far behind, so
+    /// after a failover the new primary re-sends what the old one may have
+    /// missed.
+    #[serde(default = "default_notification_failover_overlap_secs")]
+    pub failover_overlap_secs: u64,
+    pub rules: Vec<NotificationRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationRule {
+    /// Names the rule's cursors; a renamed rule starts over from the newest
+    /// change.
+    pub name: String,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default = "default_notification_events")]
+    pub events: Vec<NotificationEvent>,
+    pub target: NotificationTarget,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationEvent {
+    /// A blob was written, whether or not the path existed before.
+    Created,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotificationTarget {
+    /// `POST`s each event as JSON; any 2xx acknowledges it.
+    Webhook {
+        url: String,
+        #[serde(default = "default_notification_timeout_ms")]
+        timeout_ms: u64,
+    },
+    /// Produces to a topic through a Kafka REST proxy, keyed by path.
+    Kafka {
+        rest_url: String,
+        topic: String,
+        #[serde(default = "default_notification_timeout_ms")]
+        timeout_ms: u64,
+    },
+    /// Core NATS publish; an event counts as delivered once the server
+    /// answered the PING sent after it.
+    Nats {
+        addr: String,
+        subject: String,
+        #[serde(default = "default_notification_timeout_ms")]
+        timeout_ms: u64,
+    },
+    /// MQTT 3.1.1 publish at QoS 1.
+    Mqtt {
+        addr: String,
+        topic: String,
+        #[serde(default)]
+        client_id: Option<String>,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        #[serde(default = "default_notification_timeout_ms")]
+        timeout_ms: u64,
+    },
+}
+
+fn default_notification_interval_ms() -> u64 {
+    1_000
+}
+
+fn default_notification_batch_size() -> usize {
+    500
+}
+
+fn default_notification_failover_overlap_secs() -> u64 {
+    60
+}
+
+fn default_notification_events() -> Vec<NotificationEvent> {
+    vec![NotificationEvent::Created, NotificationEvent::Deleted]
+}
+
+fn default_notification_timeout_ms() -> u64 {
+    5_000
+}
+
 pub type BootstrapState = ClusterState;
 
+/// Built-in defaults for a common deployment shape. The config file and
+/// `RIMIO_*` variables are layered on top, so any key they set wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConfigProfile {
+    /// One node with the embedded registry; needs no config file.
+    Dev,
+    /// Three small nodes at an edge site.
+    Edge,
+    /// Local disks caching an archive that holds every blob.
+    ArchiveCache,
+}
+
+impl ConfigProfile {
+    fn defaults(self) -> &'static str {
+        match self {
+            Self::Dev => include_str!("../profiles/dev.yaml"),
+            Self::Edge => include_str!("../profiles/edge.yaml"),
+            Self::ArchiveCache => include_str!("../profiles/archive-cache.yaml"),
+        }
+    }
+}
+
 impl Config {
-    pub fn from_file(path: &str) -> Result<Self> {
-        let settings = ::config::Config::builder()
-            .add_source(::config::File::with_name(path))
+    /// Loads `path` over the defaults of `profile`. The file may be missing
+    /// when a profile is given.
+    pub fn from_file(path: &str, profile: Option<ConfigProfile>) -> Result<Self> {
+        let mut builder = ::config::Config::builder();
+        if let Some(profile) = profile {
+            builder = builder.add_source(::config::File::from_str(
+                profile.defaults(),
+                ::config::FileFormat::Yaml,
+            ));
+        }
+        let settings = builder
+            .add_source(::config::File::with_name(path).required(profile.is_none()))
             .add_source(::config::Environment::with_prefix("RIMIO"))
             .build()
             .map_err(|e| RimError::Config(e.to_string()))?;
@@ -187,6 +928,35 @@ impl Config {
         Ok(config)
     }
 
+    /// Narrows the config to a single throwaway node for `server --dev`: an
+    /// in-memory registry, one replica, and `data_dir` as its only disk.
+    pub fn into_dev(mut self, node_id: &str, data_dir: PathBuf) -> Result<Self> {
+        if self.initial_cluster.nodes.is_empty() {
+            return Err(RimError::Config(
+                "dev mode needs a node in initial_cluster".to_string(),
+            ));
+        }
+
+        let position = self
+            .initial_cluster
+            .nodes
+            .iter()
+            .position(|node| node.node_id == node_id)
+            .unwrap_or(0);
+
+        let mut node = self.initial_cluster.nodes.swap_remove(position);
+        node.node_id = node_id.to_string();
+        node.disks = vec![DiskConfig {
+            path: data_dir,
+            smart_device: None,
+            drain_on_failure: false,
+        }];
+        self.initial_cluster.nodes = vec![node];
+        self.initial_cluster.replication.min_write_replicas = 1;
+        self.registry.backend = RegistryBackend::Memory;
+        Ok(self)
+    }
+
     #[allow(dead_code)]
     pub fn to_init_cluster_request(&self) -> ClusterInitRequest {
         self.to_init_cluster_request_for_node("")
@@ -208,6 +978,8 @@ impl Config {
                         .iter()
                         .map(|disk| ClusterDiskConfig {
                             path: disk.path.clone(),
+                            smart_device: disk.smart_device.clone(),
+                            drain_on_failure: disk.drain_on_failure,
                         })
                         .collect(),
                 })
@@ -215,6 +987,14 @@ impl Config {
             replication: ClusterReplicationConfig {
                 min_write_replicas: self.initial_cluster.replication.min_write_replicas,
                 total_slots: self.initial_cluster.replication.total_slots,
+                write_lease_ttl_secs: self.initial_cluster.replication.write_lease_ttl_secs,
+                wide_probe: self.initial_cluster.replication.wide_probe,
+                read_consistency: self.initial_cluster.replication.read_consistency,
+                prefix_policies: self.initial_cluster.replication.prefix_policies.clone(),
+                hash_tags: self.initial_cluster.replication.hash_tags,
+                key_sharding: self.initial_cluster.replication.key_sharding.clone(),
+                retention: self.initial_cluster.replication.retention.clone(),
+                secure_delete: self.initial_cluster.replication.secure_delete.clone(),
             },
             archive: self.archive.as_ref().map(|archive| ClusterArchiveConfig {
                 archive_type: archive.archive_type.clone(),
@@ -235,6 +1015,27 @@ impl Config {
                         url: redis.url.clone(),
                         key_prefix: redis.key_prefix.clone(),
                     }),
+                gcs: archive.gcs.as_ref().map(|gcs| ClusterArchiveGcsConfig {
+                    bucket: gcs.bucket.clone(),
+                    service_account_path: gcs.service_account_path.clone(),
+                    service_account_key: gcs.service_account_key.clone(),
+                }),
+                azure: archive
+                    .azure
+                    .as_ref()
+                    .map(|azure| ClusterArchiveAzureConfig {
+                        account: azure.account.clone(),
+                        container: azure.container.clone(),
+                        access_key: azure.access_key.clone(),
+                        sas_token: azure.sas_token.clone(),
+                        endpoint: azure.endpoint.clone(),
+                        allow_http: azure.allow_http,
+                    }),
+                fs: archive.fs.as_ref().map(|fs| ClusterArchiveFsConfig {
+                    path: fs.path.clone(),
+                }),
+                require_write_through: archive.require_write_through,
+                tiering: archive.tiering.clone(),
             }),
             init_scan: self.init_scan.as_ref().map(|scan| ClusterInitScanConfig {
                 enabled: scan.enabled,
@@ -247,6 +1048,11 @@ impl Config {
         }
     }
 
+    /// The key this node presents to its peers' internal routes.
+    pub fn cluster_key(&self) -> Option<&str> {
+        self.auth.as_ref()?.cluster_key.as_deref()
+    }
+
     #[allow(dead_code)]
     pub fn registry_builder(&self) -> RegistryBuilder {
         self.registry_builder_for_node("")
@@ -287,7 +1093,8 @@ impl Config {
                     .backend("embed")
                     .embed_transport("openraft")
                     .embed_node_id(node_id.to_string())
-                    .embed_seeds(embed.seeds);
+                    .embed_seeds(embed.seeds)
+                    .cluster_key(self.cluster_key().map(str::to_string));
 
                 if let Some(node) = self
                     .initial_cluster
@@ -311,6 +1118,7 @@ impl Config {
 
                 builder
             }
+            RegistryBackend::Memory => builder.backend("memory"),
         }
     }
 
@@ -353,12 +1161,22 @@ impl Config {
                     .iter()
                     .map(|disk| DiskConfig {
                         path: disk.path.clone(),
+                        smart_device: disk.smart_device.clone(),
+                        drain_on_failure: disk.drain_on_failure,
                     })
                     .collect(),
             },
             replication: ReplicationConfig {
                 min_write_replicas: bootstrap.replication.min_write_replicas,
                 total_slots: bootstrap.replication.total_slots,
+                write_lease_ttl_secs: bootstrap.replication.write_lease_ttl_secs,
+                wide_probe: bootstrap.replication.wide_probe,
+                read_consistency: bootstrap.replication.read_consistency,
+                prefix_policies: bootstrap.replication.prefix_policies.clone(),
+                hash_tags: bootstrap.replication.hash_tags,
+                key_sharding: bootstrap.replication.key_sharding.clone(),
+                retention: bootstrap.replication.retention.clone(),
+                secure_delete: bootstrap.replication.secure_delete.clone(),
             },
             registry,
             archive: bootstrap.archive.as_ref().map(|archive| ArchiveConfig {
@@ -377,7 +1195,43 @@ impl Config {
                     url: redis.url.clone(),
                     key_prefix: redis.key_prefix.clone(),
                 }),
+                gcs: archive.gcs.as_ref().map(|gcs| GcsConfig {
+                    bucket: gcs.bucket.clone(),
+                    service_account_path: gcs.service_account_path.clone(),
+                    service_account_key: gcs.service_account_key.clone(),
+                }),
+                azure: archive.azure.as_ref().map(|azure| AzureConfig {
+                    account: azure.account.clone(),
+                    container: azure.container.clone(),
+                    access_key: azure.access_key.clone(),
+                    sas_token: azure.sas_token.clone(),
+                    endpoint: azure.endpoint.clone(),
+                    allow_http: azure.allow_http,
+                }),
+                fs: archive.fs.as_ref().map(|fs| ArchiveFsConfig {
+                    path: fs.path.clone(),
+                }),
+                require_write_through: archive.require_write_through,
+                tiering: archive.tiering.clone(),
             }),
+            mirror: None,
+            head_schema_target: HeadSchemaPhase::default(),
+            head_chain: false,
+            part_mmap: None,
+            cache_headers: None,
+            response_headers: None,
+            audit_export: None,
+            host_pressure: None,
+            heat_rebalance: None,
+            slot_rebalance: None,
+            sqlite_checkpoint: None,
+            sqlite_integrity: None,
+            internal_grpc: None,
+            access_log: None,
+            notifications: None,
+            oci: None,
+            auth: None,
+            request_signing: None,
         })
     }
 }
@@ -1,13 +1,14 @@
 mod config;
 use clap::{Parser, Subcommand};
-use config::Config;
+use config::{Config, ConfigProfile};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
 mod server;
+mod wipe;
 use rimio_core::InitClusterOperation;
 use serde::Deserialize;
-use server::run_server;
+use server::{run_server, run_snapshot_server};
 
 #[derive(Parser)]
 #[command(name = "rimio")]
@@ -25,6 +26,10 @@ enum Commands {
         #[arg(short, long, default_value = "config.yaml")]
         config: String,
 
+        /// Built-in defaults the config file is layered on
+        #[arg(long, value_enum)]
+        profile: Option<ConfigProfile>,
+
         /// Override current_node from config at runtime
         #[arg(long)]
         current_node: Option<String>,
@@ -32,6 +37,11 @@ enum Commands {
         /// Run initialization flow only, then exit
         #[arg(long)]
         init: bool,
+
+        /// Single throwaway node: in-memory registry, one replica and a
+        /// temporary data directory removed on Ctrl-C
+        #[arg(long)]
+        dev: bool,
     },
     /// Start node with config and auto init-or-join behavior
     Start {
@@ -39,6 +49,10 @@ enum Commands {
         #[arg(long = "conf", default_value = "config.yaml")]
         conf: String,
 
+        /// Built-in defaults the config file is layered on
+        #[arg(long, value_enum)]
+        profile: Option<ConfigProfile>,
+
         /// Current node id
         #[arg(long)]
         node: String,
@@ -67,6 +81,58 @@ enum Commands {
         /// Allow takeover for suspect same node (not yet fully implemented)
         #[arg(long = "force-takeover", default_value_t = false)]
         force_takeover: bool,
+
+        /// The cluster's `auth.cluster_key`, when it runs with auth
+        #[arg(long = "cluster-key")]
+        cluster_key: Option<String>,
+    },
+    /// Serve an imported snapshot read-only, without a registry or write path
+    ServeSnapshot {
+        /// Snapshot directory holding manifest.json and parts/
+        #[arg(long)]
+        dir: String,
+
+        /// Listen address
+        #[arg(long, default_value = "0.0.0.0:19080")]
+        listen: String,
+    },
+    /// Upgrade stopped disks to the current data layout ahead of a restart
+    MigrateLayout {
+        /// Data directory of a disk; repeat for every disk of the node
+        #[arg(long = "disk", required = true)]
+        disks: Vec<String>,
+    },
+    /// Drain a running node and remove it from the registry
+    Decommission {
+        /// Address of the node to decommission
+        #[arg(long)]
+        addr: String,
+
+        /// Only show which slots would move where
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Factory-reset a stopped, decommissioned node: deregister it and delete its data
+    Wipe {
+        /// Path to configuration file
+        #[arg(long = "conf", default_value = "config.yaml")]
+        conf: String,
+
+        /// Built-in defaults the config file is layered on
+        #[arg(long, value_enum)]
+        profile: Option<ConfigProfile>,
+
+        /// Node id to wipe
+        #[arg(long)]
+        node: String,
+
+        /// Actually delete; without it only the plan is shown
+        #[arg(long)]
+        confirm: bool,
+
+        /// Overwrite every file with random bytes before deleting it
+        #[arg(long)]
+        shred: bool,
     },
 }
 
@@ -77,6 +143,7 @@ struct JoinInvocation {
     listen: Option<String>,
     advertise_addr: Option<String>,
     force_takeover: bool,
+    cluster_key: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -242,6 +309,7 @@ fn should_check_active_node_conflict(target: &JoinRegistryTarget) -> bool {
 
 async fn fetch_bootstrap_state_from_embed_seeds(
     seeds: &[String],
+    cluster_key: Option<&str>,
 ) -> std::result::Result<(String, rimio_core::ClusterState), String> {
     if seeds.is_empty() {
         return Err("cluster:// registry_url has no seeds".to_string());
@@ -249,6 +317,7 @@ async fn fetch_bootstrap_state_from_embed_seeds(
 
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(5))
+        .default_headers(rimio_core::cluster_key_headers(cluster_key))
         .build()
         .map_err(|error| format!("failed to build HTTP client: {}", error))?;
 
@@ -363,6 +432,47 @@ fn apply_join_overrides(
     Ok(())
 }
 
+async fn run_dev(
+    config_path: &str,
+    profile: Option<ConfigProfile>,
+    current_node: Option<String>,
+    init_only: bool,
+) {
+    let node_id = current_node.unwrap_or_else(|| "dev".to_string());
+    let data_dir = std::env::temp_dir().join(format!("rimio-dev-{}", ulid::Ulid::new()));
+    let cfg = match Config::from_file(config_path, Some(profile.unwrap_or(ConfigProfile::Dev)))
+        .and_then(|cfg| cfg.into_dev(&node_id, data_dir.clone()))
+    {
+        Ok(cfg) => cfg,
+        Err(error) => {
+            tracing::error!("Failed to load config: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    tracing::warn!(
+        "Dev mode: in-memory registry, one replica, data under {}; nothing survives a restart",
+        data_dir.display()
+    );
+
+    tokio::select! {
+        _ = run_with_config(cfg, &node_id, init_only) => {}
+        _ = tokio::signal::ctrl_c() => {
+            tracing::info!("Stopping dev node {}", node_id);
+        }
+    }
+
+    if let Err(error) = std::fs::remove_dir_all(&data_dir)
+        && error.kind() != std::io::ErrorKind::NotFound
+    {
+        tracing::warn!(
+            "Failed to remove dev data {}: {}",
+            data_dir.display(),
+            error
+        );
+    }
+}
+
 async fn run_with_config(mut cfg: Config, current_node: &str, init_only: bool) {
     cfg.initial_cluster
         .nodes
@@ -401,7 +511,7 @@ async fn run_with_config(mut cfg: Config, current_node: &str, init_only: bool) {
         return;
     }
 
-    let runtime_config = match config::Config::runtime_from_bootstrap_for_node(
+    let mut runtime_config = match config::Config::runtime_from_bootstrap_for_node(
         &init_result.bootstrap_state,
         current_node,
         cfg.registry.clone(),
@@ -412,6 +522,48 @@ async fn run_with_config(mut cfg: Config, current_node: &str, init_only: bool) {
             std::process::exit(1);
         }
     };
+    runtime_config.mirror = cfg.mirror.clone();
+    runtime_config.head_schema_target = cfg.head_schema_target;
+    runtime_config.head_chain = cfg.head_chain;
+    runtime_config.part_mmap = cfg.part_mmap;
+    runtime_config.cache_headers = cfg.cache_headers.clone();
+    runtime_config.response_headers = cfg.response_headers.clone();
+    runtime_config.audit_export = cfg.audit_export.clone();
+    runtime_config.host_pressure = cfg.host_pressure;
+    runtime_config.heat_rebalance = cfg.heat_rebalance;
+    runtime_config.slot_rebalance = cfg.slot_rebalance;
+    runtime_config.sqlite_checkpoint = cfg.sqlite_checkpoint;
+    runtime_config.sqlite_integrity = cfg.sqlite_integrity;
+    runtime_config.internal_grpc = cfg.internal_grpc.clone();
+    runtime_config.access_log = cfg.access_log.clone();
+    runtime_config.notifications = cfg.notifications.clone();
+    runtime_config.oci = cfg.oci.clone();
+    runtime_config.auth = cfg.auth.clone();
+    runtime_config.request_signing = cfg.request_signing.clone();
+    if let Some(response_headers) = runtime_config.response_headers.as_ref()
+        && let Err(message) = response_headers.validate()
+    {
+        tracing::error!("Invalid response_headers config: {}", message);
+        std::process::exit(2);
+    }
+    if let Some(oci) = runtime_config.oci.as_ref()
+        && let Err(message) = oci.validate(runtime_config.replication.hash_tags)
+    {
+        tracing::error!("Invalid oci config: {}", message);
+        std::process::exit(2);
+    }
+    if let Some(auth) = runtime_config.auth.as_ref()
+        && let Err(message) = auth.validate()
+    {
+        tracing::error!("Invalid auth config: {}", message);
+        std::process::exit(2);
+    }
+    if let Some(request_signing) = runtime_config.request_signing.as_ref()
+        && let Err(message) = request_signing.validate()
+    {
+        tracing::error!("Invalid request_signing config: {}", message);
+        std::process::exit(2);
+    }
 
     tracing::info!(
         "Node ID: {}, Bind: {}, Slots: {}",
@@ -461,19 +613,50 @@ async fn run_join(join: JoinInvocation) {
                 advertise_addr: join.advertise_addr.clone(),
                 disks: vec![config::DiskConfig {
                     path: std::path::PathBuf::from("./demo/join-placeholder"),
+                    smart_device: None,
+                    drain_on_failure: false,
                 }],
             }],
             replication: config::ReplicationConfig::default(),
         },
         archive: None,
         init_scan: None,
+        mirror: None,
+        head_schema_target: rimio_core::HeadSchemaPhase::default(),
+        head_chain: false,
+        part_mmap: None,
+        cache_headers: None,
+        response_headers: None,
+        audit_export: None,
+        host_pressure: None,
+        heat_rebalance: None,
+        slot_rebalance: None,
+        sqlite_checkpoint: None,
+        sqlite_integrity: None,
+        internal_grpc: None,
+        access_log: None,
+        notifications: None,
+        oci: None,
+        // The joined node takes its keys from the registry.
+        auth: join
+            .cluster_key
+            .clone()
+            .map(|cluster_key| config::AuthConfig {
+                keys: Vec::new(),
+                public_prefixes: config::default_auth_public_prefixes(),
+                cluster_key: Some(cluster_key),
+                public_internal: false,
+                refresh_secs: config::default_auth_refresh_secs(),
+                upload_tokens: None,
+            }),
+        request_signing: None,
     };
 
     let mut preflight_registry: Option<std::sync::Arc<dyn rimio_core::Registry>> = None;
 
     let bootstrap_state: rimio_core::ClusterState = match &registry_target {
         JoinRegistryTarget::Embed { seeds } => {
-            match fetch_bootstrap_state_from_embed_seeds(seeds).await {
+            match fetch_bootstrap_state_from_embed_seeds(seeds, join.cluster_key.as_deref()).await {
                 Ok((namespace, state)) => {
                     cfg.registry.namespace = Some(namespace);
                     state
@@ -538,6 +721,8 @@ async fn run_join(join: JoinInvocation) {
                 .iter()
                 .map(|disk| config::DiskConfig {
                     path: disk.path.clone(),
+                    smart_device: disk.smart_device.clone(),
+                    drain_on_failure: disk.drain_on_failure,
                 })
                 .collect(),
         })
@@ -545,6 +730,14 @@ async fn run_join(join: JoinInvocation) {
     cfg.initial_cluster.replication = config::ReplicationConfig {
         min_write_replicas: bootstrap_state.replication.min_write_replicas,
         total_slots: bootstrap_state.replication.total_slots,
+        write_lease_ttl_secs: bootstrap_state.replication.write_lease_ttl_secs,
+        wide_probe: bootstrap_state.replication.wide_probe,
+        read_consistency: bootstrap_state.replication.read_consistency,
+        prefix_policies: bootstrap_state.replication.prefix_policies.clone(),
+        hash_tags: bootstrap_state.replication.hash_tags,
+        key_sharding: bootstrap_state.replication.key_sharding.clone(),
+        retention: bootstrap_state.replication.retention.clone(),
+        secure_delete: bootstrap_state.replication.secure_delete.clone(),
     };
     cfg.archive = bootstrap_state
         .archive
@@ -568,6 +761,24 @@ async fn run_join(join: JoinInvocation) {
                     url: redis.url.clone(),
                     key_prefix: redis.key_prefix.clone(),
                 }),
+            gcs: archive.gcs.as_ref().map(|gcs| config::GcsConfig {
+                bucket: gcs.bucket.clone(),
+                service_account_path: gcs.service_account_path.clone(),
+                service_account_key: gcs.service_account_key.clone(),
+            }),
+            azure: archive.azure.as_ref().map(|azure| config::AzureConfig {
+                account: azure.account.clone(),
+                container: azure.container.clone(),
+                access_key: azure.access_key.clone(),
+                sas_token: azure.sas_token.clone(),
+                endpoint: azure.endpoint.clone(),
+                allow_http: azure.allow_http,
+            }),
+            fs: archive.fs.as_ref().map(|fs| config::ArchiveFsConfig {
+                path: fs.path.clone(),
+            }),
+            require_write_through: archive.require_write_through,
+            tiering: archive.tiering.clone(),
         });
 
     if let Err(message) = apply_join_overrides(&mut cfg, &join) {
@@ -576,7 +787,7 @@ async fn run_join(join: JoinInvocation) {
     }
 
     let current = join.node.clone();
-    let runtime_cfg = match config::Config::runtime_from_bootstrap_for_node(
+    let mut runtime_cfg = match config::Config::runtime_from_bootstrap_for_node(
         &bootstrap_state,
         &current,
         cfg.registry.clone(),
@@ -588,6 +799,14 @@ async fn run_join(join: JoinInvocation) {
         }
     };
 
+    runtime_cfg.auth = cfg.auth.clone();
+    if let Some(auth) = runtime_cfg.auth.as_ref()
+        && let Err(message) = auth.validate()
+    {
+        tracing::error!("join validation failed: {}", message);
+        std::process::exit(2);
+    }
+
     if should_check_active_node_conflict(&registry_target) {
         let registry =
             preflight_registry.expect("preflight registry must exist for non-embed target");
@@ -648,12 +867,19 @@ async fn main() {
     match cli.command {
         Commands::Server {
             config,
+            profile,
             current_node,
             init,
+            dev,
         } => {
+            if dev {
+                run_dev(&config, profile, current_node, init).await;
+                return;
+            }
+
             tracing::info!("Starting Rimio server with config: {}", config);
 
-            let cfg = match Config::from_file(&config) {
+            let cfg = match Config::from_file(&config, profile) {
                 Ok(c) => c,
                 Err(error) => {
                     tracing::error!("Failed to load config: {}", error);
@@ -672,10 +898,15 @@ async fn main() {
             );
             std::process::exit(2);
         }
-        Commands::Start { conf, node, init } => {
+        Commands::Start {
+            conf,
+            profile,
+            node,
+            init,
+        } => {
             tracing::info!("Starting Rimio with start command, config: {}", conf);
 
-            let cfg = match Config::from_file(&conf) {
+            let cfg = match Config::from_file(&conf, profile) {
                 Ok(c) => c,
                 Err(error) => {
                     tracing::error!("Failed to load config: {}", error);
@@ -691,6 +922,7 @@ async fn main() {
             listen,
             advertise_addr,
             force_takeover,
+            cluster_key,
         } => {
             run_join(JoinInvocation {
                 registry_url,
@@ -698,8 +930,96 @@ async fn main() {
                 listen,
                 advertise_addr,
                 force_takeover,
+                cluster_key,
             })
             .await;
         }
+        Commands::ServeSnapshot { dir, listen } => {
+            if let Err(error) = run_snapshot_server(dir.into(), listen).await {
+                tracing::error!("Snapshot server error: {}", error);
+                std::process::exit(1);
+            }
+        }
+        Commands::MigrateLayout { disks } => {
+            for disk in disks {
+                match rimio_core::prepare_data_dir(std::path::Path::new(&disk)) {
+                    Ok(found) => tracing::info!(
+                        "Data layout ready: disk={} from=v{} to=v{}",
+                        disk,
+                        found,
+                        rimio_core::LAYOUT_VERSION
+                    ),
+                    Err(error) => {
+                        tracing::error!(
+                            "Data layout migration failed: disk={} error={}",
+                            disk,
+                            error
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+        Commands::Decommission { addr, dry_run } => {
+            if let Err(error) = run_decommission(&addr, dry_run).await {
+                tracing::error!("Decommission failed: node={} error={}", addr, error);
+                std::process::exit(1);
+            }
+        }
+        Commands::Wipe {
+            conf,
+            profile,
+            node,
+            confirm,
+            shred,
+        } => {
+            let cfg = match Config::from_file(&conf, profile) {
+                Ok(c) => c,
+                Err(error) => {
+                    tracing::error!("Failed to load config: {}", error);
+                    std::process::exit(1);
+                }
+            };
+            if let Err(error) = wipe::run_wipe(&cfg, &node, confirm, shred).await {
+                tracing::error!("Wipe failed: node={} error={}", node, error);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Asks the node at `addr` to drain itself and reports whether it can be
+/// shut down. Fails while any slot is still placed on the node.
+async fn run_decommission(addr: &str, dry_run: bool) -> std::result::Result<(), String> {
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/_/api/v1/decommission?dry_run={}", addr, dry_run);
+    let response = client
+        .post(&url)
+        .send()
+        .await
+        .map_err(|error| format!("node unreachable: {}", error))?;
+    let status = response.status();
+    let body: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|error| format!("invalid response ({}): {}", status, error))?;
+    if !status.is_success() {
+        return Err(format!("node returned {}: {}", status, body));
     }
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&body).unwrap_or_else(|_| body.to_string())
+    );
+    if dry_run {
+        return Ok(());
+    }
+    if body["deregistered"].as_bool() == Some(true) {
+        tracing::info!("Node drained and deregistered, safe to shut down: {}", addr);
+        return Ok(());
+    }
+    Err(format!(
+        "node still holds slots {} (under-replicated: {}); run again to retry",
+        body["remaining_slots"], body["under_replicated"]
+    ))
 }
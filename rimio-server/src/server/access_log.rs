@@ -0,0 +1,283 @@
+use crate::config::{AccessLogConfig, AccessLogFormat};
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, header},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use rimio_core::{Result, RimError};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::Instant;
+
+/// Lines waiting for the writer; further requests are not logged while the
+/// queue is full.
+const ACCESS_LOG_QUEUE: usize = 8192;
+
+/// Writes one line per HTTP request to a file, in Common Log Format or as
+/// JSON.
+///
+/// Lines are handed to a writer thread so requests never wait on the disk.
+/// The writer rotates the file by size, keeping `<path>.1` (newest) to
+/// `<path>.<max_files>`.
+pub(crate) struct AccessLog {
+    format: AccessLogFormat,
+    lines: SyncSender<String>,
+    dropped: AtomicU64,
+}
+
+impl AccessLog {
+    pub(crate) fn new(config: &AccessLogConfig) -> Result<Self> {
+        let writer = RotatingFile::open(
+            config.path.clone(),
+            config.max_size_mb.saturating_mul(1024 * 1024),
+            config.max_files,
+        )?;
+        let (lines, receiver) = mpsc::sync_channel(ACCESS_LOG_QUEUE);
+        std::thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || write_lines(writer, receiver))
+            .map_err(RimError::Io)?;
+
+        Ok(Self {
+            format: config.format,
+            lines,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    fn record(&self, entry: AccessLogEntry) {
+        let line = match self.format {
+            AccessLogFormat::Common => entry.common(),
+            AccessLogFormat::Combined => entry.combined(),
+            AccessLogFormat::Json => entry.json(),
+        };
+        match self.lines.try_send(line) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped.is_power_of_two() {
+                    tracing::warn!("access log is behind; {} lines dropped", dropped);
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+/// Middleware logging every request once its response is ready. The size is
+/// that of the response body when known up front, so streamed bodies are
+/// logged as `-`.
+pub(crate) async fn log_access(
+    State(log): State<Arc<AccessLog>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let time = Utc::now();
+    let remote = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    let method = request.method().to_string();
+    let target = request
+        .uri()
+        .path_and_query()
+        .map(|value| value.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let protocol = format!("{:?}", request.version());
+    let referer = header_value(request.headers(), header::REFERER);
+    let user_agent = header_value(request.headers(), header::USER_AGENT);
+
+    let response = next.run(request).await;
+
+    let bytes = header_value(response.headers(), header::CONTENT_LENGTH)
+        .and_then(|value| value.parse().ok())
+        .or_else(|| response.body().size_hint().exact());
+    log.record(AccessLogEntry {
+        time,
+        remote,
+        method,
+        target,
+        protocol,
+        status: response.status().as_u16(),
+        bytes,
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        referer,
+        user_agent,
+    });
+    response
+}
+
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+struct AccessLogEntry {
+    time: DateTime<Utc>,
+    remote: Option<String>,
+    method: String,
+    target: String,
+    protocol: String,
+    status: u16,
+    bytes: Option<u64>,
+    duration_ms: f64,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// `host ident authuser [time] "request" status bytes`.
+    fn common(&self) -> String {
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            self.remote.as_deref().unwrap_or("-"),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            escape_quoted(&self.target),
+            self.protocol,
+            self.status,
+            self.bytes
+                .map_or_else(|| "-".to_string(), |bytes| bytes.to_string()),
+        )
+    }
+
+    fn combined(&self) -> String {
+        format!(
+            "{} \"{}\" \"{}\"",
+            self.common(),
+            escape_quoted(self.referer.as_deref().unwrap_or("-")),
+            escape_quoted(self.user_agent.as_deref().unwrap_or("-")),
+        )
+    }
+
+    fn json(&self) -> String {
+        serde_json::json!({
+            "time": self.time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            "remote": self.remote,
+            "method": self.method,
+            "target": self.target,
+            "protocol": self.protocol,
+            "status": self.status,
+            "bytes": self.bytes,
+            "duration_ms": (self.duration_ms * 1000.0).round() / 1000.0,
+            "referer": self.referer,
+            "user_agent": self.user_agent,
+        })
+        .to_string()
+    }
+}
+
+/// Keeps a quoted field on one line and its quotes balanced.
+fn escape_quoted(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|ch| match ch {
+            '"' => vec!['\\', '"'],
+            '\\' => vec!['\\', '\\'],
+            ch if ch.is_control() => ch.escape_default().collect(),
+            ch => vec![ch],
+        })
+        .collect()
+}
+
+fn write_lines(mut writer: RotatingFile, lines: Receiver<String>) {
+    while let Ok(line) = lines.recv() {
+        let mut result = writer.write_line(&line);
+        // Flush once the queue is drained, not after every line.
+        while result.is_ok()
+            && let Ok(line) = lines.try_recv()
+        {
+            result = writer.write_line(&line);
+        }
+        if let Err(error) = result.and_then(|()| writer.flush()) {
+            tracing::warn!("Failed to write access log: {}", error);
+        }
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: BufWriter<File>,
+    len: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let (file, len) = open_append(&path)?;
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            file,
+            len,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let size = line.len() as u64 + 1;
+        if self.len > 0 && self.len + size > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.len += size;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+
+    /// Shifts `<path>.N` to `<path>.N+1`, dropping the oldest, and starts a
+    /// new file.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated_path(&self.path, self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        let (file, len) = open_append(&self.path)?;
+        self.file = file;
+        self.len = len;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> std::io::Result<(BufWriter<File>, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let len = file.metadata()?.len();
+    Ok((BufWriter::new(file), len))
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    PathBuf::from(rotated)
+}
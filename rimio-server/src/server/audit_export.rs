@@ -0,0 +1,260 @@
+use super::parquet::ParquetListing;
+use super::{AuditExportQuery, ServerState, resolve_replica_nodes, response_error};
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use rimio_core::{ListBlobItem, ListBlobsOperation, ListBlobsOperationRequest, ListOrder};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+const EXPORT_PAGE_SIZE: usize = 1000;
+
+/// Header carrying the hex sha256 of the export body.
+const EXPORT_SHA256_HEADER: &str = "x-rimio-export-sha256";
+/// Header carrying the hex HMAC-SHA256 of the export body.
+const EXPORT_SIGNATURE_HEADER: &str = "x-rimio-export-signature";
+/// Header naming the node whose slots the export covers.
+const EXPORT_NODE_HEADER: &str = "x-rimio-export-node";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+/// `GET /_/api/v1/audit/export?prefix=&format=` answers every live blob
+/// under the prefix with its generation, size, etag and the replica set of
+/// its slot, as CSV or as Parquet with the columns of a Parquet listing plus
+/// `replicas`. The body is signed with the configured key so auditors can
+/// check it was not altered after it left the cluster.
+///
+/// The export is node-local: it covers the slots this node holds, which
+/// `x-rimio-export-node` names, so auditing the cluster takes an export
+/// from each node.
+pub(crate) async fn v1_audit_export(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<AuditExportQuery>,
+) -> Response {
+    let Some(config) = state.config.audit_export.as_ref() else {
+        return response_error(StatusCode::NOT_FOUND, "audit export is not configured");
+    };
+    let format = match query.format.as_str() {
+        "csv" => ExportFormat::Csv,
+        "parquet" => ExportFormat::Parquet,
+        other => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                format!("unsupported export format: {}", other),
+            );
+        }
+    };
+
+    let items = match live_items(&state.list_blobs_operation, &query.prefix).await {
+        Ok(items) => items,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    let mut slot_replicas: HashMap<u16, String> = HashMap::new();
+    let mut replicas = Vec::with_capacity(items.len());
+    for item in &items {
+        let slot_id = state.config.replication.slot_for_key(&item.path);
+        if let Entry::Vacant(entry) = slot_replicas.entry(slot_id) {
+            match resolve_replica_nodes(&state, slot_id).await {
+                Ok(nodes) => {
+                    entry.insert(
+                        nodes
+                            .into_iter()
+                            .map(|node| node.node_id)
+                            .collect::<Vec<_>>()
+                            .join(";"),
+                    );
+                }
+                Err(error) => {
+                    return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+                }
+            }
+        }
+        replicas.push(slot_replicas[&slot_id].clone());
+    }
+
+    let (body, content_type, disposition) = match format {
+        ExportFormat::Csv => (
+            Bytes::from(csv_body(&items, &replicas)),
+            "text/csv; charset=utf-8",
+            "attachment; filename=\"rimio-audit.csv\"",
+        ),
+        ExportFormat::Parquet => (
+            parquet_body(&items, &replicas),
+            "application/vnd.apache.parquet",
+            "attachment; filename=\"rimio-audit.parquet\"",
+        ),
+    };
+
+    let digest = hex::encode(Sha256::digest(&body));
+    let mut mac = match Hmac::<Sha256>::new_from_slice(config.signing_key.as_bytes()) {
+        Ok(mac) => mac,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    mac.update(&body);
+    let signature = hex::encode(mac.finalize().into_bytes());
+
+    let mut response = body.into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static(disposition),
+    );
+    if let Ok(value) = HeaderValue::from_str(&digest) {
+        headers.insert(EXPORT_SHA256_HEADER, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&signature) {
+        headers.insert(EXPORT_SIGNATURE_HEADER, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(state.node.node_id()) {
+        headers.insert(EXPORT_NODE_HEADER, value);
+    }
+    response
+}
+
+/// Every live blob under `prefix` in the slots this node holds.
+async fn live_items(
+    list_blobs_operation: &ListBlobsOperation,
+    prefix: &str,
+) -> rimio_core::Result<Vec<ListBlobItem>> {
+    let mut items = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = list_blobs_operation
+            .run(ListBlobsOperationRequest {
+                prefix: prefix.to_string(),
+                limit: EXPORT_PAGE_SIZE,
+                cursor: cursor.take(),
+                include_deleted: false,
+                tags: BTreeMap::new(),
+                order_by: ListOrder::Path,
+                since: None,
+                until: None,
+            })
+            .await?;
+        items.extend(page.items);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => return Ok(items),
+        }
+    }
+}
+
+/// `path,generation,size_bytes,etag,replicas`, one row per item.
+fn csv_body(items: &[ListBlobItem], replicas: &[String]) -> String {
+    let mut body = String::from("path,generation,size_bytes,etag,replicas\n");
+    for (item, replicas) in items.iter().zip(replicas) {
+        body.push_str(&format!(
+            "{},{},{},{},{}\n",
+            csv_field(&item.path),
+            item.generation,
+            item.size_bytes,
+            csv_field(&item.etag),
+            csv_field(replicas),
+        ));
+    }
+    body
+}
+
+/// The listing encoder's file with a row group per export page.
+fn parquet_body(items: &[ListBlobItem], replicas: &[String]) -> Bytes {
+    let mut listing = ParquetListing::new().with_replicas();
+    let mut body = Vec::new();
+    for (items, replicas) in items
+        .chunks(EXPORT_PAGE_SIZE)
+        .zip(replicas.chunks(EXPORT_PAGE_SIZE))
+    {
+        body.extend_from_slice(&listing.row_group_with_replicas(items, replicas));
+    }
+    body.extend_from_slice(&listing.finish());
+    Bytes::from(body)
+}
+
+/// Quotes a field when it holds a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rimio_core::{
+        BlobMeta, ContentHeaders, MetadataStore, PART_SIZE, PartIndexState, SlotManager,
+        compute_hash,
+    };
+
+    /// A node holding `slot_id` with one live blob at `path` in it.
+    async fn node(
+        dir: &tempfile::TempDir,
+        node_id: &str,
+        slot_id: u16,
+        path: &str,
+    ) -> ListBlobsOperation {
+        let slot_manager =
+            Arc::new(SlotManager::new(node_id.to_string(), dir.path().join(node_id)).unwrap());
+        slot_manager.init_slot(slot_id).await.unwrap();
+        let store = MetadataStore::new(slot_manager.get_slot(slot_id).await.unwrap()).unwrap();
+        store
+            .upsert_meta(&BlobMeta {
+                path: path.to_string(),
+                slot_id,
+                generation: 1,
+                version: 1,
+                size_bytes: 0,
+                etag: compute_hash(b""),
+                part_size: PART_SIZE as u64,
+                part_count: 0,
+                part_index_state: PartIndexState::Complete,
+                archive_url: None,
+                updated_at: chrono::Utc::now(),
+                metadata: BTreeMap::new(),
+                tags: BTreeMap::new(),
+                content: ContentHeaders::default(),
+            })
+            .unwrap();
+        ListBlobsOperation::new(slot_manager)
+    }
+
+    #[tokio::test]
+    async fn export_covers_only_the_slots_this_node_holds() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_a = node(&dir, "node-a", 1, "logs/a").await;
+        node(&dir, "node-b", 2, "logs/b").await;
+
+        let items = live_items(&node_a, "logs/").await.unwrap();
+
+        let paths: Vec<&str> = items.iter().map(|item| item.path.as_str()).collect();
+        assert_eq!(paths, ["logs/a"]);
+    }
+
+    #[tokio::test]
+    async fn csv_names_the_etag_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_a = node(&dir, "node-a", 1, "logs/a,b").await;
+        let items = live_items(&node_a, "logs/").await.unwrap();
+
+        let body = csv_body(&items, &["node-a;node-b".to_string()]);
+
+        assert_eq!(
+            body,
+            format!(
+                "path,generation,size_bytes,etag,replicas\n\"logs/a,b\",1,0,{},node-a;node-b\n",
+                compute_hash(b"")
+            )
+        );
+    }
+}
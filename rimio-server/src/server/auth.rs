@@ -0,0 +1,564 @@
+use super::upload_tokens::{admit_upload, is_upload_token, verify_upload_token};
+use super::{ApiKeysBody, ServerState, response_error};
+use crate::config::{AuthConfig, UploadTokensConfig};
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use rimio_core::{ApiKey, CLUSTER_KEY_HEADER, Registry, Result, validate_api_keys};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::time::Duration;
+
+/// Header that carries a static API key when `Authorization` is taken.
+const API_KEY_HEADER: &str = "x-rimio-api-key";
+/// Node-to-node routes, which peers reach with the cluster key.
+const INTERNAL_PREFIX: &str = "/internal/";
+
+/// Checks request credentials against the configured keys and those kept in
+/// the registry, which are re-read periodically.
+pub(crate) struct Authenticator {
+    config: AuthConfig,
+    registry: Arc<dyn Registry>,
+    registry_keys: RwLock<Vec<ApiKey>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct JwtHeader {
+    pub(super) alg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) typ: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) kid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtClaims {
+    exp: i64,
+    #[serde(default)]
+    nbf: Option<i64>,
+}
+
+impl Authenticator {
+    pub(crate) fn new(config: AuthConfig, registry: Arc<dyn Registry>) -> Self {
+        Self {
+            config,
+            registry,
+            registry_keys: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.config.refresh_secs)
+    }
+
+    /// Re-reads the keys kept in the registry.
+    pub(crate) async fn refresh(&self) -> Result<()> {
+        let keys = self.registry.get_api_keys().await?;
+        *self.registry_keys.write().await = keys;
+        Ok(())
+    }
+
+    /// Drops used upload tokens past their expiry, so recording a use stays
+    /// a single insert.
+    pub(crate) async fn purge_upload_tokens(&self) -> Result<()> {
+        if self.upload_tokens().is_none() {
+            return Ok(());
+        }
+        let purged = self.registry.purge_upload_tokens().await?;
+        if purged > 0 {
+            tracing::debug!("purged {} expired upload tokens", purged);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn upload_tokens(&self) -> Option<&UploadTokensConfig> {
+        self.config.upload_tokens.as_ref()
+    }
+
+    fn is_public(&self, path: &str) -> bool {
+        (self.config.public_internal && path.starts_with(INTERNAL_PREFIX))
+            || self
+                .config
+                .public_prefixes
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    /// Whether `headers` carry the cluster key, compared by digest so the
+    /// time taken does not give it away.
+    fn is_peer(&self, headers: &HeaderMap) -> bool {
+        let (Some(expected), Some(presented)) = (
+            self.config.cluster_key.as_deref(),
+            headers.get(CLUSTER_KEY_HEADER),
+        ) else {
+            return false;
+        };
+        Sha256::digest(expected.as_bytes()) == Sha256::digest(presented.as_bytes())
+    }
+
+    /// The key `token` proves, or why it proves none.
+    async fn authenticate(&self, token: &str) -> std::result::Result<ApiKey, String> {
+        let registry_keys = self.registry_keys.read().await;
+        let mut keys = self
+            .config
+            .keys
+            .iter()
+            .chain(registry_keys.iter())
+            .filter(|key| !key.disabled);
+        if token.split('.').count() == 3 {
+            return verify_jwt(token, keys).cloned();
+        }
+
+        let digest = hex::encode(Sha256::digest(token.as_bytes()));
+        keys.find(|key| {
+            key.secret_sha256
+                .as_deref()
+                .is_some_and(|hash| hash.eq_ignore_ascii_case(&digest))
+        })
+        .cloned()
+        .ok_or_else(|| "unknown api key".to_string())
+    }
+}
+
+/// Requires a key that grants the request's method and path on every route
+/// outside `auth.public_prefixes`: `401` without a valid credential, `403`
+/// when the key does not reach the route. An upload token stands in for a
+/// key on the one write it grants, and the cluster key for one on the
+/// internal routes. Handlers find the key in the request extensions.
+pub(crate) async fn require_auth(
+    State(auth): State<Arc<Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    if auth.is_public(&path)
+        || (path.starts_with(INTERNAL_PREFIX) && auth.is_peer(request.headers()))
+    {
+        return next.run(request).await;
+    }
+
+    let Some(token) = credential(request.headers()) else {
+        return unauthorized("missing api key or bearer token");
+    };
+    if let Some(upload_tokens) = auth.upload_tokens()
+        && is_upload_token(token)
+    {
+        return match verify_upload_token(upload_tokens, token) {
+            Ok(grant) => admit_upload(auth.registry.clone(), grant, request, next).await,
+            Err(reason) => unauthorized(&reason),
+        };
+    }
+    let key = match auth.authenticate(token).await {
+        Ok(key) => key,
+        Err(reason) => return unauthorized(&reason),
+    };
+    let method = request.method().as_str();
+    if !key.allows(method, &path) {
+        return response_error(
+            StatusCode::FORBIDDEN,
+            format!("api key {} may not {} {}", key.id, method, path),
+        );
+    }
+    request.extensions_mut().insert(key);
+    next.run(request).await
+}
+
+/// `GET /_/api/v1/auth/keys` lists the keys kept in the registry, without
+/// their JWT secrets.
+pub(crate) async fn v1_get_api_keys(State(state): State<Arc<ServerState>>) -> Response {
+    match state.registry.get_api_keys().await {
+        Ok(keys) => Json(ApiKeysBody {
+            keys: keys
+                .into_iter()
+                .map(|key| ApiKey {
+                    jwt_secret: None,
+                    ..key
+                })
+                .collect(),
+        })
+        .into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `PUT /_/api/v1/auth/keys` replaces the keys kept in the registry. This
+/// node uses them at once; the others on their next refresh.
+pub(crate) async fn v1_put_api_keys(
+    State(state): State<Arc<ServerState>>,
+    body: Bytes,
+) -> Response {
+    let request: ApiKeysBody = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(error) => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                format!("invalid api keys: {}", error),
+            );
+        }
+    };
+    if let Err(error) = validate_api_keys(&request.keys) {
+        return response_error(StatusCode::BAD_REQUEST, error.to_string());
+    }
+
+    if let Err(error) = state.registry.set_api_keys(&request.keys).await {
+        return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+    }
+    if let Some(auth) = state.auth.as_ref()
+        && let Err(error) = auth.refresh().await
+    {
+        tracing::warn!("Failed to reload api keys: {}", error);
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// The token of `Authorization: Bearer`, else the `x-rimio-api-key` header.
+fn credential(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim());
+    bearer
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        })
+        .filter(|token| !token.is_empty())
+}
+
+/// Checks an HS256 JWT signed by the key its `kid` names. `exp` is required
+/// so a leaked token does not work forever.
+fn verify_jwt<'a>(
+    token: &str,
+    mut keys: impl Iterator<Item = &'a ApiKey>,
+) -> std::result::Result<&'a ApiKey, String> {
+    let kid = jwt_header(token)?
+        .kid
+        .ok_or_else(|| "bearer token has no kid".to_string())?;
+    let key = keys
+        .find(|key| key.id == kid && key.jwt_secret.is_some())
+        .ok_or_else(|| format!("unknown token key {}", kid))?;
+    let secret = key.jwt_secret.as_deref().unwrap_or_default();
+
+    let claims: JwtClaims = verify_hs256(token, secret)?;
+    check_lifetime(claims.exp, claims.nbf)?;
+    Ok(key)
+}
+
+/// The header of an HS256 JWT, read before its signature is checked so the
+/// key that signed it can be found.
+pub(super) fn jwt_header(token: &str) -> std::result::Result<JwtHeader, String> {
+    let header = token
+        .split('.')
+        .next()
+        .ok_or_else(|| "malformed bearer token".to_string())?;
+    let header: JwtHeader = decode_segment(header)?;
+    if header.alg != "HS256" {
+        return Err(format!("unsupported token algorithm {}", header.alg));
+    }
+    Ok(header)
+}
+
+/// The claims of `token` once its signature checks out against `secret`.
+pub(super) fn verify_hs256<T: DeserializeOwned>(
+    token: &str,
+    secret: &str,
+) -> std::result::Result<T, String> {
+    let (signed, signature) = token
+        .rsplit_once('.')
+        .ok_or_else(|| "malformed bearer token".to_string())?;
+    let (_, claims) = signed
+        .split_once('.')
+        .ok_or_else(|| "malformed bearer token".to_string())?;
+
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| "malformed token signature".to_string())?;
+    hs256(secret, signed)
+        .verify_slice(&signature)
+        .map_err(|_| "bad token signature".to_string())?;
+    decode_segment(claims)
+}
+
+/// Encodes `claims` as an HS256 JWT signed with `secret`.
+pub(super) fn sign_hs256(
+    header: &JwtHeader,
+    claims: &impl Serialize,
+    secret: &str,
+) -> serde_json::Result<String> {
+    let signed = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(header)?),
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?)
+    );
+    let signature = URL_SAFE_NO_PAD.encode(hs256(secret, &signed).finalize().into_bytes());
+    Ok(format!("{}.{}", signed, signature))
+}
+
+pub(super) fn check_lifetime(exp: i64, nbf: Option<i64>) -> std::result::Result<(), String> {
+    let now = chrono::Utc::now().timestamp();
+    if exp <= now {
+        return Err("bearer token has expired".to_string());
+    }
+    if nbf.is_some_and(|nbf| nbf > now) {
+        return Err("bearer token is not valid yet".to_string());
+    }
+    Ok(())
+}
+
+fn hs256(secret: &str, signed: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(signed.as_bytes());
+    mac
+}
+
+fn decode_segment<T: DeserializeOwned>(segment: &str) -> std::result::Result<T, String> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(segment)
+        .map_err(|_| "malformed bearer token".to_string())?;
+    serde_json::from_slice(&bytes).map_err(|error| format!("malformed bearer token: {}", error))
+}
+
+pub(super) fn unauthorized(reason: &str) -> Response {
+    let mut response = response_error(StatusCode::UNAUTHORIZED, reason);
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::signing::{RequestSigner, verify_signed_requests};
+    use super::*;
+    use crate::config::RequestSigningConfig;
+    use axum::{Router, body::Body, http::Method, middleware, routing::any};
+    use rimio_core::ApiKeyGrant;
+    use rimio_core::registry::memory::MemoryRegistry;
+    use tower::ServiceExt;
+
+    const READER_SECRET: &str = "reader-secret";
+    const JWT_SECRET: &str = "jwt-secret";
+    const CLUSTER_KEY: &str = "cluster-key";
+
+    fn reader() -> ApiKey {
+        ApiKey {
+            id: "reader".to_string(),
+            secret_sha256: Some(hex::encode(Sha256::digest(READER_SECRET))),
+            jwt_secret: Some(JWT_SECRET.to_string()),
+            allow: vec![ApiKeyGrant {
+                prefix: "/_/api/v1/blobs/".to_string(),
+                methods: vec!["GET".to_string()],
+            }],
+            disabled: false,
+        }
+    }
+
+    fn app(config: serde_json::Value) -> Router {
+        let mut config: AuthConfig = serde_json::from_value(config).unwrap();
+        config.keys = vec![reader()];
+        let auth = Arc::new(Authenticator::new(config, Arc::new(MemoryRegistry::new())));
+        Router::new()
+            .route("/*path", any(|| async { StatusCode::OK }))
+            .layer(middleware::from_fn_with_state(auth, require_auth))
+    }
+
+    async fn status(app: &Router, method: Method, path: &str, headers: &[(&str, &str)]) -> u16 {
+        let mut request = Request::builder().method(method).uri(path);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.status().as_u16()
+    }
+
+    fn jwt(exp: i64) -> String {
+        let header = JwtHeader {
+            alg: "HS256".to_string(),
+            typ: Some("JWT".to_string()),
+            kid: Some("reader".to_string()),
+        };
+        sign_hs256(&header, &serde_json::json!({ "exp": exp }), JWT_SECRET).unwrap()
+    }
+
+    #[tokio::test]
+    async fn keys_are_checked_against_their_grants() {
+        let app = app(serde_json::json!({ "cluster_key": CLUSTER_KEY }));
+        let blob = "/_/api/v1/blobs/a.txt";
+        let bearer = format!("Bearer {}", READER_SECRET);
+
+        assert_eq!(status(&app, Method::GET, blob, &[]).await, 401);
+        assert_eq!(
+            status(&app, Method::GET, blob, &[("authorization", "Bearer nope")]).await,
+            401
+        );
+        assert_eq!(
+            status(&app, Method::GET, blob, &[("authorization", &bearer)]).await,
+            200
+        );
+        assert_eq!(
+            status(&app, Method::HEAD, blob, &[(API_KEY_HEADER, READER_SECRET)]).await,
+            200
+        );
+        assert_eq!(
+            status(&app, Method::PUT, blob, &[("authorization", &bearer)]).await,
+            403
+        );
+        assert_eq!(
+            status(
+                &app,
+                Method::GET,
+                "/_/api/v1/nodes",
+                &[("authorization", &bearer)]
+            )
+            .await,
+            403
+        );
+        assert_eq!(
+            status(
+                &app,
+                Method::GET,
+                "/_/api/v1/inventory?prefix=logs/",
+                &[("authorization", &bearer)]
+            )
+            .await,
+            403
+        );
+        assert_eq!(
+            status(&app, Method::GET, "/_/api/v1/healthz", &[]).await,
+            200
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_refused() {
+        let app = app(serde_json::json!({ "cluster_key": CLUSTER_KEY }));
+        let blob = "/_/api/v1/blobs/a.txt";
+        let now = chrono::Utc::now().timestamp();
+
+        let valid = format!("Bearer {}", jwt(now + 60));
+        assert_eq!(
+            status(&app, Method::GET, blob, &[("authorization", &valid)]).await,
+            200
+        );
+        let expired = format!("Bearer {}", jwt(now - 1));
+        assert_eq!(
+            status(&app, Method::GET, blob, &[("authorization", &expired)]).await,
+            401
+        );
+        let forged = format!(
+            "Bearer {}",
+            sign_hs256(
+                &JwtHeader {
+                    alg: "HS256".to_string(),
+                    typ: None,
+                    kid: Some("reader".to_string()),
+                },
+                &serde_json::json!({ "exp": now + 60 }),
+                "other-secret",
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            status(&app, Method::GET, blob, &[("authorization", &forged)]).await,
+            401
+        );
+    }
+
+    #[tokio::test]
+    async fn internal_routes_need_the_cluster_key() {
+        let app = app(serde_json::json!({ "cluster_key": CLUSTER_KEY }));
+        let heads = "/internal/v1/slots/1/heads";
+
+        assert_eq!(status(&app, Method::PUT, heads, &[]).await, 401);
+        assert_eq!(
+            status(&app, Method::PUT, heads, &[(CLUSTER_KEY_HEADER, "guess")]).await,
+            401
+        );
+        assert_eq!(
+            status(
+                &app,
+                Method::PUT,
+                heads,
+                &[(CLUSTER_KEY_HEADER, CLUSTER_KEY)]
+            )
+            .await,
+            200
+        );
+        // The cluster key opens nothing outside the internal routes.
+        assert_eq!(
+            status(
+                &app,
+                Method::GET,
+                "/_/api/v1/blobs/a.txt",
+                &[(CLUSTER_KEY_HEADER, CLUSTER_KEY)]
+            )
+            .await,
+            401
+        );
+
+        let open = self::app(serde_json::json!({ "public_internal": true }));
+        assert_eq!(status(&open, Method::PUT, heads, &[]).await, 200);
+        assert_eq!(
+            status(&open, Method::GET, "/_/api/v1/blobs/a.txt", &[]).await,
+            401
+        );
+    }
+
+    #[tokio::test]
+    async fn bad_signatures_are_refused() {
+        let signer = Arc::new(RequestSigner::new(
+            serde_json::from_value::<RequestSigningConfig>(serde_json::json!({
+                "key_id": "edge",
+                "secret": "signing-secret",
+                "require_prefixes": ["/_/api/v1/blobs/"],
+            }))
+            .unwrap(),
+        ));
+        let app = Router::new()
+            .route("/*path", any(|| async { StatusCode::OK }))
+            .layer(middleware::from_fn_with_state(
+                signer.clone(),
+                verify_signed_requests,
+            ));
+        let blob = "/_/api/v1/blobs/a.txt";
+
+        let signed = signer.sign("PUT", blob, b"");
+        let headers: Vec<(&str, &str)> = signed
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+        assert_eq!(status(&app, Method::PUT, blob, &headers).await, 200);
+        assert_eq!(status(&app, Method::PUT, blob, &[]).await, 401);
+        // A signature for another method does not carry over.
+        assert_eq!(status(&app, Method::DELETE, blob, &headers).await, 401);
+
+        let tampered: Vec<(&str, &str)> = headers
+            .iter()
+            .map(|&(name, value)| match name {
+                "x-rimio-signature" => (name, "sha256=00"),
+                _ => (name, value),
+            })
+            .collect();
+        assert_eq!(status(&app, Method::PUT, blob, &tampered).await, 401);
+    }
+}
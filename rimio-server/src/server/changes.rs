@@ -0,0 +1,77 @@
+use super::external::{ndjson_response, ndjson_rows};
+use super::{ServerState, SlotChangesQuery, response_error};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+};
+use futures_util::TryStreamExt;
+use rimio_core::{ListSlotChangesOperationRequest, RimError};
+use std::sync::Arc;
+
+/// Changes a streamed feed reads from the slot database per page.
+const CHANGES_PAGE_SIZE: usize = 1000;
+
+/// `GET /_/api/v1/slots/:slot_id/changes?since=` streams the slot's change
+/// feed on this node after `since`, oldest first, one JSON object per line,
+/// and ends once it has caught up. Tailing clients call again with the last
+/// `seq` they received.
+pub(crate) async fn v1_slot_changes(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+    Query(query): Query<SlotChangesQuery>,
+) -> Response {
+    let remaining = query.limit.unwrap_or(usize::MAX);
+    let request = ListSlotChangesOperationRequest {
+        slot_id,
+        since: query.since,
+        limit: remaining.min(CHANGES_PAGE_SIZE),
+    };
+    // The first page is read up front so a slot this node does not hold
+    // answers 404 instead of an empty stream.
+    let first = match state.list_slot_changes_operation.run(request.clone()).await {
+        Ok(first) => first,
+        Err(RimError::SlotNotFound(_)) => {
+            return response_error(
+                StatusCode::NOT_FOUND,
+                format!("slot is not held by this node: {}", slot_id),
+            );
+        }
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    // Each step yields a page it already holds and reads the one after it,
+    // unless the page came back short or the limit is used up.
+    let start = Some((first, request, remaining));
+    let pages = futures_util::stream::try_unfold(start, move |page| {
+        let state = state.clone();
+        async move {
+            let Some((changes, request, remaining)) = page else {
+                return Ok(None);
+            };
+            let remaining = remaining.saturating_sub(changes.len());
+            let next = match changes.last() {
+                Some(last) if changes.len() == request.limit && remaining > 0 => {
+                    let request = ListSlotChangesOperationRequest {
+                        since: last.seq,
+                        limit: remaining.min(CHANGES_PAGE_SIZE),
+                        ..request
+                    };
+                    let changes = state
+                        .list_slot_changes_operation
+                        .run(request.clone())
+                        .await?;
+                    Some((changes, request, remaining))
+                }
+                _ => None,
+            };
+            Ok::<_, RimError>(Some((Bytes::from(ndjson_rows(&changes)), next)))
+        }
+    })
+    .inspect_err(|error| {
+        tracing::warn!("slot change feed stream failed: {}", error);
+    });
+
+    ndjson_response(Body::from_stream(pages))
+}
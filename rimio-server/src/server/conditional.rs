@@ -0,0 +1,160 @@
+//! Conditional requests on the blob API.
+//!
+//! GET and HEAD evaluate `If-Match`, `If-Unmodified-Since`, `If-None-Match`
+//! and `If-Modified-Since` against the current version in the order RFC 9110
+//! gives; PUT evaluates `If-Match`, `If-None-Match` and
+//! `If-Unmodified-Since` before writing, then commits only while the version
+//! they held against is still live. Entity tags are the blob etag in quotes;
+//! dates compare at the second Last-Modified is sent with.
+
+use super::response_error;
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use rimio_core::BlobMeta;
+
+pub(crate) const EXPECTED_GENERATION_HEADER: &str = "x-rimio-expected-generation";
+
+/// What a read's preconditions say about serving `meta`.
+pub(crate) enum ReadPrecondition {
+    Serve,
+    NotModified,
+    Failed,
+}
+
+pub(crate) fn evaluate_read(headers: &HeaderMap, meta: &BlobMeta) -> ReadPrecondition {
+    if let Some(condition) = header_str(headers, header::IF_MATCH) {
+        if !etag_list_matches(condition, &meta.etag, false) {
+            return ReadPrecondition::Failed;
+        }
+    } else if let Some(since) = header_date(headers, header::IF_UNMODIFIED_SINCE)
+        && modified_after(meta, since)
+    {
+        return ReadPrecondition::Failed;
+    }
+
+    if let Some(condition) = header_str(headers, header::IF_NONE_MATCH) {
+        if etag_list_matches(condition, &meta.etag, true) {
+            return ReadPrecondition::NotModified;
+        }
+    } else if let Some(since) = header_date(headers, header::IF_MODIFIED_SINCE)
+        && !modified_after(meta, since)
+    {
+        return ReadPrecondition::NotModified;
+    }
+
+    ReadPrecondition::Serve
+}
+
+/// Checks a write against the current version, `None` when the blob is
+/// missing or deleted. Returns the 412 to send when a precondition fails.
+pub(crate) fn check_write(headers: &HeaderMap, current: Option<&BlobMeta>) -> Option<Response> {
+    if let Some(condition) = header_str(headers, header::IF_MATCH)
+        && !current.is_some_and(|meta| etag_list_matches(condition, &meta.etag, false))
+    {
+        return Some(precondition_failed("if-match precondition failed"));
+    }
+    if let Some(condition) = header_str(headers, header::IF_NONE_MATCH)
+        && current.is_some_and(|meta| etag_list_matches(condition, &meta.etag, true))
+    {
+        return Some(precondition_failed("if-none-match precondition failed"));
+    }
+    if let Some(since) = header_date(headers, header::IF_UNMODIFIED_SINCE)
+        && current.is_some_and(|meta| modified_after(meta, since))
+    {
+        return Some(precondition_failed(
+            "if-unmodified-since precondition failed",
+        ));
+    }
+    None
+}
+
+pub(crate) fn has_write_preconditions(headers: &HeaderMap) -> bool {
+    [
+        header::IF_MATCH,
+        header::IF_NONE_MATCH,
+        header::IF_UNMODIFIED_SINCE,
+    ]
+    .iter()
+    .any(|name| headers.contains_key(name))
+}
+
+/// Reads `x-rimio-expected-generation`: the generation the live version
+/// must be at for a PUT to commit, 0 for a blob that must not exist.
+pub(crate) fn parse_expected_generation(
+    headers: &HeaderMap,
+) -> std::result::Result<Option<i64>, String> {
+    let Some(raw) = header_str(headers, EXPECTED_GENERATION_HEADER) else {
+        return Ok(None);
+    };
+    match raw.parse::<i64>() {
+        Ok(generation) if generation >= 0 => Ok(Some(generation)),
+        _ => Err(format!("invalid {}: {}", EXPECTED_GENERATION_HEADER, raw)),
+    }
+}
+
+/// A 412 for a compare-and-swap that lost, naming the live generation so
+/// the client can re-read and retry.
+pub(crate) fn generation_mismatch(current_generation: i64) -> Response {
+    let mut response = precondition_failed(&format!(
+        "expected generation does not match: current={}",
+        current_generation
+    ));
+    if let Ok(value) = HeaderValue::from_str(&current_generation.to_string()) {
+        response.headers_mut().insert("x-rimio-generation", value);
+    }
+    response
+}
+
+pub(crate) fn precondition_failed(message: &str) -> Response {
+    response_error(StatusCode::PRECONDITION_FAILED, message)
+}
+
+/// A 304 carrying the validators and cache headers a 200 would have.
+pub(crate) fn not_modified(cache_headers: HeaderMap) -> Response {
+    let mut response = Response::new(axum::body::Body::empty());
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    response.headers_mut().extend(cache_headers);
+    response
+}
+
+pub(crate) fn quoted_etag(etag: &str) -> Option<HeaderValue> {
+    HeaderValue::from_str(&format!("\"{}\"", etag)).ok()
+}
+
+/// `*` matches any current version. Weak tags only match when `weak` is
+/// allowed, as If-None-Match does and If-Match does not.
+fn etag_list_matches(condition: &str, etag: &str, weak: bool) -> bool {
+    condition.split(',').map(str::trim).any(|candidate| {
+        if candidate == "*" {
+            return true;
+        }
+        let candidate = match candidate.strip_prefix("W/") {
+            Some(_) if !weak => return false,
+            Some(tag) => tag,
+            None => candidate,
+        };
+        candidate.trim_matches('"') == etag
+    })
+}
+
+fn modified_after(meta: &BlobMeta, since: DateTime<Utc>) -> bool {
+    meta.updated_at.timestamp() > since.timestamp()
+}
+
+fn header_str(headers: &HeaderMap, name: impl header::AsHeaderName) -> Option<&str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Unparseable dates are ignored, as RFC 9110 asks.
+fn header_date(headers: &HeaderMap, name: header::HeaderName) -> Option<DateTime<Utc>> {
+    header_str(headers, name)
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .map(|date| date.with_timezone(&Utc))
+}
@@ -0,0 +1,32 @@
+use super::{DecommissionQuery, ServerState, response_error};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use rimio_core::DecommissionNodeOperationRequest;
+use std::sync::Arc;
+
+/// `POST /_/api/v1/decommission` drains this node: its slots move to the
+/// other nodes, and once none is left here the node leaves the registry and
+/// stops registering itself. `deregistered: true` in the answer means the
+/// node can be shut down; otherwise call again to retry the slots that did
+/// not move.
+pub(crate) async fn v1_decommission(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<DecommissionQuery>,
+) -> Response {
+    let result = state
+        .decommission
+        .run(DecommissionNodeOperationRequest {
+            node_id: state.node.node_id().to_string(),
+            dry_run: query.dry_run,
+        })
+        .await;
+
+    match result {
+        Ok(result) => Json(result).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
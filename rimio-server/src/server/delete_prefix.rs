@@ -0,0 +1,203 @@
+use super::jobs::JOB_KIND_DELETE_PREFIX;
+use super::{
+    DeletePrefixBody, DeletePrefixEvent, DeletePrefixQuery, InternalDeletePrefixBody, ServerState,
+    refuse_frozen_write, refuse_unarchived_write, resolve_replica_nodes, response_error,
+};
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use rimio_core::{
+    DEFAULT_DELETE_PREFIX_PAGE_SIZE, DeletePrefixOperationRequest, DeletePrefixSlot,
+    DeletePrefixSlotReport, RimError,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// `POST /_/api/v1/delete-prefix` tombstones every live blob whose path
+/// starts with `prefix`, in every slot. `dry_run` only counts them. With
+/// `?format=ndjson` the response streams a progress line per page and ends
+/// with the report, so long deletes can be followed as they run; the delete
+/// carries on if the client disconnects. `?format=job` answers `202` with a
+/// job to follow under `/_/api/v1/jobs`. Calling again resumes the work,
+/// since deleted blobs no longer match. With write leases on, slots leased
+/// to other nodes are deleted by those nodes.
+pub(crate) async fn v1_delete_prefix(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<DeletePrefixQuery>,
+    body: Bytes,
+) -> Response {
+    let format = query.format.as_str();
+    match format {
+        "json" | "ndjson" | "job" => {}
+        other => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                format!("unsupported format: {}", other),
+            );
+        }
+    }
+    let request: DeletePrefixBody = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(error) => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                format!("invalid delete-prefix body: {}", error),
+            );
+        }
+    };
+    let prefix = request.prefix.trim_start_matches('/').to_string();
+    if prefix.is_empty() {
+        return response_error(StatusCode::BAD_REQUEST, "prefix must not be empty");
+    }
+    if !request.dry_run
+        && let Some(response) = refuse_unarchived_write(&state).await
+    {
+        return response;
+    }
+
+    let mut slots = Vec::with_capacity(state.config.replication.total_slots as usize);
+    for slot_id in 0..state.config.replication.total_slots {
+        match resolve_replica_nodes(&state, slot_id).await {
+            Ok(replicas) => slots.push(DeletePrefixSlot { slot_id, replicas }),
+            Err(error) => {
+                return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+            }
+        }
+    }
+    let operation_request = DeletePrefixOperationRequest {
+        prefix,
+        slots,
+        dry_run: request.dry_run,
+        page_size: request.page_size.unwrap_or(DEFAULT_DELETE_PREFIX_PAGE_SIZE),
+        local_node_id: state.node.node_id().to_string(),
+        forwarded: false,
+    };
+
+    if format == "job" {
+        return start_delete_prefix_job(state, operation_request);
+    }
+    if format == "json" {
+        return match state.delete_prefix_operation.run(operation_request).await {
+            Ok(result) => Json(result).into_response(),
+            Err(RimError::InvalidRequest(message)) => {
+                response_error(StatusCode::BAD_REQUEST, message)
+            }
+            Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+        };
+    }
+
+    let (events, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let progress_events = events.clone();
+        let result = state
+            .delete_prefix_operation
+            .run_with_progress(operation_request, move |progress| {
+                let _ = progress_events.send(DeletePrefixEvent::Progress(progress.clone()));
+            })
+            .await;
+        let _ = events.send(match result {
+            Ok(result) => DeletePrefixEvent::Done(result),
+            Err(error) => DeletePrefixEvent::Failed {
+                error: error.to_string(),
+            },
+        });
+    });
+
+    let rows = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        let mut row = serde_json::to_string(&event).unwrap_or_default();
+        row.push('\n');
+        Some((
+            Ok::<_, std::convert::Infallible>(Bytes::from(row)),
+            receiver,
+        ))
+    });
+    let mut response = Response::new(Body::from_stream(rows));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    response
+}
+
+/// `POST /internal/v1/slots/:slot_id/delete-prefix` walks a prefix delete
+/// through one slot whose write lease this node holds, for the node that
+/// took the request, and answers with the slot's report.
+pub(crate) async fn internal_delete_prefix(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+    Json(request): Json<InternalDeletePrefixBody>,
+) -> Response {
+    if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+        return response;
+    }
+    let replicas = match resolve_replica_nodes(&state, slot_id).await {
+        Ok(replicas) => replicas,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    let result = state
+        .delete_prefix_operation
+        .run(DeletePrefixOperationRequest {
+            prefix: request.prefix,
+            slots: vec![DeletePrefixSlot { slot_id, replicas }],
+            dry_run: false,
+            page_size: request.page_size,
+            local_node_id: state.node.node_id().to_string(),
+            forwarded: true,
+        })
+        .await;
+    match result {
+        Ok(result) => Json(
+            result
+                .slots
+                .into_iter()
+                .next()
+                .unwrap_or(DeletePrefixSlotReport {
+                    slot_id,
+                    ..DeletePrefixSlotReport::default()
+                }),
+        )
+        .into_response(),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::BAD_REQUEST, message),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+fn start_delete_prefix_job(
+    state: Arc<ServerState>,
+    operation_request: DeletePrefixOperationRequest,
+) -> Response {
+    let request = json!({
+        "prefix": operation_request.prefix,
+        "dry_run": operation_request.dry_run,
+    });
+    let job = match state.jobs.start(JOB_KIND_DELETE_PREFIX, request) {
+        Ok(job) => job,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    let status = state.jobs.status(job.record().clone());
+
+    tokio::spawn(async move {
+        let result = state
+            .delete_prefix_operation
+            .run_with_progress(operation_request, |progress| job.progress(progress))
+            .await;
+        job.finish(
+            result
+                .map_err(|error| error.to_string())
+                .and_then(|result| serde_json::to_value(result).map_err(|error| error.to_string())),
+        );
+    });
+
+    let mut response = (StatusCode::ACCEPTED, Json(&status)).into_response();
+    if let Ok(location) = HeaderValue::from_str(&format!("/_/api/v1/jobs/{}", status.job.job_id)) {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    response
+}
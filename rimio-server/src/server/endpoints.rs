@@ -0,0 +1,130 @@
+use super::{
+    EndpointsQuery, EndpointsResponse, ServerState, SlotEndpoint, SlotRangeEndpoints,
+    current_nodes, replica_nodes_for, response_error, status_string,
+};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use rimio_core::Result;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// `GET /_/api/v1/endpoints` publishes which nodes serve each slot, folded
+/// into ranges of contiguous slots with the same replicas. Load balancers and
+/// clients that map keys to slots themselves (see `rimio-chunk`) can send a
+/// request straight to an owner instead of through a proxy hop.
+/// `?format=srv&domain=<zone>` renders the ranges as SRV records.
+pub(crate) async fn v1_slot_endpoints(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<EndpointsQuery>,
+) -> Response {
+    let ranges = match slot_ranges(&state).await {
+        Ok(ranges) => ranges,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    match query.format.as_deref() {
+        None | Some("json") => Json(EndpointsResponse {
+            total_slots: state.config.replication.total_slots,
+            ranges,
+        })
+        .into_response(),
+        Some("srv") => {
+            let Some(domain) = query
+                .domain
+                .as_deref()
+                .map(|domain| domain.trim().trim_end_matches('.'))
+                .filter(|domain| !domain.is_empty())
+            else {
+                return response_error(
+                    StatusCode::BAD_REQUEST,
+                    "domain is required with format=srv",
+                );
+            };
+            let mut response = srv_records(&ranges, domain, query.ttl_secs).into_response();
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; charset=utf-8"),
+            );
+            response
+        }
+        Some(other) => response_error(
+            StatusCode::BAD_REQUEST,
+            format!("unsupported format: {}", other),
+        ),
+    }
+}
+
+/// Reads every slot record and the node list once and folds the slots.
+async fn slot_ranges(state: &ServerState) -> Result<Vec<SlotRangeEndpoints>> {
+    let nodes = current_nodes(state).await?;
+    let slots = state.registry.get_all_slots().await?;
+
+    let mut ranges: Vec<SlotRangeEndpoints> = Vec::new();
+    for slot_id in 0..state.config.replication.total_slots {
+        let slot = slots.get(&slot_id);
+        let primary = slot
+            .map(|slot| slot.primary.as_str())
+            .filter(|primary| !primary.is_empty());
+        let mut endpoints: Vec<SlotEndpoint> = replica_nodes_for(state, &nodes, slot_id, slot)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, node)| SlotEndpoint {
+                primary: primary.map_or(index == 0, |primary| primary == node.node_id),
+                status: status_string(&node.status).to_string(),
+                node_id: node.node_id,
+                address: node.address,
+            })
+            .collect();
+        endpoints.sort_by_key(|endpoint| !endpoint.primary);
+
+        match ranges.last_mut() {
+            Some(range) if range.endpoints == endpoints => range.last_slot = slot_id,
+            _ => ranges.push(SlotRangeEndpoints {
+                first_slot: slot_id,
+                last_slot: slot_id,
+                endpoints,
+            }),
+        }
+    }
+
+    Ok(ranges)
+}
+
+/// One SRV record per range and replica, named
+/// `_slots-<first>-<last>._rimio._tcp.<domain>`. The primary gets priority
+/// 0, other healthy replicas 10 and the rest 20; replicas without an
+/// address are left out.
+fn srv_records(ranges: &[SlotRangeEndpoints], domain: &str, ttl_secs: u32) -> String {
+    let mut records = String::new();
+    for range in ranges {
+        for endpoint in &range.endpoints {
+            let Some((host, port)) = endpoint.address.rsplit_once(':') else {
+                continue;
+            };
+            let Ok(port) = port.parse::<u16>() else {
+                continue;
+            };
+            let priority = match (endpoint.primary, endpoint.status.as_str()) {
+                (true, _) => 0,
+                (false, "healthy") => 10,
+                (false, _) => 20,
+            };
+            let _ = writeln!(
+                records,
+                "_slots-{}-{}._rimio._tcp.{}. {} IN SRV {} 1 {} {}.",
+                range.first_slot,
+                range.last_slot,
+                domain,
+                ttl_secs,
+                priority,
+                port,
+                host.trim_start_matches('[').trim_end_matches(']'),
+            );
+        }
+    }
+    records
+}
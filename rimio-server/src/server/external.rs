@@ -1,21 +1,43 @@
+use super::conditional::{self, ReadPrecondition};
+use super::parquet::ParquetListing;
+use super::upload_tokens::UploadTicket;
 use super::{
-    ListItem, ListQuery, ListResponse, NodeItem, NodesResponse, PutBlobResponse, PutCacheEntry,
-    ResolveSlotQuery, ResolveSlotResponse, ServerState, current_nodes, normalize_blob_path,
-    resolve_replica_nodes, response_error, status_string,
+    BlobReadQuery, CommitBatchResponse, CommitBatchResponseItem, ListItem, ListQuery, ListResponse,
+    NodeItem, NodesResponse, PeerProtocolItem, ProtocolResponse, PruneQuery, PruneReplicaItem,
+    PruneSlotResponse, PutBlobResponse, PutCacheEntry, ResolveSlotQuery, ResolveSlotResponse,
+    RoutingHints, ServerState, SlotReplicaItem, SlotResponse, TaggingQuery,
+    TransactionAbortResponse, TransactionCommitResponse, TransactionEntryItem, TransactionResponse,
+    TransactionSlotItem, TransactionVoteItem, UploadQuery, archive_unavailable_response,
+    claim_write_lease, current_nodes, normalize_blob_path, range_not_satisfiable_response,
+    refuse_frozen_write, refuse_unarchived_write, resolve_replica_nodes, response_error,
+    route_blob_request, status_string,
 };
+use super::{tagging, uploads};
 use axum::{
-    Json,
-    body::Bytes,
-    extract::{Path, Query, State},
-    http::{HeaderMap, HeaderValue, StatusCode, header},
+    Extension, Json,
+    body::{Body, Bytes},
+    extract::{Multipart, OriginalUri, Path, Query, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use futures_util::TryStreamExt;
 use rimio_core::{
-    DeleteBlobOperationOutcome, DeleteBlobOperationRequest, ListBlobsOperationRequest,
-    PutBlobOperationOutcome, PutBlobOperationRequest, ReadBlobOperationOutcome,
-    ReadBlobOperationRequest, ReadByteRange, RimError, slot_for_key,
+    BlobMeta, CommitBatchEntry, CommitBatchOperationOutcome, CommitBatchOperationRequest,
+    ContentHeaders, DeleteBlobOperationOutcome, DeleteBlobOperationRequest,
+    GarbageReportOperationRequest, InternalGetSlotStatsOperationRequest, ListBlobItem,
+    ListBlobsOperationRequest, ListOrder, NodeInfo, PeerProtocol, PruneVersionsOperationRequest,
+    PutBlobOperationOutcome, PutBlobOperationRequest, PutBlobStreamRequest,
+    ReadBlobManifestOutcome, ReadBlobOperationOutcome, ReadBlobOperationRequest,
+    ReadBlobStreamOutcome, ReadConsistency, ReadRangeSpec, RimError, SlotTraffic, StagedEntry,
+    StagedTransaction, TwoPhaseCommitRequest, TwoPhaseOutcome, TwoPhaseParticipant, Vote,
+    WriteConsistency,
 };
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
+
+/// How long a write id keeps answering retries with the original result.
+const IDEMPOTENCY_RECORD_TTL: Duration = Duration::from_secs(24 * 3600);
 
 pub(crate) async fn health(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
     Json(super::HealthResponse {
@@ -33,6 +55,17 @@ pub(crate) async fn v1_healthz(State(state): State<Arc<ServerState>>) -> impl In
     })
 }
 
+pub(crate) async fn v1_readyz(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    let report = state.readiness.report().await;
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(report))
+}
+
 pub(crate) async fn v1_nodes(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
     let nodes = match current_nodes(&state).await {
         Ok(nodes) => nodes,
@@ -53,6 +86,81 @@ pub(crate) async fn v1_nodes(State(state): State<Arc<ServerState>>) -> impl Into
     (StatusCode::OK, Json(payload)).into_response()
 }
 
+pub(crate) async fn v1_reconcile_report(
+    State(state): State<Arc<ServerState>>,
+) -> impl IntoResponse {
+    Json(state.slot_reconciler.last_report().await)
+}
+
+/// `GET /_/api/v1/garbage` cross-checks the registry's placement, this
+/// node's slot databases and its part files, and lists what nothing will
+/// read with the commands to clean it up. Nothing is deleted.
+pub(crate) async fn v1_garbage_report(State(state): State<Arc<ServerState>>) -> Response {
+    let report = state
+        .garbage_report_operation
+        .run(GarbageReportOperationRequest {
+            local_node_id: state.node.node_id().to_string(),
+            total_slots: state.config.replication.total_slots,
+        })
+        .await;
+    match report {
+        Ok(report) => Json(report).into_response(),
+        Err(RimError::InvalidRequest(message)) => {
+            response_error(StatusCode::SERVICE_UNAVAILABLE, message)
+        }
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `GET /_/api/v1/slots/handoffs` reports the slot rebalancer's last round.
+pub(crate) async fn v1_slot_rebalance_report(State(state): State<Arc<ServerState>>) -> Response {
+    match state.slot_rebalancer.as_ref() {
+        Some(rebalancer) => Json(rebalancer.report().await).into_response(),
+        None => response_error(StatusCode::NOT_FOUND, "slot rebalance is not configured"),
+    }
+}
+
+pub(crate) async fn v1_scrub_report(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    Json(state.scrub.reports().await)
+}
+
+pub(crate) async fn v1_disk_health(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    Json(state.disk_health.reports().await)
+}
+
+pub(crate) async fn v1_head_schema_status(State(state): State<Arc<ServerState>>) -> Response {
+    match state.head_schema.status().await {
+        Ok(statuses) => Json(statuses).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+pub(crate) async fn v1_mirror_stats(State(state): State<Arc<ServerState>>) -> Response {
+    match &state.mirror {
+        Some(mirror) => Json(mirror.stats()).into_response(),
+        None => response_error(StatusCode::NOT_FOUND, "mirroring is not configured"),
+    }
+}
+
+/// The local internal protocol and what every contacted peer reported,
+/// useful to follow a rolling upgrade.
+pub(crate) async fn v1_protocol(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    let peers = state
+        .cluster_client
+        .protocols()
+        .snapshot()
+        .await
+        .into_iter()
+        .map(|(node_id, protocol)| PeerProtocolItem { node_id, protocol })
+        .collect();
+
+    Json(ProtocolResponse {
+        node_id: state.node.node_id().to_string(),
+        local: PeerProtocol::local(),
+        peers,
+    })
+}
+
 pub(crate) async fn v1_resolve_slot(
     State(state): State<Arc<ServerState>>,
     Query(query): Query<ResolveSlotQuery>,
@@ -62,7 +170,7 @@ pub(crate) async fn v1_resolve_slot(
         Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
     };
 
-    let slot_id = slot_for_key(&path, state.config.replication.total_slots);
+    let slot_id = state.config.replication.slot_for_key(&path);
     let replicas = match resolve_replica_nodes(&state, slot_id).await {
         Ok(replicas) => replicas,
         Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
@@ -79,18 +187,191 @@ pub(crate) async fn v1_resolve_slot(
     (StatusCode::OK, Json(payload)).into_response()
 }
 
+/// Describes a slot: its placement, and the size and freshness of the data
+/// each replica holds.
+pub(crate) async fn v1_get_slot(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+) -> impl IntoResponse {
+    if slot_id >= state.config.replication.total_slots {
+        return response_error(
+            StatusCode::NOT_FOUND,
+            format!("slot not found: {}", slot_id),
+        );
+    }
+
+    let placement = match state.placement.slot(slot_id).await {
+        Ok(placement) => placement,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    let replicas = match resolve_replica_nodes(&state, slot_id).await {
+        Ok(replicas) => replicas,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    let mut items = Vec::with_capacity(replicas.len());
+    let mut last_writes = Vec::with_capacity(replicas.len());
+    for replica in &replicas {
+        let stats = if replica.node_id == state.node.node_id() {
+            state
+                .internal_get_slot_stats_operation
+                .run(InternalGetSlotStatsOperationRequest { slot_id })
+                .await
+        } else {
+            state
+                .cluster_client
+                .fetch_slot_stats(&replica.node_id, slot_id)
+                .await
+        };
+
+        let mut item = SlotReplicaItem {
+            node_id: replica.node_id.clone(),
+            address: replica.address.clone(),
+            status: status_string(&replica.status).to_string(),
+            reachable: stats.is_ok(),
+            blob_count: None,
+            data_size_bytes: None,
+            last_write_at: None,
+            lag_ms: None,
+            sqlite: None,
+            error: None,
+        };
+
+        match stats {
+            Ok(stats) => {
+                item.blob_count = Some(stats.blob_count);
+                item.data_size_bytes = Some(stats.data_size_bytes);
+                item.last_write_at = stats.last_write_at.map(|value| value.to_rfc3339());
+                last_writes.push(stats.last_write_at);
+                item.sqlite = Some(stats.sqlite);
+            }
+            Err(error) => {
+                item.error = Some(error.to_string());
+                last_writes.push(None);
+            }
+        }
+        items.push(item);
+    }
+
+    // Lag is how far a replica's latest write trails the freshest replica.
+    if let Some(newest) = last_writes.iter().flatten().max().copied() {
+        for (item, last_write) in items.iter_mut().zip(&last_writes) {
+            item.lag_ms = last_write.map(|value| (newest - value).num_milliseconds());
+        }
+    }
+
+    let payload = SlotResponse {
+        slot_id,
+        epoch: placement
+            .as_ref()
+            .map(|slot| slot.epoch)
+            .unwrap_or_default(),
+        primary: placement
+            .map(|slot| slot.primary)
+            .or_else(|| replicas.first().map(|node| node.node_id.clone()))
+            .unwrap_or_default(),
+        write_quorum: state.coordinator.write_quorum(replicas.len()),
+        data_size_bytes: items
+            .iter()
+            .filter_map(|item| item.data_size_bytes)
+            .max()
+            .unwrap_or_default(),
+        replicas: items,
+    };
+
+    (StatusCode::OK, Json(payload)).into_response()
+}
+
+/// Prunes the versions of a slot that the retention policy no longer keeps,
+/// here and on every other replica. Replicas that are unreachable or too old
+/// to prune are reported with an error and left for a later run.
+pub(crate) async fn v1_prune_slot(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+    Query(query): Query<PruneQuery>,
+) -> impl IntoResponse {
+    if slot_id >= state.config.replication.total_slots {
+        return response_error(
+            StatusCode::NOT_FOUND,
+            format!("slot not found: {}", slot_id),
+        );
+    }
+    if !state.prune_versions_operation.retention().is_enabled() {
+        return response_error(StatusCode::NOT_FOUND, "retention is not configured");
+    }
+
+    let replicas = match resolve_replica_nodes(&state, slot_id).await {
+        Ok(replicas) => replicas,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    let mut items = Vec::with_capacity(replicas.len());
+    for replica in &replicas {
+        let result = if replica.node_id == state.node.node_id() {
+            state
+                .prune_versions_operation
+                .run(PruneVersionsOperationRequest {
+                    slot_id,
+                    dry_run: query.dry_run,
+                })
+                .await
+        } else {
+            state
+                .cluster_client
+                .prune_versions(&replica.node_id, slot_id, query.dry_run)
+                .await
+        };
+
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error.to_string())),
+        };
+        items.push(PruneReplicaItem {
+            node_id: replica.node_id.clone(),
+            result,
+            error,
+        });
+    }
+
+    (
+        StatusCode::OK,
+        Json(PruneSlotResponse {
+            slot_id,
+            dry_run: query.dry_run,
+            replicas: items,
+        }),
+    )
+        .into_response()
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn v1_put_blob(
     State(state): State<Arc<ServerState>>,
     Path(raw_path): Path<String>,
+    Query(upload): Query<UploadQuery>,
+    Query(tagging): Query<TaggingQuery>,
+    OriginalUri(uri): OriginalUri,
+    upload_ticket: Option<Extension<UploadTicket>>,
     headers: HeaderMap,
-    body: Bytes,
+    body: Body,
 ) -> impl IntoResponse {
     let path = match normalize_blob_path(&raw_path) {
         Ok(path) => path,
         Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
     };
 
-    let slot_id = slot_for_key(&path, state.config.replication.total_slots);
+    if let Some(upload_id) = upload.upload_id {
+        return uploads::upload_part(&state, path, upload_id, upload.part_number, body).await;
+    }
+    if tagging.tagging.is_some() {
+        let body = match axum::body::to_bytes(body, TAGGING_BODY_MAX_BYTES).await {
+            Ok(body) => body,
+            Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+        };
+        return tagging::put_blob_tags(&state, path, &uri, &headers, body).await;
+    }
+
+    let slot_id = state.config.replication.slot_for_key(&path);
     let write_id = headers
         .get("x-rimio-write-id")
         .and_then(|value| value.to_str().ok())
@@ -99,51 +380,122 @@ pub(crate) async fn v1_put_blob(
         .unwrap_or_else(|| format!("auto-{}", ulid::Ulid::new()));
 
     let cache_key = format!("{}:{}:{}", slot_id, path, write_id);
-    if let Some(cached) = state.idempotent_puts.read().await.get(&cache_key).cloned() {
-        let response = PutBlobResponse {
-            path,
-            slot_id,
-            generation: cached.generation,
-            etag: cached.etag,
-            size_bytes: cached.size_bytes,
-            committed_replicas: cached.committed_replicas,
-            idempotent_replay: Some(true),
-        };
+    if let Some(cached) = cached_put(&state, &cache_key).await {
+        return put_blob_response(StatusCode::OK, path, slot_id, cached, Some(true));
+    }
 
-        return (StatusCode::OK, Json(response)).into_response();
+    if let Some(response) = refuse_unarchived_write(&state).await {
+        return response;
     }
 
-    let replicas = match resolve_replica_nodes(&state, slot_id).await {
+    let consistency = match parse_write_consistency(&headers) {
+        Ok(consistency) => consistency,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
+    let mut expected_generation = match conditional::parse_expected_generation(&headers) {
+        Ok(expected_generation) => expected_generation,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
+    let metadata = match user_metadata(&headers) {
+        Ok(metadata) => metadata,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
+    let content = put_content_headers(&headers);
+
+    let hints = RoutingHints::from_request(&headers, &uri);
+    let replicas = match route_blob_request(&state, slot_id, &hints).await {
         Ok(replicas) => replicas,
-        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+        Err(response) => return response,
     };
 
-    let operation_result = state
-        .put_blob_operation
-        .run(PutBlobOperationRequest {
-            path: path.clone(),
-            slot_id,
-            write_id: write_id.clone(),
-            body,
-            replicas,
-            local_node_id: state.node.node_id().to_string(),
-        })
-        .await;
+    if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+        return response;
+    }
 
-    let (status, generation, etag, size_bytes, committed_replicas) = match operation_result {
-        Ok(PutBlobOperationOutcome::Committed(result)) => (
-            StatusCode::CREATED,
-            result.generation,
-            result.etag,
-            result.size_bytes,
-            result.committed_replicas,
-        ),
+    let _write_guard = match claim_write_lease(&state, slot_id, &replicas, &uri, &hints).await {
+        Ok(guard) => guard,
+        Err(response) => return response,
+    };
+
+    // An upload token is spent only here, on the node that coordinates the
+    // write, and not on a node that redirected or proxied the upload.
+    if let Some(Extension(ticket)) = &upload_ticket
+        && let Some(response) = ticket.redeem().await
+    {
+        return response;
+    }
+
+    // The version the preconditions held against becomes the expected
+    // generation, so the check and the write cannot be split by another
+    // writer.
+    if conditional::has_write_preconditions(&headers) {
+        let current = match current_blob_meta(&state, slot_id, &path, &replicas).await {
+            Ok(current) => current,
+            Err(response) => return response,
+        };
+        if let Some(response) = conditional::check_write(&headers, current.as_ref()) {
+            return response;
+        }
+        expected_generation =
+            expected_generation.or(Some(current.map_or(0, |meta| meta.generation)));
+    }
+
+    let operation_result = if streams_put_body(&headers) {
+        state
+            .put_blob_operation
+            .run_stream(
+                PutBlobStreamRequest {
+                    path: path.clone(),
+                    slot_id,
+                    replicas,
+                    local_node_id: state.node.node_id().to_string(),
+                    consistency,
+                    expected_generation,
+                    metadata,
+                    tags: BTreeMap::new(),
+                    content,
+                },
+                body.into_data_stream()
+                    .map_err(|error| RimError::Http(error.to_string())),
+            )
+            .await
+    } else {
+        let body = match axum::body::to_bytes(body, BUFFERED_PUT_MAX_BYTES).await {
+            Ok(body) => body,
+            Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+        };
+        state
+            .put_blob_operation
+            .run(PutBlobOperationRequest {
+                path: path.clone(),
+                slot_id,
+                write_id: write_id.clone(),
+                body,
+                replicas,
+                local_node_id: state.node.node_id().to_string(),
+                consistency,
+                expected_generation,
+                metadata,
+                tags: BTreeMap::new(),
+                content,
+            })
+            .await
+    };
+
+    let result = match operation_result {
+        Ok(PutBlobOperationOutcome::Committed(result)) => result,
         Ok(PutBlobOperationOutcome::Conflict) => {
             return response_error(
                 StatusCode::CONFLICT,
                 "meta commit rejected by generation check",
             );
         }
+        Ok(PutBlobOperationOutcome::PreconditionFailed { current_generation }) => {
+            return conditional::generation_mismatch(current_generation);
+        }
+        Err(RimError::InvalidRequest(message)) => {
+            return response_error(StatusCode::BAD_REQUEST, message);
+        }
         Err(RimError::InsufficientReplicas { required, found }) => {
             return response_error(
                 StatusCode::SERVICE_UNAVAILABLE,
@@ -156,32 +508,374 @@ pub(crate) async fn v1_put_blob(
         Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
     };
 
-    state.idempotent_puts.write().await.insert(
-        cache_key,
-        PutCacheEntry {
-            generation,
-            etag: etag.clone(),
-            size_bytes,
-            committed_replicas,
-        },
-    );
+    state
+        .slot_heat
+        .record(slot_id, SlotTraffic::Write, result.size_bytes);
+
+    let entry = PutCacheEntry {
+        generation: result.generation,
+        etag: result.etag,
+        size_bytes: result.size_bytes,
+        committed_replicas: result.committed_replicas,
+        acked_replicas: result.acked_replicas,
+        coordinator: state.node.node_id().to_string(),
+    };
+    remember_put(&state, cache_key, &entry).await;
+
+    put_blob_response(StatusCode::CREATED, path, slot_id, entry, None)
+}
+
+pub(crate) fn parse_write_consistency(
+    headers: &HeaderMap,
+) -> std::result::Result<WriteConsistency, String> {
+    match headers
+        .get("x-rimio-write-consistency")
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(raw) => WriteConsistency::parse(raw)
+            .ok_or_else(|| format!("invalid x-rimio-write-consistency: {}", raw)),
+        None => Ok(WriteConsistency::Quorum),
+    }
+}
+
+/// Reads `x-rimio-read-consistency`, falling back to the cluster default.
+fn parse_read_consistency(
+    state: &ServerState,
+    headers: &HeaderMap,
+) -> std::result::Result<ReadConsistency, String> {
+    match headers
+        .get("x-rimio-read-consistency")
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(raw) => ReadConsistency::parse(raw)
+            .ok_or_else(|| format!("invalid x-rimio-read-consistency: {}", raw)),
+        None => Ok(state.config.replication.read_consistency),
+    }
+}
+
+pub(crate) fn read_quorum_error(required: usize, found: usize) -> Response {
+    response_error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!(
+            "read quorum not reached: required={}, answered={}",
+            required, found
+        ),
+    )
+}
+
+/// Bodies up to this size are buffered and pushed to replicas with the
+/// write; larger or unsized ones are streamed to disk and pulled by replicas.
+const BUFFERED_PUT_MAX_BYTES: usize = 8 * 1024 * 1024;
+
+/// Largest `PUT ?tagging` body read.
+const TAGGING_BODY_MAX_BYTES: usize = 64 * 1024;
+
+/// Header prefixes carrying user metadata on a PUT; reads return it under
+/// the first.
+const USER_METADATA_PREFIXES: [&str; 2] = ["x-rimio-meta-", "x-amz-meta-"];
+
+/// The headers of a PUT describing its body, stored to be sent back on reads.
+fn put_content_headers(headers: &HeaderMap) -> ContentHeaders {
+    let value = |name: HeaderName| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    ContentHeaders {
+        content_type: value(header::CONTENT_TYPE),
+        content_encoding: value(header::CONTENT_ENCODING),
+        cache_control: value(header::CACHE_CONTROL),
+    }
+}
+
+/// The user metadata of a PUT, keyed by the header name without its prefix.
+fn user_metadata(headers: &HeaderMap) -> std::result::Result<BTreeMap<String, String>, String> {
+    let mut metadata = BTreeMap::new();
+    for (name, value) in headers {
+        let Some(key) = USER_METADATA_PREFIXES
+            .iter()
+            .find_map(|prefix| name.as_str().strip_prefix(prefix))
+        else {
+            continue;
+        };
+        let value = value
+            .to_str()
+            .map_err(|_| format!("{} must be ASCII", name))?;
+        metadata.insert(key.to_string(), value.trim().to_string());
+    }
+    Ok(metadata)
+}
+
+fn streams_put_body(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok())
+        .is_none_or(|length| length > BUFFERED_PUT_MAX_BYTES)
+}
+
+/// Looks a write id up in memory first, then in the node store, so retries
+/// are still recognized after a restart.
+async fn cached_put(state: &ServerState, cache_key: &str) -> Option<PutCacheEntry> {
+    if let Some(cached) = state.idempotent_puts.read().await.get(cache_key).cloned() {
+        return Some(cached);
+    }
+
+    let raw = match state.node_store.get_idempotency_record(cache_key) {
+        Ok(raw) => raw?,
+        Err(error) => {
+            tracing::warn!("Failed to load idempotency record: {}", error);
+            return None;
+        }
+    };
+    let entry: PutCacheEntry = serde_json::from_str(&raw).ok()?;
+
+    state
+        .idempotent_puts
+        .write()
+        .await
+        .insert(cache_key.to_string(), entry.clone());
+    Some(entry)
+}
+
+async fn remember_put(state: &ServerState, cache_key: String, entry: &PutCacheEntry) {
+    match serde_json::to_string(entry) {
+        Ok(raw) => {
+            if let Err(error) =
+                state
+                    .node_store
+                    .put_idempotency_record(&cache_key, &raw, IDEMPOTENCY_RECORD_TTL)
+            {
+                tracing::warn!("Failed to persist idempotency record: {}", error);
+            }
+        }
+        Err(error) => tracing::warn!("Failed to encode idempotency record: {}", error),
+    }
+
+    state
+        .idempotent_puts
+        .write()
+        .await
+        .insert(cache_key, entry.clone());
+}
+
+/// The current version of `path`, read before a conditional write; `None`
+/// when it is missing or deleted.
+async fn current_blob_meta(
+    state: &ServerState,
+    slot_id: u16,
+    path: &str,
+    replicas: &[NodeInfo],
+) -> std::result::Result<Option<BlobMeta>, Response> {
+    let outcome = state
+        .read_blob_operation
+        .run(ReadBlobOperationRequest {
+            slot_id,
+            path: path.to_string(),
+            replicas: replicas.to_vec(),
+            local_node_id: state.node.node_id().to_string(),
+            include_body: false,
+            range: None,
+            if_range: None,
+            consistency: state.config.replication.read_consistency,
+        })
+        .await;
+
+    match outcome {
+        Ok(ReadBlobOperationOutcome::Found(result)) => Ok(Some(result.meta)),
+        Ok(ReadBlobOperationOutcome::NotFound) | Ok(ReadBlobOperationOutcome::Deleted) => Ok(None),
+        Err(RimError::InsufficientReplicas { required, found }) => {
+            Err(read_quorum_error(required, found))
+        }
+        Err(error) => Err(response_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            error.to_string(),
+        )),
+    }
+}
+
+/// Reports the committed generation, which nodes acknowledged it and who
+/// coordinated the write, in the body and as `x-rimio-*` headers.
+pub(crate) fn put_blob_response(
+    status: StatusCode,
+    path: String,
+    slot_id: u16,
+    entry: PutCacheEntry,
+    idempotent_replay: Option<bool>,
+) -> Response {
+    let mut headers = HeaderMap::new();
+    if let Some(value) = conditional::quoted_etag(&entry.etag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&entry.generation.to_string()) {
+        headers.insert("x-rimio-generation", value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&entry.coordinator) {
+        headers.insert("x-rimio-coordinator", value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&entry.acked_replicas.join(",")) {
+        headers.insert("x-rimio-acked-replicas", value);
+    }
 
     let response = PutBlobResponse {
         path,
         slot_id,
-        generation,
-        etag,
-        size_bytes,
-        committed_replicas,
-        idempotent_replay: None,
+        generation: entry.generation,
+        etag: entry.etag,
+        size_bytes: entry.size_bytes,
+        committed_replicas: entry.committed_replicas,
+        acked_replicas: entry.acked_replicas,
+        coordinator: entry.coordinator,
+        idempotent_replay,
     };
 
-    (status, Json(response)).into_response()
+    (status, headers, Json(response)).into_response()
+}
+
+/// Commits a multipart batch atomically. Each field is named `put` or `delete`
+/// and carries the blob path as its filename; all paths must share one slot.
+pub(crate) async fn v1_commit_batch(
+    State(state): State<Arc<ServerState>>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    if let Some(response) = refuse_unarchived_write(&state).await {
+        return response;
+    }
+
+    let mut entries = Vec::new();
+    let mut slot_id: Option<u16> = None;
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+        };
+
+        let op = field.name().unwrap_or_default().to_string();
+        let path = match normalize_blob_path(field.file_name().unwrap_or_default()) {
+            Ok(path) => path,
+            Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+        };
+
+        let path_slot = state.config.replication.slot_for_key(&path);
+        match slot_id {
+            Some(current) if current != path_slot => {
+                return response_error(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "batch paths must share one slot: slot={} path={} path_slot={}",
+                        current, path, path_slot
+                    ),
+                );
+            }
+            _ => slot_id = Some(path_slot),
+        }
+
+        match op.as_str() {
+            "put" => {
+                let body = match field.bytes().await {
+                    Ok(body) => body,
+                    Err(error) => {
+                        return response_error(StatusCode::BAD_REQUEST, error.to_string());
+                    }
+                };
+                entries.push(CommitBatchEntry::Put { path, body });
+            }
+            "delete" => entries.push(CommitBatchEntry::Delete { path }),
+            other => {
+                return response_error(
+                    StatusCode::BAD_REQUEST,
+                    format!("unsupported batch field: {}", other),
+                );
+            }
+        }
+    }
+
+    let Some(slot_id) = slot_id else {
+        return response_error(StatusCode::BAD_REQUEST, "batch cannot be empty");
+    };
+
+    let write_id = headers
+        .get("x-rimio-write-id")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| format!("batch-{}", ulid::Ulid::new()));
+
+    let hints = RoutingHints::from_request(&headers, &uri);
+    let replicas = match route_blob_request(&state, slot_id, &hints).await {
+        Ok(replicas) => replicas,
+        Err(response) => return response,
+    };
+
+    if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+        return response;
+    }
+
+    let _write_guard = match claim_write_lease(&state, slot_id, &replicas, &uri, &hints).await {
+        Ok(guard) => guard,
+        Err(response) => return response,
+    };
+
+    let operation_result = state
+        .commit_batch_operation
+        .run(CommitBatchOperationRequest {
+            slot_id,
+            write_id,
+            entries,
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
+        })
+        .await;
+
+    match operation_result {
+        Ok(CommitBatchOperationOutcome::Committed(result)) => (
+            StatusCode::CREATED,
+            Json(CommitBatchResponse {
+                slot_id,
+                committed_replicas: result.committed_replicas,
+                items: result
+                    .items
+                    .into_iter()
+                    .map(|item| CommitBatchResponseItem {
+                        path: item.path,
+                        deleted: item.deleted,
+                        generation: item.generation,
+                        etag: item.etag,
+                        size_bytes: item.size_bytes,
+                    })
+                    .collect(),
+            }),
+        )
+            .into_response(),
+        Ok(CommitBatchOperationOutcome::Conflict) => response_error(
+            StatusCode::CONFLICT,
+            "batch commit rejected by generation check",
+        ),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::BAD_REQUEST, message),
+        Err(RimError::InsufficientReplicas { required, found }) => response_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "quorum not reached: required={}, committed={}",
+                required, found
+            ),
+        ),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
 }
 
 pub(crate) async fn v1_get_blob(
     State(state): State<Arc<ServerState>>,
     Path(raw_path): Path<String>,
+    Query(upload): Query<UploadQuery>,
+    Query(read): Query<BlobReadQuery>,
+    Query(tagging): Query<TaggingQuery>,
     headers: HeaderMap,
 ) -> impl IntoResponse {
     let path = match normalize_blob_path(&raw_path) {
@@ -189,109 +883,338 @@ pub(crate) async fn v1_get_blob(
         Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
     };
 
+    if let Some(upload_id) = upload.upload_id {
+        return uploads::list_upload_parts(&state, path, upload_id);
+    }
+    if tagging.tagging.is_some() {
+        return tagging::get_blob_tags(&state, path, &headers).await;
+    }
+    if read.manifest.is_some() {
+        return get_blob_manifest(&state, path, &headers).await;
+    }
+    let response_headers = match blob_response_headers(&state, &path, &read) {
+        Ok(response_headers) => response_headers,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
+
     let requested_range = match parse_range_header(&headers) {
         Ok(range) => range,
         Err(message) => return response_error(StatusCode::RANGE_NOT_SATISFIABLE, message),
     };
+    let if_range = headers
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let consistency = match parse_read_consistency(&state, &headers) {
+        Ok(consistency) => consistency,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
 
-    let slot_id = slot_for_key(&path, state.config.replication.total_slots);
-    let replicas = match resolve_replica_nodes(&state, slot_id).await {
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let hints = RoutingHints::from_headers(&headers);
+    let replicas = match route_blob_request(&state, slot_id, &hints).await {
         Ok(replicas) => replicas,
-        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+        Err(response) => return response,
     };
 
     let outcome = state
         .read_blob_operation
-        .run(ReadBlobOperationRequest {
+        .run_stream(ReadBlobOperationRequest {
             slot_id,
             path: path.clone(),
             replicas,
             local_node_id: state.node.node_id().to_string(),
             include_body: true,
             range: requested_range,
+            if_range,
+            consistency,
         })
         .await;
 
     let result = match outcome {
-        Ok(ReadBlobOperationOutcome::Found(result)) => result,
-        Ok(ReadBlobOperationOutcome::NotFound) => {
+        Ok(ReadBlobStreamOutcome::Found(result)) => result,
+        Ok(ReadBlobStreamOutcome::NotFound) => {
             return response_error(StatusCode::NOT_FOUND, "object not found");
         }
-        Ok(ReadBlobOperationOutcome::Deleted) => {
+        Ok(ReadBlobStreamOutcome::Deleted) => {
             return response_error(StatusCode::GONE, "object deleted");
         }
+        Err(RimError::RangeNotSatisfiable { size_bytes }) => {
+            return range_not_satisfiable_response(size_bytes);
+        }
         Err(RimError::InvalidRequest(message)) => {
-            return response_error(StatusCode::RANGE_NOT_SATISFIABLE, message);
+            return response_error(StatusCode::BAD_REQUEST, message);
+        }
+        Err(RimError::InsufficientReplicas { required, found }) => {
+            return read_quorum_error(required, found);
         }
+        Err(RimError::ArchiveUnavailable {
+            message,
+            retry_after,
+        }) => return archive_unavailable_response(message, retry_after),
         Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
     };
 
-    let body = result.body.unwrap_or_default();
-    let mut response = Response::new(body.clone().into());
-    *response.status_mut() = if requested_range.is_some() {
+    let cache_headers = blob_cache_headers(&state, &path, &result.meta);
+    match conditional::evaluate_read(&headers, &result.meta) {
+        ReadPrecondition::Serve => {}
+        ReadPrecondition::NotModified => return conditional::not_modified(cache_headers),
+        ReadPrecondition::Failed => return conditional::precondition_failed("precondition failed"),
+    }
+
+    let body_len = result
+        .body_range
+        .map(|range| range.end - range.start + 1)
+        .unwrap_or_default();
+    state.slot_heat.record(slot_id, SlotTraffic::Read, body_len);
+    let body = result.body.inspect_err(move |error| {
+        tracing::warn!("blob read failed mid-stream: path={} error={}", path, error);
+    });
+    let partial = result.partial;
+    let mut response = Response::new(Body::from_stream(body));
+    *response.status_mut() = if partial {
         StatusCode::PARTIAL_CONTENT
     } else {
         StatusCode::OK
     };
-
-    response.headers_mut().insert(
-        header::CONTENT_TYPE,
-        HeaderValue::from_static("application/octet-stream"),
-    );
+    response.headers_mut().extend(cache_headers);
+    response
+        .headers_mut()
+        .extend(blob_content_headers(&result.meta));
     response
         .headers_mut()
         .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
 
-    if let Ok(value) = HeaderValue::from_str(&body.len().to_string()) {
+    if let Ok(value) = HeaderValue::from_str(&body_len.to_string()) {
         response.headers_mut().insert(header::CONTENT_LENGTH, value);
     }
 
-    if let Ok(value) = HeaderValue::from_str(&result.meta.etag) {
-        response.headers_mut().insert(header::ETAG, value);
+    response
+        .headers_mut()
+        .extend(blob_meta_headers(&result.meta));
+
+    if partial && let Some(range) = result.body_range {
+        let content_range = format!(
+            "bytes {}-{}/{}",
+            range.start, range.end, result.meta.size_bytes
+        );
+        if let Ok(value) = HeaderValue::from_str(&content_range) {
+            response.headers_mut().insert(header::CONTENT_RANGE, value);
+        }
     }
-    if let Ok(value) = HeaderValue::from_str(&result.meta.generation.to_string()) {
-        response.headers_mut().insert("x-rimio-generation", value);
+    response.headers_mut().extend(response_headers);
+
+    response
+}
+
+/// `GET ?manifest`: the parts of the current version with their offsets,
+/// checksums and the nodes that hold them.
+async fn get_blob_manifest(state: &ServerState, path: String, headers: &HeaderMap) -> Response {
+    let consistency = match parse_read_consistency(state, headers) {
+        Ok(consistency) => consistency,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
+
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let hints = RoutingHints::from_headers(headers);
+    let replicas = match route_blob_request(state, slot_id, &hints).await {
+        Ok(replicas) => replicas,
+        Err(response) => return response,
+    };
+
+    let outcome = state
+        .read_blob_operation
+        .manifest(ReadBlobOperationRequest {
+            slot_id,
+            path,
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
+            include_body: false,
+            range: None,
+            if_range: None,
+            consistency,
+        })
+        .await;
+
+    match outcome {
+        Ok(ReadBlobManifestOutcome::Found(manifest)) => {
+            (StatusCode::OK, Json(manifest)).into_response()
+        }
+        Ok(ReadBlobManifestOutcome::NotFound) => {
+            response_error(StatusCode::NOT_FOUND, "object not found")
+        }
+        Ok(ReadBlobManifestOutcome::Deleted) => response_error(StatusCode::GONE, "object deleted"),
+        Err(RimError::InsufficientReplicas { required, found }) => {
+            read_quorum_error(required, found)
+        }
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
     }
+}
 
-    if requested_range.is_some() {
-        if let Some(range) = result.body_range {
-            let content_range = format!(
-                "bytes {}-{}/{}",
-                range.start, range.end, result.meta.size_bytes
-            );
-            if let Ok(value) = HeaderValue::from_str(&content_range) {
-                response.headers_mut().insert(header::CONTENT_RANGE, value);
+/// Validators and freshness for blob reads: a quoted `ETag`, `Last-Modified`
+/// and the `Cache-Control` stored with the blob or else configured for the
+/// path, if any.
+fn blob_cache_headers(state: &ServerState, path: &str, meta: &BlobMeta) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(value) = conditional::quoted_etag(&meta.etag) {
+        headers.insert(header::ETAG, value);
+    }
+    let last_modified = meta
+        .updated_at
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    if let Ok(value) = HeaderValue::from_str(&last_modified) {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+    if let Some(cache_control) = meta.content.cache_control.clone().or_else(|| {
+        state
+            .config
+            .cache_headers
+            .as_ref()
+            .and_then(|config| config.cache_control(path))
+    }) && let Ok(value) = HeaderValue::from_str(&cache_control)
+    {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    headers
+}
+
+/// Headers configured for the path under `response_headers`, then the
+/// `response-*` overrides of the request, as S3 allows on GET. An invalid
+/// override is returned as the message of a 400.
+fn blob_response_headers(
+    state: &ServerState,
+    path: &str,
+    read: &BlobReadQuery,
+) -> std::result::Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    if let Some(config) = state.config.response_headers.as_ref() {
+        for (name, value) in config.headers(path) {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.insert(name, value);
             }
         }
     }
 
-    response
+    let overrides = [
+        (header::CACHE_CONTROL, &read.response_cache_control),
+        (
+            header::CONTENT_DISPOSITION,
+            &read.response_content_disposition,
+        ),
+        (header::CONTENT_ENCODING, &read.response_content_encoding),
+        (header::CONTENT_LANGUAGE, &read.response_content_language),
+        (header::CONTENT_TYPE, &read.response_content_type),
+        (header::EXPIRES, &read.response_expires),
+    ];
+    for (name, value) in overrides {
+        let Some(value) = value else {
+            continue;
+        };
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| format!("invalid response-{} override", name))?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+/// `Content-Type` and `Content-Encoding` given when the blob was written;
+/// blobs written without a type are `application/octet-stream`.
+fn blob_content_headers(meta: &BlobMeta) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let content_type = meta
+        .content
+        .content_type
+        .as_deref()
+        .and_then(|value| HeaderValue::from_str(value).ok())
+        .unwrap_or_else(|| HeaderValue::from_static("application/octet-stream"));
+    headers.insert(header::CONTENT_TYPE, content_type);
+    if let Some(value) = meta
+        .content
+        .content_encoding
+        .as_deref()
+        .and_then(|value| HeaderValue::from_str(value).ok())
+    {
+        headers.insert(header::CONTENT_ENCODING, value);
+    }
+    headers
 }
 
+/// Version details of the blob beyond the HTTP validators, so a HEAD answers
+/// what a client would otherwise GET the object for.
+fn blob_meta_headers(meta: &BlobMeta) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let values = [
+        ("x-rimio-generation", meta.generation.to_string()),
+        ("x-rimio-version", meta.version.to_string()),
+        ("x-rimio-part-count", meta.part_count.to_string()),
+        ("x-rimio-part-size", meta.part_size.to_string()),
+        (
+            "x-rimio-updated-at",
+            meta.updated_at
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        ),
+    ];
+    for (name, value) in values {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    }
+    for (key, value) in &meta.metadata {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(format!("{}{}", USER_METADATA_PREFIXES[0], key).as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+    if !meta.tags.is_empty() {
+        headers.insert("x-rimio-tag-count", HeaderValue::from(meta.tags.len()));
+    }
+    headers
+}
+
+/// Answers from the head alone: no part is read, locally or from a peer.
 pub(crate) async fn v1_head_blob(
     State(state): State<Arc<ServerState>>,
     Path(raw_path): Path<String>,
+    Query(read): Query<BlobReadQuery>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let path = match normalize_blob_path(&raw_path) {
         Ok(path) => path,
         Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
     };
+    let response_headers = match blob_response_headers(&state, &path, &read) {
+        Ok(response_headers) => response_headers,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
+    let consistency = match parse_read_consistency(&state, &headers) {
+        Ok(consistency) => consistency,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
 
-    let slot_id = slot_for_key(&path, state.config.replication.total_slots);
-    let replicas = match resolve_replica_nodes(&state, slot_id).await {
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let hints = RoutingHints::from_headers(&headers);
+    let replicas = match route_blob_request(&state, slot_id, &hints).await {
         Ok(replicas) => replicas,
-        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+        Err(response) => return response,
     };
 
     let outcome = state
         .read_blob_operation
         .run(ReadBlobOperationRequest {
             slot_id,
-            path,
+            path: path.clone(),
             replicas,
             local_node_id: state.node.node_id().to_string(),
             include_body: false,
             range: None,
+            if_range: None,
+            consistency,
         })
         .await;
 
@@ -303,20 +1226,37 @@ pub(crate) async fn v1_head_blob(
         Ok(ReadBlobOperationOutcome::Deleted) => {
             return response_error(StatusCode::GONE, "object deleted");
         }
+        Err(RimError::InsufficientReplicas { required, found }) => {
+            return read_quorum_error(required, found);
+        }
+        Err(RimError::ArchiveUnavailable {
+            message,
+            retry_after,
+        }) => return archive_unavailable_response(message, retry_after),
         Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
     };
 
+    let cache_headers = blob_cache_headers(&state, &path, &result.meta);
+    match conditional::evaluate_read(&headers, &result.meta) {
+        ReadPrecondition::Serve => {}
+        ReadPrecondition::NotModified => return conditional::not_modified(cache_headers),
+        ReadPrecondition::Failed => return conditional::precondition_failed("precondition failed"),
+    }
+    state.slot_heat.record(slot_id, SlotTraffic::Read, 0);
+
     let mut response = Response::new(axum::body::Body::empty());
     *response.status_mut() = StatusCode::OK;
-    if let Ok(value) = HeaderValue::from_str(&result.meta.etag) {
-        response.headers_mut().insert(header::ETAG, value);
-    }
-    if let Ok(value) = HeaderValue::from_str(&result.meta.generation.to_string()) {
-        response.headers_mut().insert("x-rimio-generation", value);
-    }
+    response.headers_mut().extend(cache_headers);
+    response
+        .headers_mut()
+        .extend(blob_content_headers(&result.meta));
+    response
+        .headers_mut()
+        .extend(blob_meta_headers(&result.meta));
     if let Ok(value) = HeaderValue::from_str(&result.meta.size_bytes.to_string()) {
         response.headers_mut().insert(header::CONTENT_LENGTH, value);
     }
+    response.headers_mut().extend(response_headers);
     response
         .headers_mut()
         .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
@@ -327,6 +1267,9 @@ pub(crate) async fn v1_head_blob(
 pub(crate) async fn v1_delete_blob(
     State(state): State<Arc<ServerState>>,
     Path(raw_path): Path<String>,
+    Query(upload): Query<UploadQuery>,
+    Query(tagging): Query<TaggingQuery>,
+    OriginalUri(uri): OriginalUri,
     headers: HeaderMap,
 ) -> impl IntoResponse {
     let path = match normalize_blob_path(&raw_path) {
@@ -334,7 +1277,14 @@ pub(crate) async fn v1_delete_blob(
         Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
     };
 
-    let slot_id = slot_for_key(&path, state.config.replication.total_slots);
+    if let Some(upload_id) = upload.upload_id {
+        return uploads::abort_upload(&state, path, upload_id).await;
+    }
+    if tagging.tagging.is_some() {
+        return tagging::replace_blob_tags(&state, path, &uri, &headers, BTreeMap::new()).await;
+    }
+
+    let slot_id = state.config.replication.slot_for_key(&path);
     let write_id = headers
         .get("x-rimio-write-id")
         .and_then(|value| value.to_str().ok())
@@ -342,9 +1292,19 @@ pub(crate) async fn v1_delete_blob(
         .filter(|value| !value.is_empty())
         .unwrap_or_else(|| format!("delete-{}", ulid::Ulid::new()));
 
-    let replicas = match resolve_replica_nodes(&state, slot_id).await {
+    let hints = RoutingHints::from_request(&headers, &uri);
+    let replicas = match route_blob_request(&state, slot_id, &hints).await {
         Ok(replicas) => replicas,
-        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+        Err(response) => return response,
+    };
+
+    if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+        return response;
+    }
+
+    let _write_guard = match claim_write_lease(&state, slot_id, &replicas, &uri, &hints).await {
+        Ok(guard) => guard,
+        Err(response) => return response,
     };
 
     let operation_result = state
@@ -355,15 +1315,22 @@ pub(crate) async fn v1_delete_blob(
             write_id,
             replicas,
             local_node_id: state.node.node_id().to_string(),
+            expected_generation: None,
         })
         .await;
 
     match operation_result {
-        Ok(DeleteBlobOperationOutcome::Committed(_)) => StatusCode::NO_CONTENT.into_response(),
+        Ok(DeleteBlobOperationOutcome::Committed(_)) => {
+            state.slot_heat.record(slot_id, SlotTraffic::Write, 0);
+            StatusCode::NO_CONTENT.into_response()
+        }
         Ok(DeleteBlobOperationOutcome::Conflict) => response_error(
             StatusCode::CONFLICT,
             "tombstone commit rejected by generation check",
         ),
+        Ok(DeleteBlobOperationOutcome::PreconditionFailed { current_generation }) => {
+            conditional::generation_mismatch(current_generation)
+        }
         Err(RimError::InsufficientReplicas { required, found }) => response_error(
             StatusCode::SERVICE_UNAVAILABLE,
             format!(
@@ -379,45 +1346,242 @@ pub(crate) async fn v1_list_blobs(
     State(state): State<Arc<ServerState>>,
     Query(query): Query<ListQuery>,
 ) -> impl IntoResponse {
-    let result = state
-        .list_blobs_operation
-        .run(ListBlobsOperationRequest {
-            prefix: query.prefix,
-            limit: query.limit,
-            cursor: query.cursor,
-            include_deleted: query.include_deleted,
-        })
-        .await;
+    let ndjson = match query.format.as_str() {
+        "json" => false,
+        "ndjson" => true,
+        "parquet" => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                "Parquet inventories are served by /_/api/v1/inventory",
+            );
+        }
+        other => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                format!("unsupported list format: {}", other),
+            );
+        }
+    };
+    if query.stream && !ndjson {
+        return response_error(
+            StatusCode::BAD_REQUEST,
+            "stream=true requires format=ndjson",
+        );
+    }
+    let stream = query.stream;
+    let limit = if stream {
+        LIST_STREAM_PAGE_SIZE
+    } else {
+        query.limit.clamp(1, MAX_LIST_LIMIT)
+    };
+    let request = match list_request(query, limit) {
+        Ok(request) => request,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
+    if stream {
+        return stream_list_ndjson(state, request);
+    }
 
-    let result = match result {
+    let result = match state.list_blobs_operation.run(request).await {
         Ok(result) => result,
+        Err(RimError::InvalidRequest(message)) => {
+            return response_error(StatusCode::BAD_REQUEST, message);
+        }
         Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
     };
 
-    let items = result
-        .items
-        .into_iter()
-        .map(|item| ListItem {
-            path: item.path,
-            generation: item.generation,
-            etag: item.etag,
-            size_bytes: item.size_bytes,
-            deleted: item.deleted,
-            updated_at: item.updated_at.to_rfc3339(),
-        })
-        .collect();
+    let items: Vec<ListItem> = result.items.into_iter().map(list_item).collect();
+
+    if ndjson {
+        let mut response = ndjson_response(Body::from(ndjson_rows(&items)));
+        if let Some(cursor) = result.next_cursor.as_deref()
+            && let Ok(value) = HeaderValue::from_str(cursor)
+        {
+            response.headers_mut().insert(NEXT_CURSOR_HEADER, value);
+        }
+        return response;
+    }
 
     (
         StatusCode::OK,
         Json(ListResponse {
             items,
+            truncated: result.next_cursor.is_some(),
             next_cursor: result.next_cursor,
         }),
     )
         .into_response()
 }
 
-fn parse_range_header(headers: &HeaderMap) -> std::result::Result<Option<ReadByteRange>, String> {
+/// Largest page a listing answers; `next_cursor` leads to the rest.
+pub(crate) const MAX_LIST_LIMIT: usize = 1000;
+
+/// Header carrying the cursor of the next page of an NDJSON listing.
+const NEXT_CURSOR_HEADER: &str = "x-rimio-next-cursor";
+
+/// Rows a streamed listing reads from the slots per page.
+const LIST_STREAM_PAGE_SIZE: usize = 1000;
+
+/// Streams every blob under the prefix, starting after the cursor, one JSON
+/// object per line. A page is only read once the client has taken the
+/// previous one, so memory stays at one page however large the prefix is.
+/// A failure after the first row cuts the response short.
+fn stream_list_ndjson(state: Arc<ServerState>, request: ListBlobsOperationRequest) -> Response {
+    let start = Some(request);
+    let pages = futures_util::stream::try_unfold(start, move |request| {
+        let state = state.clone();
+        async move {
+            let Some(request) = request else {
+                return Ok(None);
+            };
+            let page = state.list_blobs_operation.run(request.clone()).await?;
+            let next = page
+                .next_cursor
+                .clone()
+                .map(|cursor| ListBlobsOperationRequest {
+                    cursor: Some(cursor),
+                    ..request
+                });
+            let items: Vec<ListItem> = page.items.into_iter().map(list_item).collect();
+            Ok::<_, RimError>(Some((Bytes::from(ndjson_rows(&items)), next)))
+        }
+    })
+    .inspect_err(|error| {
+        tracing::warn!("blob listing stream failed: {}", error);
+    });
+
+    ndjson_response(Body::from_stream(pages))
+}
+
+/// `GET /_/api/v1/inventory` walks every page of a listing from the cursor
+/// into one Parquet file. It takes the filters of `/_/api/v1/blobs`, and
+/// sits on its own route so that only keys granted it can pull a whole
+/// inventory; a grant on the blob routes does not reach it.
+pub(crate) async fn v1_blob_inventory(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<ListQuery>,
+) -> Response {
+    match list_request(query, LIST_STREAM_PAGE_SIZE) {
+        Ok(request) => stream_list_parquet(state, request),
+        Err(message) => response_error(StatusCode::BAD_REQUEST, message),
+    }
+}
+
+/// Walks every page from the cursor like [`stream_list_ndjson`], as one
+/// Parquet file with a row group per page.
+fn stream_list_parquet(state: Arc<ServerState>, request: ListBlobsOperationRequest) -> Response {
+    let start = Some((Some(request), ParquetListing::new()));
+    let chunks = futures_util::stream::try_unfold(start, move |walk| {
+        let state = state.clone();
+        async move {
+            let Some((request, mut listing)) = walk else {
+                return Ok(None);
+            };
+            let Some(request) = request else {
+                return Ok(Some((listing.finish(), None)));
+            };
+            let page = state.list_blobs_operation.run(request.clone()).await?;
+            let next = page
+                .next_cursor
+                .clone()
+                .map(|cursor| ListBlobsOperationRequest {
+                    cursor: Some(cursor),
+                    ..request
+                });
+            let row_group = listing.row_group(&page.items);
+            Ok::<_, RimError>(Some((row_group, Some((next, listing)))))
+        }
+    })
+    .inspect_err(|error| {
+        tracing::warn!("parquet listing stream failed: {}", error);
+    });
+
+    let mut response = Response::new(Body::from_stream(chunks));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/vnd.apache.parquet"),
+    );
+    response.headers_mut().insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"listing.parquet\""),
+    );
+    response
+}
+
+fn list_item(item: ListBlobItem) -> ListItem {
+    ListItem {
+        path: item.path,
+        generation: item.generation,
+        etag: item.etag,
+        size_bytes: item.size_bytes,
+        deleted: item.deleted,
+        updated_at: item.updated_at.to_rfc3339(),
+        tags: item.tags,
+    }
+}
+
+/// Parses the `tag=key=value` filter of a listing.
+/// Turns the list query into a request, checking the parts a page walk would
+/// otherwise only trip over once a streamed response has started.
+fn list_request(
+    query: ListQuery,
+    limit: usize,
+) -> std::result::Result<ListBlobsOperationRequest, String> {
+    let tags = tag_filter(query.tag.as_deref())?;
+    let order_by = match query.order_by.as_deref() {
+        Some(order_by) => ListOrder::parse(order_by).map_err(|error| error.to_string())?,
+        None => ListOrder::Path,
+    };
+    let request = ListBlobsOperationRequest {
+        prefix: query.prefix,
+        limit,
+        cursor: query.cursor,
+        include_deleted: query.include_deleted,
+        tags,
+        order_by,
+        since: query.since,
+        until: query.until,
+    };
+    request.validate().map_err(|error| error.to_string())?;
+    Ok(request)
+}
+
+fn tag_filter(tag: Option<&str>) -> std::result::Result<BTreeMap<String, String>, String> {
+    let Some(tag) = tag else {
+        return Ok(BTreeMap::new());
+    };
+    match tag.split_once('=') {
+        Some((key, value)) if !key.is_empty() => {
+            Ok(BTreeMap::from([(key.to_string(), value.to_string())]))
+        }
+        _ => Err(format!("tag filter must be key=value, got {:?}", tag)),
+    }
+}
+
+pub(crate) fn ndjson_rows<T: serde::Serialize>(items: &[T]) -> String {
+    let mut rows = String::new();
+    for item in items {
+        if let Ok(row) = serde_json::to_string(item) {
+            rows.push_str(&row);
+            rows.push('\n');
+        }
+    }
+    rows
+}
+
+pub(crate) fn ndjson_response(body: Body) -> Response {
+    let mut response = Response::new(body);
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    response
+}
+
+/// Reads a `Range` header; a blank one is treated as absent.
+pub(crate) fn parse_range_header(
+    headers: &HeaderMap,
+) -> std::result::Result<Option<ReadRangeSpec>, String> {
     let Some(value) = headers.get(header::RANGE) else {
         return Ok(None);
     };
@@ -431,28 +1595,252 @@ fn parse_range_header(headers: &HeaderMap) -> std::result::Result<Option<ReadByt
         return Ok(None);
     }
 
-    let Some(raw) = range_value.strip_prefix("bytes=") else {
-        return Err("only bytes= range is supported".to_string());
+    ReadRangeSpec::parse(range_value)
+        .map(Some)
+        .map_err(|error| error.to_string())
+}
+
+pub(crate) async fn v1_begin_transaction(State(state): State<Arc<ServerState>>) -> Response {
+    let txn = state.transactions.begin().await;
+    (StatusCode::CREATED, Json(transaction_response(&txn))).into_response()
+}
+
+pub(crate) async fn v1_get_transaction(
+    State(state): State<Arc<ServerState>>,
+    Path(txn_id): Path<String>,
+) -> Response {
+    match state.transactions.get(&txn_id).await {
+        Some(txn) => (StatusCode::OK, Json(transaction_response(&txn))).into_response(),
+        None => response_error(StatusCode::NOT_FOUND, "transaction not found"),
+    }
+}
+
+pub(crate) async fn v1_abort_transaction(
+    State(state): State<Arc<ServerState>>,
+    Path(txn_id): Path<String>,
+) -> Response {
+    if state.transactions.abort(&txn_id).await {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        response_error(StatusCode::NOT_FOUND, "transaction not found")
+    }
+}
+
+pub(crate) async fn v1_stage_transaction_put(
+    State(state): State<Arc<ServerState>>,
+    Path((txn_id, raw_path)): Path<(String, String)>,
+    body: Bytes,
+) -> Response {
+    stage_transaction_entry(&state, &txn_id, &raw_path, StagedEntry::Put(body)).await
+}
+
+pub(crate) async fn v1_stage_transaction_delete(
+    State(state): State<Arc<ServerState>>,
+    Path((txn_id, raw_path)): Path<(String, String)>,
+) -> Response {
+    stage_transaction_entry(&state, &txn_id, &raw_path, StagedEntry::Delete).await
+}
+
+async fn stage_transaction_entry(
+    state: &ServerState,
+    txn_id: &str,
+    raw_path: &str,
+    entry: StagedEntry,
+) -> Response {
+    let path = match normalize_blob_path(raw_path) {
+        Ok(path) => path,
+        Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
     };
 
-    let mut parts = raw.splitn(2, '-');
-    let start_str = parts.next().unwrap_or_default().trim();
-    let end_str = parts.next().unwrap_or_default().trim();
+    match state.transactions.stage(txn_id, path, entry).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(_) => response_error(StatusCode::NOT_FOUND, "transaction not found"),
+    }
+}
 
-    if start_str.is_empty() || end_str.is_empty() {
-        return Err("only explicit bytes=start-end is supported".to_string());
+pub(crate) async fn v1_commit_transaction(
+    State(state): State<Arc<ServerState>>,
+    Path(txn_id): Path<String>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = refuse_unarchived_write(&state).await {
+        return response;
     }
 
-    let start = start_str
-        .parse::<u64>()
-        .map_err(|_| "invalid range start".to_string())?;
-    let end = end_str
-        .parse::<u64>()
-        .map_err(|_| "invalid range end".to_string())?;
+    // Check the slots before taking the transaction, so a refused commit
+    // leaves the staged writes in place for a retry.
+    let Some(staged) = state.transactions.get(&txn_id).await else {
+        return response_error(StatusCode::NOT_FOUND, "transaction not found");
+    };
+    let mut slot_replicas: BTreeMap<u16, Vec<NodeInfo>> = BTreeMap::new();
+    for path in staged.entries.keys() {
+        let slot_id = state.config.replication.slot_for_key(path);
+        if slot_replicas.contains_key(&slot_id) {
+            continue;
+        }
+        if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+            return response;
+        }
+        let replicas = match resolve_replica_nodes(&state, slot_id).await {
+            Ok(replicas) => replicas,
+            Err(error) => {
+                return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+            }
+        };
+        slot_replicas.insert(slot_id, replicas);
+    }
 
-    if start > end {
-        return Err("range start must be <= range end".to_string());
+    // The staged writes live only on this node, so a slot leased elsewhere
+    // is answered with 421 instead of a redirect. Leases are claimed in slot
+    // order, so two commits over the same slots cannot hold one each.
+    let hints = RoutingHints {
+        no_proxy: true,
+        ..RoutingHints::from_request(&headers, &uri)
+    };
+    let mut write_guards = Vec::with_capacity(slot_replicas.len());
+    for (slot_id, replicas) in &slot_replicas {
+        match claim_write_lease(&state, *slot_id, replicas, &uri, &hints).await {
+            Ok(guard) => write_guards.push(guard),
+            Err(response) => return response,
+        }
     }
 
-    Ok(Some(ReadByteRange { start, end }))
+    let txn = match state.transactions.take(&txn_id).await {
+        Ok(txn) => txn,
+        Err(_) => return response_error(StatusCode::NOT_FOUND, "transaction not found"),
+    };
+
+    if txn.entries.is_empty() {
+        return response_error(StatusCode::BAD_REQUEST, "transaction has no staged writes");
+    }
+
+    let mut slots: BTreeMap<u16, Vec<CommitBatchEntry>> = BTreeMap::new();
+    for (path, entry) in txn.entries {
+        let slot_id = state.config.replication.slot_for_key(&path);
+        let entry = match entry {
+            StagedEntry::Put(body) => CommitBatchEntry::Put { path, body },
+            StagedEntry::Delete => CommitBatchEntry::Delete { path },
+        };
+        slots.entry(slot_id).or_default().push(entry);
+    }
+
+    let mut participants = Vec::with_capacity(slots.len());
+    for (slot_id, entries) in slots {
+        let Some(replicas) = slot_replicas.remove(&slot_id) else {
+            return response_error(
+                StatusCode::CONFLICT,
+                "transaction changed while its slots were being claimed",
+            );
+        };
+
+        participants.push(TwoPhaseParticipant {
+            slot_id,
+            replicas,
+            entries,
+            expected_generations: BTreeMap::new(),
+        });
+    }
+
+    let quorums: HashMap<u16, usize> = participants
+        .iter()
+        .map(|participant| {
+            (
+                participant.slot_id,
+                state.coordinator.write_quorum(participant.replicas.len()),
+            )
+        })
+        .collect();
+
+    let outcome = state
+        .two_phase_commit
+        .perform_2pc(TwoPhaseCommitRequest {
+            txn_id: txn_id.clone(),
+            participants,
+            local_node_id: state.node.node_id().to_string(),
+        })
+        .await;
+
+    match outcome {
+        Ok(TwoPhaseOutcome::Committed(results)) => (
+            StatusCode::CREATED,
+            Json(TransactionCommitResponse {
+                txn_id,
+                state: "committed".to_string(),
+                slots: results
+                    .into_iter()
+                    .map(|result| TransactionSlotItem {
+                        slot_id: result.slot_id,
+                        write_quorum: quorums.get(&result.slot_id).copied().unwrap_or(1),
+                        committed_replicas: result.committed_replicas,
+                        items: result
+                            .items
+                            .into_iter()
+                            .map(|item| CommitBatchResponseItem {
+                                path: item.path,
+                                deleted: item.deleted,
+                                generation: item.generation,
+                                etag: item.etag,
+                                size_bytes: item.size_bytes,
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            }),
+        )
+            .into_response(),
+        Ok(TwoPhaseOutcome::Aborted(votes)) => (
+            StatusCode::CONFLICT,
+            Json(TransactionAbortResponse {
+                txn_id,
+                state: "aborted".to_string(),
+                error: "prepare did not reach quorum".to_string(),
+                votes: votes
+                    .into_iter()
+                    .map(|vote| TransactionVoteItem {
+                        slot_id: vote.slot_id,
+                        node_id: vote.node_id,
+                        vote: match vote.vote {
+                            Vote::Yes => "yes",
+                            Vote::No(_) => "no",
+                            Vote::Locked { .. } => "locked",
+                        }
+                        .to_string(),
+                        reason: vote.vote.reason().map(str::to_string),
+                    })
+                    .collect(),
+            }),
+        )
+            .into_response(),
+        Ok(TwoPhaseOutcome::Conflict) => response_error(
+            StatusCode::CONFLICT,
+            "transaction commit rejected by generation check",
+        ),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::BAD_REQUEST, message),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+fn transaction_response(txn: &StagedTransaction) -> TransactionResponse {
+    TransactionResponse {
+        txn_id: txn.txn_id.clone(),
+        state: "staged".to_string(),
+        created_at: txn.created_at.to_rfc3339(),
+        entries: txn
+            .entries
+            .iter()
+            .map(|(path, entry)| match entry {
+                StagedEntry::Put(body) => TransactionEntryItem {
+                    path: path.clone(),
+                    op: "put".to_string(),
+                    size_bytes: body.len() as u64,
+                },
+                StagedEntry::Delete => TransactionEntryItem {
+                    path: path.clone(),
+                    op: "delete".to_string(),
+                    size_bytes: 0,
+                },
+            })
+            .collect(),
+    }
 }
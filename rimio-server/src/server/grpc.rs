@@ -0,0 +1,270 @@
+// Handlers answer with `tonic::Status`, which is large by design.
+#![allow(clippy::result_large_err)]
+
+use super::internal::fence_slot_epoch;
+use super::{ServerState, normalize_blob_path};
+use async_trait::async_trait;
+use rimio_core::{
+    ApplyHeadsRequest, ApplyHeadsResponse, DecideTransactionRequest, DecideTransactionResponse,
+    GetHeadRequest, GetHeadResponse, GetPartRequest, GetPartResponse, HeadKind, HeadRecord,
+    InternalGetHeadOperationOutcome, InternalGetHeadOperationRequest,
+    InternalGetPartOperationOutcome, InternalGetPartOperationRequest, InternalGrpcHandler,
+    InternalPutHeadBatchItem, InternalPutHeadBatchOperationRequest,
+    InternalPutHeadOperationRequest, InternalPutPartOperationRequest, PrepareTransactionRequest,
+    PrepareTransactionResponse, PutHeadRequest, PutHeadResponse, PutPartRequest, PutPartResponse,
+    RimError, TransactionPeers, Vote, grpc_status,
+};
+use std::sync::Arc;
+use tonic::Status;
+
+/// The internal gRPC service; each call does what its HTTP route does.
+pub(crate) struct InternalGrpcService {
+    state: Arc<ServerState>,
+}
+
+impl InternalGrpcService {
+    pub(crate) fn new(state: Arc<ServerState>) -> Self {
+        Self { state }
+    }
+
+    /// Fences a write by slot epoch and refuses it while the slot is frozen.
+    async fn fence(&self, slot_id: u16, epoch: u64) -> Result<(), Status> {
+        fence_slot_epoch(&self.state, slot_id, epoch)
+            .await
+            .map_err(|(status, message)| grpc_status(status, message))?;
+        if self.state.placement.is_frozen(slot_id).await {
+            return Err(Status::unavailable(format!(
+                "slot {} is frozen for maintenance",
+                slot_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn slot(slot_id: u32) -> Result<u16, Status> {
+    u16::try_from(slot_id).map_err(|_| Status::invalid_argument("slot_id out of range"))
+}
+
+fn path(path: &str) -> Result<String, Status> {
+    normalize_blob_path(path).map_err(|error| Status::invalid_argument(error.to_string()))
+}
+
+fn error_status(error: RimError) -> Status {
+    match error {
+        RimError::InvalidRequest(message) => Status::invalid_argument(message),
+        other => Status::internal(other.to_string()),
+    }
+}
+
+fn batch_items(heads: Vec<HeadRecord>) -> Result<Vec<InternalPutHeadBatchItem>, Status> {
+    heads
+        .into_iter()
+        .map(|head| {
+            let (meta, tombstone) = head.documents().map_err(error_status)?;
+            Ok(InternalPutHeadBatchItem {
+                path: path(&head.path)?,
+                head_kind: head.head_kind,
+                generation: head.generation,
+                head_sha256: head.head_sha256,
+                meta,
+                tombstone,
+            })
+        })
+        .collect()
+}
+
+#[async_trait]
+impl InternalGrpcHandler for InternalGrpcService {
+    async fn get_head(&self, request: GetHeadRequest) -> Result<GetHeadResponse, Status> {
+        let path = path(&request.path)?;
+        let outcome = self
+            .state
+            .internal_get_head_operation
+            .run(InternalGetHeadOperationRequest {
+                slot_id: slot(request.slot_id)?,
+                path: path.clone(),
+            })
+            .await
+            .map_err(error_status)?;
+
+        let head = match outcome {
+            InternalGetHeadOperationOutcome::NotFound => None,
+            InternalGetHeadOperationOutcome::Found(head) => Some(
+                HeadRecord::new(
+                    &path,
+                    match head.head_kind {
+                        HeadKind::Meta => "meta",
+                        HeadKind::Tombstone => "tombstone",
+                    },
+                    head.generation,
+                    &head.head_sha256,
+                    head.meta.as_ref(),
+                    head.tombstone.as_ref(),
+                )
+                .map_err(error_status)?,
+            ),
+        };
+        Ok(GetHeadResponse { head })
+    }
+
+    async fn get_part(&self, request: GetPartRequest) -> Result<GetPartResponse, Status> {
+        let outcome = self
+            .state
+            .internal_get_part_operation
+            .run(InternalGetPartOperationRequest {
+                slot_id: slot(request.slot_id)?,
+                sha256: Some(request.sha256),
+                path: Some(path(&request.path)?),
+                generation: Some(request.generation),
+                part_no: Some(request.part_no),
+            })
+            .await
+            .map_err(|error| Status::internal(error.to_string()))?;
+
+        match outcome {
+            InternalGetPartOperationOutcome::Found(part) => Ok(GetPartResponse {
+                sha256: part.sha256,
+                data: part.bytes,
+            }),
+            InternalGetPartOperationOutcome::NotFound => Err(Status::not_found("part not found")),
+        }
+    }
+
+    async fn put_part(&self, request: PutPartRequest) -> Result<PutPartResponse, Status> {
+        let slot_id = slot(request.slot_id)?;
+        self.fence(slot_id, request.epoch).await?;
+
+        let result = self
+            .state
+            .internal_put_part_operation
+            .run(InternalPutPartOperationRequest {
+                slot_id,
+                path: path(&request.path)?,
+                generation: request.generation,
+                part_no: request.part_no,
+                sha256: request.sha256,
+                body: request.data,
+            })
+            .await
+            .map_err(error_status)?;
+
+        Ok(PutPartResponse {
+            reused: result.reused,
+            sha256: result.sha256,
+        })
+    }
+
+    async fn put_head(&self, request: PutHeadRequest) -> Result<PutHeadResponse, Status> {
+        let slot_id = slot(request.slot_id)?;
+        self.fence(slot_id, request.epoch).await?;
+
+        let Some(head) = request.head else {
+            return Err(Status::invalid_argument("head is required"));
+        };
+        let (meta, tombstone) = head.documents().map_err(error_status)?;
+        let result = self
+            .state
+            .internal_put_head_operation
+            .run(InternalPutHeadOperationRequest {
+                slot_id,
+                query_path: Some(path(&head.path)?),
+                head_kind: head.head_kind,
+                generation: head.generation,
+                head_sha256: head.head_sha256,
+                meta,
+                tombstone,
+            })
+            .await
+            .map_err(error_status)?;
+
+        Ok(PutHeadResponse {
+            generation: result.generation,
+        })
+    }
+
+    async fn apply_heads(&self, request: ApplyHeadsRequest) -> Result<ApplyHeadsResponse, Status> {
+        let slot_id = slot(request.slot_id)?;
+        self.fence(slot_id, request.epoch).await?;
+
+        let result = self
+            .state
+            .internal_put_head_batch_operation
+            .run(InternalPutHeadBatchOperationRequest {
+                slot_id,
+                heads: batch_items(request.heads)?,
+            })
+            .await
+            .map_err(error_status)?;
+
+        if !result.applied {
+            return Err(Status::aborted("head batch rejected by generation check"));
+        }
+        Ok(ApplyHeadsResponse {
+            applied: result.head_count as u32,
+        })
+    }
+
+    async fn prepare_transaction(
+        &self,
+        request: PrepareTransactionRequest,
+    ) -> Result<PrepareTransactionResponse, Status> {
+        let slot_id = slot(request.slot_id)?;
+        self.fence(slot_id, request.epoch).await?;
+
+        let peers = TransactionPeers {
+            coordinator: request.coordinator,
+            participants: request.participants,
+        };
+        let vote = self
+            .state
+            .two_phase_commit
+            .prepare_participant(&request.txn_id, slot_id, peers, batch_items(request.heads)?)
+            .await
+            .map_err(error_status)?;
+
+        Ok(match vote {
+            Vote::Yes => PrepareTransactionResponse {
+                vote_yes: true,
+                reason: String::new(),
+                locked_by: String::new(),
+            },
+            Vote::No(reason) => PrepareTransactionResponse {
+                vote_yes: false,
+                reason,
+                locked_by: String::new(),
+            },
+            Vote::Locked { holder, reason } => PrepareTransactionResponse {
+                vote_yes: false,
+                reason,
+                locked_by: holder,
+            },
+        })
+    }
+
+    async fn decide_transaction(
+        &self,
+        request: DecideTransactionRequest,
+    ) -> Result<DecideTransactionResponse, Status> {
+        let slot_id = slot(request.slot_id)?;
+        let two_phase_commit = &self.state.two_phase_commit;
+        if !request.commit {
+            two_phase_commit
+                .abort_prepared(&request.txn_id, slot_id)
+                .await;
+            return Ok(DecideTransactionResponse {});
+        }
+
+        match two_phase_commit
+            .commit_prepared(&request.txn_id, slot_id)
+            .await
+        {
+            Ok(true) => Ok(DecideTransactionResponse {}),
+            Ok(false) => Err(Status::aborted(
+                "prepared batch rejected by generation check",
+            )),
+            Err(RimError::InvalidRequest(message)) => Err(Status::not_found(message)),
+            Err(error) => Err(Status::internal(error.to_string())),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use super::{
+    HeadChainReplicaItem, HeadChainResponse, ServerState, resolve_replica_nodes, response_error,
+};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use rimio_core::VerifyHeadChainOperationRequest;
+use std::sync::Arc;
+
+/// `GET /_/api/v1/slots/:slot_id/head-chain` verifies the head chains of a
+/// slot on every replica. A replica whose chains are broken, or whose digest
+/// differs from the others, had its metadata changed outside the write path
+/// or missed writes; the samples name the paths to look at.
+pub(crate) async fn v1_verify_head_chain(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+) -> Response {
+    if slot_id >= state.config.replication.total_slots {
+        return response_error(
+            StatusCode::NOT_FOUND,
+            format!("slot not found: {}", slot_id),
+        );
+    }
+    if !state.config.head_chain {
+        return response_error(StatusCode::NOT_FOUND, "head chaining is not enabled");
+    }
+
+    let replicas = match resolve_replica_nodes(&state, slot_id).await {
+        Ok(replicas) => replicas,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    let mut items = Vec::with_capacity(replicas.len());
+    for replica in &replicas {
+        let result = if replica.node_id == state.node.node_id() {
+            state
+                .verify_head_chain_operation
+                .run(VerifyHeadChainOperationRequest { slot_id })
+                .await
+        } else {
+            state
+                .cluster_client
+                .verify_head_chain(&replica.node_id, slot_id)
+                .await
+        };
+
+        let (report, error) = match result {
+            Ok(report) => (Some(report), None),
+            Err(error) => (None, Some(error.to_string())),
+        };
+        items.push(HeadChainReplicaItem {
+            node_id: replica.node_id.clone(),
+            report,
+            error,
+        });
+    }
+
+    let mut reports = items.iter().filter_map(|item| item.report.as_ref());
+    let consistent = match reports.next() {
+        Some(first) => {
+            first.is_clean()
+                && reports.all(|report| report.is_clean() && report.digest == first.digest)
+        }
+        None => false,
+    };
+
+    Json(HeadChainResponse {
+        slot_id,
+        consistent,
+        replicas: items,
+    })
+    .into_response()
+}
+
+pub(crate) async fn internal_verify_head_chain(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+) -> Response {
+    match state
+        .verify_head_chain_operation
+        .run(VerifyHeadChainOperationRequest { slot_id })
+        .await
+    {
+        Ok(report) => Json(report).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
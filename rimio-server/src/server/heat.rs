@@ -0,0 +1,29 @@
+use super::{ServerState, response_error};
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+/// `GET /_/api/v1/slots/heat` reports the blob traffic this node coordinated
+/// per slot, hottest first.
+pub(crate) async fn v1_slot_heat(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    Json(state.slot_heat.report())
+}
+
+/// `GET /_/api/v1/slots/rebalance` reports the heat rebalancer's last round.
+pub(crate) async fn v1_heat_rebalance_report(State(state): State<Arc<ServerState>>) -> Response {
+    match state.heat_rebalancer.as_ref() {
+        Some(rebalancer) => Json(rebalancer.report().await).into_response(),
+        None => response_error(StatusCode::NOT_FOUND, "heat rebalance is not configured"),
+    }
+}
+
+/// `GET /internal/v1/heat` hands the rebalancing node this node's slot heat.
+pub(crate) async fn internal_get_slot_heat(
+    State(state): State<Arc<ServerState>>,
+) -> impl IntoResponse {
+    Json(state.slot_heat.report())
+}
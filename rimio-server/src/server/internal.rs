@@ -1,29 +1,87 @@
 use super::{
     HealHeadItem, HealHeadsRequest, HealHeadsResponse, HealRepairRequest, HealRepairResponse,
-    HealSlotlet, HealSlotletsQuery, HealSlotletsResponse, InternalBootstrapResponse,
-    InternalEmbedSeedsResponse, InternalHeadApplyRequest, InternalHeadApplyResponse,
-    InternalHeadResponse, InternalPartPutResponse, InternalPartQuery, InternalPathQuery,
-    ServerState, normalize_blob_path, response_error,
+    HealSlotlet, HealSlotletsQuery, HealSlotletsResponse, HealTombstonesRequest,
+    HealTombstonesResponse, InternalBootstrapResponse, InternalEmbedSeedsResponse,
+    InternalHeadApplyRequest, InternalHeadApplyResponse, InternalHeadBatchApplyRequest,
+    InternalHeadBatchApplyResponse, InternalHeadResponse, InternalLinkPartsRequest,
+    InternalPartPutResponse, InternalPartQuery, InternalPathQuery, InternalPrepareRequest,
+    InternalPrepareResponse, InternalResolveResponse, InternalTransactionStateResponse, PruneQuery,
+    ServerState, normalize_blob_path, refuse_frozen_write, response_error,
 };
 use axum::{
     Json,
     body::Bytes,
-    extract::{Path, Query, State},
+    extract::{Path, Query, Request, State},
     http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
     response::{IntoResponse, Response},
 };
 use rimio_core::{
     HeadKind, HealHeadsOperationRequest, HealRepairOperationRequest, HealSlotletsOperationRequest,
-    InternalGetHeadOperationOutcome, InternalGetHeadOperationRequest,
-    InternalGetPartOperationOutcome, InternalGetPartOperationRequest,
-    InternalPutHeadOperationRequest, InternalPutPartOperationRequest, MetaAddLearnerRequest,
+    HealTombstonesOperationRequest, InternalGetHeadOperationOutcome,
+    InternalGetHeadOperationRequest, InternalGetPartOperationOutcome,
+    InternalGetPartOperationRequest, InternalGetSlotStatsOperationRequest,
+    InternalLinkPartsOperationRequest, InternalPutHeadBatchItem,
+    InternalPutHeadBatchOperationRequest, InternalPutHeadOperationRequest,
+    InternalPutPartOperationRequest, MIN_PROTOCOL_VERSION, MetaAddLearnerRequest,
     MetaAppendEntriesRequest, MetaInstallSnapshotRequest, MetaPromoteVoterRequest, MetaVoteRequest,
-    MetaWriteRequest, RimError, handle_global_add_learner, handle_global_append_entries,
-    handle_global_client_write, handle_global_install_snapshot, handle_global_promote_voter,
-    handle_global_vote,
+    MetaWriteRequest, PeerProtocol, PruneVersionsOperationRequest, RimError, SLOT_EPOCH_HEADER,
+    Vote, handle_global_add_learner, handle_global_append_entries, handle_global_client_write,
+    handle_global_install_snapshot, handle_global_promote_voter, handle_global_vote,
 };
 use std::sync::Arc;
 
+/// Fences internal writes stamped with an older slot epoch than ours, so a
+/// coordinator working from a superseded placement cannot write while the
+/// slot is being moved. Requests without an epoch are let through.
+async fn reject_stale_epoch(
+    state: &ServerState,
+    slot_id: u16,
+    headers: &HeaderMap,
+) -> Option<Response> {
+    let seen_epoch = headers
+        .get(SLOT_EPOCH_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())?;
+
+    fence_slot_epoch(state, slot_id, seen_epoch)
+        .await
+        .err()
+        .map(|(status, message)| response_error(status, message))
+}
+
+/// Checks a write stamped with `seen_epoch` against the current epoch of
+/// the slot; see [`reject_stale_epoch`].
+pub(crate) async fn fence_slot_epoch(
+    state: &ServerState,
+    slot_id: u16,
+    seen_epoch: u64,
+) -> Result<(), (StatusCode, String)> {
+    let current_epoch = match state.placement.fencing_epoch(slot_id, seen_epoch).await {
+        Ok(epoch) => epoch,
+        Err(error) => return Err((StatusCode::SERVICE_UNAVAILABLE, error.to_string())),
+    };
+
+    if seen_epoch >= current_epoch {
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "Rejected internal write with stale slot epoch: slot={} seen={} current={}",
+        slot_id,
+        seen_epoch,
+        current_epoch
+    );
+
+    Err((
+        StatusCode::CONFLICT,
+        format!(
+            "stale slot epoch: slot={} seen={} current={}",
+            slot_id, seen_epoch, current_epoch
+        ),
+    ))
+}
+
 pub(crate) async fn internal_put_part(
     State(state): State<Arc<ServerState>>,
     Path((slot_id, sha256)): Path<(u16, String)>,
@@ -31,6 +89,13 @@ pub(crate) async fn internal_put_part(
     headers: HeaderMap,
     body: Bytes,
 ) -> impl IntoResponse {
+    if let Some(response) = reject_stale_epoch(&state, slot_id, &headers).await {
+        return response;
+    }
+    if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+        return response;
+    }
+
     let path = match query.path {
         Some(path) => match normalize_blob_path(&path) {
             Ok(path) => path,
@@ -85,6 +150,44 @@ pub(crate) async fn internal_put_part(
     }
 }
 
+/// Stages a linked head's parts: the parts this node holds for the source
+/// version are indexed under the new one. `404` when it lacks any of them.
+pub(crate) async fn internal_link_parts(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+    headers: HeaderMap,
+    Json(request): Json<InternalLinkPartsRequest>,
+) -> impl IntoResponse {
+    if let Some(response) = reject_stale_epoch(&state, slot_id, &headers).await {
+        return response;
+    }
+    if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+        return response;
+    }
+    let path = match normalize_blob_path(&request.path) {
+        Ok(path) => path,
+        Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+
+    let linked = state
+        .internal_put_part_operation
+        .link(InternalLinkPartsOperationRequest {
+            slot_id,
+            source: request.source,
+            path,
+            generation: request.generation,
+        })
+        .await;
+    match linked {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => response_error(
+            StatusCode::NOT_FOUND,
+            "parts of the source version are not all held here",
+        ),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
 pub(crate) async fn internal_get_part(
     State(state): State<Arc<ServerState>>,
     Path((slot_id, sha256)): Path<(u16, String)>,
@@ -129,12 +232,68 @@ pub(crate) async fn internal_get_part(
     }
 }
 
+/// `HEAD` on a part answers whether this node already holds it, so a writer
+/// or repair can send only the parts a replica is missing.
+pub(crate) async fn internal_head_part(
+    State(state): State<Arc<ServerState>>,
+    Path((slot_id, sha256)): Path<(u16, String)>,
+    Query(query): Query<InternalPartQuery>,
+) -> impl IntoResponse {
+    let path = match query.path {
+        Some(path) => match normalize_blob_path(&path) {
+            Ok(path) => Some(path),
+            Err(_) => return empty_response(StatusCode::BAD_REQUEST),
+        },
+        None => None,
+    };
+
+    let result = state
+        .internal_get_part_operation
+        .probe(InternalGetPartOperationRequest {
+            slot_id,
+            sha256: Some(sha256),
+            path,
+            generation: query.generation,
+            part_no: query.part_no,
+        })
+        .await;
+
+    match result {
+        Ok(Some(part)) => {
+            let mut response = empty_response(StatusCode::OK);
+            if let Ok(value) = HeaderValue::from_str(&part.sha256) {
+                response.headers_mut().insert("x-rimio-sha256", value);
+            }
+            response
+                .headers_mut()
+                .insert("x-rimio-part-length", HeaderValue::from(part.size_bytes));
+            response
+        }
+        Ok(None) => empty_response(StatusCode::NOT_FOUND),
+        Err(_) => empty_response(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+fn empty_response(status: StatusCode) -> Response {
+    let mut response = Response::new(axum::body::Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
 pub(crate) async fn internal_put_head(
     State(state): State<Arc<ServerState>>,
     Path(slot_id): Path<u16>,
     Query(query): Query<InternalPathQuery>,
+    headers: HeaderMap,
     Json(request): Json<InternalHeadApplyRequest>,
 ) -> impl IntoResponse {
+    if let Some(response) = reject_stale_epoch(&state, slot_id, &headers).await {
+        return response;
+    }
+    if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+        return response;
+    }
+
     let query_path = match query.path {
         Some(path) => match normalize_blob_path(&path) {
             Ok(path) => Some(path),
@@ -171,6 +330,184 @@ pub(crate) async fn internal_put_head(
     }
 }
 
+pub(crate) async fn internal_put_head_batch(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+    headers: HeaderMap,
+    Json(request): Json<InternalHeadBatchApplyRequest>,
+) -> impl IntoResponse {
+    if let Some(response) = reject_stale_epoch(&state, slot_id, &headers).await {
+        return response;
+    }
+    if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+        return response;
+    }
+
+    let mut heads = Vec::with_capacity(request.heads.len());
+    for item in request.heads {
+        let path = match normalize_blob_path(&item.path) {
+            Ok(path) => path,
+            Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+        };
+
+        heads.push(InternalPutHeadBatchItem {
+            path,
+            head_kind: item.head_kind,
+            generation: item.generation,
+            head_sha256: item.head_sha256,
+            meta: item.meta,
+            tombstone: item.tombstone,
+        });
+    }
+
+    let result = state
+        .internal_put_head_batch_operation
+        .run(InternalPutHeadBatchOperationRequest { slot_id, heads })
+        .await;
+
+    match result {
+        Ok(result) if result.applied => (
+            StatusCode::OK,
+            Json(InternalHeadBatchApplyResponse {
+                applied: true,
+                heads: result.head_count,
+            }),
+        )
+            .into_response(),
+        Ok(_) => response_error(
+            StatusCode::CONFLICT,
+            "head batch rejected by generation check",
+        ),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::BAD_REQUEST, message),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+pub(crate) async fn internal_prepare_transaction(
+    State(state): State<Arc<ServerState>>,
+    Path((slot_id, txn_id)): Path<(u16, String)>,
+    headers: HeaderMap,
+    Json(request): Json<InternalPrepareRequest>,
+) -> impl IntoResponse {
+    if let Some(response) = reject_stale_epoch(&state, slot_id, &headers).await {
+        return response;
+    }
+    if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+        return response;
+    }
+
+    let mut heads = Vec::with_capacity(request.heads.len());
+    for item in request.heads {
+        let path = match normalize_blob_path(&item.path) {
+            Ok(path) => path,
+            Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+        };
+
+        heads.push(InternalPutHeadBatchItem {
+            path,
+            head_kind: item.head_kind,
+            generation: item.generation,
+            head_sha256: item.head_sha256,
+            meta: item.meta,
+            tombstone: item.tombstone,
+        });
+    }
+
+    match state
+        .two_phase_commit
+        .prepare_participant(&txn_id, slot_id, request.peers, heads)
+        .await
+    {
+        Ok(Vote::Yes) => Json(InternalPrepareResponse {
+            vote: "yes",
+            reason: None,
+            locked_by: None,
+        })
+        .into_response(),
+        Ok(Vote::No(reason)) => Json(InternalPrepareResponse {
+            vote: "no",
+            reason: Some(reason),
+            locked_by: None,
+        })
+        .into_response(),
+        Ok(Vote::Locked { holder, reason }) => Json(InternalPrepareResponse {
+            vote: "no",
+            reason: Some(reason),
+            locked_by: Some(holder),
+        })
+        .into_response(),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::BAD_REQUEST, message),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+pub(crate) async fn internal_commit_transaction(
+    State(state): State<Arc<ServerState>>,
+    Path((slot_id, txn_id)): Path<(u16, String)>,
+) -> impl IntoResponse {
+    match state
+        .two_phase_commit
+        .commit_prepared(&txn_id, slot_id)
+        .await
+    {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => response_error(
+            StatusCode::CONFLICT,
+            "prepared batch rejected by generation check",
+        ),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::NOT_FOUND, message),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// Tells a peer in doubt what this node knows about a transaction.
+pub(crate) async fn internal_get_transaction_state(
+    State(state): State<Arc<ServerState>>,
+    Path(txn_id): Path<String>,
+) -> impl IntoResponse {
+    match state.two_phase_commit.transaction_state(&txn_id).await {
+        Ok(txn_state) => Json(InternalTransactionStateResponse {
+            state: txn_state,
+            txn_id,
+        })
+        .into_response(),
+        // Not answering keeps the asking peer in doubt; a wrong `unknown`
+        // would have it abort a committed batch.
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// Settles this node's prepared batches of a transaction now, regardless of
+/// how long they waited.
+pub(crate) async fn internal_resolve_transaction(
+    State(state): State<Arc<ServerState>>,
+    Path(txn_id): Path<String>,
+) -> impl IntoResponse {
+    match state
+        .two_phase_commit
+        .resolve_in_doubt(state.node.node_id(), Some(&txn_id), 0)
+        .await
+    {
+        Ok(resolutions) => Json(InternalResolveResponse {
+            txn_id,
+            resolutions,
+        })
+        .into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+pub(crate) async fn internal_abort_transaction(
+    State(state): State<Arc<ServerState>>,
+    Path((slot_id, txn_id)): Path<(u16, String)>,
+) -> impl IntoResponse {
+    state
+        .two_phase_commit
+        .abort_prepared(&txn_id, slot_id)
+        .await;
+    StatusCode::NO_CONTENT
+}
+
 pub(crate) async fn internal_get_head(
     State(state): State<Arc<ServerState>>,
     Path(slot_id): Path<u16>,
@@ -222,6 +559,38 @@ pub(crate) async fn internal_get_head(
     }
 }
 
+pub(crate) async fn internal_get_slot_stats(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+) -> impl IntoResponse {
+    match state
+        .internal_get_slot_stats_operation
+        .run(InternalGetSlotStatsOperationRequest { slot_id })
+        .await
+    {
+        Ok(stats) => (StatusCode::OK, Json(stats)).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+pub(crate) async fn internal_prune_versions(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+    Query(query): Query<PruneQuery>,
+) -> impl IntoResponse {
+    match state
+        .prune_versions_operation
+        .run(PruneVersionsOperationRequest {
+            slot_id,
+            dry_run: query.dry_run,
+        })
+        .await
+    {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
 pub(crate) async fn v1_internal_heal_slotlets(
     State(state): State<Arc<ServerState>>,
     Path(slot_id): Path<u16>,
@@ -292,6 +661,35 @@ pub(crate) async fn v1_internal_heal_heads(
     }
 }
 
+/// Hands out the tombstones among `paths`, so a replica catching up on a
+/// mass delete applies them in one batch.
+pub(crate) async fn v1_internal_heal_tombstones(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+    Json(request): Json<HealTombstonesRequest>,
+) -> impl IntoResponse {
+    let result = state
+        .heal_tombstones_operation
+        .run(HealTombstonesOperationRequest {
+            slot_id,
+            paths: request.paths,
+        })
+        .await;
+
+    match result {
+        Ok(result) => (
+            StatusCode::OK,
+            Json(HealTombstonesResponse {
+                slot_id: result.slot_id,
+                tombstones: result.tombstones,
+            }),
+        )
+            .into_response(),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::BAD_REQUEST, message),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
 pub(crate) async fn v1_internal_heal_repair(
     State(state): State<Arc<ServerState>>,
     Path(slot_id): Path<u16>,
@@ -311,6 +709,61 @@ pub(crate) async fn v1_internal_heal_repair(
         return response_error(StatusCode::NOT_FOUND, "source node not found");
     }
 
+    let local_node_id = state.node.node_id();
+    let replicas: Vec<String> = if let Some(replacing) = request.replacing.as_deref() {
+        let slot = match state.placement.slot(slot_id).await {
+            Ok(Some(slot)) => slot,
+            Ok(None) => return response_error(StatusCode::NOT_FOUND, "slot has no placement"),
+            Err(error) => {
+                return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+            }
+        };
+        if !slot.replicas.iter().any(|node| node == replacing)
+            || slot.replicas.iter().any(|node| node == local_node_id)
+        {
+            return response_error(
+                StatusCode::CONFLICT,
+                format!(
+                    "cannot replace {} in slot: slot={} epoch={}",
+                    replacing, slot_id, slot.epoch
+                ),
+            );
+        }
+        slot.replicas
+            .into_iter()
+            .map(|node| {
+                if node == replacing {
+                    local_node_id.to_string()
+                } else {
+                    node
+                }
+            })
+            .collect()
+    } else {
+        match state.placement.slot(slot_id).await {
+            Ok(Some(slot)) if !slot.replicas.iter().any(|node| node == local_node_id) => {
+                return response_error(
+                    StatusCode::CONFLICT,
+                    format!(
+                        "slot not placed on this node: slot={} epoch={}",
+                        slot_id, slot.epoch
+                    ),
+                );
+            }
+            Ok(_) => {}
+            Err(error) => {
+                return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+            }
+        }
+
+        match super::resolve_replica_nodes(&state, slot_id).await {
+            Ok(replicas) => replicas.into_iter().map(|node| node.node_id).collect(),
+            Err(error) => {
+                return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+            }
+        }
+    };
+
     let result = state
         .heal_repair_operation
         .run(HealRepairOperationRequest {
@@ -318,6 +771,8 @@ pub(crate) async fn v1_internal_heal_repair(
             source_node_id,
             blob_paths: request.blob_paths,
             dry_run: request.dry_run,
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
         })
         .await;
 
@@ -449,3 +904,34 @@ pub(crate) async fn v1_internal_meta_write(
         Err(error) => response_error(StatusCode::SERVICE_UNAVAILABLE, error.to_string()),
     }
 }
+
+pub(crate) async fn internal_get_protocol() -> impl IntoResponse {
+    Json(PeerProtocol::local())
+}
+
+/// Stamps internal responses with the local protocol and turns away peers
+/// older than [`MIN_PROTOCOL_VERSION`]. Requests without a version come from
+/// nodes that predate the handshake and are served as before.
+pub(crate) async fn negotiate_protocol(request: Request, next: Next) -> Response {
+    if !request.uri().path().starts_with("/internal/") {
+        return next.run(request).await;
+    }
+
+    if let Some(peer) = PeerProtocol::from_headers(request.headers())
+        && !peer.is_compatible()
+    {
+        let mut response = response_error(
+            StatusCode::UPGRADE_REQUIRED,
+            format!(
+                "internal protocol v{} is older than the supported v{}",
+                peer.version, MIN_PROTOCOL_VERSION
+            ),
+        );
+        response.headers_mut().extend(PeerProtocol::local_headers());
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    response.headers_mut().extend(PeerProtocol::local_headers());
+    response
+}
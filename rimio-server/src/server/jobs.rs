@@ -0,0 +1,235 @@
+use super::{JobEvent, JobStatusResponse, JobsQuery, JobsResponse, ServerState, response_error};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::Utc;
+use rimio_core::{JobRecord, NodeStore, Result};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+pub(crate) const JOB_KIND_DELETE_PREFIX: &str = "delete-prefix";
+
+/// Every kind of job this node runs.
+const JOB_KINDS: &[&str] = &[JOB_KIND_DELETE_PREFIX];
+
+const JOB_STATE_RUNNING: &str = "running";
+const JOB_STATE_DONE: &str = "done";
+const JOB_STATE_FAILED: &str = "failed";
+
+/// Long-running operations started on this node.
+///
+/// Each job has a record in the node store, holding its request and, once it
+/// ends, its result or error. Progress is only kept in memory while the job
+/// runs, and handed to whoever watches it.
+pub(crate) struct JobTracker {
+    node_store: Arc<NodeStore>,
+    running: Mutex<HashMap<String, watch::Receiver<Option<JobEvent>>>>,
+}
+
+impl JobTracker {
+    /// Jobs the store still has as running were cut short by a restart, so
+    /// they are marked failed.
+    pub(crate) fn new(node_store: Arc<NodeStore>) -> Result<Self> {
+        for kind in JOB_KINDS {
+            for mut job in node_store.list_jobs(kind)? {
+                if job.state == JOB_STATE_RUNNING {
+                    job.state = JOB_STATE_FAILED.to_string();
+                    job.error = Some("interrupted by a restart".to_string());
+                    job.updated_at = Utc::now();
+                    node_store.upsert_job(&job)?;
+                }
+            }
+        }
+
+        Ok(Self {
+            node_store,
+            running: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Records a new running job of `kind` for `request`.
+    pub(crate) fn start(self: &Arc<Self>, kind: &str, request: Value) -> Result<JobHandle> {
+        let now = Utc::now();
+        let record = JobRecord {
+            job_id: ulid::Ulid::new().to_string(),
+            kind: kind.to_string(),
+            state: JOB_STATE_RUNNING.to_string(),
+            payload: json!({ "request": request }),
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        self.node_store.upsert_job(&record)?;
+
+        let (events, receiver) = watch::channel(None);
+        self.running
+            .lock()
+            .expect("job tracker lock poisoned")
+            .insert(record.job_id.clone(), receiver);
+        Ok(JobHandle {
+            tracker: self.clone(),
+            record,
+            events,
+        })
+    }
+
+    pub(crate) fn status(&self, job: JobRecord) -> JobStatusResponse {
+        let progress = self
+            .watch(&job.job_id)
+            .and_then(|events| match &*events.borrow() {
+                Some(JobEvent::Progress(progress)) => Some(progress.clone()),
+                _ => None,
+            });
+        JobStatusResponse { job, progress }
+    }
+
+    /// The events of a job still running here.
+    fn watch(&self, job_id: &str) -> Option<watch::Receiver<Option<JobEvent>>> {
+        self.running
+            .lock()
+            .expect("job tracker lock poisoned")
+            .get(job_id)
+            .cloned()
+    }
+}
+
+/// The side of a job doing the work: it reports progress and then how the
+/// job ended.
+pub(crate) struct JobHandle {
+    tracker: Arc<JobTracker>,
+    record: JobRecord,
+    events: watch::Sender<Option<JobEvent>>,
+}
+
+impl JobHandle {
+    pub(crate) fn record(&self) -> &JobRecord {
+        &self.record
+    }
+
+    /// Replaces the progress of the job; watchers that fall behind only see
+    /// the latest.
+    pub(crate) fn progress(&self, progress: &impl Serialize) {
+        if let Ok(progress) = serde_json::to_value(progress) {
+            self.events.send_replace(Some(JobEvent::Progress(progress)));
+        }
+    }
+
+    pub(crate) fn finish(mut self, outcome: std::result::Result<Value, String>) {
+        let event = match outcome {
+            Ok(result) => {
+                self.record.state = JOB_STATE_DONE.to_string();
+                self.record.payload["result"] = result.clone();
+                JobEvent::Done(result)
+            }
+            Err(error) => {
+                self.record.state = JOB_STATE_FAILED.to_string();
+                self.record.error = Some(error.clone());
+                JobEvent::Failed(error)
+            }
+        };
+        self.record.updated_at = Utc::now();
+        if let Err(error) = self.tracker.node_store.upsert_job(&self.record) {
+            tracing::warn!("Failed to record job {}: {}", self.record.job_id, error);
+        }
+        self.tracker
+            .running
+            .lock()
+            .expect("job tracker lock poisoned")
+            .remove(&self.record.job_id);
+        self.events.send_replace(Some(event));
+    }
+}
+
+/// `GET /_/api/v1/jobs` lists the jobs of this node, oldest first, of one
+/// `kind` or of all.
+pub(crate) async fn v1_list_jobs(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<JobsQuery>,
+) -> Response {
+    let kinds = match query.kind.as_deref() {
+        Some(kind) => vec![kind],
+        None => JOB_KINDS.to_vec(),
+    };
+    let mut jobs = Vec::new();
+    for kind in kinds {
+        match state.node_store.list_jobs(kind) {
+            Ok(records) => jobs.extend(records.into_iter().map(|job| state.jobs.status(job))),
+            Err(error) => {
+                return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+            }
+        }
+    }
+    jobs.sort_by_key(|status| status.job.created_at);
+    Json(JobsResponse { jobs }).into_response()
+}
+
+/// `GET /_/api/v1/jobs/{job_id}` returns the job, with its latest progress
+/// while it runs.
+pub(crate) async fn v1_get_job(
+    State(state): State<Arc<ServerState>>,
+    Path(job_id): Path<String>,
+) -> Response {
+    match state.node_store.get_job(&job_id) {
+        Ok(Some(job)) => Json(state.jobs.status(job)).into_response(),
+        Ok(None) => response_error(StatusCode::NOT_FOUND, "job not found"),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `GET /_/api/v1/jobs/{job_id}/events` follows a job as Server-Sent
+/// Events: `progress` whenever it moves, then one `done` with the result or
+/// `failed` with the error, after which the stream ends. A job that already
+/// ended only sends the last event.
+pub(crate) async fn v1_job_events(
+    State(state): State<Arc<ServerState>>,
+    Path(job_id): Path<String>,
+) -> Response {
+    let Some(events) = state.jobs.watch(&job_id) else {
+        let job = match state.node_store.get_job(&job_id) {
+            Ok(Some(job)) => job,
+            Ok(None) => return response_error(StatusCode::NOT_FOUND, "job not found"),
+            Err(error) => {
+                return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+            }
+        };
+        let event = match job.error {
+            Some(error) => JobEvent::Failed(error),
+            None => JobEvent::Done(job.payload.get("result").cloned().unwrap_or_default()),
+        };
+        let events = futures_util::stream::iter([Ok::<_, Infallible>(sse_event(&event))]);
+        return Sse::new(events).into_response();
+    };
+
+    let events = futures_util::stream::unfold(Some(events), |events| async move {
+        let mut events = events?;
+        events.changed().await.ok()?;
+        let event = events.borrow_and_update().clone()?;
+        let events = matches!(event, JobEvent::Progress(_)).then_some(events);
+        Some((Ok::<_, Infallible>(sse_event(&event)), events))
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn sse_event(event: &JobEvent) -> Event {
+    match event {
+        JobEvent::Progress(progress) => Event::default()
+            .event("progress")
+            .data(progress.to_string()),
+        JobEvent::Done(result) => Event::default().event("done").data(result.to_string()),
+        JobEvent::Failed(error) => Event::default()
+            .event("failed")
+            .data(json!({ "error": error }).to_string()),
+    }
+}
@@ -0,0 +1,158 @@
+use super::{
+    LifecycleRulesBody, LifecycleRunQuery, LifecycleRunResponse, ServerState, current_nodes,
+    replica_nodes_for, response_error,
+};
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use rimio_core::{
+    ApplyLifecycleOperationRequest, ApplyLifecycleOperationResult, LifecycleRule, NodeInfo, Result,
+    RimError, validate_lifecycle_rules,
+};
+use std::sync::Arc;
+
+/// `GET /_/api/v1/lifecycle/rules` lists the lifecycle rules of the group.
+pub(crate) async fn v1_get_lifecycle_rules(State(state): State<Arc<ServerState>>) -> Response {
+    match state.registry.get_lifecycle_rules().await {
+        Ok(rules) => Json(LifecycleRulesBody { rules }).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `PUT /_/api/v1/lifecycle/rules` replaces the lifecycle rules of the
+/// group; every node picks them up on its next pass.
+pub(crate) async fn v1_put_lifecycle_rules(
+    State(state): State<Arc<ServerState>>,
+    body: Bytes,
+) -> Response {
+    let request: LifecycleRulesBody = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(error) => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                format!("invalid lifecycle rules: {}", error),
+            );
+        }
+    };
+    if let Err(error) = validate_lifecycle_rules(&request.rules) {
+        return response_error(StatusCode::BAD_REQUEST, error.to_string());
+    }
+
+    match state.registry.set_lifecycle_rules(&request.rules).await {
+        Ok(()) => Json(request).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `POST /_/api/v1/lifecycle/run` applies the rules to the slots this node
+/// holds now instead of waiting for the next pass; `?dry_run=true` only
+/// counts what would change.
+pub(crate) async fn v1_run_lifecycle(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<LifecycleRunQuery>,
+) -> Response {
+    let rules = match state.registry.get_lifecycle_rules().await {
+        Ok(rules) => rules,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    match apply_lifecycle(&state, &rules, query.dry_run).await {
+        Ok(slots) => Json(LifecycleRunResponse {
+            node_id: state.node.node_id().to_string(),
+            dry_run: query.dry_run,
+            rules: rules.len(),
+            slots,
+        })
+        .into_response(),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::BAD_REQUEST, message),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// A slot this node is a replica of.
+pub(crate) struct HeldSlot {
+    pub(crate) slot_id: u16,
+    pub(crate) replicas: Vec<NodeInfo>,
+    pub(crate) primary: bool,
+}
+
+/// The slots that name this node as a replica, with whether it is their
+/// primary.
+pub(crate) async fn held_slots(state: &ServerState) -> Result<Vec<HeldSlot>> {
+    let local_node_id = state.node.node_id();
+    let nodes = current_nodes(state).await?;
+    let mut held = Vec::new();
+    for slot_id in 0..state.config.replication.total_slots {
+        let slot = state.placement.slot(slot_id).await?;
+        let replicas = replica_nodes_for(state, &nodes, slot_id, slot.as_ref())?;
+        if !replicas.iter().any(|node| node.node_id == local_node_id) {
+            continue;
+        }
+        let primary = match slot
+            .as_ref()
+            .map(|slot| slot.primary.as_str())
+            .filter(|primary| !primary.is_empty())
+        {
+            Some(primary) => primary == local_node_id,
+            None => replicas
+                .first()
+                .is_some_and(|node| node.node_id == local_node_id),
+        };
+        held.push(HeldSlot {
+            slot_id,
+            replicas,
+            primary,
+        });
+    }
+    Ok(held)
+}
+
+/// Applies `rules` to every slot that names this node as a replica and
+/// returns the slots where something was due.
+pub(crate) async fn apply_lifecycle(
+    state: &ServerState,
+    rules: &[LifecycleRule],
+    dry_run: bool,
+) -> Result<Vec<ApplyLifecycleOperationResult>> {
+    let mut results = Vec::new();
+    if rules.is_empty() {
+        return Ok(results);
+    }
+
+    let local_node_id = state.node.node_id();
+    for slot in held_slots(state).await? {
+        let slot_id = slot.slot_id;
+        let result = state
+            .apply_lifecycle_operation
+            .run(ApplyLifecycleOperationRequest {
+                slot_id,
+                rules: rules.to_vec(),
+                replicas: slot.replicas,
+                primary: slot.primary,
+                local_node_id: local_node_id.to_string(),
+                dry_run,
+            })
+            .await;
+        match result {
+            Ok(result)
+                if result.expired
+                    + result.transitioned
+                    + result.awaiting_archive
+                    + result.failed
+                    > 0 =>
+            {
+                results.push(result);
+            }
+            Ok(_) => {}
+            Err(error) => {
+                tracing::warn!("Failed to apply lifecycle to slot {}: {}", slot_id, error);
+            }
+        }
+    }
+
+    Ok(results)
+}
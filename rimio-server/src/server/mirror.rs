@@ -0,0 +1,209 @@
+use super::signing::{KEY_ID_HEADER, RequestSigner, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use super::{HOPS_HEADER, MirrorStats};
+use crate::config::MirrorConfig;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use rand::Rng;
+use reqwest::Url;
+use rimio_core::{Result, RimError};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Semaphore;
+use tokio::time::Duration;
+
+const MIRRORED_PATH_PREFIX: &str = "/_/api/v1/blobs";
+
+/// Copies a sample of client blob traffic to a secondary endpoint, e.g. a
+/// cluster running an upgrade candidate.
+///
+/// Shadow requests are sent after the primary response is ready and their
+/// responses are discarded; only whether their status matched the primary is
+/// counted. Requests other nodes forwarded here are not mirrored again, and
+/// once `max_in_flight` shadow requests are pending further samples are
+/// dropped rather than queued. Shadow requests carry this node's signature
+/// instead of any the client sent when the node has a signing key.
+pub(crate) struct RequestMirror {
+    client: reqwest::Client,
+    endpoint: Url,
+    config: MirrorConfig,
+    signer: Option<Arc<RequestSigner>>,
+    in_flight: Arc<Semaphore>,
+    mirrored: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+    status_mismatches: AtomicU64,
+}
+
+impl RequestMirror {
+    pub(crate) fn new(config: MirrorConfig, signer: Option<Arc<RequestSigner>>) -> Result<Self> {
+        let endpoint = Url::parse(&config.endpoint).map_err(|error| {
+            RimError::Config(format!(
+                "invalid mirror endpoint '{}': {}",
+                config.endpoint, error
+            ))
+        })?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        Ok(Self {
+            client,
+            endpoint,
+            in_flight: Arc::new(Semaphore::new(config.max_in_flight)),
+            config,
+            signer,
+            mirrored: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            status_mismatches: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) fn stats(&self) -> MirrorStats {
+        MirrorStats {
+            endpoint: self.config.endpoint.clone(),
+            read_percent: self.config.read_percent,
+            write_percent: self.config.write_percent,
+            mirrored: self.mirrored.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            status_mismatches: self.status_mismatches.load(Ordering::Relaxed),
+        }
+    }
+
+    fn sample_percent(&self, method: &Method) -> Option<f64> {
+        match *method {
+            Method::GET | Method::HEAD => Some(self.config.read_percent),
+            Method::PUT | Method::DELETE => Some(self.config.write_percent),
+            _ => None,
+        }
+    }
+
+    fn send(self: &Arc<Self>, mut shadow: ShadowRequest, primary_status: StatusCode) {
+        let Ok(permit) = self.in_flight.clone().try_acquire_owned() else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+
+        for name in [KEY_ID_HEADER, TIMESTAMP_HEADER, SIGNATURE_HEADER] {
+            shadow.headers.remove(name);
+        }
+        if let Some(signer) = &self.signer {
+            let body = shadow.body.as_deref().unwrap_or_default();
+            for (name, value) in signer.sign(shadow.method.as_str(), &shadow.path_and_query, body) {
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    shadow.headers.insert(name, value);
+                }
+            }
+        }
+
+        let mut url = self.endpoint.clone();
+        url.set_path(shadow.path_and_query.split('?').next().unwrap_or_default());
+        url.set_query(
+            shadow
+                .path_and_query
+                .split_once('?')
+                .map(|(_, query)| query),
+        );
+
+        let mirror = self.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let mut request = mirror
+                .client
+                .request(shadow.method, url)
+                .headers(shadow.headers);
+            if let Some(body) = shadow.body {
+                request = request.body(body);
+            }
+
+            mirror.mirrored.fetch_add(1, Ordering::Relaxed);
+            match request.send().await {
+                Ok(response) if response.status().as_u16() != primary_status.as_u16() => {
+                    mirror.status_mismatches.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!(
+                        "mirror status mismatch: url={} primary={} shadow={}",
+                        response.url(),
+                        primary_status,
+                        response.status()
+                    );
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    mirror.failed.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!("mirror request failed: {}", error);
+                }
+            }
+        });
+    }
+}
+
+struct ShadowRequest {
+    method: Method,
+    path_and_query: String,
+    headers: HeaderMap,
+    body: Option<bytes::Bytes>,
+}
+
+/// Middleware sampling blob requests into the mirror.
+pub(crate) async fn mirror_traffic(
+    State(mirror): State<Arc<RequestMirror>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let sampled = request.uri().path().starts_with(MIRRORED_PATH_PREFIX)
+        && !request.headers().contains_key(HOPS_HEADER)
+        && mirror
+            .sample_percent(request.method())
+            .is_some_and(|percent| rand::thread_rng().gen_range(0.0..100.0) < percent);
+    if !sampled {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let mut shadow = ShadowRequest {
+        method: parts.method.clone(),
+        path_and_query: parts
+            .uri
+            .path_and_query()
+            .map(|value| value.as_str().to_string())
+            .unwrap_or_default(),
+        headers: parts.headers.clone(),
+        body: None,
+    };
+    shadow.headers.remove(header::HOST);
+
+    let body = if parts.method == Method::PUT {
+        let declared = parts
+            .headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if declared.is_none_or(|length| length > mirror.config.max_write_body_bytes) {
+            mirror.dropped.fetch_add(1, Ordering::Relaxed);
+            return next.run(Request::from_parts(parts, body)).await;
+        }
+
+        match axum::body::to_bytes(body, mirror.config.max_write_body_bytes as usize).await {
+            Ok(bytes) => {
+                shadow.body = Some(bytes.clone());
+                Body::from(bytes)
+            }
+            Err(error) => {
+                return super::response_error(StatusCode::BAD_REQUEST, error.to_string());
+            }
+        }
+    } else {
+        body
+    };
+
+    let response = next.run(Request::from_parts(parts, body)).await;
+    mirror.send(shadow, response.status());
+    response
+}
@@ -2,60 +2,189 @@ use crate::config::{ArchiveConfig, RuntimeConfig};
 use axum::{
     Json, Router,
     http::StatusCode,
+    http::{HeaderMap, HeaderValue, Uri, header},
+    middleware,
     response::{IntoResponse, Response},
-    routing::{get, post, put},
+    routing::{any, get, post, put},
 };
+use reqwest::Url;
 use rimio_core::{
-    ArchiveLifecycleConfig, ArchiveLifecycleManager, ArchiveStore, ClusterClient, Coordinator,
-    DeleteBlobOperation, HealHeadsOperation, HealRepairOperation, HealSlotletsOperation,
-    InternalGetHeadOperation, InternalGetPartOperation, InternalPutHeadOperation,
-    InternalPutPartOperation, ListBlobsOperation, Node, NodeInfo, PartStore, PutBlobArchiveWriter,
-    PutBlobOperation, ReadBlobOperation, RedisArchiveStore, Registry, Result, RimError,
-    S3ArchiveStore, clear_global_embed_runtime, set_default_s3_archive_store,
+    ApplyLifecycleOperation, ArchiveLifecycleConfig, ArchiveLifecycleManager, ArchiveStore,
+    ArchiveUploader, AzureArchiveStore, ClusterClient, CommitBatchOperation, Coordinator,
+    DecommissionNodeOperation, DeleteBlobOperation, DeletePrefixOperation, DiskHealthConfig,
+    DiskHealthMonitor, GarbageReportOperation, GcsArchiveStore, HeadSchemaMigration,
+    HeadSchemaMigrationConfig, HealHeadsOperation, HealRepairOperation, HealSlotletsOperation,
+    HealTombstonesOperation, HostPressureMonitor, IN_DOUBT_AFTER_SECS, InternalGetHeadOperation,
+    InternalGetPartOperation, InternalGetSlotStatsOperation, InternalPutHeadBatchOperation,
+    InternalPutHeadOperation, InternalPutPartOperation, ListBlobsOperation,
+    ListSlotChangesOperation, LocalFsArchiveStore, MonitoredDisk, MultipartUploads, Node, NodeInfo,
+    NodeStore, PartStore, PlacementMap, PruneVersionsOperation, PruneVersionsOperationRequest,
+    PutBlobArchiveWriter, PutBlobOperation, PutBlobTagsOperation, ReadBlobOperation,
+    RedisArchiveStore, Registry, RenameBlobOperation, ReplicationPolicy, Result, RimError,
+    S3ArchiveStore, ScrubConfig, ScrubScheduler, SlotHeatRebalancer, SlotHeatTracker, SlotInfo,
+    SlotLeaseManager, SlotMover, SlotRebalancer, SlotReconciler, SlotReconcilerConfig,
+    SlotTransferOperation, SqliteMaintenance, SqliteMaintenanceConfig, TransactionManager,
+    TwoPhaseCommit, VerifyHeadChainOperation, clear_global_embed_runtime, normalize_blob_path,
+    prepare_data_dir, serve_internal_grpc, set_default_azure_archive_store,
+    set_default_gcs_archive_store, set_default_local_fs_archive_store,
+    set_default_s3_archive_store,
 };
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tokio::sync::RwLock;
+use tokio::sync::{OwnedMutexGuard, RwLock};
 use tokio::time::{Duration, interval};
 
+mod access_log;
+mod audit_export;
+mod auth;
+mod changes;
+mod conditional;
+mod decommission;
+mod delete_prefix;
+mod endpoints;
 mod external;
+mod grpc;
+mod head_chain;
+mod heat;
 mod internal;
+mod jobs;
+mod lifecycle;
+mod mirror;
+mod notifications;
+mod oci;
+mod parquet;
+mod peers;
+mod pressure;
+mod readiness;
+mod registry_view;
+mod rename;
+mod repairs;
 mod s3_gateway;
+mod signing;
+mod slot_freeze;
+mod slot_transfer;
+mod snapshot;
+mod tagging;
+mod tiering;
 mod types;
+mod upload_tokens;
+mod uploads;
+mod watch;
 
+use access_log::{AccessLog, log_access};
+use audit_export::v1_audit_export;
+use auth::{Authenticator, require_auth, v1_get_api_keys, v1_put_api_keys};
+use changes::v1_slot_changes;
+use decommission::v1_decommission;
+use delete_prefix::{internal_delete_prefix, v1_delete_prefix};
+use endpoints::v1_slot_endpoints;
 use external::{
-    health, v1_delete_blob, v1_get_blob, v1_head_blob, v1_healthz, v1_list_blobs, v1_nodes,
-    v1_put_blob, v1_resolve_slot,
+    health, v1_abort_transaction, v1_begin_transaction, v1_blob_inventory, v1_commit_batch,
+    v1_commit_transaction, v1_delete_blob, v1_disk_health, v1_garbage_report, v1_get_blob,
+    v1_get_slot, v1_get_transaction, v1_head_blob, v1_head_schema_status, v1_healthz,
+    v1_list_blobs, v1_mirror_stats, v1_nodes, v1_protocol, v1_prune_slot, v1_put_blob, v1_readyz,
+    v1_reconcile_report, v1_resolve_slot, v1_scrub_report, v1_slot_rebalance_report,
+    v1_stage_transaction_delete, v1_stage_transaction_put,
 };
+use head_chain::{internal_verify_head_chain, v1_verify_head_chain};
+use heat::{internal_get_slot_heat, v1_heat_rebalance_report, v1_slot_heat};
 use internal::{
-    internal_get_head, internal_get_part, internal_put_head, internal_put_part,
-    v1_internal_cluster_bootstrap, v1_internal_cluster_embed_seeds, v1_internal_heal_heads,
-    v1_internal_heal_repair, v1_internal_heal_slotlets, v1_internal_meta_add_learner,
-    v1_internal_meta_promote_voter, v1_internal_meta_raft_append, v1_internal_meta_raft_snapshot,
-    v1_internal_meta_raft_vote, v1_internal_meta_write,
+    internal_abort_transaction, internal_commit_transaction, internal_get_head, internal_get_part,
+    internal_get_protocol, internal_get_slot_stats, internal_get_transaction_state,
+    internal_head_part, internal_link_parts, internal_prepare_transaction, internal_prune_versions,
+    internal_put_head, internal_put_head_batch, internal_put_part, internal_resolve_transaction,
+    negotiate_protocol, v1_internal_cluster_bootstrap, v1_internal_cluster_embed_seeds,
+    v1_internal_heal_heads, v1_internal_heal_repair, v1_internal_heal_slotlets,
+    v1_internal_heal_tombstones, v1_internal_meta_add_learner, v1_internal_meta_promote_voter,
+    v1_internal_meta_raft_append, v1_internal_meta_raft_snapshot, v1_internal_meta_raft_vote,
+    v1_internal_meta_write,
 };
+use jobs::{JobTracker, v1_get_job, v1_job_events, v1_list_jobs};
+use lifecycle::{v1_get_lifecycle_rules, v1_put_lifecycle_rules, v1_run_lifecycle};
+use mirror::{RequestMirror, mirror_traffic};
+use notifications::Notifier;
+use oci::{oci_request, oci_version_check};
+use peers::v1_peers;
+use pressure::{shed_under_pressure, v1_host_pressure};
+use readiness::ReadinessMonitor;
+use registry_view::{v1_registry_slot, v1_registry_slots, v1_registry_state};
+use rename::v1_rename_blob;
+use repairs::{v1_list_dead_letters, v1_list_repairs, v1_release_dead_letter, v1_repair_stats};
+use signing::{RequestSigner, verify_signed_requests};
+use slot_freeze::{v1_freeze_slot, v1_internal_reload_placement, v1_thaw_slot};
+use slot_transfer::{
+    internal_export_slot, internal_get_slot_export, internal_get_slot_export_file,
+    internal_pull_slot,
+};
+pub use snapshot::run_snapshot_server;
+use tiering::v1_run_tiering;
 pub(crate) use types::*;
+use upload_tokens::v1_create_upload_token;
+use uploads::v1_post_blob;
+use watch::{WatchHub, v1_watch};
 
 pub struct ServerState {
     pub(crate) node: Arc<Node>,
     pub(crate) registry: Arc<dyn Registry>,
     pub(crate) config: RuntimeConfig,
     pub(crate) coordinator: Arc<Coordinator>,
+    pub(crate) cluster_client: Arc<ClusterClient>,
     pub(crate) put_blob_operation: Arc<PutBlobOperation>,
     pub(crate) read_blob_operation: Arc<ReadBlobOperation>,
     pub(crate) delete_blob_operation: Arc<DeleteBlobOperation>,
+    pub(crate) delete_prefix_operation: Arc<DeletePrefixOperation>,
     pub(crate) list_blobs_operation: Arc<ListBlobsOperation>,
+    pub(crate) commit_batch_operation: Arc<CommitBatchOperation>,
+    pub(crate) transactions: Arc<TransactionManager>,
+    pub(crate) two_phase_commit: Arc<TwoPhaseCommit>,
+    pub(crate) rename_blob_operation: Arc<RenameBlobOperation>,
+    pub(crate) put_blob_tags_operation: Arc<PutBlobTagsOperation>,
     pub(crate) internal_put_part_operation: Arc<InternalPutPartOperation>,
     pub(crate) internal_get_part_operation: Arc<InternalGetPartOperation>,
     pub(crate) internal_put_head_operation: Arc<InternalPutHeadOperation>,
+    pub(crate) internal_put_head_batch_operation: Arc<InternalPutHeadBatchOperation>,
     pub(crate) internal_get_head_operation: Arc<InternalGetHeadOperation>,
+    pub(crate) internal_get_slot_stats_operation: Arc<InternalGetSlotStatsOperation>,
+    pub(crate) prune_versions_operation: Arc<PruneVersionsOperation>,
+    pub(crate) apply_lifecycle_operation: Arc<ApplyLifecycleOperation>,
+    pub(crate) archive_uploader: Option<Arc<ArchiveUploader>>,
+    pub(crate) verify_head_chain_operation: Arc<VerifyHeadChainOperation>,
+    pub(crate) list_slot_changes_operation: Arc<ListSlotChangesOperation>,
+    pub(crate) garbage_report_operation: Arc<GarbageReportOperation>,
     pub(crate) heal_slotlets_operation: Arc<HealSlotletsOperation>,
     pub(crate) heal_heads_operation: Arc<HealHeadsOperation>,
     pub(crate) heal_repair_operation: Arc<HealRepairOperation>,
+    pub(crate) heal_tombstones_operation: Arc<HealTombstonesOperation>,
     pub(crate) idempotent_puts: Arc<RwLock<HashMap<String, PutCacheEntry>>>,
+    pub(crate) node_store: Arc<NodeStore>,
+    pub(crate) jobs: Arc<JobTracker>,
+    pub(crate) slot_leases: Option<Arc<SlotLeaseManager>>,
+    pub(crate) placement: Arc<PlacementMap>,
+    pub(crate) slot_reconciler: Arc<SlotReconciler>,
+    pub(crate) readiness: Arc<ReadinessMonitor>,
+    pub(crate) scrub: Arc<ScrubScheduler>,
+    pub(crate) disk_health: Arc<DiskHealthMonitor>,
+    pub(crate) mirror: Option<Arc<RequestMirror>>,
+    pub(crate) head_schema: Arc<HeadSchemaMigration>,
+    pub(crate) multipart_uploads: Arc<MultipartUploads>,
+    pub(crate) host_pressure: Option<Arc<HostPressureMonitor>>,
+    pub(crate) auth: Option<Arc<Authenticator>>,
+    pub(crate) slot_heat: Arc<SlotHeatTracker>,
+    pub(crate) heat_rebalancer: Option<Arc<SlotHeatRebalancer>>,
+    pub(crate) slot_rebalancer: Option<Arc<SlotRebalancer>>,
+    pub(crate) decommission: Arc<DecommissionNodeOperation>,
+    pub(crate) slot_transfer: Arc<SlotTransferOperation>,
+    pub(crate) watch_hub: Arc<WatchHub>,
 }
 
+/// How often each node prunes its local slots when retention is configured.
+const VERSION_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How often each node applies the lifecycle rules to its slots.
+const LIFECYCLE_INTERVAL: Duration = Duration::from_secs(3600);
+
 pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> Result<()> {
     let node_cfg = config.node.clone();
 
@@ -65,12 +194,23 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
         .map(|disk| disk.path.clone())
         .collect();
 
+    for disk in &disk_paths {
+        prepare_data_dir(disk)?;
+    }
+
     let node = Arc::new(Node::new(
         node_cfg.node_id.clone(),
         config.registry.namespace_or_default().to_string(),
         node_cfg.advertise_addr.clone(),
         disk_paths,
     )?);
+    node.set_grpc_address(
+        config
+            .internal_grpc
+            .as_ref()
+            .map(|grpc| grpc.advertised_addr().to_string()),
+    )
+    .await;
 
     let data_dir = node_cfg
         .disks
@@ -78,15 +218,25 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
         .map(|disk| disk.path.clone())
         .unwrap_or_else(|| std::path::PathBuf::from("/tmp/rimio"));
 
-    let slot_manager = Arc::new(rimio_core::SlotManager::new(
-        node_cfg.node_id.clone(),
-        data_dir.clone(),
-    )?);
+    let slot_manager = Arc::new(
+        rimio_core::SlotManager::new(node_cfg.node_id.clone(), data_dir.clone())?
+            .with_head_chain(config.head_chain),
+    );
 
-    let part_store = Arc::new(PartStore::new(data_dir.clone())?);
+    let part_store = Arc::new(PartStore::new(data_dir.clone())?.with_mmap(config.part_mmap));
+    let node_store = Arc::new(NodeStore::open(&data_dir)?);
 
     let coordinator = Arc::new(Coordinator::new(config.replication.min_write_replicas));
-    let cluster_client = Arc::new(ClusterClient::new(registry.clone()));
+    let cluster_client = Arc::new(
+        ClusterClient::new(registry.clone())
+            .with_grpc(config.internal_grpc.as_ref())
+            .with_cluster_key(
+                config
+                    .auth
+                    .as_ref()
+                    .and_then(|auth| auth.cluster_key.as_deref()),
+            ),
+    );
 
     let (runtime_archive_store, archive_key_prefix) =
         build_runtime_archive(config.archive.as_ref())?;
@@ -96,24 +246,104 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
             .map(|prefix| PutBlobArchiveWriter::new(store.clone(), prefix.clone()))
     });
 
-    let put_blob_operation = Arc::new(PutBlobOperation::new(
+    let archive_tiering = config
+        .archive
+        .as_ref()
+        .and_then(|archive| archive.tiering.clone());
+    let archive_uploader =
+        archive_writer
+            .clone()
+            .zip(archive_tiering.clone())
+            .map(|(writer, tiering)| {
+                Arc::new(ArchiveUploader::new(
+                    slot_manager.clone(),
+                    part_store.clone(),
+                    writer,
+                    tiering,
+                ))
+            });
+
+    let replication_policy = ReplicationPolicy::new(config.replication.prefix_policies.clone());
+    let put_blob_operation = Arc::new(
+        PutBlobOperation::new(
+            slot_manager.clone(),
+            part_store.clone(),
+            coordinator.clone(),
+            cluster_client.clone(),
+            archive_writer,
+        )
+        .with_replication_policy(replication_policy.clone()),
+    );
+    let read_blob_operation = Arc::new(
+        ReadBlobOperation::new(
+            slot_manager.clone(),
+            part_store.clone(),
+            cluster_client.clone(),
+            config.replication.wide_probe,
+        )
+        .with_replication_policy(replication_policy.clone())
+        .with_repair_log(node_store.clone()),
+    );
+    let slot_leases = config.replication.write_lease_ttl_secs.map(|ttl_secs| {
+        Arc::new(SlotLeaseManager::new(
+            registry.clone(),
+            node_cfg.node_id.clone(),
+            ttl_secs,
+        ))
+    });
+
+    let delete_blob_operation = Arc::new(
+        DeleteBlobOperation::new(
+            slot_manager.clone(),
+            coordinator.clone(),
+            cluster_client.clone(),
+        )
+        .with_replication_policy(replication_policy.clone()),
+    );
+    let delete_prefix_operation = Arc::new(
+        DeletePrefixOperation::new(
+            slot_manager.clone(),
+            delete_blob_operation.clone(),
+            cluster_client.clone(),
+        )
+        .with_slot_leases(slot_leases.clone()),
+    );
+    let apply_lifecycle_operation = Arc::new(
+        ApplyLifecycleOperation::new(
+            slot_manager.clone(),
+            part_store.clone(),
+            delete_blob_operation.clone(),
+        )
+        .with_slot_leases(slot_leases.clone()),
+    );
+    let list_blobs_operation = Arc::new(ListBlobsOperation::new(slot_manager.clone()));
+    let commit_batch_operation = Arc::new(CommitBatchOperation::new(
         slot_manager.clone(),
         part_store.clone(),
         coordinator.clone(),
         cluster_client.clone(),
-        archive_writer,
     ));
-    let read_blob_operation = Arc::new(ReadBlobOperation::new(
+
+    let two_phase_commit = Arc::new(
+        TwoPhaseCommit::new(
+            slot_manager.clone(),
+            part_store.clone(),
+            coordinator.clone(),
+            cluster_client.clone(),
+        )
+        .with_decision_log(node_store.clone())?,
+    );
+    let rename_blob_operation = Arc::new(RenameBlobOperation::new(
         slot_manager.clone(),
-        part_store.clone(),
+        read_blob_operation.clone(),
+        put_blob_operation.clone(),
+        two_phase_commit.clone(),
         cluster_client.clone(),
     ));
-    let delete_blob_operation = Arc::new(DeleteBlobOperation::new(
-        slot_manager.clone(),
-        coordinator.clone(),
-        cluster_client.clone(),
+    let put_blob_tags_operation = Arc::new(PutBlobTagsOperation::new(
+        read_blob_operation.clone(),
+        put_blob_operation.clone(),
     ));
-    let list_blobs_operation = Arc::new(ListBlobsOperation::new(slot_manager.clone()));
 
     let internal_put_part_operation = Arc::new(InternalPutPartOperation::new(
         slot_manager.clone(),
@@ -124,35 +354,287 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
         part_store.clone(),
     ));
     let internal_put_head_operation = Arc::new(InternalPutHeadOperation::new(slot_manager.clone()));
+    let internal_put_head_batch_operation =
+        Arc::new(InternalPutHeadBatchOperation::new(slot_manager.clone()));
     let internal_get_head_operation = Arc::new(InternalGetHeadOperation::new(slot_manager.clone()));
+    let internal_get_slot_stats_operation =
+        Arc::new(InternalGetSlotStatsOperation::new(slot_manager.clone()));
+    let prune_versions_operation = Arc::new(
+        PruneVersionsOperation::new(slot_manager.clone(), config.replication.retention.clone())
+            .with_secure_delete(config.replication.secure_delete.clone()),
+    );
+    let verify_head_chain_operation = Arc::new(VerifyHeadChainOperation::new(slot_manager.clone()));
+    let list_slot_changes_operation = Arc::new(ListSlotChangesOperation::new(slot_manager.clone()));
 
     let heal_slotlets_operation = Arc::new(HealSlotletsOperation::new(slot_manager.clone()));
     let heal_heads_operation = Arc::new(HealHeadsOperation::new(slot_manager.clone()));
     let heal_repair_operation = Arc::new(HealRepairOperation::new(read_blob_operation.clone()));
+    let heal_tombstones_operation = Arc::new(HealTombstonesOperation::new(slot_manager.clone()));
+
+    let placement = cluster_client.placement().clone();
+    let garbage_report_operation = Arc::new(GarbageReportOperation::new(
+        slot_manager.clone(),
+        placement.clone(),
+        registry.clone(),
+    ));
+    match placement.refresh().await {
+        Ok(count) => tracing::info!("loaded slot placement: slots={}", count),
+        Err(error) => tracing::warn!("Failed to load slot placement: {}", error),
+    }
+    placement.start_refresh(Duration::from_secs(30));
+
+    let slot_reconciler = Arc::new(SlotReconciler::new(
+        node_cfg.node_id.clone(),
+        slot_manager.clone(),
+        placement.clone(),
+        SlotReconcilerConfig::default(),
+    ));
+
+    let readiness = Arc::new(ReadinessMonitor::new(
+        node.clone(),
+        registry.clone(),
+        cluster_client.clone(),
+        runtime_archive_store.clone(),
+        config
+            .archive
+            .as_ref()
+            .is_some_and(|archive| archive.require_write_through),
+    ));
+
+    let host_pressure = config
+        .host_pressure
+        .map(|config| Arc::new(HostPressureMonitor::new(config)));
+
+    let scrub = Arc::new(
+        ScrubScheduler::new(
+            node.disks().to_vec(),
+            node_store.clone(),
+            ScrubConfig::default(),
+        )
+        .with_host_pressure(host_pressure.clone()),
+    );
+
+    let disk_health = Arc::new(DiskHealthMonitor::new(
+        node.clone(),
+        registry.clone(),
+        placement.clone(),
+        node_cfg
+            .disks
+            .iter()
+            .filter_map(|disk| {
+                disk.smart_device.clone().map(|smart_device| MonitoredDisk {
+                    path: disk.path.clone(),
+                    smart_device,
+                    drain_on_failure: disk.drain_on_failure,
+                })
+            })
+            .collect(),
+        DiskHealthConfig::default(),
+    ));
+
+    let head_schema = Arc::new(
+        HeadSchemaMigration::new(
+            slot_manager.clone(),
+            HeadSchemaMigrationConfig {
+                target: config.head_schema_target,
+                ..HeadSchemaMigrationConfig::default()
+            },
+        )
+        .with_host_pressure(host_pressure.clone()),
+    );
+
+    let slot_heat = Arc::new(SlotHeatTracker::new());
+    let heat_rebalancer = config.heat_rebalance.map(|rebalance| {
+        Arc::new(
+            SlotHeatRebalancer::new(
+                node_cfg.node_id.clone(),
+                registry.clone(),
+                placement.clone(),
+                cluster_client.clone(),
+                slot_heat.clone(),
+                rebalance,
+            )
+            .with_host_pressure(host_pressure.clone()),
+        )
+    });
+
+    let slot_mover = Arc::new(
+        SlotMover::new(registry.clone(), placement.clone(), cluster_client.clone())
+            .with_replication_policy(replication_policy.clone()),
+    );
+
+    let slot_rebalancer = config.slot_rebalance.map(|rebalance| {
+        Arc::new(
+            SlotRebalancer::new(
+                node_cfg.node_id.clone(),
+                registry.clone(),
+                placement.clone(),
+                slot_mover.clone(),
+                rebalance,
+            )
+            .with_host_pressure(host_pressure.clone()),
+        )
+    });
+
+    let decommission = Arc::new(DecommissionNodeOperation::new(
+        registry.clone(),
+        placement.clone(),
+        slot_mover,
+    ));
+
+    let slot_transfer = Arc::new(SlotTransferOperation::new(
+        slot_manager.clone(),
+        cluster_client.clone(),
+    ));
+
+    let multipart_uploads = Arc::new(MultipartUploads::new(
+        slot_manager.clone(),
+        part_store.clone(),
+        node_store.clone(),
+        put_blob_operation.clone(),
+    ));
+
+    let signer = config
+        .request_signing
+        .clone()
+        .map(|signing| Arc::new(RequestSigner::new(signing)));
+    let mirror = config
+        .mirror
+        .clone()
+        .map(|mirror| RequestMirror::new(mirror, signer.clone()))
+        .transpose()?
+        .map(Arc::new);
+    let access_log = config
+        .access_log
+        .as_ref()
+        .map(AccessLog::new)
+        .transpose()?
+        .map(Arc::new);
+    let notifier = config
+        .notifications
+        .clone()
+        .map(|notifications| Notifier::new(notifications, signer.clone()))
+        .transpose()?;
+    let auth = config
+        .auth
+        .clone()
+        .map(|auth| Arc::new(Authenticator::new(auth, registry.clone())));
 
     let state = Arc::new(ServerState {
         node,
         registry,
         config,
         coordinator,
-        put_blob_operation,
+        cluster_client: cluster_client.clone(),
+        put_blob_operation: put_blob_operation.clone(),
         read_blob_operation,
         delete_blob_operation,
+        delete_prefix_operation,
         list_blobs_operation,
+        commit_batch_operation,
+        transactions: Arc::new(TransactionManager::new()),
+        two_phase_commit,
+        rename_blob_operation,
+        put_blob_tags_operation,
         internal_put_part_operation,
         internal_get_part_operation,
         internal_put_head_operation,
+        internal_put_head_batch_operation,
         internal_get_head_operation,
+        internal_get_slot_stats_operation,
+        prune_versions_operation: prune_versions_operation.clone(),
+        apply_lifecycle_operation,
+        archive_uploader: archive_uploader.clone(),
+        verify_head_chain_operation,
+        list_slot_changes_operation,
+        garbage_report_operation,
         heal_slotlets_operation,
         heal_heads_operation,
         heal_repair_operation,
+        heal_tombstones_operation,
         idempotent_puts: Arc::new(RwLock::new(HashMap::new())),
+        node_store: node_store.clone(),
+        jobs: Arc::new(JobTracker::new(node_store.clone())?),
+        slot_leases,
+        placement,
+        slot_reconciler: slot_reconciler.clone(),
+        readiness: readiness.clone(),
+        scrub: scrub.clone(),
+        disk_health: disk_health.clone(),
+        mirror: mirror.clone(),
+        head_schema: head_schema.clone(),
+        multipart_uploads,
+        host_pressure: host_pressure.clone(),
+        auth: auth.clone(),
+        slot_heat,
+        heat_rebalancer: heat_rebalancer.clone(),
+        slot_rebalancer: slot_rebalancer.clone(),
+        decommission,
+        slot_transfer,
+        watch_hub: Arc::new(WatchHub::new()),
     });
 
+    if let Some(grpc) = &state.config.internal_grpc {
+        let listen_addr = grpc.listen_addr.parse().map_err(|error| {
+            RimError::Config(format!(
+                "invalid internal_grpc.listen_addr {}: {}",
+                grpc.listen_addr, error
+            ))
+        })?;
+        let service = Arc::new(grpc::InternalGrpcService::new(state.clone()));
+        // Guarded like the internal HTTP routes: the cluster key unless
+        // `public_internal` leaves them open.
+        let cluster_key = state
+            .config
+            .auth
+            .as_ref()
+            .filter(|auth| !auth.public_internal)
+            .and_then(|auth| auth.cluster_key.clone());
+        tokio::spawn(async move {
+            if let Err(error) = serve_internal_grpc(listen_addr, service, cluster_key).await {
+                tracing::error!("internal gRPC listener stopped: {}", error);
+            }
+        });
+        tracing::info!("internal gRPC listening on {}", listen_addr);
+    }
     register_local_node(&state).await?;
+    readiness.start().await;
+    slot_reconciler.start();
+    scrub.start();
+    disk_health.start();
+    head_schema.start();
+    Arc::new(
+        SqliteMaintenance::new(
+            slot_manager.clone(),
+            SqliteMaintenanceConfig {
+                checkpoint: state.config.sqlite_checkpoint.unwrap_or_default(),
+                integrity: state.config.sqlite_integrity.unwrap_or_default(),
+                ..SqliteMaintenanceConfig::default()
+            },
+        )
+        .with_host_pressure(host_pressure.clone())
+        .with_slot_repair(
+            state.node.node_id().to_string(),
+            state.placement.clone(),
+            state.slot_transfer.clone(),
+        ),
+    )
+    .start();
+    if let Some(host_pressure) = &host_pressure {
+        host_pressure.clone().start();
+    }
+    if let Some(heat_rebalancer) = heat_rebalancer {
+        heat_rebalancer.start();
+    }
+    if let Some(slot_rebalancer) = slot_rebalancer {
+        slot_rebalancer.start();
+    }
 
     if let (Some(archive_store), Some(archive_key_prefix)) =
         (runtime_archive_store.clone(), archive_key_prefix.clone())
+        && archive_tiering
+            .as_ref()
+            .is_none_or(|tiering| tiering.eager_sync)
     {
         let archive_manager = Arc::new(ArchiveLifecycleManager::new(
             node_cfg.node_id.clone(),
@@ -162,7 +644,7 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
             cluster_client.clone(),
             archive_store,
             archive_key_prefix,
-            data_dir.clone(),
+            node_store.clone(),
             ArchiveLifecycleConfig::default(),
         )?);
         archive_manager.start();
@@ -173,6 +655,162 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
         );
     }
 
+    if prune_versions_operation.retention().is_enabled() {
+        let slot_manager = slot_manager.clone();
+        let host_pressure = host_pressure.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(VERSION_PRUNE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if host_pressure
+                    .as_ref()
+                    .is_some_and(|pressure| pressure.sheds_background())
+                {
+                    continue;
+                }
+                let slots = match slot_manager.list_local_slots() {
+                    Ok(slots) => slots,
+                    Err(error) => {
+                        tracing::warn!("Failed to list slots for version pruning: {}", error);
+                        continue;
+                    }
+                };
+                for slot_id in slots {
+                    match prune_versions_operation
+                        .run(PruneVersionsOperationRequest {
+                            slot_id,
+                            dry_run: false,
+                        })
+                        .await
+                    {
+                        Ok(result) if result.pruned_versions + result.pruned_paths > 0 => {
+                            tracing::info!(
+                                "pruned slot {}: versions={} paths={} freed_bytes={}",
+                                slot_id,
+                                result.pruned_versions,
+                                result.pruned_paths,
+                                result.freed_bytes
+                            );
+                        }
+                        Ok(_) => {}
+                        Err(error) => {
+                            tracing::warn!("Failed to prune slot {}: {}", slot_id, error);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    {
+        let lifecycle_state = state.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(LIFECYCLE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if lifecycle_state
+                    .host_pressure
+                    .as_ref()
+                    .is_some_and(|pressure| pressure.sheds_background())
+                {
+                    continue;
+                }
+                let rules = match lifecycle_state.registry.get_lifecycle_rules().await {
+                    Ok(rules) => rules,
+                    Err(error) => {
+                        tracing::warn!("Failed to read lifecycle rules: {}", error);
+                        continue;
+                    }
+                };
+                match lifecycle::apply_lifecycle(&lifecycle_state, &rules, false).await {
+                    Ok(slots) => {
+                        for result in slots {
+                            tracing::info!(
+                                "lifecycle applied to slot {}: expired={} transitioned={} awaiting_archive={} freed_bytes={} failed={}",
+                                result.slot_id,
+                                result.expired,
+                                result.transitioned,
+                                result.awaiting_archive,
+                                result.freed_bytes,
+                                result.failed
+                            );
+                        }
+                    }
+                    Err(error) => tracing::warn!("Failed to apply lifecycle rules: {}", error),
+                }
+            }
+        });
+    }
+
+    if let Some(uploader) = archive_uploader {
+        let tiering_state = state.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(uploader.config().interval_secs.max(1)));
+            loop {
+                ticker.tick().await;
+                if tiering_state
+                    .host_pressure
+                    .as_ref()
+                    .is_some_and(|pressure| pressure.sheds_background())
+                {
+                    continue;
+                }
+                match tiering::run_tiering(&tiering_state).await {
+                    Ok(report)
+                        if report.uploaded_parts + report.evicted_parts + report.failed > 0 =>
+                    {
+                        tracing::info!(
+                            "archive tiering pass: uploaded_parts={} uploaded_bytes={} evicted_parts={} freed_bytes={} failed={}",
+                            report.uploaded_parts,
+                            report.uploaded_bytes,
+                            report.evicted_parts,
+                            report.freed_bytes,
+                            report.failed
+                        );
+                    }
+                    Ok(_) => {}
+                    Err(error) => tracing::warn!("Archive tiering pass failed: {}", error),
+                }
+            }
+        });
+    }
+
+    if let Some(auth) = &auth {
+        if let Err(error) = auth.refresh().await {
+            tracing::warn!("Failed to load api keys: {}", error);
+        }
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(auth.refresh_interval());
+            loop {
+                ticker.tick().await;
+                if let Err(error) = auth.refresh().await {
+                    tracing::warn!("Failed to refresh api keys: {}", error);
+                }
+                if let Err(error) = auth.purge_upload_tokens().await {
+                    tracing::warn!("Failed to purge used upload tokens: {}", error);
+                }
+            }
+        });
+    }
+
+    if let Some(notifier) = notifier {
+        let notify_state = state.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(notifier.interval());
+            loop {
+                ticker.tick().await;
+                match notifier.run_once(&notify_state).await {
+                    Ok(delivered) if delivered > 0 => {
+                        tracing::debug!("notification pass delivered {} events", delivered);
+                    }
+                    Ok(_) => {}
+                    Err(error) => tracing::warn!("Notification pass failed: {}", error),
+                }
+            }
+        });
+    }
+
     {
         let heartbeat_state = state.clone();
         tokio::spawn(async move {
@@ -182,6 +820,19 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
                 if let Err(error) = register_local_node(&heartbeat_state).await {
                     tracing::warn!("Failed to refresh node registration: {}", error);
                 }
+                if let Err(error) = heartbeat_state.node_store.purge_expired() {
+                    tracing::warn!("Failed to purge expired node state: {}", error);
+                }
+                if let Err(error) = heartbeat_state.multipart_uploads.purge_orphans().await {
+                    tracing::warn!("Failed to purge orphaned upload parts: {}", error);
+                }
+                if let Err(error) = heartbeat_state
+                    .two_phase_commit
+                    .resolve_in_doubt(heartbeat_state.node.node_id(), None, IN_DOUBT_AFTER_SECS)
+                    .await
+                {
+                    tracing::warn!("Failed to resolve in-doubt transactions: {}", error);
+                }
             }
         });
     }
@@ -190,24 +841,142 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
         .route("/health", get(health))
         .route("/_/health", get(health))
         .route("/_/api/v1/healthz", get(v1_healthz))
+        .route("/_/api/v1/readyz", get(v1_readyz))
         .route("/_/api/v1/nodes", get(v1_nodes))
         .route("/_/api/v1/slots/resolve", get(v1_resolve_slot))
+        .route("/_/api/v1/endpoints", get(v1_slot_endpoints))
+        .route("/_/api/v1/slots/reconcile", get(v1_reconcile_report))
+        .route("/_/api/v1/slots/heat", get(v1_slot_heat))
+        .route("/_/api/v1/slots/rebalance", get(v1_heat_rebalance_report))
+        .route("/_/api/v1/slots/handoffs", get(v1_slot_rebalance_report))
+        .route("/_/api/v1/decommission", post(v1_decommission))
+        .route("/_/api/v1/registry", get(v1_registry_state))
+        .route("/_/api/v1/registry/slots", get(v1_registry_slots))
+        .route("/_/api/v1/registry/slots/:slot_id", get(v1_registry_slot))
+        .route("/_/api/v1/scrub", get(v1_scrub_report))
+        .route("/_/api/v1/garbage", get(v1_garbage_report))
+        .route("/_/api/v1/disks", get(v1_disk_health))
+        .route("/_/api/v1/mirror", get(v1_mirror_stats))
+        .route("/_/api/v1/schema/heads", get(v1_head_schema_status))
+        .route("/_/api/v1/protocol", get(v1_protocol))
+        .route("/_/api/v1/peers", get(v1_peers))
+        .route("/_/api/v1/pressure", get(v1_host_pressure))
+        .route("/_/api/v1/repairs", get(v1_list_repairs))
+        .route("/_/api/v1/repairs/stats", get(v1_repair_stats))
+        .route(
+            "/_/api/v1/repairs/dead-letters",
+            get(v1_list_dead_letters).delete(v1_release_dead_letter),
+        )
+        .route("/_/api/v1/slots/:slot_id", get(v1_get_slot))
+        .route("/_/api/v1/slots/:slot_id/prune", post(v1_prune_slot))
+        .route("/_/api/v1/slots/:slot_id/freeze", post(v1_freeze_slot))
+        .route("/_/api/v1/slots/:slot_id/thaw", post(v1_thaw_slot))
+        .route(
+            "/_/api/v1/slots/:slot_id/head-chain",
+            get(v1_verify_head_chain),
+        )
+        .route("/_/api/v1/slots/:slot_id/changes", get(v1_slot_changes))
         .route("/_/api/v1/blobs", get(v1_list_blobs))
+        .route("/_/api/v1/inventory", get(v1_blob_inventory))
+        .route("/_/api/v1/audit/export", get(v1_audit_export))
+        .route("/_/api/v1/watch", get(v1_watch))
+        .route("/_/api/v1/batch", post(v1_commit_batch))
+        .route("/_/api/v1/rename", post(v1_rename_blob))
+        .route("/_/api/v1/delete-prefix", post(v1_delete_prefix))
+        .route("/_/api/v1/jobs", get(v1_list_jobs))
+        .route("/_/api/v1/jobs/:job_id", get(v1_get_job))
+        .route("/_/api/v1/jobs/:job_id/events", get(v1_job_events))
+        .route(
+            "/_/api/v1/lifecycle/rules",
+            get(v1_get_lifecycle_rules).put(v1_put_lifecycle_rules),
+        )
+        .route("/_/api/v1/lifecycle/run", post(v1_run_lifecycle))
+        .route(
+            "/_/api/v1/auth/keys",
+            get(v1_get_api_keys).put(v1_put_api_keys),
+        )
+        .route("/_/api/v1/upload-tokens", post(v1_create_upload_token))
+        .route("/_/api/v1/archive/tier", post(v1_run_tiering))
+        .route("/_/api/v1/transactions", post(v1_begin_transaction))
+        .route(
+            "/_/api/v1/transactions/:txn_id",
+            get(v1_get_transaction).delete(v1_abort_transaction),
+        )
+        .route(
+            "/_/api/v1/transactions/:txn_id/commit",
+            post(v1_commit_transaction),
+        )
+        .route(
+            "/_/api/v1/transactions/:txn_id/blobs/*path",
+            put(v1_stage_transaction_put).delete(v1_stage_transaction_delete),
+        )
         .route(
             "/_/api/v1/blobs/*path",
             get(v1_get_blob)
                 .head(v1_head_blob)
                 .put(v1_put_blob)
+                .post(v1_post_blob)
                 .delete(v1_delete_blob),
         )
+        .route("/v2", get(oci_version_check))
+        .route("/v2/", get(oci_version_check))
+        .route("/v2/*rest", any(oci_request))
+        .route("/internal/v1/protocol", get(internal_get_protocol))
+        .route("/internal/v1/heat", get(internal_get_slot_heat))
+        .route(
+            "/internal/v1/transactions/:txn_id",
+            get(internal_get_transaction_state),
+        )
+        .route(
+            "/internal/v1/transactions/:txn_id/resolve",
+            post(internal_resolve_transaction),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/parts/link",
+            post(internal_link_parts),
+        )
         .route(
             "/internal/v1/slots/:slot_id/parts/:sha256",
-            put(internal_put_part).get(internal_get_part),
+            put(internal_put_part)
+                .get(internal_get_part)
+                .head(internal_head_part),
         )
         .route(
             "/internal/v1/slots/:slot_id/heads",
             put(internal_put_head).get(internal_get_head),
         )
+        .route(
+            "/internal/v1/slots/:slot_id/heads/batch",
+            put(internal_put_head_batch),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/transactions/:txn_id/prepare",
+            post(internal_prepare_transaction),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/transactions/:txn_id/commit",
+            post(internal_commit_transaction),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/transactions/:txn_id/abort",
+            post(internal_abort_transaction),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/stats",
+            get(internal_get_slot_stats),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/prune",
+            post(internal_prune_versions),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/delete-prefix",
+            post(internal_delete_prefix),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/head-chain",
+            get(internal_verify_head_chain),
+        )
         .route(
             "/internal/v1/slots/:slot_id/heal/slotlets",
             get(v1_internal_heal_slotlets),
@@ -220,6 +989,30 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
             "/internal/v1/slots/:slot_id/heal/repair",
             post(v1_internal_heal_repair),
         )
+        .route(
+            "/internal/v1/slots/:slot_id/heal/tombstones",
+            post(v1_internal_heal_tombstones),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/placement",
+            post(v1_internal_reload_placement),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/transfer/exports",
+            post(internal_export_slot),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/transfer/exports/:transfer_id",
+            get(internal_get_slot_export),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/transfer/exports/:transfer_id/file",
+            get(internal_get_slot_export_file),
+        )
+        .route(
+            "/internal/v1/slots/:slot_id/transfer/pull",
+            post(internal_pull_slot),
+        )
         .route(
             "/internal/v1/cluster/bootstrap",
             get(v1_internal_cluster_bootstrap),
@@ -250,14 +1043,44 @@ pub async fn run_server(config: RuntimeConfig, registry: Arc<dyn Registry>) -> R
         )
         .route("/internal/v1/meta/write", post(v1_internal_meta_write))
         .merge(rimio_s3_gateway::router::<ServerState>())
+        .layer(middleware::from_fn(negotiate_protocol))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            shed_under_pressure,
+        ))
         .with_state(state);
+    let app = match mirror {
+        Some(mirror) => {
+            tracing::info!("mirroring blob traffic to {}", mirror.stats().endpoint);
+            app.layer(middleware::from_fn_with_state(mirror, mirror_traffic))
+        }
+        None => app,
+    };
+    let app = match signer {
+        Some(signer) => app.layer(middleware::from_fn_with_state(
+            signer,
+            verify_signed_requests,
+        )),
+        None => app,
+    };
+    let app = match auth {
+        Some(auth) => app.layer(middleware::from_fn_with_state(auth, require_auth)),
+        None => app,
+    };
+    let app = match access_log {
+        Some(access_log) => app.layer(middleware::from_fn_with_state(access_log, log_access)),
+        None => app,
+    };
 
     let listener = TcpListener::bind(&node_cfg.bind_addr).await?;
     tracing::info!("Rimio listening on {}", node_cfg.bind_addr);
 
-    let serve_result = axum::serve(listener, app)
-        .await
-        .map_err(|error| RimError::Http(error.to_string()));
+    let serve_result = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .map_err(|error| RimError::Http(error.to_string()));
 
     clear_global_embed_runtime();
 
@@ -301,13 +1124,64 @@ fn build_runtime_archive(
         return Ok((Some(store), Some("rimio/archive".to_string())));
     }
 
+    if config.archive_type.eq_ignore_ascii_case("gcs") {
+        let gcs = config.gcs.as_ref().ok_or_else(|| {
+            RimError::Config("archive.gcs is required when archive_type=gcs".to_string())
+        })?;
+
+        let gcs_store = Arc::new(GcsArchiveStore::new(
+            gcs.bucket.as_str(),
+            gcs.service_account_path.as_deref(),
+            gcs.service_account_key.as_deref(),
+        )?);
+        set_default_gcs_archive_store(gcs_store.clone());
+
+        let store: Arc<dyn ArchiveStore> = gcs_store;
+        return Ok((Some(store), Some("rimio/archive".to_string())));
+    }
+
+    if config.archive_type.eq_ignore_ascii_case("azure") {
+        let azure = config.azure.as_ref().ok_or_else(|| {
+            RimError::Config("archive.azure is required when archive_type=azure".to_string())
+        })?;
+
+        let azure_store = Arc::new(AzureArchiveStore::new(
+            azure.account.as_str(),
+            azure.container.as_str(),
+            azure.access_key.as_deref(),
+            azure.sas_token.as_deref(),
+            azure.endpoint.as_deref(),
+            azure.allow_http,
+        )?);
+        set_default_azure_archive_store(azure_store.clone());
+
+        let store: Arc<dyn ArchiveStore> = azure_store;
+        return Ok((Some(store), Some("rimio/archive".to_string())));
+    }
+
+    if config.archive_type.eq_ignore_ascii_case("fs") {
+        let fs = config.fs.as_ref().ok_or_else(|| {
+            RimError::Config("archive.fs is required when archive_type=fs".to_string())
+        })?;
+
+        let fs_store = Arc::new(LocalFsArchiveStore::new(fs.path.as_str())?);
+        set_default_local_fs_archive_store(fs_store.clone());
+
+        let store: Arc<dyn ArchiveStore> = fs_store;
+        return Ok((Some(store), Some("rimio/archive".to_string())));
+    }
+
     Err(RimError::Config(format!(
         "unsupported archive_type for runtime: {}",
         config.archive_type
     )))
 }
 
+/// Registers this node, unless it was decommissioned.
 pub(crate) async fn register_local_node(state: &ServerState) -> Result<()> {
+    if state.decommission.has_left(state.node.node_id()).await {
+        return Ok(());
+    }
     let info = state.node.info().await;
     state.registry.register_node(&info).await
 }
@@ -326,11 +1200,51 @@ pub(crate) async fn current_nodes(state: &ServerState) -> Result<Vec<NodeInfo>>
     Ok(nodes)
 }
 
+/// Resolves the replica set of a slot from the placement map.
+///
+/// Replicas that are not currently registered are still returned, so writes
+/// count them against the quorum instead of silently shrinking it. Clusters
+/// without a placement map fall back to rotating over the node list.
 pub(crate) async fn resolve_replica_nodes(
     state: &ServerState,
     slot_id: u16,
 ) -> Result<Vec<NodeInfo>> {
     let nodes = current_nodes(state).await?;
+    let slot = state.placement.slot(slot_id).await?;
+    replica_nodes_for(state, &nodes, slot_id, slot.as_ref())
+}
+
+/// The replica set of `slot_id` given the node list and its placement
+/// record, if any; see [`resolve_replica_nodes`].
+pub(crate) fn replica_nodes_for(
+    state: &ServerState,
+    nodes: &[NodeInfo],
+    slot_id: u16,
+    slot: Option<&SlotInfo>,
+) -> Result<Vec<NodeInfo>> {
+    if let Some(slot) = slot
+        && !slot.replicas.is_empty()
+    {
+        return Ok(slot
+            .replicas
+            .iter()
+            .map(|node_id| {
+                nodes
+                    .iter()
+                    .find(|node| &node.node_id == node_id)
+                    .cloned()
+                    .unwrap_or_else(|| NodeInfo {
+                        node_id: node_id.clone(),
+                        group_id: state.node.group_id().to_string(),
+                        address: String::new(),
+                        status: rimio_core::NodeStatus::Unhealthy,
+                        slots: Vec::new(),
+                        grpc_address: None,
+                    })
+            })
+            .collect());
+    }
+
     if nodes.is_empty() {
         return Err(RimError::Internal("no nodes found".to_string()));
     }
@@ -345,26 +1259,283 @@ pub(crate) async fn resolve_replica_nodes(
     Ok(rotated.into_iter().take(replica_count).collect())
 }
 
-pub(crate) fn normalize_blob_path(path: &str) -> Result<String> {
-    let trimmed = path.trim_matches('/');
-    if trimmed.is_empty() {
-        return Err(RimError::InvalidRequest(
-            "blob path cannot be empty".to_string(),
+/// Names the replica a client wants its request served by.
+pub(crate) const PREFER_NODE_HEADER: &str = "x-rimio-prefer-node";
+/// Asks the receiving node to serve the request itself rather than send the
+/// client on to another node.
+pub(crate) const NO_PROXY_HEADER: &str = "x-rimio-no-proxy";
+
+/// Times a request has already been sent from one node to another.
+pub(crate) const HOPS_HEADER: &str = "x-rimio-hops";
+/// Comma-separated nodes that already sent the request on.
+pub(crate) const VIA_HEADER: &str = "x-rimio-via";
+/// Query parameters carrying the hop state across redirects, since clients
+/// do not resend headers a redirect sets.
+const HOPS_QUERY_PARAM: &str = "rimio_hops";
+const VIA_QUERY_PARAM: &str = "rimio_via";
+/// A request is never sent on more often than this.
+pub(crate) const MAX_HOPS: u32 = 3;
+
+/// Routing hints smart clients send with blob requests, plus the hop state
+/// of requests other nodes sent on.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RoutingHints {
+    pub(crate) prefer_node: Option<String>,
+    pub(crate) no_proxy: bool,
+    pub(crate) hops: u32,
+    pub(crate) via: Vec<String>,
+}
+
+impl RoutingHints {
+    /// Reads the hints and, for requests that may be redirected, the hop
+    /// state from headers and the query string.
+    pub(crate) fn from_request(headers: &HeaderMap, uri: &Uri) -> Self {
+        let mut hints = Self::from_headers(headers);
+
+        let header_hops = headers
+            .get(HOPS_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u32>().ok());
+        let header_via = headers
+            .get(VIA_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let mut query_hops = None;
+        let mut query_via = None;
+        for (key, value) in query_pairs(uri) {
+            match key.as_str() {
+                HOPS_QUERY_PARAM => query_hops = value.trim().parse::<u32>().ok(),
+                VIA_QUERY_PARAM => query_via = Some(value),
+                _ => {}
+            }
+        }
+
+        hints.hops = header_hops.max(query_hops).unwrap_or(0);
+        hints.via = header_via
+            .into_iter()
+            .chain(query_via)
+            .flat_map(|value| {
+                value
+                    .split(',')
+                    .map(|node| node.trim().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|node| !node.is_empty())
+            .collect();
+        hints
+    }
+
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let prefer_node = headers
+            .get(PREFER_NODE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+        let no_proxy = headers
+            .get(NO_PROXY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| matches!(value.trim(), "1" | "true" | "yes"));
+
+        Self {
+            prefer_node,
+            no_proxy,
+            ..Self::default()
+        }
+    }
+}
+
+/// Resolves the replica set of a blob request and applies the client's
+/// routing hints.
+///
+/// A preferred node that is a replica moves to the front, so reads try it
+/// first and it coordinates when no lease decides otherwise. With `no-proxy`,
+/// a node outside the replica set answers `421` naming the slot owners.
+pub(crate) async fn route_blob_request(
+    state: &ServerState,
+    slot_id: u16,
+    hints: &RoutingHints,
+) -> std::result::Result<Vec<NodeInfo>, Response> {
+    let mut replicas = resolve_replica_nodes(state, slot_id)
+        .await
+        .map_err(|error| response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+
+    if let Some(prefer_node) = hints.prefer_node.as_deref()
+        && let Some(index) = replicas.iter().position(|node| node.node_id == prefer_node)
+    {
+        let preferred = replicas.remove(index);
+        replicas.insert(0, preferred);
+    }
+
+    if hints.no_proxy
+        && !replicas
+            .iter()
+            .any(|node| node.node_id == state.node.node_id())
+    {
+        return Err(misdirected_response(
+            format!("slot not owned by this node: slot={}", slot_id),
+            &replicas
+                .iter()
+                .map(|node| node.node_id.as_str())
+                .collect::<Vec<_>>(),
         ));
     }
 
-    let mut components = Vec::new();
-    for component in trimmed.split('/') {
-        if component.is_empty() || component == "." || component == ".." {
-            return Err(RimError::InvalidRequest(format!(
-                "invalid blob path component: {}",
-                component
-            )));
+    Ok(replicas)
+}
+
+fn misdirected_response(message: String, owners: &[&str]) -> Response {
+    let mut response = response_error(StatusCode::MISDIRECTED_REQUEST, message);
+    if let Ok(value) = HeaderValue::from_str(&owners.join(",")) {
+        response.headers_mut().insert("x-rimio-slot-owners", value);
+    }
+    response
+}
+
+/// Claims the write lease of a slot before coordinating a write.
+///
+/// Returns a guard serializing local writes of the slot when this node holds
+/// the lease (or `None` when leases are disabled). When another node holds
+/// it, returns a `307` response pointing the client at the holder, or `421`
+/// when the client asked not to be sent elsewhere.
+pub(crate) async fn claim_write_lease(
+    state: &ServerState,
+    slot_id: u16,
+    replicas: &[NodeInfo],
+    uri: &Uri,
+    hints: &RoutingHints,
+) -> std::result::Result<Option<OwnedMutexGuard<()>>, Response> {
+    let Some(leases) = state.slot_leases.as_ref() else {
+        return Ok(None);
+    };
+
+    let local_node_id = state.node.node_id();
+    let lease = if replicas.iter().any(|node| node.node_id == local_node_id) {
+        leases.acquire(slot_id).await.map(Some)
+    } else {
+        leases.current(slot_id).await
+    };
+
+    let holder = match lease {
+        Ok(Some(lease)) => lease.holder,
+        Ok(None) => match replicas.first() {
+            Some(node) => node.node_id.clone(),
+            None => {
+                return Err(response_error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("no replica available for slot lease: slot={}", slot_id),
+                ));
+            }
+        },
+        Err(error) => {
+            return Err(response_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("slot lease unavailable: slot={} error={}", slot_id, error),
+            ));
         }
-        components.push(component);
+    };
+
+    if holder == local_node_id {
+        return Ok(Some(leases.lock_slot(slot_id).await));
     }
 
-    Ok(components.join("/"))
+    if hints.no_proxy {
+        return Err(misdirected_response(
+            format!(
+                "slot lease held by another node: slot={} holder={}",
+                slot_id, holder
+            ),
+            &[holder.as_str()],
+        ));
+    }
+
+    let nodes = current_nodes(state).await.unwrap_or_default();
+    let Some(address) = nodes
+        .iter()
+        .find(|node| node.node_id == holder)
+        .map(|node| node.address.clone())
+    else {
+        return Err(response_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "slot lease holder unknown: slot={} holder={}",
+                slot_id, holder
+            ),
+        ));
+    };
+
+    if hints.via.iter().any(|node| node == local_node_id) || hints.hops >= MAX_HOPS {
+        tracing::warn!(
+            "redirect loop refused: slot={} holder={} hops={} via={}",
+            slot_id,
+            holder,
+            hints.hops,
+            hints.via.join(",")
+        );
+        return Err(response_error(
+            StatusCode::LOOP_DETECTED,
+            format!(
+                "request bounced between nodes: slot={} hops={} via={}",
+                slot_id,
+                hints.hops,
+                hints.via.join(",")
+            ),
+        ));
+    }
+
+    let location = match redirect_location(&address, uri, hints, local_node_id) {
+        Ok(location) => location,
+        Err(error) => return Err(response_error(StatusCode::INTERNAL_SERVER_ERROR, error)),
+    };
+
+    let mut response = StatusCode::TEMPORARY_REDIRECT.into_response();
+    if let Ok(value) = HeaderValue::from_str(&location) {
+        response.headers_mut().insert(header::LOCATION, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&holder) {
+        response.headers_mut().insert("x-rimio-lease-holder", value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&(hints.hops + 1).to_string()) {
+        response.headers_mut().insert(HOPS_HEADER, value);
+    }
+
+    Err(response)
+}
+
+fn query_pairs(uri: &Uri) -> Vec<(String, String)> {
+    uri.query()
+        .and_then(|query| Url::parse(&format!("http://localhost/?{}", query)).ok())
+        .map(|url| url.query_pairs().into_owned().collect())
+        .unwrap_or_default()
+}
+
+/// Points a redirect at `address`, carrying the incremented hop count and
+/// this node in the query string.
+fn redirect_location(
+    address: &str,
+    uri: &Uri,
+    hints: &RoutingHints,
+    local_node_id: &str,
+) -> std::result::Result<String, String> {
+    let mut location = Url::parse(&format!("http://{}{}", address, uri.path()))
+        .map_err(|error| format!("invalid redirect target: {}", error))?;
+
+    let mut via = hints.via.clone();
+    via.push(local_node_id.to_string());
+
+    {
+        let mut pairs = location.query_pairs_mut();
+        for (key, value) in query_pairs(uri)
+            .iter()
+            .filter(|(key, _)| key != HOPS_QUERY_PARAM && key != VIA_QUERY_PARAM)
+        {
+            pairs.append_pair(key, value);
+        }
+        pairs.append_pair(HOPS_QUERY_PARAM, &(hints.hops + 1).to_string());
+        pairs.append_pair(VIA_QUERY_PARAM, &via.join(","));
+    }
+
+    Ok(location.to_string())
 }
 
 pub(crate) fn response_error(status: StatusCode, message: impl Into<String>) -> Response {
@@ -377,6 +1548,57 @@ pub(crate) fn response_error(status: StatusCode, message: impl Into<String>) ->
         .into_response()
 }
 
+/// Answers 503 with a `Retry-After` hint when the archive is temporarily
+/// unavailable and no peer could serve the data either.
+pub(crate) fn archive_unavailable_response(message: String, retry_after: Duration) -> Response {
+    let mut response = response_error(StatusCode::SERVICE_UNAVAILABLE, message);
+    let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    if let Ok(value) = HeaderValue::from_str(&seconds.to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
+
+/// Answers 416 with the `Content-Range: bytes */size` a client needs to
+/// retry with a range that fits.
+pub(crate) fn range_not_satisfiable_response(size_bytes: u64) -> Response {
+    let mut response = response_error(
+        StatusCode::RANGE_NOT_SATISFIABLE,
+        format!("range not satisfiable: size={}", size_bytes),
+    );
+    if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", size_bytes)) {
+        response.headers_mut().insert(header::CONTENT_RANGE, value);
+    }
+    response
+}
+
+/// Refuses a write while write-through is required and the archive is
+/// unreachable.
+pub(crate) async fn refuse_unarchived_write(state: &ServerState) -> Option<Response> {
+    match state.readiness.ensure_writable().await {
+        Ok(()) => None,
+        Err(RimError::ArchiveUnavailable {
+            message,
+            retry_after,
+        }) => Some(archive_unavailable_response(message, retry_after)),
+        Err(error) => Some(response_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            error.to_string(),
+        )),
+    }
+}
+
+/// Refuses a write to a slot frozen for maintenance.
+pub(crate) async fn refuse_frozen_write(state: &ServerState, slot_id: u16) -> Option<Response> {
+    if !state.placement.is_frozen(slot_id).await {
+        return None;
+    }
+    Some(response_error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!("slot {} is frozen for maintenance", slot_id),
+    ))
+}
+
 pub(crate) fn status_string(status: &rimio_core::NodeStatus) -> &'static str {
     match status {
         rimio_core::NodeStatus::Healthy => "healthy",
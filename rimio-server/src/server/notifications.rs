@@ -0,0 +1,709 @@
+use super::signing::{RequestSigner, url_path_and_query};
+use super::{ServerState, lifecycle::held_slots};
+use crate::config::{NotificationEvent, NotificationRule, NotificationTarget, NotificationsConfig};
+use rimio_core::{ChangeKind, ListSlotChangesOperationRequest, Result, RimError, SlotChange};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, Instant, timeout};
+
+const NOTIFY_CURSOR_SCOPE_PREFIX: &str = "notify:";
+
+/// What a notification target receives for one change-feed entry.
+#[derive(Debug, Clone, Serialize)]
+struct ObjectEvent<'a> {
+    /// Same on every replica, so consumers can drop the duplicates
+    /// at-least-once delivery produces.
+    id: String,
+    event: &'static str,
+    rule: &'a str,
+    slot_id: u16,
+    path: &'a str,
+    generation: i64,
+    sha256: &'a str,
+    recorded_at: chrono::DateTime<chrono::Utc>,
+    node_id: &'a str,
+}
+
+impl<'a> ObjectEvent<'a> {
+    fn new(rule: &'a str, slot_id: u16, change: &'a SlotChange, node_id: &'a str) -> Self {
+        let event = match change.kind {
+            ChangeKind::Create | ChangeKind::Update => "object_created",
+            ChangeKind::Delete => "object_deleted",
+        };
+        Self {
+            id: format!("{}@{}/{}", change.path, change.generation, event),
+            event,
+            rule,
+            slot_id,
+            path: &change.path,
+            generation: change.generation,
+            sha256: &change.head_sha256,
+            recorded_at: change.recorded_at,
+            node_id,
+        }
+    }
+}
+
+/// Publishes the object events of each rule to its target.
+///
+/// Only the primary of a slot publishes; it reads the slot's change feed
+/// from the rule's cursor and moves the cursor past each event once the
+/// target acknowledged it, so a failed delivery is retried on the next pass
+/// and nothing behind it is sent first. Other replicas move their cursor
+/// without publishing, `failover_overlap_secs` behind, and a rule seeing a
+/// slot for the first time starts at its newest change. Webhook requests
+/// are signed when the node has a signing key.
+pub(crate) struct Notifier {
+    config: NotificationsConfig,
+    client: reqwest::Client,
+    signer: Option<Arc<RequestSigner>>,
+}
+
+impl Notifier {
+    pub(crate) fn new(
+        config: NotificationsConfig,
+        signer: Option<Arc<RequestSigner>>,
+    ) -> Result<Self> {
+        let mut names = HashSet::new();
+        for rule in &config.rules {
+            if rule.name.trim().is_empty() {
+                return Err(RimError::Config(
+                    "notification rule name cannot be empty".to_string(),
+                ));
+            }
+            if !names.insert(rule.name.as_str()) {
+                return Err(RimError::Config(format!(
+                    "duplicate notification rule name '{}'",
+                    rule.name
+                )));
+            }
+            if let NotificationTarget::Webhook { url, .. } = &rule.target {
+                reqwest::Url::parse(url).map_err(|error| {
+                    RimError::Config(format!("invalid webhook url '{}': {}", url, error))
+                })?;
+            }
+        }
+        let client = reqwest::Client::builder()
+            .build()
+            .map_err(|error| RimError::Http(error.to_string()))?;
+
+        Ok(Self {
+            config,
+            client,
+            signer,
+        })
+    }
+
+    pub(crate) fn interval(&self) -> Duration {
+        Duration::from_millis(self.config.interval_ms.max(1))
+    }
+
+    /// Runs one pass over the slots this node holds; returns how many events
+    /// were delivered.
+    pub(crate) async fn run_once(&self, state: &ServerState) -> Result<usize> {
+        let mut delivered = 0;
+        for slot in held_slots(state).await? {
+            for rule in &self.config.rules {
+                match self
+                    .notify_slot(state, rule, slot.slot_id, slot.primary)
+                    .await
+                {
+                    Ok(count) => delivered += count,
+                    Err(RimError::SlotNotFound(_)) => break,
+                    Err(error) => tracing::warn!(
+                        "notification rule {} failed for slot {}: {}",
+                        rule.name,
+                        slot.slot_id,
+                        error
+                    ),
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    async fn notify_slot(
+        &self,
+        state: &ServerState,
+        rule: &NotificationRule,
+        slot_id: u16,
+        primary: bool,
+    ) -> Result<usize> {
+        let scope = format!("{}{}", NOTIFY_CURSOR_SCOPE_PREFIX, rule.name);
+        let Some(cursor) = state.node_store.load_cursor::<i64>(&scope, slot_id)? else {
+            let latest = state
+                .list_slot_changes_operation
+                .latest_seq(slot_id)
+                .await?;
+            state.node_store.save_cursor(&scope, slot_id, &latest)?;
+            return Ok(0);
+        };
+
+        let changes = state
+            .list_slot_changes_operation
+            .run(ListSlotChangesOperationRequest {
+                slot_id,
+                since: cursor,
+                limit: self.config.batch_size.max(1),
+            })
+            .await?;
+
+        if !primary {
+            let horizon = chrono::Utc::now()
+                - chrono::Duration::seconds(self.config.failover_overlap_secs as i64);
+            if let Some(change) = changes
+                .iter()
+                .take_while(|change| change.recorded_at <= horizon)
+                .last()
+            {
+                state.node_store.save_cursor(&scope, slot_id, &change.seq)?;
+            }
+            return Ok(0);
+        }
+
+        let mut sink = TargetSink {
+            notifier: self,
+            rule,
+            slot_id,
+            node_id: state.node.node_id(),
+            connection: None,
+        };
+        let outcome = deliver(rule, &changes, &mut sink, |seq| {
+            state.node_store.save_cursor(&scope, slot_id, &seq)
+        })
+        .await;
+        if let Some(connection) = sink.connection {
+            connection.close().await;
+        }
+        outcome
+    }
+
+    async fn publish(
+        &self,
+        target: &NotificationTarget,
+        connection: &mut Option<Connection>,
+        event: &ObjectEvent<'_>,
+    ) -> Result<()> {
+        let payload = serde_json::to_vec(event)?;
+        match target {
+            NotificationTarget::Webhook { url, timeout_ms } => {
+                let mut request = self
+                    .client
+                    .post(url)
+                    .timeout(Duration::from_millis(*timeout_ms))
+                    .header("content-type", "application/json")
+                    .header("x-rimio-event", event.event);
+                if let Some(signer) = &self.signer {
+                    let path_and_query = reqwest::Url::parse(url)
+                        .map(|url| url_path_and_query(&url))
+                        .unwrap_or_default();
+                    for (name, value) in signer.sign("POST", &path_and_query, &payload) {
+                        request = request.header(name, value);
+                    }
+                }
+                let response = request
+                    .body(payload)
+                    .send()
+                    .await
+                    .map_err(|error| RimError::Http(error.to_string()))?;
+                check_status(url, response.status())
+            }
+            NotificationTarget::Kafka {
+                rest_url,
+                topic,
+                timeout_ms,
+            } => {
+                let url = format!("{}/topics/{}", rest_url.trim_end_matches('/'), topic);
+                let body = serde_json::json!({
+                    "records": [{ "key": event.path, "value": event }],
+                });
+                let response = self
+                    .client
+                    .post(&url)
+                    .timeout(Duration::from_millis(*timeout_ms))
+                    .header("content-type", "application/vnd.kafka.json.v2+json")
+                    .body(serde_json::to_vec(&body)?)
+                    .send()
+                    .await
+                    .map_err(|error| RimError::Http(error.to_string()))?;
+                check_status(&url, response.status())
+            }
+            NotificationTarget::Nats {
+                addr,
+                subject,
+                timeout_ms,
+            } => {
+                let wait = Duration::from_millis(*timeout_ms);
+                let result = async {
+                    if connection.is_none() {
+                        *connection = Some(Connection::nats(addr, wait).await?);
+                    }
+                    match connection.as_mut() {
+                        Some(Connection::Nats(nats)) => nats.publish(subject, &payload).await,
+                        _ => Err(RimError::Internal("NATS connection missing".to_string())),
+                    }
+                }
+                .await;
+                finish_publish(connection, result)
+            }
+            NotificationTarget::Mqtt {
+                addr,
+                topic,
+                client_id,
+                username,
+                password,
+                timeout_ms,
+            } => {
+                let wait = Duration::from_millis(*timeout_ms);
+                let result = async {
+                    if connection.is_none() {
+                        let client_id = client_id
+                            .clone()
+                            .unwrap_or_else(|| format!("rimio-{}", event.node_id));
+                        *connection = Some(
+                            Connection::mqtt(
+                                addr,
+                                &client_id,
+                                username.as_deref(),
+                                password.as_deref(),
+                                wait,
+                            )
+                            .await?,
+                        );
+                    }
+                    match connection.as_mut() {
+                        Some(Connection::Mqtt(mqtt)) => mqtt.publish(topic, &payload).await,
+                        _ => Err(RimError::Internal("MQTT connection missing".to_string())),
+                    }
+                }
+                .await;
+                finish_publish(connection, result)
+            }
+        }
+    }
+}
+
+fn rule_matches(rule: &NotificationRule, change: &SlotChange) -> bool {
+    let event = match change.kind {
+        ChangeKind::Create | ChangeKind::Update => NotificationEvent::Created,
+        ChangeKind::Delete => NotificationEvent::Deleted,
+    };
+    change.path.starts_with(&rule.prefix) && rule.events.contains(&event)
+}
+
+/// Where [`deliver`] sends the events of one rule.
+trait EventSink {
+    async fn send(&mut self, change: &SlotChange) -> Result<()>;
+}
+
+/// Publishes to the rule's target through the connection kept for the pass.
+struct TargetSink<'a> {
+    notifier: &'a Notifier,
+    rule: &'a NotificationRule,
+    slot_id: u16,
+    node_id: &'a str,
+    connection: Option<Connection>,
+}
+
+impl EventSink for TargetSink<'_> {
+    async fn send(&mut self, change: &SlotChange) -> Result<()> {
+        let event = ObjectEvent::new(&self.rule.name, self.slot_id, change, self.node_id);
+        self.notifier
+            .publish(&self.rule.target, &mut self.connection, &event)
+            .await
+    }
+}
+
+/// Sends the changes `rule` matches in feed order, calling `advance` with
+/// the seq the cursor may move to after each acknowledged event and, once
+/// all are sent, past the trailing changes the rule skips. Stops at the
+/// first failed send without moving the cursor past it.
+async fn deliver(
+    rule: &NotificationRule,
+    changes: &[SlotChange],
+    sink: &mut impl EventSink,
+    mut advance: impl FnMut(i64) -> Result<()>,
+) -> Result<usize> {
+    let mut delivered = 0;
+    let mut skipped = None;
+    for change in changes {
+        if rule_matches(rule, change) {
+            sink.send(change).await?;
+            delivered += 1;
+            advance(change.seq)?;
+            skipped = None;
+        } else {
+            skipped = Some(change.seq);
+        }
+    }
+    if let Some(seq) = skipped {
+        advance(seq)?;
+    }
+    Ok(delivered)
+}
+
+fn check_status(url: &str, status: reqwest::StatusCode) -> Result<()> {
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(RimError::Http(format!("{} answered {}", url, status)))
+    }
+}
+
+/// Drops the connection after a failed or timed-out publish, so the next
+/// attempt starts from a fresh one.
+fn finish_publish(connection: &mut Option<Connection>, result: Result<()>) -> Result<()> {
+    if result.is_err() {
+        *connection = None;
+    }
+    result
+}
+
+/// Runs one exchange with a broker, failing it once `wait` has passed.
+async fn bounded<T>(
+    wait: Duration,
+    what: impl FnOnce() -> String,
+    exchange: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    match timeout(wait, exchange).await {
+        Ok(result) => result,
+        Err(_) => Err(RimError::Http(format!("{} timed out", what()))),
+    }
+}
+
+/// Broker connection kept for the rest of a pass.
+enum Connection {
+    Nats(NatsConnection),
+    Mqtt(MqttConnection),
+}
+
+impl Connection {
+    async fn nats(addr: &str, wait: Duration) -> Result<Self> {
+        Ok(Self::Nats(NatsConnection::connect(addr, wait).await?))
+    }
+
+    async fn mqtt(
+        addr: &str,
+        client_id: &str,
+        username: Option<&str>,
+        password: Option<&str>,
+        wait: Duration,
+    ) -> Result<Self> {
+        Ok(Self::Mqtt(
+            MqttConnection::connect(addr, client_id, username, password, wait).await?,
+        ))
+    }
+
+    async fn close(self) {
+        match self {
+            Self::Nats(nats) => {
+                let mut stream = nats.stream.into_inner();
+                let _ = timeout(nats.wait, stream.shutdown()).await;
+            }
+            Self::Mqtt(mut mqtt) => {
+                let _ = timeout(mqtt.wait, async {
+                    mqtt.stream.write_all(&[0xe0, 0x00]).await?;
+                    mqtt.stream.shutdown().await
+                })
+                .await;
+            }
+        }
+    }
+}
+
+struct NatsConnection {
+    stream: BufReader<TcpStream>,
+    wait: Duration,
+}
+
+impl NatsConnection {
+    async fn connect(addr: &str, wait: Duration) -> Result<Self> {
+        bounded(wait, || format!("NATS connect to {}", addr), async {
+            let mut connection = Self {
+                stream: BufReader::new(TcpStream::connect(addr).await?),
+                wait,
+            };
+            let info = connection.read_line().await?;
+            if !info.starts_with("INFO") {
+                return Err(RimError::Http(format!(
+                    "unexpected NATS greeting from {}: {}",
+                    addr, info
+                )));
+            }
+            connection
+                .stream
+                .get_mut()
+                .write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"rimio\"}\r\n")
+                .await?;
+            Ok(connection)
+        })
+        .await
+    }
+
+    async fn publish(&mut self, subject: &str, payload: &[u8]) -> Result<()> {
+        let wait = self.wait;
+        bounded(wait, || format!("NATS publish to {}", subject), async {
+            let mut frame = format!("PUB {} {}\r\n", subject, payload.len()).into_bytes();
+            frame.extend_from_slice(payload);
+            frame.extend_from_slice(b"\r\nPING\r\n");
+            self.stream.get_mut().write_all(&frame).await?;
+
+            loop {
+                let line = self.read_line().await?;
+                if line == "PONG" {
+                    return Ok(());
+                } else if line == "PING" {
+                    self.stream.get_mut().write_all(b"PONG\r\n").await?;
+                } else if let Some(error) = line.strip_prefix("-ERR") {
+                    return Err(RimError::Http(format!("NATS error:{}", error)));
+                }
+            }
+        })
+        .await
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Err(RimError::Http(
+                "NATS server closed the connection".to_string(),
+            ));
+        }
+        Ok(line.trim_end().to_string())
+    }
+}
+
+/// Keep-alive announced in CONNECT; a connection idle for half of it sends
+/// PINGREQ before the next publish, so the broker never drops it as dead.
+const MQTT_KEEP_ALIVE_SECS: u16 = 60;
+
+struct MqttConnection {
+    stream: TcpStream,
+    next_packet_id: u16,
+    wait: Duration,
+    last_sent: Instant,
+}
+
+impl MqttConnection {
+    async fn connect(
+        addr: &str,
+        client_id: &str,
+        username: Option<&str>,
+        password: Option<&str>,
+        wait: Duration,
+    ) -> Result<Self> {
+        bounded(wait, || format!("MQTT connect to {}", addr), async {
+            let mut stream = TcpStream::connect(addr).await?;
+
+            let mut body = Vec::new();
+            put_mqtt_string(&mut body, "MQTT");
+            body.push(4);
+            let mut flags = 0x02;
+            if username.is_some() {
+                flags |= 0x80;
+            }
+            if password.is_some() {
+                flags |= 0x40;
+            }
+            body.push(flags);
+            body.extend_from_slice(&MQTT_KEEP_ALIVE_SECS.to_be_bytes());
+            put_mqtt_string(&mut body, client_id);
+            if let Some(username) = username {
+                put_mqtt_string(&mut body, username);
+            }
+            if let Some(password) = password {
+                put_mqtt_string(&mut body, password);
+            }
+            stream.write_all(&mqtt_packet(0x10, &body)).await?;
+
+            let mut connack = [0u8; 4];
+            stream.read_exact(&mut connack).await?;
+            if connack[0] != 0x20 || connack[3] != 0 {
+                return Err(RimError::Http(format!(
+                    "MQTT broker {} refused the connection (code {})",
+                    addr, connack[3]
+                )));
+            }
+
+            Ok(Self {
+                stream,
+                next_packet_id: 1,
+                wait,
+                last_sent: Instant::now(),
+            })
+        })
+        .await
+    }
+
+    async fn publish(&mut self, topic: &str, payload: &[u8]) -> Result<()> {
+        let wait = self.wait;
+        bounded(wait, || format!("MQTT publish to {}", topic), async {
+            if self.last_sent.elapsed() >= Duration::from_secs(MQTT_KEEP_ALIVE_SECS as u64 / 2) {
+                self.ping().await?;
+            }
+
+            let packet_id = self.next_packet_id;
+            self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+
+            let mut body = Vec::with_capacity(topic.len() + payload.len() + 4);
+            put_mqtt_string(&mut body, topic);
+            body.extend_from_slice(&packet_id.to_be_bytes());
+            body.extend_from_slice(payload);
+            self.send(&mqtt_packet(0x32, &body)).await?;
+
+            let mut puback = [0u8; 4];
+            self.stream.read_exact(&mut puback).await?;
+            if puback[0] != 0x40 || u16::from_be_bytes([puback[2], puback[3]]) != packet_id {
+                return Err(RimError::Http(
+                    "MQTT broker did not acknowledge the publish".to_string(),
+                ));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn ping(&mut self) -> Result<()> {
+        self.send(&[0xc0, 0x00]).await?;
+        let mut pingresp = [0u8; 2];
+        self.stream.read_exact(&mut pingresp).await?;
+        if pingresp != [0xd0, 0x00] {
+            return Err(RimError::Http(
+                "MQTT broker did not answer the ping".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, packet: &[u8]) -> Result<()> {
+        self.stream.write_all(packet).await?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+}
+
+fn put_mqtt_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn mqtt_packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut remaining = body.len();
+    loop {
+        let mut byte = (remaining % 128) as u8;
+        remaining /= 128;
+        if remaining > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if remaining == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaining_length_uses_seven_bits_per_byte() {
+        let header_of = |len: usize| {
+            let packet = mqtt_packet(0x30, &vec![0; len]);
+            packet[..packet.len() - len].to_vec()
+        };
+        assert_eq!(header_of(0), [0x30, 0x00]);
+        assert_eq!(header_of(127), [0x30, 0x7f]);
+        assert_eq!(header_of(128), [0x30, 0x80, 0x01]);
+        assert_eq!(header_of(16_383), [0x30, 0xff, 0x7f]);
+        assert_eq!(header_of(16_384), [0x30, 0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn strings_are_prefixed_with_their_byte_length() {
+        let mut buf = Vec::new();
+        put_mqtt_string(&mut buf, "MQTT");
+        put_mqtt_string(&mut buf, "");
+        put_mqtt_string(&mut buf, "é");
+        assert_eq!(buf, [0, 4, b'M', b'Q', b'T', b'T', 0, 0, 0, 2, 0xc3, 0xa9]);
+    }
+
+    /// Records what it was sent and fails on one path.
+    struct RecordingSink {
+        sent: Vec<i64>,
+        fail_on: Option<&'static str>,
+    }
+
+    impl EventSink for RecordingSink {
+        async fn send(&mut self, change: &SlotChange) -> Result<()> {
+            if self.fail_on == Some(change.path.as_str()) {
+                return Err(RimError::Http("target down".to_string()));
+            }
+            self.sent.push(change.seq);
+            Ok(())
+        }
+    }
+
+    fn change(seq: i64, path: &str) -> SlotChange {
+        SlotChange {
+            seq,
+            path: path.to_string(),
+            kind: ChangeKind::Create,
+            generation: 1,
+            head_sha256: String::new(),
+            recorded_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn cursor_moves_past_acknowledged_and_skipped_changes_only() {
+        let rule: NotificationRule = serde_json::from_value(serde_json::json!({
+            "name": "images",
+            "prefix": "images/",
+            "target": { "type": "webhook", "url": "http://127.0.0.1:9/" },
+        }))
+        .unwrap();
+        let changes = [
+            change(1, "logs/a"),
+            change(2, "images/a"),
+            change(3, "logs/b"),
+            change(4, "images/b"),
+            change(5, "logs/c"),
+        ];
+
+        let mut sink = RecordingSink {
+            sent: Vec::new(),
+            fail_on: None,
+        };
+        let mut advanced = Vec::new();
+        let delivered = deliver(&rule, &changes, &mut sink, |seq| {
+            advanced.push(seq);
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(delivered, 2);
+        assert_eq!(sink.sent, [2, 4]);
+        assert_eq!(advanced, [2, 4, 5]);
+
+        let mut sink = RecordingSink {
+            sent: Vec::new(),
+            fail_on: Some("images/b"),
+        };
+        let mut advanced = Vec::new();
+        let failed = deliver(&rule, &changes, &mut sink, |seq| {
+            advanced.push(seq);
+            Ok(())
+        })
+        .await;
+        assert!(failed.is_err());
+        assert_eq!(sink.sent, [2]);
+        assert_eq!(advanced, [2]);
+    }
+}
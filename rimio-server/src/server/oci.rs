@@ -0,0 +1,1257 @@
+//! OCI distribution API (`/v2/`) over blob paths.
+//!
+//! Layers and manifests are stored once per digest under
+//! `<prefix>/blobs/sha256/<hex>`, whichever repository pushed them, so an
+//! image pulled through several names takes the space of one. Each
+//! repository keeps its tags and the manifests it references under
+//! `<prefix>/repositories/{<name>}/`; the hash tag keeps them in one slot so
+//! a tag listing is one local read. Uploads are checked against their digest
+//! while they stream, and a blob whose digest does not match is never
+//! committed.
+
+use super::external::parse_range_header;
+use super::{
+    OciError, OciErrorResponse, OciQuery, OciTagList, RoutingHints, ServerState, claim_write_lease,
+    range_not_satisfiable_response, redirect_location, refuse_frozen_write,
+    refuse_unarchived_write, resolve_replica_nodes, route_blob_request,
+};
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{OriginalUri, Path, Query, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+};
+use futures_util::stream::{self, BoxStream};
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use rimio_core::{
+    ContentHeaders, DeleteBlobOperationOutcome, DeleteBlobOperationRequest,
+    ListBlobsOperationRequest, ListOrder, PutBlobOperationOutcome, PutBlobStreamRequest,
+    ReadBlobOperationOutcome, ReadBlobOperationRequest, ReadBlobOperationResult,
+    ReadBlobStreamOutcome, Result, RimError, SlotTraffic, UploadSession, WriteConsistency,
+    compute_hash,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+const API_VERSION_HEADER: &str = "docker-distribution-api-version";
+const CONTENT_DIGEST_HEADER: &str = "docker-content-digest";
+const UPLOAD_UUID_HEADER: &str = "docker-upload-uuid";
+const MANIFEST_MAX_BYTES: usize = 4 * 1024 * 1024;
+const DEFAULT_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+/// Upload sessions left alone for this long are dropped; their chunks stay
+/// under `<prefix>/uploads/` until a lifecycle rule removes them.
+const UPLOAD_TTL_SECS: i64 = 24 * 3600;
+const MAX_TAG_PAGE: usize = 1000;
+
+/// A blob upload in progress. The session lives in the node store of the
+/// node that started it, like a multipart upload; every chunk is a blob of
+/// its own until the upload is finished.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OciUpload {
+    repository: String,
+    chunks: Vec<OciChunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OciChunk {
+    path: String,
+    size_bytes: u64,
+}
+
+impl OciUpload {
+    fn size_bytes(&self) -> u64 {
+        self.chunks.iter().map(|chunk| chunk.size_bytes).sum()
+    }
+}
+
+enum OciRoute<'a> {
+    Blob { name: &'a str, digest: &'a str },
+    Uploads { name: &'a str },
+    Upload { name: &'a str, upload_id: &'a str },
+    Manifest { name: &'a str, reference: &'a str },
+    Tags { name: &'a str },
+}
+
+/// `GET /v2/`: tells clients this endpoint speaks the distribution API.
+pub(crate) async fn oci_version_check(State(state): State<Arc<ServerState>>) -> Response {
+    if state.config.oci.is_none() {
+        return oci_disabled();
+    }
+    with_api_version(Json(serde_json::json!({})).into_response())
+}
+
+/// Every other `/v2/...` request, routed by the repository-relative suffix.
+pub(crate) async fn oci_request(
+    State(state): State<Arc<ServerState>>,
+    Path(rest): Path<String>,
+    method: Method,
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<OciQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    if state.config.oci.is_none() {
+        return oci_disabled();
+    }
+    let prefix = oci_prefix(&state);
+    let Some(route) = parse_route(&rest) else {
+        return oci_error(StatusCode::NOT_FOUND, "NAME_UNKNOWN", "unknown endpoint");
+    };
+
+    let response = match (route, method) {
+        (OciRoute::Blob { digest, .. }, method)
+            if method == Method::GET || method == Method::HEAD =>
+        {
+            get_blob(&state, prefix, digest, method == Method::HEAD, &headers).await
+        }
+        (OciRoute::Uploads { name }, Method::POST) => {
+            start_upload(&state, prefix, name, query, &uri, &headers, body).await
+        }
+        (OciRoute::Upload { name, upload_id }, Method::GET) => {
+            match load_upload(&state, name, upload_id) {
+                Ok((_, upload)) => {
+                    upload_progress(StatusCode::NO_CONTENT, name, upload_id, &upload)
+                }
+                Err(response) => *response,
+            }
+        }
+        (OciRoute::Upload { name, upload_id }, Method::PATCH) => {
+            append_chunk(&state, prefix, name, upload_id, &uri, &headers, body).await
+        }
+        (OciRoute::Upload { name, upload_id }, Method::PUT) => {
+            finish_upload(&state, name, upload_id, query, &uri, &headers, body).await
+        }
+        (OciRoute::Upload { name, upload_id }, Method::DELETE) => {
+            cancel_upload(&state, name, upload_id, &uri, &headers).await
+        }
+        (OciRoute::Manifest { name, reference }, method)
+            if method == Method::GET || method == Method::HEAD =>
+        {
+            get_manifest(
+                &state,
+                prefix,
+                name,
+                reference,
+                method == Method::HEAD,
+                &headers,
+            )
+            .await
+        }
+        (OciRoute::Manifest { name, reference }, Method::PUT) => {
+            put_manifest(&state, prefix, name, reference, &uri, &headers, body).await
+        }
+        (OciRoute::Manifest { name, reference }, Method::DELETE) => {
+            delete_manifest(&state, prefix, name, reference, &uri, &headers).await
+        }
+        (OciRoute::Tags { name }, Method::GET) => {
+            list_tags(&state, prefix, name, query, &uri, &headers).await
+        }
+        _ => oci_error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "UNSUPPORTED",
+            "the operation is unsupported",
+        ),
+    };
+    with_api_version(response)
+}
+
+fn parse_route(rest: &str) -> Option<OciRoute<'_>> {
+    let rest = rest.trim_start_matches('/');
+    let route = if let Some(name) = rest.strip_suffix("/tags/list") {
+        OciRoute::Tags { name }
+    } else if let Some(index) = rest.rfind("/blobs/uploads") {
+        let name = &rest[..index];
+        match rest[index + "/blobs/uploads".len()..].split_once('/') {
+            None => OciRoute::Uploads { name },
+            Some(("", "")) => OciRoute::Uploads { name },
+            Some(("", upload_id)) if !upload_id.contains('/') => {
+                OciRoute::Upload { name, upload_id }
+            }
+            _ => return None,
+        }
+    } else if let Some(index) = rest.rfind("/manifests/") {
+        OciRoute::Manifest {
+            name: &rest[..index],
+            reference: &rest[index + "/manifests/".len()..],
+        }
+    } else if let Some(index) = rest.rfind("/blobs/") {
+        OciRoute::Blob {
+            name: &rest[..index],
+            digest: &rest[index + "/blobs/".len()..],
+        }
+    } else {
+        return None;
+    };
+
+    let name = match &route {
+        OciRoute::Blob { name, .. }
+        | OciRoute::Uploads { name }
+        | OciRoute::Upload { name, .. }
+        | OciRoute::Manifest { name, .. }
+        | OciRoute::Tags { name } => *name,
+    };
+    valid_repository_name(name).then_some(route)
+}
+
+/// `[a-z0-9]+([._-][a-z0-9]+)*` components joined by `/`.
+fn valid_repository_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('/').all(|component| {
+            component
+                .split(['.', '_', '-'])
+                .all(|part| !part.is_empty())
+                && component
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c))
+        })
+}
+
+fn valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= 128
+        && !tag.starts_with(['.', '-'])
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+}
+
+/// The hex part of a `sha256:` digest; other algorithms are refused.
+fn digest_hex(digest: &str) -> std::result::Result<&str, Box<Response>> {
+    match digest.strip_prefix("sha256:") {
+        Some(hex)
+            if hex.len() == 64
+                && hex
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c)) =>
+        {
+            Ok(hex)
+        }
+        _ => Err(Box::new(oci_error(
+            StatusCode::BAD_REQUEST,
+            "DIGEST_INVALID",
+            format!("unsupported or malformed digest '{}'", digest),
+        ))),
+    }
+}
+
+/// Where the OCI paths live; `oci` when the API is not configured.
+fn oci_prefix(state: &ServerState) -> &str {
+    state
+        .config
+        .oci
+        .as_ref()
+        .map_or("oci", |oci| oci.prefix.trim_matches('/'))
+}
+
+fn blob_path(prefix: &str, hex: &str) -> String {
+    format!("{}/blobs/sha256/{}", prefix, hex)
+}
+
+fn manifest_link_path(prefix: &str, name: &str, hex: &str) -> String {
+    format!(
+        "{}/repositories/{{{}}}/manifests/sha256/{}",
+        prefix, name, hex
+    )
+}
+
+fn tags_dir(prefix: &str, name: &str) -> String {
+    format!("{}/repositories/{{{}}}/tags/", prefix, name)
+}
+
+fn upload_dir(prefix: &str, upload_id: &str) -> String {
+    format!("{}/uploads/{{{}}}", prefix, upload_id)
+}
+
+/// Answers `GET`/`HEAD` for a layer or config blob.
+async fn get_blob(
+    state: &ServerState,
+    prefix: &str,
+    digest: &str,
+    head_only: bool,
+    headers: &HeaderMap,
+) -> Response {
+    let hex = match digest_hex(digest) {
+        Ok(hex) => hex,
+        Err(response) => return *response,
+    };
+    serve_object(
+        state,
+        blob_path(prefix, hex),
+        digest,
+        head_only,
+        headers,
+        "BLOB_UNKNOWN",
+    )
+    .await
+}
+
+async fn get_manifest(
+    state: &ServerState,
+    prefix: &str,
+    name: &str,
+    reference: &str,
+    head_only: bool,
+    headers: &HeaderMap,
+) -> Response {
+    let digest = match resolve_manifest(state, prefix, name, reference).await {
+        Ok(Some(digest)) => digest,
+        Ok(None) => {
+            return oci_error(
+                StatusCode::NOT_FOUND,
+                "MANIFEST_UNKNOWN",
+                format!("manifest unknown: {}:{}", name, reference),
+            );
+        }
+        Err(response) => return response,
+    };
+    let hex = match digest_hex(&digest) {
+        Ok(hex) => hex,
+        Err(response) => return *response,
+    };
+    serve_object(
+        state,
+        blob_path(prefix, hex),
+        &digest,
+        head_only,
+        headers,
+        "MANIFEST_UNKNOWN",
+    )
+    .await
+}
+
+/// The digest a tag points at, or the digest itself when the repository
+/// references it.
+async fn resolve_manifest(
+    state: &ServerState,
+    prefix: &str,
+    name: &str,
+    reference: &str,
+) -> std::result::Result<Option<String>, Response> {
+    if reference.starts_with("sha256:") {
+        let hex = digest_hex(reference).map_err(|response| *response)?;
+        let link = read_object(state, manifest_link_path(prefix, name, hex), false).await?;
+        return Ok(link.map(|_| reference.to_string()));
+    }
+    if !valid_tag(reference) {
+        return Err(oci_error(
+            StatusCode::BAD_REQUEST,
+            "TAG_INVALID",
+            format!("invalid tag '{}'", reference),
+        ));
+    }
+
+    let tag = read_object(
+        state,
+        format!("{}{}", tags_dir(prefix, name), reference),
+        true,
+    )
+    .await?;
+    Ok(tag
+        .and_then(|tag| tag.body)
+        .map(|body| String::from_utf8_lossy(&body).trim().to_string()))
+}
+
+async fn put_manifest(
+    state: &ServerState,
+    prefix: &str,
+    name: &str,
+    reference: &str,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Body,
+) -> Response {
+    let body = match axum::body::to_bytes(body, MANIFEST_MAX_BYTES).await {
+        Ok(body) => body,
+        Err(error) => {
+            return oci_error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "SIZE_INVALID",
+                error.to_string(),
+            );
+        }
+    };
+    let hex = compute_hash(&body);
+    let digest = format!("sha256:{}", hex);
+    if reference.starts_with("sha256:") {
+        if reference != digest {
+            return oci_error(
+                StatusCode::BAD_REQUEST,
+                "DIGEST_INVALID",
+                format!("manifest digest is {}, not {}", digest, reference),
+            );
+        }
+    } else if !valid_tag(reference) {
+        return oci_error(
+            StatusCode::BAD_REQUEST,
+            "TAG_INVALID",
+            format!("invalid tag '{}'", reference),
+        );
+    }
+    let media_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| DEFAULT_MANIFEST_MEDIA_TYPE.to_string());
+
+    let path = blob_path(prefix, &hex);
+    let stored = match read_object(state, path.clone(), false).await {
+        Ok(stored) => stored,
+        Err(response) => return response,
+    };
+    if stored.is_none()
+        && let Err(response) =
+            write_object(state, path, one_chunk(body), Some(media_type), uri, headers).await
+    {
+        return response;
+    }
+
+    let link = Bytes::from(digest.clone());
+    let mut links = vec![manifest_link_path(prefix, name, &hex)];
+    if !reference.starts_with("sha256:") {
+        links.push(format!("{}{}", tags_dir(prefix, name), reference));
+    }
+    for link_path in links {
+        if let Err(response) = write_object(
+            state,
+            link_path,
+            one_chunk(link.clone()),
+            None,
+            uri,
+            headers,
+        )
+        .await
+        {
+            return response;
+        }
+    }
+
+    created(format!("/v2/{}/manifests/{}", name, digest), &digest)
+}
+
+async fn delete_manifest(
+    state: &ServerState,
+    prefix: &str,
+    name: &str,
+    reference: &str,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    let path = if reference.starts_with("sha256:") {
+        match digest_hex(reference) {
+            Ok(hex) => manifest_link_path(prefix, name, hex),
+            Err(response) => return *response,
+        }
+    } else if valid_tag(reference) {
+        format!("{}{}", tags_dir(prefix, name), reference)
+    } else {
+        return oci_error(
+            StatusCode::BAD_REQUEST,
+            "TAG_INVALID",
+            format!("invalid tag '{}'", reference),
+        );
+    };
+
+    match read_object(state, path.clone(), false).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return oci_error(
+                StatusCode::NOT_FOUND,
+                "MANIFEST_UNKNOWN",
+                format!("manifest unknown: {}:{}", name, reference),
+            );
+        }
+        Err(response) => return response,
+    }
+    match delete_object(state, path, uri, headers).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(response) => response,
+    }
+}
+
+async fn list_tags(
+    state: &ServerState,
+    prefix: &str,
+    name: &str,
+    query: OciQuery,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    let dir = tags_dir(prefix, name);
+    let slot_id = state.config.replication.slot_for_key(&dir);
+    let replicas = match resolve_replica_nodes(state, slot_id).await {
+        Ok(replicas) => replicas,
+        Err(error) => {
+            return oci_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "UNKNOWN",
+                error.to_string(),
+            );
+        }
+    };
+    // Listings are local; a node that does not hold the repository's slot
+    // sends the client to one that does.
+    if !replicas
+        .iter()
+        .any(|node| node.node_id == state.node.node_id())
+    {
+        let hints = RoutingHints::from_request(headers, uri);
+        let Some(replica) = replicas.first() else {
+            return oci_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "UNAVAILABLE",
+                format!("no replica holds slot {}", slot_id),
+            );
+        };
+        if hints.via.iter().any(|node| node == state.node.node_id()) {
+            return oci_error(
+                StatusCode::LOOP_DETECTED,
+                "UNAVAILABLE",
+                format!("tag listing bounced between nodes: slot={}", slot_id),
+            );
+        }
+        return match redirect_location(&replica.address, uri, &hints, state.node.node_id()) {
+            Ok(location) => {
+                let mut response = StatusCode::TEMPORARY_REDIRECT.into_response();
+                if let Ok(value) = HeaderValue::from_str(&location) {
+                    response.headers_mut().insert(header::LOCATION, value);
+                }
+                response
+            }
+            Err(error) => oci_error(StatusCode::INTERNAL_SERVER_ERROR, "UNKNOWN", error),
+        };
+    }
+
+    let limit = query.n.unwrap_or(MAX_TAG_PAGE).clamp(1, MAX_TAG_PAGE);
+    let result = state
+        .list_blobs_operation
+        .run(ListBlobsOperationRequest {
+            prefix: dir.clone(),
+            limit,
+            cursor: query.last.as_deref().map(|last| format!("{}{}", dir, last)),
+            include_deleted: false,
+            tags: BTreeMap::new(),
+            order_by: ListOrder::Path,
+            since: None,
+            until: None,
+        })
+        .await;
+    let result = match result {
+        Ok(result) => result,
+        Err(error) => {
+            return oci_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "UNKNOWN",
+                error.to_string(),
+            );
+        }
+    };
+
+    let tags: Vec<String> = result
+        .items
+        .into_iter()
+        .filter_map(|item| item.path.strip_prefix(&dir).map(str::to_string))
+        .collect();
+    let next = result.next_cursor.and(tags.last().cloned());
+    let mut response = Json(OciTagList {
+        name: name.to_string(),
+        tags,
+    })
+    .into_response();
+    if let Some(last) = next {
+        let link = format!(
+            "</v2/{}/tags/list?n={}&last={}>; rel=\"next\"",
+            name, limit, last
+        );
+        if let Ok(value) = HeaderValue::from_str(&link) {
+            response.headers_mut().insert(header::LINK, value);
+        }
+    }
+    response
+}
+
+/// `POST .../blobs/uploads/`: mounts a blob that is already stored, takes a
+/// whole blob at once with `?digest=`, or opens an upload session.
+async fn start_upload(
+    state: &Arc<ServerState>,
+    prefix: &str,
+    name: &str,
+    query: OciQuery,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Body,
+) -> Response {
+    if let Some(mount) = query.mount.as_deref() {
+        let hex = match digest_hex(mount) {
+            Ok(hex) => hex,
+            Err(response) => return *response,
+        };
+        match read_object(state, blob_path(prefix, hex), false).await {
+            Ok(Some(_)) => return created(format!("/v2/{}/blobs/{}", name, mount), mount),
+            Ok(None) => {}
+            Err(response) => return response,
+        }
+    }
+    if let Some(digest) = query.digest.as_deref() {
+        return store_blob(state, name, digest, &[], body, uri, headers).await;
+    }
+
+    let upload_id = format!("oci-{}", ulid::Ulid::new());
+    let upload = OciUpload {
+        repository: name.to_string(),
+        chunks: Vec::new(),
+    };
+    let now = chrono::Utc::now();
+    if let Err(response) = save_upload(state, prefix, &upload_id, &upload, now) {
+        return *response;
+    }
+    upload_progress(StatusCode::ACCEPTED, name, &upload_id, &upload)
+}
+
+/// `PATCH .../blobs/uploads/<id>`: stores the request body as the next chunk.
+async fn append_chunk(
+    state: &ServerState,
+    prefix: &str,
+    name: &str,
+    upload_id: &str,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Body,
+) -> Response {
+    let (session, mut upload) = match load_upload(state, name, upload_id) {
+        Ok(loaded) => loaded,
+        Err(response) => return *response,
+    };
+    let offset = upload.size_bytes();
+    if let Some(start) = headers
+        .get(header::CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|range| range.split('-').next())
+        .and_then(|start| start.trim().parse::<u64>().ok())
+        && start != offset
+    {
+        let mut response = oci_error(
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            "BLOB_UPLOAD_INVALID",
+            format!("chunk starts at {}, upload is at {}", start, offset),
+        );
+        insert_header(&mut response, header::RANGE.as_str(), &upload_range(offset));
+        return response;
+    }
+
+    let path = format!(
+        "{}/{:05}",
+        upload_dir(prefix, upload_id),
+        upload.chunks.len()
+    );
+    let body = body
+        .into_data_stream()
+        .map_err(|error| RimError::Http(error.to_string()));
+    let size_bytes = match write_object(state, path.clone(), body, None, uri, headers).await {
+        Ok(size_bytes) => size_bytes,
+        Err(response) => return response,
+    };
+    upload.chunks.push(OciChunk { path, size_bytes });
+    if let Err(response) = save_upload(state, prefix, upload_id, &upload, session.created_at) {
+        return *response;
+    }
+    upload_progress(StatusCode::ACCEPTED, name, upload_id, &upload)
+}
+
+/// `PUT .../blobs/uploads/<id>?digest=`: joins the chunks and the request
+/// body into the blob.
+async fn finish_upload(
+    state: &Arc<ServerState>,
+    name: &str,
+    upload_id: &str,
+    query: OciQuery,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Body,
+) -> Response {
+    let Some(digest) = query.digest.as_deref() else {
+        return oci_error(
+            StatusCode::BAD_REQUEST,
+            "DIGEST_INVALID",
+            "digest is required to finish an upload",
+        );
+    };
+    let (_, upload) = match load_upload(state, name, upload_id) {
+        Ok(loaded) => loaded,
+        Err(response) => return *response,
+    };
+
+    let response = store_blob(state, name, digest, &upload.chunks, body, uri, headers).await;
+    if response.status() == StatusCode::CREATED {
+        discard_upload(state, upload_id, &upload, uri, headers).await;
+    }
+    response
+}
+
+async fn cancel_upload(
+    state: &ServerState,
+    name: &str,
+    upload_id: &str,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    let (_, upload) = match load_upload(state, name, upload_id) {
+        Ok(loaded) => loaded,
+        Err(response) => return *response,
+    };
+    discard_upload(state, upload_id, &upload, uri, headers).await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Writes `chunks` followed by `tail` to the blob of `digest`, unless a blob
+/// with that digest is already stored.
+async fn store_blob(
+    state: &Arc<ServerState>,
+    name: &str,
+    digest: &str,
+    chunks: &[OciChunk],
+    tail: Body,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    let hex = match digest_hex(digest) {
+        Ok(hex) => hex,
+        Err(response) => return *response,
+    };
+    let path = blob_path(oci_prefix(state), hex);
+    let stored = match read_object(state, path.clone(), false).await {
+        Ok(stored) => stored,
+        Err(response) => return response,
+    };
+
+    if stored.is_none() {
+        let chunk_paths: Vec<String> = chunks.iter().map(|chunk| chunk.path.clone()).collect();
+        let body = chunk_bodies(state, chunk_paths).chain(
+            tail.into_data_stream()
+                .map_err(|error| RimError::Http(error.to_string())),
+        );
+        if let Err(response) = write_object(
+            state,
+            path,
+            verify_digest(body, hex.to_string()),
+            None,
+            uri,
+            headers,
+        )
+        .await
+        {
+            return response;
+        }
+    }
+
+    created(format!("/v2/{}/blobs/{}", name, digest), digest)
+}
+
+/// The stored chunks one after another, each opened when the previous one
+/// ran out.
+fn chunk_bodies(
+    state: &Arc<ServerState>,
+    paths: Vec<String>,
+) -> impl Stream<Item = Result<Bytes>> + Send + 'static {
+    let state = state.clone();
+    stream::iter(paths)
+        .then(move |path| {
+            let state = state.clone();
+            async move { open_chunk(&state, path).await }
+        })
+        .try_flatten()
+}
+
+async fn open_chunk(
+    state: &ServerState,
+    path: String,
+) -> Result<BoxStream<'static, Result<Bytes>>> {
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let replicas = resolve_replica_nodes(state, slot_id).await?;
+    let outcome = state
+        .read_blob_operation
+        .run_stream(ReadBlobOperationRequest {
+            slot_id,
+            path: path.clone(),
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
+            include_body: true,
+            range: None,
+            if_range: None,
+            consistency: state.config.replication.read_consistency,
+        })
+        .await?;
+    match outcome {
+        ReadBlobStreamOutcome::Found(found) => Ok(found.body),
+        ReadBlobStreamOutcome::NotFound | ReadBlobStreamOutcome::Deleted => Err(
+            RimError::InvalidRequest(format!("upload chunk is gone: {}", path)),
+        ),
+    }
+}
+
+/// Passes `body` through, failing it at the end when its sha256 is not
+/// `expected`; the failed stream aborts the write before it commits.
+fn verify_digest<S>(body: S, expected: String) -> impl Stream<Item = Result<Bytes>> + Send
+where
+    S: Stream<Item = Result<Bytes>> + Send,
+{
+    let hasher = Arc::new(Mutex::new(Sha256::new()));
+    let feed = hasher.clone();
+    body.inspect_ok(move |chunk| {
+        feed.lock()
+            .expect("digest hasher lock poisoned")
+            .update(chunk)
+    })
+    .chain(
+        stream::once(async move {
+            let hasher = std::mem::take(&mut *hasher.lock().expect("digest hasher lock poisoned"));
+            let actual = hex::encode(hasher.finalize());
+            (actual != expected).then_some(Err(RimError::HashMismatch { expected, actual }))
+        })
+        .filter_map(futures_util::future::ready),
+    )
+}
+
+/// Serves a stored blob with the headers registry clients expect.
+async fn serve_object(
+    state: &ServerState,
+    path: String,
+    digest: &str,
+    head_only: bool,
+    headers: &HeaderMap,
+    unknown_code: &'static str,
+) -> Response {
+    let unknown = || {
+        oci_error(
+            StatusCode::NOT_FOUND,
+            unknown_code,
+            format!("{} is unknown", digest),
+        )
+    };
+    let slot_id = state.config.replication.slot_for_key(&path);
+
+    if head_only {
+        let meta = match read_object(state, path, false).await {
+            Ok(Some(result)) => result.meta,
+            Ok(None) => return unknown(),
+            Err(response) => return response,
+        };
+        let mut response = StatusCode::OK.into_response();
+        insert_content_headers(&mut response, &meta.content, meta.size_bytes, digest);
+        return response;
+    }
+
+    let range = match parse_range_header(headers) {
+        Ok(range) => range,
+        Err(message) => {
+            return oci_error(StatusCode::RANGE_NOT_SATISFIABLE, "RANGE_INVALID", message);
+        }
+    };
+    let replicas = match resolve_replica_nodes(state, slot_id).await {
+        Ok(replicas) => replicas,
+        Err(error) => return read_error(error),
+    };
+    let outcome = state
+        .read_blob_operation
+        .run_stream(ReadBlobOperationRequest {
+            slot_id,
+            path,
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
+            include_body: true,
+            range,
+            if_range: None,
+            consistency: state.config.replication.read_consistency,
+        })
+        .await;
+    let found = match outcome {
+        Ok(ReadBlobStreamOutcome::Found(found)) => found,
+        Ok(ReadBlobStreamOutcome::NotFound) | Ok(ReadBlobStreamOutcome::Deleted) => {
+            return unknown();
+        }
+        Err(error) => return read_error(error),
+    };
+
+    let body_len = found
+        .body_range
+        .map(|range| range.end - range.start + 1)
+        .unwrap_or_default();
+    state.slot_heat.record(slot_id, SlotTraffic::Read, body_len);
+    let mut response = Response::new(Body::from_stream(found.body));
+    insert_content_headers(&mut response, &found.meta.content, body_len, digest);
+    if found.partial
+        && let Some(range) = found.body_range
+    {
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+        insert_header(
+            &mut response,
+            header::CONTENT_RANGE.as_str(),
+            &format!(
+                "bytes {}-{}/{}",
+                range.start, range.end, found.meta.size_bytes
+            ),
+        );
+    }
+    response
+}
+
+fn insert_content_headers(
+    response: &mut Response,
+    content: &ContentHeaders,
+    content_length: u64,
+    digest: &str,
+) {
+    insert_header(
+        response,
+        header::CONTENT_TYPE.as_str(),
+        content
+            .content_type
+            .as_deref()
+            .unwrap_or("application/octet-stream"),
+    );
+    insert_header(
+        response,
+        header::CONTENT_LENGTH.as_str(),
+        &content_length.to_string(),
+    );
+    insert_header(response, header::ACCEPT_RANGES.as_str(), "bytes");
+    insert_header(response, CONTENT_DIGEST_HEADER, digest);
+}
+
+/// The current version of `path`; `None` when it is missing or deleted.
+async fn read_object(
+    state: &ServerState,
+    path: String,
+    include_body: bool,
+) -> std::result::Result<Option<ReadBlobOperationResult>, Response> {
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let replicas = resolve_replica_nodes(state, slot_id)
+        .await
+        .map_err(read_error)?;
+    let outcome = state
+        .read_blob_operation
+        .run(ReadBlobOperationRequest {
+            slot_id,
+            path,
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
+            include_body,
+            range: None,
+            if_range: None,
+            consistency: state.config.replication.read_consistency,
+        })
+        .await;
+    match outcome {
+        Ok(ReadBlobOperationOutcome::Found(result)) => Ok(Some(*result)),
+        Ok(ReadBlobOperationOutcome::NotFound) | Ok(ReadBlobOperationOutcome::Deleted) => Ok(None),
+        Err(error) => Err(read_error(error)),
+    }
+}
+
+/// Writes `body` to `path` at quorum and returns its size.
+async fn write_object<S>(
+    state: &ServerState,
+    path: String,
+    body: S,
+    content_type: Option<String>,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> std::result::Result<u64, Response>
+where
+    S: Stream<Item = Result<Bytes>> + Send,
+{
+    if let Some(response) = refuse_unarchived_write(state).await {
+        return Err(response);
+    }
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let hints = RoutingHints::from_request(headers, uri);
+    let replicas = route_blob_request(state, slot_id, &hints).await?;
+    if let Some(response) = refuse_frozen_write(state, slot_id).await {
+        return Err(response);
+    }
+    let _write_guard = claim_write_lease(state, slot_id, &replicas, uri, &hints).await?;
+
+    let outcome = state
+        .put_blob_operation
+        .run_stream(
+            PutBlobStreamRequest {
+                path,
+                slot_id,
+                replicas,
+                local_node_id: state.node.node_id().to_string(),
+                consistency: WriteConsistency::Quorum,
+                expected_generation: None,
+                metadata: BTreeMap::new(),
+                tags: BTreeMap::new(),
+                content: ContentHeaders {
+                    content_type,
+                    ..ContentHeaders::default()
+                },
+            },
+            body,
+        )
+        .await;
+    match outcome {
+        Ok(PutBlobOperationOutcome::Committed(result)) => {
+            state
+                .slot_heat
+                .record(slot_id, SlotTraffic::Write, result.size_bytes);
+            Ok(result.size_bytes)
+        }
+        Ok(PutBlobOperationOutcome::Conflict)
+        | Ok(PutBlobOperationOutcome::PreconditionFailed { .. }) => Err(oci_error(
+            StatusCode::CONFLICT,
+            "UNKNOWN",
+            "meta commit rejected by generation check",
+        )),
+        Err(RimError::HashMismatch { expected, actual }) => Err(oci_error(
+            StatusCode::BAD_REQUEST,
+            "DIGEST_INVALID",
+            format!(
+                "content digest is sha256:{}, not sha256:{}",
+                actual, expected
+            ),
+        )),
+        Err(RimError::InvalidRequest(message)) => Err(oci_error(
+            StatusCode::BAD_REQUEST,
+            "BLOB_UPLOAD_INVALID",
+            message,
+        )),
+        Err(error) => Err(write_error(error)),
+    }
+}
+
+async fn delete_object(
+    state: &ServerState,
+    path: String,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> std::result::Result<(), Response> {
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let hints = RoutingHints::from_request(headers, uri);
+    let replicas = route_blob_request(state, slot_id, &hints).await?;
+    if let Some(response) = refuse_frozen_write(state, slot_id).await {
+        return Err(response);
+    }
+    let _write_guard = claim_write_lease(state, slot_id, &replicas, uri, &hints).await?;
+
+    let outcome = state
+        .delete_blob_operation
+        .run(DeleteBlobOperationRequest {
+            path,
+            slot_id,
+            write_id: format!("oci-delete-{}", ulid::Ulid::new()),
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
+            expected_generation: None,
+        })
+        .await;
+    match outcome {
+        Ok(DeleteBlobOperationOutcome::Committed(_)) => {
+            state.slot_heat.record(slot_id, SlotTraffic::Write, 0);
+            Ok(())
+        }
+        Ok(
+            DeleteBlobOperationOutcome::Conflict
+            | DeleteBlobOperationOutcome::PreconditionFailed { .. },
+        ) => Err(oci_error(
+            StatusCode::CONFLICT,
+            "UNKNOWN",
+            "tombstone commit rejected by generation check",
+        )),
+        Err(error) => Err(write_error(error)),
+    }
+}
+
+fn one_chunk(body: Bytes) -> impl Stream<Item = Result<Bytes>> + Send {
+    stream::iter([Ok(body)])
+}
+
+fn read_error(error: RimError) -> Response {
+    match error {
+        RimError::RangeNotSatisfiable { size_bytes } => range_not_satisfiable_response(size_bytes),
+        RimError::InsufficientReplicas { required, found } => oci_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "UNAVAILABLE",
+            format!(
+                "read quorum not reached: required={}, found={}",
+                required, found
+            ),
+        ),
+        RimError::ArchiveUnavailable { message, .. } => {
+            oci_error(StatusCode::SERVICE_UNAVAILABLE, "UNAVAILABLE", message)
+        }
+        error => oci_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "UNKNOWN",
+            error.to_string(),
+        ),
+    }
+}
+
+fn write_error(error: RimError) -> Response {
+    match error {
+        RimError::InsufficientReplicas { required, found } => oci_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "UNAVAILABLE",
+            format!(
+                "quorum not reached: required={}, committed={}",
+                required, found
+            ),
+        ),
+        error => oci_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "UNKNOWN",
+            error.to_string(),
+        ),
+    }
+}
+
+/// The session of `upload_id`, if it is an upload to repository `name`.
+fn load_upload(
+    state: &ServerState,
+    name: &str,
+    upload_id: &str,
+) -> std::result::Result<(UploadSession, OciUpload), Box<Response>> {
+    let session = state
+        .node_store
+        .get_upload_session(upload_id)
+        .map_err(|error| {
+            Box::new(oci_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "UNKNOWN",
+                error.to_string(),
+            ))
+        })?;
+    let upload = session
+        .as_ref()
+        .and_then(|session| serde_json::from_value::<OciUpload>(session.payload.clone()).ok());
+    match (session, upload) {
+        (Some(session), Some(upload)) if upload.repository == name => Ok((session, upload)),
+        _ => Err(Box::new(oci_error(
+            StatusCode::NOT_FOUND,
+            "BLOB_UPLOAD_UNKNOWN",
+            format!("upload unknown: {}", upload_id),
+        ))),
+    }
+}
+
+fn save_upload(
+    state: &ServerState,
+    prefix: &str,
+    upload_id: &str,
+    upload: &OciUpload,
+    created_at: chrono::DateTime<chrono::Utc>,
+) -> std::result::Result<(), Box<Response>> {
+    let blob_path = upload_dir(prefix, upload_id);
+    let session = UploadSession {
+        upload_id: upload_id.to_string(),
+        slot_id: state.config.replication.slot_for_key(&blob_path),
+        blob_path,
+        payload: serde_json::to_value(upload).unwrap_or_default(),
+        created_at,
+        expires_at: chrono::Utc::now() + chrono::Duration::seconds(UPLOAD_TTL_SECS),
+    };
+    state
+        .node_store
+        .save_upload_session(&session)
+        .map_err(|error| {
+            Box::new(oci_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "UNKNOWN",
+                error.to_string(),
+            ))
+        })
+}
+
+/// Drops the chunks and the session of a finished or cancelled upload.
+async fn discard_upload(
+    state: &ServerState,
+    upload_id: &str,
+    upload: &OciUpload,
+    uri: &Uri,
+    headers: &HeaderMap,
+) {
+    for chunk in &upload.chunks {
+        if delete_object(state, chunk.path.clone(), uri, headers)
+            .await
+            .is_err()
+        {
+            tracing::warn!("Failed to delete OCI upload chunk: {}", chunk.path);
+        }
+    }
+    if let Err(error) = state.node_store.delete_upload_session(upload_id) {
+        tracing::warn!(
+            "Failed to delete OCI upload session {}: {}",
+            upload_id,
+            error
+        );
+    }
+}
+
+fn upload_progress(
+    status: StatusCode,
+    name: &str,
+    upload_id: &str,
+    upload: &OciUpload,
+) -> Response {
+    let mut response = status.into_response();
+    insert_header(
+        &mut response,
+        header::LOCATION.as_str(),
+        &format!("/v2/{}/blobs/uploads/{}", name, upload_id),
+    );
+    insert_header(
+        &mut response,
+        header::RANGE.as_str(),
+        &upload_range(upload.size_bytes()),
+    );
+    insert_header(&mut response, UPLOAD_UUID_HEADER, upload_id);
+    response
+}
+
+/// The `Range` of the bytes received so far, inclusive as the spec has it.
+fn upload_range(size_bytes: u64) -> String {
+    format!("0-{}", size_bytes.saturating_sub(1))
+}
+
+fn created(location: String, digest: &str) -> Response {
+    let mut response = StatusCode::CREATED.into_response();
+    insert_header(&mut response, header::LOCATION.as_str(), &location);
+    insert_header(&mut response, CONTENT_DIGEST_HEADER, digest);
+    response
+}
+
+fn oci_error(status: StatusCode, code: &'static str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(OciErrorResponse {
+            errors: vec![OciError {
+                code,
+                message: message.into(),
+            }],
+        }),
+    )
+        .into_response()
+}
+
+fn oci_disabled() -> Response {
+    oci_error(
+        StatusCode::NOT_FOUND,
+        "UNSUPPORTED",
+        "the OCI distribution API is not enabled",
+    )
+}
+
+fn with_api_version(mut response: Response) -> Response {
+    insert_header(&mut response, API_VERSION_HEADER, "registry/2.0");
+    response
+}
+
+fn insert_header(response: &mut Response, name: &'static str, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        response.headers_mut().insert(name, value);
+    }
+}
@@ -0,0 +1,568 @@
+//! Writes blob listings as Parquet for analytics tools.
+//!
+//! Only what a listing needs: required columns, `PLAIN` encoding, no
+//! compression and one data page per column chunk. Each listing page becomes
+//! a row group written as soon as it is read, and the footer follows the last
+//! one, so a response streams without holding the whole listing. The tests
+//! read the output back with the `parquet` crate, which is the contract this
+//! encoder keeps.
+
+use axum::body::Bytes;
+use rimio_core::ListBlobItem;
+
+const MAGIC: &[u8] = b"PAR1";
+
+// Parquet physical types.
+const TYPE_BOOLEAN: i32 = 0;
+const TYPE_INT64: i32 = 2;
+const TYPE_BYTE_ARRAY: i32 = 6;
+
+// Converted types.
+const CONVERTED_UTF8: i32 = 0;
+const CONVERTED_TIMESTAMP_MILLIS: i32 = 9;
+
+const REPETITION_REQUIRED: i32 = 0;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_DATA: i32 = 0;
+
+// Thrift compact protocol field types.
+const COMPACT_I32: u8 = 5;
+const COMPACT_I64: u8 = 6;
+const COMPACT_BINARY: u8 = 8;
+const COMPACT_LIST: u8 = 9;
+const COMPACT_STRUCT: u8 = 12;
+
+struct Column {
+    name: &'static str,
+    physical_type: i32,
+    converted_type: Option<i32>,
+}
+
+/// The columns of a listing; `tags` holds the tag set as a JSON object.
+const COLUMNS: [Column; 7] = [
+    Column {
+        name: "path",
+        physical_type: TYPE_BYTE_ARRAY,
+        converted_type: Some(CONVERTED_UTF8),
+    },
+    Column {
+        name: "generation",
+        physical_type: TYPE_INT64,
+        converted_type: None,
+    },
+    Column {
+        name: "etag",
+        physical_type: TYPE_BYTE_ARRAY,
+        converted_type: Some(CONVERTED_UTF8),
+    },
+    Column {
+        name: "size_bytes",
+        physical_type: TYPE_INT64,
+        converted_type: None,
+    },
+    Column {
+        name: "deleted",
+        physical_type: TYPE_BOOLEAN,
+        converted_type: None,
+    },
+    Column {
+        name: "updated_at",
+        physical_type: TYPE_INT64,
+        converted_type: Some(CONVERTED_TIMESTAMP_MILLIS),
+    },
+    Column {
+        name: "tags",
+        physical_type: TYPE_BYTE_ARRAY,
+        converted_type: Some(CONVERTED_UTF8),
+    },
+];
+
+/// The column [`ParquetListing::with_replicas`] adds: the replica set of
+/// each blob's slot, separated by `;`.
+const REPLICAS_COLUMN: Column = Column {
+    name: "replicas",
+    physical_type: TYPE_BYTE_ARRAY,
+    converted_type: Some(CONVERTED_UTF8),
+};
+
+/// Where a column chunk landed in the file.
+struct ChunkMeta {
+    data_page_offset: i64,
+    total_size: i64,
+}
+
+struct RowGroupMeta {
+    columns: Vec<ChunkMeta>,
+    num_rows: i64,
+}
+
+/// Turns listing pages into the bytes of one Parquet file, in order: one
+/// [`row_group`](Self::row_group) per page, then [`finish`](Self::finish).
+#[derive(Default)]
+pub(crate) struct ParquetListing {
+    offset: u64,
+    row_groups: Vec<RowGroupMeta>,
+    replicas: bool,
+}
+
+impl ParquetListing {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a trailing `replicas` column, filled by
+    /// [`row_group_with_replicas`](Self::row_group_with_replicas).
+    pub(crate) fn with_replicas(mut self) -> Self {
+        self.replicas = true;
+        self
+    }
+
+    /// The next row group; empty when `items` is.
+    pub(crate) fn row_group(&mut self, items: &[ListBlobItem]) -> Bytes {
+        self.row_group_with_replicas(items, &[])
+    }
+
+    /// [`row_group`](Self::row_group) with the replica set of each item,
+    /// in order, for a listing made [`with_replicas`](Self::with_replicas).
+    pub(crate) fn row_group_with_replicas(
+        &mut self,
+        items: &[ListBlobItem],
+        replicas: &[String],
+    ) -> Bytes {
+        if items.is_empty() {
+            return Bytes::new();
+        }
+        let mut out = self.start();
+        let mut columns = Vec::new();
+        for column in self.columns() {
+            let data = match column.name {
+                "replicas" => string_values(replicas),
+                name => column_values(name, items),
+            };
+            let header = page_header(items.len(), data.len());
+            let total_size = header.len() + data.len();
+            columns.push(ChunkMeta {
+                data_page_offset: (self.offset + out.len() as u64) as i64,
+                total_size: total_size as i64,
+            });
+            out.extend_from_slice(&header);
+            out.extend_from_slice(&data);
+        }
+        self.offset += out.len() as u64;
+        self.row_groups.push(RowGroupMeta {
+            columns,
+            num_rows: items.len() as i64,
+        });
+        Bytes::from(out)
+    }
+
+    /// The footer, which readers start from.
+    pub(crate) fn finish(mut self) -> Bytes {
+        let mut out = self.start();
+        let metadata = self.file_metadata();
+        out.extend_from_slice(&metadata);
+        out.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        out.extend_from_slice(MAGIC);
+        Bytes::from(out)
+    }
+
+    fn columns(&self) -> impl Iterator<Item = &'static Column> + use<> {
+        COLUMNS
+            .iter()
+            .chain(self.replicas.then_some(&REPLICAS_COLUMN))
+    }
+
+    /// The leading magic the first write carries.
+    fn start(&mut self) -> Vec<u8> {
+        if self.offset > 0 {
+            return Vec::new();
+        }
+        MAGIC.to_vec()
+    }
+
+    fn file_metadata(&self) -> Vec<u8> {
+        let mut out = CompactWriter::new();
+        out.i32(1, 1);
+
+        let columns: Vec<&Column> = self.columns().collect();
+        out.list(2, COMPACT_STRUCT, columns.len() + 1);
+        out.begin_element();
+        out.binary(4, b"schema");
+        out.i32(5, columns.len() as i32);
+        out.end_struct();
+        for column in &columns {
+            out.begin_element();
+            out.i32(1, column.physical_type);
+            out.i32(3, REPETITION_REQUIRED);
+            out.binary(4, column.name.as_bytes());
+            if let Some(converted_type) = column.converted_type {
+                out.i32(6, converted_type);
+            }
+            out.end_struct();
+        }
+
+        let num_rows: i64 = self.row_groups.iter().map(|group| group.num_rows).sum();
+        out.i64(3, num_rows);
+
+        out.list(4, COMPACT_STRUCT, self.row_groups.len());
+        for group in &self.row_groups {
+            out.begin_element();
+            out.list(1, COMPACT_STRUCT, group.columns.len());
+            for (column, chunk) in columns.iter().zip(&group.columns) {
+                out.begin_element();
+                out.i64(2, chunk.data_page_offset);
+                out.begin_struct(3);
+                out.i32(1, column.physical_type);
+                out.list(2, COMPACT_I32, 2);
+                out.element_i32(ENCODING_PLAIN);
+                out.element_i32(ENCODING_RLE);
+                out.list(3, COMPACT_BINARY, 1);
+                out.element_binary(column.name.as_bytes());
+                out.i32(4, CODEC_UNCOMPRESSED);
+                out.i64(5, group.num_rows);
+                out.i64(6, chunk.total_size);
+                out.i64(7, chunk.total_size);
+                out.i64(9, chunk.data_page_offset);
+                out.end_struct();
+                out.end_struct();
+            }
+            let total_size: i64 = group.columns.iter().map(|chunk| chunk.total_size).sum();
+            out.i64(2, total_size);
+            out.i64(3, group.num_rows);
+            out.end_struct();
+        }
+
+        out.binary(6, b"rimio");
+        out.finish()
+    }
+}
+
+/// The `PLAIN` encoding of one column of `items`.
+fn column_values(name: &str, items: &[ListBlobItem]) -> Vec<u8> {
+    let mut out = Vec::new();
+    match name {
+        "path" => items
+            .iter()
+            .for_each(|item| put_byte_array(&mut out, item.path.as_bytes())),
+        "generation" => items
+            .iter()
+            .for_each(|item| out.extend_from_slice(&item.generation.to_le_bytes())),
+        "etag" => items
+            .iter()
+            .for_each(|item| put_byte_array(&mut out, item.etag.as_bytes())),
+        "size_bytes" => items
+            .iter()
+            .for_each(|item| out.extend_from_slice(&(item.size_bytes as i64).to_le_bytes())),
+        "deleted" => {
+            // Bit-packed, least significant bit first.
+            out.resize(items.len().div_ceil(8), 0);
+            for (index, item) in items.iter().enumerate() {
+                if item.deleted {
+                    out[index / 8] |= 1 << (index % 8);
+                }
+            }
+        }
+        "updated_at" => items.iter().for_each(|item| {
+            out.extend_from_slice(&item.updated_at.timestamp_millis().to_le_bytes())
+        }),
+        "tags" => items.iter().for_each(|item| {
+            let tags = serde_json::to_string(&item.tags).unwrap_or_default();
+            put_byte_array(&mut out, tags.as_bytes());
+        }),
+        _ => {}
+    }
+    out
+}
+
+fn string_values(values: &[String]) -> Vec<u8> {
+    let mut out = Vec::new();
+    values
+        .iter()
+        .for_each(|value| put_byte_array(&mut out, value.as_bytes()));
+    out
+}
+
+fn put_byte_array(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+/// A version 1 data page header. Required columns carry no repetition or
+/// definition levels, so the page is just the values.
+fn page_header(num_values: usize, size: usize) -> Vec<u8> {
+    let mut out = CompactWriter::new();
+    out.i32(1, PAGE_DATA);
+    out.i32(2, size as i32);
+    out.i32(3, size as i32);
+    out.begin_struct(5);
+    out.i32(1, num_values as i32);
+    out.i32(2, ENCODING_PLAIN);
+    out.i32(3, ENCODING_RLE);
+    out.i32(4, ENCODING_RLE);
+    out.end_struct();
+    out.finish()
+}
+
+/// Just enough of the Thrift compact protocol for Parquet metadata.
+struct CompactWriter {
+    buf: Vec<u8>,
+    /// The last field id written in each struct being written.
+    last_field: Vec<i16>,
+}
+
+impl CompactWriter {
+    fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            last_field: vec![0],
+        }
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self
+            .last_field
+            .last_mut()
+            .expect("compact writer struct stack");
+        let delta = id - std::mem::replace(last, id);
+        if (1..=15).contains(&delta) {
+            self.buf.push(((delta as u8) << 4) | kind);
+        } else {
+            self.buf.push(kind);
+            self.varint(zigzag(id as i64));
+        }
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, COMPACT_I32);
+        self.varint(zigzag(value as i64));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, COMPACT_I64);
+        self.varint(zigzag(value));
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field(id, COMPACT_BINARY);
+        self.element_binary(value);
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, COMPACT_STRUCT);
+        self.last_field.push(0);
+    }
+
+    /// Starts a struct inside a list.
+    fn begin_element(&mut self) {
+        self.last_field.push(0);
+    }
+
+    fn end_struct(&mut self) {
+        self.buf.push(0);
+        self.last_field.pop();
+    }
+
+    fn list(&mut self, id: i16, element: u8, len: usize) {
+        self.field(id, COMPACT_LIST);
+        if len < 15 {
+            self.buf.push(((len as u8) << 4) | element);
+        } else {
+            self.buf.push(0xf0 | element);
+            self.varint(len as u64);
+        }
+    }
+
+    fn element_i32(&mut self, value: i32) {
+        self.varint(zigzag(value as i64));
+    }
+
+    fn element_binary(&mut self, value: &[u8]) {
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    /// Closes the outermost struct.
+    fn finish(mut self) -> Vec<u8> {
+        self.buf.push(0);
+        self.buf
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use parquet::basic::{ConvertedType, Type};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+    use std::collections::BTreeMap;
+
+    fn item(index: usize) -> ListBlobItem {
+        let mut tags = BTreeMap::new();
+        if index.is_multiple_of(2) {
+            tags.insert("team".to_string(), format!("t{}", index));
+        }
+        ListBlobItem {
+            path: format!("logs/{}", index),
+            generation: 10 + index as i64,
+            etag: format!("etag-{}", index),
+            size_bytes: 1000 * index as u64,
+            deleted: index.is_multiple_of(3),
+            updated_at: Utc
+                .timestamp_millis_opt(1_700_000_000_000 + index as i64)
+                .unwrap(),
+            tags,
+        }
+    }
+
+    /// Writes `pages` as row groups followed by the footer.
+    fn write(pages: &[Vec<ListBlobItem>]) -> (Vec<u8>, Vec<Vec<i64>>) {
+        let mut listing = ParquetListing::new();
+        let mut file = Vec::new();
+        for page in pages {
+            file.extend_from_slice(&listing.row_group(page));
+        }
+        let offsets = listing
+            .row_groups
+            .iter()
+            .map(|group| {
+                group
+                    .columns
+                    .iter()
+                    .map(|chunk| chunk.data_page_offset)
+                    .collect()
+            })
+            .collect();
+        file.extend_from_slice(&listing.finish());
+        (file, offsets)
+    }
+
+    #[test]
+    fn file_is_framed_by_magic_and_footer_length() {
+        let (file, _) = write(&[(0..3).map(item).collect()]);
+
+        assert_eq!(&file[..4], MAGIC);
+        assert_eq!(&file[file.len() - 4..], MAGIC);
+        let footer_len =
+            u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap()) as usize;
+        let footer_start = file.len() - 8 - footer_len;
+        let mut listing = ParquetListing::new();
+        let first_group = listing.row_group(&(0..3).map(item).collect::<Vec<_>>());
+        assert_eq!(footer_start, first_group.len());
+    }
+
+    #[test]
+    fn readers_decode_schema_offsets_and_rows() {
+        let pages: Vec<Vec<ListBlobItem>> =
+            vec![(0..9).map(item).collect(), (9..20).map(item).collect()];
+        let (file, offsets) = write(&pages);
+        let reader = SerializedFileReader::new(Bytes::from(file)).unwrap();
+        let metadata = reader.metadata();
+
+        assert_eq!(metadata.file_metadata().num_rows(), 20);
+        let schema = metadata.file_metadata().schema_descr();
+        let columns: Vec<(&str, Type, ConvertedType)> = schema
+            .columns()
+            .iter()
+            .map(|column| {
+                (
+                    column.name(),
+                    column.physical_type(),
+                    column.converted_type(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                ("path", Type::BYTE_ARRAY, ConvertedType::UTF8),
+                ("generation", Type::INT64, ConvertedType::NONE),
+                ("etag", Type::BYTE_ARRAY, ConvertedType::UTF8),
+                ("size_bytes", Type::INT64, ConvertedType::NONE),
+                ("deleted", Type::BOOLEAN, ConvertedType::NONE),
+                ("updated_at", Type::INT64, ConvertedType::TIMESTAMP_MILLIS),
+                ("tags", Type::BYTE_ARRAY, ConvertedType::UTF8),
+            ]
+        );
+
+        assert_eq!(metadata.num_row_groups(), 2);
+        assert_eq!(offsets[0][0], MAGIC.len() as i64);
+        for (index, group) in metadata.row_groups().iter().enumerate() {
+            assert_eq!(group.num_rows(), pages[index].len() as i64);
+            let data_page_offsets: Vec<i64> = group
+                .columns()
+                .iter()
+                .map(|column| column.data_page_offset())
+                .collect();
+            assert_eq!(data_page_offsets, offsets[index]);
+        }
+
+        let rows: Vec<_> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(rows.len(), 20);
+        for (row, expected) in rows.iter().zip(pages.concat()) {
+            assert_eq!(row.get_string(0).unwrap(), &expected.path);
+            assert_eq!(row.get_long(1).unwrap(), expected.generation);
+            assert_eq!(row.get_string(2).unwrap(), &expected.etag);
+            assert_eq!(row.get_long(3).unwrap(), expected.size_bytes as i64);
+            assert_eq!(row.get_bool(4).unwrap(), expected.deleted);
+            assert_eq!(
+                row.get_timestamp_millis(5).unwrap(),
+                expected.updated_at.timestamp_millis()
+            );
+            assert_eq!(
+                row.get_string(6).unwrap(),
+                &serde_json::to_string(&expected.tags).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn replicas_column_follows_the_listing() {
+        let items: Vec<ListBlobItem> = (0..3).map(item).collect();
+        let replicas: Vec<String> = (0..3).map(|index| format!("node-{}", index)).collect();
+        let mut listing = ParquetListing::new().with_replicas();
+        let mut file = listing.row_group_with_replicas(&items, &replicas).to_vec();
+        file.extend_from_slice(&listing.finish());
+        let reader = SerializedFileReader::new(Bytes::from(file)).unwrap();
+
+        let schema = reader.metadata().file_metadata().schema_descr();
+        assert_eq!(schema.num_columns(), COLUMNS.len() + 1);
+        assert_eq!(schema.column(COLUMNS.len()).name(), "replicas");
+        let rows: Vec<_> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        for (row, (item, replicas)) in rows.iter().zip(items.iter().zip(&replicas)) {
+            assert_eq!(row.get_string(0).unwrap(), &item.path);
+            assert_eq!(row.get_string(COLUMNS.len()).unwrap(), replicas);
+        }
+    }
+
+    #[test]
+    fn empty_listing_is_a_valid_file() {
+        let (file, _) = write(&[Vec::new()]);
+        let reader = SerializedFileReader::new(Bytes::from(file)).unwrap();
+
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 0);
+        assert_eq!(reader.metadata().num_row_groups(), 0);
+    }
+}
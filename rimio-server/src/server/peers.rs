@@ -0,0 +1,101 @@
+use super::{PeersQuery, ServerState, response_error};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use rimio_core::{LATENCY_BUCKETS_MS, LatencySummary, PeerCall};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// `GET /_/api/v1/peers` reports the latency of the head and part calls this
+/// node made since startup, per peer and per slot. `?format=prometheus`
+/// answers the same histograms in the Prometheus text format.
+pub(crate) async fn v1_peers(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<PeersQuery>,
+) -> Response {
+    let report = state.cluster_client.latency().report();
+    match query.format.as_deref() {
+        None | Some("json") => Json(report).into_response(),
+        Some("prometheus") => {
+            let mut body = String::new();
+            write_histograms(
+                &mut body,
+                "rimio_peer_call_duration_ms",
+                "Latency of internal calls to a peer.",
+                report
+                    .peers
+                    .iter()
+                    .map(|item| (format!("peer=\"{}\"", item.node_id), &item.calls)),
+            );
+            write_histograms(
+                &mut body,
+                "rimio_slot_call_duration_ms",
+                "Latency of internal calls for a slot.",
+                report
+                    .slots
+                    .iter()
+                    .map(|item| (format!("slot=\"{}\"", item.slot_id), &item.calls)),
+            );
+
+            let mut response = body.into_response();
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+            response
+        }
+        Some(format) => response_error(
+            StatusCode::BAD_REQUEST,
+            format!("unsupported format: {}", format),
+        ),
+    }
+}
+
+fn write_histograms<'a>(
+    body: &mut String,
+    name: &str,
+    help: &str,
+    series: impl Iterator<Item = (String, &'a BTreeMap<PeerCall, LatencySummary>)>,
+) {
+    let _ = writeln!(body, "# HELP {} {}", name, help);
+    let _ = writeln!(body, "# TYPE {} histogram", name);
+    for (label, calls) in series {
+        for (call, summary) in calls {
+            let call = serde_json::to_value(call)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string))
+                .unwrap_or_default();
+            let labels = format!("{},call=\"{}\"", label, call);
+
+            let mut cumulative = 0;
+            for (bound, calls) in LATENCY_BUCKETS_MS.iter().zip(&summary.histogram.buckets) {
+                cumulative += calls;
+                let _ = writeln!(
+                    body,
+                    "{}_bucket{{{},le=\"{}\"}} {}",
+                    name, labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                body,
+                "{}_bucket{{{},le=\"+Inf\"}} {}",
+                name, labels, summary.count
+            );
+            let _ = writeln!(
+                body,
+                "{}_sum{{{}}} {}",
+                name, labels, summary.histogram.total_ms
+            );
+            let _ = writeln!(body, "{}_count{{{}}} {}", name, labels, summary.count);
+            let _ = writeln!(
+                body,
+                "{}_errors_total{{{}}} {}",
+                name, labels, summary.errors
+            );
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use super::{ServerState, response_error};
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+/// Header clients use to mark a request `low`, `normal` or `high` priority.
+const PRIORITY_HEADER: &str = "x-rimio-priority";
+
+/// `GET /_/api/v1/pressure` reports the host's last resource sample and the
+/// pressure level derived from it.
+pub(crate) async fn v1_host_pressure(State(state): State<Arc<ServerState>>) -> Response {
+    match state.host_pressure.as_ref() {
+        Some(monitor) => Json(monitor.report().await).into_response(),
+        None => response_error(StatusCode::NOT_FOUND, "host pressure is not configured"),
+    }
+}
+
+/// Refuses low-priority public requests with `503` while the host is past
+/// its shed thresholds. Internal and health routes are never refused.
+pub(crate) async fn shed_under_pressure(
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(monitor) = state.host_pressure.as_ref() else {
+        return next.run(request).await;
+    };
+    if !monitor.sheds_traffic() || !is_low_priority(&request) {
+        return next.run(request).await;
+    }
+
+    let mut response = response_error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "node is under host pressure; retry later",
+    );
+    if let Ok(value) = HeaderValue::from_str(&monitor.interval_secs().to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
+
+/// Listings and exports are low priority unless the client says otherwise;
+/// blob reads and writes only when the client marks them `low`.
+fn is_low_priority(request: &Request) -> bool {
+    let path = request.uri().path();
+    if path.starts_with("/internal/")
+        || path == "/health"
+        || path == "/_/health"
+        || path.starts_with("/_/api/v1/healthz")
+        || path.starts_with("/_/api/v1/readyz")
+        || path.starts_with("/_/api/v1/pressure")
+    {
+        return false;
+    }
+
+    match request
+        .headers()
+        .get(PRIORITY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("low") => true,
+        Some("normal") | Some("high") => false,
+        _ => {
+            request.method() == Method::GET
+                && (path == "/_/api/v1/blobs"
+                    || path == "/_/api/v1/inventory"
+                    || path == "/_/api/v1/audit/export")
+        }
+    }
+}
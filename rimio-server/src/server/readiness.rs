@@ -0,0 +1,207 @@
+use super::{ReadinessCheck, ReadinessReport};
+use chrono::Utc;
+use rimio_core::{ArchiveStore, ClusterClient, Node, Registry, Result, RimError};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, interval};
+
+const READINESS_INTERVAL: Duration = Duration::from_secs(30);
+const PEER_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+const DISK_PROBE_FILE: &str = ".rimio-readiness";
+
+/// Checks the dependencies a node needs to serve traffic: the registry, its
+/// disks, the archive backend and its peers.
+///
+/// The first report is logged once at startup; later passes only log when
+/// readiness flips. When the archive is configured with
+/// `require_write_through`, writes are refused while the last pass could not
+/// reach it.
+pub(crate) struct ReadinessMonitor {
+    node: Arc<Node>,
+    registry: Arc<dyn Registry>,
+    cluster_client: Arc<ClusterClient>,
+    archive_store: Option<Arc<dyn ArchiveStore>>,
+    require_write_through: bool,
+    report: RwLock<ReadinessReport>,
+}
+
+impl ReadinessMonitor {
+    pub(crate) fn new(
+        node: Arc<Node>,
+        registry: Arc<dyn Registry>,
+        cluster_client: Arc<ClusterClient>,
+        archive_store: Option<Arc<dyn ArchiveStore>>,
+        require_write_through: bool,
+    ) -> Self {
+        Self {
+            node,
+            registry,
+            cluster_client,
+            archive_store,
+            require_write_through,
+            report: RwLock::new(ReadinessReport {
+                accepting_writes: !require_write_through,
+                ..ReadinessReport::default()
+            }),
+        }
+    }
+
+    /// Runs the startup check, then re-checks in the background.
+    pub(crate) async fn start(self: Arc<Self>) -> ReadinessReport {
+        let report = self.check().await;
+        match serde_json::to_string(&report) {
+            Ok(json) if report.ready => tracing::info!("readiness report: {}", json),
+            Ok(json) => tracing::warn!("readiness report: {}", json),
+            Err(error) => tracing::warn!("Failed to encode readiness report: {}", error),
+        }
+
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(READINESS_INTERVAL);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let was_ready = monitor.report.read().await.ready;
+                let report = monitor.check().await;
+                if report.ready != was_ready {
+                    let failed: Vec<&str> = report
+                        .checks
+                        .iter()
+                        .filter(|check| !check.ok)
+                        .map(|check| check.component.as_str())
+                        .collect();
+                    tracing::warn!(
+                        "readiness changed: node={} ready={} failed={:?}",
+                        monitor.node.node_id(),
+                        report.ready,
+                        failed
+                    );
+                }
+            }
+        });
+
+        report
+    }
+
+    pub(crate) async fn report(&self) -> ReadinessReport {
+        self.report.read().await.clone()
+    }
+
+    /// Fails with [`RimError::ArchiveUnavailable`] while writes must wait for
+    /// the archive to come back.
+    pub(crate) async fn ensure_writable(&self) -> Result<()> {
+        if self.report.read().await.accepting_writes {
+            return Ok(());
+        }
+
+        Err(RimError::ArchiveUnavailable {
+            message: "archive unreachable and write-through is required; writes are refused"
+                .to_string(),
+            retry_after: READINESS_INTERVAL,
+        })
+    }
+
+    async fn check(&self) -> ReadinessReport {
+        let mut checks = Vec::new();
+
+        let peers = match self.registry.get_nodes().await {
+            Ok(nodes) => {
+                checks.push(ReadinessCheck::passed(
+                    "registry",
+                    true,
+                    format!("nodes={}", nodes.len()),
+                ));
+                nodes
+            }
+            Err(error) => {
+                checks.push(ReadinessCheck::failed("registry", true, error.to_string()));
+                Vec::new()
+            }
+        };
+
+        for disk in self.node.disks() {
+            let component = format!("disk:{}", disk.display());
+            checks.push(match probe_disk(disk).await {
+                Ok(()) => ReadinessCheck::passed(component, true, "writable"),
+                Err(error) => ReadinessCheck::failed(component, true, error.to_string()),
+            });
+        }
+
+        let mut archive_ok = true;
+        if let Some(store) = &self.archive_store {
+            checks.push(match store.ping().await {
+                Ok(()) => {
+                    ReadinessCheck::passed("archive", self.require_write_through, "reachable")
+                }
+                Err(error) => {
+                    archive_ok = false;
+                    ReadinessCheck::failed("archive", self.require_write_through, error.to_string())
+                }
+            });
+        }
+
+        let mut probes = JoinSet::new();
+        for peer in peers
+            .into_iter()
+            .filter(|peer| peer.node_id != self.node.node_id())
+        {
+            let cluster_client = self.cluster_client.clone();
+            probes.spawn(async move {
+                let result = cluster_client.probe_node(&peer, PEER_PROBE_TIMEOUT).await;
+                (peer, result)
+            });
+        }
+
+        let mut peer_checks = Vec::new();
+        while let Some(joined) = probes.join_next().await {
+            let Ok((peer, result)) = joined else {
+                continue;
+            };
+            let component = format!("peer:{}", peer.node_id);
+            peer_checks.push(match result {
+                Ok(()) => ReadinessCheck::passed(component, false, peer.address),
+                Err(error) => ReadinessCheck::failed(component, false, error.to_string()),
+            });
+        }
+        peer_checks.sort_by(|a, b| a.component.cmp(&b.component));
+        checks.extend(peer_checks);
+
+        let report = ReadinessReport {
+            checked_at: Some(Utc::now()),
+            node_id: self.node.node_id().to_string(),
+            ready: checks.iter().all(|check| check.ok || !check.required),
+            accepting_writes: archive_ok || !self.require_write_through,
+            checks,
+        };
+        *self.report.write().await = report.clone();
+        report
+    }
+}
+
+impl ReadinessCheck {
+    fn passed(component: impl Into<String>, required: bool, detail: impl Into<String>) -> Self {
+        Self {
+            component: component.into(),
+            ok: true,
+            required,
+            detail: detail.into(),
+        }
+    }
+
+    fn failed(component: impl Into<String>, required: bool, detail: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            ..Self::passed(component, required, detail)
+        }
+    }
+}
+
+async fn probe_disk(disk: &Path) -> Result<()> {
+    tokio::fs::create_dir_all(disk).await?;
+    let probe = disk.join(DISK_PROBE_FILE);
+    tokio::fs::write(&probe, b"ok").await?;
+    tokio::fs::remove_file(&probe).await?;
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use super::{
+    RegistrySlotItem, RegistrySlotsQuery, RegistryStateResponse, ServerState, response_error,
+};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use rimio_core::{ClusterState, Result};
+use std::sync::Arc;
+
+/// Registry reads in flight while collecting per-slot details.
+const REGISTRY_READ_CONCURRENCY: usize = 16;
+
+/// `GET /_/api/v1/registry` dumps what the registry holds as this node reads
+/// it: the bootstrap state and the node records, as stored.
+pub(crate) async fn v1_registry_state(State(state): State<Arc<ServerState>>) -> Response {
+    let bootstrap = match state.registry.get_bootstrap_state().await {
+        Ok(payload) => payload,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    let bootstrap = match bootstrap
+        .map(|payload| serde_json::from_slice::<ClusterState>(&payload))
+        .transpose()
+    {
+        Ok(bootstrap) => bootstrap,
+        Err(error) => {
+            return response_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("invalid bootstrap state payload in registry: {}", error),
+            );
+        }
+    };
+    let nodes = match state.registry.get_nodes().await {
+        Ok(nodes) => nodes,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    let slot_count = match state.registry.get_all_slots().await {
+        Ok(slots) => slots.len(),
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    Json(RegistryStateResponse {
+        backend: state.config.registry.backend.clone(),
+        namespace: state.config.registry.namespace_or_default().to_string(),
+        bootstrap,
+        nodes,
+        slot_count,
+    })
+    .into_response()
+}
+
+/// `GET /_/api/v1/registry/slots` lists every slot record in the registry,
+/// including slots without one. `?details=true` adds each slot's health
+/// reports and write lease, at two registry reads per slot.
+pub(crate) async fn v1_registry_slots(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<RegistrySlotsQuery>,
+) -> Response {
+    let mut slots = match state.registry.get_all_slots().await {
+        Ok(slots) => slots,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    let total_slots = state.config.replication.total_slots;
+    let items = (0..total_slots).map(|slot_id| RegistrySlotItem {
+        slot_id,
+        slot: slots.remove(&slot_id),
+        health: None,
+        lease: None,
+    });
+    if !query.details {
+        return Json(items.collect::<Vec<_>>()).into_response();
+    }
+
+    let items: Result<Vec<RegistrySlotItem>> = stream::iter(items)
+        .map(|item| {
+            let state = state.clone();
+            async move { with_details(&state, item).await }
+        })
+        .buffered(REGISTRY_READ_CONCURRENCY)
+        .try_collect()
+        .await;
+    match items {
+        Ok(items) => Json(items).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `GET /_/api/v1/registry/slots/:slot_id` shows one slot's record, health
+/// reports and write lease as stored in the registry.
+pub(crate) async fn v1_registry_slot(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+) -> Response {
+    if slot_id >= state.config.replication.total_slots {
+        return response_error(
+            StatusCode::NOT_FOUND,
+            format!("slot not found: {}", slot_id),
+        );
+    }
+
+    let slot = match state.registry.get_slot(slot_id).await {
+        Ok(slot) => slot,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    let item = RegistrySlotItem {
+        slot_id,
+        slot,
+        health: None,
+        lease: None,
+    };
+    match with_details(&state, item).await {
+        Ok(item) => Json(item).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+async fn with_details(state: &ServerState, mut item: RegistrySlotItem) -> Result<RegistrySlotItem> {
+    item.health = Some(state.registry.get_slot_health(item.slot_id).await?);
+    item.lease = state.registry.get_slot_lease(item.slot_id).await?;
+    Ok(item)
+}
@@ -0,0 +1,136 @@
+use super::{
+    RenameBody, RenameResponse, RoutingHints, ServerState, claim_write_lease, refuse_frozen_write,
+    refuse_unarchived_write, resolve_replica_nodes, response_error,
+};
+use axum::{
+    Json,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use rimio_core::{
+    RenameBlobOperationOutcome, RenameBlobOperationRequest, RenameBlobTarget, RimError,
+    normalize_blob_path,
+};
+use std::sync::Arc;
+
+/// `POST /_/api/v1/rename` moves a blob to a new path, for pipelines that
+/// upload under a temporary name and publish by renaming. Paths sharing a
+/// slot switch in one transaction; otherwise the destination is written
+/// first and rolled back if the source cannot be removed. The write leases of
+/// both slots are held throughout; a request is redirected to the holder of
+/// the first slot but refused with `421` when another node holds the second.
+pub(crate) async fn v1_rename_blob(
+    State(state): State<Arc<ServerState>>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Some(response) = refuse_unarchived_write(&state).await {
+        return response;
+    }
+
+    let request: RenameBody = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(error) => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                format!("invalid rename body: {}", error),
+            );
+        }
+    };
+    let source = match target(&state, &request.source).await {
+        Ok(source) => source,
+        Err(response) => return response,
+    };
+    let destination = match target(&state, &request.destination).await {
+        Ok(destination) => destination,
+        Err(response) => return response,
+    };
+
+    for slot_id in [source.slot_id, destination.slot_id] {
+        if let Some(response) = refuse_frozen_write(&state, slot_id).await {
+            return response;
+        }
+    }
+
+    // Claimed in slot order so two renames crossing the same slots in
+    // opposite directions cannot wait on each other.
+    let (first, second) = if source.slot_id <= destination.slot_id {
+        (&source, &destination)
+    } else {
+        (&destination, &source)
+    };
+    let hints = RoutingHints::from_request(&headers, &uri);
+    let _first_guard =
+        match claim_write_lease(&state, first.slot_id, &first.replicas, &uri, &hints).await {
+            Ok(guard) => guard,
+            Err(response) => return response,
+        };
+    let _second_guard = if second.slot_id == first.slot_id {
+        None
+    } else {
+        let hints = RoutingHints {
+            no_proxy: true,
+            ..hints
+        };
+        match claim_write_lease(&state, second.slot_id, &second.replicas, &uri, &hints).await {
+            Ok(guard) => guard,
+            Err(response) => return response,
+        }
+    };
+
+    let outcome = state
+        .rename_blob_operation
+        .run(RenameBlobOperationRequest {
+            source: source.clone(),
+            destination: destination.clone(),
+            overwrite: request.overwrite,
+            local_node_id: state.node.node_id().to_string(),
+        })
+        .await;
+
+    match outcome {
+        Ok(RenameBlobOperationOutcome::Renamed(result)) => Json(RenameResponse {
+            source: source.path,
+            destination: destination.path,
+            generation: result.destination.generation,
+            etag: result.destination.etag,
+            size_bytes: result.destination.size_bytes,
+            source_generation: result.source_generation,
+            atomic: result.atomic,
+        })
+        .into_response(),
+        Ok(RenameBlobOperationOutcome::SourceNotFound) => {
+            response_error(StatusCode::NOT_FOUND, "source not found")
+        }
+        Ok(RenameBlobOperationOutcome::DestinationExists { generation }) => response_error(
+            StatusCode::CONFLICT,
+            format!(
+                "destination exists: generation={}; pass overwrite to replace it",
+                generation
+            ),
+        ),
+        Ok(RenameBlobOperationOutcome::Conflict) => response_error(
+            StatusCode::CONFLICT,
+            "source or destination changed during the rename; nothing was renamed",
+        ),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::BAD_REQUEST, message),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+async fn target(state: &ServerState, raw_path: &str) -> Result<RenameBlobTarget, Response> {
+    let path = normalize_blob_path(raw_path)
+        .map_err(|error| response_error(StatusCode::BAD_REQUEST, error.to_string()))?;
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let replicas = resolve_replica_nodes(state, slot_id)
+        .await
+        .map_err(|error| response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+    Ok(RenameBlobTarget {
+        slot_id,
+        path,
+        replicas,
+    })
+}
@@ -0,0 +1,124 @@
+use super::{
+    DeadLettersQuery, DeadLettersResponse, ReleaseDeadLetterQuery, ReleaseDeadLetterResponse,
+    RepairStatsQuery, RepairStatsResponse, RepairsQuery, RepairsResponse, ServerState,
+    normalize_blob_path, response_error,
+};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use rimio_core::RepairStats;
+use std::sync::Arc;
+
+const DEFAULT_REPAIRS_LIMIT: usize = 100;
+const MAX_REPAIRS_LIMIT: usize = 1000;
+
+/// Repairs are summed over the last day unless asked otherwise.
+const DEFAULT_REPAIR_STATS_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// `GET /_/api/v1/repairs` lists the part fetches repairs made on this node,
+/// newest first. `?failed=true` keeps the failed ones; `?before=<id>` pages
+/// back from the `next_before` of the previous page.
+pub(crate) async fn v1_list_repairs(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<RepairsQuery>,
+) -> Response {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_REPAIRS_LIMIT)
+        .clamp(1, MAX_REPAIRS_LIMIT);
+
+    match state
+        .node_store
+        .list_repairs(query.before, query.failed, limit)
+    {
+        Ok(repairs) => {
+            let next_before = (repairs.len() == limit)
+                .then(|| repairs.last().map(|repair| repair.repair_id))
+                .flatten();
+            Json(RepairsResponse {
+                repairs,
+                next_before,
+            })
+            .into_response()
+        }
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `GET /_/api/v1/repairs/stats` sums the repairs of the last
+/// `window_secs` per source kind: attempts, failures, bytes stored and time
+/// spent.
+pub(crate) async fn v1_repair_stats(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<RepairStatsQuery>,
+) -> Response {
+    let window_secs = query
+        .window_secs
+        .unwrap_or(DEFAULT_REPAIR_STATS_WINDOW_SECS);
+    let since = chrono::Utc::now() - chrono::Duration::seconds(window_secs as i64);
+
+    match state.node_store.repair_stats(since) {
+        Ok(sources) => {
+            let total = sources.iter().fold(
+                RepairStats {
+                    source_kind: "all".to_string(),
+                    ..RepairStats::default()
+                },
+                |mut total, source| {
+                    total.attempts += source.attempts;
+                    total.succeeded += source.succeeded;
+                    total.failed += source.failed;
+                    total.bytes += source.bytes;
+                    total.total_duration_ms += source.total_duration_ms;
+                    total
+                },
+            );
+            Json(RepairStatsResponse {
+                node_id: state.node.node_id().to_string(),
+                window_secs,
+                total,
+                sources,
+            })
+            .into_response()
+        }
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `GET /_/api/v1/repairs/dead-letters` lists the repairs parked on this node
+/// after failing repeatedly for want of a good copy, with the last error.
+pub(crate) async fn v1_list_dead_letters(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<DeadLettersQuery>,
+) -> Response {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_REPAIRS_LIMIT)
+        .clamp(1, MAX_REPAIRS_LIMIT);
+
+    match state.node_store.list_dead_letters(limit) {
+        Ok(dead_letters) => Json(DeadLettersResponse { dead_letters }).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `DELETE /_/api/v1/repairs/dead-letters?slot_id=..&path=..` releases the
+/// parked repairs of a path, e.g. once a good copy has been restored, so the
+/// next read or heal tries again.
+pub(crate) async fn v1_release_dead_letter(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<ReleaseDeadLetterQuery>,
+) -> Response {
+    let path = match normalize_blob_path(&query.path) {
+        Ok(path) => path,
+        Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+
+    match state.node_store.release_dead_letters(query.slot_id, &path) {
+        Ok(released) => Json(ReleaseDeadLetterResponse { released }).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
@@ -1,18 +1,23 @@
-use super::{ServerState, normalize_blob_path, resolve_replica_nodes};
+use super::{
+    RoutingHints, ServerState, claim_write_lease, normalize_blob_path, resolve_replica_nodes,
+};
 use async_trait::async_trait;
-use axum::http::StatusCode;
+use axum::http::{StatusCode, Uri};
 use chrono::SecondsFormat;
+use futures_util::TryStreamExt;
 use rimio_core::{
-    DeleteBlobOperationOutcome, DeleteBlobOperationRequest, ListBlobsOperationRequest,
-    PutBlobOperationOutcome, PutBlobOperationRequest, ReadBlobOperationOutcome,
-    ReadBlobOperationRequest, RimError, slot_for_key,
+    ContentHeaders, DeleteBlobOperationOutcome, DeleteBlobOperationRequest,
+    ListBlobsOperationRequest, ListOrder, NodeInfo, PutBlobOperationOutcome,
+    PutBlobOperationRequest, PutBlobStreamRequest, ReadBlobOperationOutcome,
+    ReadBlobOperationRequest, ReadRangeSpec, RimError, SlotTraffic, WriteConsistency,
 };
 use rimio_s3_gateway::{
-    DeleteObjectRequest, GetObjectRequest, GetObjectResponse, HeadObjectRequest,
-    HeadObjectResponse, ListObjectItem, ListObjectsV2Request, ListObjectsV2Response,
+    ByteRangeSpec, DeleteObjectRequest, GetObjectRequest, GetObjectResponse, HeadObjectRequest,
+    HeadObjectResponse, ListObjectItem, ListObjectsV2Request, ListObjectsV2Response, PutObjectBody,
     PutObjectRequest, PutObjectResponse, S3Error, S3GatewayBackend, S3GatewayResult,
 };
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use tokio::sync::OwnedMutexGuard;
 
 fn validate_bucket(bucket: &str) -> S3GatewayResult<String> {
     let trimmed = bucket.trim().trim_matches('/');
@@ -50,20 +55,101 @@ fn map_write_error(error: RimError) -> S3Error {
                 required, found
             ),
         ),
+        RimError::ArchiveUnavailable { message, .. } => S3Error::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "ServiceUnavailable",
+            message,
+        ),
         RimError::InvalidRequest(message) => S3Error::invalid_argument(message),
         other => S3Error::internal(other.to_string()),
     }
 }
 
+/// Refuses a write to a slot frozen for maintenance.
+async fn ensure_slot_writable(state: &ServerState, slot_id: u16) -> S3GatewayResult<()> {
+    if !state.placement.is_frozen(slot_id).await {
+        return Ok(());
+    }
+    Err(S3Error::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "ServiceUnavailable",
+        format!("slot {} is frozen for maintenance", slot_id),
+    ))
+}
+
+/// Claims the write lease of the slot like the HTTP write paths do. S3
+/// clients are not sent on to the holder, so a lease held elsewhere is a
+/// `503` naming the holder.
+async fn claim_slot_lease(
+    state: &ServerState,
+    slot_id: u16,
+    replicas: &[NodeInfo],
+) -> S3GatewayResult<Option<OwnedMutexGuard<()>>> {
+    let hints = RoutingHints {
+        no_proxy: true,
+        ..RoutingHints::default()
+    };
+    claim_write_lease(state, slot_id, replicas, &Uri::from_static("/"), &hints)
+        .await
+        .map_err(|response| {
+            let holder = response
+                .headers()
+                .get("x-rimio-slot-owners")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("unknown");
+            S3Error::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "ServiceUnavailable",
+                format!(
+                    "slot {} write lease unavailable here: status={} holder={}",
+                    slot_id,
+                    response.status().as_u16(),
+                    holder
+                ),
+            )
+        })
+}
+
 fn map_read_error(error: RimError) -> S3Error {
     match error {
-        RimError::InvalidRequest(message) => {
-            S3Error::new(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange", message)
-        }
+        RimError::RangeNotSatisfiable { size_bytes } => S3Error::new(
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            "InvalidRange",
+            format!(
+                "the requested range is not satisfiable: size={}",
+                size_bytes
+            ),
+        ),
+        RimError::InvalidRequest(message) => S3Error::invalid_argument(message),
+        RimError::InsufficientReplicas { required, found } => S3Error::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "ServiceUnavailable",
+            format!(
+                "read quorum not reached: required replicas={}, answered replicas={}",
+                required, found
+            ),
+        ),
+        RimError::ArchiveUnavailable { message, .. } => S3Error::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "ServiceUnavailable",
+            message,
+        ),
         other => S3Error::internal(other.to_string()),
     }
 }
 
+/// The headers given when the object was written, sent back on reads.
+fn stored_content_headers(content: &ContentHeaders) -> Vec<(String, String)> {
+    [
+        ("content-type", &content.content_type),
+        ("content-encoding", &content.content_encoding),
+        ("cache-control", &content.cache_control),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name.to_string(), value.clone()?)))
+    .collect()
+}
+
 fn normalize_etag(raw: &str) -> &str {
     raw.trim().trim_matches('"')
 }
@@ -110,18 +196,36 @@ impl S3GatewayBackend for ServerState {
             bucket,
             key,
             body,
+            cache_control,
+            content_encoding,
+            content_type,
             if_match,
             if_none_match,
+            metadata,
             ..
         } = request;
+        let metadata: BTreeMap<String, String> = metadata.into_iter().collect();
+        let content = ContentHeaders {
+            content_type,
+            content_encoding,
+            cache_control,
+        };
 
         let path = s3_object_path(bucket.as_str(), key.as_str())?;
-        let slot_id = slot_for_key(&path, self.config.replication.total_slots);
+        let slot_id = self.config.replication.slot_for_key(&path);
+
+        self.readiness
+            .ensure_writable()
+            .await
+            .map_err(map_write_error)?;
+        ensure_slot_writable(self, slot_id).await?;
 
         let replicas = resolve_replica_nodes(self, slot_id)
             .await
             .map_err(|error| S3Error::internal(error.to_string()))?;
+        let _write_guard = claim_slot_lease(self, slot_id, &replicas).await?;
 
+        let mut expected_generation = None;
         if if_match.is_some() || if_none_match.is_some() {
             let head_outcome = self
                 .read_blob_operation
@@ -132,16 +236,20 @@ impl S3GatewayBackend for ServerState {
                     local_node_id: self.node.node_id().to_string(),
                     include_body: false,
                     range: None,
+                    if_range: None,
+                    consistency: self.config.replication.read_consistency,
                 })
                 .await;
 
-            let existing_etag = match head_outcome {
-                Ok(ReadBlobOperationOutcome::Found(head)) => Some(head.meta.etag),
+            let existing = match head_outcome {
+                Ok(ReadBlobOperationOutcome::Found(head)) => Some(head.meta),
                 Ok(ReadBlobOperationOutcome::NotFound) | Ok(ReadBlobOperationOutcome::Deleted) => {
                     None
                 }
                 Err(error) => return Err(map_read_error(error)),
             };
+            expected_generation = Some(existing.as_ref().map_or(0, |meta| meta.generation));
+            let existing_etag = existing.map(|meta| meta.etag);
 
             if let Some(if_match) = if_match.as_deref() {
                 let Some(existing_etag) = existing_etag.as_deref() else {
@@ -174,20 +282,48 @@ impl S3GatewayBackend for ServerState {
             }
         }
 
-        let outcome = self
-            .put_blob_operation
-            .run(PutBlobOperationRequest {
-                path,
-                slot_id,
-                write_id: format!("s3-put-{}", ulid::Ulid::new()),
-                body,
-                replicas,
-                local_node_id: self.node.node_id().to_string(),
-            })
-            .await;
+        let outcome = match body {
+            PutObjectBody::Buffered(body) => {
+                self.put_blob_operation
+                    .run(PutBlobOperationRequest {
+                        path,
+                        slot_id,
+                        write_id: format!("s3-put-{}", ulid::Ulid::new()),
+                        body,
+                        replicas,
+                        local_node_id: self.node.node_id().to_string(),
+                        consistency: WriteConsistency::Quorum,
+                        expected_generation,
+                        metadata,
+                        tags: BTreeMap::new(),
+                        content,
+                    })
+                    .await
+            }
+            PutObjectBody::Streaming(body) => {
+                self.put_blob_operation
+                    .run_stream(
+                        PutBlobStreamRequest {
+                            path,
+                            slot_id,
+                            replicas,
+                            local_node_id: self.node.node_id().to_string(),
+                            consistency: WriteConsistency::Quorum,
+                            expected_generation,
+                            metadata,
+                            tags: BTreeMap::new(),
+                            content,
+                        },
+                        body.map_err(|error| RimError::InvalidRequest(error.message().to_string())),
+                    )
+                    .await
+            }
+        };
 
         match outcome {
             Ok(PutBlobOperationOutcome::Committed(result)) => {
+                self.slot_heat
+                    .record(slot_id, SlotTraffic::Write, result.size_bytes);
                 Ok(PutObjectResponse { etag: result.etag })
             }
             Ok(PutBlobOperationOutcome::Conflict) => Err(S3Error::new(
@@ -195,6 +331,11 @@ impl S3GatewayBackend for ServerState {
                 "OperationAborted",
                 "meta commit rejected by generation check",
             )),
+            Ok(PutBlobOperationOutcome::PreconditionFailed { .. }) => Err(S3Error::new(
+                StatusCode::PRECONDITION_FAILED,
+                "PreconditionFailed",
+                "object changed while the precondition was checked",
+            )),
             Err(error) => Err(map_write_error(error)),
         }
     }
@@ -246,7 +387,7 @@ impl S3GatewayBackend for ServerState {
         }
 
         let path = s3_object_path(bucket.as_str(), key.as_str())?;
-        let slot_id = slot_for_key(&path, self.config.replication.total_slots);
+        let slot_id = self.config.replication.slot_for_key(&path);
 
         let replicas = resolve_replica_nodes(self, slot_id)
             .await
@@ -268,6 +409,8 @@ impl S3GatewayBackend for ServerState {
                     local_node_id: self.node.node_id().to_string(),
                     include_body: false,
                     range: None,
+                    if_range: None,
+                    consistency: self.config.replication.read_consistency,
                 })
                 .await;
 
@@ -291,11 +434,12 @@ impl S3GatewayBackend for ServerState {
 
             let end = (start + part_size - 1).min(head.meta.size_bytes.saturating_sub(1));
 
-            Some(rimio_core::ReadByteRange { start, end })
+            Some(ReadRangeSpec::Bounded { start, end })
         } else {
-            range.map(|range| rimio_core::ReadByteRange {
-                start: range.start,
-                end: range.end,
+            range.map(|range| match range {
+                ByteRangeSpec::Bounded { start, end } => ReadRangeSpec::Bounded { start, end },
+                ByteRangeSpec::From { start } => ReadRangeSpec::From { start },
+                ByteRangeSpec::Suffix { length } => ReadRangeSpec::Suffix { length },
             })
         };
 
@@ -308,20 +452,46 @@ impl S3GatewayBackend for ServerState {
                 local_node_id: self.node.node_id().to_string(),
                 include_body: true,
                 range: effective_range,
+                if_range: None,
+                consistency: self.config.replication.read_consistency,
             })
             .await;
 
         match outcome {
-            Ok(ReadBlobOperationOutcome::Found(result)) => Ok(GetObjectResponse {
-                body: result.body.unwrap_or_default(),
-                etag: result.meta.etag,
-                last_modified: result.meta.updated_at.to_rfc2822(),
-                size_bytes: result.meta.size_bytes,
-                body_range: result.body_range.map(|range| rimio_s3_gateway::ByteRange {
-                    start: range.start,
-                    end: range.end,
-                }),
-            }),
+            Ok(ReadBlobOperationOutcome::Found(result)) => {
+                let body = result.body.unwrap_or_default();
+                self.slot_heat
+                    .record(slot_id, SlotTraffic::Read, body.len() as u64);
+                Ok(GetObjectResponse {
+                    body,
+                    etag: result.meta.etag,
+                    last_modified: result.meta.updated_at.to_rfc2822(),
+                    size_bytes: result.meta.size_bytes,
+                    body_range: result.body_range.filter(|_| result.partial).map(|range| {
+                        rimio_s3_gateway::ByteRange {
+                            start: range.start,
+                            end: range.end,
+                        }
+                    }),
+                    headers: stored_content_headers(&result.meta.content)
+                        .into_iter()
+                        .chain(
+                            self.config
+                                .response_headers
+                                .as_ref()
+                                .map(|config| {
+                                    config
+                                        .headers(&path)
+                                        .iter()
+                                        .map(|(name, value)| (name.clone(), value.clone()))
+                                        .collect::<Vec<_>>()
+                                })
+                                .unwrap_or_default(),
+                        )
+                        .collect(),
+                    metadata: result.meta.metadata.into_iter().collect(),
+                })
+            }
             Ok(ReadBlobOperationOutcome::NotFound) | Ok(ReadBlobOperationOutcome::Deleted) => {
                 Err(S3Error::no_such_key(bucket.as_str(), key.as_str()))
             }
@@ -332,7 +502,7 @@ impl S3GatewayBackend for ServerState {
     async fn head_object(&self, request: HeadObjectRequest) -> S3GatewayResult<HeadObjectResponse> {
         let HeadObjectRequest { bucket, key } = request;
         let path = s3_object_path(bucket.as_str(), key.as_str())?;
-        let slot_id = slot_for_key(&path, self.config.replication.total_slots);
+        let slot_id = self.config.replication.slot_for_key(&path);
         let replicas = resolve_replica_nodes(self, slot_id)
             .await
             .map_err(|error| S3Error::internal(error.to_string()))?;
@@ -346,14 +516,21 @@ impl S3GatewayBackend for ServerState {
                 local_node_id: self.node.node_id().to_string(),
                 include_body: false,
                 range: None,
+                if_range: None,
+                consistency: self.config.replication.read_consistency,
             })
             .await;
 
         match outcome {
-            Ok(ReadBlobOperationOutcome::Found(result)) => Ok(HeadObjectResponse {
-                etag: result.meta.etag,
-                size_bytes: result.meta.size_bytes,
-            }),
+            Ok(ReadBlobOperationOutcome::Found(result)) => {
+                self.slot_heat.record(slot_id, SlotTraffic::Read, 0);
+                Ok(HeadObjectResponse {
+                    etag: result.meta.etag,
+                    size_bytes: result.meta.size_bytes,
+                    headers: stored_content_headers(&result.meta.content),
+                    metadata: result.meta.metadata.into_iter().collect(),
+                })
+            }
             Ok(ReadBlobOperationOutcome::NotFound) | Ok(ReadBlobOperationOutcome::Deleted) => {
                 Err(S3Error::no_such_key(bucket.as_str(), key.as_str()))
             }
@@ -364,10 +541,12 @@ impl S3GatewayBackend for ServerState {
     async fn delete_object(&self, request: DeleteObjectRequest) -> S3GatewayResult<()> {
         let DeleteObjectRequest { bucket, key } = request;
         let path = s3_object_path(bucket.as_str(), key.as_str())?;
-        let slot_id = slot_for_key(&path, self.config.replication.total_slots);
+        let slot_id = self.config.replication.slot_for_key(&path);
+        ensure_slot_writable(self, slot_id).await?;
         let replicas = resolve_replica_nodes(self, slot_id)
             .await
             .map_err(|error| S3Error::internal(error.to_string()))?;
+        let _write_guard = claim_slot_lease(self, slot_id, &replicas).await?;
 
         let outcome = self
             .delete_blob_operation
@@ -377,12 +556,19 @@ impl S3GatewayBackend for ServerState {
                 write_id: format!("s3-delete-{}", ulid::Ulid::new()),
                 replicas,
                 local_node_id: self.node.node_id().to_string(),
+                expected_generation: None,
             })
             .await;
 
         match outcome {
-            Ok(DeleteBlobOperationOutcome::Committed(_))
-            | Ok(DeleteBlobOperationOutcome::Conflict) => Ok(()),
+            Ok(DeleteBlobOperationOutcome::Committed(_)) => {
+                self.slot_heat.record(slot_id, SlotTraffic::Write, 0);
+                Ok(())
+            }
+            Ok(
+                DeleteBlobOperationOutcome::Conflict
+                | DeleteBlobOperationOutcome::PreconditionFailed { .. },
+            ) => Ok(()),
             Err(error) => Err(map_write_error(error)),
         }
     }
@@ -439,6 +625,10 @@ impl S3GatewayBackend for ServerState {
                     limit: fetch_limit.max(1),
                     cursor: effective_cursor,
                     include_deleted: false,
+                    tags: BTreeMap::new(),
+                    order_by: ListOrder::Path,
+                    since: None,
+                    until: None,
                 })
                 .await
                 .map_err(|error| S3Error::internal(error.to_string()))?;
@@ -508,6 +698,10 @@ impl S3GatewayBackend for ServerState {
                     limit: batch_limit,
                     cursor: scan_cursor.clone(),
                     include_deleted: false,
+                    tags: BTreeMap::new(),
+                    order_by: ListOrder::Path,
+                    since: None,
+                    until: None,
                 })
                 .await
                 .map_err(|error| S3Error::internal(error.to_string()))?;
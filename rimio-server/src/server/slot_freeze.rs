@@ -0,0 +1,101 @@
+use super::{
+    InternalPlacementQuery, ServerState, SlotFreezeResponse, current_nodes, response_error,
+};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use rimio_core::RimError;
+use std::sync::Arc;
+
+/// `POST /_/api/v1/slots/:slot_id/freeze` stops a slot from taking writes
+/// while reads go on, for backups, migrations and debugging that need a
+/// quiescent slot.
+pub(crate) async fn v1_freeze_slot(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+) -> Response {
+    set_slot_frozen(&state, slot_id, true).await
+}
+
+/// `POST /_/api/v1/slots/:slot_id/thaw` lets a frozen slot take writes
+/// again.
+pub(crate) async fn v1_thaw_slot(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+) -> Response {
+    set_slot_frozen(&state, slot_id, false).await
+}
+
+/// Flips the flag in the registry, then asks every peer to reload the slot
+/// so the change holds group-wide before we answer.
+async fn set_slot_frozen(state: &ServerState, slot_id: u16, frozen: bool) -> Response {
+    if slot_id >= state.config.replication.total_slots {
+        return response_error(
+            StatusCode::NOT_FOUND,
+            format!("slot not found: {}", slot_id),
+        );
+    }
+
+    let slot = match state.placement.set_frozen(slot_id, frozen).await {
+        Ok(Some(slot)) => slot,
+        Ok(None) => {
+            return response_error(
+                StatusCode::CONFLICT,
+                format!("slot {} changed while updating it, retry", slot_id),
+            );
+        }
+        Err(RimError::SlotNotFound(_)) => {
+            return response_error(
+                StatusCode::NOT_FOUND,
+                format!("slot has no placement: {}", slot_id),
+            );
+        }
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    let nodes = match current_nodes(state).await {
+        Ok(nodes) => nodes,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    let local_node_id = state.node.node_id();
+    let mut unreachable = Vec::new();
+    for node in nodes.iter().filter(|node| node.node_id != local_node_id) {
+        if let Err(error) = state
+            .cluster_client
+            .reload_peer_placement(&node.node_id, slot_id, slot.epoch)
+            .await
+        {
+            tracing::warn!(
+                "Failed to reload slot placement on peer: slot={} node={} error={}",
+                slot_id,
+                node.node_id,
+                error
+            );
+            unreachable.push(node.node_id.clone());
+        }
+    }
+
+    Json(SlotFreezeResponse {
+        slot_id,
+        frozen: slot.frozen,
+        epoch: slot.epoch,
+        unreachable,
+    })
+    .into_response()
+}
+
+/// `POST /internal/v1/slots/:slot_id/placement?epoch=` reloads the slot
+/// from the registry unless this node already has `epoch`.
+pub(crate) async fn v1_internal_reload_placement(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+    Query(query): Query<InternalPlacementQuery>,
+) -> Response {
+    match state.placement.fencing_epoch(slot_id, query.epoch).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(error) => response_error(StatusCode::SERVICE_UNAVAILABLE, error.to_string()),
+    }
+}
@@ -0,0 +1,130 @@
+use super::{ServerState, SlotTransferFileQuery, SlotTransferPullRequest, response_error};
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use rimio_core::{RimError, SlotTransferOperationRequest};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Bytes read from an export file per body chunk.
+const EXPORT_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// `POST /internal/v1/slots/:slot_id/transfer/exports` snapshots a local
+/// slot for a peer to pull.
+pub(crate) async fn internal_export_slot(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+) -> Response {
+    match state.slot_transfer.export(slot_id).await {
+        Ok(manifest) => Json(manifest).into_response(),
+        Err(RimError::SlotNotFound(_)) => response_error(StatusCode::NOT_FOUND, "slot not found"),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+pub(crate) async fn internal_get_slot_export(
+    State(state): State<Arc<ServerState>>,
+    Path((slot_id, transfer_id)): Path<(u16, String)>,
+) -> Response {
+    match state
+        .slot_transfer
+        .export_manifest(slot_id, &transfer_id)
+        .await
+    {
+        Ok(Some(manifest)) => Json(manifest).into_response(),
+        Ok(None) => response_error(StatusCode::NOT_FOUND, "slot transfer not found"),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// Streams one export file from `offset`, so an interrupted download can
+/// pick up where it stopped.
+pub(crate) async fn internal_get_slot_export_file(
+    State(state): State<Arc<ServerState>>,
+    Path((slot_id, transfer_id)): Path<(u16, String)>,
+    Query(query): Query<SlotTransferFileQuery>,
+) -> Response {
+    let file_path = match state
+        .slot_transfer
+        .export_file(slot_id, &transfer_id, &query.name)
+        .await
+    {
+        Ok(file_path) => file_path,
+        Err(RimError::PartNotFound(message)) => {
+            return response_error(StatusCode::NOT_FOUND, message);
+        }
+        Err(RimError::InvalidRequest(message)) => {
+            return response_error(StatusCode::BAD_REQUEST, message);
+        }
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    let mut file = match tokio::fs::File::open(&file_path).await {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return response_error(StatusCode::NOT_FOUND, "slot transfer file not found");
+        }
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    let size = match file.metadata().await {
+        Ok(metadata) => metadata.len(),
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+    if query.offset > size {
+        return response_error(StatusCode::RANGE_NOT_SATISFIABLE, "offset past end of file");
+    }
+    if let Err(error) = file.seek(std::io::SeekFrom::Start(query.offset)).await {
+        return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+    }
+
+    let body = futures_util::stream::try_unfold(file, |mut file| async move {
+        let mut buffer = vec![0u8; EXPORT_CHUNK_BYTES];
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        buffer.truncate(read);
+        Ok(Some((Bytes::from(buffer), file)))
+    });
+
+    let mut response = Response::new(Body::from_stream(body));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    if let Ok(value) = HeaderValue::from_str(&(size - query.offset).to_string()) {
+        response.headers_mut().insert(header::CONTENT_LENGTH, value);
+    }
+    response
+}
+
+/// `POST /internal/v1/slots/:slot_id/transfer/pull` fills an empty local
+/// slot from `source_node_id`, resuming an earlier pull when it can.
+pub(crate) async fn internal_pull_slot(
+    State(state): State<Arc<ServerState>>,
+    Path(slot_id): Path<u16>,
+    Json(request): Json<SlotTransferPullRequest>,
+) -> Response {
+    let source_node_id = request.source_node_id.trim().to_string();
+    if source_node_id.is_empty() {
+        return response_error(StatusCode::BAD_REQUEST, "source_node_id is required");
+    }
+
+    let result = state
+        .slot_transfer
+        .run(SlotTransferOperationRequest {
+            slot_id,
+            source_node_id,
+        })
+        .await;
+
+    match result {
+        Ok(result) => Json(result).into_response(),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::CONFLICT, message),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
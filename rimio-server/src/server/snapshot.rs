@@ -0,0 +1,188 @@
+use super::external::{MAX_LIST_LIMIT, parse_range_header};
+use super::{
+    HealthResponse, ListItem, ListQuery, ListResponse, normalize_blob_path,
+    range_not_satisfiable_response, response_error,
+};
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use rimio_core::{MountedSnapshot, Result, RimError, SnapshotBlob};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+const SNAPSHOT_NODE_ID: &str = "snapshot";
+
+/// Serves a mounted snapshot through the regular read API. There is no
+/// registry, placement or write path: every write is answered with 405.
+pub async fn run_snapshot_server(dir: PathBuf, bind_addr: String) -> Result<()> {
+    let snapshot = Arc::new(MountedSnapshot::open(&dir)?);
+    tracing::info!(
+        "Serving snapshot '{}' from {} ({} blobs, created {})",
+        snapshot.name(),
+        dir.display(),
+        snapshot.len(),
+        snapshot.created_at().to_rfc3339()
+    );
+
+    let app = Router::new()
+        .route("/health", get(snapshot_health))
+        .route("/_/health", get(snapshot_health))
+        .route("/_/api/v1/healthz", get(snapshot_health))
+        .route("/_/api/v1/readyz", get(snapshot_health))
+        .route("/_/api/v1/blobs", get(snapshot_list_blobs))
+        .route(
+            "/_/api/v1/blobs/*path",
+            get(snapshot_get_blob)
+                .head(snapshot_head_blob)
+                .put(snapshot_read_only)
+                .delete(snapshot_read_only),
+        )
+        .with_state(snapshot);
+
+    let listener = TcpListener::bind(&bind_addr).await?;
+    tracing::info!("Rimio snapshot listening on {}", bind_addr);
+
+    axum::serve(listener, app)
+        .await
+        .map_err(|error| RimError::Http(error.to_string()))
+}
+
+async fn snapshot_health(State(snapshot): State<Arc<MountedSnapshot>>) -> impl IntoResponse {
+    Json(HealthResponse {
+        status: "ok".to_string(),
+        node_id: SNAPSHOT_NODE_ID.to_string(),
+        group_id: snapshot.name().to_string(),
+    })
+}
+
+async fn snapshot_read_only() -> Response {
+    response_error(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "node serves a read-only snapshot",
+    )
+}
+
+async fn snapshot_list_blobs(
+    State(snapshot): State<Arc<MountedSnapshot>>,
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
+    let (blobs, next_cursor) = snapshot.list(
+        &query.prefix,
+        query.limit.clamp(1, MAX_LIST_LIMIT),
+        query.cursor.as_deref(),
+    );
+    let items = blobs
+        .into_iter()
+        .map(|blob| ListItem {
+            path: blob.path.clone(),
+            generation: 1,
+            etag: blob.etag.clone(),
+            size_bytes: blob.size_bytes,
+            deleted: false,
+            updated_at: blob.updated_at.to_rfc3339(),
+            tags: BTreeMap::new(),
+        })
+        .collect();
+
+    Json(ListResponse {
+        items,
+        truncated: next_cursor.is_some(),
+        next_cursor,
+    })
+}
+
+async fn snapshot_get_blob(
+    State(snapshot): State<Arc<MountedSnapshot>>,
+    Path(raw_path): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let path = match normalize_blob_path(&raw_path) {
+        Ok(path) => path,
+        Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+
+    let requested_range = match parse_range_header(&headers) {
+        Ok(range) => range,
+        Err(message) => return response_error(StatusCode::RANGE_NOT_SATISFIABLE, message),
+    };
+    let Some(blob) = snapshot.get(&path) else {
+        return response_error(StatusCode::NOT_FOUND, "object not found");
+    };
+    let range = match requested_range.map(|spec| spec.resolve(blob.size_bytes)) {
+        Some(Some(range)) => Some(range),
+        Some(None) => return range_not_satisfiable_response(blob.size_bytes),
+        None => None,
+    };
+
+    let (body, range) = match snapshot.read(&path, range).await {
+        Ok(Some(read)) => read,
+        Ok(None) => return response_error(StatusCode::NOT_FOUND, "object not found"),
+        Err(RimError::InvalidRequest(message)) => {
+            return response_error(StatusCode::RANGE_NOT_SATISFIABLE, message);
+        }
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    let body_len = body.len();
+    let mut response = Response::new(body.into());
+    *response.status_mut() = if requested_range.is_some() {
+        StatusCode::PARTIAL_CONTENT
+    } else {
+        StatusCode::OK
+    };
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    if let Ok(value) = HeaderValue::from_str(&body_len.to_string()) {
+        response.headers_mut().insert(header::CONTENT_LENGTH, value);
+    }
+    insert_blob_headers(&mut response, blob);
+
+    if requested_range.is_some() {
+        let content_range = format!("bytes {}-{}/{}", range.start, range.end, blob.size_bytes);
+        if let Ok(value) = HeaderValue::from_str(&content_range) {
+            response.headers_mut().insert(header::CONTENT_RANGE, value);
+        }
+    }
+
+    response
+}
+
+async fn snapshot_head_blob(
+    State(snapshot): State<Arc<MountedSnapshot>>,
+    Path(raw_path): Path<String>,
+) -> Response {
+    let path = match normalize_blob_path(&raw_path) {
+        Ok(path) => path,
+        Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+    let Some(blob) = snapshot.get(&path) else {
+        return response_error(StatusCode::NOT_FOUND, "object not found");
+    };
+
+    let mut response = Response::new(axum::body::Body::empty());
+    if let Ok(value) = HeaderValue::from_str(&blob.size_bytes.to_string()) {
+        response.headers_mut().insert(header::CONTENT_LENGTH, value);
+    }
+    insert_blob_headers(&mut response, blob);
+    response
+}
+
+fn insert_blob_headers(response: &mut Response, blob: &SnapshotBlob) {
+    if let Ok(value) = HeaderValue::from_str(&blob.etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+        .headers_mut()
+        .insert("x-rimio-generation", HeaderValue::from_static("1"));
+    response
+        .headers_mut()
+        .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+}
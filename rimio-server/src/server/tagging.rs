@@ -0,0 +1,158 @@
+use super::external::read_quorum_error;
+use super::{
+    BlobTagsBody, BlobTagsResponse, RoutingHints, ServerState, archive_unavailable_response,
+    claim_write_lease, refuse_frozen_write, refuse_unarchived_write, response_error,
+    route_blob_request,
+};
+use axum::{
+    Json,
+    body::Bytes,
+    http::{HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use rimio_core::{
+    PutBlobTagsOperationOutcome, PutBlobTagsOperationRequest, ReadBlobOperationOutcome,
+    ReadBlobOperationRequest, ReadConsistency, RimError,
+};
+use std::collections::BTreeMap;
+
+/// `GET /_/api/v1/blobs/{path}?tagging` returns the tag set of the live
+/// version.
+pub(crate) async fn get_blob_tags(
+    state: &ServerState,
+    path: String,
+    headers: &HeaderMap,
+) -> Response {
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let hints = RoutingHints::from_headers(headers);
+    let replicas = match route_blob_request(state, slot_id, &hints).await {
+        Ok(replicas) => replicas,
+        Err(response) => return response,
+    };
+
+    let outcome = state
+        .read_blob_operation
+        .run(ReadBlobOperationRequest {
+            slot_id,
+            path: path.clone(),
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
+            include_body: false,
+            range: None,
+            if_range: None,
+            consistency: ReadConsistency::Quorum,
+        })
+        .await;
+
+    match outcome {
+        Ok(ReadBlobOperationOutcome::Found(result)) => Json(BlobTagsResponse {
+            path,
+            generation: result.meta.generation,
+            tags: result.meta.tags,
+        })
+        .into_response(),
+        Ok(ReadBlobOperationOutcome::NotFound) => {
+            response_error(StatusCode::NOT_FOUND, "object not found")
+        }
+        Ok(ReadBlobOperationOutcome::Deleted) => response_error(StatusCode::GONE, "object deleted"),
+        Err(RimError::InsufficientReplicas { required, found }) => {
+            read_quorum_error(required, found)
+        }
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// `PUT /_/api/v1/blobs/{path}?tagging` replaces the tag set with the
+/// `tags` of the JSON body.
+pub(crate) async fn put_blob_tags(
+    state: &ServerState,
+    path: String,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Response {
+    let request: BlobTagsBody = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(error) => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                format!("invalid tagging body: {}", error),
+            );
+        }
+    };
+    replace_blob_tags(state, path, uri, headers, request.tags).await
+}
+
+/// Writes `tags` as the tag set of `path`. The tags are part of the head, so
+/// this commits a new version with the same body and metadata; `DELETE
+/// ?tagging` is the same with no tags.
+pub(crate) async fn replace_blob_tags(
+    state: &ServerState,
+    path: String,
+    uri: &Uri,
+    headers: &HeaderMap,
+    tags: BTreeMap<String, String>,
+) -> Response {
+    if let Some(response) = refuse_unarchived_write(state).await {
+        return response;
+    }
+
+    let slot_id = state.config.replication.slot_for_key(&path);
+    let hints = RoutingHints::from_request(headers, uri);
+    let replicas = match route_blob_request(state, slot_id, &hints).await {
+        Ok(replicas) => replicas,
+        Err(response) => return response,
+    };
+
+    if let Some(response) = refuse_frozen_write(state, slot_id).await {
+        return response;
+    }
+
+    let _write_guard = match claim_write_lease(state, slot_id, &replicas, uri, &hints).await {
+        Ok(guard) => guard,
+        Err(response) => return response,
+    };
+
+    let outcome = state
+        .put_blob_tags_operation
+        .run(PutBlobTagsOperationRequest {
+            path: path.clone(),
+            slot_id,
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
+            tags: tags.clone(),
+        })
+        .await;
+
+    match outcome {
+        Ok(PutBlobTagsOperationOutcome::Updated(result)) => Json(BlobTagsResponse {
+            path,
+            generation: result.generation,
+            tags,
+        })
+        .into_response(),
+        Ok(PutBlobTagsOperationOutcome::NotFound) => {
+            response_error(StatusCode::NOT_FOUND, "object not found")
+        }
+        Ok(PutBlobTagsOperationOutcome::Deleted) => {
+            response_error(StatusCode::GONE, "object deleted")
+        }
+        Ok(PutBlobTagsOperationOutcome::Conflict) => response_error(
+            StatusCode::CONFLICT,
+            "object changed while its tags were written",
+        ),
+        Err(RimError::InvalidRequest(message)) => response_error(StatusCode::BAD_REQUEST, message),
+        Err(RimError::InsufficientReplicas { required, found }) => response_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "quorum not reached: required={}, committed={}",
+                required, found
+            ),
+        ),
+        Err(RimError::ArchiveUnavailable {
+            message,
+            retry_after,
+        }) => archive_unavailable_response(message, retry_after),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
@@ -0,0 +1,37 @@
+use super::{ServerState, lifecycle::held_slots, response_error};
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use rimio_core::{ArchiveUploadReport, Result};
+use std::sync::Arc;
+
+/// `POST /_/api/v1/archive/tier` runs a tiering pass on this node now
+/// instead of waiting for the next one.
+pub(crate) async fn v1_run_tiering(State(state): State<Arc<ServerState>>) -> Response {
+    if state.archive_uploader.is_none() {
+        return response_error(StatusCode::NOT_FOUND, "archive tiering is not configured");
+    }
+
+    match run_tiering(&state).await {
+        Ok(report) => Json(report).into_response(),
+        Err(error) => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
+/// Tiers the cold parts of the slots this node holds.
+pub(crate) async fn run_tiering(state: &ServerState) -> Result<ArchiveUploadReport> {
+    let Some(uploader) = state.archive_uploader.as_ref() else {
+        return Ok(ArchiveUploadReport::default());
+    };
+
+    let rules = state.registry.get_lifecycle_rules().await?;
+    let slot_ids: Vec<u16> = held_slots(state)
+        .await?
+        .into_iter()
+        .map(|slot| slot.slot_id)
+        .collect();
+    uploader.run_once(&slot_ids, &rules).await
+}
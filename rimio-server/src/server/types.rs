@@ -1,12 +1,24 @@
-use rimio_core::{BlobMeta, ClusterState, TombstoneMeta};
+use crate::config::RegistryBackend;
+use chrono::{DateTime, Utc};
+use rimio_core::{
+    ApiKey, ApplyLifecycleOperationResult, BlobMeta, ClusterState, CompletedPart,
+    DeletePrefixOperationResult, DeletePrefixProgress, HeadChainReport, HealTombstoneItem,
+    InDoubtResolution, JobRecord, LifecycleRule, NodeInfo, PartSource, PeerProtocol,
+    PruneVersionsOperationResult, RepairDeadLetter, RepairRecord, RepairStats, SlotChange,
+    SlotHealth, SlotInfo, SlotLease, SqliteStats, TombstoneMeta, TransactionPeers,
+    TransactionState,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct PutCacheEntry {
     pub(crate) generation: i64,
     pub(crate) etag: String,
     pub(crate) size_bytes: u64,
     pub(crate) committed_replicas: usize,
+    pub(crate) acked_replicas: Vec<String>,
+    pub(crate) coordinator: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -46,6 +58,32 @@ pub(crate) struct ResolveSlotResponse {
     pub(crate) write_quorum: usize,
 }
 
+#[derive(Debug, Serialize)]
+pub(crate) struct SlotResponse {
+    pub(crate) slot_id: u16,
+    pub(crate) epoch: u64,
+    pub(crate) primary: String,
+    pub(crate) write_quorum: usize,
+    pub(crate) data_size_bytes: u64,
+    pub(crate) replicas: Vec<SlotReplicaItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SlotReplicaItem {
+    pub(crate) node_id: String,
+    pub(crate) address: String,
+    pub(crate) status: String,
+    pub(crate) reachable: bool,
+    pub(crate) blob_count: Option<u64>,
+    pub(crate) data_size_bytes: Option<u64>,
+    pub(crate) last_write_at: Option<String>,
+    pub(crate) lag_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) sqlite: Option<SqliteStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub(crate) struct PutBlobResponse {
     pub(crate) path: String,
@@ -54,25 +92,120 @@ pub(crate) struct PutBlobResponse {
     pub(crate) etag: String,
     pub(crate) size_bytes: u64,
     pub(crate) committed_replicas: usize,
+    /// Nodes that held the committed generation when the write was
+    /// acknowledged.
+    pub(crate) acked_replicas: Vec<String>,
+    /// Node that coordinated the write.
+    pub(crate) coordinator: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) idempotent_replay: Option<bool>,
 }
 
+#[derive(Debug, Serialize)]
+pub(crate) struct CommitBatchResponse {
+    pub(crate) slot_id: u16,
+    pub(crate) committed_replicas: usize,
+    pub(crate) items: Vec<CommitBatchResponseItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CommitBatchResponseItem {
+    pub(crate) path: String,
+    pub(crate) deleted: bool,
+    pub(crate) generation: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) etag: Option<String>,
+    pub(crate) size_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TransactionResponse {
+    pub(crate) txn_id: String,
+    pub(crate) state: String,
+    pub(crate) created_at: String,
+    pub(crate) entries: Vec<TransactionEntryItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TransactionEntryItem {
+    pub(crate) path: String,
+    pub(crate) op: String,
+    pub(crate) size_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TransactionCommitResponse {
+    pub(crate) txn_id: String,
+    pub(crate) state: String,
+    pub(crate) slots: Vec<TransactionSlotItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TransactionSlotItem {
+    pub(crate) slot_id: u16,
+    pub(crate) write_quorum: usize,
+    pub(crate) committed_replicas: usize,
+    pub(crate) items: Vec<CommitBatchResponseItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TransactionAbortResponse {
+    pub(crate) txn_id: String,
+    pub(crate) state: String,
+    pub(crate) error: String,
+    pub(crate) votes: Vec<TransactionVoteItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TransactionVoteItem {
+    pub(crate) slot_id: u16,
+    pub(crate) node_id: String,
+    pub(crate) vote: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reason: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct ListQuery {
     #[serde(default)]
     pub(crate) prefix: String,
+    /// Items per page, at most 1000.
     #[serde(default = "default_limit")]
     pub(crate) limit: usize,
-    #[serde(default)]
+    /// The `next_cursor` of the previous page; `marker` is accepted too.
+    #[serde(default, alias = "marker")]
     pub(crate) cursor: Option<String>,
     #[serde(default)]
     pub(crate) include_deleted: bool,
+    /// `json` (default) or `ndjson`, one item per line.
+    #[serde(default = "default_list_format")]
+    pub(crate) format: String,
+    /// Walk every page from the cursor in one NDJSON response.
+    #[serde(default)]
+    pub(crate) stream: bool,
+    /// `key=value`: only list blobs carrying this tag.
+    #[serde(default)]
+    pub(crate) tag: Option<String>,
+    /// `path` (default) or `updated_at`, oldest write first.
+    #[serde(default)]
+    pub(crate) order_by: Option<String>,
+    /// Only list blobs last written at or after this RFC 3339 time.
+    #[serde(default)]
+    pub(crate) since: Option<DateTime<Utc>>,
+    /// Only list blobs last written before this RFC 3339 time.
+    #[serde(default)]
+    pub(crate) until: Option<DateTime<Utc>>,
+}
+
+fn default_list_format() -> String {
+    "json".to_string()
 }
 
 #[derive(Debug, Serialize)]
 pub(crate) struct ListResponse {
     pub(crate) items: Vec<ListItem>,
+    /// Whether more items follow `next_cursor`.
+    pub(crate) truncated: bool,
     pub(crate) next_cursor: Option<String>,
 }
 
@@ -84,6 +217,489 @@ pub(crate) struct ListItem {
     pub(crate) size_bytes: u64,
     pub(crate) deleted: bool,
     pub(crate) updated_at: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AuditExportQuery {
+    #[serde(default)]
+    pub(crate) prefix: String,
+    #[serde(default = "default_export_format")]
+    pub(crate) format: String,
+}
+
+fn default_export_format() -> String {
+    "csv".to_string()
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct PeersQuery {
+    #[serde(default)]
+    pub(crate) format: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct PruneQuery {
+    #[serde(default)]
+    pub(crate) dry_run: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SlotTransferPullRequest {
+    pub(crate) source_node_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SlotTransferFileQuery {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) offset: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DecommissionQuery {
+    #[serde(default)]
+    pub(crate) dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct RegistryStateResponse {
+    pub(crate) backend: RegistryBackend,
+    pub(crate) namespace: String,
+    pub(crate) bootstrap: Option<ClusterState>,
+    pub(crate) nodes: Vec<NodeInfo>,
+    pub(crate) slot_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RegistrySlotsQuery {
+    /// Also read each slot's health reports and lease.
+    #[serde(default)]
+    pub(crate) details: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct RegistrySlotItem {
+    pub(crate) slot_id: u16,
+    /// Slot record as stored; `None` when the registry has none.
+    pub(crate) slot: Option<SlotInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) health: Option<Vec<SlotHealth>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) lease: Option<SlotLease>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EndpointsQuery {
+    /// `srv` answers with zone-file SRV records instead of JSON.
+    #[serde(default)]
+    pub(crate) format: Option<String>,
+    /// Zone the SRV records are named under; required with `format=srv`.
+    #[serde(default)]
+    pub(crate) domain: Option<String>,
+    #[serde(default = "default_endpoints_ttl_secs")]
+    pub(crate) ttl_secs: u32,
+}
+
+fn default_endpoints_ttl_secs() -> u32 {
+    30
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EndpointsResponse {
+    pub(crate) total_slots: u16,
+    pub(crate) ranges: Vec<SlotRangeEndpoints>,
+}
+
+/// Contiguous slots served by the same replicas, primary first.
+#[derive(Debug, Serialize)]
+pub(crate) struct SlotRangeEndpoints {
+    pub(crate) first_slot: u16,
+    pub(crate) last_slot: u16,
+    pub(crate) endpoints: Vec<SlotEndpoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct SlotEndpoint {
+    pub(crate) node_id: String,
+    pub(crate) address: String,
+    pub(crate) primary: bool,
+    pub(crate) status: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PruneSlotResponse {
+    pub(crate) slot_id: u16,
+    pub(crate) dry_run: bool,
+    pub(crate) replicas: Vec<PruneReplicaItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PruneReplicaItem {
+    pub(crate) node_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) result: Option<PruneVersionsOperationResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct HeadChainResponse {
+    pub(crate) slot_id: u16,
+    /// Every replica that answered has intact chains with the same digest.
+    pub(crate) consistent: bool,
+    pub(crate) replicas: Vec<HeadChainReplicaItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct HeadChainReplicaItem {
+    pub(crate) node_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) report: Option<HeadChainReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+/// Query parameters that turn blob requests into multipart upload calls.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct UploadQuery {
+    /// Present (usually empty) on `POST ?uploads` to start an upload.
+    #[serde(default)]
+    pub(crate) uploads: Option<String>,
+    #[serde(default)]
+    pub(crate) upload_id: Option<String>,
+    #[serde(default)]
+    pub(crate) part_number: Option<u32>,
+    #[serde(default)]
+    pub(crate) part_size: Option<u64>,
+}
+
+/// Query parameters of blob reads.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct BlobReadQuery {
+    /// Present (usually empty) on `GET ?manifest` to list the parts instead
+    /// of the body.
+    #[serde(default)]
+    pub(crate) manifest: Option<String>,
+    /// S3-style response header overrides, e.g.
+    /// `?response-content-disposition=attachment`.
+    #[serde(default, rename = "response-cache-control")]
+    pub(crate) response_cache_control: Option<String>,
+    #[serde(default, rename = "response-content-disposition")]
+    pub(crate) response_content_disposition: Option<String>,
+    #[serde(default, rename = "response-content-encoding")]
+    pub(crate) response_content_encoding: Option<String>,
+    #[serde(default, rename = "response-content-language")]
+    pub(crate) response_content_language: Option<String>,
+    #[serde(default, rename = "response-content-type")]
+    pub(crate) response_content_type: Option<String>,
+    #[serde(default, rename = "response-expires")]
+    pub(crate) response_expires: Option<String>,
+}
+
+/// Present (usually empty) as `?tagging` to address the tag set of a blob
+/// instead of its body.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct TaggingQuery {
+    #[serde(default)]
+    pub(crate) tagging: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BlobTagsBody {
+    #[serde(default)]
+    pub(crate) tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct BlobTagsResponse {
+    pub(crate) path: String,
+    pub(crate) generation: i64,
+    pub(crate) tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UploadResponse {
+    pub(crate) upload_id: String,
+    pub(crate) path: String,
+    pub(crate) slot_id: u16,
+    pub(crate) part_size: u64,
+    pub(crate) expires_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UploadPartItem {
+    pub(crate) part_number: u32,
+    pub(crate) sha256: String,
+    pub(crate) size_bytes: u64,
+    pub(crate) uploaded_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UploadPartsResponse {
+    pub(crate) upload_id: String,
+    pub(crate) path: String,
+    pub(crate) part_size: u64,
+    pub(crate) parts: Vec<UploadPartItem>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct CompleteUploadBody {
+    #[serde(default)]
+    pub(crate) parts: Option<Vec<CompletedPart>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RenameBody {
+    pub(crate) source: String,
+    pub(crate) destination: String,
+    /// Replace an existing destination instead of answering 409.
+    #[serde(default)]
+    pub(crate) overwrite: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct RenameResponse {
+    pub(crate) source: String,
+    pub(crate) destination: String,
+    pub(crate) generation: i64,
+    pub(crate) etag: Option<String>,
+    pub(crate) size_bytes: u64,
+    /// Generation of the tombstone left at the source.
+    pub(crate) source_generation: i64,
+    /// Whether both paths were switched by one transaction; false when they
+    /// live in different slots.
+    pub(crate) atomic: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeletePrefixBody {
+    pub(crate) prefix: String,
+    /// Count the blobs that would be deleted without deleting them.
+    #[serde(default)]
+    pub(crate) dry_run: bool,
+    #[serde(default)]
+    pub(crate) page_size: Option<usize>,
+}
+
+/// A prefix delete of one slot handed to its write lease holder.
+#[derive(Debug, Deserialize)]
+pub(crate) struct InternalDeletePrefixBody {
+    pub(crate) prefix: String,
+    pub(crate) page_size: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeletePrefixQuery {
+    /// `json` (default) for the final report, `ndjson` for a progress line
+    /// per page followed by the report, or `job` to run it as a job and
+    /// answer at once.
+    #[serde(default = "default_list_format")]
+    pub(crate) format: String,
+}
+
+/// One line of a streamed prefix delete.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum DeletePrefixEvent {
+    Progress(DeletePrefixProgress),
+    Done(DeletePrefixOperationResult),
+    Failed { error: String },
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SlotChangesQuery {
+    /// The last `seq` already seen; 0 reads the feed from the start.
+    #[serde(default)]
+    pub(crate) since: i64,
+    /// Stop after this many changes.
+    #[serde(default)]
+    pub(crate) limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct WatchQuery {
+    /// Only changes to paths under this prefix are sent.
+    #[serde(default)]
+    pub(crate) prefix: String,
+}
+
+/// One change pushed to a watcher.
+#[derive(Debug, Serialize)]
+pub(crate) struct WatchEvent {
+    pub(crate) slot_id: u16,
+    #[serde(flatten)]
+    pub(crate) change: SlotChange,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct OciQuery {
+    /// Digest a finished upload must have.
+    #[serde(default)]
+    pub(crate) digest: Option<String>,
+    /// Digest of a blob to link instead of uploading it again.
+    #[serde(default)]
+    pub(crate) mount: Option<String>,
+    /// Page size of a tag listing.
+    #[serde(default)]
+    pub(crate) n: Option<usize>,
+    /// Tag a tag listing starts after.
+    #[serde(default)]
+    pub(crate) last: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct OciErrorResponse {
+    pub(crate) errors: Vec<OciError>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct OciError {
+    pub(crate) code: &'static str,
+    pub(crate) message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct OciTagList {
+    pub(crate) name: String,
+    pub(crate) tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct JobsQuery {
+    #[serde(default)]
+    pub(crate) kind: Option<String>,
+}
+
+/// A job and, while it runs, its latest progress.
+#[derive(Debug, Serialize)]
+pub(crate) struct JobStatusResponse {
+    #[serde(flatten)]
+    pub(crate) job: JobRecord,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) progress: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct JobsResponse {
+    pub(crate) jobs: Vec<JobStatusResponse>,
+}
+
+/// What watchers of a job are told.
+#[derive(Debug, Clone)]
+pub(crate) enum JobEvent {
+    Progress(serde_json::Value),
+    Done(serde_json::Value),
+    Failed(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct LifecycleRulesBody {
+    pub(crate) rules: Vec<LifecycleRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ApiKeysBody {
+    pub(crate) keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UploadTokenRequest {
+    pub(crate) path: String,
+    pub(crate) max_bytes: u64,
+    /// Defaults to `auth.upload_tokens.max_ttl_secs`.
+    #[serde(default)]
+    pub(crate) ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UploadTokenResponse {
+    pub(crate) token: String,
+    pub(crate) path: String,
+    pub(crate) max_bytes: u64,
+    pub(crate) expires_at: DateTime<Utc>,
+    /// Where to `PUT` the blob, relative to any node.
+    pub(crate) url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct LifecycleRunQuery {
+    #[serde(default)]
+    pub(crate) dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct LifecycleRunResponse {
+    pub(crate) node_id: String,
+    pub(crate) dry_run: bool,
+    pub(crate) rules: usize,
+    /// Slots where a rule was due; the rest are left out.
+    pub(crate) slots: Vec<ApplyLifecycleOperationResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RepairsQuery {
+    /// Lists repairs older than this id, to page back through the history.
+    pub(crate) before: Option<i64>,
+    #[serde(default)]
+    pub(crate) failed: bool,
+    pub(crate) limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct RepairsResponse {
+    pub(crate) repairs: Vec<RepairRecord>,
+    pub(crate) next_before: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeadLettersQuery {
+    pub(crate) limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct DeadLettersResponse {
+    pub(crate) dead_letters: Vec<RepairDeadLetter>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReleaseDeadLetterQuery {
+    pub(crate) slot_id: u16,
+    pub(crate) path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ReleaseDeadLetterResponse {
+    pub(crate) released: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RepairStatsQuery {
+    pub(crate) window_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct RepairStatsResponse {
+    pub(crate) node_id: String,
+    pub(crate) window_secs: u64,
+    pub(crate) total: RepairStats,
+    pub(crate) sources: Vec<RepairStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SlotFreezeResponse {
+    pub(crate) slot_id: u16,
+    pub(crate) frozen: bool,
+    pub(crate) epoch: u64,
+    /// Peers that could not be told to reload the slot; they pick the change
+    /// up on their next placement refresh.
+    pub(crate) unreachable: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct InternalPlacementQuery {
+    pub(crate) epoch: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -105,6 +721,13 @@ pub(crate) struct InternalPartPutResponse {
     pub(crate) sha256: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub(crate) struct InternalLinkPartsRequest {
+    pub(crate) source: PartSource,
+    pub(crate) path: String,
+    pub(crate) generation: i64,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub(crate) struct InternalHeadApplyRequest {
     pub(crate) head_kind: String,
@@ -124,6 +747,56 @@ pub(crate) struct InternalHeadResponse {
     pub(crate) tombstone: Option<TombstoneMeta>,
 }
 
+#[derive(Debug, Deserialize)]
+pub(crate) struct InternalHeadBatchApplyItem {
+    pub(crate) path: String,
+    pub(crate) head_kind: String,
+    pub(crate) generation: i64,
+    pub(crate) head_sha256: String,
+    pub(crate) meta: Option<BlobMeta>,
+    pub(crate) tombstone: Option<TombstoneMeta>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct InternalHeadBatchApplyRequest {
+    pub(crate) heads: Vec<InternalHeadBatchApplyItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct InternalHeadBatchApplyResponse {
+    pub(crate) applied: bool,
+    pub(crate) heads: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct InternalPrepareRequest {
+    pub(crate) peers: TransactionPeers,
+    pub(crate) heads: Vec<InternalHeadBatchApplyItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct InternalTransactionStateResponse {
+    pub(crate) txn_id: String,
+    pub(crate) state: TransactionState,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct InternalResolveResponse {
+    pub(crate) txn_id: String,
+    pub(crate) resolutions: Vec<InDoubtResolution>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct InternalPrepareResponse {
+    pub(crate) vote: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reason: Option<String>,
+    /// Transaction holding the paths; older coordinators read the vote as a
+    /// plain no.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) locked_by: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub(crate) struct InternalHeadApplyResponse {
     pub(crate) applied: bool,
@@ -170,12 +843,28 @@ pub(crate) struct HealHeadItem {
     pub(crate) head_sha256: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub(crate) struct HealTombstonesRequest {
+    pub(crate) paths: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct HealTombstonesResponse {
+    pub(crate) slot_id: u16,
+    pub(crate) tombstones: Vec<HealTombstoneItem>,
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct HealRepairRequest {
     pub(crate) source_node_id: String,
     pub(crate) blob_paths: Vec<String>,
     #[serde(default)]
     pub(crate) dry_run: bool,
+    /// Copy ahead of taking this replica's place in the slot: the slot need
+    /// not be placed here yet, and paths are checked against the replica set
+    /// after the swap.
+    #[serde(default)]
+    pub(crate) replacing: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -186,6 +875,25 @@ pub(crate) struct HealRepairResponse {
     pub(crate) errors: Vec<String>,
 }
 
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct ReadinessReport {
+    pub(crate) checked_at: Option<DateTime<Utc>>,
+    pub(crate) node_id: String,
+    /// Every required check passed.
+    pub(crate) ready: bool,
+    /// False while write-through is required and the archive is unreachable.
+    pub(crate) accepting_writes: bool,
+    pub(crate) checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ReadinessCheck {
+    pub(crate) component: String,
+    pub(crate) ok: bool,
+    pub(crate) required: bool,
+    pub(crate) detail: String,
+}
+
 #[derive(Debug, Serialize)]
 pub(crate) struct InternalBootstrapResponse {
     pub(crate) found: bool,
@@ -205,3 +913,30 @@ fn default_limit() -> usize {
 fn default_slotlet_prefix_len() -> usize {
     2
 }
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct MirrorStats {
+    pub(crate) endpoint: String,
+    pub(crate) read_percent: f64,
+    pub(crate) write_percent: f64,
+    pub(crate) mirrored: u64,
+    /// Samples skipped because too many shadow requests were pending or the
+    /// body was too large to buffer.
+    pub(crate) dropped: u64,
+    pub(crate) failed: u64,
+    pub(crate) status_mismatches: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ProtocolResponse {
+    pub(crate) node_id: String,
+    pub(crate) local: PeerProtocol,
+    pub(crate) peers: Vec<PeerProtocolItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PeerProtocolItem {
+    pub(crate) node_id: String,
+    #[serde(flatten)]
+    pub(crate) protocol: PeerProtocol,
+}
@@ -0,0 +1,408 @@
+use super::auth::{JwtHeader, check_lifetime, jwt_header, sign_hs256, unauthorized, verify_hs256};
+use super::conditional::EXPECTED_GENERATION_HEADER;
+use super::{
+    HOPS_QUERY_PARAM, ServerState, UploadTokenRequest, UploadTokenResponse, VIA_QUERY_PARAM,
+    query_pairs, response_error,
+};
+use crate::config::UploadTokensConfig;
+use axum::{
+    Extension, Json,
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::Limited;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use rimio_core::{ApiKey, Registry, normalize_blob_path};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// `typ` header of upload tokens, which sets them apart from key JWTs.
+const UPLOAD_TOKEN_TYPE: &str = "rimio-upload";
+
+const BLOB_ROUTE_PREFIX: &str = "/_/api/v1/blobs/";
+
+/// Characters escaped when a blob path is put in a URL.
+const PATH_ESCAPES: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// The claims of an upload token.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct UploadGrant {
+    /// Id under which the registry records the token once it is used.
+    jti: String,
+    /// Normalized blob path the token may create.
+    path: String,
+    max_bytes: u64,
+    exp: i64,
+    /// The key that minted the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+}
+
+/// `POST /_/api/v1/upload-tokens` mints a one-time token that lets its holder
+/// create one blob at `path` of at most `max_bytes`, without a key of its own. The
+/// calling key must be allowed to `PUT` that blob itself.
+pub(crate) async fn v1_create_upload_token(
+    State(state): State<Arc<ServerState>>,
+    key: Option<Extension<ApiKey>>,
+    body: Bytes,
+) -> Response {
+    let Some(config) = state.auth.as_ref().and_then(|auth| auth.upload_tokens()) else {
+        return response_error(StatusCode::NOT_FOUND, "upload tokens are not configured");
+    };
+    let request: UploadTokenRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(error) => {
+            return response_error(
+                StatusCode::BAD_REQUEST,
+                format!("invalid upload token request: {}", error),
+            );
+        }
+    };
+    let path = match normalize_blob_path(&request.path) {
+        Ok(path) => path,
+        Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+    if request.max_bytes == 0 {
+        return response_error(StatusCode::BAD_REQUEST, "max_bytes must be greater than 0");
+    }
+    let ttl_secs = request.ttl_secs.unwrap_or(config.max_ttl_secs);
+    if ttl_secs == 0 || ttl_secs > config.max_ttl_secs {
+        return response_error(
+            StatusCode::BAD_REQUEST,
+            format!("ttl_secs must be between 1 and {}", config.max_ttl_secs),
+        );
+    }
+
+    let url = format!(
+        "{}{}",
+        BLOB_ROUTE_PREFIX,
+        utf8_percent_encode(&path, PATH_ESCAPES)
+    );
+    let key = key.map(|Extension(key)| key);
+    if let Some(key) = &key
+        && !key.allows("PUT", &url)
+    {
+        return response_error(
+            StatusCode::FORBIDDEN,
+            format!("api key {} may not upload to {}", key.id, path),
+        );
+    }
+
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(ttl_secs as i64);
+    let grant = UploadGrant {
+        jti: ulid::Ulid::new().to_string(),
+        path,
+        max_bytes: request.max_bytes,
+        exp: expires_at.timestamp(),
+        sub: key.map(|key| key.id),
+    };
+    let header = JwtHeader {
+        alg: "HS256".to_string(),
+        typ: Some(UPLOAD_TOKEN_TYPE.to_string()),
+        kid: None,
+    };
+    let token = match sign_hs256(&header, &grant, &config.secret) {
+        Ok(token) => token,
+        Err(error) => return response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    };
+
+    (
+        StatusCode::CREATED,
+        Json(UploadTokenResponse {
+            token,
+            path: grant.path,
+            max_bytes: grant.max_bytes,
+            expires_at,
+            url,
+        }),
+    )
+        .into_response()
+}
+
+/// An admitted upload token that is not spent yet. The blob handler redeems
+/// it once it holds the slot's write lease, so a request redirected or
+/// proxied to the lease holder does not spend the token on the way there.
+#[derive(Clone)]
+pub(crate) struct UploadTicket {
+    registry: Arc<dyn Registry>,
+    jti: String,
+    exp: i64,
+}
+
+impl UploadTicket {
+    /// Records the token as used until it expires. Answers `401` when it
+    /// already was, and `503` when the registry cannot record it.
+    pub(crate) async fn redeem(&self) -> Option<Response> {
+        let ttl_secs = (self.exp - chrono::Utc::now().timestamp()).max(1) as u64;
+        match self
+            .registry
+            .consume_upload_token(&self.jti, ttl_secs)
+            .await
+        {
+            Ok(true) => None,
+            Ok(false) => Some(unauthorized("upload token was already used")),
+            Err(error) => Some(response_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("failed to record upload token: {}", error),
+            )),
+        }
+    }
+}
+
+pub(super) fn is_upload_token(token: &str) -> bool {
+    jwt_header(token).is_ok_and(|header| header.typ.as_deref() == Some(UPLOAD_TOKEN_TYPE))
+}
+
+pub(super) fn verify_upload_token(
+    config: &UploadTokensConfig,
+    token: &str,
+) -> std::result::Result<UploadGrant, String> {
+    let grant: UploadGrant = verify_hs256(token, &config.secret)?;
+    check_lifetime(grant.exp, None)?;
+    Ok(grant)
+}
+
+/// Lets through only the write a token grants: a `PUT` to its path that
+/// declares a length within `max_bytes`, with a body cut off there. Only the
+/// hop parameters of a redirect may ride in the query. The request carries
+/// an [`UploadTicket`] that the blob handler redeems before the write
+/// starts, so a replay on any node answers `401` even when the first upload
+/// failed or the blob was deleted since. The write must also create the
+/// blob.
+pub(super) async fn admit_upload(
+    registry: Arc<dyn Registry>,
+    grant: UploadGrant,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::PUT {
+        return response_error(StatusCode::FORBIDDEN, "upload tokens only allow PUT");
+    }
+    if query_pairs(request.uri())
+        .iter()
+        .any(|(key, _)| key != HOPS_QUERY_PARAM && key != VIA_QUERY_PARAM)
+    {
+        return response_error(
+            StatusCode::FORBIDDEN,
+            "upload tokens do not take query parameters",
+        );
+    }
+    let target = request
+        .uri()
+        .path()
+        .strip_prefix(BLOB_ROUTE_PREFIX)
+        .and_then(|raw| percent_decode_str(raw).decode_utf8().ok())
+        .and_then(|raw| normalize_blob_path(&raw).ok());
+    if target.as_deref() != Some(grant.path.as_str()) {
+        return response_error(
+            StatusCode::FORBIDDEN,
+            format!("upload token only allows {}", grant.path),
+        );
+    }
+
+    let Some(length) = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+    else {
+        return response_error(
+            StatusCode::LENGTH_REQUIRED,
+            "uploads with a token need a content-length",
+        );
+    };
+    if length > grant.max_bytes {
+        return response_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("upload token allows at most {} bytes", grant.max_bytes),
+        );
+    }
+
+    request
+        .headers_mut()
+        .insert(EXPECTED_GENERATION_HEADER, HeaderValue::from_static("0"));
+    request.extensions_mut().insert(UploadTicket {
+        registry,
+        jti: grant.jti,
+        exp: grant.exp,
+    });
+    let max_bytes = usize::try_from(grant.max_bytes).unwrap_or(usize::MAX);
+    let request = request.map(|body| Body::new(Limited::new(body, max_bytes)));
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::auth::{Authenticator, require_auth};
+    use super::*;
+    use crate::config::AuthConfig;
+    use axum::{Router, extract::OriginalUri, middleware, routing::put};
+    use rimio_core::registry::memory::MemoryRegistry;
+    use tower::ServiceExt;
+
+    fn token(config: &UploadTokensConfig, path: &str) -> String {
+        let grant = UploadGrant {
+            jti: ulid::Ulid::new().to_string(),
+            path: path.to_string(),
+            max_bytes: 16,
+            exp: chrono::Utc::now().timestamp() + 60,
+            sub: None,
+        };
+        let header = JwtHeader {
+            alg: "HS256".to_string(),
+            typ: Some(UPLOAD_TOKEN_TYPE.to_string()),
+            kid: None,
+        };
+        sign_hs256(&header, &grant, &config.secret).unwrap()
+    }
+
+    fn upload(token: &str, path: &str) -> Request {
+        upload_body(token, path, "4", "data")
+    }
+
+    fn upload_body(token: &str, path: &str, length: &str, body: &'static str) -> Request {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("{}{}", BLOB_ROUTE_PREFIX, path))
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_LENGTH, length)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn upload_tokens_config() -> UploadTokensConfig {
+        UploadTokensConfig {
+            secret: "upload-secret".to_string(),
+            max_ttl_secs: 3_600,
+        }
+    }
+
+    /// Routes blob `PUT`s to `handler` behind the auth middleware.
+    fn app<H, T>(config: &UploadTokensConfig, handler: H) -> Router
+    where
+        H: axum::handler::Handler<T, ()>,
+        T: 'static,
+    {
+        let auth: AuthConfig = serde_json::from_value(serde_json::json!({
+            "upload_tokens": config,
+        }))
+        .unwrap();
+        let auth = Arc::new(Authenticator::new(auth, Arc::new(MemoryRegistry::new())));
+        Router::new()
+            .route("/_/api/v1/blobs/*path", put(handler))
+            .layer(middleware::from_fn_with_state(auth, require_auth))
+    }
+
+    #[tokio::test]
+    async fn tokens_are_refused_after_their_first_use() {
+        let config = upload_tokens_config();
+        // The blob handler spends the token and then answers as if the blob
+        // never landed, so only the token record stands between a replay and
+        // a second upload.
+        let app = app(
+            &config,
+            |Extension(ticket): Extension<UploadTicket>| async move {
+                ticket
+                    .redeem()
+                    .await
+                    .unwrap_or_else(|| StatusCode::SERVICE_UNAVAILABLE.into_response())
+            },
+        );
+        let token = token(&config, "sensors/7.bin");
+
+        let first = app
+            .clone()
+            .oneshot(upload(&token, "sensors/7.bin"))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let replay = app
+            .clone()
+            .oneshot(upload(&token, "sensors/7.bin"))
+            .await
+            .unwrap();
+        assert_eq!(replay.status(), StatusCode::UNAUTHORIZED);
+
+        let elsewhere = app.oneshot(upload(&token, "sensors/8.bin")).await.unwrap();
+        assert_eq!(elsewhere.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn redirected_uploads_spend_the_token_on_the_lease_holder() {
+        let config = upload_tokens_config();
+        // Stands in for a node that does not hold the slot's lease: it sends
+        // the upload on with the hop parameters a real redirect carries.
+        let app = app(
+            &config,
+            |OriginalUri(uri): OriginalUri, Extension(ticket): Extension<UploadTicket>| async move {
+                if uri.query().is_none() {
+                    let location = format!("{}?rimio_hops=1&rimio_via=node-a", uri.path());
+                    return (
+                        StatusCode::TEMPORARY_REDIRECT,
+                        [(header::LOCATION, location)],
+                    )
+                        .into_response();
+                }
+                match ticket.redeem().await {
+                    Some(response) => response,
+                    None => StatusCode::CREATED.into_response(),
+                }
+            },
+        );
+        let token = token(&config, "sensors/7.bin");
+
+        let first = app
+            .clone()
+            .oneshot(upload(&token, "sensors/7.bin"))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::TEMPORARY_REDIRECT);
+        let location = first.headers()[header::LOCATION].to_str().unwrap();
+        let follow = |token: &str| {
+            let mut request = upload(token, "sensors/7.bin");
+            *request.uri_mut() = location.parse().unwrap();
+            request
+        };
+
+        let redirected = app.clone().oneshot(follow(&token)).await.unwrap();
+        assert_eq!(redirected.status(), StatusCode::CREATED);
+
+        let replay = app.clone().oneshot(follow(&token)).await.unwrap();
+        assert_eq!(replay.status(), StatusCode::UNAUTHORIZED);
+
+        let mut other_query = upload(&token, "sensors/7.bin");
+        *other_query.uri_mut() = format!("{}sensors/7.bin?tagging", BLOB_ROUTE_PREFIX)
+            .parse()
+            .unwrap();
+        let refused = app.oneshot(other_query).await.unwrap();
+        assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn bodies_past_max_bytes_are_cut_off() {
+        let config = upload_tokens_config();
+        let app = app(&config, |body: Bytes| async move { body.len().to_string() });
+        let token = token(&config, "sensors/7.bin");
+
+        // The declared length fits, the body does not.
+        let response = app
+            .oneshot(upload_body(
+                &token,
+                "sensors/7.bin",
+                "4",
+                "twenty bytes of data",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,237 @@
+use super::external::{parse_write_consistency, put_blob_response};
+use super::{
+    CompleteUploadBody, PutCacheEntry, RoutingHints, ServerState, UploadPartItem,
+    UploadPartsResponse, UploadQuery, UploadResponse, claim_write_lease, normalize_blob_path,
+    refuse_frozen_write, refuse_unarchived_write, response_error, route_blob_request,
+};
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{OriginalUri, Path, Query, State},
+    http::{HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use futures_util::TryStreamExt;
+use rimio_core::{CompleteUploadRequest, MultipartUpload, PutBlobOperationOutcome, RimError};
+use std::sync::Arc;
+
+/// `POST /_/api/v1/blobs/{path}` starts an upload with `?uploads` and
+/// completes one with `?upload_id=`.
+pub(crate) async fn v1_post_blob(
+    State(state): State<Arc<ServerState>>,
+    Path(raw_path): Path<String>,
+    Query(query): Query<UploadQuery>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = match normalize_blob_path(&raw_path) {
+        Ok(path) => path,
+        Err(error) => return response_error(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+
+    match (query.uploads.is_some(), query.upload_id) {
+        (true, None) => initiate_upload(&state, path, query.part_size),
+        (false, Some(upload_id)) => {
+            complete_upload(&state, path, upload_id, &uri, &headers, body).await
+        }
+        _ => response_error(
+            StatusCode::BAD_REQUEST,
+            "POST needs either ?uploads or ?upload_id=",
+        ),
+    }
+}
+
+fn initiate_upload(state: &ServerState, path: String, part_size: Option<u64>) -> Response {
+    let slot_id = state.config.replication.slot_for_key(&path);
+    match state.multipart_uploads.initiate(slot_id, &path, part_size) {
+        Ok(upload) => (StatusCode::CREATED, Json(upload_response(upload))).into_response(),
+        Err(error) => upload_error(error),
+    }
+}
+
+async fn complete_upload(
+    state: &ServerState,
+    path: String,
+    upload_id: String,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Response {
+    let request: CompleteUploadBody = if body.is_empty() {
+        CompleteUploadBody::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(error) => {
+                return response_error(
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid completion body: {}", error),
+                );
+            }
+        }
+    };
+
+    let upload = match state.multipart_uploads.get(&upload_id, &path) {
+        Ok(upload) => upload,
+        Err(error) => return upload_error(error),
+    };
+
+    if let Some(response) = refuse_unarchived_write(state).await {
+        return response;
+    }
+
+    let consistency = match parse_write_consistency(headers) {
+        Ok(consistency) => consistency,
+        Err(message) => return response_error(StatusCode::BAD_REQUEST, message),
+    };
+
+    let hints = RoutingHints::from_request(headers, uri);
+    let replicas = match route_blob_request(state, upload.slot_id, &hints).await {
+        Ok(replicas) => replicas,
+        Err(response) => return response,
+    };
+
+    if let Some(response) = refuse_frozen_write(state, upload.slot_id).await {
+        return response;
+    }
+
+    let _write_guard = match claim_write_lease(state, upload.slot_id, &replicas, uri, &hints).await
+    {
+        Ok(guard) => guard,
+        Err(response) => return response,
+    };
+
+    let outcome = state
+        .multipart_uploads
+        .complete(CompleteUploadRequest {
+            upload_id,
+            path: path.clone(),
+            replicas,
+            local_node_id: state.node.node_id().to_string(),
+            consistency,
+            parts: request.parts,
+        })
+        .await;
+
+    let result = match outcome {
+        Ok(PutBlobOperationOutcome::Committed(result)) => result,
+        Ok(PutBlobOperationOutcome::Conflict)
+        | Ok(PutBlobOperationOutcome::PreconditionFailed { .. }) => {
+            return response_error(
+                StatusCode::CONFLICT,
+                "meta commit rejected by generation check",
+            );
+        }
+        Err(error) => return upload_error(error),
+    };
+
+    put_blob_response(
+        StatusCode::CREATED,
+        path,
+        upload.slot_id,
+        PutCacheEntry {
+            generation: result.generation,
+            etag: result.etag,
+            size_bytes: result.size_bytes,
+            committed_replicas: result.committed_replicas,
+            acked_replicas: result.acked_replicas,
+            coordinator: state.node.node_id().to_string(),
+        },
+        None,
+    )
+}
+
+/// `PUT /_/api/v1/blobs/{path}?upload_id=&part_number=` stores one part.
+pub(crate) async fn upload_part(
+    state: &ServerState,
+    path: String,
+    upload_id: String,
+    part_number: Option<u32>,
+    body: Body,
+) -> Response {
+    let Some(part_number) = part_number else {
+        return response_error(StatusCode::BAD_REQUEST, "part_number is required");
+    };
+
+    let result = state
+        .multipart_uploads
+        .upload_part(
+            &upload_id,
+            &path,
+            part_number,
+            body.into_data_stream()
+                .map_err(|error| RimError::Http(error.to_string())),
+        )
+        .await;
+
+    match result {
+        Ok(part) => (StatusCode::OK, Json(part_item(part))).into_response(),
+        Err(error) => upload_error(error),
+    }
+}
+
+/// `GET /_/api/v1/blobs/{path}?upload_id=` lists the parts received so far.
+pub(crate) fn list_upload_parts(state: &ServerState, path: String, upload_id: String) -> Response {
+    let upload = match state.multipart_uploads.get(&upload_id, &path) {
+        Ok(upload) => upload,
+        Err(error) => return upload_error(error),
+    };
+    let parts = match state.multipart_uploads.list_parts(&upload_id, &path) {
+        Ok(parts) => parts,
+        Err(error) => return upload_error(error),
+    };
+
+    Json(UploadPartsResponse {
+        upload_id,
+        path,
+        part_size: upload.part_size,
+        parts: parts.into_iter().map(part_item).collect(),
+    })
+    .into_response()
+}
+
+/// `DELETE /_/api/v1/blobs/{path}?upload_id=` abandons an upload.
+pub(crate) async fn abort_upload(state: &ServerState, path: String, upload_id: String) -> Response {
+    match state.multipart_uploads.abort(&upload_id, &path).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(error) => upload_error(error),
+    }
+}
+
+fn upload_response(upload: MultipartUpload) -> UploadResponse {
+    UploadResponse {
+        upload_id: upload.upload_id,
+        path: upload.path,
+        slot_id: upload.slot_id,
+        part_size: upload.part_size,
+        expires_at: upload.expires_at.to_rfc3339(),
+    }
+}
+
+fn part_item(part: rimio_core::UploadPartRecord) -> UploadPartItem {
+    UploadPartItem {
+        part_number: part.part_number,
+        sha256: part.sha256,
+        size_bytes: part.size_bytes,
+        uploaded_at: part.uploaded_at.to_rfc3339(),
+    }
+}
+
+fn upload_error(error: RimError) -> Response {
+    match error {
+        RimError::UploadNotFound(upload_id) => response_error(
+            StatusCode::NOT_FOUND,
+            format!("upload not found: {}", upload_id),
+        ),
+        RimError::InvalidRequest(message) => response_error(StatusCode::BAD_REQUEST, message),
+        RimError::InsufficientReplicas { required, found } => response_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "quorum not reached: required={}, committed={}",
+                required, found
+            ),
+        ),
+        error => response_error(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
@@ -0,0 +1,335 @@
+use super::{ServerState, WatchEvent, WatchQuery, lifecycle::held_slots};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use rimio_core::{ChangeKind, ListSlotChangesOperationRequest, Result};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast;
+use tokio::time::{Duration, Instant, interval};
+
+/// How often the poller reads the change feeds of its slots.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How often the poller re-reads which slots this node is primary for.
+const WATCH_SLOTS_REFRESH: Duration = Duration::from_secs(30);
+/// Changes read from one slot per poll.
+const WATCH_PAGE_SIZE: usize = 500;
+/// Recent changes kept for watchers resuming with `Last-Event-ID`.
+const WATCH_REPLAY_EVENTS: usize = 4096;
+/// Changes a watcher may fall behind before its stream is ended.
+const WATCH_CHANNEL_CAPACITY: usize = 1024;
+/// How long the poller keeps following the feeds after the last watcher
+/// left, so one that reconnects can still resume.
+const WATCH_IDLE_GRACE: Duration = Duration::from_secs(60);
+
+/// `GET /_/api/v1/watch?prefix=` pushes every change under `prefix` as a
+/// server-sent event named after the change kind, from the slots this node
+/// is primary for. Event ids are `slot:seq`. A client reconnecting with
+/// `Last-Event-ID` gets the changes after that event when the node still
+/// buffers it; otherwise, as on a first connect, the stream starts at the
+/// newest change and a client that must not miss any lists the prefix
+/// again. A client too slow to keep up has its stream ended and resumes
+/// the same way.
+pub(crate) async fn v1_watch(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<WatchQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok());
+    let (backlog, receiver) = state.watch_hub.subscribe(&state, last_event_id);
+    let watch = Watch {
+        prefix: query.prefix,
+        backlog,
+        receiver,
+    };
+    let events = futures_util::stream::unfold(watch, |mut watch| async move {
+        let event = watch.next_event().await?;
+        Some((Ok::<_, Infallible>(event), watch))
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Fans the changes of one poller out to every watch on this node.
+pub(crate) struct WatchHub {
+    sender: broadcast::Sender<Arc<WatchEvent>>,
+    /// The newest changes sent, oldest first.
+    replay: Mutex<VecDeque<Arc<WatchEvent>>>,
+    started: AtomicBool,
+}
+
+impl WatchHub {
+    pub(crate) fn new() -> Self {
+        let (sender, _) = broadcast::channel(WATCH_CHANNEL_CAPACITY);
+        Self {
+            sender,
+            replay: Mutex::new(VecDeque::new()),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// Starts the poller on first use, then attaches a watcher.
+    fn subscribe(
+        self: &Arc<Self>,
+        state: &Arc<ServerState>,
+        last_event_id: Option<&str>,
+    ) -> (
+        VecDeque<Arc<WatchEvent>>,
+        broadcast::Receiver<Arc<WatchEvent>>,
+    ) {
+        if !self.started.swap(true, Ordering::AcqRel) {
+            let poller = Poller {
+                hub: self.clone(),
+                state: state.clone(),
+                cursors: HashMap::new(),
+                refreshed_at: None,
+                idle_since: None,
+            };
+            tokio::spawn(poller.run());
+        }
+        self.attach(last_event_id)
+    }
+
+    /// The buffered changes after `last_event_id`, if it is still buffered,
+    /// and a receiver for every change sent after them.
+    fn attach(
+        &self,
+        last_event_id: Option<&str>,
+    ) -> (
+        VecDeque<Arc<WatchEvent>>,
+        broadcast::Receiver<Arc<WatchEvent>>,
+    ) {
+        let replay = self.lock_replay();
+        let receiver = self.sender.subscribe();
+        let backlog = last_event_id
+            .and_then(|id| replay.iter().position(|event| event_id(event) == id))
+            .map(|index| replay.iter().skip(index + 1).cloned().collect())
+            .unwrap_or_default();
+        (backlog, receiver)
+    }
+
+    fn publish(&self, event: WatchEvent) {
+        let event = Arc::new(event);
+        let mut replay = self.lock_replay();
+        if replay.len() == WATCH_REPLAY_EVENTS {
+            replay.pop_front();
+        }
+        replay.push_back(event.clone());
+        let _ = self.sender.send(event);
+    }
+
+    fn forget(&self) {
+        self.lock_replay().clear();
+    }
+
+    fn lock_replay(&self) -> MutexGuard<'_, VecDeque<Arc<WatchEvent>>> {
+        self.replay
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// One client's view of the hub.
+struct Watch {
+    prefix: String,
+    backlog: VecDeque<Arc<WatchEvent>>,
+    receiver: broadcast::Receiver<Arc<WatchEvent>>,
+}
+
+impl Watch {
+    /// The next change under the prefix; `None` ends the stream.
+    async fn next_event(&mut self) -> Option<Event> {
+        loop {
+            let event = match self.backlog.pop_front() {
+                Some(event) => event,
+                None => match self.receiver.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(
+                            "watch fell behind and was ended: prefix={} skipped={}",
+                            self.prefix,
+                            skipped
+                        );
+                        return None;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+            };
+            if event.change.path.starts_with(&self.prefix) {
+                return Some(watch_event(&event));
+            }
+        }
+    }
+}
+
+/// Reads the change feeds of the slots this node is primary for and hands
+/// every change to the hub.
+struct Poller {
+    hub: Arc<WatchHub>,
+    state: Arc<ServerState>,
+    /// The last `seq` read per slot.
+    cursors: HashMap<u16, i64>,
+    refreshed_at: Option<Instant>,
+    idle_since: Option<Instant>,
+}
+
+impl Poller {
+    async fn run(mut self) {
+        let mut ticker = interval(WATCH_POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if self.hub.sender.receiver_count() == 0 {
+                let idle_since = *self.idle_since.get_or_insert_with(Instant::now);
+                if idle_since.elapsed() >= WATCH_IDLE_GRACE {
+                    // Nobody can resume from the buffer any more; the next
+                    // watcher starts at the newest change again.
+                    if self.refreshed_at.take().is_some() {
+                        self.cursors.clear();
+                        self.hub.forget();
+                    }
+                    continue;
+                }
+            } else {
+                self.idle_since = None;
+            }
+            if let Err(error) = self.poll().await {
+                tracing::warn!("watch poll failed: {}", error);
+            }
+        }
+    }
+
+    async fn poll(&mut self) -> Result<()> {
+        if self
+            .refreshed_at
+            .is_none_or(|refreshed_at| refreshed_at.elapsed() >= WATCH_SLOTS_REFRESH)
+        {
+            self.refresh_slots().await?;
+        }
+
+        for (&slot_id, cursor) in self.cursors.iter_mut() {
+            let changes = self
+                .state
+                .list_slot_changes_operation
+                .run(ListSlotChangesOperationRequest {
+                    slot_id,
+                    since: *cursor,
+                    limit: WATCH_PAGE_SIZE,
+                })
+                .await?;
+            for change in changes {
+                *cursor = change.seq;
+                self.hub.publish(WatchEvent { slot_id, change });
+            }
+        }
+        Ok(())
+    }
+
+    /// Follows the slots this node is primary for now; a slot it just
+    /// took over starts at its newest change.
+    async fn refresh_slots(&mut self) -> Result<()> {
+        let primaries: Vec<u16> = held_slots(&self.state)
+            .await?
+            .into_iter()
+            .filter(|slot| slot.primary)
+            .map(|slot| slot.slot_id)
+            .collect();
+        self.cursors
+            .retain(|slot_id, _| primaries.contains(slot_id));
+        for slot_id in primaries {
+            if !self.cursors.contains_key(&slot_id) {
+                let latest = self
+                    .state
+                    .list_slot_changes_operation
+                    .latest_seq(slot_id)
+                    .await?;
+                self.cursors.insert(slot_id, latest);
+            }
+        }
+        self.refreshed_at = Some(Instant::now());
+        Ok(())
+    }
+}
+
+fn event_id(event: &WatchEvent) -> String {
+    format!("{}:{}", event.slot_id, event.change.seq)
+}
+
+fn watch_event(event: &WatchEvent) -> Event {
+    let name = match event.change.kind {
+        ChangeKind::Create => "create",
+        ChangeKind::Update => "update",
+        ChangeKind::Delete => "delete",
+    };
+    Event::default()
+        .event(name)
+        .id(event_id(event))
+        .data(serde_json::to_string(event).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rimio_core::SlotChange;
+
+    fn event(slot_id: u16, seq: i64, path: &str) -> WatchEvent {
+        WatchEvent {
+            slot_id,
+            change: SlotChange {
+                seq,
+                path: path.to_string(),
+                kind: ChangeKind::Create,
+                generation: 1,
+                head_sha256: String::new(),
+                recorded_at: chrono::Utc::now(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn watchers_resume_after_their_last_event_id() {
+        let hub = WatchHub::new();
+        hub.publish(event(1, 10, "logs/a"));
+        hub.publish(event(2, 4, "images/a"));
+        hub.publish(event(1, 11, "logs/b"));
+
+        let (backlog, mut receiver) = hub.attach(Some("1:10"));
+        let resumed: Vec<String> = backlog.iter().map(|event| event_id(event)).collect();
+        assert_eq!(resumed, ["2:4", "1:11"]);
+
+        hub.publish(event(2, 5, "images/b"));
+        assert_eq!(event_id(&receiver.recv().await.unwrap()), "2:5");
+
+        let (backlog, _) = hub.attach(Some("3:1"));
+        assert!(backlog.is_empty());
+        let (backlog, _) = hub.attach(None);
+        assert!(backlog.is_empty());
+    }
+
+    #[tokio::test]
+    async fn watches_only_see_their_prefix() {
+        let hub = WatchHub::new();
+        let (backlog, receiver) = hub.attach(None);
+        let mut watch = Watch {
+            prefix: "images/".to_string(),
+            backlog,
+            receiver,
+        };
+        hub.publish(event(1, 1, "logs/a"));
+        hub.publish(event(1, 2, "images/a"));
+        drop(hub);
+
+        assert!(watch.next_event().await.is_some());
+        assert!(watch.receiver.is_empty());
+        assert!(watch.next_event().await.is_none());
+    }
+}
@@ -0,0 +1,323 @@
+//! `rimio wipe`: factory reset of a stopped node before its hardware is
+//! recycled or returned.
+//!
+//! The wipe refuses to run while the node is listening or while any slot
+//! still names it, so it never removes the last copy of a blob; drain the
+//! node with `rimio decommission` first. It then removes the node record
+//! from the registry and deletes everything under the node's disks,
+//! optionally overwriting each file before unlinking it. With the embedded
+//! registry the record can only be removed by a running member, which
+//! `rimio decommission` does once the node is drained, so the wipe checks
+//! through a peer that it is gone instead.
+
+use crate::config::{Config, RegistryBackend};
+use rand::RngCore;
+use rimio_core::SlotInfo;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+const SHRED_CHUNK_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct PeerSlotItem {
+    slot: Option<SlotInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PeerNodes {
+    nodes: Vec<PeerNodeItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PeerNodeItem {
+    node_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct WipeReport {
+    node_id: String,
+    registry: &'static str,
+    shred: bool,
+    wiped: bool,
+    disks: Vec<DiskUsage>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct DiskUsage {
+    path: PathBuf,
+    files: u64,
+    bytes: u64,
+}
+
+pub(crate) async fn run_wipe(
+    cfg: &Config,
+    node_id: &str,
+    confirm: bool,
+    shred: bool,
+) -> std::result::Result<(), String> {
+    let node = cfg
+        .initial_cluster
+        .nodes
+        .iter()
+        .find(|node| node.node_id == node_id)
+        .ok_or_else(|| format!("node '{}' is not in the config", node_id))?;
+
+    match std::net::TcpListener::bind(&node.bind_addr) {
+        Ok(_) => {}
+        Err(error) if error.kind() == ErrorKind::AddrInUse => {
+            return Err(format!(
+                "{} is in use; stop the node before wiping it",
+                node.bind_addr
+            ));
+        }
+        Err(error) => {
+            return Err(format!(
+                "cannot tell whether the node is stopped: binding {} failed: {}",
+                node.bind_addr, error
+            ));
+        }
+    }
+
+    let registry = match cfg.registry.backend {
+        // Embedded registry records live in the raft log of the other
+        // members, so slots and the node record are checked through a peer.
+        RegistryBackend::Embed => None,
+        _ => Some(
+            cfg.registry_builder_for_node(node_id)
+                .build()
+                .await
+                .map_err(|error| format!("failed to connect registry: {}", error))?,
+        ),
+    };
+    let slots: Vec<SlotInfo> = match &registry {
+        Some(registry) => registry
+            .get_all_slots()
+            .await
+            .map_err(|error| format!("failed to read slots: {}", error))?
+            .into_values()
+            .collect(),
+        None => get_from_peer::<Vec<PeerSlotItem>>(cfg, node_id, "/_/api/v1/registry/slots")
+            .await?
+            .into_iter()
+            .filter_map(|item| item.slot)
+            .collect(),
+    };
+    let mut held: Vec<u16> = slots
+        .iter()
+        .filter(|slot| slot.replicas.iter().any(|replica| replica == node_id))
+        .map(|slot| slot.slot_id)
+        .collect();
+    if !held.is_empty() {
+        held.sort_unstable();
+        return Err(format!(
+            "node '{}' still holds slots {:?}; run `rimio decommission` first",
+            node_id, held
+        ));
+    }
+    if registry.is_none() {
+        let peers: PeerNodes = get_from_peer(cfg, node_id, "/_/api/v1/nodes").await?;
+        if peers.nodes.iter().any(|peer| peer.node_id == node_id) {
+            return Err(format!(
+                "node '{}' is still registered; run `rimio decommission` until it reports \
+                 the node deregistered",
+                node_id
+            ));
+        }
+    }
+
+    let mut report = WipeReport {
+        node_id: node_id.to_string(),
+        registry: match registry {
+            Some(_) if confirm => "deregistered",
+            Some(_) => "will deregister",
+            None => "already deregistered (embedded registry)",
+        },
+        shred,
+        wiped: false,
+        disks: Vec::new(),
+    };
+    for disk in &node.disks {
+        let mut usage = DiskUsage {
+            path: disk.path.clone(),
+            ..DiskUsage::default()
+        };
+        measure(&disk.path, &mut usage)
+            .map_err(|error| format!("failed to scan {}: {}", disk.path.display(), error))?;
+        report.disks.push(usage);
+    }
+
+    if !confirm {
+        print_report(&report);
+        tracing::info!("Nothing was deleted; pass --confirm to wipe the node");
+        return Ok(());
+    }
+
+    if let Some(registry) = &registry {
+        registry
+            .deregister_node(node_id)
+            .await
+            .map_err(|error| format!("failed to deregister node: {}", error))?;
+        tracing::info!("Node removed from registry: {}", node_id);
+    }
+    for disk in &node.disks {
+        wipe_dir_contents(&disk.path, shred)
+            .map_err(|error| format!("failed to wipe {}: {}", disk.path.display(), error))?;
+        tracing::info!("Disk wiped: {}", disk.path.display());
+    }
+    report.wiped = true;
+    print_report(&report);
+    Ok(())
+}
+
+/// Reads `path` from the first other node that answers, for registries
+/// that are only reachable through a running member.
+async fn get_from_peer<T: DeserializeOwned>(
+    cfg: &Config,
+    node_id: &str,
+    path: &str,
+) -> std::result::Result<T, String> {
+    let client = reqwest::Client::new();
+    let mut last_error = "no other node in the config".to_string();
+    for peer in cfg
+        .initial_cluster
+        .nodes
+        .iter()
+        .filter(|peer| peer.node_id != node_id)
+    {
+        let address = peer
+            .advertise_addr
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .unwrap_or(&peer.bind_addr);
+        let url = format!("http://{}{}", address, path);
+        let response = match client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                last_error = format!("{} returned {}", address, response.status());
+                continue;
+            }
+            Err(error) => {
+                last_error = format!("{} unreachable: {}", address, error);
+                continue;
+            }
+        };
+        match response.json::<T>().await {
+            Ok(value) => return Ok(value),
+            Err(error) => last_error = format!("invalid response from {}: {}", address, error),
+        }
+    }
+    Err(format!(
+        "cannot confirm through a peer that the node is drained: {}",
+        last_error
+    ))
+}
+
+fn print_report(report: &WipeReport) {
+    match serde_json::to_string_pretty(report) {
+        Ok(json) => println!("{}", json),
+        Err(_) => println!("{:?}", report),
+    }
+}
+
+/// Counts the files and bytes below `dir`; a missing disk counts as empty.
+fn measure(dir: &Path, usage: &mut DiskUsage) -> std::io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            measure(&entry.path(), usage)?;
+        } else {
+            usage.files += 1;
+            usage.bytes += metadata.len();
+        }
+    }
+    Ok(())
+}
+
+/// Empties `dir` but keeps it, since a disk path is often a mount point.
+/// Symlinks are removed without touching what they point to.
+fn wipe_dir_contents(dir: &Path, shred: bool) -> std::io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            wipe_dir_contents(&path, shred)?;
+            fs::remove_dir(&path)?;
+        } else {
+            if shred && file_type.is_file() {
+                overwrite(&path)?;
+            }
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Overwrites a file in place with random bytes and flushes it to the
+/// device before it is unlinked.
+fn overwrite(path: &Path) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut buffer = vec![0u8; SHRED_CHUNK_BYTES];
+    let mut rng = rand::thread_rng();
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = remaining.min(SHRED_CHUNK_BYTES as u64) as usize;
+        rng.fill_bytes(&mut buffer[..chunk]);
+        file.write_all(&buffer[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wiping_keeps_the_disk_and_leaves_symlink_targets_alone() {
+        let disk = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let kept = outside.path().join("kept.bin");
+        fs::write(&kept, b"not ours").unwrap();
+        fs::create_dir_all(disk.path().join("slots/7")).unwrap();
+        fs::write(disk.path().join("slots/7/part"), b"data").unwrap();
+        std::os::unix::fs::symlink(&kept, disk.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), disk.path().join("dir-link")).unwrap();
+
+        wipe_dir_contents(disk.path(), true).unwrap();
+
+        assert!(disk.path().is_dir());
+        assert_eq!(fs::read_dir(disk.path()).unwrap().count(), 0);
+        assert_eq!(fs::read(&kept).unwrap(), b"not ours");
+    }
+
+    #[test]
+    fn overwrite_replaces_every_byte_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("part");
+        let original = vec![0u8; SHRED_CHUNK_BYTES + 17];
+        fs::write(&path, &original).unwrap();
+
+        overwrite(&path).unwrap();
+
+        let shredded = fs::read(&path).unwrap();
+        assert_eq!(shredded.len(), original.len());
+        assert_ne!(shredded, original);
+    }
+}